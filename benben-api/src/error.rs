@@ -0,0 +1,94 @@
+//! 接口返回的错误。
+
+use serde::{Deserialize, Serialize};
+
+/// 稳定的错误码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    InvalidArgument,
+    NotFound,
+    InvalidState,
+    /// 记录已经被并发修改
+    Conflict,
+    PolicyRejected,
+    ApprovalRequired,
+    ProviderRateLimited,
+    ProviderContextLengthExceeded,
+    ProviderAuthFailed,
+    ProviderModelNotFound,
+    ProviderTimeout,
+    ProviderUnavailable,
+    ProviderError,
+    Internal,
+    Timeout,
+    Unavailable,
+    /// 比客户端更新的服务端新增的错误码，服务端不会返回
+    #[serde(other)]
+    Unknown,
+}
+
+/// 对外返回的错误信息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ErrorEnvelope {
+    pub code: ErrorCode,
+    pub message: String,
+    /// 原样重试是否可能成功
+    #[serde(default)]
+    pub retryable: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "utoipa", schema(value_type = Option<Object>))]
+    pub details: Option<serde_json::Value>,
+}
+
+impl ErrorEnvelope {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            retryable: false,
+            details: None,
+        }
+    }
+
+    pub fn retryable(mut self, retryable: bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+
+    pub fn details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+}
+
+impl std::fmt::Display for ErrorEnvelope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for ErrorEnvelope {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_codes_decode() {
+        let envelope: ErrorEnvelope =
+            serde_json::from_str(r#"{"code":"SOMETHING_NEW","message":"x"}"#).unwrap();
+        assert_eq!(envelope.code, ErrorCode::Unknown);
+        assert!(!envelope.retryable);
+    }
+}
@@ -0,0 +1,15 @@
+//! benben-task HTTP 接口的线上结构。
+//!
+//! 服务端的 `rest` 模块直接返回这里的类型，`benben-client` 也用它们解析响应，两边共用同一份定义，
+//! 接口变更时只需要修改这里。错误统一为 [ErrorEnvelope]，`code` 的取值是稳定的，新增错误时只能追加。
+
+mod error;
+pub mod models;
+
+pub use error::{ErrorCode, ErrorEnvelope};
+pub use models::*;
+
+/// 请求所属的用户，由接口前面的网关在认证后设置，服务端只返回该用户的任务
+pub const OWNER_HEADER: &str = "x-owner-id";
+/// webhook 请求携带的触发器密钥
+pub const SECRET_HEADER: &str = "x-trigger-secret";
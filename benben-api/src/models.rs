@@ -0,0 +1,319 @@
+//! 接口的请求和响应。
+
+use serde::{Deserialize, Serialize};
+
+/// 任务状态枚举
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    Running,
+    Stopped,
+    Cancelled,
+    Finished,
+    Pending,
+    Waiting,
+}
+
+impl TaskState {
+    /// 将TaskState转换为字符串表示
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskState::Running => "running",
+            TaskState::Stopped => "stopped",
+            TaskState::Cancelled => "cancelled",
+            TaskState::Finished => "finished",
+            TaskState::Pending => "pending",
+            TaskState::Waiting => "waiting",
+        }
+    }
+
+    /// 从字符串表示解析TaskState
+    pub fn parse(state: &str) -> Option<TaskState> {
+        match state {
+            "running" => Some(TaskState::Running),
+            "stopped" => Some(TaskState::Stopped),
+            "cancelled" => Some(TaskState::Cancelled),
+            "finished" => Some(TaskState::Finished),
+            "pending" => Some(TaskState::Pending),
+            "waiting" => Some(TaskState::Waiting),
+            _ => None,
+        }
+    }
+
+    /// 终止状态的任务不能再变更
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, TaskState::Finished | TaskState::Cancelled)
+    }
+
+    /// 状态机是否允许从当前状态变更到 `to`
+    pub fn can_transition_to(&self, to: &TaskState) -> bool {
+        use TaskState::*;
+        match self {
+            Waiting => matches!(to, Pending | Running | Stopped | Cancelled),
+            Pending => matches!(to, Waiting | Running | Stopped | Cancelled),
+            Running => matches!(to, Waiting | Pending | Stopped | Finished | Cancelled),
+            Stopped => matches!(to, Waiting | Pending | Running),
+            Finished | Cancelled => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct TaskSummary {
+    pub id: i32,
+    pub state: TaskState,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct TaskView {
+    pub id: i32,
+    pub state: TaskState,
+    pub history: Vec<String>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 累计费用，美元
+    pub cost: f64,
+}
+
+/// 对任务的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum TaskAction {
+    Start,
+    Pause,
+    Resume,
+    Stop,
+    Cancel,
+    Finish,
+}
+
+impl TaskAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskAction::Start => "start",
+            TaskAction::Pause => "pause",
+            TaskAction::Resume => "resume",
+            TaskAction::Stop => "stop",
+            TaskAction::Cancel => "cancel",
+            TaskAction::Finish => "finish",
+        }
+    }
+}
+
+/// 任务的产物：每个job记录的输出
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ArtifactView {
+    pub id: i32,
+    pub job_id: Option<i32>,
+    /// 未通过后处理链的输出
+    pub rejected: bool,
+    /// 模型的推理过程，不是job的最终输出
+    pub reasoning: bool,
+    /// 检查点的差异和结论
+    pub evidence: bool,
+    pub output: Option<String>,
+}
+
+/// 计划步骤的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum PlanStatus {
+    Pending,
+    Running,
+    Success,
+    Failure,
+    Skipped,
+}
+
+impl PlanStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlanStatus::Pending => "pending",
+            PlanStatus::Running => "running",
+            PlanStatus::Success => "success",
+            PlanStatus::Failure => "failure",
+            PlanStatus::Skipped => "skipped",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<PlanStatus> {
+        match status {
+            "pending" => Some(PlanStatus::Pending),
+            "running" => Some(PlanStatus::Running),
+            "success" => Some(PlanStatus::Success),
+            "failure" => Some(PlanStatus::Failure),
+            "skipped" => Some(PlanStatus::Skipped),
+            _ => None,
+        }
+    }
+}
+
+/// 计划步骤及其状态
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct PlanStepView {
+    pub seq: i32,
+    pub step: String,
+    pub job_id: Option<i32>,
+    pub status: PlanStatus,
+}
+
+/// 任务的一次状态变更
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct TransitionView {
+    pub from: String,
+    pub to: String,
+    /// user、scheduler 或 engine
+    pub actor: String,
+    pub reason: String,
+    /// unix 毫秒
+    pub created_at: i64,
+}
+
+/// job的一次执行尝试
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct JobRunView {
+    pub job_id: i32,
+    /// 这个job的第几次尝试
+    pub attempt: i32,
+    pub agent: Option<String>,
+    /// 渲染后的prompt
+    pub prompt: Option<String>,
+    pub output: String,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub duration_ms: i64,
+    /// running、success、failure 或 rejected
+    pub status: String,
+    /// unix 毫秒
+    pub started_at: i64,
+    /// 使用的系统提示版本
+    pub prompt_version: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct GoldenRequest {
+    /// 写入的数据集
+    pub dataset: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct GoldenView {
+    pub dataset: String,
+    /// 写入的样本数
+    pub samples: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct WorkflowView {
+    pub id: String,
+    pub code: Option<String>,
+    pub name: Option<String>,
+    pub desc: Option<String>,
+    pub plan: Option<String>,
+}
+
+/// 等待人工审批的job
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ApprovalView {
+    pub task_id: i32,
+    pub job_id: i32,
+    pub reason: String,
+}
+
+/// 触发器创建的任务
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct TriggeredTask {
+    pub task_id: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct DryRunRequest {
+    pub input: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct AgentView {
+    pub name: String,
+    pub desc: String,
+    /// 初始化失败的原因
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ExampleView {
+    pub id: i32,
+    pub input: String,
+    pub output: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ExampleRequest {
+    pub input: String,
+    pub output: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct PromptVersionView {
+    pub version: i32,
+    pub preamble: String,
+    /// draft、active 或 retired
+    pub state: String,
+    pub note: Option<String>,
+    /// unix 毫秒
+    pub created_at: i64,
+    pub activated_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct PromptDraftRequest {
+    pub preamble: String,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// 恢复已停止的任务
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ResumeStoppedRequest {
+    /// 只恢复在这之后停止的任务，unix 毫秒
+    #[serde(default)]
+    pub stopped_since: Option<i64>,
+}
+
+/// 批量操作中失败的任务
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct BulkFailureView {
+    pub task_id: i32,
+    pub reason: String,
+}
+
+/// 批量操作的结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct BulkReportView {
+    /// 选中的任务数
+    pub total: usize,
+    pub succeeded: Vec<i32>,
+    pub failed: Vec<BulkFailureView>,
+}
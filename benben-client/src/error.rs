@@ -0,0 +1,76 @@
+//! 接口返回的错误。
+//!
+//! 服务端的错误统一以 [ErrorEnvelope] 返回。`code` 的取值只会追加，客户端不认识的错误码解析为
+//! [ErrorCode::Unknown]，不会导致解析失败。
+
+use benben_api::{ErrorCode, ErrorEnvelope};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// 服务端返回了错误
+    #[error("ApiError({status}): {:?}: {}", envelope.code, envelope.message)]
+    Api {
+        status: u16,
+        envelope: ErrorEnvelope,
+    },
+    /// 服务端返回了错误状态，但响应不是 [ErrorEnvelope]，例如经过的代理返回的错误页
+    #[error("HttpStatus({status}): {body}")]
+    Status { status: u16, body: String },
+    #[error("HttpError: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("UrlError: {0}")]
+    Url(#[from] url::ParseError),
+    /// 路径中的 id 为 `.` 或 `..`，按 URL 规范会被当作相对路径
+    #[error("InvalidPathSegment: {0:?}")]
+    InvalidSegment(String),
+}
+
+impl ClientError {
+    /// 服务端返回的错误码
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            ClientError::Api { envelope, .. } => Some(envelope.code),
+            _ => None,
+        }
+    }
+
+    /// 原样重试是否可能成功。服务端的错误按 `retryable` 判断，连接失败、超时和 5xx 状态也可以重试
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::Api { envelope, .. } => envelope.retryable,
+            ClientError::Status { status, .. } => *status >= 500,
+            ClientError::Http(e) => e.is_connect() || e.is_timeout(),
+            ClientError::Url(_) | ClientError::InvalidSegment(_) => false,
+        }
+    }
+
+    pub(crate) fn from_response(status: u16, body: String) -> Self {
+        match serde_json::from_str(&body) {
+            Ok(envelope) => ClientError::Api { status, envelope },
+            Err(_) => ClientError::Status { status, body },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_error_responses() {
+        let body = r#"{"code":"PROVIDER_RATE_LIMITED","message":"slow down","retryable":true,"details":{"retry_after_secs":2.0}}"#;
+        let err = ClientError::from_response(502, body.to_string());
+        assert_eq!(err.code(), Some(ErrorCode::ProviderRateLimited));
+        assert!(err.is_retryable());
+
+        let err =
+            ClientError::from_response(409, r#"{"code":"SOMETHING_NEW","message":"x"}"#.into());
+        assert_eq!(err.code(), Some(ErrorCode::Unknown));
+        assert!(!err.is_retryable());
+
+        let err = ClientError::from_response(503, "<html>bad gateway</html>".into());
+        assert!(matches!(err, ClientError::Status { status: 503, .. }));
+        assert!(err.is_retryable());
+    }
+}
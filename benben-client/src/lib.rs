@@ -0,0 +1,307 @@
+//! benben-task HTTP 接口的客户端。
+//!
+//! 其他 Rust 服务通过 [Client] 调用任务引擎的 REST 接口，请求和响应使用 [models] 中的类型，
+//! 与服务端共用 `benben-api` 中的定义。错误统一为 [ClientError]：服务端返回的错误解析为
+//! [ErrorEnvelope]，可以按稳定的 [ErrorCode] 和 `retryable` 判断，不需要解析错误文本。
+//!
+//! ```rust,ignore
+//! let client = Client::new("http://127.0.0.1:8080")?.owner("alice");
+//! for task in client.list_tasks().await? {
+//!     if task.state == TaskState::Pending {
+//!         client.task_action(task.id, TaskAction::Resume).await?;
+//!     }
+//! }
+//! ```
+
+mod error;
+
+use reqwest::{Method, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use url::Url;
+
+pub use benben_api::models::{self, *};
+pub use benben_api::{ErrorCode, ErrorEnvelope, OWNER_HEADER, SECRET_HEADER};
+pub use error::ClientError;
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+/// 任务引擎 HTTP 接口的客户端，克隆后共用连接池
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: Url,
+    owner: Option<String>,
+}
+
+impl Client {
+    /// `base_url` 为接口的根地址，例如 `http://127.0.0.1:8080`，挂载在子路径下时带上子路径
+    pub fn new(base_url: &str) -> ClientResult<Self> {
+        Self::with_http_client(reqwest::Client::new(), base_url)
+    }
+
+    /// 使用自定义的 HTTP 客户端，例如设置了超时或者代理的客户端
+    pub fn with_http_client(http: reqwest::Client, base_url: &str) -> ClientResult<Self> {
+        let mut base_url = Url::parse(base_url)?;
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+        Ok(Self {
+            http,
+            base_url,
+            owner: None,
+        })
+    }
+
+    /// 以该用户的身份发送请求，只能访问该用户的任务
+    pub fn owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    /// `segments` 逐段编码后接在根地址后面，id 中的 `/`、`?` 等字符不会改变请求的路径
+    fn request(&self, method: Method, segments: &[&str]) -> ClientResult<RequestBuilder> {
+        if let Some(segment) = segments.iter().find(|s| matches!(**s, "." | "..")) {
+            return Err(ClientError::InvalidSegment(segment.to_string()));
+        }
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+            .pop_if_empty()
+            .extend(segments);
+        let request = self.http.request(method, url);
+        Ok(match &self.owner {
+            Some(owner) => request.header(OWNER_HEADER, owner),
+            None => request,
+        })
+    }
+
+    async fn send(request: RequestBuilder) -> ClientResult<Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await?;
+        Err(ClientError::from_response(status.as_u16(), body))
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &[&str]) -> ClientResult<T> {
+        let request = self.request(Method::GET, path)?;
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    async fn post<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &[&str],
+        body: &B,
+    ) -> ClientResult<T> {
+        let request = self.request(Method::POST, path)?.json(body);
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    /// 发送没有请求体、也没有响应体的 POST 请求
+    async fn post_empty(&self, path: &[&str]) -> ClientResult<()> {
+        Self::send(self.request(Method::POST, path)?).await?;
+        Ok(())
+    }
+
+    /// 任务列表，按id排列
+    pub async fn list_tasks(&self) -> ClientResult<Vec<TaskSummary>> {
+        self.get(&["tasks"]).await
+    }
+
+    pub async fn get_task(&self, task_id: i32) -> ClientResult<TaskView> {
+        self.get(&["tasks", &task_id.to_string()]).await
+    }
+
+    /// 改变任务状态
+    pub async fn task_action(&self, task_id: i32, action: TaskAction) -> ClientResult<()> {
+        self.post_empty(&["tasks", &task_id.to_string(), action.as_str()])
+            .await
+    }
+
+    /// 审批通过任务的job
+    pub async fn approve_job(&self, task_id: i32, job_id: i32) -> ClientResult<()> {
+        self.post_empty(&[
+            "tasks",
+            &task_id.to_string(),
+            "jobs",
+            &job_id.to_string(),
+            "approve",
+        ])
+        .await
+    }
+
+    /// 暂停所有未结束的任务，设置了用户时只暂停该用户的任务
+    pub async fn pause_tasks(&self) -> ClientResult<BulkReportView> {
+        let request = self.request(Method::POST, &["bulk", "pause"])?;
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    /// 恢复已停止的任务，指定 `stopped_since`（unix 毫秒）时只恢复在这之后停止的任务
+    pub async fn resume_stopped_tasks(
+        &self,
+        stopped_since: Option<i64>,
+    ) -> ClientResult<BulkReportView> {
+        self.post(&["bulk", "resume"], &ResumeStoppedRequest { stopped_since })
+            .await
+    }
+
+    /// 等待人工审批的job
+    pub async fn list_approvals(&self) -> ClientResult<Vec<ApprovalView>> {
+        self.get(&["approvals"]).await
+    }
+
+    pub async fn list_artifacts(&self, task_id: i32) -> ClientResult<Vec<ArtifactView>> {
+        self.get(&["tasks", &task_id.to_string(), "artifacts"])
+            .await
+    }
+
+    pub async fn get_plan(&self, task_id: i32) -> ClientResult<Vec<PlanStepView>> {
+        self.get(&["tasks", &task_id.to_string(), "plan"]).await
+    }
+
+    pub async fn list_transitions(&self, task_id: i32) -> ClientResult<Vec<TransitionView>> {
+        self.get(&["tasks", &task_id.to_string(), "transitions"])
+            .await
+    }
+
+    /// 任务的job执行记录，指定 `job_id` 时只返回该job的记录
+    pub async fn list_job_runs(
+        &self,
+        task_id: i32,
+        job_id: Option<i32>,
+    ) -> ClientResult<Vec<JobRunView>> {
+        let mut request = self.request(Method::GET, &["tasks", &task_id.to_string(), "runs"])?;
+        if let Some(job_id) = job_id {
+            request = request.query(&[("job_id", job_id)]);
+        }
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    /// 把任务的执行记录写入评测数据集
+    pub async fn mark_golden(&self, task_id: i32, dataset: &str) -> ClientResult<GoldenView> {
+        self.post(
+            &["tasks", &task_id.to_string(), "golden"],
+            &GoldenRequest {
+                dataset: dataset.to_string(),
+            },
+        )
+        .await
+    }
+
+    /// 使用 `payload` 触发webhook，触发器配置了密钥时传入 `secret`
+    pub async fn fire_webhook(
+        &self,
+        name: &str,
+        payload: &serde_json::Value,
+        secret: Option<&str>,
+    ) -> ClientResult<TriggeredTask> {
+        let mut request = self.request(Method::POST, &["hooks", name])?.json(payload);
+        if let Some(secret) = secret {
+            request = request.header(SECRET_HEADER, secret);
+        }
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn list_workflows(&self) -> ClientResult<Vec<WorkflowView>> {
+        self.get(&["workflows"]).await
+    }
+
+    pub async fn get_workflow(&self, workflow_id: &str) -> ClientResult<WorkflowView> {
+        self.get(&["workflows", workflow_id]).await
+    }
+
+    /// 取消工作流下所有未结束的任务，设置了用户时只取消该用户的任务
+    pub async fn cancel_workflow_tasks(&self, workflow_id: &str) -> ClientResult<BulkReportView> {
+        let request = self.request(Method::POST, &["workflows", workflow_id, "cancel-tasks"])?;
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    /// 暂停工作流下所有未结束的任务，设置了用户时只暂停该用户的任务
+    pub async fn pause_workflow_tasks(&self, workflow_id: &str) -> ClientResult<BulkReportView> {
+        let request = self.request(Method::POST, &["workflows", workflow_id, "pause-tasks"])?;
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn list_agents(&self) -> ClientResult<Vec<AgentView>> {
+        self.get(&["agents"]).await
+    }
+
+    pub async fn list_examples(&self, code: &str) -> ClientResult<Vec<ExampleView>> {
+        self.get(&["agents", code, "examples"]).await
+    }
+
+    pub async fn add_example(
+        &self,
+        code: &str,
+        input: &str,
+        output: &str,
+    ) -> ClientResult<ExampleView> {
+        self.post(
+            &["agents", code, "examples"],
+            &ExampleRequest {
+                input: input.to_string(),
+                output: output.to_string(),
+            },
+        )
+        .await
+    }
+
+    pub async fn list_prompt_versions(&self, code: &str) -> ClientResult<Vec<PromptVersionView>> {
+        self.get(&["agents", code, "prompts"]).await
+    }
+
+    /// 创建系统提示的草稿版本
+    pub async fn create_prompt_draft(
+        &self,
+        code: &str,
+        preamble: &str,
+        note: Option<&str>,
+    ) -> ClientResult<PromptVersionView> {
+        self.post(
+            &["agents", code, "prompts"],
+            &PromptDraftRequest {
+                preamble: preamble.to_string(),
+                note: note.map(str::to_string),
+            },
+        )
+        .await
+    }
+
+    /// 启用系统提示的版本
+    pub async fn activate_prompt_version(
+        &self,
+        code: &str,
+        version: i32,
+    ) -> ClientResult<PromptVersionView> {
+        let request = self.request(
+            Method::POST,
+            &["agents", code, "prompts", &version.to_string(), "activate"],
+        )?;
+        Ok(Self::send(request).await?.json().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_encoded_as_path_segments() {
+        let client = Client::new("http://127.0.0.1:8080/api").unwrap();
+        let url = |segments: &[&str]| {
+            let request = client.request(Method::GET, segments).unwrap();
+            request.build().unwrap().url().to_string()
+        };
+        assert_eq!(
+            url(&["workflows", "a/b?c#d"]),
+            "http://127.0.0.1:8080/api/workflows/a%2Fb%3Fc%23d"
+        );
+        assert!(matches!(
+            client.request(Method::GET, &["agents", "..", "examples"]),
+            Err(ClientError::InvalidSegment(_))
+        ));
+    }
+}
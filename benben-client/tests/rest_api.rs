@@ -0,0 +1,55 @@
+//! 客户端与 benben-task 的 HTTP 接口互通。
+
+use std::sync::Arc;
+
+use benben_client::{Client, ErrorCode, TaskAction, TaskState};
+use benben_task::engine::TaskEngine;
+use benben_task::rest::router;
+
+#[tokio::test]
+async fn client_speaks_the_rest_api() {
+    let engine = Arc::new(TaskEngine::new());
+    let task_id = engine.create_task_as("alice", "a".into()).await.unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let app = router(engine);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let client = Client::new(&base_url).unwrap().owner("alice");
+    client
+        .task_action(task_id, TaskAction::Start)
+        .await
+        .unwrap();
+    let task = client.get_task(task_id).await.unwrap();
+    assert_eq!(task.state, TaskState::Running);
+    assert_eq!(client.list_tasks().await.unwrap().len(), 1);
+
+    let err = client
+        .task_action(task_id, TaskAction::Start)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::InvalidState));
+    let err = client
+        .clone()
+        .owner("bob")
+        .get_task(task_id)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Some(ErrorCode::NotFound));
+    assert!(!err.is_retryable());
+
+    // 批量操作只作用于发起请求的用户的任务
+    assert_eq!(
+        client
+            .clone()
+            .owner("bob")
+            .pause_tasks()
+            .await
+            .unwrap()
+            .total,
+        0
+    );
+    let report = client.pause_tasks().await.unwrap();
+    assert_eq!(report.succeeded, vec![task_id]);
+    assert!(report.failed.is_empty());
+}
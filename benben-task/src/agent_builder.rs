@@ -1,15 +1,33 @@
 use crate::agent_support::DefaultProviders;
-use rig::agent::{Agent, AgentBuilder};
+use crate::call_agent_tool::CallAgentTool;
+use crate::http_tool::{HttpTool, HttpToolError};
+use crate::mcp_manager::McpManager;
+use crate::engine::TaskEngine;
+use crate::example_library::ExampleLibrary;
+use crate::patch_tool::ApplyPatchTool;
+use crate::tool_registry::ToolRegistry;
+use crate::workspace::{create_task_workspace, task_workspace_path};
+use rig::agent::{root_from_path, Agent, AgentBuilder, McpClient, McpClientHandler};
 use rig::client::completion::CompletionModelHandle;
-use rig::client::{AgentConfig, McpStdio, McpType, ProviderClient};
+use rig::client::cache::{CachedCompletionModel, CompletionCache};
+use rig::client::fallback::FallbackCompletionModel;
+use rig::client::load_balance::LoadBalancedCompletionModel;
+use rig::client::rate_limit::{RateLimit, RateLimitedCompletionModel, RateLimiter};
+use rig::client::secret_scan::{SecretScanStats, SecretScanner, SecretScanningCompletionModel};
+use rig::client::size_limit::{SizeLimitedCompletionModel, SizeLimits};
+use rig::client::{
+    AgentConfig, ClientBuilderError, McpStdio, McpType, ProviderClient, VerifyError,
+};
+use rig::tool::Tool as _;
 use rig::completion::CompletionModelDyn;
 use rig::embeddings::embedding::EmbeddingModelDyn;
-use rmcp::model::{ClientCapabilities, ClientInfo, Implementation, InitializeRequestParam};
-use rmcp::service::RunningService;
+use rmcp::model::{ClientCapabilities, ClientInfo, Implementation};
 use rmcp::transport::{ConfigureCommandExt as _, TokioChildProcess};
-use rmcp::{RoleClient, ServiceExt as _};
+use rmcp::ServiceExt as _;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
 use tokio::process::Command;
 
@@ -17,6 +35,8 @@ use tokio::process::Command;
 pub enum ClientBuildError {
     #[error("factory error: {}", .0)]
     FactoryError(String),
+    #[error("invalid client config: {}", .0)]
+    InvalidConfig(ClientBuilderError),
     #[error("invalid id string: {}", .0)]
     InvalidIdString(String),
     #[error("unsupported feature: {} for {}", .1, .0)]
@@ -25,17 +45,37 @@ pub enum ClientBuildError {
     UnknownProvider,
     #[error("Stdio MCP Execute Failed")]
     MCPStidioExecuteFailed(std::io::Error),
+    /// 初始化错误较大，装箱后 `Result<_, ClientBuildError>` 不会因为它变大
     #[error("Stdio MCP Client Init Failed {}",.0)]
-    MCPClinetInitError(rmcp::service::ClientInitializeError),
+    MCPClinetInitError(Box<rmcp::service::ClientInitializeError>),
+    #[error("invalid mcp root {}: {}", .0, .1)]
+    InvalidMcpRoot(String, std::io::Error),
+    #[error("unknown tool: {}", .0)]
+    UnknownTool(String),
+    #[error("invalid redaction pattern: {}", .0)]
+    InvalidRedaction(regex::Error),
+    #[error("invalid http tool: {}", .0)]
+    InvalidHttpTool(HttpToolError),
+    #[error("verify failed: {}", .0)]
+    VerifyFailed(VerifyError),
 }
 
 pub type BoxCompletionModel<'a> = Box<dyn CompletionModelDyn + 'a>;
 pub type BoxAgentBuilder<'a> = AgentBuilder<CompletionModelHandle<'a>>;
 pub type BoxAgent<'a> = Agent<CompletionModelHandle<'a>>;
 pub type BoxEmbeddingModel<'a> = Box<dyn EmbeddingModelDyn + 'a>;
+/// 按agent配置创建provider client，配置不合法时返回错误
+pub type CreateByConfig =
+    dyn Fn(AgentConfig) -> Result<Box<dyn ProviderClient>, ClientBuilderError> + Send + Sync;
 #[derive(Default)]
 pub struct DynClientBuilder {
     pub registry: HashMap<DefaultProviders, ClientFactory>,
+    /// 按 provider + base_url 共享的限流器，同一个账号下的所有agent共用额度。
+    limiters: Mutex<HashMap<String, RateLimiter>>,
+    /// 出站请求的敏感信息扫描，所有agent共用，设置后创建的agent生效。
+    secret_scanner: Mutex<Option<Arc<SecretScanner>>>,
+    /// 大模型回答的缓存，所有agent共用，设置后创建的agent生效。
+    completion_cache: Mutex<Option<Arc<dyn CompletionCache>>>,
 }
 
 impl<'a> DynClientBuilder {
@@ -74,17 +114,25 @@ impl<'a> DynClientBuilder {
         provider: DefaultProviders,
         config: AgentConfig,
     ) -> Result<Agent<CompletionModelHandle<'static>>, ClientBuildError> {
-        let modle = config.model.clone();
-        let client = self.build(provider, config.clone())?;
-
-        let client = client
-            .as_completion()
-            .ok_or(ClientBuildError::UnsupportedFeature(
-                provider.to_string(),
-                "completion".to_string(),
-            ))?;
+        self.agent_in(provider, config, None).await
+    }
 
-        let mut build = client.agent(&modle);
+    /// 创建agent，`workspace` 为任务的工作目录，文件类的原生工具只能修改这个目录
+    async fn agent_in(
+        &self,
+        provider: DefaultProviders,
+        config: AgentConfig,
+        workspace: Option<PathBuf>,
+    ) -> Result<Agent<CompletionModelHandle<'static>>, ClientBuildError> {
+        let mut build = if !config.fallback.is_empty() {
+            AgentBuilder::new(CompletionModelHandle {
+                inner: Arc::new(self.fallback_model(provider, &config)?),
+            })
+        } else if !config.pool.is_empty() {
+            AgentBuilder::new(self.pooled_handle(provider, &config)?)
+        } else {
+            AgentBuilder::new(self.completion_handle(provider, config.clone())?)
+        };
 
         // 设置名称
         if !config.name.is_empty() {
@@ -102,15 +150,76 @@ impl<'a> DynClientBuilder {
         if let Some(sys_promte) = &config.sys_promte {
             build = build.preamble(sys_promte);
         }
+
+        // 按prompt检索相似的示例，附加在系统提示之后
+        if let (Some(k), Some(library)) = (config.few_shot, ExampleLibrary::global()) {
+            build = build.few_shot(library.for_agent(&config.code), k);
+        }
         build = build.temperature(0.0);
+        if let Some(think) = config.think {
+            build = build.think(think);
+        }
+
+        // agent自己的脱敏配置，覆盖全局配置
+        if let Some(redaction) = &config.redaction {
+            build = build.redactor(Arc::new(
+                redaction.build().map_err(ClientBuildError::InvalidRedaction)?,
+            ));
+        }
+
+        // 多轮工具调用的轮数上限和死循环检测
+        if let Some(max_turns) = config.max_turns {
+            build = build.max_turns(max_turns);
+        }
+        if let Some(limit) = config.tool_loop_limit {
+            build = build.tool_loop_limit(limit);
+        }
+
+        // 原生工具，请求时与mcp工具合并
+        for name in &config.tools {
+            // 配置了域名白名单的agent使用自己的http工具，否则使用注册表中的共享实例
+            if name == HttpTool::NAME && !config.http_allowlist.is_empty() {
+                build = build.tool(
+                    HttpTool::from_config(&config).map_err(ClientBuildError::InvalidHttpTool)?,
+                );
+                continue;
+            }
+            // 从 AgentManager 查找目标agent，agent自身也可以作为目标
+            if name == CallAgentTool::NAME {
+                build = build.tool(CallAgentTool::new());
+                continue;
+            }
+            // 修改暂存在任务工作目录中，由全局引擎的审批策略或者人工审批后应用
+            if name == ApplyPatchTool::NAME {
+                let workspace = workspace.clone().ok_or_else(|| {
+                    ClientBuildError::UnsupportedFeature(name.clone(), "agent without task workspace".to_string())
+                })?;
+                let mut tool = ApplyPatchTool::new(workspace);
+                if let Some(policy) = TaskEngine::global().and_then(|engine| engine.policy()) {
+                    tool = tool.policy(Arc::new(move |subject| policy.evaluate(subject)));
+                }
+                build = build.tool(tool);
+                continue;
+            }
+            let tool = ToolRegistry::global()
+                .get(name)
+                .ok_or_else(|| ClientBuildError::UnknownTool(name.clone()))?;
+            build = build.tool_dyn(tool);
+        }
 
         // 无论如何也需要进行roots 配置。
         match config.mcp {
             McpType::Nothing => {}
             McpType::STDIO(mcp_stdio) => {
-                let client: RunningService<RoleClient, InitializeRequestParam> =
-                    build_agent(mcp_stdio).await?;
-                build = build.mcp_client(client);
+                // 交给 McpManager 托管，子进程退出后可以自动重启。
+                // roots 不同的服务各自独立，同一个agent在不同任务的工作目录下互不影响
+                let key = if mcp_stdio.roots.is_empty() {
+                    config.code.clone()
+                } else {
+                    format!("{}@{}", config.code, mcp_stdio.roots.join(","))
+                };
+                let slot = McpManager::global().register(&key, mcp_stdio).await?;
+                build = build.mcp_slot(slot);
             }
             McpType::SHTTP(_) => todo!(),
         }
@@ -120,6 +229,213 @@ impl<'a> DynClientBuilder {
         Ok(agent)
     }
 
+    /// 为任务创建独立的工作目录，并作为mcp roots 创建agent
+    pub async fn task_agent(
+        &self,
+        provider: DefaultProviders,
+        mut config: AgentConfig,
+        task_id: i32,
+        workspace_base: &Path,
+    ) -> Result<Agent<CompletionModelHandle<'static>>, ClientBuildError> {
+        let needs_workspace = matches!(config.mcp, McpType::STDIO(_))
+            || config.tools.iter().any(|name| name == ApplyPatchTool::NAME);
+        if !needs_workspace {
+            return self.agent(provider, config).await;
+        }
+        let workspace = create_task_workspace(workspace_base, task_id).map_err(|e| {
+            ClientBuildError::InvalidMcpRoot(
+                task_workspace_path(workspace_base, task_id).display().to_string(),
+                e,
+            )
+        })?;
+        if let McpType::STDIO(ref mut mcp_stdio) = config.mcp {
+            mcp_stdio.roots = vec![workspace.display().to_string()];
+        }
+        self.agent_in(provider, config, Some(workspace)).await
+    }
+
+    /// 按照配置的fallback 组装备用模型链，主模型排在第一位。
+    fn fallback_model(
+        &self,
+        provider: DefaultProviders,
+        config: &AgentConfig,
+    ) -> Result<FallbackCompletionModel<'static>, ClientBuildError> {
+        let primary = if config.pool.is_empty() {
+            self.completion_handle(provider, config.clone())?
+        } else {
+            self.pooled_handle(provider, config)?
+        };
+        let mut model =
+            FallbackCompletionModel::new(format!("{}/{}", provider, config.model), primary);
+
+        for target in &config.fallback {
+            let fallback_provider = target.provider.parse::<DefaultProviders>()?;
+            let mut fallback_config = config.clone();
+            fallback_config.model = target.model.clone();
+            fallback_config.fallback = Vec::new();
+            fallback_config.pool = Vec::new();
+            if let Some(base_url) = &target.base_url {
+                fallback_config.base_url = base_url.clone();
+            }
+            if target.api_key.is_some() {
+                fallback_config.api_key = target.api_key.clone();
+            }
+            model = model.fallback(
+                format!("{}/{}", fallback_provider, target.model),
+                self.completion_handle(fallback_provider, fallback_config)?,
+            );
+        }
+        Ok(model)
+    }
+
+    /// 为pool中的每个地址创建一个模型实例，并通过负载均衡组合成一个模型。
+    fn pooled_handle(
+        &self,
+        provider: DefaultProviders,
+        config: &AgentConfig,
+    ) -> Result<CompletionModelHandle<'static>, ClientBuildError> {
+        let mut model = LoadBalancedCompletionModel::new(config.pool_strategy);
+        for base_url in &config.pool {
+            let mut member_config = config.clone();
+            member_config.base_url = base_url.clone();
+            member_config.pool = Vec::new();
+            model = model.member(base_url, self.completion_handle(provider, member_config)?);
+        }
+        Ok(CompletionModelHandle {
+            inner: Arc::new(model),
+        })
+    }
+
+    fn completion_handle(
+        &self,
+        provider: DefaultProviders,
+        config: AgentConfig,
+    ) -> Result<CompletionModelHandle<'static>, ClientBuildError> {
+        let model = config.model.clone();
+        let cache_model = format!("{}/{}", provider, model);
+        // 大小检查和限流按模型的分词器估算token
+        let counter = rig::tokens::counter_for(&model);
+        let limiter = self.rate_limiter(provider, &config)?;
+        let provider_limits = self.get_factory(provider)?.size_limit.clone();
+        // 按模型的上下文窗口收紧prompt的token上限
+        let size_limit = config
+            .size_limit
+            .clone()
+            .or(provider_limits)
+            .map(|limits| limits.for_model(&model))
+            .filter(|limits| !limits.is_empty());
+        let client = self
+            .build(provider, config)?
+            .as_completion()
+            .ok_or(ClientBuildError::UnsupportedFeature(
+                provider.to_string(),
+                "completion".to_string(),
+            ))?;
+        let handle = CompletionModelHandle {
+            inner: Arc::from(client.completion_model(&model)),
+        };
+        // 延迟和token按实际发到provider的请求记录
+        #[cfg(feature = "otel-metrics")]
+        let handle = CompletionModelHandle {
+            inner: Arc::new(rig::telemetry::metrics::MeteredCompletionModel::new(
+                handle,
+                provider.to_string(),
+                model.clone(),
+            )),
+        };
+        // 大小检查在敏感信息脱敏之后，按实际发出的请求计算
+        let handle = match size_limit {
+            Some(limits) => CompletionModelHandle {
+                inner: Arc::new(
+                    SizeLimitedCompletionModel::new(handle, limits).with_counter(counter.clone()),
+                ),
+            },
+            None => handle,
+        };
+        let handle = match self.secret_scanner() {
+            Some(scanner) => CompletionModelHandle {
+                inner: Arc::new(SecretScanningCompletionModel::new(handle, scanner)),
+            },
+            None => handle,
+        };
+        let handle = match limiter {
+            Some(limiter) => CompletionModelHandle {
+                inner: Arc::new(
+                    RateLimitedCompletionModel::new(handle, limiter).with_counter(counter),
+                ),
+            },
+            None => handle,
+        };
+        // 缓存在最外层，命中时不占用限流额度
+        Ok(match self.completion_cache() {
+            Some(cache) => CompletionModelHandle {
+                inner: Arc::new(CachedCompletionModel::new(handle, cache, cache_model)),
+            },
+            None => handle,
+        })
+    }
+
+    /// 校验 provider 的地址和凭证，不支持校验的 provider 直接通过
+    pub async fn verify(
+        &self,
+        provider: DefaultProviders,
+        config: AgentConfig,
+    ) -> Result<(), ClientBuildError> {
+        let Some(client) = self.build(provider, config)?.as_verify() else {
+            return Ok(());
+        };
+        client.verify().await.map_err(ClientBuildError::VerifyFailed)
+    }
+
+    /// 获取限流器，AgentConfig 中的配置优先于 ClientFactory 的默认配置。
+    /// 第一次创建时的配置生效，之后同一个 provider + base_url 复用同一个限流器。
+    pub fn rate_limiter(
+        &self,
+        provider: DefaultProviders,
+        config: &AgentConfig,
+    ) -> Result<Option<RateLimiter>, ClientBuildError> {
+        let Some(limit) = config.rate_limit.or(self.get_factory(provider)?.rate_limit) else {
+            return Ok(None);
+        };
+        let key = format!("{}|{}", provider, config.base_url);
+        let mut limiters = self.limiters.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(Some(
+            limiters
+                .entry(key)
+                .or_insert_with(|| RateLimiter::new(limit))
+                .clone(),
+        ))
+    }
+
+    /// 设置出站请求的敏感信息扫描
+    pub fn set_secret_scanner(&self, scanner: Arc<SecretScanner>) {
+        *self.secret_scanner.lock().unwrap_or_else(|e| e.into_inner()) = Some(scanner);
+    }
+
+    pub fn secret_scanner(&self) -> Option<Arc<SecretScanner>> {
+        self.secret_scanner
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// 设置大模型回答的缓存
+    pub fn set_completion_cache(&self, cache: Arc<dyn CompletionCache>) {
+        *self.completion_cache.lock().unwrap_or_else(|e| e.into_inner()) = Some(cache);
+    }
+
+    pub fn completion_cache(&self) -> Option<Arc<dyn CompletionCache>> {
+        self.completion_cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// 敏感信息扫描的计数，未设置扫描时为空
+    pub fn secret_scan_stats(&self) -> Option<SecretScanStats> {
+        self.secret_scanner().map(|scanner| scanner.stats())
+    }
+
     // pub fn embeddings(
     //     &self,
     //     provider: &str,
@@ -139,7 +455,11 @@ impl<'a> DynClientBuilder {
 }
 pub struct ClientFactory {
     pub name: DefaultProviders,
-    pub create_by_config: Box<dyn Fn(AgentConfig) -> Box<dyn ProviderClient> + Send + Sync>,
+    pub create_by_config: Box<CreateByConfig>,
+    /// provider 默认的限流配置
+    pub rate_limit: Option<RateLimit>,
+    /// provider 默认的请求大小限制
+    pub size_limit: Option<SizeLimits>,
 }
 
 impl UnwindSafe for ClientFactory {}
@@ -148,26 +468,52 @@ impl RefUnwindSafe for ClientFactory {}
 impl ClientFactory {
     pub fn new<F1>(name: DefaultProviders, create_by_config: F1) -> Self
     where
-        F1: 'static + Fn(AgentConfig) -> Box<dyn ProviderClient> + Send + Sync,
+        F1: 'static
+            + Fn(AgentConfig) -> Result<Box<dyn ProviderClient>, ClientBuilderError>
+            + Send
+            + Sync,
     {
         Self {
             name,
             create_by_config: Box::new(create_by_config),
+            rate_limit: None,
+            size_limit: None,
         }
     }
 
+    /// 设置 provider 默认的限流配置
+    pub fn with_rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// 设置 provider 默认的请求大小限制
+    pub fn with_size_limit(mut self, size_limit: SizeLimits) -> Self {
+        self.size_limit = Some(size_limit);
+        self
+    }
+
     fn build(&self, agent_conf: AgentConfig) -> Result<Box<dyn ProviderClient>, ClientBuildError> {
+        // 额外注册的factory仍可能panic，保留panic的信息
         std::panic::catch_unwind(|| (self.create_by_config)(agent_conf))
-            .map_err(|e| ClientBuildError::FactoryError(format!("{e:?}")))
+            .map_err(|e| ClientBuildError::FactoryError(panic_message(e.as_ref())))?
+            .map_err(ClientBuildError::InvalidConfig)
     }
 }
 
-async fn build_agent(
-    mcp_stdio: McpStdio,
-) -> Result<RunningService<RoleClient, InitializeRequestParam>, ClientBuildError> {
-    let servers_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
-        .parent()
-        .expect("CARGO_MANIFEST_DIR is not set");
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "factory panicked".to_string()
+    }
+}
+
+pub(crate) async fn build_agent(mcp_stdio: McpStdio) -> Result<McpClient, ClientBuildError> {
+    let manifest_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+    let servers_dir = manifest_dir.parent().unwrap_or(manifest_dir);
 
     let client_info = ClientInfo {
         protocol_version: Default::default(),
@@ -180,6 +526,15 @@ async fn build_agent(
             icons: None,
         },
     };
+    // roots 使用相对于进程当前目录的路径
+    let roots = mcp_stdio
+        .roots
+        .iter()
+        .map(|root| {
+            root_from_path(root).map_err(|e| ClientBuildError::InvalidMcpRoot(root.clone(), e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
     //mcp_stdio 判断是否存在...  bug ../容易形成漏洞攻击。 但是，本质上已经允许  stdio 启动了，可不在意这种级别的漏洞，因为已经透明了。
     let zhiding_loction = servers_dir.join(mcp_stdio.path.unwrap_or_default());
     let mut command = Command::new(mcp_stdio.command);
@@ -192,14 +547,15 @@ async fn build_agent(
     let transport =
         TokioChildProcess::new(command).map_err(|e| ClientBuildError::MCPStidioExecuteFailed(e))?;
 
-    let client = client_info
+    let client = McpClientHandler::new(client_info)
+        .roots(roots)
         .serve(transport)
         .await
         .inspect_err(|e| {
             tracing::error!("client error: {:?}", e);
         })
         .map_err(|e: rmcp::service::ClientInitializeError| {
-            ClientBuildError::MCPClinetInitError(e)
+            ClientBuildError::MCPClinetInitError(Box::new(e))
         })?;
     // Ok("".to_string())
     Ok(client)
@@ -209,6 +565,11 @@ async fn build_agent(
 mod test {
     use std::fs;
 
+    use rig::client::mock;
+    use rig::completion::Prompt;
+
+    use super::*;
+
     #[test]
     fn test_path() {
         let servers_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -222,4 +583,97 @@ mod test {
         println!("{}", dd.to_str().unwrap_or_default());
         println!("{}", yy.to_str().unwrap_or_default());
     }
+
+    fn config(model: &str) -> AgentConfig {
+        AgentConfig {
+            name: "mock".to_string(),
+            code: "mock".to_string(),
+            desc: String::new(),
+            error: None,
+            model: model.to_string(),
+            base_url: String::new(),
+            sys_promte: None,
+            api_key: None,
+            mcp: McpType::Nothing,
+            fallback: Vec::new(),
+            pool: Vec::new(),
+            pool_strategy: Default::default(),
+            rate_limit: None,
+            tools: Vec::new(),
+            vram_mb: None,
+            http_allowlist: Vec::new(),
+            http_max_response_bytes: None,
+            size_limit: None,
+            redaction: None,
+            max_turns: None,
+            tool_loop_limit: None,
+            timeout: None,
+            connect_timeout: None,
+            proxy: None,
+            root_certificates: Vec::new(),
+            headers: HashMap::new(),
+            think: None,
+            max_concurrency: None,
+            few_shot: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_provider_agent() {
+        let config = config("mock-agent-builder");
+        mock::script(&config.model).push_text("计划已生成");
+        let agent = DynClientBuilder::global()
+            .agent(DefaultProviders::Mock, config)
+            .await
+            .unwrap();
+        assert_eq!(agent.prompt("生成计划").await.unwrap(), "计划已生成");
+        assert_eq!(agent.prompt("再说一遍").await.unwrap(), "再说一遍");
+    }
+
+    #[tokio::test]
+    async fn invalid_config_is_an_error() {
+        let mut config = config("deepseek-chat");
+        config.api_key = Some("key".to_string());
+        config.headers.insert("bad header".to_string(), "value".to_string());
+        let err = DynClientBuilder::global()
+            .agent(DefaultProviders::Deepseek, config.clone())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            ClientBuildError::InvalidConfig(ClientBuilderError::InvalidProperty("headers"))
+        ));
+
+        config.headers.clear();
+        config.api_key = None;
+        let err = DynClientBuilder::global()
+            .agent(DefaultProviders::Deepseek, config.clone())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            ClientBuildError::InvalidConfig(ClientBuilderError::InvalidProperty("api_key"))
+        ));
+
+        config.model = "qwen3".to_string();
+        config.base_url = "localhost 11434".to_string();
+        let err = DynClientBuilder::global()
+            .agent(DefaultProviders::Ollama, config)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            ClientBuildError::InvalidConfig(ClientBuilderError::InvalidProperty("base_url"))
+        ));
+    }
+
+    #[test]
+    fn factory_panics_keep_the_message() {
+        let factory = ClientFactory::new(DefaultProviders::Mock, |_| panic!("no credentials"));
+        let err = factory.build(config("mock-panic")).unwrap_err();
+        assert_eq!(err.to_string(), "factory error: no credentials");
+    }
 }
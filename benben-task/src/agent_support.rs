@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fmt, sync::Arc};
+use std::{fmt, str::FromStr, sync::Arc};
 
 use once_cell::sync::OnceCell;
 use rig::{
@@ -9,12 +9,30 @@ use rig_deepseek::completion::DsCompletionModel;
 use rig_ollama::completion::OllamaCompletionModel;
 use serde_json;
 
-use crate::agent_builder::{ClientFactory, DynClientBuilder};
+use crate::agent_builder::{ClientBuildError, ClientFactory, DynClientBuilder};
+use crate::global::InitError;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DefaultProviders {
     Deepseek,
     Ollama,
+    /// 返回预设响应的 [rig::client::mock::MockClient]，只用于测试
+    #[cfg(any(test, feature = "mock"))]
+    Mock,
+}
+
+impl FromStr for DefaultProviders {
+    type Err = ClientBuildError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "deepseek" => Ok(DefaultProviders::Deepseek),
+            "ollama" => Ok(DefaultProviders::Ollama),
+            #[cfg(any(test, feature = "mock"))]
+            "mock" => Ok(DefaultProviders::Mock),
+            _ => Err(ClientBuildError::UnknownProvider),
+        }
+    }
 }
 
 impl fmt::Display for DefaultProviders {
@@ -22,28 +40,38 @@ impl fmt::Display for DefaultProviders {
         match self {
             DefaultProviders::Deepseek => write!(f, "deepseek"),
             DefaultProviders::Ollama => write!(f, "ollama"),
+            #[cfg(any(test, feature = "mock"))]
+            DefaultProviders::Mock => write!(f, "mock"),
         }
     }
 }
 
 static INST: OnceCell<Arc<DynClientBuilder>> = OnceCell::new();
 impl<'a> DynClientBuilder {
+    /// 全局构建器，没有通过 [DynClientBuilder::init] 设置时使用注册了默认provider的构建器
     pub fn global() -> Arc<DynClientBuilder> {
-        if INST.get().is_none() {
-            if INST.set(Arc::new(Self::new())).is_err() {
-                panic!("Client Factory init Failed!")
-            }
-        }
-        let x = INST.get().expect("Builder not initialized");
-        x.clone()
+        INST.get_or_init(|| Arc::new(Self::new())).clone()
+    }
+
+    /// 已经设置或者创建的全局构建器
+    pub fn try_global() -> Result<Arc<DynClientBuilder>, InitError> {
+        INST.get()
+            .cloned()
+            .ok_or(InitError::NotInitialized("client builder"))
     }
 
-    fn new() -> Self {
+    /// 设置全局构建器，例如注册了额外provider的构建器，必须在第一次使用之前调用
+    pub fn init(builder: DynClientBuilder) -> Result<Arc<DynClientBuilder>, InitError> {
+        let builder = Arc::new(builder);
+        INST.set(builder.clone())
+            .map_err(|_| InitError::AlreadyInitialized("client builder"))?;
+        Ok(builder)
+    }
+
+    /// 注册了默认provider的构建器
+    pub fn new() -> Self {
         // 这里可以控制feature 进行条件装填。
-        Self {
-            registry: HashMap::new(),
-        }
-        .register_all(vec![
+        Self::default().register_all(vec![
             ClientFactory::new(
                 DefaultProviders::Ollama,
                 rig_ollama::client::Client::from_config,
@@ -52,6 +80,11 @@ impl<'a> DynClientBuilder {
                 DefaultProviders::Deepseek,
                 rig_deepseek::client::Client::from_config,
             ),
+            #[cfg(any(test, feature = "mock"))]
+            ClientFactory::new(
+                DefaultProviders::Mock,
+                rig::client::mock::MockClient::from_config,
+            ),
         ])
     }
 }
@@ -105,7 +138,28 @@ impl SupportFindTrait for EnvAgentFinder {
 /// ollama.sys_promte=
 /// ollama.mcp=
 /// ollama.mcp.path=
+/// ollama.mcp.roots=["./workspace/task-42"]
 /// ollama.mcp.addtion_key={"",""}
+/// ollama.fallback=[{"provider":"deepseek","model":"deepseek-chat"}]
+/// ollama.pool=["http://host1:11434","http://host2:11434"]
+/// ollama.pool_strategy=round_robin | least_in_flight
+/// ollama.rate_limit={"requests_per_minute":60,"tokens_per_minute":100000}
+/// ollama.tools=["add","subtract"]
+/// ollama.vram_mb=6000
+/// ollama.http_allowlist=["api.internal","*.corp.example"]
+/// ollama.http_max_response_bytes=262144
+/// ollama.size_limit={"max_request_bytes":1048576,"max_prompt_tokens":8192,"truncate_history":true,"summarize_documents":true,"context_windows":{"qwen2.5:7b":32768}}
+/// ollama.redaction={"patterns":[{"name":"phone","regex":"1\\d{10}"}],"deny_keys":["password"]}
+/// ollama.max_turns=8
+/// ollama.tool_loop_limit=3
+/// ollama.timeout=120
+/// ollama.connect_timeout=10
+/// ollama.proxy={"url":"socks5://proxy.corp:1080","username":"u","password":"p"}
+/// ollama.root_certificates=["./certs/gateway.pem"]
+/// ollama.headers={"OpenAI-Organization":"org-benben"}
+/// ollama.think=false
+/// ollama.few_shot=3
+/// ollama.max_concurrency=1
 /// ollama1.model=
 /// ollama1.api_key=
 /// ....
@@ -142,13 +196,107 @@ fn from_env(id: &str, provider: DefaultProviders) -> Option<AgentConfOwn> {
     let sys_promte = std::env::var(format!("{}.sys_promte", id)).ok();
     let mcp = std::env::var(format!("{}.mcp", id)).ok();
 
-    let mcp: McpType = if let Some(mcp) = mcp {
+    let mut mcp: McpType = if let Some(mcp) = mcp {
         serde_json::from_str(&mcp).unwrap_or(McpType::Nothing)
     } else {
         McpType::Nothing
     };
 
-    let mcp: McpType = serde_json::from_str("").unwrap();
+    if let McpType::STDIO(ref mut mcp_stdio) = mcp {
+        if let Some(roots) = std::env::var(format!("{}.mcp.roots", id))
+            .ok()
+            .and_then(|roots| serde_json::from_str(&roots).ok())
+        {
+            mcp_stdio.roots = roots;
+        }
+    }
+
+    let fallback = std::env::var(format!("{}.fallback", id))
+        .ok()
+        .and_then(|fallback| serde_json::from_str(&fallback).ok())
+        .unwrap_or_default();
+
+    let pool = std::env::var(format!("{}.pool", id))
+        .ok()
+        .and_then(|pool| serde_json::from_str(&pool).ok())
+        .unwrap_or_default();
+
+    let pool_strategy = std::env::var(format!("{}.pool_strategy", id))
+        .ok()
+        .and_then(|strategy| serde_json::from_value(serde_json::Value::String(strategy)).ok())
+        .unwrap_or_default();
+
+    let rate_limit = std::env::var(format!("{}.rate_limit", id))
+        .ok()
+        .and_then(|rate_limit| serde_json::from_str(&rate_limit).ok());
+
+    let tools = std::env::var(format!("{}.tools", id))
+        .ok()
+        .and_then(|tools| serde_json::from_str(&tools).ok())
+        .unwrap_or_default();
+
+    let vram_mb = std::env::var(format!("{}.vram_mb", id))
+        .ok()
+        .and_then(|vram_mb| vram_mb.parse().ok());
+
+    let http_allowlist = std::env::var(format!("{}.http_allowlist", id))
+        .ok()
+        .and_then(|allowlist| serde_json::from_str(&allowlist).ok())
+        .unwrap_or_default();
+
+    let http_max_response_bytes = std::env::var(format!("{}.http_max_response_bytes", id))
+        .ok()
+        .and_then(|max| max.parse().ok());
+
+    let size_limit = std::env::var(format!("{}.size_limit", id))
+        .ok()
+        .and_then(|size_limit| serde_json::from_str(&size_limit).ok());
+
+    let redaction = std::env::var(format!("{}.redaction", id))
+        .ok()
+        .and_then(|redaction| serde_json::from_str(&redaction).ok());
+
+    let max_turns = std::env::var(format!("{}.max_turns", id))
+        .ok()
+        .and_then(|max_turns| max_turns.parse().ok());
+
+    let tool_loop_limit = std::env::var(format!("{}.tool_loop_limit", id))
+        .ok()
+        .and_then(|limit| limit.parse().ok());
+
+    let timeout = std::env::var(format!("{}.timeout", id))
+        .ok()
+        .and_then(|timeout| timeout.parse().ok());
+
+    let connect_timeout = std::env::var(format!("{}.connect_timeout", id))
+        .ok()
+        .and_then(|timeout| timeout.parse().ok());
+
+    let proxy = std::env::var(format!("{}.proxy", id))
+        .ok()
+        .and_then(|proxy| serde_json::from_str(&proxy).ok());
+
+    let root_certificates = std::env::var(format!("{}.root_certificates", id))
+        .ok()
+        .and_then(|certificates| serde_json::from_str(&certificates).ok())
+        .unwrap_or_default();
+
+    let headers = std::env::var(format!("{}.headers", id))
+        .ok()
+        .and_then(|headers| serde_json::from_str(&headers).ok())
+        .unwrap_or_default();
+
+    let think = std::env::var(format!("{}.think", id))
+        .ok()
+        .and_then(|think| think.parse().ok());
+
+    let max_concurrency = std::env::var(format!("{}.max_concurrency", id))
+        .ok()
+        .and_then(|max_concurrency| max_concurrency.parse().ok());
+
+    let few_shot = std::env::var(format!("{}.few_shot", id))
+        .ok()
+        .and_then(|few_shot| few_shot.parse().ok());
 
     Some(AgentConfOwn {
         provider,
@@ -162,6 +310,26 @@ fn from_env(id: &str, provider: DefaultProviders) -> Option<AgentConfOwn> {
             api_key,
             sys_promte,
             mcp,
+            fallback,
+            pool,
+            pool_strategy,
+            rate_limit,
+            tools,
+            vram_mb,
+            http_allowlist,
+            http_max_response_bytes,
+            size_limit,
+            redaction,
+            max_turns,
+            tool_loop_limit,
+            timeout,
+            connect_timeout,
+            proxy,
+            root_certificates,
+            headers,
+            think,
+            max_concurrency,
+            few_shot,
         },
     })
 }
@@ -0,0 +1,78 @@
+//! 对外接口共享的错误结构。
+//!
+//! HTTP / gRPC 等对外层统一返回 [ErrorEnvelope]，调用方根据 `code` 与 `retryable` 做判断，
+//! 不需要解析错误文本。`code` 的取值是稳定的，新增错误时只能追加。结构定义在 `benben-api`
+//! 中，与 `benben-client` 共用，这里负责把引擎的错误转换为对应的错误码。
+
+use rig::completion::{CompletionError, ProviderErrorKind};
+
+use crate::agent_builder::ClientBuildError;
+use crate::engine::TaskEngineError;
+
+pub use benben_api::{ErrorCode, ErrorEnvelope};
+
+/// provider 错误对应的错误信息，限流时在 `details` 中带上建议的重试间隔
+pub fn provider_error(err: &CompletionError) -> ErrorEnvelope {
+    let code = match err.provider_kind() {
+        Some(ProviderErrorKind::RateLimited { .. }) => ErrorCode::ProviderRateLimited,
+        Some(ProviderErrorKind::ContextLengthExceeded) => ErrorCode::ProviderContextLengthExceeded,
+        Some(ProviderErrorKind::AuthFailed) => ErrorCode::ProviderAuthFailed,
+        Some(ProviderErrorKind::ModelNotFound) => ErrorCode::ProviderModelNotFound,
+        Some(ProviderErrorKind::Timeout) => ErrorCode::ProviderTimeout,
+        Some(ProviderErrorKind::Unavailable) => ErrorCode::ProviderUnavailable,
+        _ => ErrorCode::ProviderError,
+    };
+    let mut envelope = ErrorEnvelope::new(code, err.to_string()).retryable(err.is_retryable());
+    if let Some(retry_after) = err.retry_after() {
+        envelope = envelope.details(serde_json::json!({
+            "retry_after_secs": retry_after.as_secs_f64()
+        }));
+    }
+    envelope
+}
+
+impl From<&ClientBuildError> for ErrorEnvelope {
+    fn from(err: &ClientBuildError) -> Self {
+        let code = match err {
+            ClientBuildError::InvalidIdString(_)
+            | ClientBuildError::UnsupportedFeature(_, _)
+            | ClientBuildError::UnknownProvider
+            | ClientBuildError::InvalidMcpRoot(_, _)
+            | ClientBuildError::UnknownTool(_) => ErrorCode::InvalidArgument,
+            _ => ErrorCode::Internal,
+        };
+        Self::new(code, err.to_string())
+    }
+}
+
+impl From<&TaskEngineError> for ErrorEnvelope {
+    fn from(err: &TaskEngineError) -> Self {
+        let code = match err {
+            TaskEngineError::Provider(err) => return provider_error(err),
+            TaskEngineError::NotFound(_) => ErrorCode::NotFound,
+            TaskEngineError::InvalidTransition { .. } | TaskEngineError::Cancelled(_) => {
+                ErrorCode::InvalidState
+            }
+            TaskEngineError::Conflict(_) => ErrorCode::Conflict,
+            TaskEngineError::Rejected(_) => ErrorCode::PolicyRejected,
+            TaskEngineError::Timeout(_) => ErrorCode::Timeout,
+            TaskEngineError::Unavailable(_) => ErrorCode::Unavailable,
+            TaskEngineError::Db(_) | TaskEngineError::Other(_) => ErrorCode::Internal,
+        };
+        Self::new(code, err.to_string()).retryable(err.is_retryable())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_wire_format() {
+        let err = CompletionError::provider("rate limit reached");
+        let json = serde_json::to_value(provider_error(&err)).unwrap();
+        assert_eq!(json["code"], "PROVIDER_RATE_LIMITED");
+        assert_eq!(json["retryable"], true);
+        assert!(json.get("details").is_none());
+    }
+}
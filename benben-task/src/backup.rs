@@ -0,0 +1,303 @@
+//! 引擎数据的备份与恢复。
+//!
+//! 备份文件是一个带版本号的json文档，包含所有实体的数据。恢复时先校验归档格式和
+//! 表结构版本，只允许恢复到空的数据库中，用于灾备以及环境复制。
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sea_orm::{
+    ActiveModelTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, PaginatorTrait,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::entities::{
+    agent_config, agent_example, dataset_item, job, job_run, owner_usage, plan, prompt_version,
+    task, task_event, task_transition, tool_log, workflow, workflow_version, SCHEMA_VERSION,
+};
+use crate::migrate::{create_schema, reset_all_sequences};
+use crate::soft_delete::SoftDelete;
+
+/// 归档格式版本
+pub const ARCHIVE_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("database error: {0}")]
+    Db(#[from] DbErr),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("archive error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unsupported archive format version {0}")]
+    UnsupportedFormat(u32),
+    #[error(
+        "archive schema version {archive} is not compatible with current schema version {current}"
+    )]
+    IncompatibleSchema { archive: u32, current: u32 },
+    #[error("target database is not empty: table {0} has rows")]
+    TargetNotEmpty(String),
+}
+
+/// 备份归档
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Archive {
+    pub format_version: u32,
+    pub schema_version: u32,
+    /// 备份时间，unix 秒
+    pub created_at: u64,
+    pub workflows: Vec<workflow::Model>,
+    pub jobs: Vec<job::Model>,
+    pub tasks: Vec<task::Model>,
+    pub plans: Vec<plan::Model>,
+    pub tool_logs: Vec<tool_log::Model>,
+    pub agent_configs: Vec<agent_config::Model>,
+    #[serde(default)]
+    pub task_events: Vec<task_event::Model>,
+    #[serde(default)]
+    pub workflow_versions: Vec<workflow_version::Model>,
+    #[serde(default)]
+    pub job_runs: Vec<job_run::Model>,
+    #[serde(default)]
+    pub task_transitions: Vec<task_transition::Model>,
+    #[serde(default)]
+    pub dataset_items: Vec<dataset_item::Model>,
+    #[serde(default)]
+    pub agent_examples: Vec<agent_example::Model>,
+    #[serde(default)]
+    pub prompt_versions: Vec<prompt_version::Model>,
+    #[serde(default)]
+    pub owner_usages: Vec<owner_usage::Model>,
+}
+
+impl Archive {
+    /// 校验归档是否可以恢复到当前版本
+    pub fn validate(&self) -> Result<(), BackupError> {
+        if self.format_version != ARCHIVE_FORMAT_VERSION {
+            return Err(BackupError::UnsupportedFormat(self.format_version));
+        }
+        if self.schema_version != SCHEMA_VERSION {
+            return Err(BackupError::IncompatibleSchema {
+                archive: self.schema_version,
+                current: SCHEMA_VERSION,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// 导出数据库中的实体，不包括已删除的工作流、job和agent配置
+pub async fn snapshot(db: &DatabaseConnection) -> Result<Archive, BackupError> {
+    export(db, false).await
+}
+
+/// 导出数据库中的所有实体，包括已删除的记录，恢复后仍然可以撤销删除
+pub async fn snapshot_with_deleted(db: &DatabaseConnection) -> Result<Archive, BackupError> {
+    export(db, true).await
+}
+
+async fn export(db: &DatabaseConnection, include_deleted: bool) -> Result<Archive, BackupError> {
+    let (workflows, jobs, agent_configs) = if include_deleted {
+        (
+            workflow::Entity::find().all(db).await?,
+            job::Entity::find().all(db).await?,
+            agent_config::Entity::find().all(db).await?,
+        )
+    } else {
+        (
+            workflow::Entity::find_active().all(db).await?,
+            job::Entity::find_active().all(db).await?,
+            agent_config::Entity::find_active().all(db).await?,
+        )
+    };
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    Ok(Archive {
+        format_version: ARCHIVE_FORMAT_VERSION,
+        schema_version: SCHEMA_VERSION,
+        created_at,
+        workflows,
+        jobs,
+        tasks: task::Entity::find().all(db).await?,
+        plans: plan::Entity::find().all(db).await?,
+        tool_logs: tool_log::Entity::find().all(db).await?,
+        agent_configs,
+        task_events: task_event::Entity::find().all(db).await?,
+        workflow_versions: workflow_version::Entity::find().all(db).await?,
+        job_runs: job_run::Entity::find().all(db).await?,
+        task_transitions: task_transition::Entity::find().all(db).await?,
+        dataset_items: dataset_item::Entity::find().all(db).await?,
+        agent_examples: agent_example::Entity::find().all(db).await?,
+        prompt_versions: prompt_version::Entity::find().all(db).await?,
+        owner_usages: owner_usage::Entity::find().all(db).await?,
+    })
+}
+
+/// 将归档恢复到一个空数据库中
+pub async fn restore(db: &DatabaseConnection, archive: Archive) -> Result<(), BackupError> {
+    archive.validate()?;
+    create_schema(db).await?;
+
+    ensure_empty(db, workflow::Entity).await?;
+    ensure_empty(db, job::Entity).await?;
+    ensure_empty(db, task::Entity).await?;
+    ensure_empty(db, plan::Entity).await?;
+    ensure_empty(db, tool_log::Entity).await?;
+    ensure_empty(db, agent_config::Entity).await?;
+    ensure_empty(db, task_event::Entity).await?;
+    ensure_empty(db, workflow_version::Entity).await?;
+    ensure_empty(db, job_run::Entity).await?;
+    ensure_empty(db, task_transition::Entity).await?;
+    ensure_empty(db, dataset_item::Entity).await?;
+    ensure_empty(db, agent_example::Entity).await?;
+    ensure_empty(db, prompt_version::Entity).await?;
+    ensure_empty(db, owner_usage::Entity).await?;
+
+    insert_all::<workflow::Entity, _>(db, archive.workflows).await?;
+    insert_all::<job::Entity, _>(db, archive.jobs).await?;
+    insert_all::<task::Entity, _>(db, archive.tasks).await?;
+    insert_all::<plan::Entity, _>(db, archive.plans).await?;
+    insert_all::<tool_log::Entity, _>(db, archive.tool_logs).await?;
+    insert_all::<agent_config::Entity, _>(db, archive.agent_configs).await?;
+    insert_all::<task_event::Entity, _>(db, archive.task_events).await?;
+    insert_all::<workflow_version::Entity, _>(db, archive.workflow_versions).await?;
+    insert_all::<job_run::Entity, _>(db, archive.job_runs).await?;
+    insert_all::<task_transition::Entity, _>(db, archive.task_transitions).await?;
+    insert_all::<dataset_item::Entity, _>(db, archive.dataset_items).await?;
+    insert_all::<agent_example::Entity, _>(db, archive.agent_examples).await?;
+    insert_all::<prompt_version::Entity, _>(db, archive.prompt_versions).await?;
+    insert_all::<owner_usage::Entity, _>(db, archive.owner_usages).await?;
+
+    reset_all_sequences(db).await?;
+    Ok(())
+}
+
+/// 备份到文件
+pub async fn backup_to_file(db: &DatabaseConnection, path: &Path) -> Result<Archive, BackupError> {
+    let archive = snapshot(db).await?;
+    tokio::fs::write(path, serde_json::to_vec_pretty(&archive)?).await?;
+    Ok(archive)
+}
+
+/// 从文件恢复
+pub async fn restore_from_file(db: &DatabaseConnection, path: &Path) -> Result<(), BackupError> {
+    let archive: Archive = read_json(path).await?;
+    restore(db, archive).await
+}
+
+async fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, BackupError> {
+    let bytes = tokio::fs::read(path).await?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+async fn ensure_empty<E>(db: &DatabaseConnection, entity: E) -> Result<(), BackupError>
+where
+    E: EntityTrait,
+    E::Model: Sync,
+{
+    if E::find().count(db).await? > 0 {
+        return Err(BackupError::TargetNotEmpty(entity.table_name().to_string()));
+    }
+    Ok(())
+}
+
+async fn insert_all<E, A>(db: &DatabaseConnection, rows: Vec<E::Model>) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+    E::Model: IntoActiveModel<A>,
+    A: ActiveModelTrait<Entity = E>,
+{
+    // 分批写入，避免单条语句的参数数量超过数据库限制
+    let mut rows = rows.into_iter().peekable();
+    while rows.peek().is_some() {
+        let batch: Vec<A> = rows
+            .by_ref()
+            .take(100)
+            .map(|row| row.into_active_model())
+            .collect();
+        E::insert_many(batch).exec_without_returning(db).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::{ActiveValue::Set, Database};
+
+    #[tokio::test]
+    async fn snapshot_and_restore_round_trip() {
+        let source = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&source).await.unwrap();
+        workflow::ActiveModel {
+            id: Set("wf".to_string()),
+            deleted: Set(false),
+            ..Default::default()
+        }
+        .insert(&source)
+        .await
+        .unwrap();
+        workflow::ActiveModel {
+            id: Set("old".to_string()),
+            deleted: Set(true),
+            ..Default::default()
+        }
+        .insert(&source)
+        .await
+        .unwrap();
+        assert_eq!(
+            snapshot_with_deleted(&source)
+                .await
+                .unwrap()
+                .workflows
+                .len(),
+            2
+        );
+
+        let archive = snapshot(&source).await.unwrap();
+        let json = serde_json::to_string(&archive).unwrap();
+        let archive: Archive = serde_json::from_str(&json).unwrap();
+
+        let target = Database::connect("sqlite::memory:").await.unwrap();
+        restore(&target, archive.clone()).await.unwrap();
+        assert_eq!(workflow::Entity::find().count(&target).await.unwrap(), 1);
+
+        // 不允许恢复到非空数据库
+        assert!(matches!(
+            restore(&target, archive).await,
+            Err(BackupError::TargetNotEmpty(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_newer_schema() {
+        let archive = Archive {
+            format_version: ARCHIVE_FORMAT_VERSION,
+            schema_version: SCHEMA_VERSION + 1,
+            created_at: 0,
+            workflows: vec![],
+            jobs: vec![],
+            tasks: vec![],
+            plans: vec![],
+            tool_logs: vec![],
+            agent_configs: vec![],
+            task_events: vec![],
+            workflow_versions: vec![],
+            job_runs: vec![],
+            task_transitions: vec![],
+            dataset_items: vec![],
+            agent_examples: vec![],
+            prompt_versions: vec![],
+            owner_usages: vec![],
+        };
+        assert!(matches!(
+            archive.validate(),
+            Err(BackupError::IncompatibleSchema { .. })
+        ));
+    }
+}
@@ -0,0 +1,45 @@
+//! benben 命令行工具
+//!
+//! benben backup <database_url> <file>    导出所有数据到归档文件
+//! benben restore <database_url> <file>   将归档文件恢复到空数据库
+
+use std::path::Path;
+
+use benben_task::backup;
+use benben_task::database::DatabaseConfig;
+
+const USAGE: &str = "usage: benben <backup|restore> <database_url> <file>";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [command, url, file] = args.as_slice() else {
+        eprintln!("{USAGE}");
+        std::process::exit(2);
+    };
+
+    let db = DatabaseConfig::new(url.as_str()).connect().await?;
+    match command.as_str() {
+        "backup" => {
+            let archive = backup::backup_to_file(&db, Path::new(file)).await?;
+            println!(
+                "backup written to {}: {} workflows, {} jobs, {} tasks",
+                file,
+                archive.workflows.len(),
+                archive.jobs.len(),
+                archive.tasks.len()
+            );
+        }
+        "restore" => {
+            backup::restore_from_file(&db, Path::new(file)).await?;
+            println!("restored {} into {}", file, url);
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    }
+    Ok(())
+}
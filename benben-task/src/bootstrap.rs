@@ -0,0 +1,148 @@
+//! 首次运行时的本地模型准备。
+//!
+//! 新机器上配置的 ollama 模型可能还没有下载。开启后在创建agent之前，按 base_url
+//! 对照 `/api/tags` 检查所有 ollama 配置的模型，缺失的模型通过 `/api/pull` 下载，
+//! 进度通过广播通道发布。全部完成或超时后agent才会创建。
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use rig_ollama::client::Client;
+use rig_ollama::pull::PullProgress;
+use thiserror::Error;
+use tokio::sync::broadcast;
+
+use crate::agent_support::{AgentConfOwn, DefaultProviders};
+
+#[derive(Debug, Error)]
+pub enum BootstrapError {
+    #[error("model bootstrap timed out after {0:?}")]
+    Timeout(Duration),
+}
+
+/// 模型准备过程中发布的事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum BootstrapEvent {
+    /// 缺失的模型
+    Missing {
+        base_url: String,
+        models: Vec<String>,
+    },
+    /// 下载进度
+    Progress {
+        model: String,
+        progress: PullProgress,
+    },
+    /// 下载完成
+    Pulled { model: String },
+    /// 检查或下载失败
+    Failed { model: String, error: String },
+}
+
+/// 模型准备结果
+#[derive(Debug, Clone, Default)]
+pub struct BootstrapReport {
+    pub pulled: Vec<String>,
+    /// 失败的模型以及原因
+    pub failed: Vec<(String, String)>,
+}
+
+pub struct ModelBootstrap {
+    timeout: Duration,
+    events: broadcast::Sender<BootstrapEvent>,
+}
+
+impl ModelBootstrap {
+    pub fn new(timeout: Duration) -> Self {
+        let (events, _) = broadcast::channel(256);
+        Self { timeout, events }
+    }
+
+    /// 订阅准备过程中的事件
+    pub fn subscribe(&self) -> broadcast::Receiver<BootstrapEvent> {
+        self.events.subscribe()
+    }
+
+    /// 检查并下载所有 ollama 配置缺失的模型，超时返回错误
+    pub async fn run(&self, configs: &[AgentConfOwn]) -> Result<BootstrapReport, BootstrapError> {
+        tokio::time::timeout(self.timeout, self.pull_missing(configs))
+            .await
+            .map_err(|_| BootstrapError::Timeout(self.timeout))
+    }
+
+    async fn pull_missing(&self, configs: &[AgentConfOwn]) -> BootstrapReport {
+        // 同一个地址的模型只检查一次
+        let mut servers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for conf in configs {
+            if conf.provider == DefaultProviders::Ollama {
+                servers
+                    .entry(conf.config.base_url.clone())
+                    .or_default()
+                    .push(conf.config.model.clone());
+            }
+        }
+
+        let mut report = BootstrapReport::default();
+        for (base_url, models) in servers {
+            let client = match Client::builder().base_url(&base_url).build() {
+                Ok(client) => client,
+                Err(e) => {
+                    self.fail_all(&mut report, &models, e.to_string());
+                    continue;
+                }
+            };
+            let missing = match client.missing_models(&models).await {
+                Ok(missing) => missing,
+                Err(e) => {
+                    self.fail_all(&mut report, &models, e.to_string());
+                    continue;
+                }
+            };
+            if missing.is_empty() {
+                continue;
+            }
+            self.publish(BootstrapEvent::Missing {
+                base_url: base_url.clone(),
+                models: missing.clone(),
+            });
+
+            for model in missing {
+                tracing::info!("pulling ollama model {} from {}", model, base_url);
+                let result = client
+                    .pull_model(&model, |progress| {
+                        self.publish(BootstrapEvent::Progress {
+                            model: model.clone(),
+                            progress: progress.clone(),
+                        })
+                    })
+                    .await;
+                match result {
+                    Ok(()) => {
+                        self.publish(BootstrapEvent::Pulled {
+                            model: model.clone(),
+                        });
+                        report.pulled.push(model);
+                    }
+                    Err(e) => self.fail_all(&mut report, &[model], e.to_string()),
+                }
+            }
+        }
+        report
+    }
+
+    fn fail_all(&self, report: &mut BootstrapReport, models: &[String], error: String) {
+        for model in models {
+            tracing::error!("bootstrap of ollama model {} failed: {}", model, error);
+            self.publish(BootstrapEvent::Failed {
+                model: model.clone(),
+                error: error.clone(),
+            });
+            report.failed.push((model.clone(), error.clone()));
+        }
+    }
+
+    fn publish(&self, event: BootstrapEvent) {
+        // 没有订阅者时忽略
+        let _ = self.events.send(event);
+    }
+}
@@ -0,0 +1,226 @@
+//! agent之间的委派工具。
+//!
+//! AgentConfig 的 tools 中包含 `call_agent` 的agent可以发出 `call_agent{code, prompt}` 的工具调用，
+//! 工具从 [AgentManager] 中按 code 找到目标agent，执行 prompt 后把回答作为工具结果返回，
+//! 调度agent不需要写死路由就可以组合多层agent。
+//!
+//! 目标agent同样可以继续委派，嵌套超过 [CallAgentTool::DEFAULT_MAX_DEPTH] 层时拒绝，
+//! 避免agent之间互相调用形成死循环。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use rig::agent::Agent;
+use rig::client::completion::CompletionModelHandle;
+use rig::completion::{Prompt, PromptError};
+use rig::tool::Tool;
+use rmcp::model::Tool as ToolDefinition;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::mananger::AgentManager;
+
+type SharedAgent = Arc<Agent<CompletionModelHandle<'static>>>;
+
+tokio::task_local! {
+    /// 当前的委派层数
+    static DEPTH: usize;
+}
+
+#[derive(Debug, Error)]
+pub enum CallAgentError {
+    #[error("agent manager not initialized")]
+    NoManager,
+    #[error("agent not found: {0}")]
+    NotFound(String),
+    #[error("agent call depth exceeds {0}")]
+    TooDeep(usize),
+    #[error("agent {0} failed: {1}")]
+    Prompt(String, Box<PromptError>),
+}
+
+/// 工具参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CallAgentArgs {
+    /// 目标agent的code
+    pub code: String,
+    pub prompt: String,
+}
+
+pub struct CallAgentTool {
+    /// 为空时使用全局的 [AgentManager]
+    agents: Option<HashMap<String, SharedAgent>>,
+    max_depth: usize,
+    /// 目标agent处理工具调用的最大轮数
+    max_turns: usize,
+}
+
+impl Default for CallAgentTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CallAgentTool {
+    pub const DEFAULT_MAX_DEPTH: usize = 3;
+
+    /// 从全局 [AgentManager] 查找目标agent
+    pub fn new() -> Self {
+        Self {
+            agents: None,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+            max_turns: 5,
+        }
+    }
+
+    /// 只允许委派给指定的agent
+    pub fn with_agents(agents: HashMap<String, SharedAgent>) -> Self {
+        Self {
+            agents: Some(agents),
+            ..Self::new()
+        }
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = max_turns;
+        self
+    }
+
+    fn agent(&self, code: &str) -> Result<SharedAgent, CallAgentError> {
+        let found = match &self.agents {
+            Some(agents) => agents.get(code).cloned(),
+            None => AgentManager::global()
+                .ok_or(CallAgentError::NoManager)?
+                .agent_map
+                .get(code)
+                .cloned(),
+        };
+        found.ok_or_else(|| CallAgentError::NotFound(code.to_string()))
+    }
+
+    /// 可委派的agent，code 和描述
+    fn targets(&self) -> Vec<(String, String)> {
+        let mut targets: Vec<(String, String)> = match &self.agents {
+            Some(agents) => agents
+                .iter()
+                .map(|(code, agent)| (code.clone(), agent.description.clone().unwrap_or_default()))
+                .collect(),
+            None => AgentManager::global()
+                .map(|manager| {
+                    manager
+                        .agent_vec
+                        .iter()
+                        .filter(|config| manager.agent_map.contains_key(&config.code))
+                        .map(|config| (config.code.clone(), config.desc.clone()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+        targets.sort();
+        targets
+    }
+}
+
+impl Tool for CallAgentTool {
+    const NAME: &'static str = "call_agent";
+
+    type Args = CallAgentArgs;
+    type Output = String;
+    type Error = CallAgentError;
+
+    fn definition(&self) -> ToolDefinition {
+        let targets = self.targets();
+        let codes: Vec<&String> = targets.iter().map(|(code, _)| code).collect();
+        let mut description = "Delegate a task to another agent and return its answer.".to_string();
+        for (code, desc) in &targets {
+            description += &format!("\n- {code}: {desc}");
+        }
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "code": { "type": "string", "enum": codes, "description": "code of the agent" },
+                "prompt": { "type": "string", "description": "what the agent should do" }
+            },
+            "required": ["code", "prompt"]
+        });
+        ToolDefinition::new(
+            Self::NAME,
+            description,
+            schema.as_object().cloned().unwrap_or_default(),
+        )
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let depth = DEPTH.try_with(|depth| *depth).unwrap_or(0);
+        if depth >= self.max_depth {
+            return Err(CallAgentError::TooDeep(self.max_depth));
+        }
+        let agent = self.agent(&args.code)?;
+        tracing::info!(target: "benben", "Delegating to agent {} at depth {}", args.code, depth + 1);
+        DEPTH
+            .scope(depth + 1, async {
+                agent.prompt(args.prompt).multi_turn(self.max_turns).await
+            })
+            .await
+            .map_err(|e| CallAgentError::Prompt(args.code, Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::agent::AgentBuilder;
+    use rig::client::mock::{self, MockClient};
+    use rig::client::CompletionClient;
+    use serde_json::json;
+
+    fn mock_agent(model: &str, tool: Option<CallAgentTool>) -> SharedAgent {
+        let handle = CompletionModelHandle {
+            inner: Arc::new(MockClient::new().completion_model(model)),
+        };
+        let mut builder = AgentBuilder::new(handle).description("mock");
+        if let Some(tool) = tool {
+            builder = builder.tool(tool);
+        }
+        Arc::new(builder.build())
+    }
+
+    #[tokio::test]
+    async fn dispatcher_delegates_to_agent() {
+        mock::script("call-agent-worker").push_text("42");
+        let dispatcher_script = mock::script("call-agent-dispatcher");
+        dispatcher_script
+            .push_tool_call("call_agent", json!({"code": "worker", "prompt": "6 * 7"}));
+        dispatcher_script.push_text("答案是42");
+
+        let worker = mock_agent("call-agent-worker", None);
+        let tool = CallAgentTool::with_agents(HashMap::from([("worker".to_string(), worker)]));
+        assert!(tool
+            .definition()
+            .description
+            .unwrap_or_default()
+            .contains("- worker: mock"));
+        let dispatcher = mock_agent("call-agent-dispatcher", Some(tool));
+
+        let answer = dispatcher.prompt("算一下").multi_turn(2).await.unwrap();
+        assert_eq!(answer, "答案是42");
+        let worker_requests = mock::script("call-agent-worker").requests();
+        assert_eq!(worker_requests.len(), 1);
+        assert_eq!(dispatcher_script.requests().len(), 2);
+
+        let tool = CallAgentTool::with_agents(HashMap::new()).max_depth(0);
+        let args = CallAgentArgs {
+            code: "worker".to_string(),
+            prompt: String::new(),
+        };
+        assert!(matches!(
+            tool.call(args).await,
+            Err(CallAgentError::TooDeep(0))
+        ));
+    }
+}
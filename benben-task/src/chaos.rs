@@ -0,0 +1,73 @@
+//! 任务引擎的故障注入，只在开启 `chaos` feature 时编译，用于恢复、重试相关的集成测试。
+//!
+//! 安装 [JobFaults] 后 `execute_job` 会按照固定种子伪随机地杀掉job，同样的种子每次
+//! 杀掉的job顺序一致，测试结果可以复现。provider 响应丢失、mcp 调用延迟见 `rig::chaos`。
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+static FAULTS: RwLock<Option<Arc<JobFaults>>> = RwLock::new(None);
+
+/// job 故障配置
+#[derive(Debug)]
+pub struct JobFaults {
+    /// 杀掉job的概率，0.0 ~ 1.0
+    kill_probability: f64,
+    state: AtomicU64,
+}
+
+impl JobFaults {
+    pub fn new(kill_probability: f64, seed: u64) -> Self {
+        Self {
+            kill_probability: kill_probability.clamp(0.0, 1.0),
+            // xorshift 的状态不能为0
+            state: AtomicU64::new(seed.max(1)),
+        }
+    }
+
+    fn kill_job(&self) -> bool {
+        let mut x = self.state.load(Ordering::SeqCst);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state.store(x, Ordering::SeqCst);
+        (x as f64 / u64::MAX as f64) < self.kill_probability
+    }
+}
+
+/// 安装job故障，替换之前的配置
+pub fn install(faults: JobFaults) {
+    *FAULTS.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(faults));
+}
+
+/// 清除job故障
+pub fn clear() {
+    *FAULTS.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// 当前执行的job是否应该被杀掉
+pub(crate) fn should_kill_job() -> bool {
+    FAULTS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .is_some_and(|faults| faults.kill_job())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_kills_same_jobs() {
+        let a = JobFaults::new(0.5, 42);
+        let b = JobFaults::new(0.5, 42);
+        let a: Vec<bool> = (0..32).map(|_| a.kill_job()).collect();
+        let b: Vec<bool> = (0..32).map(|_| b.kill_job()).collect();
+        assert_eq!(a, b);
+        assert!(a.contains(&true) && a.contains(&false));
+
+        let never = JobFaults::new(0.0, 42);
+        assert!((0..32).all(|_| !never.kill_job()));
+    }
+}
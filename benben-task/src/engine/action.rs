@@ -0,0 +1,174 @@
+//! 计划中派发给job的动作。
+//!
+//! 规划agent在派发job时可以为每个job指定生成参数，例如抽取类job使用 temperature 0，
+//! 头脑风暴类job使用 0.8。参数在执行前按照引擎配置的上下限裁剪，再覆盖到agent的
+//! CompletionRequestBuilder 上。旧的纯文本 action 视为没有参数覆盖的prompt。
+//!
+//! `think` 控制推理模型是否思考：简单的格式化步骤关闭思考以降低延迟和token消耗，
+//! 关键的规划步骤打开思考换取质量。
+
+use rig::agent::Agent;
+use rig::completion::{
+    Completion, CompletionError, CompletionModel, CompletionRequestBuilder, CompletionResponse,
+};
+use serde::{Deserialize, Serialize};
+
+use super::evidence::StepCheck;
+use super::guardrail::{GuardrailError, GuardrailFailure, Guardrails};
+
+/// 单个job的生成参数，为空的字段沿用agent的配置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "http-api", derive(utoipa::ToSchema))]
+pub struct GenerationParams {
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    /// 推理模型是否思考，为空时沿用agent的配置
+    #[serde(default)]
+    pub think: Option<bool>,
+    /// provider 相关的额外参数，例如 top_p
+    #[serde(default)]
+    #[cfg_attr(feature = "http-api", schema(value_type = Option<Object>))]
+    pub additional_params: Option<serde_json::Value>,
+}
+
+impl GenerationParams {
+    pub fn is_empty(&self) -> bool {
+        self.temperature.is_none()
+            && self.max_tokens.is_none()
+            && self.think.is_none()
+            && self.additional_params.is_none()
+    }
+
+    /// 按照上下限裁剪参数
+    pub fn bounded(mut self, bounds: &ParamBounds) -> Self {
+        self.temperature = self
+            .temperature
+            .map(|t| t.clamp(bounds.min_temperature, bounds.max_temperature));
+        if let Some(limit) = bounds.max_tokens {
+            self.max_tokens = self.max_tokens.map(|n| n.min(limit));
+        }
+        self
+    }
+
+    /// 覆盖请求中的参数
+    pub fn apply<M: CompletionModel>(
+        &self,
+        builder: CompletionRequestBuilder<M>,
+    ) -> CompletionRequestBuilder<M> {
+        let builder = match self.temperature {
+            Some(temperature) => builder.temperature(temperature),
+            None => builder,
+        };
+        let builder = match self.max_tokens {
+            Some(max_tokens) => builder.max_tokens(max_tokens),
+            None => builder,
+        };
+        let builder = match self.think {
+            Some(think) => builder.think(think),
+            None => builder,
+        };
+        match &self.additional_params {
+            Some(params) => builder.additional_params(params.clone()),
+            None => builder,
+        }
+    }
+}
+
+/// 规划agent可以设置的参数范围
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParamBounds {
+    pub min_temperature: f64,
+    pub max_temperature: f64,
+    /// 单个job允许的最大输出token数
+    pub max_tokens: Option<u64>,
+}
+
+impl Default for ParamBounds {
+    fn default() -> Self {
+        Self {
+            min_temperature: 0.0,
+            max_temperature: 1.0,
+            max_tokens: None,
+        }
+    }
+}
+
+/// job.action 的结构化内容
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JobAction {
+    pub prompt: String,
+    #[serde(default)]
+    pub params: GenerationParams,
+    /// 输出的后处理与校验
+    #[serde(default)]
+    pub guardrails: Guardrails,
+    /// 前置/后置检查点，见 [super::evidence]
+    #[serde(default)]
+    pub check: Option<StepCheck>,
+    /// 预估费用超过该金额时暂停任务等待确认，美元，见 [super::budget]
+    #[serde(default)]
+    pub requires_confirmation_if_cost_above: Option<f64>,
+}
+
+impl JobAction {
+    /// 解析 job.action，不是json对象时整体作为prompt
+    pub fn parse(action: &str) -> Self {
+        serde_json::from_str(action).unwrap_or_else(|_| Self {
+            prompt: action.to_string(),
+            params: GenerationParams::default(),
+            guardrails: Guardrails::default(),
+            check: None,
+            requires_confirmation_if_cost_above: None,
+        })
+    }
+
+    pub fn bounded(mut self, bounds: &ParamBounds) -> Self {
+        self.params = self.params.bounded(bounds);
+        self
+    }
+
+    /// 使用agent执行该动作，job参数覆盖agent的默认参数
+    pub async fn complete<M: CompletionModel>(
+        &self,
+        agent: &Agent<M>,
+    ) -> Result<CompletionResponse<M::Response>, CompletionError> {
+        let builder = agent.completion(self.prompt.as_str(), vec![]).await?;
+        self.params.apply(builder).send().await
+    }
+
+    /// 使用agent执行该动作并经过后处理链，校验失败时重新提问
+    pub async fn complete_checked<M: CompletionModel>(
+        &self,
+        agent: &Agent<M>,
+    ) -> Result<(String, Vec<GuardrailFailure>), GuardrailError> {
+        self.guardrails
+            .complete(agent, &self.prompt, |builder| self.params.apply(builder))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_params_within_bounds() {
+        let action = JobAction::parse(
+            r#"{"prompt":"brainstorm names","params":{"temperature":1.5,"max_tokens":4000,"think":false}}"#,
+        )
+        .bounded(&ParamBounds {
+            max_tokens: Some(1000),
+            ..Default::default()
+        });
+        assert_eq!(action.prompt, "brainstorm names");
+        assert_eq!(action.params.temperature, Some(1.0));
+        assert_eq!(action.params.max_tokens, Some(1000));
+        assert_eq!(action.params.think, Some(false));
+
+        let plain = JobAction::parse("extract the invoice number");
+        assert_eq!(plain.prompt, "extract the invoice number");
+        assert!(plain.params.is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+//! 汇总job。
+//!
+//! map-reduce 形式的调研、总结类工作流中，多个分支各自产出部分结果，最后由一个agent合并。
+//! 类型为 [AGGREGATE_JOB_TYPE] 的job在 action 中声明 [AggregateSpec]：
+//!
+//! ```json
+//! {"prompt": "Merge the findings about {{ task.input }}", "jobs": [3, 4, 5]}
+//! ```
+//!
+//! 执行时收集本任务中 `jobs` 的输出（为空时收集所有已完成的job），`batch` 为 true 时再收集
+//! 任务变量 `batch` 指定的批次中其他任务的输出，见 [super::batch]。各分支的输出依次附在渲染后的
+//! prompt 之后，由job的agent生成合并的结果。有分支还没有完成时job失败，等分支完成后再次执行。
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::batch::BATCH_TAG;
+use super::query::TaskFilter;
+use super::subworkflow::child_output;
+use super::{TaskEngine, TaskEngineError, TaskState};
+
+/// 汇总job的类型
+pub const AGGREGATE_JOB_TYPE: &str = "aggregate";
+
+/// 汇总job的 action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregateSpec {
+    /// 合并的要求，支持prompt模板
+    pub prompt: String,
+    /// 汇总这些job的输出，为空时汇总本任务所有已完成的job
+    #[serde(default)]
+    pub jobs: Vec<i32>,
+    /// 同时汇总同一批次中其他任务的输出
+    #[serde(default)]
+    pub batch: bool,
+}
+
+/// 一个分支的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Branch {
+    Job(i32),
+    /// 同一批次的任务
+    Task(i32),
+}
+
+/// 一个分支的输出
+#[derive(Debug, Clone, PartialEq)]
+pub struct BranchOutput {
+    pub branch: Branch,
+    pub output: String,
+}
+
+impl AggregateSpec {
+    pub fn parse(action: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(action)
+    }
+
+    /// 本任务中要汇总的job的输出，按job id排列；有job还没有完成时返回这些job
+    pub fn job_outputs(
+        &self,
+        outputs: &HashMap<i32, String>,
+    ) -> Result<Vec<BranchOutput>, Vec<i32>> {
+        let mut jobs = if self.jobs.is_empty() {
+            outputs.keys().copied().collect()
+        } else {
+            self.jobs.clone()
+        };
+        jobs.sort();
+        let missing: Vec<i32> = jobs
+            .iter()
+            .copied()
+            .filter(|job_id| !outputs.contains_key(job_id))
+            .collect();
+        if !missing.is_empty() {
+            return Err(missing);
+        }
+        Ok(jobs
+            .into_iter()
+            .map(|job_id| BranchOutput {
+                branch: Branch::Job(job_id),
+                output: outputs[&job_id].clone(),
+            })
+            .collect())
+    }
+}
+
+/// 把各分支的输出附在prompt之后
+pub fn compose(prompt: &str, branches: &[BranchOutput]) -> String {
+    let mut composed = prompt.trim_end().to_string();
+    for branch in branches {
+        let title = match branch.branch {
+            Branch::Job(id) => format!("Output of job {id}"),
+            Branch::Task(id) => format!("Output of task {id}"),
+        };
+        composed.push_str(&format!("\n\n## {title}\n\n{}", branch.output.trim()));
+    }
+    composed
+}
+
+impl TaskEngine {
+    /// 任务变量 `batch` 指定的批次中其他任务的输出，按任务id排列。
+    /// 调用时不能持有任何任务的锁，有任务还没有结束时返回错误
+    pub(crate) async fn batch_outputs(
+        &self,
+        task_id: i32,
+    ) -> Result<Vec<BranchOutput>, TaskEngineError> {
+        let batch = self
+            .tasks
+            .lock(task_id)
+            .await
+            .and_then(|c| c.variables.get(BATCH_TAG).cloned())
+            .ok_or_else(|| format!("Task {} has no batch to aggregate", task_id))?;
+        let page = self
+            .query_tasks(&TaskFilter::default().tag(BATCH_TAG, batch.clone()))
+            .await?;
+        let mut branches = Vec::new();
+        let mut pending = Vec::new();
+        for task in page.tasks.into_iter().filter(|t| t.id != task_id) {
+            match task.state.as_deref().and_then(TaskState::parse) {
+                Some(TaskState::Finished) => {}
+                Some(state) if state.is_terminal() => continue,
+                _ => {
+                    pending.push(task.id);
+                    continue;
+                }
+            }
+            let output = match self.tasks.lock(task.id).await {
+                Some(context) => child_output(&context),
+                None => task.output.unwrap_or_default(),
+            };
+            branches.push(BranchOutput {
+                branch: Branch::Task(task.id),
+                output,
+            });
+        }
+        if !pending.is_empty() {
+            return Err(format!("Batch {} still has unfinished tasks {:?}", batch, pending).into());
+        }
+        branches.sort_by_key(|b| match b.branch {
+            Branch::Job(id) | Branch::Task(id) => id,
+        });
+        Ok(branches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::engine::fixtures::{self, agent_job, downstream};
+    use crate::engine::simulation::StubAgents;
+    use crate::entities::job;
+
+    #[tokio::test]
+    async fn branches_are_merged_by_the_aggregate_agent() {
+        let stubs = Arc::new(
+            StubAgents::new()
+                .with_handler("researcher", |call| Ok(format!("facts on {}", call.prompt)))
+                .with_handler("merger", |call| Ok(call.prompt.clone())),
+        );
+        let engine = TaskEngine::new().with_stub_agents(stubs);
+        let task_id = engine.create_task("rust".into()).await.unwrap();
+        let jobs = vec![
+            agent_job(1, "researcher", "history of {{ task.input }}"),
+            agent_job(2, "researcher", "users of {{ task.input }}"),
+            downstream(
+                2,
+                job::Model {
+                    r#type: Some(AGGREGATE_JOB_TYPE.into()),
+                    ..agent_job(
+                        3,
+                        "merger",
+                        r#"{"prompt": "Summarize {{ task.input }}", "jobs": [1, 2]}"#,
+                    )
+                },
+            ),
+        ];
+        let report = engine
+            .simulate(task_id, fixtures::workflow("1"), &jobs, 0)
+            .await
+            .unwrap();
+        assert_eq!(
+            report.outputs[&3],
+            "Summarize rust\n\n## Output of job 1\n\nfacts on history of rust\
+             \n\n## Output of job 2\n\nfacts on users of rust"
+        );
+
+        // 汇总同一批次中其他任务的输出
+        let batch = engine
+            .start_batch_with(fixtures::workflow("1"), vec!["a".into(), "b".into()])
+            .await
+            .unwrap();
+        let reducer = engine.create_task("all".into()).await.unwrap();
+        engine
+            .set_variable(reducer, BATCH_TAG, &batch.id)
+            .await
+            .unwrap();
+        let merge = job::Model {
+            r#type: Some(AGGREGATE_JOB_TYPE.into()),
+            ..agent_job(
+                4,
+                "merger",
+                r#"{"prompt": "Merge", "jobs": [], "batch": true}"#,
+            )
+        };
+        assert!(engine.execute_job(reducer, merge.clone()).await.is_err());
+        for id in &batch.task_ids {
+            engine.start(*id).await.unwrap();
+            engine
+                .execute_job(*id, agent_job(1, "researcher", "{{ task.input }}"))
+                .await
+                .unwrap();
+            engine.finish(*id).await.unwrap();
+        }
+        let merged = engine.execute_job(reducer, merge).await.unwrap();
+        assert!(merged.contains(&format!(
+            "## Output of task {}\n\nfacts on b",
+            batch.task_ids[1]
+        )));
+    }
+}
@@ -0,0 +1,192 @@
+//! job的执行记录。
+//!
+//! 每次执行job都在 `job_run` 表写入一条记录：这个job的第几次尝试、执行的agent、渲染后的
+//! prompt、输出、token用量、耗时和结果。tool_log 只保留通过或被拒绝的输出，job_run 保留
+//! 每一次尝试，重试、失败以及中断的执行都可以逐条排查。开始执行时先写入 running 的记录，
+//! 进程在执行过程中崩溃时留下未完成的记录，流式执行的检查点也写在这条记录上，见 [super::checkpoint]。
+
+use rig::completion::Usage;
+use serde::{Deserialize, Serialize};
+
+use super::{TaskEngine, TaskEngineError};
+use crate::entities::job_run;
+use crate::mananger::AgentManager;
+
+/// 一次尝试的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Running,
+    Success,
+    Failure,
+    /// 输出未通过后处理链
+    Rejected,
+}
+
+impl RunStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunStatus::Running => "running",
+            RunStatus::Success => "success",
+            RunStatus::Failure => "failure",
+            RunStatus::Rejected => "rejected",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<RunStatus> {
+        match status {
+            "running" => Some(RunStatus::Running),
+            "success" => Some(RunStatus::Success),
+            "failure" => Some(RunStatus::Failure),
+            "rejected" => Some(RunStatus::Rejected),
+            _ => None,
+        }
+    }
+}
+
+impl TaskEngine {
+    /// 开始一次尝试并写入存储，尝试次数按这个job已有的记录递增
+    pub(crate) async fn begin_run(
+        &self,
+        task_id: i32,
+        job_id: i32,
+        agent: Option<String>,
+        prompt: Option<String>,
+    ) -> Result<job_run::Model, TaskEngineError> {
+        let now = self.clock.now_millis();
+        let mut run = job_run::Model {
+            id: 0,
+            taskid: task_id,
+            job_id,
+            output: String::new(),
+            chunks: 0,
+            finished: false,
+            started_at: now,
+            updated_at: now,
+            attempt: 1,
+            agent,
+            prompt,
+            input_tokens: 0,
+            output_tokens: 0,
+            duration_ms: 0,
+            status: RunStatus::Running.as_str().to_string(),
+            prompt_version: None,
+        };
+        if let (Some(manager), Some(agent)) = (AgentManager::global(), run.agent.as_deref()) {
+            run.prompt_version = manager.prompt_version(agent);
+        }
+        if let Some(store) = self.store() {
+            run.attempt = store
+                .load_job_runs(task_id)
+                .await?
+                .iter()
+                .filter(|r| r.job_id == job_id)
+                .map(|r| r.attempt)
+                .max()
+                .unwrap_or_default()
+                + 1;
+            run.id = store.save_job_run(run.clone()).await?;
+        }
+        Ok(run)
+    }
+
+    /// 结束一次尝试，记录结果、耗时以及模型上报的用量
+    pub(crate) async fn finish_run(
+        &self,
+        mut run: job_run::Model,
+        status: RunStatus,
+        usage: Option<Usage>,
+    ) -> Result<job_run::Model, TaskEngineError> {
+        self.close_run(&mut run, status, usage);
+        if let Some(store) = self.store() {
+            store.save_job_run(run.clone()).await?;
+        }
+        Ok(run)
+    }
+
+    /// 记录尝试的结果、耗时以及用量，不写入存储
+    pub(crate) fn close_run(
+        &self,
+        run: &mut job_run::Model,
+        status: RunStatus,
+        usage: Option<Usage>,
+    ) {
+        let now = self.clock.now_millis();
+        run.finished = true;
+        run.updated_at = now;
+        run.duration_ms = now - run.started_at;
+        run.status = status.as_str().to_string();
+        if let Some(usage) = usage {
+            run.input_tokens = usage.input_tokens as i64;
+            run.output_tokens = usage.output_tokens as i64;
+        }
+    }
+
+    /// 任务的执行记录，按开始顺序排列，指定 `job_id` 时只返回这个job的尝试
+    pub async fn get_job_runs(
+        &self,
+        task_id: i32,
+        job_id: Option<i32>,
+    ) -> Result<Vec<job_run::Model>, TaskEngineError> {
+        let store = self
+            .store()
+            .ok_or_else(|| TaskEngineError::Unavailable("Task engine has no store".to_string()))?;
+        Ok(store
+            .load_job_runs(task_id)
+            .await?
+            .into_iter()
+            .filter(|run| job_id.is_none_or(|job_id| run.job_id == job_id))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::engine::fixtures;
+    use crate::engine::store::MemoryStore;
+    use crate::entities::job;
+
+    #[tokio::test]
+    async fn every_attempt_is_recorded() {
+        let mut engine = TaskEngine::new().with_store(Arc::new(MemoryStore::new()));
+        engine.init(1, "orders".to_string()).await.unwrap();
+        engine.start(1).await.unwrap();
+        let job = fixtures::agent_job(
+            7,
+            "counter",
+            r#"{"prompt": "count {{ task.input }}",
+                "guardrails": {"steps": [{"type": "banned", "words": ["executed"]}]}}"#,
+        );
+
+        assert!(matches!(
+            engine.execute_job(1, job.clone()).await,
+            Err(TaskEngineError::Rejected(_))
+        ));
+        engine
+            .execute_job(
+                1,
+                job::Model {
+                    action: Some("count {{ task.input }}".into()),
+                    ..job
+                },
+            )
+            .await
+            .unwrap();
+
+        let runs = engine.get_job_runs(1, Some(7)).await.unwrap();
+        let attempts: Vec<(i32, &str)> = runs
+            .iter()
+            .map(|r| (r.attempt, r.status.as_str()))
+            .collect();
+        assert_eq!(attempts, vec![(1, "rejected"), (2, "success")]);
+        assert_eq!(runs[1].prompt.as_deref(), Some("count orders"));
+        assert_eq!(runs[1].agent.as_deref(), Some("counter"));
+        assert!(runs.iter().all(|r| r.finished));
+
+        let report = engine.build_report(1).await.unwrap();
+        assert_eq!(report.steps[0].attempt, 2);
+    }
+}
@@ -0,0 +1,211 @@
+//! 批量提交任务。
+//!
+//! 同一个工作流处理一批输入（例如CSV的每一行）时，[TaskEngine::start_batch] 为每个输入创建一个
+//! 任务并关联工作流，任务等待调度器启动。同一批的任务带有相同的 `batch` 标签，见 [super::query]，
+//! 可以按批次汇总进度以及整批取消：
+//!
+//! ```rust,ignore
+//! let batch = engine.start_batch("42", rows).await?;
+//! let progress = engine.batch_progress(&batch.id).await?;
+//! engine.cancel_batch(&batch.id, |_| {}).await?;
+//! ```
+
+use sea_orm::EntityTrait;
+use serde::Serialize;
+
+use super::bulk::{BulkAction, BulkProgress, BulkReport};
+use super::query::TaskFilter;
+use super::schema::TaskSchemas;
+use super::{TaskEngine, TaskEngineError, TaskState};
+use crate::entities::workflow;
+
+/// 标记任务所属批次的标签
+pub const BATCH_TAG: &str = "batch";
+
+/// 一次批量提交
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Batch {
+    pub id: String,
+    /// 按输入顺序排列的任务id
+    pub task_ids: Vec<i32>,
+}
+
+/// 一批任务的进度
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BatchProgress {
+    pub total: usize,
+    pub waiting: usize,
+    pub pending: usize,
+    pub running: usize,
+    pub stopped: usize,
+    pub finished: usize,
+    pub cancelled: usize,
+}
+
+impl BatchProgress {
+    /// 已经结束的任务数
+    pub fn done(&self) -> usize {
+        self.finished + self.cancelled
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done() == self.total
+    }
+}
+
+impl TaskEngine {
+    /// 按数据库中的工作流批量创建任务
+    pub async fn start_batch(
+        &self,
+        workflow_id: &str,
+        inputs: Vec<String>,
+    ) -> Result<Batch, TaskEngineError> {
+        let db = self.db().ok_or_else(|| {
+            TaskEngineError::Unavailable("Batch submission requires a database".to_string())
+        })?;
+        let workflow = workflow::Entity::find_by_id(workflow_id.to_string())
+            .one(db.as_ref())
+            .await?
+            .filter(|w| !w.deleted)
+            .ok_or_else(|| TaskEngineError::NotFound(format!("Workflow {workflow_id}")))?;
+        self.start_batch_with(workflow, inputs).await
+    }
+
+    /// 为每个输入创建一个关联 `workflow` 的任务。先校验所有输入，有输入不符合工作流的输入schema时
+    /// 不创建任何任务
+    pub async fn start_batch_with(
+        &self,
+        workflow: workflow::Model,
+        inputs: Vec<String>,
+    ) -> Result<Batch, TaskEngineError> {
+        self.ensure_writable()?;
+        let schemas = TaskSchemas::from_workflow(&workflow)?;
+        for input in &inputs {
+            schemas.validate_input(input)?;
+        }
+
+        let mut task_ids = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            let task_id = self.create_task(input).await?;
+            self.attach_workflow(task_id, workflow.clone()).await?;
+            task_ids.push(task_id);
+        }
+        // 任务id不会重复，第一个任务的id即可区分批次
+        let id = format!(
+            "{}-{}",
+            workflow.id,
+            task_ids.first().copied().unwrap_or_default()
+        );
+        for task_id in &task_ids {
+            self.set_tag(*task_id, BATCH_TAG, id.clone()).await?;
+        }
+        tracing::info!(
+            "batch {} submitted with {} tasks of workflow {}",
+            id,
+            task_ids.len(),
+            workflow.id
+        );
+        Ok(Batch { id, task_ids })
+    }
+
+    /// 批次中的任务id
+    pub async fn batch_task_ids(&self, batch_id: &str) -> Result<Vec<i32>, TaskEngineError> {
+        let page = self
+            .query_tasks(&TaskFilter::default().tag(BATCH_TAG, batch_id))
+            .await?;
+        Ok(page.tasks.iter().map(|t| t.id).collect())
+    }
+
+    /// 按任务状态汇总批次的进度
+    pub async fn batch_progress(&self, batch_id: &str) -> Result<BatchProgress, TaskEngineError> {
+        let page = self
+            .query_tasks(&TaskFilter::default().tag(BATCH_TAG, batch_id))
+            .await?;
+        if page.total == 0 {
+            return Err(TaskEngineError::NotFound(format!("Batch {batch_id}")));
+        }
+        let mut progress = BatchProgress {
+            total: page.total,
+            ..Default::default()
+        };
+        for task in &page.tasks {
+            match task.state.as_deref().and_then(TaskState::parse) {
+                Some(TaskState::Waiting) | None => progress.waiting += 1,
+                Some(TaskState::Pending) => progress.pending += 1,
+                Some(TaskState::Running) => progress.running += 1,
+                Some(TaskState::Stopped) => progress.stopped += 1,
+                Some(TaskState::Finished) => progress.finished += 1,
+                Some(TaskState::Cancelled) => progress.cancelled += 1,
+            }
+        }
+        Ok(progress)
+    }
+
+    /// 取消批次中所有未结束的任务
+    pub async fn cancel_batch<F>(
+        &self,
+        batch_id: &str,
+        progress: F,
+    ) -> Result<BulkReport, TaskEngineError>
+    where
+        F: FnMut(&BulkProgress),
+    {
+        let ids = self.active_ids(self.batch_task_ids(batch_id).await?).await;
+        Ok(self.bulk_apply(ids, BulkAction::Cancel, progress).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::engine::fixtures;
+    use crate::engine::store::MemoryStore;
+
+    #[tokio::test]
+    async fn batch_is_tracked_and_cancelled_together() {
+        let engine = TaskEngine::new().with_store(Arc::new(MemoryStore::new()));
+        let workflow = workflow::Model {
+            input_schema: Some(r#"{"type": "object", "required": ["order"]}"#.into()),
+            ..fixtures::workflow("7")
+        };
+        let rows = |n: i32| (1..=n).map(|i| format!(r#"{{"order": {i}}}"#)).collect();
+
+        let invalid = vec![r#"{"order": 1}"#.to_string(), "{}".to_string()];
+        assert!(engine
+            .start_batch_with(workflow.clone(), invalid)
+            .await
+            .is_err());
+        assert!(engine.tasks.ids().is_empty());
+
+        let batch = engine
+            .start_batch_with(workflow.clone(), rows(3))
+            .await
+            .unwrap();
+        let other = engine.start_batch_with(workflow, rows(1)).await.unwrap();
+        assert_eq!(batch.task_ids.len(), 3);
+        assert_ne!(batch.id, other.id);
+        assert_eq!(
+            engine.batch_task_ids(&batch.id).await.unwrap(),
+            batch.task_ids
+        );
+
+        engine.start(batch.task_ids[0]).await.unwrap();
+        engine.finish(batch.task_ids[0]).await.unwrap();
+        engine.start(batch.task_ids[1]).await.unwrap();
+        let progress = engine.batch_progress(&batch.id).await.unwrap();
+        assert_eq!(
+            (progress.finished, progress.running, progress.waiting),
+            (1, 1, 1)
+        );
+
+        let report = engine.cancel_batch(&batch.id, |_| {}).await.unwrap();
+        assert_eq!(report.succeeded, batch.task_ids[1..].to_vec());
+        assert!(engine.batch_progress(&batch.id).await.unwrap().is_done());
+        assert_eq!(
+            engine.get_state(other.task_ids[0]).await.unwrap(),
+            TaskState::Waiting
+        );
+    }
+}
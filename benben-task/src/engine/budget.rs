@@ -0,0 +1,182 @@
+//! 昂贵步骤前的预算检查点。
+//!
+//! job的 action 可以声明 `requires_confirmation_if_cost_above`，单位美元：
+//!
+//! ```json
+//! {"prompt": "review every module of {{ task.input }}", "requires_confirmation_if_cost_above": 0.5}
+//! ```
+//!
+//! 执行这个job前，任务的累计费用加上这一步的预估费用超过阈值时，引擎不再继续花费，而是暂停任务，
+//! 以 [BlockReason::AwaitingBudget] 阻塞并附带 [BudgetReport]。这一步的费用按任务已完成job的
+//! 平均费用预估；这一步的prompt较长时按prompt的token数和任务每个token的平均费用预估，token数使用
+//! agent模型的分词器计算，见 [rig::tokens::counter_for]。人工通过 [TaskEngine::approve_job]
+//! 确认后任务恢复执行，再次执行该job；也可以跳过审批类的阻塞条件。
+//!
+//! [BlockReason::AwaitingBudget]: super::queue::BlockReason::AwaitingBudget
+//! [TaskEngine::approve_job]: super::TaskEngine::approve_job
+
+use rig::completion::Usage;
+use serde::Serialize;
+
+use super::TaskContext;
+
+/// 预算检查点暂停任务时的费用报告，金额单位美元
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetReport {
+    pub job_id: i32,
+    pub threshold: f64,
+    /// 任务已经花费的费用
+    pub accumulated: f64,
+    /// 这一步的预估费用
+    pub projected_step: f64,
+    /// 已完成的job数
+    pub steps: usize,
+    /// 这一步prompt的token数
+    pub prompt_tokens: u64,
+    /// 任务累计的token用量
+    pub usage: Usage,
+}
+
+impl BudgetReport {
+    /// 执行这一步之后的预估总费用
+    pub fn projected_total(&self) -> f64 {
+        self.accumulated + self.projected_step
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "projected cost ${:.4} (spent ${:.4} over {} steps, next step ~${:.4}) exceeds ${:.4}",
+            self.projected_total(),
+            self.accumulated,
+            self.steps,
+            self.projected_step,
+            self.threshold
+        )
+    }
+}
+
+/// 按模型的分词器计算prompt的token数
+pub fn prompt_tokens(model: &str, prompt: &str) -> u64 {
+    rig::tokens::counter_for(model).count(prompt)
+}
+
+/// 下一个job的预估费用：任务已完成job的平均费用，与prompt按任务每个token的平均费用计算的费用中
+/// 较大的一个。还没有完成的job时为0
+pub fn projected_step_cost(context: &TaskContext, prompt_tokens: u64) -> f64 {
+    if context.step == 0 {
+        return 0.0;
+    }
+    let per_step = context.cost / context.step as f64;
+    let per_token = match context.usage.total_tokens {
+        0 => 0.0,
+        tokens => context.cost / tokens as f64,
+    };
+    per_step.max(per_token * prompt_tokens as f64)
+}
+
+/// 执行job前检查预算，预估总费用超过阈值时返回报告
+pub fn check_budget(
+    job_id: i32,
+    threshold: Option<f64>,
+    context: &TaskContext,
+    prompt_tokens: u64,
+) -> Option<BudgetReport> {
+    let threshold = threshold?;
+    let report = BudgetReport {
+        job_id,
+        threshold,
+        accumulated: context.cost,
+        projected_step: projected_step_cost(context, prompt_tokens),
+        steps: context.step,
+        prompt_tokens,
+        usage: context.usage,
+    };
+    (report.projected_total() > threshold).then_some(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::fixtures;
+    use crate::engine::queue::BlockReason;
+    use crate::engine::{TaskEngine, TaskEngineError, TaskState};
+
+    #[tokio::test]
+    async fn expensive_job_waits_for_confirmation() {
+        let mut engine = TaskEngine::new();
+        engine.init(1, "audit".to_string()).await.unwrap();
+        engine.start(1).await.unwrap();
+        let gated = fixtures::job(
+            2,
+            r#"{"prompt": "review all", "requires_confirmation_if_cost_above": 0.5}"#,
+        );
+
+        engine
+            .execute_job(1, fixtures::job(1, "scan"))
+            .await
+            .unwrap();
+        engine
+            .record_model_usage(
+                1,
+                "deepseek",
+                "deepseek-chat",
+                Usage {
+                    input_tokens: 1_000_000,
+                    output_tokens: 0,
+                    total_tokens: 1_000_000,
+                    cached_input_tokens: 0,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            engine.execute_job(1, gated.clone()).await,
+            Err(TaskEngineError::Rejected(_))
+        ));
+        assert_eq!(engine.get_state(1).await.unwrap(), TaskState::Pending);
+        let queue = engine.inspect_queue().await;
+        let Some(BlockReason::AwaitingBudget { report }) = queue[0].reasons.first() else {
+            panic!("task is not waiting for budget: {:?}", queue);
+        };
+        assert!((report.projected_total() - 0.56).abs() < 1e-9);
+
+        engine.approve_job(1, 2).await.unwrap();
+        assert_eq!(engine.get_state(1).await.unwrap(), TaskState::Running);
+        assert!(engine.execute_job(1, gated).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn long_prompts_are_priced_by_tokens() {
+        let mut engine = TaskEngine::new();
+        engine.init(1, "audit".to_string()).await.unwrap();
+        engine.start(1).await.unwrap();
+        engine
+            .execute_job(1, fixtures::job(1, "scan"))
+            .await
+            .unwrap();
+        let usage = Usage {
+            input_tokens: 1_000,
+            output_tokens: 0,
+            total_tokens: 1_000,
+            cached_input_tokens: 0,
+        };
+        engine
+            .record_model_usage(1, "deepseek", "deepseek-chat", usage)
+            .await
+            .unwrap();
+
+        // 平均每步的费用远低于阈值，但这一步的prompt有约两万五千个token
+        let action = serde_json::json!({
+            "prompt": "review every module ".repeat(5_000),
+            "requires_confirmation_if_cost_above": 0.001,
+        });
+        let gated = fixtures::job(2, &action.to_string());
+        assert!(engine.execute_job(1, gated).await.is_err());
+        let queue = engine.inspect_queue().await;
+        let Some(BlockReason::AwaitingBudget { report }) = queue[0].reasons.first() else {
+            panic!("task is not waiting for budget: {:?}", queue);
+        };
+        assert!(report.prompt_tokens > 20_000);
+        assert!(report.projected_step > report.accumulated * 20.0);
+    }
+}
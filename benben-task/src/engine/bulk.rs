@@ -0,0 +1,258 @@
+//! 批量管理操作。
+//!
+//! 按工作流、用户、状态筛选出一批任务后统一执行 取消/暂停/恢复/停止，并通过回调汇报进度，
+//! 调用方不需要自己循环单个任务id。重试停止的任务时可以只选择某个时间之后停止的任务，
+//! 停止时间取自状态变更记录，见 [super::transition]。HTTP 接口在 `/bulk` 和
+//! `/workflows/{id}/*-tasks` 下提供这些操作，只作用于发起请求的用户的任务。
+
+use super::tenant::owner_of;
+use super::{TaskEngine, TaskState};
+
+/// 批量操作的动作
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BulkAction {
+    Cancel,
+    Pause,
+    Resume,
+    Stop,
+}
+
+/// 批量操作的进度
+#[derive(Debug, Clone, Default)]
+pub struct BulkProgress {
+    pub total: usize,
+    pub done: usize,
+    pub failed: usize,
+}
+
+/// 批量操作的结果
+#[derive(Debug, Clone, Default)]
+pub struct BulkReport {
+    pub succeeded: Vec<i32>,
+    /// 失败的任务id以及原因
+    pub failed: Vec<(i32, String)>,
+}
+
+impl TaskEngine {
+    /// 获取属于指定工作流的任务id
+    pub async fn task_ids_by_workflow(&self, workflow_id: &str) -> Vec<i32> {
+        self.tasks
+            .collect(|id, context| {
+                let matches = context.workflow.as_ref().is_some_and(|w| w.id == workflow_id)
+                    || context
+                        .task
+                        .as_ref()
+                        .and_then(|t| t.wid)
+                        .is_some_and(|wid| wid.to_string() == workflow_id);
+                matches.then_some(id)
+            })
+            .await
+    }
+
+    /// 获取属于指定用户的任务id
+    pub async fn task_ids_by_owner(&self, owner: &str) -> Vec<i32> {
+        self.tasks
+            .collect(|id, context| (owner_of(context) == Some(owner)).then_some(id))
+            .await
+    }
+
+    /// 获取处于指定状态的任务id
+    pub async fn task_ids_by_state(&self, state: TaskState) -> Vec<i32> {
+        self.tasks
+            .collect(|id, context| (context.state == state).then_some(id))
+            .await
+    }
+
+    /// 对一批任务执行同一个动作，每处理完一个任务调用一次 `progress`
+    pub async fn bulk_apply<F>(&self, task_ids: Vec<i32>, action: BulkAction, mut progress: F) -> BulkReport
+    where
+        F: FnMut(&BulkProgress),
+    {
+        let mut report = BulkReport::default();
+        let mut current = BulkProgress {
+            total: task_ids.len(),
+            ..Default::default()
+        };
+
+        for task_id in task_ids {
+            let result = match action {
+                BulkAction::Cancel => self.cancel(task_id).await,
+                BulkAction::Pause => self.pause(task_id).await,
+                BulkAction::Resume => self.resume(task_id).await,
+                BulkAction::Stop => self.stop(task_id).await,
+            };
+            match result {
+                Ok(()) => report.succeeded.push(task_id),
+                Err(e) => {
+                    current.failed += 1;
+                    report.failed.push((task_id, e.to_string()));
+                }
+            }
+            current.done += 1;
+            progress(&current);
+        }
+
+        tracing::info!(
+            "bulk {:?} finished: {} succeeded, {} failed",
+            action,
+            report.succeeded.len(),
+            report.failed.len()
+        );
+        report
+    }
+
+    /// 取消指定工作流下的所有未结束任务，指定 `owner` 时只取消该用户的任务
+    pub async fn cancel_workflow_tasks<F>(&self, workflow_id: &str, owner: Option<&str>, progress: F) -> BulkReport
+    where
+        F: FnMut(&BulkProgress),
+    {
+        let ids = self.owned_by(self.task_ids_by_workflow(workflow_id).await, owner).await;
+        let ids = self.active_ids(ids).await;
+        self.bulk_apply(ids, BulkAction::Cancel, progress).await
+    }
+
+    /// 暂停指定工作流下的所有未结束任务，指定 `owner` 时只暂停该用户的任务
+    pub async fn pause_workflow_tasks<F>(&self, workflow_id: &str, owner: Option<&str>, progress: F) -> BulkReport
+    where
+        F: FnMut(&BulkProgress),
+    {
+        let ids = self.owned_by(self.task_ids_by_workflow(workflow_id).await, owner).await;
+        let ids = self.active_ids(ids).await;
+        self.bulk_apply(ids, BulkAction::Pause, progress).await
+    }
+
+    /// 暂停指定用户的所有未结束任务，没有指定用户时暂停所有任务
+    pub async fn pause_owner_tasks<F>(&self, owner: Option<&str>, progress: F) -> BulkReport
+    where
+        F: FnMut(&BulkProgress),
+    {
+        let ids = self.active_ids(self.owned_by(self.tasks.ids(), owner).await).await;
+        self.bulk_apply(ids, BulkAction::Pause, progress).await
+    }
+
+    /// 恢复处于Stopped状态的任务。指定 `owner` 时只恢复该用户的任务，指定 `since`（unix 毫秒）
+    /// 时只恢复在这之后停止的任务
+    pub async fn resume_stopped_tasks<F>(&self, owner: Option<&str>, since: Option<i64>, progress: F) -> BulkReport
+    where
+        F: FnMut(&BulkProgress),
+    {
+        let stopped = self.task_ids_by_state(TaskState::Stopped).await;
+        let mut ids = self.owned_by(stopped, owner).await;
+        if let Some(since) = since {
+            ids = self.stopped_since(ids, since).await;
+        }
+        self.bulk_apply(ids, BulkAction::Resume, progress).await
+    }
+
+    /// 保留最近一次进入Stopped状态的时间不早于 `since` 的任务，没有存储时无法得知停止时间，不做筛选
+    async fn stopped_since(&self, ids: Vec<i32>, since: i64) -> Vec<i32> {
+        let Some(store) = self.store() else {
+            return ids;
+        };
+        let mut recent = Vec::new();
+        for id in ids {
+            let stopped_at = match store.load_transitions(id).await {
+                Ok(transitions) => transitions
+                    .iter()
+                    .rev()
+                    .find(|t| t.to_state == TaskState::Stopped.as_str())
+                    .map(|t| t.created_at),
+                Err(e) => {
+                    tracing::warn!("load transitions of task {} failed: {}", id, e);
+                    None
+                }
+            };
+            if stopped_at.is_some_and(|at| at >= since) {
+                recent.push(id);
+            }
+        }
+        recent
+    }
+
+    /// 只保留属于 `owner` 的任务，没有指定用户时不筛选
+    async fn owned_by(&self, ids: Vec<i32>, owner: Option<&str>) -> Vec<i32> {
+        match owner {
+            Some(owner) => {
+                let owned = self.task_ids_by_owner(owner).await;
+                ids.into_iter().filter(|id| owned.contains(id)).collect()
+            }
+            None => ids,
+        }
+    }
+
+    /// 过滤掉已经取消或完成的任务
+    pub(crate) async fn active_ids(&self, ids: Vec<i32>) -> Vec<i32> {
+        let mut active = Vec::new();
+        for id in ids {
+            let Some(context) = self.tasks.lock(id).await else {
+                continue;
+            };
+            if !matches!(context.state, TaskState::Cancelled | TaskState::Finished) {
+                active.push(id);
+            }
+        }
+        active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::engine::clock::{Clock, MockClock};
+    use crate::engine::store::MemoryStore;
+
+    #[tokio::test]
+    async fn cancel_all_tasks_of_workflow() {
+        let mut engine = TaskEngine::new();
+        for id in 1..=3 {
+            engine.init(id, format!("input {}", id)).await.unwrap();
+        }
+        for id in [1, 2] {
+            let mut context = engine.tasks.lock(id).await.unwrap();
+            context.task.as_mut().unwrap().wid = Some(7);
+        }
+
+        let mut seen = Vec::new();
+        let report = engine
+            .cancel_workflow_tasks("7", None, |p| seen.push(p.done))
+            .await;
+
+        assert_eq!(report.succeeded, vec![1, 2]);
+        assert!(report.failed.is_empty());
+        assert_eq!(seen, vec![1, 2]);
+        assert_eq!(engine.get_state(3).await.unwrap(), TaskState::Waiting);
+    }
+
+    #[tokio::test]
+    async fn retry_recently_stopped_tasks_of_one_owner() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let engine = TaskEngine::new()
+            .with_store(Arc::new(MemoryStore::new()))
+            .with_clock(clock.clone());
+        let old = engine.create_task_as("alice", "old".into()).await.unwrap();
+        let recent = engine.create_task_as("alice", "recent".into()).await.unwrap();
+        let other = engine.create_task_as("bob", "other".into()).await.unwrap();
+        for id in [old, recent, other] {
+            engine.start(id).await.unwrap();
+        }
+        engine.stop(old).await.unwrap();
+        clock.advance(Duration::from_secs(7200));
+        engine.stop(recent).await.unwrap();
+        engine.stop(other).await.unwrap();
+
+        let last_hour = clock.now_millis() - 3_600_000;
+        let report = engine
+            .resume_stopped_tasks(Some("alice"), Some(last_hour), |_| {})
+            .await;
+        assert_eq!(report.succeeded, vec![recent]);
+        assert_eq!(engine.get_state(old).await.unwrap(), TaskState::Stopped);
+        assert_eq!(engine.get_state(other).await.unwrap(), TaskState::Stopped);
+
+        let report = engine.pause_owner_tasks(Some("alice"), |_| {}).await;
+        assert_eq!(report.succeeded, vec![old, recent]);
+        assert_eq!(engine.get_state(other).await.unwrap(), TaskState::Stopped);
+    }
+}
@@ -0,0 +1,169 @@
+//! 大模型回答的缓存。
+//!
+//! 重放任务或者重新执行确定性的job（temperature 为 0）时，相同的请求会得到相同的回答，
+//! 不需要再次调用模型、消耗token。[DbCompletionCache] 先查内存中的LRU缓存，再查数据库的
+//! `completion_cache` 表，引擎重启之后缓存仍然有效。
+//!
+//! 通过 [TaskEngine::enable_completion_cache] 开启，之后创建的agent生效。
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::future::BoxFuture;
+use rig::client::cache::{CachedCompletion, CompletionCache, MemoryCache};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ActiveModelTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel};
+
+use crate::agent_builder::DynClientBuilder;
+use crate::engine::TaskEngine;
+use crate::entities::completion_cache;
+
+/// 内存 + 数据库的两级缓存
+pub struct DbCompletionCache {
+    memory: MemoryCache,
+    /// 为空时使用全局引擎当前的数据库连接，切换数据库后写入新库
+    db: Option<Arc<DatabaseConnection>>,
+}
+
+impl DbCompletionCache {
+    /// `capacity` 为内存中缓存的回答数
+    pub fn new(db: Arc<DatabaseConnection>, capacity: usize) -> Self {
+        Self {
+            memory: MemoryCache::new(capacity),
+            db: Some(db),
+        }
+    }
+
+    /// 使用全局引擎的数据库连接
+    pub fn for_engine(capacity: usize) -> Self {
+        Self {
+            memory: MemoryCache::new(capacity),
+            db: None,
+        }
+    }
+
+    fn db(&self) -> Option<Arc<DatabaseConnection>> {
+        self.db
+            .clone()
+            .or_else(|| TaskEngine::global().and_then(|engine| engine.db()))
+    }
+
+    async fn load(&self, key: &str) -> Result<Option<CachedCompletion>, DbErr> {
+        let Some(db) = self.db() else {
+            return Ok(None);
+        };
+        let Some(row) = completion_cache::Entity::find_by_id(key.to_string())
+            .one(db.as_ref())
+            .await?
+        else {
+            return Ok(None);
+        };
+        match serde_json::from_str(&row.response) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => {
+                tracing::warn!(target: "benben", "invalid cached completion {key}: {e}");
+                Ok(None)
+            }
+        }
+    }
+
+    async fn store(&self, key: &str, value: &CachedCompletion) -> Result<(), DbErr> {
+        let Some(db) = self.db() else {
+            return Ok(());
+        };
+        let response = serde_json::to_string(value).map_err(|e| DbErr::Custom(e.to_string()))?;
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        match completion_cache::Entity::find_by_id(key.to_string())
+            .one(db.as_ref())
+            .await?
+        {
+            Some(row) => {
+                let mut row = row.into_active_model();
+                row.response = Set(response);
+                row.created_at = Set(created_at);
+                row.update(db.as_ref()).await?;
+            }
+            None => {
+                completion_cache::ActiveModel {
+                    key: Set(key.to_string()),
+                    response: Set(response),
+                    created_at: Set(created_at),
+                }
+                .insert(db.as_ref())
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CompletionCache for DbCompletionCache {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<CachedCompletion>> {
+        Box::pin(async move {
+            if let Some(value) = self.memory.get(key).await {
+                return Some(value);
+            }
+            let value = self.load(key).await.unwrap_or_else(|e| {
+                tracing::warn!(target: "benben", "completion cache lookup failed: {e}");
+                None
+            })?;
+            self.memory.put(key, value.clone()).await;
+            Some(value)
+        })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, value: CachedCompletion) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            // 缓存写入失败不影响本次回答
+            if let Err(e) = self.store(key, &value).await {
+                tracing::warn!(target: "benben", "completion cache write failed: {e}");
+            }
+            self.memory.put(key, value).await;
+        })
+    }
+}
+
+impl TaskEngine {
+    /// 开启大模型回答的缓存，有数据库连接时缓存同时写入数据库。
+    /// 只对之后创建的agent生效，需要在 [crate::mananger::AgentManager] 初始化之前调用。
+    pub fn enable_completion_cache(&self, capacity: usize) {
+        let cache: Arc<dyn CompletionCache> = match self.db() {
+            Some(_) => Arc::new(DbCompletionCache::for_engine(capacity)),
+            None => Arc::new(MemoryCache::new(capacity)),
+        };
+        DynClientBuilder::global().set_completion_cache(cache);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrate::create_schema;
+    use rig::completion::{AssistantContent, Usage};
+    use rig::OneOrMany;
+    use sea_orm::Database;
+
+    #[tokio::test]
+    async fn cached_completion_survives_restart() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&db).await.unwrap();
+        let db = Arc::new(db);
+        let value = CachedCompletion {
+            choice: OneOrMany::one(AssistantContent::text("4")),
+            usage: Usage::new(),
+        };
+
+        let cache = DbCompletionCache::new(db.clone(), 4);
+        cache.put("k1", value.clone()).await;
+        cache.put("k1", value.clone()).await;
+
+        let restarted = DbCompletionCache::new(db, 4);
+        assert!(restarted.memory.is_empty());
+        assert_eq!(restarted.get("k1").await, Some(value));
+        assert_eq!(restarted.memory.len(), 1);
+        assert_eq!(restarted.get("k2").await, None);
+    }
+}
@@ -0,0 +1,304 @@
+//! job的检查agent（后置条件）。
+//!
+//! job.check 声明检查agent和检查要求：
+//!
+//! ```json
+//! {"agent": "reviewer", "prompt": "totals must match the input", "max_retries": 2}
+//! ```
+//!
+//! 不是json时整体作为检查agent的code。主agent给出通过后处理链的输出后，检查agent对照job的
+//! 目标判断结果：通过、带着反馈重试、或者判定这一步失败。重试时把上一次的回答和反馈追加到
+//! prompt中，最多重试 `max_retries` 次。每一轮的结论通过 [TaskEngine::record_check] 记录。
+
+use rig::agent::Agent;
+use rig::completion::{AssistantContent, Completion, CompletionError, CompletionModel};
+use serde::{Deserialize, Serialize};
+
+use super::action::JobAction;
+use super::guardrail::GuardrailError;
+use super::plan::PlanStatus;
+use super::replay::ToolLogArgs;
+use super::{TaskEngine, TaskEngineError};
+use crate::entities::{job, tool_log};
+
+/// job.check 的内容
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckerSpec {
+    /// 检查agent的code
+    pub agent: String,
+    /// 检查要求，为空时只对照job的目标
+    #[serde(default)]
+    pub prompt: Option<String>,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: usize,
+}
+
+fn default_max_retries() -> usize {
+    1
+}
+
+impl CheckerSpec {
+    /// 解析 job.check，为空时没有检查
+    pub fn parse(check: &str) -> Option<Self> {
+        let check = check.trim();
+        if check.is_empty() {
+            return None;
+        }
+        Some(serde_json::from_str(check).unwrap_or_else(|_| Self {
+            agent: check.to_string(),
+            prompt: None,
+            max_retries: default_max_retries(),
+        }))
+    }
+
+    /// 让检查agent判断一次输出
+    pub async fn review<M: CompletionModel>(
+        &self,
+        checker: &Agent<M>,
+        goal: &str,
+        output: &str,
+    ) -> Result<CheckDecision, CompletionError> {
+        let criteria = self
+            .prompt
+            .as_deref()
+            .map(|prompt| format!("\nRequirements:\n{prompt}\n"))
+            .unwrap_or_default();
+        let prompt = format!(
+            "Check whether the answer achieves the goal of the step.\n\nGoal:\n{goal}\n{criteria}\n\
+             Answer:\n{output}\n\nReply with json: {{\"decision\": \"pass\"}}, \
+             {{\"decision\": \"retry\", \"feedback\": \"...\"}} or {{\"decision\": \"fail\", \"reason\": \"...\"}}."
+        );
+        let response = checker
+            .completion(prompt.as_str(), vec![])
+            .await?
+            .send()
+            .await?;
+        let answer = response
+            .choice
+            .iter()
+            .filter_map(|content| match content {
+                AssistantContent::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        Ok(CheckDecision::parse(&answer))
+    }
+}
+
+/// 检查agent的结论
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum CheckDecision {
+    Pass,
+    /// 带着反馈重新回答
+    Retry {
+        feedback: String,
+    },
+    /// 这一步失败，不再重试
+    Fail {
+        reason: String,
+    },
+}
+
+impl CheckDecision {
+    /// 解析检查agent的回答，不是json时按 PASS/RETRY/FAIL 开头判断，无法判断时作为反馈重试
+    pub fn parse(answer: &str) -> Self {
+        let answer = answer.trim();
+        let json = answer
+            .find('{')
+            .zip(answer.rfind('}'))
+            .and_then(|(start, end)| answer.get(start..=end))
+            .and_then(|json| serde_json::from_str(json).ok());
+        if let Some(decision) = json {
+            return decision;
+        }
+        let upper = answer.to_uppercase();
+        let rest = |prefix: &str| {
+            answer[prefix.len()..]
+                .trim_start_matches([':', ' '])
+                .to_string()
+        };
+        if upper.starts_with("PASS") {
+            CheckDecision::Pass
+        } else if upper.starts_with("FAIL") {
+            CheckDecision::Fail {
+                reason: rest("FAIL"),
+            }
+        } else if upper.starts_with("RETRY") {
+            CheckDecision::Retry {
+                feedback: rest("RETRY"),
+            }
+        } else {
+            CheckDecision::Retry {
+                feedback: answer.to_string(),
+            }
+        }
+    }
+}
+
+/// 一轮检查
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckRound {
+    pub attempt: usize,
+    pub output: String,
+    pub decision: CheckDecision,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CheckerError {
+    #[error("{0}")]
+    Completion(#[from] CompletionError),
+    #[error("{0}")]
+    Guardrail(#[from] GuardrailError),
+    #[error("checker failed the step: {reason}")]
+    Failed {
+        reason: String,
+        rounds: Vec<CheckRound>,
+    },
+    #[error("checker rejected the output after {} attempts", .0.len())]
+    Exhausted(Vec<CheckRound>),
+}
+
+impl JobAction {
+    /// 执行动作并交给检查agent判断，检查要求重试时带上反馈重新回答。
+    /// 返回通过检查的输出以及所有检查轮次
+    pub async fn complete_reviewed<M: CompletionModel, C: CompletionModel>(
+        &self,
+        agent: &Agent<M>,
+        checker: &Agent<C>,
+        spec: &CheckerSpec,
+    ) -> Result<(String, Vec<CheckRound>), CheckerError> {
+        let mut rounds = Vec::new();
+        let mut action = self.clone();
+        for attempt in 1..=spec.max_retries + 1 {
+            let (output, _) = action.complete_checked(agent).await?;
+            let decision = spec.review(checker, &self.prompt, &output).await?;
+            rounds.push(CheckRound {
+                attempt,
+                output: output.clone(),
+                decision: decision.clone(),
+            });
+            match decision {
+                CheckDecision::Pass => return Ok((output, rounds)),
+                CheckDecision::Fail { reason } => {
+                    return Err(CheckerError::Failed { reason, rounds })
+                }
+                CheckDecision::Retry { feedback } => {
+                    action.prompt = format!(
+                        "{}\n\nYour previous answer was:\n{output}\n\n\
+                         A reviewer asked for changes: {feedback}\nAnswer again.",
+                        self.prompt
+                    );
+                }
+            }
+        }
+        Err(CheckerError::Exhausted(rounds))
+    }
+}
+
+impl TaskEngine {
+    /// job声明的检查agent
+    pub fn job_checker(&self, job: &job::Model) -> Option<CheckerSpec> {
+        job.check.as_deref().and_then(CheckerSpec::parse)
+    }
+
+    /// 记录检查轮次，没有通过的输出记为被拒绝，检查判定失败时计划中对应的步骤标记为失败
+    pub async fn record_check(
+        &self,
+        task_id: i32,
+        job_id: i32,
+        rounds: &[CheckRound],
+    ) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        let store = self.store();
+        for round in rounds.iter().filter(|r| r.decision != CheckDecision::Pass) {
+            if let Some(store) = &store {
+                let args = ToolLogArgs {
+                    job_id,
+                    rejected: true,
+                    ..Default::default()
+                };
+                store
+                    .append_tool_log(tool_log::Model {
+                        id: 0,
+                        taskid: Some(task_id),
+                        planid: None,
+                        args: Some(serde_json::to_string(&args)?),
+                        output: Some(rig::telemetry::redact::redact(&round.output)),
+                    })
+                    .await?;
+            }
+        }
+        let mut context = self
+            .tasks
+            .lock(task_id)
+            .await
+            .ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+        for round in rounds {
+            context.execution_history.push(match &round.decision {
+                CheckDecision::Pass => format!("Job {job_id} passed the checker"),
+                CheckDecision::Retry { feedback } => {
+                    format!("Job {job_id} retried by the checker: {feedback}")
+                }
+                CheckDecision::Fail { reason } => {
+                    format!("Job {job_id} failed by the checker: {reason}")
+                }
+            });
+        }
+        drop(context);
+        if rounds.last().map(|r| &r.decision) != Some(&CheckDecision::Pass) {
+            self.set_plan_job_status(task_id, job_id, PlanStatus::Failure)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::client::mock::{self, MockClient};
+    use rig::client::CompletionClient;
+
+    #[tokio::test]
+    async fn checker_feedback_triggers_retry() {
+        let spec =
+            CheckerSpec::parse(r#"{"agent": "reviewer", "prompt": "include totals"}"#).unwrap();
+        assert_eq!(CheckerSpec::parse("reviewer").unwrap().agent, "reviewer");
+        assert_eq!(
+            CheckDecision::parse("FAIL: wrong table"),
+            CheckDecision::Fail {
+                reason: "wrong table".into()
+            }
+        );
+
+        let main = mock::script("checker-main");
+        main.push_text("3 orders");
+        main.push_text("3 orders, total 42");
+        let review = mock::script("checker-review");
+        review.push_text(r#"{"decision": "retry", "feedback": "add the total"}"#);
+        review.push_text("PASS");
+        let client = MockClient::new();
+        let agent = client.agent("checker-main").build();
+        let checker = client.agent("checker-review").build();
+
+        let action = JobAction::parse("summarise the orders");
+        let (output, rounds) = action
+            .complete_reviewed(&agent, &checker, &spec)
+            .await
+            .unwrap();
+        assert_eq!(output, "3 orders, total 42");
+        assert_eq!(rounds.len(), 2);
+        assert!(format!("{:?}", main.requests()[1].chat_history).contains("add the total"));
+        assert!(format!("{:?}", review.requests()[0].chat_history).contains("include totals"));
+
+        review.push_text(r#"{"decision": "fail", "reason": "no data"}"#);
+        main.push_text("nothing");
+        let err = action
+            .complete_reviewed(&agent, &checker, &spec)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CheckerError::Failed { .. }));
+    }
+}
@@ -0,0 +1,218 @@
+//! 流式输出的检查点。
+//!
+//! 流式执行job时，[TaskEngine::stream_job] 每收到一定数量的数据块或者每隔一段时间，
+//! 把已生成的输出写入这次尝试的 `job_run` 记录（见 [super::attempts]），进程在生成过程中崩溃时最多丢失最后一个间隔的输出。
+//!
+//! 恢复任务时 [TaskEngine::interrupted_runs] 返回没有完成的运行记录，由 [resume] 决定
+//! 重新执行job，还是接受部分输出、用续写prompt让模型从中断处继续，
+//! 续写的回答通过 [TaskEngine::continue_job] 接在部分输出之后。
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use rig::completion::{AssistantContent, GetTokenUsage};
+use rig::streaming::{StreamedAssistantContent, StreamingCompletionResponse};
+use serde::{Deserialize, Serialize};
+
+use super::attempts::RunStatus;
+use super::{TaskEngine, TaskEngineError};
+use crate::entities::job_run;
+
+/// 写入检查点的频率，两个条件满足任意一个即写入
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointPolicy {
+    /// 每收到多少个数据块
+    pub every_chunks: u64,
+    /// 距离上次检查点的最长时间
+    pub every: Duration,
+}
+
+impl Default for CheckpointPolicy {
+    fn default() -> Self {
+        Self {
+            every_chunks: 32,
+            every: Duration::from_secs(2),
+        }
+    }
+}
+
+/// 被中断的job的恢复方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resume {
+    /// 部分输出太短，重新执行job
+    Rerun,
+    /// 保留部分输出，用续写prompt继续生成
+    Continue { prompt: String },
+}
+
+/// 部分输出不少于 `min_chars` 个字符时续写，否则重新执行
+pub fn resume(run: &job_run::Model, prompt: &str, min_chars: usize) -> Resume {
+    if run.output.chars().count() < min_chars {
+        return Resume::Rerun;
+    }
+    Resume::Continue {
+        prompt: format!(
+            "{prompt}\n\nYour previous answer was interrupted. It ended with:\n\n{}\n\n\
+             Continue exactly where it stopped, without repeating it.",
+            run.output
+        ),
+    }
+}
+
+impl TaskEngine {
+    /// 设置流式输出的检查点频率
+    pub fn with_checkpoint_policy(mut self, policy: CheckpointPolicy) -> Self {
+        self.checkpoint = policy;
+        self
+    }
+
+    /// 读取流式回答作为job的输出，按检查点策略写入 `job_run`，返回完整的输出。
+    /// 回答中的推理过程通过 [TaskEngine::record_reasoning] 单独记录。
+    pub async fn stream_job<R: Clone + Unpin + GetTokenUsage>(
+        &self,
+        task_id: i32,
+        job_id: i32,
+        stream: &mut StreamingCompletionResponse<R>,
+    ) -> Result<String, TaskEngineError> {
+        self.ensure_writable()?;
+        let run = self.begin_run(task_id, job_id, None, None).await?;
+        self.checkpointed(run, stream).await
+    }
+
+    /// 续写被中断的运行，新的回答接在部分输出之后，返回完整的输出
+    pub async fn continue_job<R: Clone + Unpin + GetTokenUsage>(
+        &self,
+        run: job_run::Model,
+        stream: &mut StreamingCompletionResponse<R>,
+    ) -> Result<String, TaskEngineError> {
+        self.checkpointed(run, stream).await
+    }
+
+    /// 任务中没有完成的运行记录
+    pub async fn interrupted_runs(
+        &self,
+        task_id: i32,
+    ) -> Result<Vec<job_run::Model>, TaskEngineError> {
+        let Some(store) = self.store() else {
+            return Ok(vec![]);
+        };
+        Ok(store
+            .load_job_runs(task_id)
+            .await?
+            .into_iter()
+            .filter(|run| !run.finished)
+            .collect())
+    }
+
+    async fn checkpointed<R: Clone + Unpin + GetTokenUsage>(
+        &self,
+        mut run: job_run::Model,
+        stream: &mut StreamingCompletionResponse<R>,
+    ) -> Result<String, TaskEngineError> {
+        self.ensure_writable()?;
+        let store = self.store();
+        let policy = self.checkpoint;
+        let mut pending = 0;
+        while let Some(item) = stream.next().await {
+            if let StreamedAssistantContent::Text(text) = item? {
+                run.output.push_str(&text.text);
+                run.chunks += 1;
+                pending += 1;
+            }
+            let now = self.clock.now_millis();
+            let due = pending >= policy.every_chunks
+                || now - run.updated_at >= policy.every.as_millis() as i64;
+            if pending > 0 && due {
+                run.updated_at = now;
+                pending = 0;
+                if let Some(store) = &store {
+                    store.save_job_run(run.clone()).await?;
+                }
+            }
+        }
+
+        let run = self
+            .finish_run(run, RunStatus::Success, Some(stream.usage()))
+            .await?;
+        for content in stream.choice.iter() {
+            if let AssistantContent::Reasoning(reasoning) = content {
+                self.record_reasoning(run.taskid, run.job_id, &reasoning.reasoning.join("\n"))
+                    .await?;
+            }
+        }
+        Ok(run.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::engine::store::{MemoryStore, TaskStore};
+    use rig::client::mock::{self, MockClient};
+    use rig::client::CompletionClient;
+    use rig::completion::CompletionModel;
+
+    #[tokio::test]
+    async fn interrupted_stream_resumes_from_checkpoint() {
+        let store = Arc::new(MemoryStore::new());
+        let mut engine = TaskEngine::new()
+            .with_store(store.clone())
+            .with_checkpoint_policy(CheckpointPolicy {
+                every_chunks: 1,
+                every: Duration::from_secs(60),
+            });
+        engine.init(1, "orders".to_string()).await.unwrap();
+
+        let script = mock::script("checkpoint-test");
+        script.push_text("3 orders");
+        let model = MockClient::new().completion_model("checkpoint-test");
+        let mut stream = model
+            .stream(model.completion_request("count").build())
+            .await
+            .unwrap();
+        assert_eq!(
+            engine.stream_job(1, 7, &mut stream).await.unwrap(),
+            "3 orders"
+        );
+        assert!(engine.interrupted_runs(1).await.unwrap().is_empty());
+
+        // 模拟生成到一半时进程崩溃留下的记录
+        let crashed = job_run::Model {
+            id: 0,
+            taskid: 1,
+            job_id: 8,
+            output: "The total is".to_string(),
+            chunks: 3,
+            finished: false,
+            started_at: 0,
+            updated_at: 0,
+            attempt: 1,
+            agent: None,
+            prompt: None,
+            input_tokens: 0,
+            output_tokens: 0,
+            duration_ms: 0,
+            status: RunStatus::Running.as_str().to_string(),
+            prompt_version: None,
+        };
+        store.save_job_run(crashed).await.unwrap();
+        let run = engine.interrupted_runs(1).await.unwrap().remove(0);
+        assert_eq!(resume(&run, "sum", 100), Resume::Rerun);
+        let Resume::Continue { prompt } = resume(&run, "sum", 4) else {
+            panic!("expected a continuation");
+        };
+        assert!(prompt.contains("The total is"));
+
+        script.push_text(" 42");
+        let mut stream = model
+            .stream(model.completion_request(prompt).build())
+            .await
+            .unwrap();
+        let output = engine.continue_job(run, &mut stream).await.unwrap();
+        assert_eq!(output, "The total is 42");
+        assert!(engine.interrupted_runs(1).await.unwrap().is_empty());
+        assert_eq!(store.load_job_runs(1).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,165 @@
+//! 引擎使用的时钟。
+//!
+//! SLA 计时、关闭时的宽限期、主备租约的过期时间都依赖时间。引擎通过 [Clock] 读取时间和
+//! 等待，默认的 [SystemClock] 使用 tokio 的时钟和系统时间；测试中注入 [MockClock]，
+//! 调用 [MockClock::advance] 快进时间，到期的等待立即返回，不需要真的 sleep。
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// 单调时间，用于计算耗时
+    fn now(&self) -> Instant;
+
+    /// 当前的unix时间，毫秒
+    fn now_millis(&self) -> i64;
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// 真实时间
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn now_millis(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        tokio::time::sleep(duration).boxed()
+    }
+}
+
+/// 手动推进的时钟
+pub struct MockClock {
+    start: Instant,
+    start_millis: i64,
+    inner: Mutex<MockState>,
+}
+
+#[derive(Default)]
+struct MockState {
+    elapsed: Duration,
+    sleepers: Vec<(Duration, oneshot::Sender<()>)>,
+}
+
+impl MockClock {
+    /// 从指定的unix时间开始，毫秒
+    pub fn new(start_millis: i64) -> Self {
+        Self {
+            start: Instant::now(),
+            start_millis,
+            inner: Mutex::new(MockState::default()),
+        }
+    }
+
+    /// 快进时间，唤醒所有到期的等待
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.lock();
+        state.elapsed += duration;
+        let elapsed = state.elapsed;
+        let sleepers = std::mem::take(&mut state.sleepers);
+        for (deadline, waker) in sleepers {
+            if deadline <= elapsed {
+                let _ = waker.send(());
+            } else {
+                state.sleepers.push((deadline, waker));
+            }
+        }
+    }
+
+    /// 从创建开始经过的时间
+    pub fn elapsed(&self) -> Duration {
+        self.lock().elapsed
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl fmt::Debug for MockClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockClock")
+            .field("start_millis", &self.start_millis)
+            .field("elapsed", &self.elapsed())
+            .finish()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn now_millis(&self) -> i64 {
+        self.start_millis + self.elapsed().as_millis() as i64
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        if duration.is_zero() {
+            return futures::future::ready(()).boxed();
+        }
+        let mut state = self.lock();
+        let deadline = state.elapsed + duration;
+        let (waker, woken) = oneshot::channel();
+        state.sleepers.push((deadline, waker));
+        async move {
+            let _ = woken.await;
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::sla::{SlaKind, SlaPolicy};
+    use crate::engine::TaskEngine;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn mock_clock_drives_sleeps_and_sla() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let sleep = tokio::spawn(clock.sleep(Duration::from_secs(60)));
+        clock.advance(Duration::from_secs(30));
+        tokio::task::yield_now().await;
+        assert!(!sleep.is_finished());
+        clock.advance(Duration::from_secs(30));
+        sleep.await.unwrap();
+        assert_eq!(clock.now_millis(), 61_000);
+
+        let engine = TaskEngine::new().with_clock(clock.clone());
+        engine.sla().track(
+            1,
+            SlaPolicy {
+                finish_within_secs: Some(600),
+                approval_within_secs: None,
+                warn_ratio: 0.8,
+            },
+        );
+        clock.advance(Duration::from_secs(601));
+        engine.sla().stop(1, SlaKind::Finish);
+        assert_eq!(engine.sla_breaches(1).len(), 1);
+    }
+}
@@ -0,0 +1,182 @@
+//! job结束时的持久化。
+//!
+//! job的输出（tool_log）、关联的计划步骤的状态、这次尝试的执行记录以及任务状态通过
+//! [TaskStore::write_batch] 一次写入。使用数据库时在同一个事务中提交，进程在写入中途退出时
+//! 不会出现计划已经标记完成、而任务和执行记录还停留在之前状态的情况，恢复后按执行记录重试这个job。
+//! 写入失败时其他修改全部回滚，这次尝试的执行记录单独记为失败，不会一直停留在 running。
+//!
+//! [TaskStore::write_batch]: super::store::TaskStore::write_batch
+
+use super::attempts::RunStatus;
+use super::plan::{plan_id, PlanStatus};
+use super::replay::ToolLogArgs;
+use super::store::StoreBatch;
+use super::{TaskEngine, TaskEngineError};
+use crate::entities::{job_run, tool_log};
+
+/// job结束时与执行记录一起写入的输出
+pub(crate) struct JobCompletion {
+    pub job_id: i32,
+    /// 写入 tool_log 的输出，写入前按当前的脱敏配置处理
+    pub output: String,
+    /// 输出未通过后处理链，计划步骤记为失败
+    pub rejected: bool,
+}
+
+impl TaskEngine {
+    /// 结束一次尝试。job产生了输出时，输出、计划步骤的状态和任务状态与执行记录一起原子写入。
+    /// 调用时不能持有任务的锁
+    pub(crate) async fn complete_job(
+        &self,
+        task_id: i32,
+        mut run: job_run::Model,
+        status: RunStatus,
+        completion: Option<JobCompletion>,
+    ) -> Result<job_run::Model, TaskEngineError> {
+        self.close_run(&mut run, status, None);
+        if completion.is_some() {
+            self.ensure_writable()?;
+        }
+        let Some(store) = self.store() else {
+            return Ok(run);
+        };
+        // 任务记录与其他写入排队，使用上一次写入得到的版本
+        let _write = self.tasks.lock_write(task_id).await;
+        let mut batch = StoreBatch {
+            job_runs: vec![run.clone()],
+            ..Default::default()
+        };
+        let recorded = completion.as_ref().map(|c| c.job_id);
+        if let Some(completion) = completion {
+            let JobCompletion {
+                job_id,
+                output,
+                rejected,
+            } = completion;
+            let plan_status = if rejected {
+                PlanStatus::Failure
+            } else {
+                PlanStatus::Success
+            };
+            batch.plans = store
+                .load_plans(plan_id(task_id))
+                .await?
+                .into_iter()
+                .filter(|row| row.job_id == Some(job_id))
+                .map(|mut row| {
+                    row.state = Some(plan_status.as_str().to_string());
+                    row
+                })
+                .collect();
+            batch.tool_logs.push(tool_log::Model {
+                id: 0,
+                taskid: Some(task_id),
+                planid: None,
+                args: Some(serde_json::to_string(&ToolLogArgs {
+                    job_id,
+                    rejected,
+                    ..Default::default()
+                })?),
+                output: Some(rig::telemetry::redact::redact(&output)),
+            });
+            // 写入内存中的任务记录和当前状态，只短暂持有任务锁
+            if let Some(mut context) = self.tasks.lock(task_id).await {
+                let state = context.state.as_str().to_string();
+                if let Some(task) = context.task.as_mut() {
+                    task.state = Some(state);
+                    batch.task = Some(task.clone());
+                }
+            }
+        }
+        let version = match store.write_batch(batch).await {
+            Ok(version) => version,
+            Err(e) => {
+                // 批次整体回滚，执行记录仍是 running，单独记为失败，恢复时按失败的尝试重试这个job
+                self.close_run(&mut run, RunStatus::Failure, None);
+                if let Err(save) = store.save_job_run(run).await {
+                    tracing::warn!(
+                        "failed to record the failed run of task {}: {}",
+                        task_id,
+                        save
+                    );
+                }
+                return Err(e.into());
+            }
+        };
+        if let Some(mut context) = self.tasks.lock(task_id).await {
+            if let (Some(task), Some(version)) = (context.task.as_mut(), version) {
+                task.version = version;
+            }
+            if let Some(job_id) = recorded {
+                context
+                    .execution_history
+                    .push(format!("Tool log recorded for job {}", job_id));
+            }
+        }
+        Ok(run)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use sea_orm::{ConnectionTrait, Database};
+
+    use super::*;
+    use crate::engine::fixtures;
+    use crate::engine::simulation::StubAgents;
+    use crate::entities::plan;
+    use crate::migrate::create_schema;
+
+    #[tokio::test]
+    async fn job_completion_is_atomic() {
+        let db = Arc::new(Database::connect("sqlite::memory:").await.unwrap());
+        create_schema(&db).await.unwrap();
+        let stubs = StubAgents::new().with_results(
+            "analyst",
+            [Ok("12 orders".to_string()), Ok("12 orders".to_string())],
+        );
+        let engine = TaskEngine::new()
+            .with_db(db.clone())
+            .with_stub_agents(Arc::new(stubs));
+        let task_id = engine.create_task("march".into()).await.unwrap();
+        engine.start(task_id).await.unwrap();
+        let store = engine.store().unwrap();
+        store
+            .save_plan(plan::Model {
+                id: 0,
+                pid: Some(task_id),
+                state: Some(PlanStatus::Pending.as_str().to_string()),
+                planid: Some(plan_id(task_id)),
+                seq: 1,
+                step: Some("count orders".into()),
+                job_id: Some(3),
+                version: 0,
+            })
+            .await
+            .unwrap();
+        let job = fixtures::agent_job(3, "analyst", "count orders in {{ task.input }}");
+
+        // tool_log 写入失败时计划步骤不前进，执行记录单独记为失败
+        db.execute_unprepared("ALTER TABLE tool_log RENAME TO tool_log_moved")
+            .await
+            .unwrap();
+        assert!(engine.execute_job(task_id, job.clone()).await.is_err());
+        let plans = store.load_plans(plan_id(task_id)).await.unwrap();
+        assert_eq!(plans[0].state.as_deref(), Some("pending"));
+        let runs = store.load_job_runs(task_id).await.unwrap();
+        assert_eq!(runs[0].status, RunStatus::Failure.as_str());
+        assert!(runs[0].finished);
+
+        db.execute_unprepared("ALTER TABLE tool_log_moved RENAME TO tool_log")
+            .await
+            .unwrap();
+        engine.execute_job(task_id, job).await.unwrap();
+        let plans = store.load_plans(plan_id(task_id)).await.unwrap();
+        assert_eq!(plans[0].state.as_deref(), Some("success"));
+        let runs = store.load_job_runs(task_id).await.unwrap();
+        assert_eq!(runs[1].status, RunStatus::Success.as_str());
+        assert_eq!(store.load_tool_logs(task_id).await.unwrap().len(), 1);
+    }
+}
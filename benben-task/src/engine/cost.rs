@@ -0,0 +1,110 @@
+//! 模型价格表以及任务成本核算。
+//!
+//! 价格按照每百万token计，区分输入、缓存命中的输入以及输出。价格表的键为
+//! `provider/model`，`provider/*` 作为该provider下所有模型的默认价格。本地运行的
+//! ollama 默认不计费，没有价格的模型按0计算。
+
+use std::collections::HashMap;
+
+use rig::completion::Usage;
+use serde::{Deserialize, Serialize};
+
+/// 单个模型的价格，每百万token，美元
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+    /// 缓存命中的输入价格，为空时按普通输入计
+    #[serde(default)]
+    pub cached_input_per_million: Option<f64>,
+}
+
+impl ModelPrice {
+    /// 计算一次调用的费用
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let cached = usage.cached_input_tokens.min(usage.input_tokens);
+        let uncached = usage.input_tokens - cached;
+        let cached_price = self
+            .cached_input_per_million
+            .unwrap_or(self.input_per_million);
+        (uncached as f64 * self.input_per_million
+            + cached as f64 * cached_price
+            + usage.output_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// 价格表
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PricingTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl Default for PricingTable {
+    fn default() -> Self {
+        let deepseek = ModelPrice {
+            input_per_million: 0.28,
+            output_per_million: 0.42,
+            cached_input_per_million: Some(0.028),
+        };
+        Self::empty()
+            .price("deepseek", "*", deepseek)
+            .price("ollama", "*", ModelPrice::default())
+    }
+}
+
+impl PricingTable {
+    pub fn empty() -> Self {
+        Self {
+            prices: HashMap::new(),
+        }
+    }
+
+    /// 从json加载，格式为 `{"prices": {"deepseek/deepseek-chat": {...}}}`
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// 设置模型价格，model 为 `*` 时作为provider的默认价格
+    pub fn price(mut self, provider: &str, model: &str, price: ModelPrice) -> Self {
+        self.prices.insert(format!("{provider}/{model}"), price);
+        self
+    }
+
+    pub fn lookup(&self, provider: &str, model: &str) -> Option<ModelPrice> {
+        self.prices
+            .get(&format!("{provider}/{model}"))
+            .or_else(|| self.prices.get(&format!("{provider}/*")))
+            .copied()
+    }
+
+    /// 计算一次调用的费用，没有价格的模型按0计算
+    pub fn cost(&self, provider: &str, model: &str, usage: &Usage) -> f64 {
+        match self.lookup(provider, model) {
+            Some(price) => price.cost(usage),
+            None => {
+                tracing::debug!("no price for {}/{}, counted as free", provider, model);
+                0.0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prices_cached_input_separately() {
+        let table = PricingTable::default();
+        let usage = Usage {
+            input_tokens: 1_000_000,
+            output_tokens: 1_000_000,
+            total_tokens: 2_000_000,
+            cached_input_tokens: 500_000,
+        };
+        let cost = table.cost("deepseek", "deepseek-chat", &usage);
+        assert!((cost - (0.14 + 0.014 + 0.42)).abs() < 1e-9);
+        assert_eq!(table.cost("ollama", "qwen3:8b", &usage), 0.0);
+    }
+}
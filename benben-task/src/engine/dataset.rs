@@ -0,0 +1,179 @@
+//! 由认可的任务构建微调数据集。
+//!
+//! 人工确认一个已完成任务的结果足够好之后，通过 [TaskEngine::mark_golden] 把任务标记为golden，
+//! 任务中每个job最后一次成功尝试的 (渲染后的prompt, 输出) 写入 `dataset_item` 表的指定数据集，
+//! 任务带上 `golden` 标签。数据集可以导出为 OpenAI chat 微调格式的JSONL，每行一条样本，
+//! 也可以作为few-shot示例的来源：
+//!
+//! ```rust,ignore
+//! engine.mark_golden(task_id, "order-analysis").await?;
+//! let jsonl = engine.export_dataset("order-analysis").await?;
+//! ```
+
+use std::collections::BTreeMap;
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+};
+use serde_json::json;
+
+use super::attempts::RunStatus;
+use super::trace::agent_preambles;
+use super::{TaskEngine, TaskEngineError, TaskState};
+use crate::entities::dataset_item;
+
+/// 标记golden任务的标签，值为数据集名称
+pub const GOLDEN_TAG: &str = "golden";
+
+impl TaskEngine {
+    /// 把已完成的任务标记为golden，将每个job最后一次成功的 (prompt, 输出) 写入数据集。
+    /// 重复标记时替换这个任务在数据集中原有的样本，返回写入的样本
+    pub async fn mark_golden(
+        &self,
+        task_id: i32,
+        dataset: &str,
+    ) -> Result<Vec<dataset_item::Model>, TaskEngineError> {
+        self.ensure_writable()?;
+        let db = self.db().ok_or_else(|| {
+            TaskEngineError::Unavailable("Datasets require a database".to_string())
+        })?;
+        let state = self.get_state(task_id).await?;
+        if state != TaskState::Finished {
+            return Err(TaskEngineError::Rejected(format!(
+                "Task {} is {}, only finished tasks can be golden",
+                task_id,
+                state.as_str()
+            )));
+        }
+
+        // 同一个job的多次尝试只保留最后一次成功的，按job id排列
+        let mut accepted = BTreeMap::new();
+        for run in self.get_job_runs(task_id, None).await? {
+            if run.status == RunStatus::Success.as_str() && run.prompt.is_some() {
+                accepted.insert(run.job_id, run);
+            }
+        }
+        if accepted.is_empty() {
+            return Err(format!("Task {} has no successful job to learn from", task_id).into());
+        }
+
+        dataset_item::Entity::delete_many()
+            .filter(dataset_item::Column::Dataset.eq(dataset))
+            .filter(dataset_item::Column::Taskid.eq(task_id))
+            .exec(db.as_ref())
+            .await?;
+        let created_at = self.clock().now_millis();
+        let mut items = Vec::with_capacity(accepted.len());
+        for run in accepted.into_values() {
+            let item = dataset_item::Model {
+                id: 0,
+                dataset: dataset.to_string(),
+                taskid: task_id,
+                job_id: run.job_id,
+                agent: run.agent,
+                prompt: run.prompt.unwrap_or_default(),
+                output: run.output,
+                created_at,
+            };
+            let mut active = item.into_active_model().reset_all();
+            active.id = sea_orm::ActiveValue::NotSet;
+            items.push(active.insert(db.as_ref()).await?);
+        }
+        self.set_tag(task_id, GOLDEN_TAG, dataset).await?;
+        tracing::info!(
+            "task {} added {} samples to dataset {}",
+            task_id,
+            items.len(),
+            dataset
+        );
+        Ok(items)
+    }
+
+    /// 数据集中的样本，按写入顺序排列
+    pub async fn dataset_items(
+        &self,
+        dataset: &str,
+    ) -> Result<Vec<dataset_item::Model>, TaskEngineError> {
+        let db = self.db().ok_or_else(|| {
+            TaskEngineError::Unavailable("Datasets require a database".to_string())
+        })?;
+        Ok(dataset_item::Entity::find()
+            .filter(dataset_item::Column::Dataset.eq(dataset))
+            .order_by_asc(dataset_item::Column::Id)
+            .all(db.as_ref())
+            .await?)
+    }
+
+    /// 把数据集导出为 OpenAI chat 微调格式的JSONL，agent有系统提示时作为第一条消息
+    pub async fn export_dataset(&self, dataset: &str) -> Result<String, TaskEngineError> {
+        let items = self.dataset_items(dataset).await?;
+        if items.is_empty() {
+            return Err(TaskEngineError::NotFound(format!("Dataset {dataset}")));
+        }
+        let preambles = agent_preambles(None);
+        let mut jsonl = String::new();
+        for item in items {
+            let mut messages = Vec::new();
+            if let Some(preamble) = item.agent.as_ref().and_then(|a| preambles.get(a)) {
+                messages.push(json!({"role": "system", "content": preamble}));
+            }
+            messages.push(json!({"role": "user", "content": item.prompt}));
+            messages.push(json!({"role": "assistant", "content": item.output}));
+            let line = json!({
+                "messages": messages,
+                "metadata": {"task_id": item.taskid, "job_id": item.job_id, "agent": item.agent},
+            });
+            jsonl.push_str(&serde_json::to_string(&line)?);
+            jsonl.push('\n');
+        }
+        Ok(jsonl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use sea_orm::Database;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::engine::fixtures;
+    use crate::engine::simulation::StubAgents;
+    use crate::migrate::create_schema;
+
+    #[tokio::test]
+    async fn golden_task_becomes_dataset_samples() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&db).await.unwrap();
+        let stubs = StubAgents::new().with_results(
+            "analyst",
+            [Err("timeout".to_string()), Ok("12 orders".into())],
+        );
+        let engine = TaskEngine::new()
+            .with_db(Arc::new(db))
+            .with_stub_agents(Arc::new(stubs));
+        let task_id = engine.create_task("march".into()).await.unwrap();
+        engine.start(task_id).await.unwrap();
+        let job = fixtures::agent_job(3, "analyst", "count orders in {{ task.input }}");
+        assert!(engine.execute_job(task_id, job.clone()).await.is_err());
+        engine.execute_job(task_id, job).await.unwrap();
+        assert!(engine.mark_golden(task_id, "orders").await.is_err());
+
+        engine.finish(task_id).await.unwrap();
+        engine.mark_golden(task_id, "orders").await.unwrap();
+        let items = engine.mark_golden(task_id, "orders").await.unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(engine.dataset_items("orders").await.unwrap(), items);
+        assert_eq!(
+            engine.get_tags(task_id).await.unwrap().get(GOLDEN_TAG),
+            Some(&"orders".to_string())
+        );
+
+        let jsonl = engine.export_dataset("orders").await.unwrap();
+        let line: Value = serde_json::from_str(jsonl.trim_end()).unwrap();
+        assert_eq!(line["messages"][0]["content"], "count orders in march");
+        assert_eq!(line["messages"][1]["content"], "12 orders");
+        assert!(engine.export_dataset("refunds").await.is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! 任务引擎的错误。
+//!
+//! 调用方（HTTP接口、UI）按变体把错误映射为状态码，并根据 [TaskEngineError::is_retryable]
+//! 决定是否重试。没有单独变体的错误（模板渲染、schema 校验等）保留原始信息归入 `Other`。
+
+use rig::completion::{CompletionError, PromptError};
+use sea_orm::DbErr;
+use thiserror::Error;
+
+use super::schema::SchemaError;
+use super::store::StoreError;
+use super::template::TemplateError;
+use super::versioning::VersionError;
+use super::TaskState;
+
+#[derive(Debug, Error)]
+pub enum TaskEngineError {
+    /// 任务、job或者其他资源不存在
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("Cannot transition from {from:?} to {to:?} state")]
+    InvalidTransition { from: TaskState, to: TaskState },
+    #[error("DbError: {0}")]
+    Db(#[from] DbErr),
+    /// 另一个引擎实例或者直接写库的程序已经修改了这条记录，需要重新读取后再修改
+    #[error("{0}")]
+    Conflict(String),
+    /// 调用模型失败
+    #[error("{0}")]
+    Provider(#[from] CompletionError),
+    /// 任务或者它等待的子任务已经被取消
+    #[error("{0}")]
+    Cancelled(String),
+    #[error("{0}")]
+    Timeout(String),
+    /// 维护模式、只读观察节点或者备用节点拒绝修改
+    #[error("{0}")]
+    Unavailable(String),
+    /// 被审批策略拒绝、等待审批或者超出配额
+    #[error("{0}")]
+    Rejected(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl TaskEngineError {
+    pub fn task_not_found(task_id: i32) -> Self {
+        TaskEngineError::NotFound(format!("Task {task_id}"))
+    }
+
+    /// 稍后重试可能成功的错误
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TaskEngineError::Provider(err) => err.is_retryable(),
+            TaskEngineError::Db(_) | TaskEngineError::Timeout(_) | TaskEngineError::Conflict(_) => {
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<StoreError> for TaskEngineError {
+    fn from(err: StoreError) -> Self {
+        match err {
+            StoreError::Db(err) => TaskEngineError::Db(err),
+            err @ StoreError::Conflict { .. } => TaskEngineError::Conflict(err.to_string()),
+            err => TaskEngineError::Other(err.to_string()),
+        }
+    }
+}
+
+impl From<VersionError> for TaskEngineError {
+    fn from(err: VersionError) -> Self {
+        match err {
+            VersionError::Db(err) => TaskEngineError::Db(err),
+            VersionError::WorkflowNotFound(id) => {
+                TaskEngineError::NotFound(format!("Workflow {id}"))
+            }
+            VersionError::VersionNotFound(id, version) => {
+                TaskEngineError::NotFound(format!("Version {version} of workflow {id}"))
+            }
+            err => TaskEngineError::Other(err.to_string()),
+        }
+    }
+}
+
+impl From<PromptError> for TaskEngineError {
+    fn from(err: PromptError) -> Self {
+        match err {
+            PromptError::CompletionError(err) => TaskEngineError::Provider(err),
+            err => TaskEngineError::Other(err.to_string()),
+        }
+    }
+}
+
+impl From<String> for TaskEngineError {
+    fn from(message: String) -> Self {
+        TaskEngineError::Other(message)
+    }
+}
+
+impl From<&str> for TaskEngineError {
+    fn from(message: &str) -> Self {
+        TaskEngineError::Other(message.to_string())
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for TaskEngineError {
+    fn from(err: Box<dyn std::error::Error>) -> Self {
+        TaskEngineError::Other(err.to_string())
+    }
+}
+
+/// 没有单独变体的错误保留信息归入 `Other`
+macro_rules! other_errors {
+    ($($err:ty),* $(,)?) => {
+        $(impl From<$err> for TaskEngineError {
+            fn from(err: $err) -> Self {
+                TaskEngineError::Other(err.to_string())
+            }
+        })*
+    };
+}
+
+other_errors!(
+    serde_json::Error,
+    std::io::Error,
+    SchemaError,
+    TemplateError
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::TaskEngine;
+
+    #[tokio::test]
+    async fn engine_errors_are_typed() {
+        let mut engine = TaskEngine::new();
+        assert!(matches!(
+            engine.get_state(1).await,
+            Err(TaskEngineError::NotFound(_))
+        ));
+
+        engine.init(1, "orders".to_string()).await.unwrap();
+        engine.start(1).await.unwrap();
+        engine.stop(1).await.unwrap();
+        let err = engine.finish(1).await.unwrap_err();
+        assert!(matches!(
+            err,
+            TaskEngineError::InvalidTransition {
+                from: TaskState::Stopped,
+                to: TaskState::Finished,
+            }
+        ));
+        assert!(!err.is_retryable());
+    }
+}
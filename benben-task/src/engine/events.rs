@@ -0,0 +1,104 @@
+//! 任务事件。
+//!
+//! 引擎在任务状态变化、记录用量时广播事件，UI 等订阅方可以实时展示任务进度和花费。
+//! 订阅方处理过慢时会丢失较早的事件，完整的事件记录可以从存储中读取。
+//! 设置了脱敏时，事件在保存和广播前脱敏。
+//!
+//! 推理模型的推理过程以 [TaskEvent::Reasoning] 单独广播，不是任务的最终输出，
+//! 订阅方可以按 [TaskEvent::is_reasoning] 过滤掉。
+//!
+//! 看门狗发现卡住的任务时广播 [TaskEvent::Stuck]，用于告警。
+
+use rig::completion::Usage;
+use rig::telemetry::redact;
+use serde::{Deserialize, Serialize};
+
+use super::watchdog::{StuckAction, StuckReason};
+use super::TaskState;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TaskEvent {
+    /// 任务状态发生变化
+    StateChanged { task_id: i32, state: TaskState },
+    /// 记录了一次模型调用的用量
+    UsageRecorded {
+        task_id: i32,
+        usage: Usage,
+        /// 本次调用的费用，美元
+        cost: f64,
+        /// 任务累计的费用，美元
+        total_cost: f64,
+    },
+    /// 模型在回答job前的推理过程，不是job的输出
+    Reasoning {
+        task_id: i32,
+        job_id: i32,
+        reasoning: String,
+    },
+    /// 看门狗发现任务卡住，`action` 为空时任务正在执行job，只告警不处理
+    Stuck {
+        task_id: i32,
+        reason: StuckReason,
+        action: Option<StuckAction>,
+    },
+}
+
+impl TaskEvent {
+    pub fn task_id(&self) -> i32 {
+        match self {
+            TaskEvent::StateChanged { task_id, .. }
+            | TaskEvent::UsageRecorded { task_id, .. }
+            | TaskEvent::Reasoning { task_id, .. }
+            | TaskEvent::Stuck { task_id, .. } => *task_id,
+        }
+    }
+
+    pub fn is_reasoning(&self) -> bool {
+        matches!(self, TaskEvent::Reasoning { .. })
+    }
+
+    /// 按当前生效的脱敏配置处理事件中的文本，无法往返序列化时保持原样
+    pub fn redacted(self) -> Self {
+        let Some(redactor) = redact::current() else {
+            return self;
+        };
+        let Ok(mut value) = serde_json::to_value(&self) else {
+            return self;
+        };
+        redactor.redact_json(&mut value);
+        serde_json::from_value(value).unwrap_or(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::engine::replay::{ReplayLog, ToolLogArgs};
+    use crate::engine::store::{MemoryStore, TaskStore};
+    use crate::engine::TaskEngine;
+
+    #[tokio::test]
+    async fn reasoning_is_recorded_apart_from_output() {
+        let store = Arc::new(MemoryStore::new());
+        let mut engine = TaskEngine::new().with_store(store.clone());
+        engine.init(1, "orders".to_string()).await.unwrap();
+        let mut events = engine.subscribe();
+
+        engine
+            .record_reasoning(1, 7, "count the orders first")
+            .await
+            .unwrap();
+        engine.record_reasoning(1, 7, "  ").await.unwrap();
+
+        let event = events.recv().await.unwrap();
+        assert!(event.is_reasoning());
+        assert!(events.try_recv().is_err());
+        let logs = store.load_tool_logs(1).await.unwrap();
+        assert_eq!(logs.len(), 1);
+        let args: ToolLogArgs = serde_json::from_str(logs[0].args.as_deref().unwrap()).unwrap();
+        assert!(args.reasoning);
+        assert_eq!(ReplayLog::from_logs(&logs).remaining(), 0);
+    }
+}
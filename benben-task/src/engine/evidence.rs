@@ -0,0 +1,430 @@
+//! 前置/后置检查点的证据。
+//!
+//! job在 action 中声明它会改动的产物：任务工作目录中的文件或目录，或者由注册的
+//! [RowSource] 读取的数据库行。执行前后各做一次快照，[StepDiff] 记录每个产物变化前后的完整
+//! 内容，既能看出job做了什么，也能据此撤销改动。差异作为证据写入 tool_log，再交给规则
+//! ([DiffRule]) 或者检查agent ([judge]) 判断这一步是否通过，未通过时计划中对应的步骤标记为失败。
+//!
+//! ```json
+//! {"prompt": "...", "check": {"artifacts": [{"type": "file", "path": "src"}],
+//!  "rule": {"require_changes": true, "forbidden": ["file:src/secrets"]}, "checker": "reviewer"}}
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use rig::agent::Agent;
+use rig::completion::{Completion, CompletionError, CompletionModel};
+use serde::{Deserialize, Serialize};
+
+use super::plan::PlanStatus;
+use super::replay::ToolLogArgs;
+use super::{TaskEngine, TaskEngineError};
+use crate::entities::tool_log;
+use crate::workspace::task_workspace_path;
+
+/// 读取任务相关的数据库行，返回行的键到内容的映射
+pub type RowSource =
+    Arc<dyn Fn(i32) -> BoxFuture<'static, Result<BTreeMap<String, String>, String>> + Send + Sync>;
+
+/// job会改动的产物
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Artifact {
+    /// 任务工作目录中的文件，目录时包含其中所有的文件
+    File { path: String },
+    /// 通过 [TaskEngine::register_row_source] 注册的数据库行
+    Rows { source: String },
+}
+
+/// 执行前后的检查
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StepCheck {
+    #[serde(default)]
+    pub artifacts: Vec<Artifact>,
+    #[serde(default)]
+    pub rule: Option<DiffRule>,
+    /// 判断差异的检查agent，由调用方解析后传给 [judge]
+    #[serde(default)]
+    pub checker: Option<String>,
+}
+
+/// 产物的快照，键为 `file:<相对路径>` 或者 `rows:<source>/<行的键>`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub entries: BTreeMap<String, String>,
+}
+
+/// 一个产物的变化，`before` 为空表示新增，`after` 为空表示删除
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub key: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// 执行前后的差异
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepDiff {
+    pub changes: Vec<Change>,
+}
+
+impl StepDiff {
+    pub fn between(before: &Snapshot, after: &Snapshot) -> Self {
+        let mut changes: Vec<Change> = before
+            .entries
+            .iter()
+            .filter(|(key, value)| after.entries.get(*key) != Some(value))
+            .map(|(key, value)| Change {
+                key: key.clone(),
+                before: Some(value.clone()),
+                after: after.entries.get(key).cloned(),
+            })
+            .collect();
+        changes.extend(
+            after
+                .entries
+                .iter()
+                .filter(|(key, _)| !before.entries.contains_key(*key))
+                .map(|(key, value)| Change {
+                    key: key.clone(),
+                    before: None,
+                    after: Some(value.clone()),
+                }),
+        );
+        changes.sort_by(|a, b| a.key.cmp(&b.key));
+        Self { changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// 按行渲染差异，供检查agent阅读
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for change in &self.changes {
+            let (before, after) = (change.before.as_deref(), change.after.as_deref());
+            let mark = match (before, after) {
+                (None, _) => "added",
+                (_, None) => "removed",
+                _ => "modified",
+            };
+            out.push_str(&format!("{mark} {}\n", change.key));
+            let old: Vec<&str> = before.map(|s| s.lines().collect()).unwrap_or_default();
+            let new: Vec<&str> = after.map(|s| s.lines().collect()).unwrap_or_default();
+            for line in old.iter().filter(|line| !new.contains(line)) {
+                out.push_str(&format!("- {line}\n"));
+            }
+            for line in new.iter().filter(|line| !old.contains(line)) {
+                out.push_str(&format!("+ {line}\n"));
+            }
+        }
+        out
+    }
+}
+
+/// 检查的结论
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Verdict {
+    pub pass: bool,
+    #[serde(default)]
+    pub reason: String,
+}
+
+impl Verdict {
+    pub fn pass() -> Self {
+        Self {
+            pass: true,
+            reason: String::new(),
+        }
+    }
+
+    pub fn fail(reason: impl Into<String>) -> Self {
+        Self {
+            pass: false,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// 按规则判断差异，`allowed` 和 `forbidden` 是键的前缀
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffRule {
+    /// job必须改动至少一个产物
+    #[serde(default)]
+    pub require_changes: bool,
+    /// 只允许改动这些产物，为空时不限制
+    #[serde(default)]
+    pub allowed: Vec<String>,
+    /// 不允许改动这些产物
+    #[serde(default)]
+    pub forbidden: Vec<String>,
+}
+
+impl DiffRule {
+    pub fn evaluate(&self, diff: &StepDiff) -> Verdict {
+        if self.require_changes && diff.is_empty() {
+            return Verdict::fail("no artifact was changed");
+        }
+        for change in &diff.changes {
+            let matches = |prefixes: &[String]| prefixes.iter().any(|p| change.key.starts_with(p));
+            if matches(&self.forbidden) {
+                return Verdict::fail(format!("{} must not be changed", change.key));
+            }
+            if !self.allowed.is_empty() && !matches(&self.allowed) {
+                return Verdict::fail(format!("{} is not allowed to change", change.key));
+            }
+        }
+        Verdict::pass()
+    }
+}
+
+/// 让检查agent判断差异是否完成了job的要求，回答json `{"pass": bool, "reason": "..."}`，
+/// 不是json时以 PASS 开头视为通过
+pub async fn judge<M: CompletionModel>(
+    agent: &Agent<M>,
+    task: &str,
+    diff: &StepDiff,
+) -> Result<Verdict, CompletionError> {
+    let prompt = format!(
+        "A step was asked to do:\n{task}\n\nIt made these changes:\n{}\n\
+         Does the change fulfil the step? Answer with json {{\"pass\": true|false, \"reason\": \"...\"}}.",
+        if diff.is_empty() {
+            "(no changes)\n".to_string()
+        } else {
+            diff.render()
+        }
+    );
+    let response = agent
+        .completion(prompt.as_str(), vec![])
+        .await?
+        .send()
+        .await?;
+    let answer = response
+        .choice
+        .iter()
+        .filter_map(|content| match content {
+            rig::completion::AssistantContent::Text(text) => Some(text.text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let answer = answer.trim();
+    let json = answer
+        .find('{')
+        .zip(answer.rfind('}'))
+        .and_then(|(start, end)| answer.get(start..=end));
+    Ok(
+        match json.and_then(|json| serde_json::from_str(json).ok()) {
+            Some(verdict) => verdict,
+            None if answer.to_uppercase().starts_with("PASS") => Verdict::pass(),
+            None => Verdict::fail(answer),
+        },
+    )
+}
+
+/// 目录下所有文件的相对路径和内容
+fn collect_files(
+    root: &Path,
+    path: &Path,
+    entries: &mut BTreeMap<String, String>,
+) -> std::io::Result<()> {
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            collect_files(root, &entry?.path(), entries)?;
+        }
+    } else if path.is_file() {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let content = std::fs::read(path)?;
+        entries.insert(
+            format!("file:{}", relative.to_string_lossy().replace('\\', "/")),
+            String::from_utf8_lossy(&content).into_owned(),
+        );
+    }
+    Ok(())
+}
+
+/// 检查证据，记录在 tool_log.output 中
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Evidence {
+    pub diff: StepDiff,
+    pub verdict: Verdict,
+}
+
+impl TaskEngine {
+    /// 设置任务工作目录的根路径
+    pub fn with_workspace_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.workspace_dir = dir.into();
+        self
+    }
+
+    /// 注册数据库行的来源，同名的来源被替换
+    pub fn register_row_source(&self, name: impl Into<String>, source: RowSource) {
+        self.row_sources
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.into(), source);
+    }
+
+    /// 对产物做快照，不存在的文件不出现在快照中
+    pub async fn snapshot(
+        &self,
+        task_id: i32,
+        artifacts: &[Artifact],
+    ) -> Result<Snapshot, TaskEngineError> {
+        let root = task_workspace_path(&self.workspace_dir, task_id);
+        let mut snapshot = Snapshot::default();
+        for artifact in artifacts {
+            match artifact {
+                Artifact::File { path } => {
+                    collect_files(&root, &root.join(path), &mut snapshot.entries)?;
+                }
+                Artifact::Rows { source } => {
+                    let read = self
+                        .row_sources
+                        .read()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .get(source)
+                        .cloned()
+                        .ok_or_else(|| TaskEngineError::NotFound(format!("Row source {source}")))?;
+                    for (key, row) in read(task_id).await? {
+                        snapshot.entries.insert(format!("rows:{source}/{key}"), row);
+                    }
+                }
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// job执行后再次快照，按规则判断差异并把证据写入 tool_log。
+    /// 规则通过且给了检查agent时再由agent判断，未通过时计划中对应的步骤标记为失败
+    pub async fn check_step<M: CompletionModel>(
+        &self,
+        task_id: i32,
+        job_id: i32,
+        check: &StepCheck,
+        before: &Snapshot,
+        checker: Option<(&Agent<M>, &str)>,
+    ) -> Result<Verdict, TaskEngineError> {
+        self.ensure_writable()?;
+        let after = self.snapshot(task_id, &check.artifacts).await?;
+        let diff = StepDiff::between(before, &after);
+        let mut verdict = check
+            .rule
+            .as_ref()
+            .map_or_else(Verdict::pass, |rule| rule.evaluate(&diff));
+        if let (true, Some((agent, task))) = (verdict.pass, checker) {
+            verdict = judge(agent, task, &diff).await?;
+        }
+        if let Some(store) = self.store() {
+            let args = ToolLogArgs {
+                job_id,
+                rejected: !verdict.pass,
+                evidence: true,
+                ..Default::default()
+            };
+            let evidence = Evidence {
+                diff,
+                verdict: verdict.clone(),
+            };
+            store
+                .append_tool_log(tool_log::Model {
+                    id: 0,
+                    taskid: Some(task_id),
+                    planid: None,
+                    args: Some(serde_json::to_string(&args)?),
+                    output: Some(rig::telemetry::redact::redact(&serde_json::to_string(
+                        &evidence,
+                    )?)),
+                })
+                .await?;
+        }
+        if let Some(mut context) = self.tasks.lock(task_id).await {
+            context.execution_history.push(match verdict.pass {
+                true => format!("Job {job_id} passed its check"),
+                false => format!("Job {job_id} failed its check: {}", verdict.reason),
+            });
+        }
+        if !verdict.pass {
+            self.set_plan_job_status(task_id, job_id, PlanStatus::Failure)
+                .await?;
+        }
+        Ok(verdict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::store::{MemoryStore, TaskStore};
+    use rig::client::mock::{self, MockClient};
+    use rig::client::CompletionClient;
+
+    #[tokio::test]
+    async fn step_diff_is_checked_and_recorded() {
+        let dir = std::env::temp_dir().join(format!("benben-evidence-{}", std::process::id()));
+        let store = Arc::new(MemoryStore::new());
+        let mut engine = TaskEngine::new()
+            .with_store(store.clone())
+            .with_workspace_dir(&dir);
+        engine.init(1, "orders".to_string()).await.unwrap();
+        engine.register_row_source(
+            "orders",
+            Arc::new(|_| Box::pin(async { Ok(BTreeMap::from([("1".into(), "open".into())])) })),
+        );
+        let workspace = task_workspace_path(&dir, 1);
+        std::fs::create_dir_all(workspace.join("src")).unwrap();
+        std::fs::write(workspace.join("src/a.rs"), "fn a() {}\n").unwrap();
+
+        let check = StepCheck {
+            artifacts: vec![
+                Artifact::File { path: "src".into() },
+                Artifact::Rows {
+                    source: "orders".into(),
+                },
+            ],
+            rule: Some(DiffRule {
+                require_changes: true,
+                forbidden: vec!["file:src/secret".into()],
+                ..Default::default()
+            }),
+            checker: None,
+        };
+        let before = engine.snapshot(1, &check.artifacts).await.unwrap();
+        std::fs::write(workspace.join("src/a.rs"), "fn a() { b() }\n").unwrap();
+        std::fs::write(workspace.join("src/b.rs"), "fn b() {}\n").unwrap();
+
+        let script = mock::script("evidence-test");
+        script.push_text(r#"{"pass": false, "reason": "b is not tested"}"#);
+        let agent = MockClient::new().agent("evidence-test").build();
+        let verdict = engine
+            .check_step(1, 7, &check, &before, Some((&agent, "add b")))
+            .await
+            .unwrap();
+        assert_eq!(verdict, Verdict::fail("b is not tested"));
+        assert!(script.requests()[0]
+            .chat_history
+            .iter()
+            .any(|m| format!("{m:?}").contains("+ fn a() { b() }")));
+
+        let logs = store.load_tool_logs(1).await.unwrap();
+        let evidence: Evidence = serde_json::from_str(logs[0].output.as_deref().unwrap()).unwrap();
+        assert_eq!(evidence.diff.changes.len(), 2);
+        assert_eq!(
+            evidence.diff.changes[0].before.as_deref(),
+            Some("fn a() {}\n")
+        );
+        assert_eq!(evidence.diff.changes[1].before, None);
+
+        std::fs::write(workspace.join("src/secret.key"), "x").unwrap();
+        let verdict = engine
+            .check_step::<rig::client::mock::MockCompletionModel>(1, 7, &check, &before, None)
+            .await
+            .unwrap();
+        assert!(!verdict.pass);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
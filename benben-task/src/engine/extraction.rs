@@ -0,0 +1,232 @@
+//! 多阶段抽取流水线。
+//!
+//! 长文档先按窗口切分，每个窗口分别抽取，再把各窗口的部分结果合并。流水线由多个阶段组成，
+//! 例如先粗抽出聚合列表，再带着上一阶段的结果细化出实体、行为和值对象。每个阶段有自己的
+//! schema，后一阶段的schema在前一阶段的基础上扩展，即 schema 随阶段演进。
+//!
+//! 类型为 [EXTRACTION_JOB_TYPE] 的job在 action 中声明 [ExtractionSpec]，
+//! 用于 workflow.rs 中描述的 ddd 分析步骤，[ExtractionSpec::ddd] 是内置的分析流水线。
+
+use rig::agent::Agent;
+use rig::completion::CompletionModel;
+use rig::extractor::{DynExtractor, ExtractionError};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// 抽取job的类型
+pub const EXTRACTION_JOB_TYPE: &str = "extraction";
+
+/// 流水线中的一个阶段
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtractionStage {
+    pub name: String,
+    /// 该阶段输出的json schema
+    pub schema: Value,
+    #[serde(default)]
+    pub instructions: Option<String>,
+}
+
+/// 抽取job的 action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtractionSpec {
+    /// 待抽取的文档，支持prompt模板
+    pub document: String,
+    pub stages: Vec<ExtractionStage>,
+    /// 每个窗口的字符数
+    #[serde(default = "default_window_chars")]
+    pub window_chars: usize,
+    /// 相邻窗口重叠的字符数，避免实体被切断
+    #[serde(default = "default_overlap")]
+    pub overlap: usize,
+}
+
+fn default_window_chars() -> usize {
+    8000
+}
+
+fn default_overlap() -> usize {
+    400
+}
+
+impl ExtractionSpec {
+    pub fn parse(action: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(action)
+    }
+
+    /// ddd 分析：先粗抽聚合，再细化每个聚合的实体、行为和值对象
+    pub fn ddd(document: impl Into<String>) -> Self {
+        let aggregates = json!({
+            "type": "object",
+            "properties": {
+                "aggregates": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": { "name": { "type": "string" } },
+                        "required": ["name"]
+                    }
+                }
+            },
+            "required": ["aggregates"]
+        });
+        let detailed = json!({
+            "type": "object",
+            "properties": {
+                "aggregates": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string" },
+                            "entities": { "type": "array", "items": { "type": "string" } },
+                            "behaviors": { "type": "array", "items": { "type": "string" } },
+                            "value_objects": { "type": "array", "items": { "type": "string" } }
+                        },
+                        "required": ["name"]
+                    }
+                }
+            },
+            "required": ["aggregates"]
+        });
+        Self {
+            document: document.into(),
+            stages: vec![
+                ExtractionStage {
+                    name: "aggregates".to_string(),
+                    schema: aggregates,
+                    instructions: Some(
+                        "List the DDD aggregates mentioned in the text.".to_string(),
+                    ),
+                },
+                ExtractionStage {
+                    name: "refine".to_string(),
+                    schema: detailed,
+                    instructions: Some(
+                        "For each aggregate list its entities, behaviors and value objects."
+                            .to_string(),
+                    ),
+                },
+            ],
+            window_chars: default_window_chars(),
+            overlap: default_overlap(),
+        }
+    }
+
+    /// 按窗口切分文档，按字符计
+    pub fn windows<'a>(&self, document: &'a str) -> Vec<&'a str> {
+        let window = self.window_chars.max(1);
+        let step = window.saturating_sub(self.overlap).max(1);
+        let bounds: Vec<usize> = document
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(document.len()))
+            .collect();
+        let chars = bounds.len() - 1;
+        let mut windows = Vec::new();
+        let mut start = 0;
+        loop {
+            let end = (start + window).min(chars);
+            windows.push(&document[bounds[start]..bounds[end]]);
+            if end == chars {
+                break;
+            }
+            start += step;
+        }
+        windows
+    }
+
+    /// 执行流水线，返回最后一个阶段合并后的结果
+    pub async fn run<M: CompletionModel>(
+        &self,
+        agent: &Agent<M>,
+        document: &str,
+    ) -> Result<Value, ExtractionError> {
+        let windows = self.windows(document);
+        let mut previous: Option<Value> = None;
+        for stage in &self.stages {
+            let mut instructions = stage.instructions.clone().unwrap_or_default();
+            if let Some(previous) = &previous {
+                instructions.push_str(&format!(
+                    "\nRefine the result of the previous step, keep every item it found:\n{previous}"
+                ));
+            }
+            let extractor =
+                DynExtractor::new(agent.clone(), stage.schema.clone()).instructions(instructions);
+            let mut merged = Value::Null;
+            for window in &windows {
+                let partial = extractor.extract_value(window).await?;
+                merge(&mut merged, partial);
+            }
+            tracing::debug!("extraction stage {} done", stage.name);
+            previous = Some(merged);
+        }
+        Ok(previous.unwrap_or(Value::Null))
+    }
+}
+
+/// 合并两个窗口的部分结果：对象按字段递归合并，数组拼接并按 name 字段（没有时按整体）去重，
+/// 其他值保留先出现的非空值
+pub fn merge(into: &mut Value, from: Value) {
+    match (into, from) {
+        (_, Value::Null) => {}
+        (into @ Value::Null, from) => *into = from,
+        (Value::Object(into), Value::Object(from)) => {
+            for (key, value) in from {
+                merge(into.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (Value::Array(into), Value::Array(from)) => {
+            for item in from {
+                match into.iter_mut().find(|existing| same_item(existing, &item)) {
+                    Some(existing) => merge(existing, item),
+                    None => into.push(item),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn same_item(a: &Value, b: &Value) -> bool {
+    match (name_of(a), name_of(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn name_of(value: &Value) -> Option<&Value> {
+    value.as_object().and_then(|o| o.get("name"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_overlap_and_partials_merge() {
+        let mut spec = ExtractionSpec::ddd("");
+        spec.window_chars = 4;
+        spec.overlap = 1;
+        assert_eq!(spec.windows("订单聚合与库存"), vec!["订单聚合", "合与库存"]);
+
+        let mut merged = Value::Null;
+        merge(
+            &mut merged,
+            json!({"aggregates": [{"name": "Order", "entities": ["Order"]}]}),
+        );
+        merge(
+            &mut merged,
+            json!({"aggregates": [
+                {"name": "Order", "entities": ["OrderLine"], "value_objects": ["Money"]},
+                {"name": "Stock"}
+            ]}),
+        );
+        assert_eq!(
+            merged,
+            json!({"aggregates": [
+                {"name": "Order", "entities": ["Order", "OrderLine"], "value_objects": ["Money"]},
+                {"name": "Stock"}
+            ]})
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! 测试共用的实体构造函数。
+//!
+//! 测试只关心少数字段，其余字段取这里的默认值，需要时用结构体更新语法覆盖：
+//!
+//! ```rust,ignore
+//! let merge = job::Model {
+//!     r#type: Some(AGGREGATE_JOB_TYPE.into()),
+//!     ..fixtures::agent_job(3, "merger", r#"{"prompt": "Summarize", "jobs": [1, 2]}"#)
+//! };
+//! ```
+
+use crate::entities::{job, workflow};
+
+/// 工作流1中的job，`workid` 为 `w{id}`，没有上游job，也不指定agent
+pub(crate) fn job(id: i32, action: &str) -> job::Model {
+    job::Model {
+        id,
+        workid: format!("w{id}"),
+        workflow_id: 1,
+        pid: None,
+        code: None,
+        action: Some(action.to_string()),
+        description: None,
+        check: None,
+        r#type: None,
+        deleted: false,
+    }
+}
+
+/// 由 `agent` 执行的job
+pub(crate) fn agent_job(id: i32, agent: &str, action: &str) -> job::Model {
+    job::Model {
+        code: Some(agent.to_string()),
+        ..job(id, action)
+    }
+}
+
+/// 以 `pid` 为上游的job
+pub(crate) fn downstream(pid: i32, job: job::Model) -> job::Model {
+    job::Model {
+        pid: Some(pid),
+        ..job
+    }
+}
+
+/// 第一个版本的工作流，没有计划和输入输出约束
+pub(crate) fn workflow(id: &str) -> workflow::Model {
+    workflow::Model {
+        id: id.to_string(),
+        code: None,
+        name: None,
+        desc: None,
+        plan: None,
+        input_schema: None,
+        output_schema: None,
+        sla: None,
+        version: 1,
+        deleted: false,
+        owner_id: None,
+    }
+}
@@ -0,0 +1,201 @@
+//! job输出的后处理与校验。
+//!
+//! 每个job可以在 action 中声明一组按顺序执行的 guardrail：正则校验、JSON Schema 校验、
+//! 禁用内容检查、最大长度截断。校验失败时把失败原因追加到对话中让agent重新回答，
+//! 最多尝试 `max_attempts` 次，只有通过所有校验的输出才算job完成。
+
+use regex::Regex;
+use rig::agent::Agent;
+use rig::completion::{
+    AssistantContent, Completion, CompletionError, CompletionModel, CompletionRequestBuilder,
+    Message,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 单个后处理步骤
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Guardrail {
+    /// 输出必须匹配正则
+    Regex { pattern: String },
+    /// 输出必须是符合schema的json
+    JsonSchema { schema: Value },
+    /// 输出不能包含这些内容，忽略大小写
+    Banned { words: Vec<String> },
+    /// 超过长度的输出被截断，按字符计
+    MaxLength { chars: usize },
+}
+
+impl Guardrail {
+    /// 处理输出，返回处理后的输出或者失败原因
+    pub fn apply(&self, output: String) -> Result<String, String> {
+        match self {
+            Guardrail::Regex { pattern } => {
+                let regex = Regex::new(pattern).map_err(|e| format!("invalid regex: {e}"))?;
+                if regex.is_match(&output) {
+                    Ok(output)
+                } else {
+                    Err(format!("output does not match pattern {pattern}"))
+                }
+            }
+            Guardrail::JsonSchema { schema } => {
+                let validator = jsonschema::validator_for(schema)
+                    .map_err(|e| format!("invalid schema: {e}"))?;
+                let value: Value = serde_json::from_str(output.trim())
+                    .map_err(|e| format!("output is not valid json: {e}"))?;
+                let errors: Vec<String> = validator
+                    .iter_errors(&value)
+                    .map(|e| e.to_string())
+                    .collect();
+                if errors.is_empty() {
+                    Ok(output)
+                } else {
+                    Err(format!(
+                        "output does not match schema: {}",
+                        errors.join("; ")
+                    ))
+                }
+            }
+            Guardrail::Banned { words } => {
+                let lower = output.to_lowercase();
+                match words
+                    .iter()
+                    .find(|word| lower.contains(&word.to_lowercase()))
+                {
+                    Some(word) => Err(format!("output contains banned content: {word}")),
+                    None => Ok(output),
+                }
+            }
+            Guardrail::MaxLength { chars } => match output.char_indices().nth(*chars) {
+                Some((end, _)) => Ok(output[..end].to_string()),
+                None => Ok(output),
+            },
+        }
+    }
+}
+
+/// job的后处理链
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Guardrails {
+    #[serde(default)]
+    pub steps: Vec<Guardrail>,
+    /// 校验失败时最多尝试的次数，包含第一次
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: usize,
+}
+
+fn default_max_attempts() -> usize {
+    1
+}
+
+impl Default for Guardrails {
+    fn default() -> Self {
+        Self {
+            steps: Vec::new(),
+            max_attempts: default_max_attempts(),
+        }
+    }
+}
+
+/// 一次校验失败
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GuardrailFailure {
+    pub attempt: usize,
+    pub reason: String,
+    pub output: String,
+}
+
+impl Guardrails {
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// 按顺序执行所有步骤
+    pub fn check(&self, output: String) -> Result<String, String> {
+        self.steps
+            .iter()
+            .try_fold(output, |output, step| step.apply(output))
+    }
+
+    /// 调用agent并校验输出，失败时带上失败原因重新提问。
+    /// 返回通过校验的输出以及之前所有失败的记录
+    pub async fn complete<M: CompletionModel>(
+        &self,
+        agent: &Agent<M>,
+        prompt: &str,
+        build: impl Fn(CompletionRequestBuilder<M>) -> CompletionRequestBuilder<M>,
+    ) -> Result<(String, Vec<GuardrailFailure>), GuardrailError> {
+        let mut history: Vec<Message> = Vec::new();
+        let mut prompt = Message::user(prompt);
+        let mut failures = Vec::new();
+        for attempt in 1..=self.max_attempts.max(1) {
+            let builder = agent.completion(prompt.clone(), history.clone()).await?;
+            let response = build(builder).send().await?;
+            let output = response
+                .choice
+                .iter()
+                .filter_map(|content| match content {
+                    AssistantContent::Text(text) => Some(text.text.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            match self.check(output.clone()) {
+                Ok(output) => return Ok((output, failures)),
+                Err(reason) => {
+                    history.push(prompt);
+                    history.push(Message::assistant(output.clone()));
+                    prompt = Message::user(format!(
+                        "The previous answer was rejected: {reason}. Answer again and fix the problem."
+                    ));
+                    failures.push(GuardrailFailure {
+                        attempt,
+                        reason,
+                        output,
+                    });
+                }
+            }
+        }
+        Err(GuardrailError::Rejected(failures))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GuardrailError {
+    #[error("{0}")]
+    Completion(#[from] CompletionError),
+    #[error("output rejected after {} attempts: {}", .0.len(), .0.last().map(|f| f.reason.as_str()).unwrap_or_default())]
+    Rejected(Vec<GuardrailFailure>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_steps_in_order() {
+        let guardrails = Guardrails {
+            steps: vec![
+                Guardrail::Banned {
+                    words: vec!["password".to_string()],
+                },
+                Guardrail::MaxLength { chars: 12 },
+                Guardrail::JsonSchema {
+                    schema: serde_json::json!({"type": "object", "required": ["ok"]}),
+                },
+            ],
+            max_attempts: 3,
+        };
+        assert_eq!(
+            guardrails.check(r#"{"ok":true}"#.to_string()).unwrap(),
+            r#"{"ok":true}"#
+        );
+        assert!(guardrails.check("my PASSWORD is".to_string()).is_err());
+        // 截断之后不再是合法的json
+        assert!(guardrails
+            .check(r#"{"ok":true,"more":1}"#.to_string())
+            .is_err());
+    }
+}
@@ -0,0 +1,70 @@
+//! 引擎的 OpenTelemetry 指标。
+//!
+//! 开启 `otel-metrics` feature 后引擎记录各状态的任务数和job耗时，provider 请求延迟、
+//! token 用量和 MCP 调用失败由 [rig::telemetry::metrics] 记录。指标写入全局的 meter provider，
+//! 运维在启动时安装导出器（OTLP、Prometheus 等）即可接入 Grafana。
+//!
+//! - `benben.tasks`：各状态的任务数，`task.state` 区分状态
+//! - `benben.job.duration`：job 耗时，秒，`job.type` 和 `agent` 区分
+
+use once_cell::sync::OnceCell;
+use opentelemetry::metrics::{Gauge, Histogram, Meter};
+use opentelemetry::KeyValue;
+
+use super::report::StepRecord;
+use super::TaskState;
+
+const STATES: [TaskState; 6] = [
+    TaskState::Waiting,
+    TaskState::Running,
+    TaskState::Pending,
+    TaskState::Finished,
+    TaskState::Cancelled,
+    TaskState::Stopped,
+];
+
+pub struct EngineMetrics {
+    tasks: Gauge<u64>,
+    job_duration: Histogram<f64>,
+}
+
+impl EngineMetrics {
+    pub fn new(meter: &Meter) -> Self {
+        Self {
+            tasks: meter
+                .u64_gauge("benben.tasks")
+                .with_unit("{task}")
+                .with_description("各状态的任务数")
+                .build(),
+            job_duration: meter
+                .f64_histogram("benben.job.duration")
+                .with_unit("s")
+                .with_description("job 耗时")
+                .build(),
+        }
+    }
+
+    pub fn global() -> &'static Self {
+        static GLOBAL: OnceCell<EngineMetrics> = OnceCell::new();
+        GLOBAL.get_or_init(|| Self::new(&opentelemetry::global::meter("benben-task")))
+    }
+
+    /// 记录各状态的任务数，没有任务的状态记为0
+    pub fn record_tasks(&self, states: &[TaskState]) {
+        for state in STATES {
+            let count = states.iter().filter(|s| **s == state).count();
+            self.tasks
+                .record(count as u64, &[KeyValue::new("task.state", state.as_str())]);
+        }
+    }
+
+    pub fn record_job(&self, step: &StepRecord) {
+        self.job_duration.record(
+            step.duration_ms as f64 / 1000.0,
+            &[
+                KeyValue::new("job.type", step.job_type.clone().unwrap_or_default()),
+                KeyValue::new("agent", step.agent.clone().unwrap_or_default()),
+            ],
+        );
+    }
+}
@@ -5,42 +5,96 @@
 //! 3、任务的执行过程可根据任务调用ai程序进行处理。其本质作用是为了使用更少的token完成更长链路的工作。
 //! 4、长趋势的留痕有助于任务的连贯性。
 
+pub mod action;
 pub mod adapter;
+pub mod aggregate;
+pub mod attempts;
+pub mod batch;
+pub mod budget;
+pub mod bulk;
+pub mod cache;
+pub mod checker;
+pub mod checkpoint;
+mod completion;
+pub mod clock;
+pub mod cost;
+pub mod dataset;
+pub mod error;
+pub mod events;
+pub mod evidence;
+pub mod extraction;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod guardrail;
+#[cfg(feature = "otel-metrics")]
+pub mod metrics;
+pub mod observer;
+pub mod plan;
+pub mod policy;
+pub mod preview;
+pub mod query;
+pub mod queue;
+pub mod replay;
+pub mod report;
 pub mod runnings;
+pub mod scheduler;
+pub mod simulation;
+pub mod schema;
+pub mod sla;
+pub mod store;
+pub mod subworkflow;
+pub mod tasks;
+pub mod template;
+pub mod tenant;
+pub mod trace;
+pub mod transition;
+pub mod trigger;
+pub mod versioning;
+pub mod vram;
+pub mod watchdog;
 
 
 use crate::entities::{task, job, tool_log, workflow};
-use std::sync::Arc;
+use crate::global::InitError;
+use crate::mananger::AgentManager;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::collections::HashMap;
-use tokio::sync::Mutex;
-use sea_orm::{DatabaseConnection, EntityTrait, ActiveModelTrait};
-use sea_orm::ActiveValue::Set;
+use std::path::PathBuf;
+use tokio::sync::broadcast;
+use sea_orm::DatabaseConnection;
 use once_cell::sync::OnceCell;
+use rig::completion::{GetTokenUsage, Usage};
+use rig::streaming::StreamingCompletionResponse;
+use action::{JobAction, ParamBounds};
+use aggregate::{AggregateSpec, AGGREGATE_JOB_TYPE};
+use schema::{SchemaError, TaskSchemas};
+use policy::{PolicyDecision, PolicyEngine, PolicySubject};
+use checkpoint::CheckpointPolicy;
+use clock::{Clock, SystemClock};
+use completion::JobCompletion;
+use tasks::TaskMap;
+use transition::{Actor, Transition};
+use cost::PricingTable;
+pub use error::TaskEngineError;
+use events::TaskEvent;
+use evidence::RowSource;
+use extraction::{ExtractionSpec, EXTRACTION_JOB_TYPE};
+use queue::{BlockKind, BlockReason};
+use replay::{ReplayLog, ToolLogArgs};
+use report::StepRecord;
+use simulation::StubAgents;
+use sla::{SlaKind, SlaMonitor, SlaPolicy};
+use store::{SeaOrmStore, TaskStore};
+use subworkflow::{SubWorkflowMode, SubWorkflowSpec, SUBWORKFLOW_JOB_TYPE};
+use template::{render_prompt, PromptContext};
+use tenant::{owner_of, OwnerQuota};
+use trigger::Trigger;
+use versioning::{VersionError, WorkflowSnapshot};
+use vram::VramScheduler;
 
-/// 任务状态枚举
-#[derive(Debug, Clone, PartialEq)]
-pub enum TaskState {
-    Running,
-    Stopped,
-    Cancelled,
-    Finished,
-    Pending,
-    Waiting,
-}
-
-impl TaskState {
-    /// 将TaskState转换为字符串表示
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            TaskState::Running => "running",
-            TaskState::Stopped => "stopped",
-            TaskState::Cancelled => "cancelled",
-            TaskState::Finished => "finished",
-            TaskState::Pending => "pending",
-            TaskState::Waiting => "waiting",
-        }
-    }
-}
+/// 任务状态枚举，与接口共用同一个定义
+pub use benben_api::TaskState;
 
 /// 单个任务的上下文信息
 #[derive(Debug, Clone)]
@@ -53,6 +107,36 @@ pub struct TaskContext {
     pub workflow: Option<workflow::Model>,
     /// 任务执行历史记录
     pub execution_history: Vec<String>,
+    /// 任务累计的token用量，包含provider上报的缓存命中数
+    pub usage: Usage,
+    /// 已经人工审批通过的job
+    pub approved_jobs: Vec<i32>,
+    /// 当前阻塞任务的条件
+    pub blocked: Option<BlockReason>,
+    /// 已经人工跳过的阻塞条件
+    pub skipped_blocks: Vec<BlockKind>,
+    /// 上一个执行完成的job及其输出
+    pub last_output: Option<(i32, String)>,
+    /// 已经执行完成的job数
+    pub step: usize,
+    /// 任务变量，prompt模板中通过 workspace.xxx 引用
+    pub variables: HashMap<String, String>,
+    /// 任务累计的费用，美元
+    pub cost: f64,
+    /// 重放任务时使用的job输出，见 [replay]
+    pub replay: Option<ReplayLog>,
+    /// 任务开始时工作流版本的job定义，以job id为键，见 [versioning]
+    pub pinned_jobs: HashMap<i32, job::Model>,
+    /// 等待本任务的父任务，见 [subworkflow]
+    pub parent: Option<i32>,
+    /// 子工作流job派生的子任务，以job id为键
+    pub children: HashMap<i32, i32>,
+    /// 被高优先级任务抢占而暂停时，抢占它的任务，见 [scheduler]
+    pub preempted_by: Option<i32>,
+    /// 已经执行完成的job的记录，用于生成任务报告，见 [report]
+    pub steps: Vec<StepRecord>,
+    /// 已经执行完成的job的输出，以job id为键，见 [aggregate]
+    pub outputs: HashMap<i32, String>,
 }
 
 // Static instance for global access
@@ -61,17 +145,71 @@ static ENGINE_INSTANCE: OnceCell<Arc<TaskEngine>> = OnceCell::new();
 /// 任务引擎核心结构
 pub struct TaskEngine {
     /// 多个任务的上下文，以任务ID为键
-    tasks: Arc<Mutex<HashMap<i32, TaskContext>>>,
-    /// 数据库连接
-    db: Option<Arc<DatabaseConnection>>,
+    tasks: TaskMap,
+    /// 数据库连接，维护模式下可以切换
+    db: RwLock<Option<Arc<DatabaseConnection>>>,
+    /// 维护模式，开启后不再接收新任务，也不再推进已有任务
+    maintenance: AtomicBool,
+    /// 只读观察模式，只提供查询，拒绝所有修改操作
+    observer: bool,
+    /// 主备部署中的备用节点，提升为主节点前拒绝所有修改操作
+    standby: AtomicBool,
+    /// 任务审批策略
+    policy: Option<Arc<PolicyEngine>>,
+    /// 显存感知调度，共用显卡的本地模型job按显存预算排队
+    vram: Option<Arc<VramScheduler>>,
+    /// 规划agent为job设置生成参数时允许的范围
+    param_bounds: ParamBounds,
+    /// 任务的SLA计时
+    sla: SlaMonitor,
+    /// 模型价格表
+    pricing: PricingTable,
+    /// 任务事件
+    events: broadcast::Sender<TaskEvent>,
+    /// 任务数据的存储，未设置时使用数据库连接
+    store: Option<Arc<dyn TaskStore>>,
+    /// 时钟，测试中可以替换为 MockClock
+    clock: Arc<dyn Clock>,
+    /// 按名称注册的触发器，见 [trigger]
+    triggers: RwLock<HashMap<String, Trigger>>,
+    /// 每个用户的配额，见 [tenant]
+    quotas: RwLock<HashMap<String, OwnerQuota>>,
+    /// 把任务报告写成markdown的agent，见 [report]
+    report_agent: Option<String>,
+    /// 流式输出写入检查点的频率，见 [checkpoint]
+    checkpoint: CheckpointPolicy,
+    /// 任务工作目录的根路径，检查点在其中对文件做快照
+    workspace_dir: PathBuf,
+    /// 按名称注册的数据库行来源，见 [evidence]
+    row_sources: RwLock<HashMap<String, RowSource>>,
+    /// 代替模型的桩agent，见 [simulation]
+    stubs: Option<Arc<StubAgents>>,
 }
 
 impl TaskEngine {
     /// 创建新的任务引擎实例
     pub fn new() -> Self {
         Self {
-            tasks: Arc::new(Mutex::new(HashMap::new())),
-            db: None,
+            tasks: TaskMap::new(),
+            db: RwLock::new(None),
+            maintenance: AtomicBool::new(false),
+            observer: false,
+            standby: AtomicBool::new(false),
+            policy: None,
+            vram: None,
+            param_bounds: ParamBounds::default(),
+            sla: SlaMonitor::default(),
+            pricing: PricingTable::default(),
+            events: broadcast::channel(256).0,
+            store: None,
+            clock: Arc::new(SystemClock),
+            triggers: RwLock::new(HashMap::new()),
+            quotas: RwLock::new(HashMap::new()),
+            report_agent: None,
+            checkpoint: CheckpointPolicy::default(),
+            workspace_dir: PathBuf::from(crate::workspace::DEFAULT_WORKSPACE_DIR),
+            row_sources: RwLock::new(HashMap::new()),
+            stubs: None,
         }
     }
 
@@ -80,264 +218,1083 @@ impl TaskEngine {
         ENGINE_INSTANCE.get().cloned()
     }
 
-    /// 初始化全局任务引擎实例
-    pub fn init_global(engine: TaskEngine) -> Result<Arc<TaskEngine>, Box<dyn std::error::Error>> {
+    /// 获取全局任务引擎实例，未初始化时返回错误
+    pub fn try_global() -> Result<Arc<TaskEngine>, InitError> {
+        Self::global().ok_or(InitError::NotInitialized("task engine"))
+    }
+
+    /// 初始化全局任务引擎实例，只能初始化一次
+    pub fn init_global(engine: TaskEngine) -> Result<Arc<TaskEngine>, InitError> {
         let engine = Arc::new(engine);
-        ENGINE_INSTANCE.set(engine.clone()).map_err(|_| "Failed to set global engine instance")?;
+        ENGINE_INSTANCE.set(engine.clone()).map_err(|_| InitError::AlreadyInitialized("task engine"))?;
         Ok(engine)
     }
 
     /// 设置数据库连接
-    pub fn with_db(mut self, db: Arc<DatabaseConnection>) -> Self {
-        self.db = Some(db);
+    pub fn with_db(self, db: Arc<DatabaseConnection>) -> Self {
+        *self.db.write().unwrap_or_else(|e| e.into_inner()) = Some(db);
         self
     }
 
-    /// 初始化任务引擎，设置任务ID和输入
-    pub async fn init(&mut self, task_id: i32, input: String) -> Result<(), Box<dyn std::error::Error>> {
-        let mut tasks = self.tasks.lock().await;
-        
-        let task_context = TaskContext {
-            state: TaskState::Waiting,
-            task: Some(task::Model {
-                id: task_id,
-                input: Some(input),
-                output: None,
-                state: Some("waiting".to_string()),
-                wid: None,
-                planid: None,
-            }),
-            workflow: None,
-            execution_history: Vec::new(),
-        };
-        
-        tasks.insert(task_id, task_context);
+    /// 设置时钟，SLA 计时也使用这个时钟
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.sla = SlaMonitor::new(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// 引擎使用的时钟
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    /// 设置任务数据的存储后端
+    pub fn with_store(mut self, store: Arc<dyn TaskStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// 任务数据的存储，没有单独设置时使用当前数据库连接
+    pub fn store(&self) -> Option<Arc<dyn TaskStore>> {
+        self.store.clone().or_else(|| {
+            self.db().map(|db| Arc::new(SeaOrmStore::new(db)) as Arc<dyn TaskStore>)
+        })
+    }
+
+    /// 当前的数据库连接
+    pub fn db(&self) -> Option<Arc<DatabaseConnection>> {
+        self.db.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// 切换数据库连接，只允许在维护模式下进行
+    pub fn switch_db(&self, db: Arc<DatabaseConnection>) -> Result<(), TaskEngineError> {
+        if !self.is_maintenance() {
+            return Err("Database can only be switched in maintenance mode".into());
+        }
+        *self.db.write().unwrap_or_else(|e| e.into_inner()) = Some(db);
         Ok(())
     }
 
-    /// 更新数据库中的任务状态
-    async fn update_task_state_in_db(&self, task_id: i32, state: TaskState) -> Result<(), Box<dyn std::error::Error>> {
-        // 如果没有数据库连接，直接返回
-        if let Some(ref db) = self.db {
-            // 查找并更新任务状态
-            let task_model = task::Entity::find_by_id(task_id).one(db.as_ref()).await?;
-            
-            if let Some(task_model) = task_model {
-                let mut task_active_model: task::ActiveModel = task_model.into();
-                task_active_model.state = Set(Some(state.as_str().to_string()));
-                task_active_model.update(db.as_ref()).await?;
-            }
+    /// 进入维护模式
+    pub fn enter_maintenance(&self) {
+        self.maintenance.store(true, Ordering::SeqCst);
+        tracing::info!("task engine entered maintenance mode");
+    }
+
+    /// 退出维护模式
+    pub fn exit_maintenance(&self) {
+        self.maintenance.store(false, Ordering::SeqCst);
+        tracing::info!("task engine left maintenance mode");
+    }
+
+    /// 是否处于维护模式
+    pub fn is_maintenance(&self) -> bool {
+        self.maintenance.load(Ordering::SeqCst)
+    }
+
+    fn ensure_not_maintenance(&self) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        if self.is_maintenance() {
+            return Err(TaskEngineError::Unavailable("Task engine is in maintenance mode".to_string()));
         }
         Ok(())
     }
 
-    /// 检查状态转换是否合法
-    fn is_valid_state_transition(current_state: &TaskState, new_state: &TaskState) -> bool {
-        match current_state {
-            // Stopped状态不能转换为Finish或Cancel状态
-            TaskState::Stopped => {
-                match new_state {
-                    TaskState::Finished | TaskState::Cancelled => false,
-                    _ => true,
+    /// 切换为只读观察模式，用于报表、UI 等副本
+    pub fn as_observer(mut self) -> Self {
+        self.observer = true;
+        self
+    }
+
+    /// 是否处于只读观察模式
+    pub fn is_observer(&self) -> bool {
+        self.observer
+    }
+
+    fn ensure_writable(&self) -> Result<(), TaskEngineError> {
+        if self.observer {
+            return Err(TaskEngineError::Unavailable("Task engine is a read-only observer".to_string()));
+        }
+        if self.is_standby() {
+            return Err(TaskEngineError::Unavailable("Task engine is a standby instance".to_string()));
+        }
+        Ok(())
+    }
+
+    /// 以备用节点启动，需要选举成为主节点后才能执行任务
+    pub fn as_standby(self) -> Self {
+        self.standby.store(true, Ordering::SeqCst);
+        self
+    }
+
+    /// 是否为备用节点
+    pub fn is_standby(&self) -> bool {
+        self.standby.load(Ordering::SeqCst)
+    }
+
+    /// 提升为主节点，并从数据库恢复未结束的任务
+    pub async fn promote(&self) -> Result<usize, TaskEngineError> {
+        self.standby.store(false, Ordering::SeqCst);
+        tracing::info!("task engine promoted to leader");
+        self.recover_from_db().await
+    }
+
+    /// 降级为备用节点，停止推进任务
+    pub fn demote(&self) {
+        self.standby.store(true, Ordering::SeqCst);
+        tracing::warn!("task engine demoted to standby");
+    }
+
+    /// 从存储加载未结束且不在内存中的任务，返回恢复的任务数
+    pub async fn recover_from_db(&self) -> Result<usize, TaskEngineError> {
+        let Some(store) = self.store() else {
+            return Ok(0);
+        };
+        self.recover_tasks(store.list_tasks().await?).await
+    }
+
+    /// 为存储中读取的任务创建上下文，跳过已结束和内存中已有的任务，返回恢复的任务数量
+    pub async fn recover_tasks(&self, rows: Vec<task::Model>) -> Result<usize, TaskEngineError> {
+        let rows: Vec<(task::Model, TaskState)> = rows
+            .into_iter()
+            .filter_map(|row| {
+                let state = row.state.as_deref().and_then(TaskState::parse)?;
+                (!matches!(state, TaskState::Finished | TaskState::Cancelled)).then_some((row, state))
+            })
+            .collect();
+
+        // 恢复的任务继续使用开始时的工作流版本
+        let mut pinned: HashMap<i32, WorkflowSnapshot> = HashMap::new();
+        if let Some(db) = self.db() {
+            for (row, _) in &rows {
+                if let (Some(wid), Some(version)) = (row.wid, row.wversion) {
+                    match versioning::load_version(db.as_ref(), &wid.to_string(), version).await {
+                        Ok(snapshot) => {
+                            pinned.insert(row.id, snapshot);
+                        }
+                        Err(e) => tracing::warn!("task {} recovered without its workflow version: {}", row.id, e),
+                    }
                 }
-            },
-            _ => true, // 其他状态转换都是允许的
+            }
         }
+
+        let mut recovered = 0;
+        for (row, state) in rows {
+            if self.tasks.contains(row.id) {
+                continue;
+            }
+            let snapshot = pinned.remove(&row.id);
+            let task_id = row.id;
+            // 用量、费用和进度从存储中的记录恢复，重启后预算检查和模板中的步数不会从零开始
+            let (usage, cost, step) = match self.stored_progress(task_id).await {
+                Ok(progress) => progress,
+                Err(e) => {
+                    tracing::warn!("task {} recovered without its usage and progress: {}", task_id, e);
+                    (Usage::new(), 0.0, 0)
+                }
+            };
+            let context = TaskContext {
+                state,
+                task: Some(row),
+                workflow: snapshot.as_ref().map(|s| s.workflow.clone()),
+                execution_history: vec!["Task recovered".to_string()],
+                usage,
+                approved_jobs: Vec::new(),
+                blocked: None,
+                skipped_blocks: Vec::new(),
+                last_output: None,
+                step,
+                variables: HashMap::new(),
+                cost,
+                replay: None,
+                pinned_jobs: snapshot.map(|s| s.jobs.into_iter().map(|job| (job.id, job)).collect()).unwrap_or_default(),
+                parent: None,
+                children: HashMap::new(),
+                preempted_by: None,
+                steps: Vec::new(),
+                outputs: HashMap::new(),
+            };
+            if self.tasks.insert_new(task_id, context) {
+                recovered += 1;
+            }
+        }
+        Ok(recovered)
+    }
+
+    /// 按存储中的用量事件累计token和费用，按成功的job执行记录计算已完成的步数
+    async fn stored_progress(&self, task_id: i32) -> Result<(Usage, f64, usize), TaskEngineError> {
+        let mut usage = Usage::new();
+        let mut cost = 0.0;
+        let Some(store) = self.store() else {
+            return Ok((usage, cost, 0));
+        };
+        for event in store.load_events(task_id).await? {
+            if let TaskEvent::UsageRecorded { usage: recorded, cost: recorded_cost, .. } = event {
+                usage += recorded;
+                cost += recorded_cost;
+            }
+        }
+        let step = store
+            .load_job_runs(task_id)
+            .await?
+            .iter()
+            .filter(|run| attempts::RunStatus::parse(&run.status) == Some(attempts::RunStatus::Success))
+            .count();
+        Ok((usage, cost, step))
+    }
+
+    /// 设置任务审批策略
+    pub fn with_policy(mut self, policy: PolicyEngine) -> Self {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// 配置的审批策略
+    pub fn policy(&self) -> Option<Arc<PolicyEngine>> {
+        self.policy.clone()
+    }
+
+    /// 设置显存感知调度
+    pub fn with_vram_scheduler(mut self, scheduler: Arc<VramScheduler>) -> Self {
+        self.vram = Some(scheduler);
+        self
+    }
+
+    /// 设置模型价格表
+    pub fn with_pricing(mut self, pricing: PricingTable) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    /// 订阅任务事件
+    pub fn subscribe(&self) -> broadcast::Receiver<TaskEvent> {
+        self.events.subscribe()
+    }
+
+    /// 广播事件，没有订阅方时忽略
+    fn emit(&self, event: TaskEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// 保存事件到存储并广播，任务的每个事件都算作一次进展
+    async fn publish(&self, task_id: i32, event: TaskEvent) -> Result<(), TaskEngineError> {
+        if !matches!(event, TaskEvent::Stuck { .. }) {
+            self.tasks.touch(task_id, self.clock.now_millis());
+        }
+        let event = event.redacted();
+        if let Some(store) = self.store() {
+            store.append_event(task_id, event.clone()).await?;
+        }
+        self.emit(event);
+        Ok(())
+    }
+
+    /// 读取存储中任务的事件
+    pub async fn get_events(&self, task_id: i32) -> Result<Vec<TaskEvent>, TaskEngineError> {
+        let store = self.store().ok_or_else(|| TaskEngineError::Unavailable("Task engine has no store".to_string()))?;
+        Ok(store.load_events(task_id).await?)
+    }
+
+    /// 设置job生成参数的范围
+    pub fn with_param_bounds(mut self, bounds: ParamBounds) -> Self {
+        self.param_bounds = bounds;
+        self
+    }
+
+    /// 解析job的动作，生成参数裁剪到允许的范围内
+    pub fn job_action(&self, job: &job::Model) -> JobAction {
+        JobAction::parse(job.action.as_deref().unwrap_or_default()).bounded(&self.param_bounds)
+    }
+
+    /// job的agent配置的工具，桩agent声明的工具优先
+    fn job_tools(&self, job: &job::Model) -> Vec<String> {
+        let Some(code) = job.code.as_deref() else {
+            return Vec::new();
+        };
+        if let Some(tools) = self.stubs.as_ref().and_then(|stubs| stubs.tools(code)) {
+            return tools;
+        }
+        AgentManager::global().map(|manager| manager.tools(code)).unwrap_or_default()
+    }
+
+    /// 这一步prompt的token数，按job的agent模型的分词器计算，用于预估这一步的费用
+    fn prompt_tokens(
+        &self,
+        task_id: i32,
+        job: &job::Model,
+        action: &JobAction,
+        context: &TaskContext,
+    ) -> u64 {
+        let model = match (&job.code, AgentManager::global()) {
+            (Some(code), Some(manager)) => manager.model(code).unwrap_or_default(),
+            _ => String::new(),
+        };
+        let prompt = render_prompt(&action.prompt, &PromptContext::from_task(task_id, context))
+            .unwrap_or_else(|_| action.prompt.clone());
+        budget::prompt_tokens(&model, &prompt)
+    }
+
+    /// 使用审批策略进行判断，未配置策略时默认通过
+    pub fn check_policy(&self, subject: &PolicySubject) -> PolicyDecision {
+        match self.policy {
+            Some(ref policy) => policy.evaluate(subject),
+            None => PolicyDecision::Approve,
+        }
+    }
+
+    /// 初始化任务引擎，设置任务ID和输入
+    pub async fn init(&mut self, task_id: i32, input: String) -> Result<(), TaskEngineError> {
+        let task_context = self.new_context(task_id, input, None).await?;
+        self.tasks.insert(task_id, task_context);
+        Ok(())
+    }
+
+    /// 创建任务并分配任务ID，id不与内存和存储中已有的任务重复
+    pub async fn create_task(&self, input: String) -> Result<i32, TaskEngineError> {
+        self.create_owned_task(input, None).await
+    }
+
+    /// 创建属于指定用户的任务，所属用户和任务在同一次写入中保存，见 [TaskEngine::create_task_as]
+    pub(crate) async fn create_owned_task(&self, input: String, owner: Option<String>) -> Result<i32, TaskEngineError> {
+        let mut task_context = self.build_context(0, input, owner)?;
+        let task_id = self.insert_new_task(&mut task_context).await?;
+        self.tasks.insert(task_id, task_context);
+        Ok(task_id)
+    }
+
+    /// 为新任务分配一个内存和存储中都没有使用过的id并写入存储，返回分配的id。
+    /// 多个进程共用存储时，存储中最大id之后的id可能刚被其他进程占用，写入冲突时换下一个id
+    pub(crate) async fn insert_new_task(&self, context: &mut TaskContext) -> Result<i32, TaskEngineError> {
+        let task = context.task.as_mut().ok_or_else(|| TaskEngineError::Other("New task has no model".to_string()))?;
+        let Some(store) = self.store() else {
+            task.id = self.tasks.allocate_id(1);
+            return Ok(task.id);
+        };
+        loop {
+            task.id = self.tasks.allocate_id(store.max_task_id().await? + 1);
+            if store.insert_task(task.clone()).await? {
+                return Ok(task.id);
+            }
+        }
+    }
+
+    /// 经过审批策略创建新任务的上下文，存储中还没有这个任务时写入
+    async fn new_context(&self, task_id: i32, input: String, owner: Option<String>) -> Result<TaskContext, TaskEngineError> {
+        let mut context = self.build_context(task_id, input, owner)?;
+        // 存储中还没有这个任务时写入，已有的任务保留原来的记录，之后基于存储中的版本写入
+        if let (Some(store), Some(model)) = (self.store(), context.task.as_mut()) {
+            model.version = match store.load_task(task_id).await? {
+                Some(stored) => stored.version,
+                None => store.save_task(model.clone()).await?,
+            };
+        }
+        Ok(context)
+    }
+
+    /// 经过审批策略创建新任务的上下文，不写入存储
+    pub(crate) fn build_context(&self, task_id: i32, input: String, owner: Option<String>) -> Result<TaskContext, TaskEngineError> {
+        self.ensure_not_maintenance()?;
+
+        // 创建任务前先经过审批策略
+        let (state, history) = match self.check_policy(&PolicySubject::for_task(&input)) {
+            PolicyDecision::Approve => (TaskState::Waiting, Vec::new()),
+            PolicyDecision::RequireApproval(reason) => {
+                (TaskState::Pending, vec![format!("Task awaiting approval: {}", reason)])
+            }
+            PolicyDecision::Reject(reason) => {
+                return Err(TaskEngineError::Rejected(format!("Task rejected by policy: {}", reason)));
+            }
+        };
+
+        let model = task::Model {
+            id: task_id,
+            input: Some(input),
+            output: None,
+            state: Some(state.as_str().to_string()),
+            wid: None,
+            planid: None,
+            wversion: None,
+            priority: 0,
+            owner_id: owner,
+            tags: None,
+            created_at: self.clock.now_millis(),
+            report: None,
+            version: 0,
+            worker_id: None,
+            heartbeat_at: None,
+        };
+
+        Ok(TaskContext {
+            task: Some(model),
+            state,
+            workflow: None,
+            execution_history: history,
+            usage: Usage::new(),
+            approved_jobs: Vec::new(),
+            blocked: None,
+            skipped_blocks: Vec::new(),
+            last_output: None,
+            step: 0,
+            variables: HashMap::new(),
+            cost: 0.0,
+            replay: None,
+            pinned_jobs: HashMap::new(),
+            parent: None,
+            children: HashMap::new(),
+            preempted_by: None,
+            steps: Vec::new(),
+            outputs: HashMap::new(),
+        })
     }
 
     /// 启动指定任务的执行
-    pub async fn start(&self, task_id: i32) -> Result<(), Box<dyn std::error::Error>> {
-        let mut tasks = self.tasks.lock().await;
-        if let Some(context) = tasks.get_mut(&task_id) {
+    pub async fn start(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_not_maintenance()?;
+        if let Some(reason) = self.quota_violation(task_id, true).await {
+            return Err(TaskEngineError::Rejected(format!("Quota exceeded: {}", reason)));
+        }
+        if let Some(mut context) = self.tasks.lock(task_id).await {
             // 检查状态转换是否合法
-            if !Self::is_valid_state_transition(&context.state, &TaskState::Running) {
-                return Err(format!("Cannot transition from {:?} to Running state", context.state).into());
-            }
+            let transition = Transition::check(task_id, &context.state, TaskState::Running, Actor::User, "Task started")?;
             
             context.state = TaskState::Running;
-            context.execution_history.push("Task started".to_string());
+            context.execution_history.push(transition.reason.clone());
             
             // 更新数据库中的状态
-            drop(tasks); // 释放锁以避免死锁
-            self.update_task_state_in_db(task_id, TaskState::Running).await?;
+            drop(context); // 释放锁以避免死锁
+            self.save_transition(transition).await?;
             Ok(())
         } else {
-            Err("Task not found".into())
+            Err(TaskEngineError::task_not_found(task_id))
         }
     }
 
     /// 暂停指定任务的执行
-    pub async fn pause(&self, task_id: i32) -> Result<(), Box<dyn std::error::Error>> {
-        let mut tasks = self.tasks.lock().await;
-        if let Some(context) = tasks.get_mut(&task_id) {
+    pub async fn pause(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        if let Some(mut context) = self.tasks.lock(task_id).await {
             // 检查状态转换是否合法
-            if !Self::is_valid_state_transition(&context.state, &TaskState::Pending) {
-                return Err(format!("Cannot transition from {:?} to Pending state", context.state).into());
-            }
+            let transition = Transition::check(task_id, &context.state, TaskState::Pending, Actor::User, "Task paused")?;
             
             context.state = TaskState::Pending;
-            context.execution_history.push("Task paused".to_string());
+            context.execution_history.push(transition.reason.clone());
             
             // 更新数据库中的状态
-            drop(tasks); // 释放锁以避免死锁
-            self.update_task_state_in_db(task_id, TaskState::Pending).await?;
+            drop(context); // 释放锁以避免死锁
+            self.save_transition(transition).await?;
             Ok(())
         } else {
-            Err("Task not found".into())
+            Err(TaskEngineError::task_not_found(task_id))
         }
     }
 
     /// 恢复指定任务的执行
-    pub async fn resume(&self, task_id: i32) -> Result<(), Box<dyn std::error::Error>> {
-        let mut tasks = self.tasks.lock().await;
-        if let Some(context) = tasks.get_mut(&task_id) {
+    pub async fn resume(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_not_maintenance()?;
+        if let Some(reason) = self.quota_violation(task_id, true).await {
+            return Err(TaskEngineError::Rejected(format!("Quota exceeded: {}", reason)));
+        }
+        if let Some(mut context) = self.tasks.lock(task_id).await {
             // 检查状态转换是否合法
-            if !Self::is_valid_state_transition(&context.state, &TaskState::Running) {
-                return Err(format!("Cannot transition from {:?} to Running state", context.state).into());
-            }
+            let transition = Transition::check(task_id, &context.state, TaskState::Running, Actor::User, "Task resumed")?;
             
             context.state = TaskState::Running;
-            context.execution_history.push("Task resumed".to_string());
+            context.preempted_by = None;
+            context.execution_history.push(transition.reason.clone());
             
             // 更新数据库中的状态
-            drop(tasks); // 释放锁以避免死锁
-            self.update_task_state_in_db(task_id, TaskState::Running).await?;
+            drop(context); // 释放锁以避免死锁
+            self.save_transition(transition).await?;
             Ok(())
         } else {
-            Err("Task not found".into())
+            Err(TaskEngineError::task_not_found(task_id))
         }
     }
 
     /// 取消指定任务的执行
-    pub async fn cancel(&self, task_id: i32) -> Result<(), Box<dyn std::error::Error>> {
-        let mut tasks = self.tasks.lock().await;
-        if let Some(context) = tasks.get_mut(&task_id) {
+    pub async fn cancel(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        if let Some(mut context) = self.tasks.lock(task_id).await {
             // 检查状态转换是否合法
-            if !Self::is_valid_state_transition(&context.state, &TaskState::Cancelled) {
-                return Err(format!("Cannot transition from {:?} to Cancelled state", context.state).into());
-            }
+            let transition = Transition::check(task_id, &context.state, TaskState::Cancelled, Actor::User, "Task cancelled")?;
             
             context.state = TaskState::Cancelled;
-            context.execution_history.push("Task cancelled".to_string());
+            context.execution_history.push(transition.reason.clone());
+            self.sla.untrack(task_id);
             
             // 更新数据库中的状态
-            drop(tasks); // 释放锁以避免死锁
-            self.update_task_state_in_db(task_id, TaskState::Cancelled).await?;
+            drop(context); // 释放锁以避免死锁
+            self.save_transition(transition).await?;
+            self.cancel_children(task_id).await?;
+            self.release_parent(task_id).await;
             Ok(())
         } else {
-            Err("Task not found".into())
+            Err(TaskEngineError::task_not_found(task_id))
         }
     }
 
-    /// 完成指定任务的执行
-    pub async fn finish(&self, task_id: i32) -> Result<(), Box<dyn std::error::Error>> {
-        let mut tasks = self.tasks.lock().await;
-        if let Some(context) = tasks.get_mut(&task_id) {
-            // 检查状态转换是否合法
-            if !Self::is_valid_state_transition(&context.state, &TaskState::Finished) {
-                return Err(format!("Cannot transition from {:?} to Finished state", context.state).into());
+    /// 关联任务的工作流，工作流声明了输入schema时校验任务输入。
+    /// 工作流保存在数据库中时，任务固定使用工作流当前的版本，见 [versioning]
+    pub async fn attach_workflow(&self, task_id: i32, workflow: workflow::Model) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        let snapshot = match self.db() {
+            Some(db) => match versioning::ensure_version(db.as_ref(), &workflow.id).await {
+                Ok(snapshot) => Some(snapshot),
+                Err(VersionError::WorkflowNotFound(_)) => None,
+                Err(e) => return Err(e.into()),
+            },
+            None => None,
+        };
+        let workflow = snapshot.as_ref().map(|s| s.workflow.clone()).unwrap_or(workflow);
+        let schemas = TaskSchemas::from_workflow(&workflow)?;
+        if let Some(mut context) = self.tasks.lock(task_id).await {
+            let input = context.task.as_ref().and_then(|t| t.input.as_deref()).unwrap_or_default();
+            schemas.validate_input(input)?;
+            if let Some(sla) = workflow.sla.as_deref() {
+                self.sla.track(task_id, SlaPolicy::parse(sla)?);
+            }
+            let version = snapshot.as_ref().map(WorkflowSnapshot::version);
+            if let Some(snapshot) = snapshot {
+                context.pinned_jobs = snapshot.jobs.into_iter().map(|job| (job.id, job)).collect();
+                context.execution_history.push(format!("Workflow {} pinned at version {}", workflow.id, snapshot.workflow.version));
+            }
+            let workflow_plan = workflow.clone();
+            context.workflow = Some(workflow);
+            drop(context);
+            if version.is_some() {
+                self.persist_task(task_id, |task| task.wversion = version).await?;
             }
+            self.fill_plan(task_id, &workflow_plan).await?;
+            Ok(())
+        } else {
+            Err(TaskEngineError::task_not_found(task_id))
+        }
+    }
+
+    /// 任务固定的工作流版本中的job，没有固定版本时为空
+    pub async fn pinned_jobs(&self, task_id: i32) -> Result<Vec<job::Model>, TaskEngineError> {
+        let context = self.tasks.lock(task_id).await.ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+        let mut jobs: Vec<job::Model> = context.pinned_jobs.values().cloned().collect();
+        jobs.sort_by_key(|job| job.id);
+        Ok(jobs)
+    }
+
+    /// 完成指定任务的执行，工作流声明了输出schema时必须通过 [TaskEngine::finish_with_output] 完成
+    pub async fn finish(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.finish_inner(task_id, None).await
+    }
+
+    /// 完成任务并记录结构化输出，输出不符合工作流的输出schema时拒绝完成
+    pub async fn finish_with_output(&self, task_id: i32, output: serde_json::Value) -> Result<(), TaskEngineError> {
+        self.finish_inner(task_id, Some(output)).await
+    }
+
+    async fn finish_inner(&self, task_id: i32, output: Option<serde_json::Value>) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        if let Some(mut context) = self.tasks.lock(task_id).await {
+            // 检查状态转换是否合法
+            let transition = Transition::check(task_id, &context.state, TaskState::Finished, Actor::User, "Task finished")?;
+
+            let schemas = match &context.workflow {
+                Some(workflow) => TaskSchemas::from_workflow(workflow)?,
+                None => TaskSchemas::default(),
+            };
+            let output = match output {
+                Some(output) => {
+                    schemas.validate_output(&output)?;
+                    Some(output.to_string())
+                }
+                None if schemas.has_output() => return Err(SchemaError::MissingOutput.into()),
+                None => None,
+            };
             
             context.state = TaskState::Finished;
-            context.execution_history.push("Task finished".to_string());
+            context.execution_history.push(transition.reason.clone());
+            self.sla.stop(task_id, SlaKind::Finish);
             
             // 更新数据库中的状态
-            drop(tasks); // 释放锁以避免死锁
-            self.update_task_state_in_db(task_id, TaskState::Finished).await?;
+            drop(context); // 释放锁以避免死锁
+            if let Some(output) = output {
+                self.persist_task(task_id, |task| task.output = Some(output)).await?;
+            }
+            self.save_transition(transition).await?;
+            self.save_report(task_id).await?;
+            self.release_parent(task_id).await;
             Ok(())
         } else {
-            Err("Task not found".into())
+            Err(TaskEngineError::task_not_found(task_id))
         }
     }
 
+    /// 修改任务记录并写入存储。内存中有这个任务时修改内存中的记录，用上一次写入得到的版本写入并记住新的版本，
+    /// 其他实例在这之后修改过存储中的任务时返回冲突；内存中没有这个任务时读取存储中的记录修改。
+    /// 调用时不能持有任务的锁
+    pub(crate) async fn persist_task(&self, task_id: i32, update: impl FnOnce(&mut task::Model)) -> Result<(), TaskEngineError> {
+        let store = self.store();
+        let _write = self.tasks.lock_write(task_id).await;
+        let mut context = self.tasks.lock(task_id).await;
+        let Some(task) = context.as_mut().and_then(|c| c.task.as_mut()) else {
+            drop(context);
+            if let Some(store) = store {
+                if let Some(mut task) = store.load_task(task_id).await? {
+                    update(&mut task);
+                    store.save_task(task).await?;
+                }
+            }
+            return Ok(());
+        };
+        update(task);
+        let Some(store) = store else {
+            return Ok(());
+        };
+        let model = task.clone();
+        drop(context);
+        let version = store.save_task(model).await?;
+        if let Some(task) = self.tasks.lock(task_id).await.as_mut().and_then(|c| c.task.as_mut()) {
+            task.version = version;
+        }
+        Ok(())
+    }
+
     /// 停止指定任务的执行
-    pub async fn stop(&self, task_id: i32) -> Result<(), Box<dyn std::error::Error>> {
-        let mut tasks = self.tasks.lock().await;
-        if let Some(context) = tasks.get_mut(&task_id) {
+    pub async fn stop(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        if let Some(mut context) = self.tasks.lock(task_id).await {
             // 检查状态转换是否合法
-            if !Self::is_valid_state_transition(&context.state, &TaskState::Stopped) {
-                return Err(format!("Cannot transition from {:?} to Stopped state", context.state).into());
-            }
+            let transition = Transition::check(task_id, &context.state, TaskState::Stopped, Actor::User, "Task stopped")?;
             
             context.state = TaskState::Stopped;
-            context.execution_history.push("Task stopped".to_string());
+            context.execution_history.push(transition.reason.clone());
             
             // 更新数据库中的状态
-            drop(tasks); // 释放锁以避免死锁
-            self.update_task_state_in_db(task_id, TaskState::Stopped).await?;
+            drop(context); // 释放锁以避免死锁
+            self.save_transition(transition).await?;
             Ok(())
         } else {
-            Err("Task not found".into())
+            Err(TaskEngineError::task_not_found(task_id))
         }
     }
 
     /// 获取指定任务的当前状态
-    pub async fn get_state(&self, task_id: i32) -> Result<TaskState, Box<dyn std::error::Error>> {
-        let tasks = self.tasks.lock().await;
-        if let Some(context) = tasks.get(&task_id) {
+    pub async fn get_state(&self, task_id: i32) -> Result<TaskState, TaskEngineError> {
+        if let Some(context) = self.tasks.lock(task_id).await {
             Ok(context.state.clone())
         } else {
-            Err("Task not found".into())
+            Err(TaskEngineError::task_not_found(task_id))
         }
     }
 
     /// 获取所有任务的ID列表
     pub async fn list_tasks(&self) -> Vec<i32> {
-        let tasks = self.tasks.lock().await;
-        tasks.keys().cloned().collect()
+        self.tasks.ids()
     }
 
     /// 执行任务中的作业
-    pub async fn execute_job(&self, task_id: i32, job: job::Model) -> Result<String, Box<dyn std::error::Error>> {
-        let mut tasks = self.tasks.lock().await;
-        if let Some(context) = tasks.get_mut(&task_id) {
-            let record = format!("Executing job: {:?}", job);
+    pub async fn execute_job(&self, task_id: i32, job: job::Model) -> Result<String, TaskEngineError> {
+        self.ensure_not_maintenance()?;
+        // 同一个任务的job依次执行；任务锁只在读写内存中的上下文时持有，读写存储时释放
+        let _running = self.tasks.lock_job(task_id).await.ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+        let started_at = self.clock.now_millis();
+        // 任务固定了工作流版本时使用该版本的job定义
+        let job = self.tasks.lock(task_id).await.and_then(|c| c.pinned_jobs.get(&job.id).cloned()).unwrap_or(job);
+        let skipped = self.tasks.lock(task_id).await.map(|c| c.skipped_blocks.clone()).unwrap_or_default();
+        // job 执行期间持有显存额度
+        let _vram = match (&self.vram, &job.code) {
+            (Some(vram), Some(code)) if !skipped.contains(&BlockKind::Vram) => {
+                if let Some(required_mb) = vram.declared(code) {
+                    self.set_blocked(task_id, Some(BlockReason::Vram {
+                        job_id: job.id,
+                        code: code.clone(),
+                        required_mb,
+                        used_mb: vram.used_mb(),
+                        budget_mb: vram.budget_mb(),
+                    })).await;
+                }
+                let permit = vram.acquire(code).await;
+                self.set_blocked(task_id, None).await;
+                permit
+            }
+            _ => None,
+        };
+        // agent有并发上限时排队等待额度，见 [AgentManager::acquire]
+        let _slot = match (&job.code, AgentManager::global()) {
+            (Some(code), Some(manager)) => manager.acquire(code).await,
+            _ => None,
+        };
+        if let Some(reason) = self.quota_violation(task_id, false).await {
+            return Err(TaskEngineError::Rejected(format!("Quota exceeded: {}", reason)));
+        }
+        // 子工作流job已经派生的子任务的状态和输出，先于本任务加锁读取
+        let child = match self.tasks.lock(task_id).await.and_then(|c| c.children.get(&job.id).copied()) {
+            Some(id) => self.tasks.lock(id).await.map(|c| (id, c.state.clone(), subworkflow::child_output(&c))),
+            None => None,
+        };
+        // 汇总job收集同一批次中其他任务的输出，同样先于本任务加锁读取
+        let aggregate = match job.r#type.as_deref() {
+            Some(AGGREGATE_JOB_TYPE) => Some(AggregateSpec::parse(job.action.as_deref().unwrap_or_default())?),
+            _ => None,
+        };
+        let batch_branches = match &aggregate {
+            Some(spec) if spec.batch => self.batch_outputs(task_id).await?,
+            _ => Vec::new(),
+        };
+        let mut spawned = None;
+        if let Some(mut context) = self.tasks.lock(task_id).await {
+            let mut action = self.job_action(&job);
+            let prompt_tokens = self.prompt_tokens(task_id, &job, &action, &context);
+            // 危险job执行前经过审批策略，人工审批过的job不再重复判断
+            if !context.approved_jobs.contains(&job.id) && !skipped.contains(&BlockKind::Approval) {
+                let subject = PolicySubject {
+                    workflow_id: context.workflow.as_ref().map(|w| w.id.clone()),
+                    input: job.action.clone().unwrap_or_default(),
+                    estimated_cost: budget::projected_step_cost(&context, prompt_tokens),
+                    tools: self.job_tools(&job),
+                    job_type: job.r#type.clone(),
+                };
+                match self.check_policy(&subject) {
+                    PolicyDecision::Approve => {}
+                    PolicyDecision::RequireApproval(reason) => {
+                        let transition = Transition::check(task_id, &context.state, TaskState::Pending, Actor::Engine, format!("Job {} awaiting approval: {}", job.id, reason))?;
+                        context.state = TaskState::Pending;
+                        context.blocked = Some(BlockReason::AwaitingApproval { job_id: job.id, reason: reason.clone() });
+                        self.sla.start(task_id, SlaKind::Approval);
+                        context.execution_history.push(format!("Job {} awaiting approval: {}", job.id, reason));
+                        drop(context);
+                        self.save_transition(transition).await?;
+                        return Err(TaskEngineError::Rejected(format!("Job {} requires approval: {}", job.id, reason)));
+                    }
+                    PolicyDecision::Reject(reason) => {
+                        context.execution_history.push(format!("Job {} rejected: {}", job.id, reason));
+                        return Err(TaskEngineError::Rejected(format!("Job {} rejected by policy: {}", job.id, reason)));
+                    }
+                }
+            }
+
+            #[cfg(any(test, feature = "chaos"))]
+            if crate::chaos::should_kill_job() {
+                context.execution_history.push(format!("Job {} killed by fault injection", job.id));
+                return Err(format!("Job {} killed by fault injection", job.id).into());
+            }
+
+            // 预估费用超过job的预算检查点时暂停任务，等待人工确认
+            if !context.approved_jobs.contains(&job.id) && !skipped.contains(&BlockKind::Approval) {
+                if let Some(report) = budget::check_budget(
+                    job.id,
+                    action.requires_confirmation_if_cost_above,
+                    &context,
+                    prompt_tokens,
+                ) {
+                    let reason = format!("Job {} awaiting budget confirmation: {}", job.id, report.describe());
+                    let transition = Transition::check(task_id, &context.state, TaskState::Pending, Actor::Engine, reason.clone())?;
+                    context.state = TaskState::Pending;
+                    context.blocked = Some(BlockReason::AwaitingBudget { report });
+                    self.sla.start(task_id, SlaKind::Approval);
+                    context.execution_history.push(reason.clone());
+                    drop(context);
+                    self.save_transition(transition).await?;
+                    return Err(TaskEngineError::Rejected(reason));
+                }
+            }
+            // 抽取job的 action 是流水线声明，只渲染其中的文档
+            if job.r#type.as_deref() == Some(EXTRACTION_JOB_TYPE) {
+                let spec = ExtractionSpec::parse(job.action.as_deref().unwrap_or_default())?;
+                context.execution_history.push(format!("Extraction job {} with stages {:?}", job.id, spec.stages.iter().map(|s| s.name.as_str()).collect::<Vec<_>>()));
+                action = JobAction { prompt: spec.document, ..Default::default() };
+            }
+            // 子工作流job只渲染子任务的输入
+            if job.r#type.as_deref() == Some(SUBWORKFLOW_JOB_TYPE) {
+                let spec = SubWorkflowSpec::parse(job.action.as_deref().unwrap_or_default())?;
+                action = JobAction { prompt: spec.input, ..Default::default() };
+            }
+            // 汇总job渲染合并的要求，分支的输出不经过模板渲染
+            if let Some(spec) = &aggregate {
+                action = JobAction { prompt: spec.prompt.clone(), ..Default::default() };
+            }
+            action.prompt = render_prompt(&action.prompt, &PromptContext::from_task(task_id, &context))?;
+            if let Some(spec) = &aggregate {
+                let mut branches = spec
+                    .job_outputs(&context.outputs)
+                    .map_err(|missing| format!("Job {} waiting for upstream jobs {:?}", job.id, missing))?;
+                branches.extend(batch_branches);
+                action.prompt = aggregate::compose(&action.prompt, &branches);
+            }
+            let record = format!("Executing job: {:?} with params {:?}", job, action.params);
             context.execution_history.push(record);
+            let replaying = context.replay.is_some();
+            let owner = owner_of(&context).map(str::to_string);
+            drop(context);
+
+            // 每次执行记为一次尝试，见 [attempts]
+            let mut run = self.begin_run(task_id, job.id, job.code.clone(), Some(action.prompt.clone())).await?;
+            // 需要派生的子任务同样在不持有任务锁时分配id并写入存储
+            let new_child = match (&child, job.r#type.as_deref()) {
+                (None, Some(SUBWORKFLOW_JOB_TYPE)) if !replaying => match SubWorkflowSpec::parse(job.action.as_deref().unwrap_or_default()) {
+                    Ok(spec) => self.child_context(task_id, &spec, action.prompt.clone(), owner).await.map(Some),
+                    Err(e) => Err(e.into()),
+                },
+                _ => Ok(None),
+            };
+            let Some(mut context) = self.tasks.lock(task_id).await else {
+                return Err(TaskEngineError::task_not_found(task_id));
+            };
+            let mut completion = None;
+            let outcome = async {
+                // 重放任务使用记录的输出，否则模拟作业执行
+                let result = match context.replay.as_mut() {
+                    Some(replay) => replay.next(job.id).ok_or_else(|| format!("Job {} has no recorded output to replay", job.id))?,
+                    None if job.r#type.as_deref() == Some(SUBWORKFLOW_JOB_TYPE) => {
+                        let spec = SubWorkflowSpec::parse(job.action.as_deref().unwrap_or_default())?;
+                        match child {
+                            Some((child_id, TaskState::Finished, output)) if spec.mode == SubWorkflowMode::Wait => {
+                                context.execution_history.push(format!("Job {} received output of child task {}", job.id, child_id));
+                                output
+                            }
+                            Some((child_id, TaskState::Cancelled | TaskState::Stopped, _)) if spec.mode == SubWorkflowMode::Wait => {
+                                return Err(TaskEngineError::Cancelled(format!("Child task {} of job {} did not finish", child_id, job.id)));
+                            }
+                            child => {
+                                let child_id = match child {
+                                    Some((child_id, _, _)) => child_id,
+                                    None => {
+                                        let (child_id, child_context) = new_child?.ok_or_else(|| format!("Job {} has no child task", job.id))?;
+                                        context.children.insert(job.id, child_id);
+                                        context.execution_history.push(format!("Job {} started child task {} for workflow {}", job.id, child_id, spec.workflow_id));
+                                        spawned = Some(child_context);
+                                        child_id
+                                    }
+                                };
+                                match spec.mode {
+                                    SubWorkflowMode::Wait => {
+                                        context.blocked = Some(BlockReason::ChildTask { job_id: job.id, child_id });
+                                        if let Some(child_context) = spawned.take() {
+                                            self.tasks.insert(child_id, child_context);
+                                        }
+                                        return Err(format!("Job {} waiting for child task {}", job.id, child_id).into());
+                                    }
+                                    SubWorkflowMode::Detach => format!("Job {} started child task {}", job.id, child_id),
+                                }
+                            }
+                        }
+                    }
+                    None => match (&self.stubs, &job.code) {
+                        (Some(stubs), Some(_)) => self.call_stub(stubs, task_id, &job, &action.prompt, run.attempt)?,
+                        _ => format!("Job {} executed with action {:?}", job.id, job.action),
+                    },
+                };
+
+                // 输出未通过后处理链时记录失败，job不算完成
+                let result = match action.guardrails.check(result) {
+                    Ok(result) => result,
+                    Err(reason) => {
+                        let failure = format!("Job {} output rejected: {}", job.id, reason);
+                        completion = Some(JobCompletion { job_id: job.id, output: failure.clone(), rejected: true });
+                        context.execution_history.push(failure.clone());
+                        return Err(TaskEngineError::Rejected(failure));
+                    }
+                };
             
-            // 模拟作业执行
-            let result = format!("Job {} executed with action {:?}", job.id, job.action);
-            
-            // 记录工具调用日志
-            self.log_tool_call(context, job.id, result.clone()).await?;
-            
-            Ok(result)
+                // 输出、计划步骤和执行记录在 complete_job 中一起写入
+                completion = Some(JobCompletion { job_id: job.id, output: result.clone(), rejected: false });
+                Ok::<_, TaskEngineError>(result)
+            }
+            .await;
+            let status = match &outcome {
+                Ok(output) => {
+                    run.output = output.clone();
+                    attempts::RunStatus::Success
+                }
+                Err(e) => {
+                    run.output = e.to_string();
+                    match e {
+                        TaskEngineError::Rejected(_) => attempts::RunStatus::Rejected,
+                        _ => attempts::RunStatus::Failure,
+                    }
+                }
+            };
+            drop(context);
+            let run = self.complete_job(task_id, run, status, completion).await?;
+            let Some(mut context) = self.tasks.lock(task_id).await else {
+                return Err(TaskEngineError::task_not_found(task_id));
+            };
+            // 输出写入存储之后才推进内存中的进度
+            if let Ok(result) = &outcome {
+                context.last_output = Some((job.id, result.clone()));
+                context.outputs.insert(job.id, result.clone());
+                context.step += 1;
+                self.tasks.touch(task_id, self.clock.now_millis());
+                context.steps.push(StepRecord {
+                    job_id: job.id,
+                    agent: job.code.clone(),
+                    job_type: job.r#type.clone(),
+                    started_at,
+                    duration_ms: self.clock.now_millis() - started_at,
+                    attempt: run.attempt,
+                });
+                #[cfg(feature = "otel-metrics")]
+                if let Some(step) = context.steps.last() {
+                    metrics::EngineMetrics::global().record_job(step);
+                }
+                if let Some(child_context) = spawned {
+                    let child_id = child_context.task.as_ref().map(|t| t.id).unwrap_or_default();
+                    self.tasks.insert(child_id, child_context);
+                }
+            }
+            outcome
         } else {
-            Err("Task not found".into())
+            Err(TaskEngineError::task_not_found(task_id))
         }
     }
 
-    /// 记录工具调用日志
-    async fn log_tool_call(&self, context: &mut TaskContext, job_id: i32, output: String) -> Result<(), Box<dyn std::error::Error>> {
-        // 在实际实现中，这里应该将日志写入数据库
-        let _log = tool_log::Model {
-            id: 0, // This would be auto-generated in real implementation
-            taskid: context.task.as_ref().map(|t| t.id),
-            planid: None,
-            args: None,
-            output: Some(output),
-        };
-        
-        context.execution_history.push(format!("Tool log recorded for job {}", job_id));
+    /// 设置任务变量，供prompt模板使用
+    pub async fn set_variable(&self, task_id: i32, name: &str, value: impl Into<String>) -> Result<(), TaskEngineError> {
+        let mut context = self.tasks.lock(task_id).await.ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+        context.variables.insert(name.to_string(), value.into());
         Ok(())
     }
 
+    /// 记录阻塞任务的条件
+    async fn set_blocked(&self, task_id: i32, blocked: Option<BlockReason>) {
+        if let Some(mut context) = self.tasks.lock(task_id).await {
+            context.blocked = blocked;
+        }
+    }
+
+    /// 人工审批通过指定job，之后执行该job时跳过审批策略
+    pub async fn approve_job(&self, task_id: i32, job_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        if let Some(mut context) = self.tasks.lock(task_id).await {
+            if !context.approved_jobs.contains(&job_id) {
+                context.approved_jobs.push(job_id);
+            }
+            let blocked_job = match &context.blocked {
+                Some(BlockReason::AwaitingApproval { job_id, .. }) => Some(*job_id),
+                Some(BlockReason::AwaitingBudget { report }) => Some(report.job_id),
+                _ => None,
+            };
+            if blocked_job == Some(job_id) {
+                context.blocked = None;
+            }
+            self.sla.stop(task_id, SlaKind::Approval);
+            context.execution_history.push(format!("Job {} approved", job_id));
+            // 任务因为等待这个job的审批而暂停时恢复执行
+            if blocked_job == Some(job_id) && context.state == TaskState::Pending {
+                let transition = Transition::check(task_id, &context.state, TaskState::Running, Actor::User, format!("Job {} approved", job_id))?;
+                context.state = TaskState::Running;
+                drop(context);
+                self.save_transition(transition).await?;
+            }
+            Ok(())
+        } else {
+            Err(TaskEngineError::task_not_found(task_id))
+        }
+    }
+
+    /// 记录模型在回答job前的推理过程，与job的输出分开保存，标记为非最终输出。
+    /// 有存储时写入 tool_log，并广播 [TaskEvent::Reasoning]，推理为空时忽略。
+    pub async fn record_reasoning(&self, task_id: i32, job_id: i32, reasoning: &str) -> Result<(), TaskEngineError> {
+        if reasoning.trim().is_empty() {
+            return Ok(());
+        }
+        self.ensure_writable()?;
+        if let Some(store) = self.store() {
+            store.append_tool_log(tool_log::Model {
+                id: 0,
+                taskid: Some(task_id),
+                planid: None,
+                args: Some(serde_json::to_string(&ToolLogArgs { job_id, reasoning: true, ..Default::default() })?),
+                output: Some(rig::telemetry::redact::redact(reasoning)),
+            }).await?;
+        }
+        let mut context = self.tasks.lock(task_id).await.ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+        context.execution_history.push(format!("Reasoning recorded for job {}", job_id));
+        drop(context);
+        self.publish(task_id, TaskEvent::Reasoning { task_id, job_id, reasoning: reasoning.to_string() }).await?;
+        Ok(())
+    }
+
+    /// 累加一次模型调用的token用量到指定任务
+    pub async fn record_usage(&self, task_id: i32, usage: Usage) -> Result<(), TaskEngineError> {
+        self.add_usage(task_id, usage, 0.0).await
+    }
+
+    /// 累加一次模型调用的token用量，并按照价格表计算费用
+    pub async fn record_model_usage(&self, task_id: i32, provider: &str, model: &str, usage: Usage) -> Result<(), TaskEngineError> {
+        let cost = self.pricing.cost(provider, model, &usage);
+        self.add_usage(task_id, usage, cost).await
+    }
+
+    /// 累加一次流式调用的token用量，流被取消、没有收到最终响应时按已收到的数据块估算输出token
+    pub async fn record_stream_usage<R: Clone + Unpin + GetTokenUsage>(&self, task_id: i32, provider: &str, model: &str, stream: &StreamingCompletionResponse<R>) -> Result<(), TaskEngineError> {
+        if !stream.has_final_usage() {
+            tracing::debug!("task {} stream ended without usage, estimated from received chunks", task_id);
+        }
+        self.record_model_usage(task_id, provider, model, stream.usage()).await
+    }
+
+    async fn add_usage(&self, task_id: i32, usage: Usage, cost: f64) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        if let Some(mut context) = self.tasks.lock(task_id).await {
+            context.usage += usage;
+            context.cost += cost;
+            if usage.cached_input_tokens > 0 {
+                tracing::debug!(
+                    "task {} prompt cache hit {} / {} input tokens",
+                    task_id,
+                    usage.cached_input_tokens,
+                    usage.input_tokens
+                );
+            }
+            let total_cost = context.cost;
+            let owner = owner_of(&context).map(str::to_string);
+            drop(context);
+            // 用户的token预算按存储中的累计值检查，任务结束或者引擎重启后不会清零
+            let tokens = usage.input_tokens + usage.output_tokens;
+            if let (Some(owner), Some(store)) = (owner, self.store()) {
+                if tokens > 0 {
+                    store.add_owner_tokens(owner, tokens).await?;
+                }
+            }
+            self.publish(task_id, TaskEvent::UsageRecorded { task_id, usage, cost, total_cost }).await?;
+            Ok(())
+        } else {
+            Err(TaskEngineError::task_not_found(task_id))
+        }
+    }
+
+    /// 获取指定任务的累计费用，美元
+    pub async fn get_cost(&self, task_id: i32) -> Result<f64, TaskEngineError> {
+        if let Some(context) = self.tasks.lock(task_id).await {
+            Ok(context.cost)
+        } else {
+            Err(TaskEngineError::task_not_found(task_id))
+        }
+    }
+
+    /// 获取指定任务的累计token用量
+    pub async fn get_usage(&self, task_id: i32) -> Result<Usage, TaskEngineError> {
+        if let Some(context) = self.tasks.lock(task_id).await {
+            Ok(context.usage)
+        } else {
+            Err(TaskEngineError::task_not_found(task_id))
+        }
+    }
+
     /// 获取指定任务的执行历史
-    pub async fn get_execution_history(&self, task_id: i32) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let tasks = self.tasks.lock().await;
-        if let Some(context) = tasks.get(&task_id) {
+    pub async fn get_execution_history(&self, task_id: i32) -> Result<Vec<String>, TaskEngineError> {
+        if let Some(context) = self.tasks.lock(task_id).await {
             Ok(context.execution_history.clone())
         } else {
-            Err("Task not found".into())
+            Err(TaskEngineError::task_not_found(task_id))
         }
     }
     
     /// 移除已完成的任务
-    pub async fn remove_task(&self, task_id: i32) -> Result<(), Box<dyn std::error::Error>> {
-        let mut tasks = self.tasks.lock().await;
-        if tasks.remove(&task_id).is_some() {
+    pub async fn remove_task(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        if self.tasks.remove(task_id) {
             Ok(())
         } else {
-            Err("Task not found".into())
+            Err(TaskEngineError::task_not_found(task_id))
         }
     }
 }
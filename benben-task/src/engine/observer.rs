@@ -0,0 +1,62 @@
+//! 只读观察模式。
+//!
+//! 观察者引擎连接与执行引擎相同的数据库，但拒绝所有会修改任务的操作，只提供查询。
+//! 报表、UI 等副本可以独立于执行引擎横向扩展，而不会出现任务被重复执行的风险。
+//! 观察者本地没有任务上下文，任务数据直接从数据库读取。
+
+use super::{TaskEngine, TaskEngineError, TaskState};
+use crate::entities::task;
+
+impl TaskEngine {
+    /// 从存储读取任务
+    pub async fn query_task(&self, task_id: i32) -> Result<Option<task::Model>, TaskEngineError> {
+        let store = self.store().ok_or_else(|| TaskEngineError::Unavailable("Task engine has no store".to_string()))?;
+        Ok(store.load_task(task_id).await?)
+    }
+
+    /// 从存储读取任务状态，状态未知的任务返回 None
+    pub async fn query_task_state(&self, task_id: i32) -> Result<Option<TaskState>, TaskEngineError> {
+        let task = self.query_task(task_id).await?;
+        Ok(task.and_then(|t| t.state).and_then(|s| TaskState::parse(&s)))
+    }
+
+    /// 从存储读取处于指定状态的任务
+    pub async fn query_tasks_by_state(&self, state: TaskState) -> Result<Vec<task::Model>, TaskEngineError> {
+        let store = self.store().ok_or_else(|| TaskEngineError::Unavailable("Task engine has no store".to_string()))?;
+        Ok(store
+            .list_tasks()
+            .await?
+            .into_iter()
+            .filter(|task| task.state.as_deref() == Some(state.as_str()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrate::create_schema;
+    use sea_orm::{ActiveModelTrait, ActiveValue::Set, Database};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn observer_reads_but_refuses_mutations() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&db).await.unwrap();
+        task::ActiveModel {
+            state: Set(Some(TaskState::Running.as_str().to_string())),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let mut observer = TaskEngine::new().with_db(Arc::new(db)).as_observer();
+        assert!(observer.is_observer());
+        assert!(observer.init(2, "input".to_string()).await.is_err());
+        assert!(observer.cancel(1).await.is_err());
+
+        assert_eq!(observer.query_task_state(1).await.unwrap(), Some(TaskState::Running));
+        assert_eq!(observer.query_tasks_by_state(TaskState::Running).await.unwrap().len(), 1);
+    }
+}
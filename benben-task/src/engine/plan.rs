@@ -0,0 +1,246 @@
+//! 任务的计划表。
+//!
+//! 工作流的 plan 字段描述任务的执行步骤，可以是 `|` 分隔的步骤，也可以是结构化的json数组：
+//!
+//! ```json
+//! [{"step": "分析涉及的实体", "job_id": 3}, "人工确认"]
+//! ```
+//!
+//! 工作流关联到任务时按顺序装填到 `plan` 表，所有步骤的 planid 相同。关联了job的步骤在
+//! job执行成功或者输出被拒绝时自动更新状态，其余步骤由规划agent通过
+//! [TaskEngine::set_plan_status] 维护。[TaskEngine::get_plan] 返回步骤和状态，供UI展示进度。
+
+use serde::{Deserialize, Serialize};
+
+use super::store::StoreBatch;
+use super::{TaskEngine, TaskEngineError};
+use crate::entities::{plan, workflow};
+
+/// 计划中的一个步骤
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub step: String,
+    /// 执行该步骤的job
+    #[serde(default)]
+    pub job_id: Option<i32>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawStep {
+    Text(String),
+    Step(PlanStep),
+}
+
+/// 解析工作流的 plan 字段，json数组之外的内容按 `|` 分隔，忽略空的步骤
+pub fn parse_plan(plan: &str) -> Vec<PlanStep> {
+    let steps = match serde_json::from_str::<Vec<RawStep>>(plan.trim()) {
+        Ok(steps) => steps
+            .into_iter()
+            .map(|step| match step {
+                RawStep::Text(step) => PlanStep { step, job_id: None },
+                RawStep::Step(step) => step,
+            })
+            .collect(),
+        Err(_) => plan
+            .split('|')
+            .map(|step| PlanStep {
+                step: step.to_string(),
+                job_id: None,
+            })
+            .collect::<Vec<_>>(),
+    };
+    steps
+        .into_iter()
+        .map(|step| PlanStep {
+            step: step.step.trim().to_string(),
+            ..step
+        })
+        .filter(|step| !step.step.is_empty())
+        .collect()
+}
+
+pub use benben_api::{PlanStatus, PlanStepView};
+
+impl From<&plan::Model> for PlanStepView {
+    fn from(row: &plan::Model) -> Self {
+        Self {
+            seq: row.seq,
+            step: row.step.clone().unwrap_or_default(),
+            job_id: row.job_id,
+            status: row
+                .state
+                .as_deref()
+                .and_then(PlanStatus::parse)
+                .unwrap_or(PlanStatus::Pending),
+        }
+    }
+}
+
+/// 任务的计划表的 planid
+pub fn plan_id(task_id: i32) -> String {
+    format!("task-{task_id}")
+}
+
+impl TaskEngine {
+    /// 按工作流的 plan 字段装填任务的计划表，已经装填过时保留原有的步骤和状态
+    pub(crate) async fn fill_plan(
+        &self,
+        task_id: i32,
+        workflow: &workflow::Model,
+    ) -> Result<(), TaskEngineError> {
+        let Some(store) = self.store() else {
+            return Ok(());
+        };
+        let planid = plan_id(task_id);
+        if !store.load_plans(planid.clone()).await?.is_empty() {
+            return Ok(());
+        }
+        let steps = parse_plan(workflow.plan.as_deref().unwrap_or_default());
+        if steps.is_empty() {
+            return Ok(());
+        }
+        // 计划的步骤和任务的 planid 一起写入，避免留下没有关联到任务的计划。
+        // 任务使用内存中的记录和上一次写入得到的版本，内存中没有这个任务时读取存储
+        let _write = self.tasks.lock_write(task_id).await;
+        let task = match self.tasks.lock(task_id).await {
+            Some(context) => context.task.clone(),
+            None => store.load_task(task_id).await?,
+        };
+        let mut batch = StoreBatch {
+            task,
+            ..Default::default()
+        };
+        for (i, step) in steps.into_iter().enumerate() {
+            batch.plans.push(plan::Model {
+                id: 0,
+                pid: Some(task_id),
+                state: Some(PlanStatus::Pending.as_str().to_string()),
+                planid: Some(planid.clone()),
+                seq: i as i32 + 1,
+                step: Some(step.step),
+                job_id: step.job_id,
+                version: 0,
+            });
+        }
+        if let Some(task) = batch.task.as_mut() {
+            task.planid = Some(planid.clone());
+        }
+        let version = store.write_batch(batch).await?;
+        if let Some(task) = self
+            .tasks
+            .lock(task_id)
+            .await
+            .as_mut()
+            .and_then(|c| c.task.as_mut())
+        {
+            task.planid = Some(planid);
+            task.version = version.unwrap_or(task.version);
+        }
+        Ok(())
+    }
+
+    /// 任务的计划步骤，按序号排列
+    pub async fn get_plan(&self, task_id: i32) -> Result<Vec<PlanStepView>, TaskEngineError> {
+        let store = self
+            .store()
+            .ok_or_else(|| TaskEngineError::Unavailable("Task engine has no store".to_string()))?;
+        let mut rows = store.load_plans(plan_id(task_id)).await?;
+        rows.sort_by_key(|row| row.seq);
+        Ok(rows.iter().map(PlanStepView::from).collect())
+    }
+
+    /// 更新计划中序号为 `seq` 的步骤的状态
+    pub async fn set_plan_status(
+        &self,
+        task_id: i32,
+        seq: i32,
+        status: PlanStatus,
+    ) -> Result<(), TaskEngineError> {
+        self.update_plan(task_id, |row| row.seq == seq, status)
+            .await
+    }
+
+    /// 更新关联了该job的步骤的状态，没有这样的步骤时忽略
+    pub(crate) async fn set_plan_job_status(
+        &self,
+        task_id: i32,
+        job_id: i32,
+        status: PlanStatus,
+    ) -> Result<(), TaskEngineError> {
+        self.update_plan(task_id, |row| row.job_id == Some(job_id), status)
+            .await
+    }
+
+    async fn update_plan(
+        &self,
+        task_id: i32,
+        matches: impl Fn(&plan::Model) -> bool,
+        status: PlanStatus,
+    ) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        let Some(store) = self.store() else {
+            return Ok(());
+        };
+        for mut row in store.load_plans(plan_id(task_id)).await? {
+            if matches(&row) {
+                row.state = Some(status.as_str().to_string());
+                store.save_plan(row).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::engine::fixtures;
+    use crate::engine::store::MemoryStore;
+
+    #[tokio::test]
+    async fn plan_is_filled_from_workflow() {
+        assert_eq!(
+            parse_plan(" analyse | | create "),
+            vec![
+                PlanStep {
+                    step: "analyse".into(),
+                    job_id: None
+                },
+                PlanStep {
+                    step: "create".into(),
+                    job_id: None
+                },
+            ]
+        );
+
+        let mut engine = TaskEngine::new().with_store(Arc::new(MemoryStore::new()));
+        engine.init(1, "orders".to_string()).await.unwrap();
+        let workflow = workflow::Model {
+            plan: Some(r#"[{"step": "analyse", "job_id": 7}, "review"]"#.into()),
+            ..fixtures::workflow("w1")
+        };
+        engine.attach_workflow(1, workflow).await.unwrap();
+        engine
+            .set_plan_job_status(1, 7, PlanStatus::Success)
+            .await
+            .unwrap();
+        engine
+            .set_plan_status(1, 2, PlanStatus::Running)
+            .await
+            .unwrap();
+
+        let plan = engine.get_plan(1).await.unwrap();
+        let steps: Vec<(&str, PlanStatus)> =
+            plan.iter().map(|s| (s.step.as_str(), s.status)).collect();
+        assert_eq!(
+            steps,
+            vec![
+                ("analyse", PlanStatus::Success),
+                ("review", PlanStatus::Running)
+            ]
+        );
+    }
+}
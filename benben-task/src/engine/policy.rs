@@ -0,0 +1,273 @@
+//! 任务审批策略引擎。
+//!
+//! 在任务创建以及执行危险job之前统一进行规则判断，避免检查逻辑散落在各处。
+//! 规则按声明顺序匹配，第一个所有条件均满足的规则决定结果；没有规则命中时使用默认决策。
+
+use serde::{Deserialize, Serialize};
+
+/// 策略判断的结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "decision", content = "reason", rename_all = "snake_case")]
+pub enum PolicyDecision {
+    /// 自动通过
+    Approve,
+    /// 需要人工审批，任务进入Pending等待唤醒
+    RequireApproval(String),
+    /// 直接拒绝
+    Reject(String),
+}
+
+/// 参与策略判断的任务元数据
+#[derive(Debug, Clone, Default)]
+pub struct PolicySubject {
+    pub workflow_id: Option<String>,
+    pub input: String,
+    /// 预估花费，美元。job执行前按任务已完成job的平均费用预估，见 [super::budget::projected_step_cost]
+    pub estimated_cost: f64,
+    /// 本次请求需要用到的工具，job执行前为job的agent配置的工具
+    pub tools: Vec<String>,
+    /// job 的类型，任务创建时为空
+    pub job_type: Option<String>,
+}
+
+impl PolicySubject {
+    /// 任务创建时的判断主体
+    pub fn for_task(input: &str) -> Self {
+        Self {
+            input: input.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 声明式的规则条件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum PolicyCondition {
+    Always,
+    WorkflowIs(String),
+    InputLongerThan(usize),
+    InputContains(String),
+    CostAbove(f64),
+    UsesTool(String),
+    JobTypeIs(String),
+}
+
+impl PolicyCondition {
+    fn matches(&self, subject: &PolicySubject) -> bool {
+        match self {
+            PolicyCondition::Always => true,
+            PolicyCondition::WorkflowIs(id) => subject.workflow_id.as_deref() == Some(id),
+            PolicyCondition::InputLongerThan(len) => subject.input.chars().count() > *len,
+            PolicyCondition::InputContains(pattern) => subject.input.contains(pattern.as_str()),
+            PolicyCondition::CostAbove(cost) => subject.estimated_cost > *cost,
+            PolicyCondition::UsesTool(tool) => subject.tools.iter().any(|t| t == tool),
+            PolicyCondition::JobTypeIs(job_type) => {
+                subject.job_type.as_deref() == Some(job_type)
+            }
+        }
+    }
+}
+
+/// 单条规则，所有条件同时满足时生效
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyRule {
+    pub name: String,
+    pub conditions: Vec<PolicyCondition>,
+    pub decision: PolicyDecision,
+}
+
+impl PolicyRule {
+    pub fn new(name: &str, decision: PolicyDecision) -> Self {
+        Self {
+            name: name.to_string(),
+            conditions: Vec::new(),
+            decision,
+        }
+    }
+
+    pub fn when(mut self, condition: PolicyCondition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    fn matches(&self, subject: &PolicySubject) -> bool {
+        self.conditions.iter().all(|c| c.matches(subject))
+    }
+}
+
+/// 策略引擎
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyEngine {
+    pub rules: Vec<PolicyRule>,
+    pub default_decision: PolicyDecision,
+}
+
+impl Default for PolicyEngine {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            default_decision: PolicyDecision::Approve,
+        }
+    }
+}
+
+impl PolicyEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 从json配置中加载规则
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// 追加一条规则
+    pub fn rule(mut self, rule: PolicyRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// 设置没有规则命中时的默认决策
+    pub fn default_decision(mut self, decision: PolicyDecision) -> Self {
+        self.default_decision = decision;
+        self
+    }
+
+    /// 对主体进行判断
+    pub fn evaluate(&self, subject: &PolicySubject) -> PolicyDecision {
+        for rule in &self.rules {
+            if rule.matches(subject) {
+                tracing::debug!("policy rule {} matched: {:?}", rule.name, rule.decision);
+                return rule.decision.clone();
+            }
+        }
+        self.default_decision.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let engine = PolicyEngine::new()
+            .rule(
+                PolicyRule::new("no-rm", PolicyDecision::Reject("rm".into()))
+                    .when(PolicyCondition::InputContains("rm -rf".into())),
+            )
+            .rule(
+                PolicyRule::new("shell", PolicyDecision::RequireApproval("shell".into()))
+                    .when(PolicyCondition::UsesTool("shell".into())),
+            );
+
+        let mut subject = PolicySubject::for_task("please rm -rf /");
+        subject.tools.push("shell".into());
+        assert_eq!(
+            engine.evaluate(&subject),
+            PolicyDecision::Reject("rm".into())
+        );
+
+        let mut subject = PolicySubject::for_task("list files");
+        subject.tools.push("shell".into());
+        assert_eq!(
+            engine.evaluate(&subject),
+            PolicyDecision::RequireApproval("shell".into())
+        );
+
+        assert_eq!(
+            engine.evaluate(&PolicySubject::for_task("hello")),
+            PolicyDecision::Approve
+        );
+    }
+
+    #[test]
+    fn rules_load_from_json() {
+        let engine = PolicyEngine::from_json(
+            r#"{
+                "rules": [{
+                    "name": "expensive",
+                    "conditions": [{"type": "cost_above", "value": 1.5}],
+                    "decision": {"decision": "require_approval", "reason": "budget"}
+                }],
+                "default_decision": {"decision": "approve"}
+            }"#,
+        )
+        .unwrap();
+
+        let subject = PolicySubject {
+            estimated_cost: 2.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            engine.evaluate(&subject),
+            PolicyDecision::RequireApproval("budget".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn jobs_are_checked_with_cost_and_tools() {
+        use std::sync::Arc;
+
+        use rig::completion::Usage;
+
+        use crate::engine::fixtures;
+        use crate::engine::simulation::StubAgents;
+        use crate::engine::{TaskEngine, TaskEngineError, TaskState};
+
+        let policy = PolicyEngine::new()
+            .rule(
+                PolicyRule::new("shell", PolicyDecision::RequireApproval("shell".into()))
+                    .when(PolicyCondition::UsesTool("shell".into())),
+            )
+            .rule(
+                PolicyRule::new(
+                    "expensive",
+                    PolicyDecision::RequireApproval("budget".into()),
+                )
+                .when(PolicyCondition::CostAbove(0.1)),
+            );
+        let stubs = StubAgents::new()
+            .with_replies("ops", ["done"])
+            .with_tools("ops", ["shell"])
+            .with_replies("analyst", ["12 orders"]);
+        let engine = TaskEngine::new()
+            .with_policy(policy)
+            .with_stub_agents(Arc::new(stubs));
+        let job = |id: i32, code: &str| fixtures::agent_job(id, code, "check {{ task.input }}");
+        let task_id = engine.create_task("audit".into()).await.unwrap();
+        engine.start(task_id).await.unwrap();
+
+        // agent配置了 shell 工具，执行前等待审批，审批后任务恢复执行
+        assert!(matches!(
+            engine.execute_job(task_id, job(1, "ops")).await,
+            Err(TaskEngineError::Rejected(_))
+        ));
+        assert_eq!(engine.get_state(task_id).await.unwrap(), TaskState::Pending);
+        engine.approve_job(task_id, 1).await.unwrap();
+        assert_eq!(engine.get_state(task_id).await.unwrap(), TaskState::Running);
+        engine.execute_job(task_id, job(1, "ops")).await.unwrap();
+
+        // 已完成job的平均费用作为下一步的预估费用
+        engine
+            .record_model_usage(
+                task_id,
+                "deepseek",
+                "deepseek-chat",
+                Usage {
+                    input_tokens: 1_000_000,
+                    output_tokens: 0,
+                    total_tokens: 1_000_000,
+                    cached_input_tokens: 0,
+                },
+            )
+            .await
+            .unwrap();
+        let err = engine
+            .execute_job(task_id, job(2, "analyst"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("budget"), "{err}");
+    }
+}
@@ -0,0 +1,233 @@
+//! 工作流的试运行（dry run）。
+//!
+//! 按照job之间的 pid 依赖遍历工作流，使用给定的输入渲染每个job的prompt，列出执行job的
+//! agent会提供的工具，返回完整的执行计划，不调用任何模型。用于在消耗token之前调试
+//! 工作流定义：模板里拼错的变量、缺少的agent、依赖成环都会在计划中标出来。
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use rig::client::{AgentConfig, McpType};
+use serde::Serialize;
+
+use super::action::{GenerationParams, JobAction};
+use super::checker::CheckerSpec;
+use super::extraction::{ExtractionSpec, EXTRACTION_JOB_TYPE};
+use super::plan::parse_plan;
+use super::template::{render_prompt, PlanVars, PrevVars, PromptContext, TaskVars};
+use crate::entities::{job, workflow};
+
+/// 计划中的一个job
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "http-api", derive(utoipa::ToSchema))]
+pub struct PlannedJob {
+    pub job_id: i32,
+    /// 执行顺序，从1开始
+    pub step: usize,
+    pub depends_on: Option<i32>,
+    /// 执行job的agent
+    pub agent: Option<String>,
+    pub job_type: Option<String>,
+    /// 渲染后的prompt，上游job的输出用占位符代替
+    pub prompt: String,
+    pub params: GenerationParams,
+    /// agent提供的原生工具
+    pub tools: Vec<String>,
+    /// agent连接的MCP服务，其工具在运行时才能列出
+    pub mcp: Option<String>,
+    /// 检查agent，见 [super::checker]
+    pub checker: Option<String>,
+    /// 渲染失败、找不到agent等问题
+    pub problems: Vec<String>,
+}
+
+/// 试运行得到的执行计划
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "http-api", derive(utoipa::ToSchema))]
+pub struct DryRunPlan {
+    pub workflow_id: String,
+    pub input: String,
+    /// workflow.plan 中的计划步骤，见 [parse_plan]
+    pub plan: Vec<String>,
+    pub jobs: Vec<PlannedJob>,
+    /// 依赖成环或者依赖不存在的job
+    pub unreachable: Vec<i32>,
+}
+
+impl DryRunPlan {
+    /// 计划中是否存在问题
+    pub fn is_ok(&self) -> bool {
+        self.unreachable.is_empty() && self.jobs.iter().all(|job| job.problems.is_empty())
+    }
+}
+
+/// 生成工作流的执行计划，不调用模型
+pub fn dry_run(
+    workflow: &workflow::Model,
+    jobs: &[job::Model],
+    input: &str,
+    agents: &[Arc<AgentConfig>],
+) -> DryRunPlan {
+    let (ordered, unreachable) = order_jobs(jobs);
+    let steps: HashMap<i32, usize> = ordered
+        .iter()
+        .enumerate()
+        .map(|(i, job)| (job.id, i + 1))
+        .collect();
+    let planned = ordered
+        .iter()
+        .map(|job| plan_job(job, steps[&job.id], input, agents))
+        .collect();
+
+    DryRunPlan {
+        workflow_id: workflow.id.clone(),
+        input: input.to_string(),
+        plan: parse_plan(workflow.plan.as_deref().unwrap_or_default())
+            .into_iter()
+            .map(|step| step.step)
+            .collect(),
+        jobs: planned,
+        unreachable,
+    }
+}
+
+/// 按 pid 依赖排列未删除的job，父job总是排在子job之前；同时返回依赖成环的job
+pub(crate) fn order_jobs(jobs: &[job::Model]) -> (Vec<&job::Model>, Vec<i32>) {
+    let jobs: Vec<&job::Model> = jobs.iter().filter(|job| !job.deleted).collect();
+    let ids: HashSet<i32> = jobs.iter().map(|job| job.id).collect();
+
+    // 从没有依赖的job开始按层遍历
+    let mut ordered: Vec<&job::Model> = Vec::new();
+    let mut done: HashSet<i32> = HashSet::new();
+    loop {
+        let ready: Vec<&job::Model> = jobs
+            .iter()
+            .filter(|job| !done.contains(&job.id))
+            .filter(|job| match job.pid {
+                Some(pid) if ids.contains(&pid) => done.contains(&pid),
+                _ => true,
+            })
+            .copied()
+            .collect();
+        if ready.is_empty() {
+            break;
+        }
+        for job in ready {
+            done.insert(job.id);
+            ordered.push(job);
+        }
+    }
+    let unreachable = jobs
+        .iter()
+        .filter(|job| !done.contains(&job.id))
+        .map(|job| job.id)
+        .collect();
+    (ordered, unreachable)
+}
+
+fn plan_job(job: &job::Model, step: usize, input: &str, agents: &[Arc<AgentConfig>]) -> PlannedJob {
+    let mut problems = Vec::new();
+    let action = job.action.as_deref().unwrap_or_default();
+    let action = if job.r#type.as_deref() == Some(EXTRACTION_JOB_TYPE) {
+        match ExtractionSpec::parse(action) {
+            Ok(spec) => JobAction {
+                prompt: spec.document,
+                ..Default::default()
+            },
+            Err(e) => {
+                problems.push(format!("invalid extraction spec: {e}"));
+                JobAction::default()
+            }
+        }
+    } else {
+        JobAction::parse(action)
+    };
+
+    let context = PromptContext {
+        task: TaskVars {
+            id: 0,
+            input: input.to_string(),
+        },
+        prev: PrevVars {
+            job_id: job.pid,
+            output: job
+                .pid
+                .map(|pid| format!("<output of job {pid}>"))
+                .unwrap_or_default(),
+        },
+        plan: PlanVars { step },
+        workspace: HashMap::new(),
+    };
+    let prompt = match render_prompt(&action.prompt, &context) {
+        Ok(prompt) => prompt,
+        Err(e) => {
+            problems.push(e.to_string());
+            action.prompt.clone()
+        }
+    };
+
+    let agent = job
+        .code
+        .as_ref()
+        .and_then(|code| agents.iter().find(|agent| &agent.code == code));
+    match (&job.code, agent) {
+        (Some(code), None) => problems.push(format!("agent {code} not found")),
+        (_, Some(agent)) if agent.error.is_some() => problems.push(format!(
+            "agent {} is unavailable: {}",
+            agent.code,
+            agent.error.as_deref().unwrap_or_default()
+        )),
+        _ => {}
+    }
+    let checker = job.check.as_deref().and_then(CheckerSpec::parse);
+    if let Some(checker) = &checker {
+        if !agents.iter().any(|agent| agent.code == checker.agent) {
+            problems.push(format!("checker agent {} not found", checker.agent));
+        }
+    }
+
+    PlannedJob {
+        job_id: job.id,
+        step,
+        depends_on: job.pid,
+        agent: job.code.clone(),
+        job_type: job.r#type.clone(),
+        prompt,
+        params: action.params,
+        tools: agent.map(|agent| agent.tools.clone()).unwrap_or_default(),
+        mcp: agent.and_then(|agent| match &agent.mcp {
+            McpType::Nothing => None,
+            McpType::STDIO(_) => Some("stdio".to_string()),
+            McpType::SHTTP(url) => Some(url.clone()),
+        }),
+        checker: checker.map(|checker| checker.agent),
+        problems,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::fixtures::{self, downstream, job};
+
+    #[test]
+    fn orders_jobs_and_renders_prompts() {
+        let workflow = workflow::Model {
+            plan: Some("analyse | create".to_string()),
+            ..fixtures::workflow("1")
+        };
+        let jobs = vec![
+            downstream(1, job(2, "create {{ prev.output }}")),
+            job(1, "analyse {{ task.input }}"),
+            downstream(4, job(3, "a")),
+            downstream(3, job(4, "b")),
+        ];
+        let plan = dry_run(&workflow, &jobs, "orders", &[]);
+        assert_eq!(plan.plan, vec!["analyse", "create"]);
+        assert_eq!(plan.jobs.len(), 2);
+        assert_eq!(plan.jobs[0].prompt, "analyse orders");
+        assert_eq!(plan.jobs[1].prompt, "create <output of job 1>");
+        assert_eq!(plan.unreachable, vec![3, 4]);
+        assert!(!plan.is_ok());
+    }
+}
@@ -0,0 +1,269 @@
+//! 任务标签和查询。
+//!
+//! 任务可以带键值对标签，以json对象保存在任务记录的 `tags` 字段。
+//! [TaskEngine::query_tasks] 按状态、工作流、标签和创建时间筛选任务，排序后分页返回。
+//! 查询合并存储中的任务和内存中的任务，同一个任务以内存中的状态为准。
+//!
+//! ```rust,ignore
+//! engine.set_tag(task_id, "team", "billing").await?;
+//! let page = engine
+//!     .query_tasks(&TaskFilter::default().tag("team", "billing").sort_by(TaskSort::CreatedAt, true).page(0, 20))
+//!     .await?;
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use super::{TaskEngine, TaskEngineError, TaskState};
+use crate::entities::task;
+
+/// 任务的标签
+pub type Tags = BTreeMap<String, String>;
+
+/// 任务记录中的标签，无法解析时视为没有标签
+pub fn task_tags(task: &task::Model) -> Tags {
+    task.tags
+        .as_deref()
+        .and_then(|tags| serde_json::from_str(tags).ok())
+        .unwrap_or_default()
+}
+
+/// 排序字段，相同时按任务id
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskSort {
+    #[default]
+    Id,
+    CreatedAt,
+    Priority,
+}
+
+/// 任务查询条件，为空的条件不筛选
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaskFilter {
+    pub state: Option<TaskState>,
+    pub workflow_id: Option<i32>,
+    /// 任务需要带有所有这些标签
+    pub tags: Tags,
+    /// 创建时间不早于该时间，毫秒时间戳
+    pub created_after: Option<i64>,
+    /// 创建时间早于该时间，毫秒时间戳
+    pub created_before: Option<i64>,
+    pub sort: TaskSort,
+    pub descending: bool,
+    pub offset: usize,
+    /// 每页的任务数，None 时返回剩余所有任务
+    pub limit: Option<usize>,
+}
+
+impl TaskFilter {
+    pub fn state(mut self, state: TaskState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    pub fn workflow(mut self, workflow_id: i32) -> Self {
+        self.workflow_id = Some(workflow_id);
+        self
+    }
+
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn created_between(mut self, after: i64, before: i64) -> Self {
+        self.created_after = Some(after);
+        self.created_before = Some(before);
+        self
+    }
+
+    pub fn sort_by(mut self, sort: TaskSort, descending: bool) -> Self {
+        self.sort = sort;
+        self.descending = descending;
+        self
+    }
+
+    pub fn page(mut self, offset: usize, limit: usize) -> Self {
+        self.offset = offset;
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn matches(&self, task: &task::Model) -> bool {
+        let tags = task_tags(task);
+        self.state
+            .as_ref()
+            .is_none_or(|state| task.state.as_deref() == Some(state.as_str()))
+            && self.workflow_id.is_none_or(|id| task.wid == Some(id))
+            && self
+                .tags
+                .iter()
+                .all(|(key, value)| tags.get(key) == Some(value))
+            && self.created_after.is_none_or(|t| task.created_at >= t)
+            && self.created_before.is_none_or(|t| task.created_at < t)
+    }
+}
+
+/// 一页查询结果
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TaskPage {
+    pub tasks: Vec<task::Model>,
+    /// 符合条件的任务总数
+    pub total: usize,
+}
+
+impl TaskEngine {
+    /// 设置任务的标签，同名标签被覆盖
+    pub async fn set_tag(
+        &self,
+        task_id: i32,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), TaskEngineError> {
+        let (key, value) = (key.into(), value.into());
+        self.update_tags(task_id, |tags| {
+            tags.insert(key, value);
+        })
+        .await
+    }
+
+    pub async fn remove_tag(&self, task_id: i32, key: &str) -> Result<(), TaskEngineError> {
+        self.update_tags(task_id, |tags| {
+            tags.remove(key);
+        })
+        .await
+    }
+
+    /// 任务的标签
+    pub async fn get_tags(&self, task_id: i32) -> Result<Tags, TaskEngineError> {
+        let context = self
+            .tasks
+            .lock(task_id)
+            .await
+            .ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+        Ok(context.task.as_ref().map(task_tags).unwrap_or_default())
+    }
+
+    async fn update_tags(
+        &self,
+        task_id: i32,
+        update: impl FnOnce(&mut Tags),
+    ) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        let tags = {
+            let mut context = self
+                .tasks
+                .lock(task_id)
+                .await
+                .ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+            let task = context
+                .task
+                .as_mut()
+                .ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+            let mut tags = task_tags(task);
+            update(&mut tags);
+            task.tags = (!tags.is_empty())
+                .then(|| serde_json::to_string(&tags))
+                .transpose()?;
+            task.tags.clone()
+        };
+        self.persist_task(task_id, |task| task.tags = tags).await
+    }
+
+    /// 按条件查询存储和内存中的任务
+    pub async fn query_tasks(&self, filter: &TaskFilter) -> Result<TaskPage, TaskEngineError> {
+        let mut all: HashMap<i32, task::Model> = HashMap::new();
+        if let Some(store) = self.store() {
+            all.extend(store.list_tasks().await?.into_iter().map(|t| (t.id, t)));
+        }
+        let in_memory = self
+            .tasks
+            .collect(|_, context| {
+                let mut task = context.task.clone()?;
+                task.state = Some(context.state.as_str().to_string());
+                Some(task)
+            })
+            .await;
+        all.extend(in_memory.into_iter().map(|t| (t.id, t)));
+
+        let mut tasks: Vec<task::Model> = all.into_values().filter(|t| filter.matches(t)).collect();
+        tasks.sort_by_key(|t| {
+            let key = match filter.sort {
+                TaskSort::Id => 0,
+                TaskSort::CreatedAt => t.created_at,
+                TaskSort::Priority => t.priority as i64,
+            };
+            (key, t.id)
+        });
+        if filter.descending {
+            tasks.reverse();
+        }
+        let total = tasks.len();
+        let tasks = tasks
+            .into_iter()
+            .skip(filter.offset)
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .collect();
+        Ok(TaskPage { tasks, total })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::clock::MockClock;
+    use crate::engine::store::MemoryStore;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn filters_by_tag_and_time_with_paging() {
+        let clock = Arc::new(MockClock::new(0));
+        let mut engine = TaskEngine::new()
+            .with_store(Arc::new(MemoryStore::new()))
+            .with_clock(clock.clone());
+        for id in 1..=4 {
+            engine.init(id, format!("task {id}")).await.unwrap();
+            let team = if id % 2 == 0 { "billing" } else { "search" };
+            engine.set_tag(id, "team", team).await.unwrap();
+            clock.advance(Duration::from_secs(1));
+        }
+        engine.start(2).await.unwrap();
+        engine.remove_tag(3, "team").await.unwrap();
+
+        let billing = TaskFilter::default().tag("team", "billing");
+        let page = engine
+            .query_tasks(
+                &billing
+                    .clone()
+                    .sort_by(TaskSort::CreatedAt, true)
+                    .page(0, 1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.tasks.iter().map(|t| t.id).collect::<Vec<_>>(), vec![4]);
+
+        let running = engine
+            .query_tasks(&billing.state(TaskState::Running))
+            .await
+            .unwrap();
+        assert_eq!(
+            running.tasks.iter().map(|t| t.id).collect::<Vec<_>>(),
+            vec![2]
+        );
+
+        let early = engine
+            .query_tasks(&TaskFilter::default().created_between(0, 2_000))
+            .await
+            .unwrap();
+        assert_eq!(
+            early.tasks.iter().map(|t| t.id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert!(engine.get_tags(3).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+//! 任务队列的检查与人工干预。
+//!
+//! 任务卡住时可以查看每个等待中的任务被什么条件阻塞：引擎维护模式、备用节点、
+//! job等待审批、job等待预算确认、job等待显存额度、job等待子任务。对于单个任务的阻塞条件可以人工跳过，或者强制调度，
+//! 跳过该任务所有可跳过的条件。维护模式和备用节点是引擎级别的条件，不能跳过。
+
+use serde::{Deserialize, Serialize};
+
+use super::budget::BudgetReport;
+use super::transition::{Actor, Transition};
+use super::{TaskEngine, TaskEngineError, TaskState};
+
+/// 阻塞任务的原因
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BlockReason {
+    /// 引擎处于维护模式
+    Maintenance,
+    /// 引擎是备用节点
+    Standby,
+    /// job等待人工审批
+    AwaitingApproval { job_id: i32, reason: String },
+    /// job的预估费用超过预算检查点，等待人工确认
+    AwaitingBudget { report: BudgetReport },
+    /// job等待显存额度
+    Vram {
+        job_id: i32,
+        code: String,
+        required_mb: u64,
+        used_mb: u64,
+        budget_mb: u64,
+    },
+    /// job等待子工作流的子任务完成
+    ChildTask { job_id: i32, child_id: i32 },
+}
+
+impl BlockReason {
+    /// 可以人工跳过的条件类型，引擎级别的条件和等待子任务返回 None
+    pub fn kind(&self) -> Option<BlockKind> {
+        match self {
+            BlockReason::Maintenance | BlockReason::Standby | BlockReason::ChildTask { .. } => None,
+            BlockReason::AwaitingApproval { .. } | BlockReason::AwaitingBudget { .. } => {
+                Some(BlockKind::Approval)
+            }
+            BlockReason::Vram { .. } => Some(BlockKind::Vram),
+        }
+    }
+}
+
+/// 可以人工跳过的阻塞条件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    /// 跳过审批策略和预算检查点
+    Approval,
+    /// 不再申请显存额度
+    Vram,
+}
+
+impl BlockKind {
+    pub const ALL: [BlockKind; 2] = [BlockKind::Approval, BlockKind::Vram];
+}
+
+/// 队列中的一个任务
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueueEntry {
+    pub task_id: i32,
+    pub state: TaskState,
+    pub reasons: Vec<BlockReason>,
+    /// 已经人工跳过的条件
+    pub skipped: Vec<BlockKind>,
+}
+
+impl TaskEngine {
+    /// 所有等待中或者被阻塞的任务，以及阻塞的原因
+    pub async fn inspect_queue(&self) -> Vec<QueueEntry> {
+        let mut engine_reasons = Vec::new();
+        if self.is_maintenance() {
+            engine_reasons.push(BlockReason::Maintenance);
+        }
+        if self.is_standby() {
+            engine_reasons.push(BlockReason::Standby);
+        }
+
+        self.tasks
+            .collect(|task_id, context| {
+                let queued = matches!(context.state, TaskState::Waiting | TaskState::Pending)
+                    || context.blocked.is_some();
+                queued.then(|| QueueEntry {
+                    task_id,
+                    state: context.state.clone(),
+                    reasons: engine_reasons
+                        .iter()
+                        .cloned()
+                        .chain(context.blocked.clone())
+                        .collect(),
+                    skipped: context.skipped_blocks.clone(),
+                })
+            })
+            .await
+    }
+
+    /// 跳过任务的一个阻塞条件，对该任务之后执行的job生效。
+    /// 已经在等待显存的job不会被打断
+    pub async fn skip_block(&self, task_id: i32, kind: BlockKind) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        let mut context = self
+            .tasks
+            .lock(task_id)
+            .await
+            .ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+        if !context.skipped_blocks.contains(&kind) {
+            context.skipped_blocks.push(kind);
+        }
+        if context.blocked.as_ref().and_then(BlockReason::kind) == Some(kind) {
+            context.blocked = None;
+        }
+        context
+            .execution_history
+            .push(format!("Block {:?} skipped manually", kind));
+        Ok(())
+    }
+
+    /// 强制调度任务：跳过所有可跳过的条件，并将任务置为运行中
+    pub async fn force_dispatch(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_not_maintenance()?;
+        for kind in BlockKind::ALL {
+            self.skip_block(task_id, kind).await?;
+        }
+
+        let mut context = self
+            .tasks
+            .lock(task_id)
+            .await
+            .ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+        if context.state == TaskState::Running {
+            return Ok(());
+        }
+        let transition = Transition::check(
+            task_id,
+            &context.state,
+            TaskState::Running,
+            Actor::User,
+            "Task force dispatched",
+        )?;
+        context.state = TaskState::Running;
+        context.execution_history.push(transition.reason.clone());
+        drop(context);
+        self.save_transition(transition).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::fixtures;
+    use crate::engine::policy::{PolicyCondition, PolicyDecision, PolicyEngine, PolicyRule};
+
+    #[tokio::test]
+    async fn skipping_approval_unblocks_task() {
+        let mut engine = TaskEngine::new().with_policy(
+            PolicyEngine::new().rule(
+                PolicyRule::new("deploy", PolicyDecision::RequireApproval("deploy".into()))
+                    .when(PolicyCondition::InputContains("deploy".into())),
+            ),
+        );
+        engine.init(1, "release".to_string()).await.unwrap();
+        let job = fixtures::job(7, "deploy to prod");
+
+        assert!(engine.execute_job(1, job.clone()).await.is_err());
+        let queue = engine.inspect_queue().await;
+        assert_eq!(queue.len(), 1);
+        assert!(matches!(
+            queue[0].reasons.as_slice(),
+            [BlockReason::AwaitingApproval { job_id: 7, .. }]
+        ));
+
+        engine.force_dispatch(1).await.unwrap();
+        assert!(engine.execute_job(1, job).await.is_ok());
+        assert!(engine.inspect_queue().await.is_empty());
+    }
+}
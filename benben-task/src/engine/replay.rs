@@ -0,0 +1,224 @@
+//! 根据 tool_log 确定性地重放任务。
+//!
+//! 每个job的输出都以 [ToolLogArgs] 为参数记录在 tool_log 中。重放时使用已完成任务的输入
+//! 和工作流创建一个新任务，执行job时按job依次取出当时记录的输出，不再调用模型和工具，
+//! 这样修改工作流引擎之后可以低成本地做确定性的回归测试。
+
+use std::collections::{HashMap, VecDeque};
+
+use rig::completion::Usage;
+use serde::{Deserialize, Serialize};
+
+use super::{versioning, TaskContext, TaskEngine, TaskEngineError, TaskState};
+use crate::entities::{task, tool_log, workflow};
+use crate::soft_delete;
+
+/// tool_log.args 中记录的内容
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolLogArgs {
+    pub job_id: i32,
+    /// 未通过后处理链的输出，重放时跳过
+    #[serde(default)]
+    pub rejected: bool,
+    /// 模型的推理过程，不是job的最终输出，重放时跳过
+    #[serde(default)]
+    pub reasoning: bool,
+    /// 检查点的差异和结论，见 [super::evidence]，重放时跳过
+    #[serde(default)]
+    pub evidence: bool,
+}
+
+/// 重放使用的job输出，按记录顺序排列
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayLog {
+    outputs: HashMap<i32, VecDeque<String>>,
+}
+
+impl ReplayLog {
+    /// 从任务的 tool_log 构建，没有参数、被拒绝的记录、推理过程和检查证据不参与重放
+    pub fn from_logs(logs: &[tool_log::Model]) -> Self {
+        let mut outputs: HashMap<i32, VecDeque<String>> = HashMap::new();
+        for log in logs {
+            let Some(args) = log
+                .args
+                .as_deref()
+                .and_then(|args| serde_json::from_str::<ToolLogArgs>(args).ok())
+            else {
+                continue;
+            };
+            if args.rejected || args.reasoning || args.evidence {
+                continue;
+            }
+            outputs
+                .entry(args.job_id)
+                .or_default()
+                .push_back(log.output.clone().unwrap_or_default());
+        }
+        Self { outputs }
+    }
+
+    /// 取出job的下一条记录的输出
+    pub fn next(&mut self, job_id: i32) -> Option<String> {
+        self.outputs.get_mut(&job_id).and_then(VecDeque::pop_front)
+    }
+
+    /// 还没有被重放的输出数
+    pub fn remaining(&self) -> usize {
+        self.outputs.values().map(VecDeque::len).sum()
+    }
+}
+
+impl TaskEngine {
+    /// 使用存储中已完成任务的输入、工作流和 tool_log 创建一个重放任务
+    pub async fn replay(&self, source_id: i32, task_id: i32) -> Result<(), TaskEngineError> {
+        let store = self.store().ok_or_else(|| {
+            TaskEngineError::Unavailable("Replay requires a task store".to_string())
+        })?;
+        let source = store
+            .load_task(source_id)
+            .await?
+            .ok_or_else(|| TaskEngineError::task_not_found(source_id))?;
+        if source.state.as_deref() != Some(TaskState::Finished.as_str()) {
+            return Err(format!("Task {} is not finished", source_id).into());
+        }
+        // 使用源任务开始时的工作流版本
+        let workflow = match (source.wid, source.wversion, self.db()) {
+            (Some(wid), Some(version), Some(db)) => Some(
+                versioning::load_version(db.as_ref(), &wid.to_string(), version)
+                    .await?
+                    .workflow,
+            ),
+            // 没有记录版本时使用当前定义，已删除的工作流不再执行
+            (Some(wid), None, Some(db)) => {
+                soft_delete::active_workflow(db.as_ref(), &wid.to_string()).await?
+            }
+            _ => None,
+        };
+        let logs = store.load_tool_logs(source_id).await?;
+        self.replay_with_logs(source, workflow, &logs, task_id)
+            .await
+    }
+
+    /// 使用给定的任务、工作流和 tool_log 创建重放任务，之后执行的job返回记录的输出
+    pub async fn replay_with_logs(
+        &self,
+        source: task::Model,
+        workflow: Option<workflow::Model>,
+        logs: &[tool_log::Model],
+        task_id: i32,
+    ) -> Result<(), TaskEngineError> {
+        self.ensure_not_maintenance()?;
+        let replay = ReplayLog::from_logs(logs);
+        let inserted = self.tasks.insert_new(
+            task_id,
+            TaskContext {
+                state: TaskState::Waiting,
+                task: Some(task::Model {
+                    id: task_id,
+                    input: source.input,
+                    output: None,
+                    state: Some(TaskState::Waiting.as_str().to_string()),
+                    wid: source.wid,
+                    planid: None,
+                    wversion: source.wversion,
+                    priority: source.priority,
+                    owner_id: source.owner_id,
+                    tags: source.tags,
+                    created_at: self.clock.now_millis(),
+                    report: None,
+                    version: 0,
+                    worker_id: None,
+                    heartbeat_at: None,
+                }),
+                workflow,
+                execution_history: vec![format!(
+                    "Replaying task {} with {} recorded outputs",
+                    source.id,
+                    replay.remaining()
+                )],
+                usage: Usage::new(),
+                approved_jobs: Vec::new(),
+                blocked: None,
+                skipped_blocks: Vec::new(),
+                last_output: None,
+                step: 0,
+                variables: HashMap::new(),
+                cost: 0.0,
+                replay: Some(replay),
+                pinned_jobs: HashMap::new(),
+                parent: None,
+                children: HashMap::new(),
+                preempted_by: None,
+                steps: Vec::new(),
+                outputs: HashMap::new(),
+            },
+        );
+        if !inserted {
+            return Err(format!("Task {} already exists", task_id).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::fixtures;
+
+    fn log(id: i32, job_id: i32, rejected: bool, output: &str) -> tool_log::Model {
+        tool_log::Model {
+            id,
+            taskid: Some(1),
+            planid: None,
+            args: Some(
+                serde_json::to_string(&ToolLogArgs {
+                    job_id,
+                    rejected,
+                    ..Default::default()
+                })
+                .unwrap(),
+            ),
+            output: Some(output.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_outputs() {
+        let engine = TaskEngine::new();
+        let source = task::Model {
+            id: 1,
+            input: Some("orders".to_string()),
+            output: None,
+            state: Some("finished".to_string()),
+            wid: None,
+            planid: None,
+            wversion: None,
+            priority: 0,
+            owner_id: None,
+            tags: None,
+            created_at: 0,
+            report: None,
+            version: 0,
+            worker_id: None,
+            heartbeat_at: None,
+        };
+        let mut reasoning = log(2, 7, false, "count the aggregates first");
+        reasoning.args = Some(r#"{"job_id":7,"reasoning":true}"#.to_string());
+        let logs = vec![
+            log(1, 7, true, "rejected answer"),
+            reasoning,
+            log(3, 7, false, "3 aggregates"),
+        ];
+        engine
+            .replay_with_logs(source, None, &logs, 2)
+            .await
+            .unwrap();
+
+        let job = fixtures::job(7, "analyse {{ task.input }}");
+        assert_eq!(
+            engine.execute_job(2, job.clone()).await.unwrap(),
+            "3 aggregates"
+        );
+        assert!(engine.execute_job(2, job).await.is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! 任务报告。
+//!
+//! 任务完成时引擎汇总执行过的job、使用的agent、工具调用次数、token和费用、每个job的耗时
+//! 以及最终输出，生成 [TaskReport] 以json保存在任务记录的 `report` 字段，通过
+//! [TaskEngine::get_report] 读取。[TaskEngine::render_report] 输出markdown，
+//! 配置了报告agent时由agent改写成便于阅读的文字。
+
+use rig::completion::{Prompt, Usage};
+use serde::{Deserialize, Serialize};
+
+use super::replay::ToolLogArgs;
+use super::{TaskEngine, TaskEngineError, TaskState};
+use crate::mananger::AgentManager;
+
+/// 一个执行完成的job
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub job_id: i32,
+    /// 执行job的agent
+    pub agent: Option<String>,
+    pub job_type: Option<String>,
+    /// 开始执行的时间，毫秒时间戳
+    pub started_at: i64,
+    pub duration_ms: i64,
+    /// 成功的是这个job的第几次尝试，每次尝试的记录见 [TaskEngine::get_job_runs]
+    #[serde(default = "first_attempt")]
+    pub attempt: i32,
+}
+
+fn first_attempt() -> i32 {
+    1
+}
+
+/// 任务完成时的报告
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskReport {
+    pub task_id: i32,
+    pub workflow_id: Option<String>,
+    pub state: TaskState,
+    pub steps: Vec<StepRecord>,
+    /// 执行过job的agent，按首次使用的顺序
+    pub agents: Vec<String>,
+    /// 记录的工具调用数，包含未通过后处理链的输出
+    pub tool_calls: usize,
+    /// 未通过后处理链的输出数
+    pub rejected_outputs: usize,
+    pub usage: Usage,
+    /// 累计费用，美元
+    pub cost: f64,
+    /// 任务创建到完成的时间
+    pub duration_ms: i64,
+    pub output: Option<String>,
+}
+
+impl TaskReport {
+    /// 不经过agent的markdown报告
+    pub fn to_markdown(&self) -> String {
+        let mut md = format!("# 任务 {} 报告\n\n", self.task_id);
+        if let Some(workflow_id) = &self.workflow_id {
+            md += &format!("- 工作流：{}\n", workflow_id);
+        }
+        md += &format!("- 状态：{}\n", self.state.as_str());
+        md += &format!("- 耗时：{} ms\n", self.duration_ms);
+        md += &format!(
+            "- token：输入 {}，输出 {}\n",
+            self.usage.input_tokens, self.usage.output_tokens
+        );
+        md += &format!("- 费用：${:.4}\n", self.cost);
+        md += &format!(
+            "- 工具调用：{}（被拒绝 {}）\n",
+            self.tool_calls, self.rejected_outputs
+        );
+        if !self.agents.is_empty() {
+            md += &format!("- agent：{}\n", self.agents.join("、"));
+        }
+        md += "\n## 步骤\n\n| job | agent | 尝试 | 耗时(ms) |\n| --- | --- | --- | --- |\n";
+        for step in &self.steps {
+            md += &format!(
+                "| {} | {} | {} | {} |\n",
+                step.job_id,
+                step.agent.as_deref().unwrap_or("-"),
+                step.attempt,
+                step.duration_ms
+            );
+        }
+        if let Some(output) = &self.output {
+            md += &format!("\n## 输出\n\n```json\n{}\n```\n", output);
+        }
+        md
+    }
+}
+
+impl TaskEngine {
+    /// 设置把报告改写成markdown的agent，agent从 [AgentManager] 中按名称查找
+    pub fn with_report_agent(mut self, agent: impl Into<String>) -> Self {
+        self.report_agent = Some(agent.into());
+        self
+    }
+
+    /// 汇总任务当前的执行情况
+    pub async fn build_report(&self, task_id: i32) -> Result<TaskReport, TaskEngineError> {
+        let mut report = {
+            let context = self
+                .tasks
+                .lock(task_id)
+                .await
+                .ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+            let mut agents: Vec<String> = Vec::new();
+            for agent in context.steps.iter().filter_map(|s| s.agent.as_ref()) {
+                if !agents.contains(agent) {
+                    agents.push(agent.clone());
+                }
+            }
+            let created_at = context.task.as_ref().map(|t| t.created_at);
+            TaskReport {
+                task_id,
+                workflow_id: context.workflow.as_ref().map(|w| w.id.clone()),
+                state: context.state.clone(),
+                steps: context.steps.clone(),
+                agents,
+                tool_calls: context.steps.len(),
+                rejected_outputs: 0,
+                usage: context.usage,
+                cost: context.cost,
+                duration_ms: created_at.map_or(0, |t| self.clock.now_millis() - t),
+                output: context.task.as_ref().and_then(|t| t.output.clone()),
+            }
+        };
+        // 有存储时按 tool_log 统计，包含被拒绝的输出
+        if let Some(store) = self.store() {
+            let logs = store.load_tool_logs(task_id).await?;
+            report.tool_calls = logs.len();
+            report.rejected_outputs = logs
+                .iter()
+                .filter_map(|log| log.args.as_deref())
+                .filter_map(|args| serde_json::from_str::<ToolLogArgs>(args).ok())
+                .filter(|args| args.rejected)
+                .count();
+        }
+        Ok(report)
+    }
+
+    /// 生成报告并保存到任务记录
+    pub(crate) async fn save_report(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        let report = serde_json::to_string(&self.build_report(task_id).await?)?;
+        self.persist_task(task_id, |task| task.report = Some(report))
+            .await
+    }
+
+    /// 任务完成时生成的报告，内存中没有该任务时从存储读取，未完成的任务返回 None
+    pub async fn get_report(&self, task_id: i32) -> Result<Option<TaskReport>, TaskEngineError> {
+        let in_memory = self
+            .tasks
+            .lock(task_id)
+            .await
+            .map(|c| c.task.as_ref().and_then(|t| t.report.clone()));
+        let report = match in_memory {
+            Some(report) => report,
+            None => match self.store() {
+                Some(store) => store.load_task(task_id).await?.and_then(|t| t.report),
+                None => return Err(TaskEngineError::task_not_found(task_id)),
+            },
+        };
+        Ok(report.map(|r| serde_json::from_str(&r)).transpose()?)
+    }
+
+    /// markdown格式的报告，配置了报告agent时由agent改写
+    pub async fn render_report(&self, task_id: i32) -> Result<String, TaskEngineError> {
+        let report = self
+            .get_report(task_id)
+            .await?
+            .ok_or_else(|| TaskEngineError::NotFound(format!("Report of task {}", task_id)))?;
+        let Some(name) = &self.report_agent else {
+            return Ok(report.to_markdown());
+        };
+        let agent = AgentManager::global()
+            .and_then(|manager| manager.agent_map.get(name).cloned())
+            .ok_or_else(|| TaskEngineError::NotFound(format!("Report agent {}", name)))?;
+        let prompt = format!(
+            "把下面的任务执行报告整理成便于阅读的markdown，保留所有数字，不要编造内容。\n\n{}",
+            report.to_markdown()
+        );
+        Ok(agent.prompt(prompt).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::clock::MockClock;
+    use crate::engine::fixtures;
+    use crate::engine::store::{MemoryStore, TaskStore};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn finish_persists_report() {
+        let clock = Arc::new(MockClock::new(0));
+        let store = Arc::new(MemoryStore::new());
+        let mut engine = TaskEngine::new()
+            .with_store(store.clone())
+            .with_clock(clock.clone());
+        engine.init(1, "orders".to_string()).await.unwrap();
+        engine.start(1).await.unwrap();
+        for (id, agent) in [(1, "planner"), (2, "coder"), (3, "planner")] {
+            let job = fixtures::agent_job(id, agent, "step");
+            engine.execute_job(1, job).await.unwrap();
+            clock.advance(Duration::from_secs(1));
+        }
+        engine
+            .record_usage(
+                1,
+                Usage {
+                    input_tokens: 30,
+                    output_tokens: 12,
+                    ..Usage::new()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(engine.get_report(1).await.unwrap(), None);
+        engine.finish(1).await.unwrap();
+
+        let report = engine.get_report(1).await.unwrap().unwrap();
+        assert_eq!(report.steps.len(), 3);
+        assert_eq!(report.agents, vec!["planner", "coder"]);
+        assert_eq!(report.tool_calls, 3);
+        assert_eq!(report.usage.output_tokens, 12);
+        assert_eq!(report.duration_ms, 3_000);
+        assert!(store.load_task(1).await.unwrap().unwrap().report.is_some());
+        assert!(engine
+            .render_report(1)
+            .await
+            .unwrap()
+            .contains("| 2 | coder |"));
+    }
+}
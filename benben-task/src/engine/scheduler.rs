@@ -0,0 +1,285 @@
+//! 按优先级调度任务。
+//!
+//! 每次调度在并发上限内按优先级从高到低启动等待中的任务，同优先级按任务id先后。
+//! 配置了抢占阈值时，优先级达到阈值的任务到达而没有空位，会暂停一个优先级更低的运行中任务，
+//! 被抢占的任务记住抢占它的任务，等该任务结束后在之后的调度中按原优先级恢复。
+//! 调度按任务快照挑选，只锁住要启动和抢占的任务；正被锁住的任务本次调度不启动也不抢占，
+//! 正在执行job的任务不会被抢占，暂停只会发生在两个job之间。
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+use super::transition::{Actor, Transition};
+use super::{TaskContext, TaskEngine, TaskEngineError, TaskState};
+
+/// 调度配置
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchedulePolicy {
+    /// 同时运行的任务数上限
+    pub max_running: usize,
+    /// 优先级不低于这个值的任务可以抢占低优先级的运行中任务，None 时不抢占
+    pub preempt_priority: Option<i32>,
+}
+
+impl Default for SchedulePolicy {
+    fn default() -> Self {
+        Self {
+            max_running: 4,
+            preempt_priority: None,
+        }
+    }
+}
+
+/// 一次调度的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScheduleReport {
+    /// 启动或恢复的任务，按启动顺序
+    pub started: Vec<i32>,
+    /// 被抢占的任务及抢占它的任务
+    pub preempted: Vec<(i32, i32)>,
+}
+
+fn priority_of(context: &TaskContext) -> i32 {
+    context
+        .task
+        .as_ref()
+        .map(|t| t.priority)
+        .unwrap_or_default()
+}
+
+impl TaskEngine {
+    /// 设置任务的优先级
+    pub async fn set_priority(&self, task_id: i32, priority: i32) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        if !self.tasks.contains(task_id) {
+            return Err(TaskEngineError::task_not_found(task_id));
+        }
+        self.persist_task(task_id, |task| task.priority = priority)
+            .await
+    }
+
+    /// 任务的优先级
+    pub async fn get_priority(&self, task_id: i32) -> Result<i32, TaskEngineError> {
+        let context = self
+            .tasks
+            .lock(task_id)
+            .await
+            .ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+        Ok(priority_of(&context))
+    }
+
+    /// 执行一次调度
+    pub async fn schedule(
+        &self,
+        policy: &SchedulePolicy,
+    ) -> Result<ScheduleReport, TaskEngineError> {
+        self.ensure_not_maintenance()?;
+        self.ensure_writable()?;
+        let mut report = ScheduleReport::default();
+        let mut transitions = Vec::new();
+        // 按快照挑选任务，只锁住要启动和抢占的任务，不等待正在执行job的任务
+        let mut tasks = self.tasks.snapshots();
+
+        // 等待中的任务，以及抢占者已经结束的被抢占任务
+        let ended = |id: &i32| {
+            tasks.get(id).is_none_or(|s| {
+                matches!(
+                    s.state,
+                    TaskState::Finished | TaskState::Cancelled | TaskState::Stopped
+                )
+            })
+        };
+        let mut candidates: Vec<(i32, i32)> = tasks
+            .iter()
+            .filter(|(_, s)| !s.blocked)
+            .filter(|(_, s)| match s.preempted_by {
+                Some(by) => s.state == TaskState::Pending && ended(&by),
+                None => s.state == TaskState::Waiting,
+            })
+            .map(|(id, s)| (s.priority, *id))
+            .collect();
+        candidates.sort_by_key(|(priority, id)| (-priority, *id));
+        let mut running: Vec<(i32, i32)> = tasks
+            .iter()
+            .filter(|(_, s)| s.state == TaskState::Running)
+            .map(|(id, s)| (s.priority, *id))
+            .collect();
+
+        // 有配额的用户在存储中的累计token
+        let mut stored_tokens = BTreeMap::new();
+        for (_, task_id) in &candidates {
+            let Some(owner) = tasks.get(task_id).and_then(|s| s.owner.as_deref()) else {
+                continue;
+            };
+            if stored_tokens.contains_key(owner) || self.quota(owner).is_none() {
+                continue;
+            }
+            if let Some(tokens) = self.stored_tokens(owner).await {
+                stored_tokens.insert(owner.to_string(), tokens);
+            }
+        }
+
+        for (priority, task_id) in candidates {
+            if self
+                .quota_violation_in(&tasks, &stored_tokens, task_id, true)
+                .is_some()
+            {
+                continue;
+            }
+            // 快照之后任务可能已经变化，或者正被其他操作锁住，留到下一次调度
+            let Some(mut context) = self.tasks.try_lock(task_id) else {
+                continue;
+            };
+            if tasks.get(&task_id).map(|s| &s.state) != Some(&context.state)
+                || context.blocked.is_some()
+            {
+                continue;
+            }
+            if running.len() >= policy.max_running {
+                if policy
+                    .preempt_priority
+                    .is_none_or(|threshold| priority < threshold)
+                {
+                    break;
+                }
+                // 抢占优先级最低的运行中任务，正在执行job的任务本次不抢占
+                let mut victims: Vec<(usize, i32, i32)> = running
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (p, _))| *p < priority)
+                    .map(|(index, (p, id))| (index, *p, *id))
+                    .collect();
+                victims.sort_by_key(|(_, p, id)| (*p, -id));
+                let Some((index, mut victim_context)) =
+                    victims.into_iter().find_map(|(index, _, id)| {
+                        if self.tasks.is_executing(id) {
+                            return None;
+                        }
+                        let context = self.tasks.try_lock(id)?;
+                        (context.state == TaskState::Running).then_some((index, context))
+                    })
+                else {
+                    break;
+                };
+                let (_, victim) = running.remove(index);
+                let transition = Transition::check(
+                    victim,
+                    &victim_context.state,
+                    TaskState::Pending,
+                    Actor::Scheduler,
+                    format!("Task preempted by task {}", task_id),
+                )?;
+                victim_context.state = TaskState::Pending;
+                victim_context.preempted_by = Some(task_id);
+                victim_context
+                    .execution_history
+                    .push(transition.reason.clone());
+                transitions.push(transition);
+                if let Some(snapshot) = tasks.get_mut(&victim) {
+                    snapshot.state = TaskState::Pending;
+                }
+                report.preempted.push((victim, task_id));
+            }
+            let resumed = context.preempted_by.take().is_some();
+            let reason = if resumed {
+                "Task resumed by scheduler"
+            } else {
+                "Task started by scheduler"
+            };
+            let transition = Transition::check(
+                task_id,
+                &context.state,
+                TaskState::Running,
+                Actor::Scheduler,
+                reason,
+            )?;
+            context.state = TaskState::Running;
+            context.execution_history.push(reason.to_string());
+            transitions.push(transition);
+            if let Some(snapshot) = tasks.get_mut(&task_id) {
+                snapshot.state = TaskState::Running;
+            }
+            running.push((priority, task_id));
+            report.started.push(task_id);
+        }
+
+        for transition in transitions {
+            self.save_transition(transition).await?;
+        }
+        Ok(report)
+    }
+}
+
+/// 在后台定期调度，维护模式和备用节点上跳过
+pub fn run_scheduler(
+    engine: Arc<TaskEngine>,
+    policy: SchedulePolicy,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let clock = engine.clock();
+        loop {
+            if !engine.is_maintenance() && !engine.is_standby() {
+                if let Err(e) = engine.schedule(&policy).await.map_err(|e| e.to_string()) {
+                    tracing::warn!("scheduling failed: {}", e);
+                }
+            }
+            clock.sleep(interval).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn critical_task_preempts_and_releases() {
+        let mut engine = TaskEngine::new();
+        for (id, priority) in [(1, 0), (2, 5), (3, 1)] {
+            engine.init(id, format!("batch {id}")).await.unwrap();
+            engine.set_priority(id, priority).await.unwrap();
+        }
+        let policy = SchedulePolicy {
+            max_running: 2,
+            preempt_priority: Some(10),
+        };
+        let report = engine.schedule(&policy).await.unwrap();
+        assert_eq!(report.started, vec![2, 3]);
+        assert_eq!(engine.get_state(1).await.unwrap(), TaskState::Waiting);
+
+        engine.init(4, "incident".to_string()).await.unwrap();
+        engine.set_priority(4, 10).await.unwrap();
+        let report = engine.schedule(&policy).await.unwrap();
+        assert_eq!(report.preempted, vec![(3, 4)]);
+        assert_eq!(report.started, vec![4]);
+        assert_eq!(engine.get_state(3).await.unwrap(), TaskState::Pending);
+
+        // 抢占者结束后被抢占的任务优先于更低优先级的等待任务恢复
+        engine.finish(4).await.unwrap();
+        let report = engine.schedule(&policy).await.unwrap();
+        assert_eq!(report.started, vec![3]);
+        assert_eq!(engine.get_state(1).await.unwrap(), TaskState::Waiting);
+    }
+
+    #[tokio::test]
+    async fn schedule_does_not_wait_for_locked_tasks() {
+        let mut engine = TaskEngine::new();
+        engine.init(1, "busy".to_string()).await.unwrap();
+        engine.init(2, "queued".to_string()).await.unwrap();
+        engine.start(1).await.unwrap();
+        let policy = SchedulePolicy::default();
+
+        // 任务1正在执行job
+        let held = engine.tasks.lock(1).await.unwrap();
+        let report = tokio::time::timeout(Duration::from_secs(1), engine.schedule(&policy))
+            .await
+            .expect("schedule blocked behind a running job")
+            .unwrap();
+        assert_eq!(report.started, vec![2]);
+        drop(held);
+    }
+}
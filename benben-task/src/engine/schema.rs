@@ -0,0 +1,115 @@
+//! 工作流声明的任务输入、输出 JSON Schema。
+//!
+//! 启动任务时校验输入，完成任务时校验最终输出，不符合的输出会拒绝完成，
+//! 下游系统可以依赖输出的结构。
+
+use jsonschema::Validator;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::entities::workflow;
+
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("invalid {0} schema: {1}")]
+    InvalidSchema(&'static str, String),
+    #[error("{0} is not valid json: {1}")]
+    InvalidJson(&'static str, serde_json::Error),
+    #[error("{kind} does not match schema: {}", .1.join("; "), kind = .0)]
+    Mismatch(&'static str, Vec<String>),
+    #[error("workflow declares an output schema but the task has no output")]
+    MissingOutput,
+}
+
+/// 工作流的输入输出校验器，未声明的部分不校验
+#[derive(Default)]
+pub struct TaskSchemas {
+    input: Option<Validator>,
+    output: Option<Validator>,
+}
+
+impl TaskSchemas {
+    pub fn from_workflow(workflow: &workflow::Model) -> Result<Self, SchemaError> {
+        Ok(Self {
+            input: compile("input", workflow.input_schema.as_deref())?,
+            output: compile("output", workflow.output_schema.as_deref())?,
+        })
+    }
+
+    pub fn has_output(&self) -> bool {
+        self.output.is_some()
+    }
+
+    /// 校验任务输入，声明了输入schema时输入必须是json
+    pub fn validate_input(&self, input: &str) -> Result<(), SchemaError> {
+        let Some(validator) = &self.input else {
+            return Ok(());
+        };
+        let input =
+            serde_json::from_str(input).map_err(|e| SchemaError::InvalidJson("input", e))?;
+        validate("input", validator, &input)
+    }
+
+    /// 校验任务最终输出
+    pub fn validate_output(&self, output: &Value) -> Result<(), SchemaError> {
+        match &self.output {
+            Some(validator) => validate("output", validator, output),
+            None => Ok(()),
+        }
+    }
+}
+
+fn compile(kind: &'static str, schema: Option<&str>) -> Result<Option<Validator>, SchemaError> {
+    let Some(schema) = schema.filter(|schema| !schema.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let schema: Value = serde_json::from_str(schema)
+        .map_err(|e| SchemaError::InvalidSchema(kind, e.to_string()))?;
+    jsonschema::validator_for(&schema)
+        .map(Some)
+        .map_err(|e| SchemaError::InvalidSchema(kind, e.to_string()))
+}
+
+fn validate(kind: &'static str, validator: &Validator, value: &Value) -> Result<(), SchemaError> {
+    let errors: Vec<String> = validator
+        .iter_errors(value)
+        .map(|e| format!("{} at '{}'", e, e.instance_path))
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(SchemaError::Mismatch(kind, errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::fixtures;
+
+    #[test]
+    fn validates_input_and_output() {
+        let workflow = workflow::Model {
+            input_schema: Some(r#"{"type":"object","required":["repo"]}"#.to_string()),
+            output_schema: Some(
+                r#"{"type":"object","properties":{"score":{"type":"number"}},"required":["score"]}"#
+                    .to_string(),
+            ),
+            ..fixtures::workflow("wf")
+        };
+        let schemas = TaskSchemas::from_workflow(&workflow).unwrap();
+
+        assert!(schemas.validate_input(r#"{"repo":"rig"}"#).is_ok());
+        assert!(matches!(
+            schemas.validate_input("free text"),
+            Err(SchemaError::InvalidJson(..))
+        ));
+        assert!(schemas
+            .validate_output(&serde_json::json!({"score": 0.9}))
+            .is_ok());
+        assert!(matches!(
+            schemas.validate_output(&serde_json::json!({"score": "high"})),
+            Err(SchemaError::Mismatch(..))
+        ));
+    }
+}
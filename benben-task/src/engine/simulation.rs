@@ -0,0 +1,328 @@
+//! 使用桩agent模拟执行工作流。
+//!
+//! 单元测试和CI中没有模型服务，工作流的逻辑却需要覆盖：job的依赖、计划表的状态、重试、
+//! 后处理链和执行记录。[StubAgents] 为每个agent code绑定一个桩，可以是按顺序返回的固定回复，
+//! 也可以是根据prompt计算回复的闭包。引擎设置了桩之后，[TaskEngine::execute_job] 调用桩代替
+//! 模型，其余的处理都走正常的路径；[TaskEngine::simulate] 按 pid 依赖依次执行工作流的全部job：
+//!
+//! ```ignore
+//! let stubs = StubAgents::new()
+//!     .with_replies("planner", ["计划已生成"])
+//!     .with_handler("writer", |call| Ok(format!("draft for {}", call.prompt)));
+//! let engine = TaskEngine::new().with_stub_agents(Arc::new(stubs));
+//! let task_id = engine.create_task("orders".into()).await?;
+//! let report = engine.simulate(task_id, workflow, &jobs, 2).await?;
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use super::plan::PlanStatus;
+use super::preview::order_jobs;
+use super::{TaskEngine, TaskEngineError, TaskState};
+use crate::entities::{job, workflow};
+
+/// 一次对桩agent的调用
+#[derive(Debug, Clone, PartialEq)]
+pub struct StubCall {
+    pub task_id: i32,
+    pub job_id: i32,
+    pub agent: String,
+    /// 渲染后的prompt
+    pub prompt: String,
+    /// 这个job的第几次尝试，从1开始
+    pub attempt: i32,
+}
+
+type StubHandler = Arc<dyn Fn(&StubCall) -> Result<String, String> + Send + Sync>;
+
+enum Stub {
+    /// 按顺序返回的回复，最后一条重复使用
+    Replies(VecDeque<Result<String, String>>),
+    Handler(StubHandler),
+}
+
+/// 按agent code绑定的桩
+#[derive(Default)]
+pub struct StubAgents {
+    stubs: Mutex<HashMap<String, Stub>>,
+    calls: Mutex<Vec<StubCall>>,
+    /// agent配置的工具，审批策略按工具判断时使用
+    tools: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl fmt::Debug for StubAgents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stubs = self.stubs.lock().unwrap_or_else(|e| e.into_inner());
+        f.debug_struct("StubAgents")
+            .field("agents", &stubs.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl StubAgents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// agent按顺序返回这些回复
+    pub fn with_replies<S: Into<String>>(
+        self,
+        agent: impl Into<String>,
+        replies: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.with_results(agent, replies.into_iter().map(|r| Ok(r.into())))
+    }
+
+    /// agent按顺序返回这些结果，`Err` 作为执行失败，用于覆盖重试
+    pub fn with_results(
+        self,
+        agent: impl Into<String>,
+        results: impl IntoIterator<Item = Result<String, String>>,
+    ) -> Self {
+        self.bind(agent.into(), Stub::Replies(results.into_iter().collect()));
+        self
+    }
+
+    /// agent的回复由闭包计算
+    pub fn with_handler(
+        self,
+        agent: impl Into<String>,
+        handler: impl Fn(&StubCall) -> Result<String, String> + Send + Sync + 'static,
+    ) -> Self {
+        self.bind(agent.into(), Stub::Handler(Arc::new(handler)));
+        self
+    }
+
+    /// agent配置了这些工具，代替真实agent的配置参与审批策略的判断
+    pub fn with_tools<S: Into<String>>(
+        self,
+        agent: impl Into<String>,
+        tools: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.tools
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(agent.into(), tools.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// agent配置的工具，没有声明时为 None
+    pub fn tools(&self, agent: &str) -> Option<Vec<String>> {
+        self.tools
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(agent)
+            .cloned()
+    }
+
+    fn bind(&self, agent: String, stub: Stub) {
+        self.stubs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(agent, stub);
+    }
+
+    /// 到目前为止的调用，按调用顺序排列
+    pub fn calls(&self) -> Vec<StubCall> {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// 调用agent的桩，没有绑定桩的agent返回错误
+    pub fn call(&self, call: StubCall) -> Result<String, String> {
+        self.calls
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(call.clone());
+        let handler = {
+            let mut stubs = self.stubs.lock().unwrap_or_else(|e| e.into_inner());
+            match stubs.get_mut(&call.agent) {
+                None => return Err(format!("agent {} has no stub", call.agent)),
+                Some(Stub::Replies(replies)) => {
+                    let reply = if replies.len() > 1 {
+                        replies.pop_front()
+                    } else {
+                        replies.front().cloned()
+                    };
+                    return reply.unwrap_or_else(|| {
+                        Err(format!("agent {} has no stub replies", call.agent))
+                    });
+                }
+                Some(Stub::Handler(handler)) => handler.clone(),
+            }
+        };
+        // 闭包在锁外调用，闭包中可以再读取调用记录
+        handler(&call)
+    }
+}
+
+/// 模拟执行工作流的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationReport {
+    /// 执行成功的job的输出
+    pub outputs: BTreeMap<i32, String>,
+    /// 每个执行过的job的尝试次数
+    pub attempts: BTreeMap<i32, usize>,
+    /// 重试用尽仍然失败的job，以及最后一次的错误
+    pub failed: BTreeMap<i32, String>,
+    /// 上游job失败或者依赖成环而没有执行的job
+    pub skipped: Vec<i32>,
+    /// 任务最后的状态
+    pub state: TaskState,
+}
+
+impl TaskEngine {
+    /// 设置桩agent，之后执行的job调用桩代替模型
+    pub fn with_stub_agents(mut self, stubs: Arc<StubAgents>) -> Self {
+        self.stubs = Some(stubs);
+        self
+    }
+
+    /// 调用job的agent的桩
+    pub(crate) fn call_stub(
+        &self,
+        stubs: &StubAgents,
+        task_id: i32,
+        job: &job::Model,
+        prompt: &str,
+        attempt: i32,
+    ) -> Result<String, TaskEngineError> {
+        let call = StubCall {
+            task_id,
+            job_id: job.id,
+            agent: job.code.clone().unwrap_or_default(),
+            prompt: prompt.to_string(),
+            attempt,
+        };
+        stubs
+            .call(call)
+            .map_err(|e| format!("Job {} failed: {}", job.id, e).into())
+    }
+
+    /// 关联工作流并启动等待中的任务，按依赖顺序执行所有job，失败的job最多重试 `max_retries` 次。
+    /// 所有job成功时完成任务，否则跳过失败job的下游job并停止任务
+    pub async fn simulate(
+        &self,
+        task_id: i32,
+        workflow: workflow::Model,
+        jobs: &[job::Model],
+        max_retries: usize,
+    ) -> Result<SimulationReport, TaskEngineError> {
+        self.attach_workflow(task_id, workflow).await?;
+        self.start(task_id).await?;
+
+        let (ordered, unreachable) = order_jobs(jobs);
+        let mut report = SimulationReport {
+            outputs: BTreeMap::new(),
+            attempts: BTreeMap::new(),
+            failed: BTreeMap::new(),
+            skipped: unreachable,
+            state: TaskState::Running,
+        };
+        let mut blocked: HashSet<i32> = report.skipped.iter().copied().collect();
+        for job in ordered {
+            if job.pid.is_some_and(|pid| blocked.contains(&pid)) {
+                blocked.insert(job.id);
+                report.skipped.push(job.id);
+                self.set_plan_job_status(task_id, job.id, PlanStatus::Skipped)
+                    .await?;
+                continue;
+            }
+            let mut last_error = String::new();
+            for attempt in 1..=max_retries + 1 {
+                report.attempts.insert(job.id, attempt);
+                match self.execute_job(task_id, job.clone()).await {
+                    Ok(output) => {
+                        report.outputs.insert(job.id, output);
+                        break;
+                    }
+                    Err(e) => last_error = e.to_string(),
+                }
+            }
+            if !report.outputs.contains_key(&job.id) {
+                blocked.insert(job.id);
+                report.failed.insert(job.id, last_error);
+                self.set_plan_job_status(task_id, job.id, PlanStatus::Failure)
+                    .await?;
+            }
+        }
+
+        if blocked.is_empty() {
+            self.finish(task_id).await?;
+        } else {
+            self.stop(task_id).await?;
+        }
+        report.state = self.get_state(task_id).await?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::fixtures::{self, agent_job, downstream};
+    use crate::engine::store::MemoryStore;
+
+    #[tokio::test]
+    async fn workflow_runs_against_stub_agents() {
+        let stubs = Arc::new(
+            StubAgents::new()
+                .with_replies("planner", ["plan: count orders"])
+                .with_results("writer", [Err("timeout".to_string()), Ok("42".into())])
+                .with_handler("reviewer", |call| Err(format!("rejected {}", call.prompt))),
+        );
+        let engine = TaskEngine::new()
+            .with_store(Arc::new(MemoryStore::new()))
+            .with_stub_agents(stubs.clone());
+        let task_id = engine.create_task("orders".into()).await.unwrap();
+        let workflow = workflow::Model {
+            plan: Some(
+                r#"[{"step": "plan", "job_id": 1}, {"step": "write", "job_id": 2},
+                {"step": "review", "job_id": 3}, {"step": "publish", "job_id": 4}]"#
+                    .into(),
+            ),
+            ..fixtures::workflow("w1")
+        };
+        let jobs = vec![
+            agent_job(1, "planner", "plan {{ task.input }}"),
+            downstream(1, agent_job(2, "writer", "{{ prev.output }}")),
+            downstream(1, agent_job(3, "reviewer", "review")),
+            downstream(3, agent_job(4, "writer", "publish")),
+        ];
+
+        let report = engine.simulate(task_id, workflow, &jobs, 1).await.unwrap();
+        assert_eq!(report.outputs.get(&2).map(String::as_str), Some("42"));
+        assert_eq!(report.attempts, BTreeMap::from([(1, 1), (2, 2), (3, 2)]));
+        assert!(report.failed[&3].contains("rejected review"));
+        assert_eq!(report.skipped, vec![4]);
+        assert_eq!(report.state, TaskState::Stopped);
+
+        let calls = stubs.calls();
+        assert_eq!(calls[0].prompt, "plan orders");
+        assert_eq!(
+            (calls[2].prompt.as_str(), calls[2].attempt),
+            ("plan: count orders", 2)
+        );
+        let plan: Vec<PlanStatus> = engine
+            .get_plan(task_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|step| step.status)
+            .collect();
+        assert_eq!(
+            plan,
+            vec![
+                PlanStatus::Success,
+                PlanStatus::Success,
+                PlanStatus::Failure,
+                PlanStatus::Skipped
+            ]
+        );
+        assert_eq!(
+            engine.get_job_runs(task_id, Some(2)).await.unwrap().len(),
+            2
+        );
+    }
+}
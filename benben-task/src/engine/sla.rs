@@ -0,0 +1,298 @@
+//! 工作流声明的SLA。
+//!
+//! 工作流可以声明任务的完成时限以及人工审批的答复时限，例如30分钟内完成、4小时内
+//! 答复审批。引擎为每个任务计时，耗时达到时限的一定比例时发出预警事件，超过时限时
+//! 发出违约事件并记录下来，用于用量报表。
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use super::clock::{Clock, SystemClock};
+use super::TaskEngine;
+
+/// SLA 配置，保存在 workflow.sla 中，时限为秒
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlaPolicy {
+    /// 任务从关联工作流开始到完成的时限
+    #[serde(default)]
+    pub finish_within_secs: Option<u64>,
+    /// 单次人工审批从发起到答复的时限
+    #[serde(default)]
+    pub approval_within_secs: Option<u64>,
+    /// 耗时达到时限的这个比例时发出预警
+    #[serde(default = "default_warn_ratio")]
+    pub warn_ratio: f64,
+}
+
+fn default_warn_ratio() -> f64 {
+    0.8
+}
+
+impl SlaPolicy {
+    pub fn parse(sla: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(sla)
+    }
+
+    fn limit(&self, kind: SlaKind) -> Option<Duration> {
+        match kind {
+            SlaKind::Finish => self.finish_within_secs,
+            SlaKind::Approval => self.approval_within_secs,
+        }
+        .map(Duration::from_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlaKind {
+    Finish,
+    Approval,
+}
+
+/// 一次SLA违约
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlaBreach {
+    pub task_id: i32,
+    pub kind: SlaKind,
+    pub limit: Duration,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlaEvent {
+    Warning {
+        task_id: i32,
+        kind: SlaKind,
+        limit: Duration,
+        elapsed: Duration,
+    },
+    Breach(SlaBreach),
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Timer {
+    started: Option<Instant>,
+    warned: bool,
+    breached: bool,
+}
+
+impl Timer {
+    fn start(now: Instant) -> Self {
+        Self {
+            started: Some(now),
+            ..Default::default()
+        }
+    }
+}
+
+struct TaskTimers {
+    policy: SlaPolicy,
+    timers: HashMap<SlaKind, Timer>,
+}
+
+/// 任务的SLA计时
+pub struct SlaMonitor {
+    tasks: Mutex<HashMap<i32, TaskTimers>>,
+    breaches: Mutex<Vec<SlaBreach>>,
+    events: broadcast::Sender<SlaEvent>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for SlaMonitor {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+impl SlaMonitor {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            tasks: Mutex::new(HashMap::new()),
+            breaches: Mutex::new(Vec::new()),
+            events,
+            clock,
+        }
+    }
+
+    /// 订阅预警和违约事件
+    pub fn subscribe(&self) -> broadcast::Receiver<SlaEvent> {
+        self.events.subscribe()
+    }
+
+    /// 开始为任务计时
+    pub fn track(&self, task_id: i32, policy: SlaPolicy) {
+        let mut timers = HashMap::new();
+        timers.insert(SlaKind::Finish, Timer::start(self.clock.now()));
+        self.lock_tasks()
+            .insert(task_id, TaskTimers { policy, timers });
+    }
+
+    /// 开始一个计时，例如发起人工审批
+    pub fn start(&self, task_id: i32, kind: SlaKind) {
+        if let Some(task) = self.lock_tasks().get_mut(&task_id) {
+            task.timers
+                .entry(kind)
+                .or_insert_with(|| Timer::start(self.clock.now()));
+        }
+    }
+
+    /// 结束一个计时，超时结束的计时记为违约
+    pub fn stop(&self, task_id: i32, kind: SlaKind) {
+        let mut tasks = self.lock_tasks();
+        let Some(task) = tasks.get_mut(&task_id) else {
+            return;
+        };
+        let Some(timer) = task.timers.remove(&kind) else {
+            return;
+        };
+        if let (Some(limit), Some(started)) = (task.policy.limit(kind), timer.started) {
+            let elapsed = self.clock.now().duration_since(started);
+            if elapsed > limit && !timer.breached {
+                self.breach(SlaBreach {
+                    task_id,
+                    kind,
+                    limit,
+                    elapsed,
+                });
+            }
+        }
+        if kind == SlaKind::Finish {
+            tasks.remove(&task_id);
+        }
+    }
+
+    /// 不再为任务计时，例如任务被取消
+    pub fn untrack(&self, task_id: i32) {
+        self.lock_tasks().remove(&task_id);
+    }
+
+    /// 检查所有计时，发出预警以及违约事件
+    pub fn check(&self) {
+        let now = self.clock.now();
+        let mut tasks = self.lock_tasks();
+        for (task_id, task) in tasks.iter_mut() {
+            for (kind, timer) in task.timers.iter_mut() {
+                let (Some(limit), Some(started)) = (task.policy.limit(*kind), timer.started) else {
+                    continue;
+                };
+                let elapsed = now.duration_since(started);
+                if elapsed > limit {
+                    if !timer.breached {
+                        timer.breached = true;
+                        self.breach(SlaBreach {
+                            task_id: *task_id,
+                            kind: *kind,
+                            limit,
+                            elapsed,
+                        });
+                    }
+                } else if !timer.warned && elapsed >= limit.mul_f64(task.policy.warn_ratio) {
+                    timer.warned = true;
+                    tracing::warn!(
+                        "task {} {:?} sla at {:?} of {:?}",
+                        task_id,
+                        kind,
+                        elapsed,
+                        limit
+                    );
+                    let _ = self.events.send(SlaEvent::Warning {
+                        task_id: *task_id,
+                        kind: *kind,
+                        limit,
+                        elapsed,
+                    });
+                }
+            }
+        }
+    }
+
+    /// 所有记录的违约
+    pub fn breaches(&self) -> Vec<SlaBreach> {
+        self.breaches
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn breach(&self, breach: SlaBreach) {
+        tracing::warn!("sla breach: {:?}", breach);
+        self.breaches
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(breach.clone());
+        let _ = self.events.send(SlaEvent::Breach(breach));
+    }
+
+    fn lock_tasks(&self) -> std::sync::MutexGuard<'_, HashMap<i32, TaskTimers>> {
+        self.tasks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl TaskEngine {
+    /// SLA 计时
+    pub fn sla(&self) -> &SlaMonitor {
+        &self.sla
+    }
+
+    /// 指定任务的SLA违约记录
+    pub fn sla_breaches(&self, task_id: i32) -> Vec<SlaBreach> {
+        self.sla
+            .breaches()
+            .into_iter()
+            .filter(|breach| breach.task_id == task_id)
+            .collect()
+    }
+
+    /// 按固定间隔在后台检查SLA
+    pub fn spawn_sla_checks(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.sla.check();
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn warns_then_records_breach() {
+        let monitor = SlaMonitor::default();
+        let mut events = monitor.subscribe();
+        monitor.track(
+            1,
+            SlaPolicy {
+                finish_within_secs: Some(100),
+                approval_within_secs: None,
+                warn_ratio: 0.8,
+            },
+        );
+
+        tokio::time::advance(Duration::from_secs(85)).await;
+        monitor.check();
+        assert!(matches!(
+            events.try_recv(),
+            Ok(SlaEvent::Warning {
+                kind: SlaKind::Finish,
+                ..
+            })
+        ));
+
+        tokio::time::advance(Duration::from_secs(20)).await;
+        monitor.check();
+        monitor.stop(1, SlaKind::Finish);
+        assert!(matches!(events.try_recv(), Ok(SlaEvent::Breach(_))));
+        assert_eq!(monitor.breaches().len(), 1);
+    }
+}
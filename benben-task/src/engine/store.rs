@@ -0,0 +1,900 @@
+//! 任务数据的存储后端。
+//!
+//! 引擎通过 [TaskStore] 读写任务、计划、tool_log、任务事件、状态变更记录和job运行记录，默认使用 [SeaOrmStore]
+//! 包装引擎的数据库连接。嵌入引擎的程序不想部署数据库时可以使用 [MemoryStore]，也可以
+//! 实现 [TaskStore] 接入自己的存储。工作流和job的定义仍然从数据库读取。
+//!
+//! 需要同时修改任务、计划和 tool_log 的地方通过 [TaskStore::write_batch] 一次写入，
+//! [SeaOrmStore] 在一个事务中执行，中途失败时全部回滚。
+//!
+//! 任务和计划带有乐观锁版本：覆盖时只有存储中的版本与读取时相同才写入，并把版本加1，
+//! 否则返回 [StoreError::Conflict]。多个引擎实例或者直接写库的管理页面同时修改同一个任务时，
+//! 后写入的一方得到冲突错误，而不是静默覆盖对方的修改，重新读取后再修改即可。
+//! 写入成功时返回新的版本，引擎把它记在内存中的任务上，下一次写入使用这个版本，
+//! 而不是写入前重新读取的版本，否则其他实例在两次写入之间的修改会被覆盖。
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    IntoActiveModel, NotSet, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+use thiserror::Error;
+
+use super::events::TaskEvent;
+use crate::entities::{job_run, owner_usage, plan, task, task_event, task_transition, tool_log};
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("DbError: {0}")]
+    Db(#[from] DbErr),
+    #[error("JsonError: {0}")]
+    Json(#[from] serde_json::Error),
+    /// 记录在读取之后被修改过
+    #[error("{entity} {id} was modified concurrently, expected version {expected}")]
+    Conflict {
+        entity: &'static str,
+        id: i32,
+        expected: i32,
+    },
+}
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+/// 需要原子写入的一组修改，按 任务、计划、tool_log、job运行记录 的顺序写入
+#[derive(Debug, Clone, Default)]
+pub struct StoreBatch {
+    pub task: Option<task::Model>,
+    /// id为0的计划插入，否则覆盖
+    pub plans: Vec<plan::Model>,
+    pub tool_logs: Vec<tool_log::Model>,
+    /// id为0的记录插入，否则覆盖
+    pub job_runs: Vec<job_run::Model>,
+}
+
+impl StoreBatch {
+    pub fn is_empty(&self) -> bool {
+        self.task.is_none()
+            && self.plans.is_empty()
+            && self.tool_logs.is_empty()
+            && self.job_runs.is_empty()
+    }
+}
+
+/// 任务数据的存储
+pub trait TaskStore: Send + Sync {
+    fn load_task(&self, task_id: i32) -> BoxFuture<'_, StoreResult<Option<task::Model>>>;
+
+    /// 所有任务，按id排序
+    fn list_tasks(&self) -> BoxFuture<'_, StoreResult<Vec<task::Model>>>;
+
+    /// 存储中最大的任务id，没有任务时为0
+    fn max_task_id(&self) -> BoxFuture<'_, StoreResult<i32>>;
+
+    /// 插入新任务，id已经被使用时不写入并返回 false
+    fn insert_task(&self, task: task::Model) -> BoxFuture<'_, StoreResult<bool>>;
+
+    /// 按id插入或覆盖任务，覆盖时检查并递增版本，返回存储中任务的新版本
+    fn save_task(&self, task: task::Model) -> BoxFuture<'_, StoreResult<i32>>;
+
+    /// 属于同一次执行的计划，按id排序
+    fn load_plans(&self, planid: String) -> BoxFuture<'_, StoreResult<Vec<plan::Model>>>;
+
+    /// 保存计划，id为0时插入新的计划，否则检查并递增版本后覆盖，返回计划的id
+    fn save_plan(&self, plan: plan::Model) -> BoxFuture<'_, StoreResult<i32>>;
+
+    /// 任务的 tool_log，按写入顺序排列
+    fn load_tool_logs(&self, task_id: i32) -> BoxFuture<'_, StoreResult<Vec<tool_log::Model>>>;
+
+    /// 追加一条 tool_log，忽略传入的id，返回新的id
+    fn append_tool_log(&self, log: tool_log::Model) -> BoxFuture<'_, StoreResult<i32>>;
+
+    /// 任务的事件，按写入顺序排列
+    fn load_events(&self, task_id: i32) -> BoxFuture<'_, StoreResult<Vec<TaskEvent>>>;
+
+    fn append_event(&self, task_id: i32, event: TaskEvent) -> BoxFuture<'_, StoreResult<()>>;
+
+    /// 任务的job运行记录，按id排序
+    fn load_job_runs(&self, task_id: i32) -> BoxFuture<'_, StoreResult<Vec<job_run::Model>>>;
+
+    /// 保存job运行记录，id为0时插入新的记录，返回记录的id
+    fn save_job_run(&self, run: job_run::Model) -> BoxFuture<'_, StoreResult<i32>>;
+
+    /// 任务的状态变更记录，按写入顺序排列
+    fn load_transitions(
+        &self,
+        task_id: i32,
+    ) -> BoxFuture<'_, StoreResult<Vec<task_transition::Model>>>;
+
+    /// 追加一条状态变更记录，忽略传入的id
+    fn append_transition(
+        &self,
+        transition: task_transition::Model,
+    ) -> BoxFuture<'_, StoreResult<()>>;
+
+    /// 给用户累计的token用量加上 `tokens`
+    fn add_owner_tokens(&self, owner: String, tokens: u64) -> BoxFuture<'_, StoreResult<()>>;
+
+    /// 用户所有任务累计的token用量，包括已经结束或者不在内存中的任务
+    fn owner_tokens(&self, owner: String) -> BoxFuture<'_, StoreResult<u64>>;
+
+    /// 写入一组修改，返回任务的新版本，批次中没有任务时为 None。
+    /// 默认逐条写入，不保证原子性，支持事务的存储应当覆盖这个方法
+    fn write_batch(&self, batch: StoreBatch) -> BoxFuture<'_, StoreResult<Option<i32>>> {
+        async move {
+            let mut version = None;
+            if let Some(task) = batch.task {
+                version = Some(self.save_task(task).await?);
+            }
+            for plan in batch.plans {
+                self.save_plan(plan).await?;
+            }
+            for log in batch.tool_logs {
+                self.append_tool_log(log).await?;
+            }
+            for run in batch.job_runs {
+                self.save_job_run(run).await?;
+            }
+            Ok(version)
+        }
+        .boxed()
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// 基于 SeaORM 的存储
+#[derive(Debug, Clone)]
+pub struct SeaOrmStore {
+    db: Arc<DatabaseConnection>,
+}
+
+impl SeaOrmStore {
+    pub fn new(db: Arc<DatabaseConnection>) -> Self {
+        Self { db }
+    }
+}
+
+async fn save_task_on<C: ConnectionTrait>(db: &C, task: task::Model) -> StoreResult<i32> {
+    let (id, expected) = (task.id, task.version);
+    let exists = task::Entity::find_by_id(id).one(db).await?.is_some();
+    let active = task.into_active_model();
+    if !exists {
+        task::Entity::insert(active)
+            .exec_without_returning(db)
+            .await?;
+        return Ok(expected);
+    }
+    let mut active = active.reset_all();
+    active.version = Set(expected + 1);
+    // 租约只由 worker 维护，覆盖任务时保留存储中的值，见 [crate::worker]
+    active.worker_id = NotSet;
+    active.heartbeat_at = NotSet;
+    let result = task::Entity::update_many()
+        .set(active)
+        .filter(task::Column::Id.eq(id))
+        .filter(task::Column::Version.eq(expected))
+        .exec(db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(StoreError::Conflict {
+            entity: "Task",
+            id,
+            expected,
+        });
+    }
+    Ok(expected + 1)
+}
+
+async fn save_plan_on<C: ConnectionTrait>(db: &C, plan: plan::Model) -> StoreResult<i32> {
+    let (id, expected) = (plan.id, plan.version);
+    let mut active = plan.into_active_model();
+    if id == 0 {
+        active.id = NotSet;
+        return Ok(active.reset_all().insert(db).await?.id);
+    }
+    active.version = Set(expected + 1);
+    let result = plan::Entity::update_many()
+        .set(active.reset_all())
+        .filter(plan::Column::Id.eq(id))
+        .filter(plan::Column::Version.eq(expected))
+        .exec(db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(StoreError::Conflict {
+            entity: "Plan",
+            id,
+            expected,
+        });
+    }
+    Ok(id)
+}
+
+async fn save_job_run_on<C: ConnectionTrait>(db: &C, run: job_run::Model) -> StoreResult<i32> {
+    let id = run.id;
+    let mut active = run.into_active_model();
+    if id == 0 {
+        active.id = NotSet;
+        Ok(active.reset_all().insert(db).await?.id)
+    } else {
+        active.reset_all().update(db).await?;
+        Ok(id)
+    }
+}
+
+async fn append_tool_log_on<C: ConnectionTrait>(db: &C, log: tool_log::Model) -> StoreResult<i32> {
+    let mut active = log.into_active_model().reset_all();
+    active.id = NotSet;
+    Ok(active.insert(db).await?.id)
+}
+
+impl TaskStore for SeaOrmStore {
+    fn load_task(&self, task_id: i32) -> BoxFuture<'_, StoreResult<Option<task::Model>>> {
+        async move {
+            Ok(task::Entity::find_by_id(task_id)
+                .one(self.db.as_ref())
+                .await?)
+        }
+        .boxed()
+    }
+
+    fn list_tasks(&self) -> BoxFuture<'_, StoreResult<Vec<task::Model>>> {
+        async move {
+            Ok(task::Entity::find()
+                .order_by_asc(task::Column::Id)
+                .all(self.db.as_ref())
+                .await?)
+        }
+        .boxed()
+    }
+
+    fn max_task_id(&self) -> BoxFuture<'_, StoreResult<i32>> {
+        async move {
+            let max: Option<Option<i32>> = task::Entity::find()
+                .select_only()
+                .column_as(task::Column::Id.max(), "id")
+                .into_tuple()
+                .one(self.db.as_ref())
+                .await?;
+            Ok(max.flatten().unwrap_or_default())
+        }
+        .boxed()
+    }
+
+    fn insert_task(&self, task: task::Model) -> BoxFuture<'_, StoreResult<bool>> {
+        async move {
+            let db = self.db.as_ref();
+            let id = task.id;
+            match task::Entity::insert(task.into_active_model())
+                .exec_without_returning(db)
+                .await
+            {
+                Ok(_) => Ok(true),
+                // 主键冲突时id已经被使用，其他错误原样返回
+                Err(e) => match task::Entity::find_by_id(id).one(db).await? {
+                    Some(_) => Ok(false),
+                    None => Err(e.into()),
+                },
+            }
+        }
+        .boxed()
+    }
+
+    fn save_task(&self, task: task::Model) -> BoxFuture<'_, StoreResult<i32>> {
+        async move { save_task_on(self.db.as_ref(), task).await }.boxed()
+    }
+
+    fn load_plans(&self, planid: String) -> BoxFuture<'_, StoreResult<Vec<plan::Model>>> {
+        async move {
+            Ok(plan::Entity::find()
+                .filter(plan::Column::Planid.eq(planid))
+                .order_by_asc(plan::Column::Id)
+                .all(self.db.as_ref())
+                .await?)
+        }
+        .boxed()
+    }
+
+    fn save_plan(&self, plan: plan::Model) -> BoxFuture<'_, StoreResult<i32>> {
+        async move { save_plan_on(self.db.as_ref(), plan).await }.boxed()
+    }
+
+    fn load_tool_logs(&self, task_id: i32) -> BoxFuture<'_, StoreResult<Vec<tool_log::Model>>> {
+        async move {
+            Ok(tool_log::Entity::find()
+                .filter(tool_log::Column::Taskid.eq(task_id))
+                .order_by_asc(tool_log::Column::Id)
+                .all(self.db.as_ref())
+                .await?)
+        }
+        .boxed()
+    }
+
+    fn append_tool_log(&self, log: tool_log::Model) -> BoxFuture<'_, StoreResult<i32>> {
+        async move { append_tool_log_on(self.db.as_ref(), log).await }.boxed()
+    }
+
+    fn load_events(&self, task_id: i32) -> BoxFuture<'_, StoreResult<Vec<TaskEvent>>> {
+        async move {
+            let rows = task_event::Entity::find()
+                .filter(task_event::Column::Taskid.eq(task_id))
+                .order_by_asc(task_event::Column::Id)
+                .all(self.db.as_ref())
+                .await?;
+            Ok(rows
+                .iter()
+                .map(|row| serde_json::from_str(&row.payload))
+                .collect::<Result<_, _>>()?)
+        }
+        .boxed()
+    }
+
+    fn append_event(&self, task_id: i32, event: TaskEvent) -> BoxFuture<'_, StoreResult<()>> {
+        async move {
+            task_event::ActiveModel {
+                id: NotSet,
+                taskid: sea_orm::Set(task_id),
+                payload: sea_orm::Set(serde_json::to_string(&event)?),
+                created_at: sea_orm::Set(now_millis()),
+            }
+            .insert(self.db.as_ref())
+            .await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn load_job_runs(&self, task_id: i32) -> BoxFuture<'_, StoreResult<Vec<job_run::Model>>> {
+        async move {
+            Ok(job_run::Entity::find()
+                .filter(job_run::Column::Taskid.eq(task_id))
+                .order_by_asc(job_run::Column::Id)
+                .all(self.db.as_ref())
+                .await?)
+        }
+        .boxed()
+    }
+
+    fn save_job_run(&self, run: job_run::Model) -> BoxFuture<'_, StoreResult<i32>> {
+        async move { save_job_run_on(self.db.as_ref(), run).await }.boxed()
+    }
+
+    fn load_transitions(
+        &self,
+        task_id: i32,
+    ) -> BoxFuture<'_, StoreResult<Vec<task_transition::Model>>> {
+        async move {
+            Ok(task_transition::Entity::find()
+                .filter(task_transition::Column::Taskid.eq(task_id))
+                .order_by_asc(task_transition::Column::Id)
+                .all(self.db.as_ref())
+                .await?)
+        }
+        .boxed()
+    }
+
+    fn append_transition(
+        &self,
+        transition: task_transition::Model,
+    ) -> BoxFuture<'_, StoreResult<()>> {
+        async move {
+            let mut active = transition.into_active_model().reset_all();
+            active.id = NotSet;
+            active.insert(self.db.as_ref()).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn add_owner_tokens(&self, owner: String, tokens: u64) -> BoxFuture<'_, StoreResult<()>> {
+        async move {
+            let db = self.db.as_ref();
+            let increment = || {
+                owner_usage::Entity::update_many()
+                    .col_expr(
+                        owner_usage::Column::Tokens,
+                        Expr::col(owner_usage::Column::Tokens).add(tokens as i64),
+                    )
+                    .filter(owner_usage::Column::OwnerId.eq(owner.clone()))
+                    .exec(db)
+            };
+            if increment().await?.rows_affected > 0 {
+                return Ok(());
+            }
+            let inserted = owner_usage::ActiveModel {
+                owner_id: Set(owner.clone()),
+                tokens: Set(tokens as i64),
+            }
+            .insert(db)
+            .await;
+            // 另一个进程先插入了这个用户时主键冲突，改为累加
+            if let Err(e) = inserted {
+                if increment().await?.rows_affected == 0 {
+                    return Err(e.into());
+                }
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn owner_tokens(&self, owner: String) -> BoxFuture<'_, StoreResult<u64>> {
+        async move {
+            let usage = owner_usage::Entity::find_by_id(owner)
+                .one(self.db.as_ref())
+                .await?;
+            Ok(usage.map_or(0, |u| u.tokens.max(0) as u64))
+        }
+        .boxed()
+    }
+
+    fn write_batch(&self, batch: StoreBatch) -> BoxFuture<'_, StoreResult<Option<i32>>> {
+        async move {
+            // 出错时 txn 被丢弃，事务自动回滚
+            let txn = self.db.begin().await?;
+            let mut version = None;
+            if let Some(task) = batch.task {
+                version = Some(save_task_on(&txn, task).await?);
+            }
+            for plan in batch.plans {
+                save_plan_on(&txn, plan).await?;
+            }
+            for log in batch.tool_logs {
+                append_tool_log_on(&txn, log).await?;
+            }
+            for run in batch.job_runs {
+                save_job_run_on(&txn, run).await?;
+            }
+            txn.commit().await?;
+            Ok(version)
+        }
+        .boxed()
+    }
+}
+
+/// 内存中的存储，进程退出后数据丢失，适合测试和不需要持久化的嵌入场景
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    inner: Mutex<MemoryData>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryData {
+    tasks: BTreeMap<i32, task::Model>,
+    plans: BTreeMap<i32, plan::Model>,
+    tool_logs: Vec<tool_log::Model>,
+    events: Vec<(i32, TaskEvent)>,
+    job_runs: BTreeMap<i32, job_run::Model>,
+    transitions: Vec<task_transition::Model>,
+    owner_tokens: BTreeMap<String, u64>,
+}
+
+impl MemoryData {
+    fn check_task(&self, task: &task::Model) -> StoreResult<()> {
+        match self.tasks.get(&task.id) {
+            Some(stored) if stored.version != task.version => Err(StoreError::Conflict {
+                entity: "Task",
+                id: task.id,
+                expected: task.version,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn save_task(&mut self, mut task: task::Model) -> i32 {
+        if self.tasks.contains_key(&task.id) {
+            task.version += 1;
+        }
+        let version = task.version;
+        self.tasks.insert(task.id, task);
+        version
+    }
+
+    fn check_plan(&self, plan: &plan::Model) -> StoreResult<()> {
+        match self.plans.get(&plan.id) {
+            Some(stored) if plan.id != 0 && stored.version != plan.version => {
+                Err(StoreError::Conflict {
+                    entity: "Plan",
+                    id: plan.id,
+                    expected: plan.version,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn save_plan(&mut self, mut plan: plan::Model) -> i32 {
+        if plan.id == 0 {
+            plan.id = self.plans.keys().next_back().map_or(1, |id| id + 1);
+        } else if self.plans.contains_key(&plan.id) {
+            plan.version += 1;
+        }
+        let id = plan.id;
+        self.plans.insert(id, plan);
+        id
+    }
+
+    fn append_tool_log(&mut self, mut log: tool_log::Model) -> i32 {
+        log.id = self.tool_logs.len() as i32 + 1;
+        self.tool_logs.push(log);
+        self.tool_logs.len() as i32
+    }
+
+    fn save_job_run(&mut self, mut run: job_run::Model) -> i32 {
+        if run.id == 0 {
+            run.id = self.job_runs.keys().next_back().map_or(1, |id| id + 1);
+        }
+        let id = run.id;
+        self.job_runs.insert(id, run);
+        id
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut MemoryData) -> T) -> T {
+        f(&mut self.inner.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl TaskStore for MemoryStore {
+    fn load_task(&self, task_id: i32) -> BoxFuture<'_, StoreResult<Option<task::Model>>> {
+        let task = self.with(|data| data.tasks.get(&task_id).cloned());
+        async move { Ok(task) }.boxed()
+    }
+
+    fn list_tasks(&self) -> BoxFuture<'_, StoreResult<Vec<task::Model>>> {
+        let tasks = self.with(|data| data.tasks.values().cloned().collect());
+        async move { Ok(tasks) }.boxed()
+    }
+
+    fn max_task_id(&self) -> BoxFuture<'_, StoreResult<i32>> {
+        let max = self.with(|data| data.tasks.keys().next_back().copied().unwrap_or_default());
+        async move { Ok(max) }.boxed()
+    }
+
+    fn insert_task(&self, task: task::Model) -> BoxFuture<'_, StoreResult<bool>> {
+        let inserted = self.with(|data| match data.tasks.entry(task.id) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(task);
+                true
+            }
+        });
+        async move { Ok(inserted) }.boxed()
+    }
+
+    fn save_task(&self, task: task::Model) -> BoxFuture<'_, StoreResult<i32>> {
+        let result = self.with(|data| {
+            data.check_task(&task)?;
+            Ok(data.save_task(task))
+        });
+        async move { result }.boxed()
+    }
+
+    fn load_plans(&self, planid: String) -> BoxFuture<'_, StoreResult<Vec<plan::Model>>> {
+        let plans = self.with(|data| {
+            data.plans
+                .values()
+                .filter(|plan| plan.planid.as_deref() == Some(planid.as_str()))
+                .cloned()
+                .collect()
+        });
+        async move { Ok(plans) }.boxed()
+    }
+
+    fn save_plan(&self, plan: plan::Model) -> BoxFuture<'_, StoreResult<i32>> {
+        let result = self.with(|data| {
+            data.check_plan(&plan)?;
+            Ok(data.save_plan(plan))
+        });
+        async move { result }.boxed()
+    }
+
+    fn load_tool_logs(&self, task_id: i32) -> BoxFuture<'_, StoreResult<Vec<tool_log::Model>>> {
+        let logs = self.with(|data| {
+            data.tool_logs
+                .iter()
+                .filter(|log| log.taskid == Some(task_id))
+                .cloned()
+                .collect()
+        });
+        async move { Ok(logs) }.boxed()
+    }
+
+    fn append_tool_log(&self, log: tool_log::Model) -> BoxFuture<'_, StoreResult<i32>> {
+        let id = self.with(|data| data.append_tool_log(log));
+        async move { Ok(id) }.boxed()
+    }
+
+    fn load_events(&self, task_id: i32) -> BoxFuture<'_, StoreResult<Vec<TaskEvent>>> {
+        let events = self.with(|data| {
+            data.events
+                .iter()
+                .filter(|(id, _)| *id == task_id)
+                .map(|(_, event)| event.clone())
+                .collect()
+        });
+        async move { Ok(events) }.boxed()
+    }
+
+    fn append_event(&self, task_id: i32, event: TaskEvent) -> BoxFuture<'_, StoreResult<()>> {
+        self.with(|data| data.events.push((task_id, event)));
+        async move { Ok(()) }.boxed()
+    }
+
+    fn load_job_runs(&self, task_id: i32) -> BoxFuture<'_, StoreResult<Vec<job_run::Model>>> {
+        let runs = self.with(|data| {
+            data.job_runs
+                .values()
+                .filter(|run| run.taskid == task_id)
+                .cloned()
+                .collect()
+        });
+        async move { Ok(runs) }.boxed()
+    }
+
+    fn save_job_run(&self, run: job_run::Model) -> BoxFuture<'_, StoreResult<i32>> {
+        let id = self.with(|data| data.save_job_run(run));
+        async move { Ok(id) }.boxed()
+    }
+
+    fn load_transitions(
+        &self,
+        task_id: i32,
+    ) -> BoxFuture<'_, StoreResult<Vec<task_transition::Model>>> {
+        let transitions = self.with(|data| {
+            data.transitions
+                .iter()
+                .filter(|t| t.taskid == task_id)
+                .cloned()
+                .collect()
+        });
+        async move { Ok(transitions) }.boxed()
+    }
+
+    fn append_transition(
+        &self,
+        mut transition: task_transition::Model,
+    ) -> BoxFuture<'_, StoreResult<()>> {
+        self.with(|data| {
+            transition.id = data.transitions.len() as i32 + 1;
+            data.transitions.push(transition);
+        });
+        async move { Ok(()) }.boxed()
+    }
+
+    fn add_owner_tokens(&self, owner: String, tokens: u64) -> BoxFuture<'_, StoreResult<()>> {
+        self.with(|data| *data.owner_tokens.entry(owner).or_default() += tokens);
+        async move { Ok(()) }.boxed()
+    }
+
+    fn owner_tokens(&self, owner: String) -> BoxFuture<'_, StoreResult<u64>> {
+        let tokens = self.with(|data| data.owner_tokens.get(&owner).copied().unwrap_or_default());
+        async move { Ok(tokens) }.boxed()
+    }
+
+    fn write_batch(&self, batch: StoreBatch) -> BoxFuture<'_, StoreResult<Option<i32>>> {
+        // 持有同一把锁写入，其他读取者看不到写了一半的修改，版本冲突时什么都不写
+        let result = self.with(|data| {
+            if let Some(task) = &batch.task {
+                data.check_task(task)?;
+            }
+            for plan in &batch.plans {
+                data.check_plan(plan)?;
+            }
+            let version = batch.task.map(|task| data.save_task(task));
+            for plan in batch.plans {
+                data.save_plan(plan);
+            }
+            for log in batch.tool_logs {
+                data.append_tool_log(log);
+            }
+            for run in batch.job_runs {
+                data.save_job_run(run);
+            }
+            Ok(version)
+        });
+        async move { result }.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::attempts::RunStatus;
+    use crate::engine::{TaskEngine, TaskEngineError, TaskState};
+    use rig::completion::Usage;
+
+    #[tokio::test]
+    async fn engine_runs_on_memory_store() {
+        let store = Arc::new(MemoryStore::new());
+        let mut engine = TaskEngine::new().with_store(store.clone());
+        engine.init(1, "orders".to_string()).await.unwrap();
+        engine.start(1).await.unwrap();
+
+        let task = store.load_task(1).await.unwrap().unwrap();
+        assert_eq!(task.state.as_deref(), Some("running"));
+        assert_eq!(
+            store.load_events(1).await.unwrap(),
+            vec![TaskEvent::StateChanged {
+                task_id: 1,
+                state: TaskState::Running
+            }]
+        );
+
+        let recovered = TaskEngine::new().with_store(store);
+        assert_eq!(recovered.recover_from_db().await.unwrap(), 1);
+        assert_eq!(recovered.get_state(1).await.unwrap(), TaskState::Running);
+    }
+
+    #[tokio::test]
+    async fn recovered_tasks_keep_usage_and_progress() {
+        let store = Arc::new(MemoryStore::new());
+        let mut engine = TaskEngine::new().with_store(store.clone());
+        engine.init(1, "orders".to_string()).await.unwrap();
+        engine.start(1).await.unwrap();
+        let usage = Usage {
+            input_tokens: 1_000,
+            output_tokens: 200,
+            total_tokens: 1_200,
+            cached_input_tokens: 0,
+        };
+        for _ in 0..2 {
+            engine
+                .record_model_usage(1, "deepseek", "deepseek-chat", usage)
+                .await
+                .unwrap();
+        }
+        for status in [RunStatus::Success, RunStatus::Failure, RunStatus::Success] {
+            let run = engine.begin_run(1, 7, None, None).await.unwrap();
+            engine.finish_run(run, status, None).await.unwrap();
+        }
+
+        let recovered = TaskEngine::new().with_store(store);
+        assert_eq!(recovered.recover_from_db().await.unwrap(), 1);
+        let recovered_usage = recovered.get_usage(1).await.unwrap();
+        assert_eq!(recovered_usage.input_tokens, 2_000);
+        assert_eq!(recovered_usage.output_tokens, 400);
+        assert_eq!(
+            recovered.get_cost(1).await.unwrap(),
+            engine.get_cost(1).await.unwrap()
+        );
+        assert_eq!(recovered.tasks.lock(1).await.unwrap().step, 2);
+    }
+
+    #[tokio::test]
+    async fn new_tasks_do_not_reuse_stored_ids() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        crate::migrate::create_schema(&db).await.unwrap();
+        let stores: [Arc<dyn TaskStore>; 2] = [
+            Arc::new(MemoryStore::new()),
+            Arc::new(SeaOrmStore::new(Arc::new(db))),
+        ];
+        for store in stores {
+            // 两个实例共用存储，各自内存中的id计数互不知道
+            let first = TaskEngine::new().with_store(store.clone());
+            let second = TaskEngine::new().with_store(store.clone());
+            let a = first.create_task("a".into()).await.unwrap();
+            let b = second.create_task("b".into()).await.unwrap();
+            let c = first.create_task("c".into()).await.unwrap();
+            assert_eq!((a, b, c), (1, 2, 3));
+            assert_eq!(store.max_task_id().await.unwrap(), 3);
+
+            let stored = store.load_task(b).await.unwrap().unwrap();
+            assert!(!store.insert_task(stored).await.unwrap());
+            assert_eq!(
+                store.load_task(b).await.unwrap().unwrap().input.as_deref(),
+                Some("b")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn owner_tokens_accumulate() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        crate::migrate::create_schema(&db).await.unwrap();
+        let stores: [Arc<dyn TaskStore>; 2] = [
+            Arc::new(MemoryStore::new()),
+            Arc::new(SeaOrmStore::new(Arc::new(db))),
+        ];
+        for store in stores {
+            assert_eq!(store.owner_tokens("alice".into()).await.unwrap(), 0);
+            store.add_owner_tokens("alice".into(), 30).await.unwrap();
+            store.add_owner_tokens("alice".into(), 12).await.unwrap();
+            store.add_owner_tokens("bob".into(), 5).await.unwrap();
+            assert_eq!(store.owner_tokens("alice".into()).await.unwrap(), 42);
+        }
+    }
+
+    #[tokio::test]
+    async fn stale_overwrites_are_rejected() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        crate::migrate::create_schema(&db).await.unwrap();
+        let stores: [Arc<dyn TaskStore>; 2] = [
+            Arc::new(MemoryStore::new()),
+            Arc::new(SeaOrmStore::new(Arc::new(db))),
+        ];
+        for store in stores {
+            let engine = TaskEngine::new().with_store(store.clone());
+            let task_id = engine.create_task("orders".into()).await.unwrap();
+
+            // 两个实例读取同一个任务，后写入的一方基于过期的版本
+            let mut first = store.load_task(task_id).await.unwrap().unwrap();
+            let mut second = first.clone();
+            first.output = Some("12".into());
+            assert_eq!(store.save_task(first).await.unwrap(), 1);
+            second.output = Some("13".into());
+            let err = store.save_task(second).await.unwrap_err();
+            assert!(matches!(err, StoreError::Conflict { expected: 0, .. }));
+
+            let stored = store.load_task(task_id).await.unwrap().unwrap();
+            assert_eq!(stored.output.as_deref(), Some("12"));
+            assert_eq!(stored.version, 1);
+            let err = store
+                .write_batch(StoreBatch {
+                    task: Some(task::Model {
+                        version: 0,
+                        ..stored
+                    }),
+                    ..Default::default()
+                })
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                TaskEngineError::from(err),
+                TaskEngineError::Conflict(_)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn engine_writes_with_the_version_it_last_saw() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        crate::migrate::create_schema(&db).await.unwrap();
+        let stores: [Arc<dyn TaskStore>; 2] = [
+            Arc::new(MemoryStore::new()),
+            Arc::new(SeaOrmStore::new(Arc::new(db))),
+        ];
+        for store in stores {
+            let engine = TaskEngine::new().with_store(store.clone());
+            let task_id = engine.create_task("orders".into()).await.unwrap();
+            engine.start(task_id).await.unwrap();
+            engine.set_priority(task_id, 3).await.unwrap();
+            let stored = store.load_task(task_id).await.unwrap().unwrap();
+            assert_eq!(stored.priority, 3);
+
+            // 另一个实例在引擎两次写入之间修改了任务，引擎不再重新读取后覆盖
+            store
+                .save_task(task::Model {
+                    output: Some("other".into()),
+                    ..stored
+                })
+                .await
+                .unwrap();
+            let err = engine.set_priority(task_id, 5).await.unwrap_err();
+            assert!(matches!(err, TaskEngineError::Conflict(_)));
+            assert!(matches!(
+                engine.finish(task_id).await.unwrap_err(),
+                TaskEngineError::Conflict(_)
+            ));
+            let stored = store.load_task(task_id).await.unwrap().unwrap();
+            assert_eq!(stored.output.as_deref(), Some("other"));
+            assert_eq!(stored.priority, 3);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! 子工作流job。
+//!
+//! 类型为 [SUBWORKFLOW_JOB_TYPE] 的job在 action 中声明 [SubWorkflowSpec]，执行时派生一个
+//! 子任务运行另一个工作流，子任务的输入由父任务渲染的模板生成。等待模式下父任务被
+//! [BlockReason::ChildTask] 阻塞，子任务完成后解除阻塞，再次执行该job时得到子任务的输出；
+//! 取消父任务时一并取消等待中的子任务。不等待模式下job立即完成，子任务独立运行。
+
+use serde::{Deserialize, Serialize};
+
+use super::queue::BlockReason;
+use super::schema::TaskSchemas;
+use super::sla::SlaPolicy;
+use super::{versioning, TaskContext, TaskEngine, TaskEngineError, TaskState};
+
+/// 子工作流job的类型
+pub const SUBWORKFLOW_JOB_TYPE: &str = "subworkflow";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubWorkflowMode {
+    /// 父任务等待子任务完成，使用子任务的输出
+    #[default]
+    Wait,
+    /// 派生子任务后继续执行，子任务不随父任务取消
+    Detach,
+}
+
+/// 子工作流job的 action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubWorkflowSpec {
+    pub workflow_id: String,
+    /// 子任务的输入，支持prompt模板，默认使用父任务的输入
+    #[serde(default = "default_input")]
+    pub input: String,
+    #[serde(default)]
+    pub mode: SubWorkflowMode,
+}
+
+fn default_input() -> String {
+    "{{ task.input }}".to_string()
+}
+
+impl SubWorkflowSpec {
+    pub fn parse(action: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(action)
+    }
+}
+
+/// 子任务的输出：结构化输出，没有时为最后一个job的输出
+pub fn child_output(context: &TaskContext) -> String {
+    context
+        .task
+        .as_ref()
+        .and_then(|t| t.output.clone())
+        .or_else(|| {
+            context
+                .last_output
+                .as_ref()
+                .map(|(_, output)| output.clone())
+        })
+        .unwrap_or_default()
+}
+
+impl TaskEngine {
+    /// 创建子任务的上下文，固定子工作流当前的版本，子任务与父任务属于同一个用户。
+    /// 子任务的id不与内存和存储中已有的任务重复
+    pub(crate) async fn child_context(
+        &self,
+        parent_id: i32,
+        spec: &SubWorkflowSpec,
+        input: String,
+        owner: Option<String>,
+    ) -> Result<(i32, TaskContext), TaskEngineError> {
+        let db = self.db().ok_or_else(|| {
+            TaskEngineError::Unavailable("Sub-workflow jobs require a database".to_string())
+        })?;
+        let snapshot = versioning::ensure_version(db.as_ref(), &spec.workflow_id).await?;
+        TaskSchemas::from_workflow(&snapshot.workflow)?.validate_input(&input)?;
+
+        let mut context = self.build_context(0, input, owner)?;
+        if spec.mode == SubWorkflowMode::Wait {
+            context.parent = Some(parent_id);
+        }
+        let sla = snapshot
+            .workflow
+            .sla
+            .as_deref()
+            .map(SlaPolicy::parse)
+            .transpose()?;
+        if let Some(task) = context.task.as_mut() {
+            task.wid = snapshot.workflow.id.parse().ok();
+            task.wversion = Some(snapshot.version());
+        }
+        context.execution_history.push(format!(
+            "Child task of task {} pinned to workflow {} version {}",
+            parent_id,
+            snapshot.workflow.id,
+            snapshot.version()
+        ));
+        context.pinned_jobs = snapshot.jobs.into_iter().map(|job| (job.id, job)).collect();
+        context.workflow = Some(snapshot.workflow);
+        let child_id = self.insert_new_task(&mut context).await?;
+        if let Some(sla) = sla {
+            self.sla.track(child_id, sla);
+        }
+        Ok((child_id, context))
+    }
+
+    /// 任务的子任务，按派生它们的job排序
+    pub async fn child_tasks(&self, task_id: i32) -> Result<Vec<i32>, TaskEngineError> {
+        let context = self
+            .tasks
+            .lock(task_id)
+            .await
+            .ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+        let mut children: Vec<(i32, i32)> = context
+            .children
+            .iter()
+            .map(|(job_id, child_id)| (*job_id, *child_id))
+            .collect();
+        children.sort();
+        Ok(children.into_iter().map(|(_, child_id)| child_id).collect())
+    }
+
+    /// 等待子任务的父任务
+    pub async fn parent_task(&self, task_id: i32) -> Option<i32> {
+        self.tasks.lock(task_id).await?.parent
+    }
+
+    /// 子任务结束后解除父任务的阻塞，父任务再次执行job时读取子任务的结果
+    pub(crate) async fn release_parent(&self, child_id: i32) {
+        let Some(parent_id) = self.parent_task(child_id).await else {
+            return;
+        };
+        if let Some(mut parent) = self.tasks.lock(parent_id).await {
+            if matches!(parent.blocked, Some(BlockReason::ChildTask { child_id: blocked, .. }) if blocked == child_id)
+            {
+                parent.blocked = None;
+                parent
+                    .execution_history
+                    .push(format!("Child task {} ended", child_id));
+            }
+        }
+    }
+
+    /// 取消任务等待中的子任务
+    pub(crate) async fn cancel_children(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        let children = self
+            .tasks
+            .collect(|id, c| {
+                let waiting = c.parent == Some(task_id)
+                    && !matches!(
+                        c.state,
+                        TaskState::Finished | TaskState::Cancelled | TaskState::Stopped
+                    );
+                waiting.then_some(id)
+            })
+            .await;
+        for child_id in children {
+            Box::pin(self.cancel(child_id)).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::fixtures;
+    use crate::entities::{job, workflow};
+    use crate::migrate::create_schema;
+    use sea_orm::ActiveValue::Set;
+    use sea_orm::{ActiveModelTrait, Database};
+    use std::sync::Arc;
+
+    fn subworkflow_job(mode: &str) -> job::Model {
+        let action = format!(
+            r#"{{"workflow_id":"2","input":"child of {{{{ task.input }}}}","mode":"{mode}"}}"#
+        );
+        job::Model {
+            r#type: Some(SUBWORKFLOW_JOB_TYPE.to_string()),
+            ..fixtures::job(10, &action)
+        }
+    }
+
+    #[tokio::test]
+    async fn parent_waits_for_child_output() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&db).await.unwrap();
+        workflow::ActiveModel {
+            id: Set("2".to_string()),
+            version: Set(1),
+            deleted: Set(false),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        let mut engine = TaskEngine::new().with_db(Arc::new(db));
+        engine.init(1, "report".to_string()).await.unwrap();
+        engine.start(1).await.unwrap();
+
+        assert!(engine
+            .execute_job(1, subworkflow_job("wait"))
+            .await
+            .is_err());
+        let child = engine.child_tasks(1).await.unwrap()[0];
+        assert_eq!(engine.parent_task(child).await, Some(1));
+        let child_task = engine.query_task(child).await.unwrap().unwrap();
+        assert_eq!(child_task.input.as_deref(), Some("child of report"));
+        assert_eq!(child_task.wversion, Some(1));
+        assert!(engine.inspect_queue().await.iter().any(|entry| entry.task_id == 1
+            && matches!(entry.reasons[..], [BlockReason::ChildTask { child_id, .. }] if child_id == child)));
+
+        engine.start(child).await.unwrap();
+        engine
+            .finish_with_output(child, serde_json::json!({"summary": "done"}))
+            .await
+            .unwrap();
+        let output = engine
+            .execute_job(1, subworkflow_job("wait"))
+            .await
+            .unwrap();
+        assert_eq!(output, r#"{"summary":"done"}"#);
+
+        // 取消父任务时等待中的子任务一并取消，不等待的子任务继续运行
+        let mut wait = subworkflow_job("wait");
+        wait.id = 11;
+        assert!(engine.execute_job(1, wait).await.is_err());
+        let mut detach = subworkflow_job("detach");
+        detach.id = 12;
+        assert!(engine.execute_job(1, detach).await.is_ok());
+        let children = engine.child_tasks(1).await.unwrap();
+        engine.cancel(1).await.unwrap();
+        assert_eq!(
+            engine.get_state(children[1]).await.unwrap(),
+            TaskState::Cancelled
+        );
+        assert_eq!(
+            engine.get_state(children[2]).await.unwrap(),
+            TaskState::Waiting
+        );
+    }
+}
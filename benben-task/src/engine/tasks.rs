@@ -0,0 +1,318 @@
+//! 任务上下文表。
+//!
+//! 每个任务的上下文有自己的锁，表本身按任务id分片，分片锁只在查找、插入和删除时短暂持有，
+//! 不会跨越 await。执行job时只锁住这个任务，其他任务的状态查询和控制操作不需要等待；
+//! 同一个任务的job由单独的job锁 [TaskMap::lock_job] 串行执行，读写存储时不持有任务锁。
+//! 同一个任务写入存储时持有写入锁 [TaskMap::lock_write]，按顺序使用上一次写入返回的版本。
+//!
+//! 读取多个任务时使用 [TaskMap::collect]，逐个短暂加锁。持有一个任务的锁时不能再等待其他任务的锁。
+//!
+//! 释放任务锁时把状态、优先级、所属用户和用量写入任务的快照 [TaskSnapshot]。调度、用户配额
+//! 和指标这类只需要概况的读取使用 [TaskMap::snapshots]，不会等待正在执行job的任务；
+//! 调度按快照选出要修改的任务后用 [TaskMap::try_lock] 逐个锁住，正被锁住的任务留到下一次调度。
+//!
+//! 每个任务还记录最近一次进展的时间，不需要加锁就能读取，看门狗据此判断执行中的任务是否卡住，
+//! 见 [super::watchdog]。
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicI32, AtomicI64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use super::tenant::owner_of;
+use super::{TaskContext, TaskState};
+
+const SHARDS: usize = 16;
+
+/// 任务最近一次释放锁时的概况
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TaskSnapshot {
+    pub state: TaskState,
+    pub priority: i32,
+    pub owner: Option<String>,
+    /// 累计的输入输出token
+    pub tokens: u64,
+    pub blocked: bool,
+    pub preempted_by: Option<i32>,
+}
+
+impl From<&TaskContext> for TaskSnapshot {
+    fn from(context: &TaskContext) -> Self {
+        Self {
+            state: context.state.clone(),
+            priority: context.task.as_ref().map_or(0, |t| t.priority),
+            owner: owner_of(context).map(str::to_string),
+            tokens: context.usage.input_tokens + context.usage.output_tokens,
+            blocked: context.blocked.is_some(),
+            preempted_by: context.preempted_by,
+        }
+    }
+}
+
+/// 持有任务锁期间对上下文的访问，释放时更新任务的快照
+pub struct TaskGuard {
+    context: OwnedMutexGuard<TaskContext>,
+    snapshot: Arc<RwLock<TaskSnapshot>>,
+}
+
+impl Deref for TaskGuard {
+    type Target = TaskContext;
+
+    fn deref(&self) -> &TaskContext {
+        &self.context
+    }
+}
+
+impl DerefMut for TaskGuard {
+    fn deref_mut(&mut self) -> &mut TaskContext {
+        &mut self.context
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        // 仍然持有任务锁，快照与上下文一致
+        *self.snapshot.write().unwrap_or_else(|e| e.into_inner()) =
+            TaskSnapshot::from(&*self.context);
+    }
+}
+
+struct Slot {
+    context: Arc<Mutex<TaskContext>>,
+    /// 串行执行同一个任务的job
+    job: Arc<Mutex<()>>,
+    /// 串行写入同一个任务的存储记录
+    write: Arc<Mutex<()>>,
+    snapshot: Arc<RwLock<TaskSnapshot>>,
+    /// 最近一次进展的unix时间，毫秒，0 表示还没有记录
+    progress: Arc<AtomicI64>,
+}
+
+impl Slot {
+    fn new(context: TaskContext) -> Self {
+        Self {
+            snapshot: Arc::new(RwLock::new(TaskSnapshot::from(&context))),
+            context: Arc::new(Mutex::new(context)),
+            job: Arc::default(),
+            write: Arc::default(),
+            progress: Arc::new(AtomicI64::new(0)),
+        }
+    }
+
+    fn guard(&self) -> (Arc<Mutex<TaskContext>>, Arc<RwLock<TaskSnapshot>>) {
+        (self.context.clone(), self.snapshot.clone())
+    }
+}
+
+type Shard = RwLock<HashMap<i32, Slot>>;
+
+pub(crate) struct TaskMap {
+    shards: Vec<Shard>,
+    /// 不小于所有已分配和已插入的任务id
+    next_id: AtomicI32,
+}
+
+impl TaskMap {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| RwLock::default()).collect(),
+            next_id: AtomicI32::new(1),
+        }
+    }
+
+    fn shard(&self, task_id: i32) -> &Shard {
+        &self.shards[task_id.rem_euclid(SHARDS as i32) as usize]
+    }
+
+    fn read(&self, task_id: i32) -> RwLockReadGuard<'_, HashMap<i32, Slot>> {
+        self.shard(task_id)
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self, task_id: i32) -> RwLockWriteGuard<'_, HashMap<i32, Slot>> {
+        self.shard(task_id)
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 锁住任务的上下文，任务不存在时返回 None
+    pub async fn lock(&self, task_id: i32) -> Option<TaskGuard> {
+        let (task, snapshot) = self.read(task_id).get(&task_id)?.guard();
+        Some(TaskGuard {
+            context: task.lock_owned().await,
+            snapshot,
+        })
+    }
+
+    /// 不等待地锁住任务的上下文，任务不存在或者锁正被持有（例如正在执行job）时返回 None
+    pub fn try_lock(&self, task_id: i32) -> Option<TaskGuard> {
+        let (task, snapshot) = self.read(task_id).get(&task_id)?.guard();
+        Some(TaskGuard {
+            context: task.try_lock_owned().ok()?,
+            snapshot,
+        })
+    }
+
+    /// 锁住任务的job执行，同一个任务同一时间只执行一个job，不影响其他操作锁住任务的上下文
+    pub async fn lock_job(&self, task_id: i32) -> Option<OwnedMutexGuard<()>> {
+        let job = self.read(task_id).get(&task_id)?.job.clone();
+        Some(job.lock_owned().await)
+    }
+
+    /// 锁住任务的存储写入，持有期间可以短暂锁住任务的上下文，反过来不行
+    pub async fn lock_write(&self, task_id: i32) -> Option<OwnedMutexGuard<()>> {
+        let write = self.read(task_id).get(&task_id)?.write.clone();
+        Some(write.lock_owned().await)
+    }
+
+    /// 任务是否正在执行job
+    pub fn is_executing(&self, task_id: i32) -> bool {
+        self.read(task_id)
+            .get(&task_id)
+            .is_some_and(|slot| slot.job.try_lock().is_err())
+    }
+
+    /// 所有任务最近一次释放锁时的快照，按id排列，不等待任务锁
+    pub fn snapshots(&self) -> BTreeMap<i32, TaskSnapshot> {
+        self.shards
+            .iter()
+            .flat_map(|shard| {
+                shard
+                    .read()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .iter()
+                    .map(|(id, slot)| {
+                        let snapshot = slot.snapshot.read().unwrap_or_else(|e| e.into_inner());
+                        (*id, snapshot.clone())
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// 记录任务在 `now_millis` 有进展
+    pub fn touch(&self, task_id: i32, now_millis: i64) {
+        if let Some(slot) = self.read(task_id).get(&task_id) {
+            slot.progress.fetch_max(now_millis, Ordering::SeqCst);
+        }
+    }
+
+    /// 任务最近一次进展的时间，还没有记录时为 None
+    pub fn last_progress(&self, task_id: i32) -> Option<i64> {
+        let progress = self
+            .read(task_id)
+            .get(&task_id)?
+            .progress
+            .load(Ordering::SeqCst);
+        (progress > 0).then_some(progress)
+    }
+
+    pub fn contains(&self, task_id: i32) -> bool {
+        self.read(task_id).contains_key(&task_id)
+    }
+
+    /// 插入任务，替换同id的任务
+    pub fn insert(&self, task_id: i32, context: TaskContext) {
+        self.next_id
+            .fetch_max(task_id.saturating_add(1), Ordering::SeqCst);
+        self.write(task_id).insert(task_id, Slot::new(context));
+    }
+
+    /// 插入任务，已经有同id的任务时不插入并返回 false
+    pub fn insert_new(&self, task_id: i32, context: TaskContext) -> bool {
+        let mut shard = self.write(task_id);
+        if shard.contains_key(&task_id) {
+            return false;
+        }
+        self.next_id
+            .fetch_max(task_id.saturating_add(1), Ordering::SeqCst);
+        shard.insert(task_id, Slot::new(context));
+        true
+    }
+
+    pub fn remove(&self, task_id: i32) -> bool {
+        self.write(task_id).remove(&task_id).is_some()
+    }
+
+    /// 所有任务的id，从小到大排列
+    pub fn ids(&self) -> Vec<i32> {
+        let mut ids: Vec<i32> = self
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                shard
+                    .read()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .keys()
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// 分配一个新的任务id，不小于 `at_least`，也不与已经分配或插入的id重复
+    pub fn allocate_id(&self, at_least: i32) -> i32 {
+        self.next_id.fetch_max(at_least, Ordering::SeqCst);
+        self.next_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// 按id顺序逐个锁住任务并收集结果，同一时刻只持有一个任务的锁
+    pub async fn collect<T>(&self, mut f: impl FnMut(i32, &TaskContext) -> Option<T>) -> Vec<T> {
+        let mut items = Vec::new();
+        for task_id in self.ids() {
+            if let Some(context) = self.lock(task_id).await {
+                items.extend(f(task_id, &context));
+            }
+        }
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::engine::{TaskEngine, TaskState};
+
+    #[tokio::test]
+    async fn locked_task_does_not_block_others() {
+        let mut engine = TaskEngine::new();
+        engine.init(1, "slow".to_string()).await.unwrap();
+        engine.init(2, "fast".to_string()).await.unwrap();
+
+        let held = engine.tasks.lock(1).await.unwrap();
+        let state = tokio::time::timeout(Duration::from_secs(1), async {
+            engine.start(2).await.unwrap();
+            engine.get_state(2).await.unwrap()
+        })
+        .await
+        .expect("task 2 blocked behind task 1");
+        assert_eq!(state, TaskState::Running);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), engine.get_state(1))
+                .await
+                .is_err()
+        );
+        drop(held);
+        assert_eq!(engine.get_state(1).await.unwrap(), TaskState::Waiting);
+
+        // 执行job只占用job锁，任务的上下文仍然可以读写
+        let job = engine.tasks.lock_job(2).await.unwrap();
+        assert!(engine.tasks.is_executing(2));
+        assert!(engine.tasks.try_lock(2).is_some());
+        drop(job);
+        assert!(!engine.tasks.is_executing(2));
+
+        let map = TaskMap::new();
+        map.insert(5, engine.tasks.lock(2).await.unwrap().clone());
+        assert_eq!(map.allocate_id(1), 6);
+        assert_eq!(map.allocate_id(10), 10);
+        assert_eq!(map.ids(), vec![5]);
+    }
+}
@@ -0,0 +1,119 @@
+//! job action 中的prompt模板。
+//!
+//! prompt 使用 minijinja 语法，可以引用任务输入、上一步的输出、计划步骤以及任务变量：
+//!
+//! ```text
+//! 根据 {{ task.input }} 以及上一步的结论 {{ prev.output }}，完成第 {{ plan.step }} 步。
+//! 代码目录：{{ workspace.repo }}
+//! ```
+//!
+//! 引用不存在的变量会报错，避免拼写错误的变量被静默渲染为空字符串。
+
+use std::collections::HashMap;
+
+use minijinja::{Environment, UndefinedBehavior};
+use serde::Serialize;
+use thiserror::Error;
+
+use super::TaskContext;
+
+#[derive(Debug, Error)]
+#[error("prompt template error: {0}")]
+pub struct TemplateError(#[from] minijinja::Error);
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TaskVars {
+    pub id: i32,
+    pub input: String,
+}
+
+/// 上一步job的结果，第一步时为空
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PrevVars {
+    pub job_id: Option<i32>,
+    pub output: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PlanVars {
+    /// 当前步骤，从1开始
+    pub step: usize,
+}
+
+/// 渲染prompt可以使用的变量
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PromptContext {
+    pub task: TaskVars,
+    pub prev: PrevVars,
+    pub plan: PlanVars,
+    /// 任务变量
+    pub workspace: HashMap<String, String>,
+}
+
+impl PromptContext {
+    pub fn from_task(task_id: i32, context: &TaskContext) -> Self {
+        Self {
+            task: TaskVars {
+                id: task_id,
+                input: context
+                    .task
+                    .as_ref()
+                    .and_then(|t| t.input.clone())
+                    .unwrap_or_default(),
+            },
+            prev: context
+                .last_output
+                .clone()
+                .map(|(job_id, output)| PrevVars {
+                    job_id: Some(job_id),
+                    output,
+                })
+                .unwrap_or_default(),
+            plan: PlanVars {
+                step: context.step + 1,
+            },
+            workspace: context.variables.clone(),
+        }
+    }
+}
+
+/// 渲染prompt模板
+pub fn render_prompt(template: &str, context: &PromptContext) -> Result<String, TemplateError> {
+    render_template(template, context)
+}
+
+/// 使用任意变量渲染模板，引用不存在的变量同样报错
+pub fn render_template<S: Serialize>(template: &str, context: &S) -> Result<String, TemplateError> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    Ok(env.render_str(template, context)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_previous_output_into_prompt() {
+        let context = PromptContext {
+            task: TaskVars {
+                id: 1,
+                input: "rig".to_string(),
+            },
+            prev: PrevVars {
+                job_id: Some(3),
+                output: "3 entities".to_string(),
+            },
+            plan: PlanVars { step: 2 },
+            workspace: [("repo".to_string(), "/src/rig".to_string())].into(),
+        };
+        let prompt = render_prompt(
+            "step {{ plan.step }} of {{ task.input }} in {{ workspace.repo }}: {{ prev.output }}",
+            &context,
+        )
+        .unwrap();
+        assert_eq!(prompt, "step 2 of rig in /src/rig: 3 entities");
+
+        assert!(render_prompt("{{ prev.outptu }}", &context).is_err());
+    }
+}
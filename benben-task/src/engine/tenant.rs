@@ -0,0 +1,355 @@
+//! 多用户。
+//!
+//! 任务和工作流可以属于某个用户（owner_id），为空时视为单用户部署中的共享数据。
+//! [TaskEngine::for_owner] 返回只能看到和操作该用户任务的视图，其他用户的任务表现为不存在。
+//! 每个用户可以配置配额：同时运行的任务数和累计token预算，超出时拒绝启动任务和执行job，
+//! 调度时跳过超出配额的用户的任务。有存储时累计token记在存储中，已经结束、被移出内存的任务
+//! 以及引擎重启前的用量都计入预算；没有存储时只统计内存中的任务。
+
+use std::collections::BTreeMap;
+
+use rig::completion::Usage;
+use sea_orm::{ColumnTrait, Condition, EntityTrait, QueryFilter};
+
+use super::tasks::TaskSnapshot;
+use super::{TaskContext, TaskEngine, TaskEngineError, TaskState};
+use crate::entities::{task, workflow};
+
+/// 单个用户的配额，为空的项不限制
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OwnerQuota {
+    /// 同时运行的任务数上限
+    pub max_running: Option<usize>,
+    /// 所有任务累计的输入输出token上限
+    pub token_budget: Option<u64>,
+}
+
+/// 用户当前的用量
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OwnerUsage {
+    pub running: usize,
+    pub tokens: u64,
+}
+
+pub(crate) fn owner_of(context: &TaskContext) -> Option<&str> {
+    context.task.as_ref().and_then(|t| t.owner_id.as_deref())
+}
+
+fn usage_of<'a>(tasks: impl IntoIterator<Item = &'a TaskSnapshot>, owner: &str) -> OwnerUsage {
+    tasks
+        .into_iter()
+        .filter(|s| s.owner.as_deref() == Some(owner))
+        .fold(OwnerUsage::default(), |mut usage, s| {
+            if s.state == TaskState::Running {
+                usage.running += 1;
+            }
+            usage.tokens += s.tokens;
+            usage
+        })
+}
+
+/// 用户的任务超出配额的原因，`running` 为 false 时只检查token预算
+fn violation(
+    owner: &str,
+    quota: &OwnerQuota,
+    mut usage: OwnerUsage,
+    state: &TaskState,
+    running: bool,
+) -> Option<String> {
+    // 已经在运行的任务不占用新的名额
+    if *state == TaskState::Running {
+        usage.running -= 1;
+    }
+    if let Some(budget) = quota.token_budget {
+        if usage.tokens >= budget {
+            return Some(format!(
+                "Owner {} used {} of {} tokens",
+                owner, usage.tokens, budget
+            ));
+        }
+    }
+    match quota.max_running {
+        Some(limit) if running && usage.running >= limit => Some(format!(
+            "Owner {} already runs {} of {} tasks",
+            owner, usage.running, limit
+        )),
+        _ => None,
+    }
+}
+
+impl TaskEngine {
+    /// 设置用户的配额
+    pub fn set_quota(&self, owner: impl Into<String>, quota: OwnerQuota) {
+        self.quotas
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(owner.into(), quota);
+    }
+
+    pub fn quota(&self, owner: &str) -> Option<OwnerQuota> {
+        self.quotas
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(owner)
+            .cloned()
+    }
+
+    /// 用户当前运行中的任务数和累计token
+    pub async fn owner_usage(&self, owner: &str) -> OwnerUsage {
+        let usage = usage_of(self.tasks.snapshots().values(), owner);
+        match self.stored_tokens(owner).await {
+            Some(tokens) => OwnerUsage { tokens, ..usage },
+            None => usage,
+        }
+    }
+
+    /// 存储中用户累计的token，没有存储或者读取失败时为空
+    pub(crate) async fn stored_tokens(&self, owner: &str) -> Option<u64> {
+        let store = self.store()?;
+        match store.owner_tokens(owner.to_string()).await {
+            Ok(tokens) => Some(tokens),
+            Err(e) => {
+                tracing::warn!("failed to read token usage of owner {}: {}", owner, e);
+                None
+            }
+        }
+    }
+
+    /// 为用户创建任务，返回任务id。任务写入存储时已经带有所属用户
+    pub async fn create_task_as(&self, owner: &str, input: String) -> Result<i32, TaskEngineError> {
+        self.create_owned_task(input, Some(owner.to_string())).await
+    }
+
+    /// 只能访问指定用户任务的视图
+    pub fn for_owner(&self, owner: impl Into<String>) -> OwnerScope<'_> {
+        OwnerScope {
+            engine: self,
+            owner: owner.into(),
+        }
+    }
+
+    /// 任务的所属用户启动这个任务会超出的配额，`running` 为 false 时只检查token预算。
+    /// 用户配置了配额时逐个读取其他任务的用量，调用时不能持有任务的锁
+    pub(crate) async fn quota_violation(&self, task_id: i32, running: bool) -> Option<String> {
+        let (owner, state) = {
+            let context = self.tasks.lock(task_id).await?;
+            (owner_of(&context)?.to_string(), context.state.clone())
+        };
+        let quota = self.quota(&owner)?;
+        let usage = self.owner_usage(&owner).await;
+        violation(&owner, &quota, usage, &state, running)
+    }
+
+    /// 同 [TaskEngine::quota_violation]，用于按快照调度，
+    /// `stored_tokens` 为事先读取的各用户存储中的累计token
+    pub(crate) fn quota_violation_in(
+        &self,
+        tasks: &BTreeMap<i32, TaskSnapshot>,
+        stored_tokens: &BTreeMap<String, u64>,
+        task_id: i32,
+        running: bool,
+    ) -> Option<String> {
+        let snapshot = tasks.get(&task_id)?;
+        let owner = snapshot.owner.as_deref()?;
+        let quota = self.quota(owner)?;
+        let mut usage = usage_of(tasks.values(), owner);
+        if let Some(tokens) = stored_tokens.get(owner) {
+            usage.tokens = *tokens;
+        }
+        violation(owner, &quota, usage, &snapshot.state, running)
+    }
+}
+
+/// 单个用户的视图
+pub struct OwnerScope<'a> {
+    engine: &'a TaskEngine,
+    owner: String,
+}
+
+impl OwnerScope<'_> {
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// 属于该用户的任务，其他用户的任务返回未找到。
+    /// 不在当前进程中的任务（例如由其他 worker 持有）按存储中的记录判断
+    pub async fn ensure_owned(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        let owner = match self.engine.tasks.lock(task_id).await {
+            Some(context) => owner_of(&context).map(str::to_string),
+            None => match self.engine.store() {
+                Some(store) => store.load_task(task_id).await?.and_then(|t| t.owner_id),
+                None => None,
+            },
+        };
+        match owner {
+            Some(owner) if owner == self.owner => Ok(()),
+            _ => Err(TaskEngineError::task_not_found(task_id)),
+        }
+    }
+
+    pub async fn create_task(&self, input: String) -> Result<i32, TaskEngineError> {
+        self.engine.create_task_as(&self.owner, input).await
+    }
+
+    pub async fn list_tasks(&self) -> Vec<i32> {
+        self.engine
+            .tasks
+            .snapshots()
+            .into_iter()
+            .filter(|(_, s)| s.owner.as_deref() == Some(self.owner.as_str()))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    pub async fn get_state(&self, task_id: i32) -> Result<TaskState, TaskEngineError> {
+        self.ensure_owned(task_id).await?;
+        self.engine.get_state(task_id).await
+    }
+
+    pub async fn get_usage(&self, task_id: i32) -> Result<Usage, TaskEngineError> {
+        self.ensure_owned(task_id).await?;
+        self.engine.get_usage(task_id).await
+    }
+
+    pub async fn get_cost(&self, task_id: i32) -> Result<f64, TaskEngineError> {
+        self.ensure_owned(task_id).await?;
+        self.engine.get_cost(task_id).await
+    }
+
+    pub async fn get_execution_history(
+        &self,
+        task_id: i32,
+    ) -> Result<Vec<String>, TaskEngineError> {
+        self.ensure_owned(task_id).await?;
+        self.engine.get_execution_history(task_id).await
+    }
+
+    pub async fn start(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_owned(task_id).await?;
+        self.engine.start(task_id).await
+    }
+
+    pub async fn pause(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_owned(task_id).await?;
+        self.engine.pause(task_id).await
+    }
+
+    pub async fn resume(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_owned(task_id).await?;
+        self.engine.resume(task_id).await
+    }
+
+    pub async fn cancel(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_owned(task_id).await?;
+        self.engine.cancel(task_id).await
+    }
+
+    pub async fn stop(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_owned(task_id).await?;
+        self.engine.stop(task_id).await
+    }
+
+    pub async fn finish(&self, task_id: i32) -> Result<(), TaskEngineError> {
+        self.ensure_owned(task_id).await?;
+        self.engine.finish(task_id).await
+    }
+
+    /// 从存储读取该用户的任务
+    pub async fn query_task(&self, task_id: i32) -> Result<Option<task::Model>, TaskEngineError> {
+        let task = self.engine.query_task(task_id).await?;
+        Ok(task.filter(|t| t.owner_id.as_deref() == Some(self.owner.as_str())))
+    }
+
+    /// 从存储读取该用户处于指定状态的任务
+    pub async fn query_tasks_by_state(
+        &self,
+        state: TaskState,
+    ) -> Result<Vec<task::Model>, TaskEngineError> {
+        let tasks = self.engine.query_tasks_by_state(state).await?;
+        Ok(tasks
+            .into_iter()
+            .filter(|t| t.owner_id.as_deref() == Some(self.owner.as_str()))
+            .collect())
+    }
+
+    /// 该用户可以使用的工作流：自己的以及共享的
+    pub async fn list_workflows(&self) -> Result<Vec<workflow::Model>, TaskEngineError> {
+        let db = self.engine.db().ok_or_else(|| {
+            TaskEngineError::Unavailable("Task engine has no database".to_string())
+        })?;
+        Ok(workflow::Entity::find()
+            .filter(workflow::Column::Deleted.eq(false))
+            .filter(
+                Condition::any()
+                    .add(workflow::Column::OwnerId.eq(self.owner.as_str()))
+                    .add(workflow::Column::OwnerId.is_null()),
+            )
+            .all(db.as_ref())
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::engine::fixtures;
+    use crate::engine::store::MemoryStore;
+
+    #[tokio::test]
+    async fn owners_are_isolated_and_limited() {
+        let engine = TaskEngine::new().with_store(Arc::new(MemoryStore::new()));
+        engine.set_quota(
+            "alice",
+            OwnerQuota {
+                max_running: Some(1),
+                token_budget: Some(100),
+            },
+        );
+        let alice = engine.for_owner("alice");
+        let bob = engine.for_owner("bob");
+        let first = alice.create_task("a".to_string()).await.unwrap();
+        let second = alice.create_task("b".to_string()).await.unwrap();
+        let other = bob.create_task("c".to_string()).await.unwrap();
+        // 所属用户和任务一起写入，没有第二次覆盖
+        let stored = engine
+            .store()
+            .unwrap()
+            .load_task(first)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.owner_id.as_deref(), Some("alice"));
+        assert_eq!(stored.version, 0);
+
+        assert_eq!(alice.list_tasks().await, vec![first, second]);
+        assert!(bob.get_state(first).await.is_err());
+        assert!(bob.cancel(first).await.is_err());
+
+        alice.start(first).await.unwrap();
+        assert!(alice.start(second).await.is_err());
+        bob.start(other).await.unwrap();
+
+        engine
+            .record_usage(
+                first,
+                Usage {
+                    input_tokens: 80,
+                    output_tokens: 40,
+                    ..Usage::new()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(engine.owner_usage("alice").await.tokens, 120);
+        let job = fixtures::job(1, "next");
+        assert!(engine.execute_job(first, job.clone()).await.is_err());
+        assert!(engine.execute_job(other, job).await.is_ok());
+
+        // 任务移出内存后用量仍然计入预算
+        engine.remove_task(first).await.unwrap();
+        assert_eq!(engine.owner_usage("alice").await.tokens, 120);
+        assert!(alice.start(second).await.is_err());
+    }
+}
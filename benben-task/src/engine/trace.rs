@@ -0,0 +1,314 @@
+//! 导出任务的执行轨迹。
+//!
+//! 把任务每个job的每一次尝试（渲染后的prompt、agent的系统提示、输出、推理过程、token用量和结果）
+//! 导出为JSONL，每行一个json对象，便于在外部工具中查看，或者作为微调数据：
+//!
+//! - [TraceFormat::OpenAi]：OpenAI chat 微调格式，每次尝试一行 `{"messages": [...], "metadata": {...}}`，
+//!   推理过程放在 assistant 消息的 `reasoning_content` 中
+//! - [TraceFormat::LangSmith]：LangSmith 的 run 格式，第一行是任务的 chain run，
+//!   之后每次尝试是它的一个 llm 子run，run的id由任务id和尝试的记录id确定
+//!
+//! 执行记录来自 `job_run` 表，见 [super::attempts]，导出需要存储。
+
+use std::collections::HashMap;
+
+use sea_orm::prelude::ChronoDateTimeUtc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::replay::ToolLogArgs;
+use super::{TaskEngine, TaskEngineError};
+use crate::entities::{job_run, task};
+use crate::mananger::AgentManager;
+
+/// 轨迹的格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TraceFormat {
+    #[default]
+    #[serde(rename = "openai")]
+    OpenAi,
+    #[serde(rename = "langsmith")]
+    LangSmith,
+}
+
+/// 导出轨迹需要的任务数据
+struct TraceSource {
+    task: task::Model,
+    workflow_id: Option<String>,
+    runs: Vec<job_run::Model>,
+    /// 每个job记录的推理过程
+    reasoning: HashMap<i32, String>,
+    /// 每个agent的系统提示
+    preambles: HashMap<String, String>,
+}
+
+impl TraceSource {
+    fn messages(&self, run: &job_run::Model) -> Vec<Value> {
+        let mut messages = Vec::new();
+        if let Some(preamble) = run.agent.as_ref().and_then(|a| self.preambles.get(a)) {
+            messages.push(json!({"role": "system", "content": preamble}));
+        }
+        messages.push(json!({"role": "user", "content": run.prompt.clone().unwrap_or_default()}));
+        messages
+    }
+
+    fn answer(&self, run: &job_run::Model) -> Value {
+        let mut answer = json!({"role": "assistant", "content": run.output});
+        if let Some(reasoning) = self.reasoning.get(&run.job_id) {
+            answer["reasoning_content"] = json!(reasoning);
+        }
+        answer
+    }
+
+    fn usage(run: &job_run::Model) -> Value {
+        json!({
+            "prompt_tokens": run.input_tokens,
+            "completion_tokens": run.output_tokens,
+            "total_tokens": run.input_tokens + run.output_tokens,
+        })
+    }
+
+    fn openai(&self) -> Vec<Value> {
+        self.runs
+            .iter()
+            .map(|run| {
+                let mut messages = self.messages(run);
+                messages.push(self.answer(run));
+                json!({
+                    "messages": messages,
+                    "metadata": {
+                        "task_id": self.task.id,
+                        "workflow_id": self.workflow_id,
+                        "job_id": run.job_id,
+                        "attempt": run.attempt,
+                        "agent": run.agent,
+                        "prompt_version": run.prompt_version,
+                        "status": run.status,
+                        "duration_ms": run.duration_ms,
+                        "usage": Self::usage(run),
+                    },
+                })
+            })
+            .collect()
+    }
+
+    fn langsmith(&self) -> Vec<Value> {
+        let root = run_id(self.task.id, 0);
+        let end = self
+            .runs
+            .iter()
+            .map(|r| r.updated_at)
+            .max()
+            .unwrap_or(self.task.created_at);
+        let mut lines = vec![json!({
+            "id": root,
+            "trace_id": root,
+            "name": self.workflow_id.clone().unwrap_or_else(|| format!("task {}", self.task.id)),
+            "run_type": "chain",
+            "start_time": iso_time(self.task.created_at),
+            "end_time": iso_time(end),
+            "inputs": {"input": self.task.input},
+            "outputs": {"output": self.task.output},
+            "extra": {"metadata": {"task_id": self.task.id, "state": self.task.state}},
+        })];
+        for run in &self.runs {
+            let failed = run.status != "success";
+            lines.push(json!({
+                "id": run_id(self.task.id, run.id),
+                "trace_id": root,
+                "parent_run_id": root,
+                "name": run.agent.clone().unwrap_or_else(|| format!("job {}", run.job_id)),
+                "run_type": "llm",
+                "start_time": iso_time(run.started_at),
+                "end_time": iso_time(run.updated_at),
+                "inputs": {"messages": self.messages(run)},
+                "outputs": if failed { Value::Null } else { json!({"choices": [{"message": self.answer(run)}]}) },
+                "error": if failed { json!(format!("{}: {}", run.status, run.output)) } else { Value::Null },
+                "extra": {"metadata": {
+                    "job_id": run.job_id,
+                    "attempt": run.attempt,
+                    "prompt_version": run.prompt_version,
+                    "status": run.status,
+                }},
+                "prompt_tokens": run.input_tokens,
+                "completion_tokens": run.output_tokens,
+                "total_tokens": run.input_tokens + run.output_tokens,
+            }));
+        }
+        lines
+    }
+}
+
+/// 由任务id和记录id确定的uuid格式的run id
+fn run_id(task_id: i32, record_id: i32) -> String {
+    format!(
+        "{:08x}-0000-4000-8000-{:012x}",
+        task_id as u32, record_id as u32
+    )
+}
+
+/// 已加载的agent在工作流中当前的系统提示，按agent code索引，见 [crate::preamble]
+pub(crate) fn agent_preambles(workflow_id: Option<&str>) -> HashMap<String, String> {
+    AgentManager::global()
+        .map(|manager| {
+            manager
+                .agent_vec
+                .iter()
+                .filter_map(|c| {
+                    let preamble = manager.layered_preamble(&c.code, workflow_id).ok()??;
+                    Some((c.code.clone(), preamble))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 毫秒时间戳转为 RFC 3339 时间
+fn iso_time(millis: i64) -> String {
+    ChronoDateTimeUtc::from_timestamp_millis(millis)
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_default()
+}
+
+impl TaskEngine {
+    /// 按格式导出任务的执行轨迹，每行一个json对象
+    pub async fn export_trace(
+        &self,
+        task_id: i32,
+        format: TraceFormat,
+    ) -> Result<String, TaskEngineError> {
+        let lines = self.trace_records(task_id, format).await?;
+        let mut jsonl = String::new();
+        for line in lines {
+            jsonl.push_str(&serde_json::to_string(&line)?);
+            jsonl.push('\n');
+        }
+        Ok(jsonl)
+    }
+
+    /// 按格式导出任务的执行轨迹，每次尝试一条记录
+    pub async fn trace_records(
+        &self,
+        task_id: i32,
+        format: TraceFormat,
+    ) -> Result<Vec<Value>, TaskEngineError> {
+        let source = self.trace_source(task_id).await?;
+        Ok(match format {
+            TraceFormat::OpenAi => source.openai(),
+            TraceFormat::LangSmith => source.langsmith(),
+        })
+    }
+
+    async fn trace_source(&self, task_id: i32) -> Result<TraceSource, TaskEngineError> {
+        let store = self.store().ok_or_else(|| {
+            TaskEngineError::Unavailable("Trace export requires a task store".to_string())
+        })?;
+        // 内存中的任务状态更新
+        let live = self.tasks.lock(task_id).await.map(|context| {
+            let mut task = context.task.clone();
+            if let Some(task) = task.as_mut() {
+                task.state = Some(context.state.as_str().to_string());
+            }
+            (task, context.workflow.as_ref().map(|w| w.id.clone()))
+        });
+        let (task, workflow_id) = match live {
+            Some((Some(task), workflow_id)) => (task, workflow_id),
+            _ => {
+                let task = store
+                    .load_task(task_id)
+                    .await?
+                    .ok_or_else(|| TaskEngineError::task_not_found(task_id))?;
+                let workflow_id = task.wid.map(|wid| wid.to_string());
+                (task, workflow_id)
+            }
+        };
+
+        let mut reasoning: HashMap<i32, String> = HashMap::new();
+        for log in store.load_tool_logs(task_id).await? {
+            let Some(args) = log
+                .args
+                .as_deref()
+                .and_then(|args| serde_json::from_str::<ToolLogArgs>(args).ok())
+            else {
+                continue;
+            };
+            if args.reasoning {
+                let text = reasoning.entry(args.job_id).or_default();
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text.push_str(log.output.as_deref().unwrap_or_default());
+            }
+        }
+        Ok(TraceSource {
+            preambles: agent_preambles(workflow_id.as_deref()),
+            task,
+            workflow_id,
+            runs: store.load_job_runs(task_id).await?,
+            reasoning,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::engine::fixtures;
+    use crate::engine::simulation::StubAgents;
+    use crate::engine::store::MemoryStore;
+
+    #[tokio::test]
+    async fn runs_are_exported_as_jsonl() {
+        let stubs = StubAgents::new().with_results(
+            "analyst",
+            [Err("timeout".to_string()), Ok("3 aggregates".into())],
+        );
+        let engine = TaskEngine::new()
+            .with_store(Arc::new(MemoryStore::new()))
+            .with_stub_agents(Arc::new(stubs));
+        let task_id = engine.create_task("orders".into()).await.unwrap();
+        engine.start(task_id).await.unwrap();
+        let job = fixtures::agent_job(5, "analyst", "analyse {{ task.input }}");
+        assert!(engine.execute_job(task_id, job.clone()).await.is_err());
+        engine.execute_job(task_id, job).await.unwrap();
+        engine
+            .record_reasoning(task_id, 5, "look for entities first")
+            .await
+            .unwrap();
+
+        let openai = engine
+            .export_trace(task_id, TraceFormat::OpenAi)
+            .await
+            .unwrap();
+        let lines: Vec<Value> = openai
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["messages"][0]["content"], "analyse orders");
+        assert_eq!(lines[1]["messages"][1]["content"], "3 aggregates");
+        assert_eq!(
+            lines[1]["messages"][1]["reasoning_content"],
+            "look for entities first"
+        );
+        assert_eq!(lines[0]["metadata"]["status"], "failure");
+
+        let runs = engine
+            .trace_records(task_id, TraceFormat::LangSmith)
+            .await
+            .unwrap();
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs[0]["run_type"], "chain");
+        assert!(runs
+            .iter()
+            .skip(1)
+            .all(|run| run["parent_run_id"] == runs[0]["id"]));
+        assert!(runs[1]["error"].as_str().unwrap().contains("timeout"));
+        assert_eq!(
+            runs[2]["outputs"]["choices"][0]["message"]["content"],
+            "3 aggregates"
+        );
+    }
+}
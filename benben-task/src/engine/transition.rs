@@ -0,0 +1,192 @@
+//! 任务状态机。
+//!
+//! 合法的状态变更：
+//!
+//! | 从 \ 到  | Waiting | Pending | Running | Stopped | Finished | Cancelled |
+//! |----------|---------|---------|---------|---------|----------|-----------|
+//! | Waiting  |         | ✓       | ✓*      | ✓       |          | ✓         |
+//! | Pending  | ✓       |         | ✓       | ✓       |          | ✓         |
+//! | Running  | ✓       | ✓       |         | ✓       | ✓        | ✓         |
+//! | Stopped  | ✓       | ✓       | ✓       |         |          |           |
+//!
+//! \* 只能由调度器或者用户发起。
+//!
+//! Finished 和 Cancelled 是终止状态，不能再变更；Stopped 的任务只能恢复，不能直接完成或取消。
+//! 规则由 [TaskState::can_transition_to] 实现。
+//! 每次变更通过 [TaskEngine::save_transition] 写入 `task_transition` 表，记录发起方和原因。
+
+use serde::{Deserialize, Serialize};
+
+use super::events::TaskEvent;
+use super::{TaskEngine, TaskEngineError, TaskState};
+use crate::entities::task_transition;
+
+/// 发起状态变更的一方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Actor {
+    /// 通过接口或者UI操作的用户
+    User,
+    Scheduler,
+    /// 引擎自身，例如审批策略暂停任务
+    Engine,
+}
+
+impl Actor {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Actor::User => "user",
+            Actor::Scheduler => "scheduler",
+            Actor::Engine => "engine",
+        }
+    }
+}
+
+/// 一次经过校验的状态变更
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub task_id: i32,
+    pub from: TaskState,
+    pub to: TaskState,
+    pub actor: Actor,
+    pub reason: String,
+}
+
+impl Transition {
+    /// 按状态机和发起方校验变更，等待中的任务只能由调度器或者用户启动
+    pub fn check(
+        task_id: i32,
+        from: &TaskState,
+        to: TaskState,
+        actor: Actor,
+        reason: impl Into<String>,
+    ) -> Result<Self, TaskEngineError> {
+        let by_engine =
+            *from == TaskState::Waiting && to == TaskState::Running && actor == Actor::Engine;
+        if !from.can_transition_to(&to) || by_engine {
+            return Err(TaskEngineError::InvalidTransition {
+                from: from.clone(),
+                to,
+            });
+        }
+        Ok(Self {
+            task_id,
+            from: from.clone(),
+            to,
+            actor,
+            reason: reason.into(),
+        })
+    }
+}
+
+impl TaskEngine {
+    /// 广播状态变更，更新存储中的任务状态并写入审计记录
+    pub(crate) async fn save_transition(
+        &self,
+        transition: Transition,
+    ) -> Result<(), TaskEngineError> {
+        self.ensure_writable()?;
+        let Transition {
+            task_id,
+            from,
+            to,
+            actor,
+            reason,
+        } = transition;
+        self.publish(
+            task_id,
+            TaskEvent::StateChanged {
+                task_id,
+                state: to.clone(),
+            },
+        )
+        .await?;
+        #[cfg(feature = "otel-metrics")]
+        {
+            // 按快照统计，不等待正在执行job的任务
+            let states: Vec<TaskState> = self
+                .tasks
+                .snapshots()
+                .into_values()
+                .map(|s| s.state)
+                .collect();
+            super::metrics::EngineMetrics::global().record_tasks(&states);
+        }
+        self.persist_task(task_id, |task| task.state = Some(to.as_str().to_string()))
+            .await?;
+        let Some(store) = self.store() else {
+            return Ok(());
+        };
+        store
+            .append_transition(task_transition::Model {
+                id: 0,
+                taskid: task_id,
+                from_state: from.as_str().to_string(),
+                to_state: to.as_str().to_string(),
+                actor: actor.as_str().to_string(),
+                reason,
+                created_at: self.clock.now_millis(),
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// 任务的状态变更记录，按发生顺序排列
+    pub async fn get_transitions(
+        &self,
+        task_id: i32,
+    ) -> Result<Vec<task_transition::Model>, TaskEngineError> {
+        let store = self
+            .store()
+            .ok_or_else(|| TaskEngineError::Unavailable("Task engine has no store".to_string()))?;
+        Ok(store.load_transitions(task_id).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::engine::store::MemoryStore;
+
+    #[tokio::test]
+    async fn transitions_are_guarded_and_audited() {
+        assert!(Transition::check(
+            1,
+            &TaskState::Waiting,
+            TaskState::Running,
+            Actor::Engine,
+            ""
+        )
+        .is_err());
+        assert!(!TaskState::Cancelled.can_transition_to(&TaskState::Running));
+
+        let mut engine = TaskEngine::new().with_store(Arc::new(MemoryStore::new()));
+        engine.init(1, "orders".to_string()).await.unwrap();
+        engine.start(1).await.unwrap();
+        engine.finish(1).await.unwrap();
+        assert!(matches!(
+            engine.resume(1).await,
+            Err(TaskEngineError::InvalidTransition {
+                from: TaskState::Finished,
+                ..
+            })
+        ));
+
+        let audit: Vec<(String, String, String)> = engine
+            .get_transitions(1)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.from_state, t.to_state, t.actor))
+            .collect();
+        assert_eq!(
+            audit,
+            vec![
+                ("waiting".into(), "running".into(), "user".into()),
+                ("running".into(), "finished".into(), "user".into()),
+            ]
+        );
+    }
+}
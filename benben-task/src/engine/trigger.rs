@@ -0,0 +1,303 @@
+//! 事件触发的任务创建。
+//!
+//! 触发器把外部事件映射到一个工作流：webhook 收到的json，或者监视的目录中新建、修改的文件，
+//! 都作为 payload 创建一个任务并关联工作流。任务输入由触发器的模板渲染，模板中通过
+//! `{{ payload.xxx }}` 引用 payload；payload 顶层的字段同时写入任务变量，job 的prompt模板
+//! 可以通过 `{{ workspace.xxx }}` 引用。触发的任务属于触发器配置的用户，只能使用该用户自己的或者共享的
+//! 工作流，见 [super::tenant]。
+//!
+//! ```rust,ignore
+//! let engine = TaskEngine::new().with_db(db);
+//! engine.register_trigger(Trigger::new("orders", "3", "alice").input("处理订单 {{ payload.order_id }}"));
+//! trigger::watch_dir(engine.clone(), "inbox", "/data/inbox", Duration::from_secs(5));
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+
+use super::template::{render_template, TemplateError};
+use super::{TaskEngine, TaskEngineError};
+use crate::soft_delete;
+
+#[derive(Debug, Error)]
+pub enum TriggerError {
+    #[error("Trigger {0} not found")]
+    NotFound(String),
+    #[error("Trigger {0} rejected the request: bad secret")]
+    Unauthorized(String),
+    #[error("Workflow {0} not found")]
+    WorkflowNotFound(String),
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    #[error(transparent)]
+    Engine(#[from] TaskEngineError),
+}
+
+impl From<sea_orm::DbErr> for TriggerError {
+    fn from(err: sea_orm::DbErr) -> Self {
+        TriggerError::Engine(err.into())
+    }
+}
+
+/// 外部事件到工作流的映射
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trigger {
+    pub name: String,
+    pub workflow_id: String,
+    /// 触发的任务所属的用户
+    pub owner_id: String,
+    /// 任务输入的模板，默认为整个 payload 的json
+    #[serde(default = "default_input")]
+    pub input: String,
+    /// webhook 请求需要携带的密钥，见 [SECRET_HEADER]
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+/// webhook 请求携带密钥的请求头
+pub use benben_api::SECRET_HEADER;
+
+fn default_input() -> String {
+    "{{ payload | tojson }}".to_string()
+}
+
+impl Trigger {
+    pub fn new(
+        name: impl Into<String>,
+        workflow_id: impl Into<String>,
+        owner_id: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            workflow_id: workflow_id.into(),
+            owner_id: owner_id.into(),
+            input: default_input(),
+            secret: None,
+        }
+    }
+
+    pub fn input(mut self, template: impl Into<String>) -> Self {
+        self.input = template.into();
+        self
+    }
+
+    pub fn secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// 校验请求携带的密钥，没有配置密钥时不校验
+    pub fn verify(&self, secret: Option<&str>) -> bool {
+        match &self.secret {
+            Some(expected) => {
+                secret.is_some_and(|s| constant_time_eq(s.as_bytes(), expected.as_bytes()))
+            }
+            None => true,
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// payload 顶层字段作为任务变量，字符串保持原样，其他值使用json
+fn payload_variables(payload: &Value) -> Vec<(String, String)> {
+    match payload {
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                (key.clone(), value)
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl TaskEngine {
+    /// 注册触发器，同名的触发器被替换
+    pub fn register_trigger(&self, trigger: Trigger) {
+        self.triggers
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(trigger.name.clone(), trigger);
+    }
+
+    pub fn remove_trigger(&self, name: &str) -> Option<Trigger> {
+        self.triggers
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(name)
+    }
+
+    pub fn trigger(&self, name: &str) -> Option<Trigger> {
+        self.triggers
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned()
+    }
+
+    /// 由 payload 创建任务并关联触发器映射的工作流，返回任务id
+    pub async fn fire_trigger(&self, name: &str, payload: Value) -> Result<i32, TriggerError> {
+        let trigger = self
+            .trigger(name)
+            .ok_or_else(|| TriggerError::NotFound(name.to_string()))?;
+        let db = self.db().ok_or_else(|| {
+            TaskEngineError::Unavailable("Task engine has no database".to_string())
+        })?;
+        // 已删除的以及其他用户的工作流视为不存在
+        let workflow = soft_delete::active_workflow(db.as_ref(), &trigger.workflow_id)
+            .await?
+            .filter(|w| {
+                w.owner_id
+                    .as_ref()
+                    .is_none_or(|owner| *owner == trigger.owner_id)
+            })
+            .ok_or_else(|| TriggerError::WorkflowNotFound(trigger.workflow_id.clone()))?;
+        let input = render_template(&trigger.input, &json!({ "payload": payload }))?;
+
+        let task_id = self.create_task_as(&trigger.owner_id, input).await?;
+        for (key, value) in payload_variables(&payload) {
+            self.set_variable(task_id, &key, value).await?;
+        }
+        let attached = self
+            .attach_workflow(task_id, workflow)
+            .await
+            .map_err(TriggerError::from);
+        if let Err(e) = attached {
+            // 输入不符合工作流的schema时不保留半成品任务
+            let _ = self.cancel(task_id).await;
+            return Err(e);
+        }
+        tracing::info!("trigger {} created task {}", name, task_id);
+        Ok(task_id)
+    }
+}
+
+/// 轮询目录，新建或修改的文件触发任务，payload 包含 `event`、`path`、`name` 和文本内容 `content`。
+/// 启动时已经存在的文件不触发
+pub fn watch_dir(
+    engine: Arc<TaskEngine>,
+    trigger: impl Into<String>,
+    dir: impl Into<PathBuf>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    let (trigger, dir) = (trigger.into(), dir.into());
+    tokio::spawn(async move {
+        let clock = engine.clock();
+        let mut seen = scan_dir(&dir);
+        loop {
+            clock.sleep(interval).await;
+            let current = scan_dir(&dir);
+            for (path, modified) in &current {
+                let event = match seen.get(path) {
+                    None => "created",
+                    Some(previous) if previous != modified => "modified",
+                    Some(_) => continue,
+                };
+                let payload = json!({
+                    "event": event,
+                    "path": path.display().to_string(),
+                    "name": path.file_name().map(|n| n.to_string_lossy().into_owned()),
+                    "content": std::fs::read_to_string(path).ok(),
+                });
+                if let Err(e) = engine.fire_trigger(&trigger, payload).await {
+                    tracing::warn!("trigger {} failed for {}: {}", trigger, path.display(), e);
+                }
+            }
+            seen = current;
+        }
+    })
+}
+
+/// 目录中的文件及其修改时间，不递归子目录
+fn scan_dir(dir: &Path) -> HashMap<PathBuf, SystemTime> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return HashMap::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            metadata.is_file().then(|| {
+                (
+                    entry.path(),
+                    metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::fixtures;
+    use crate::entities::workflow;
+    use crate::migrate::create_schema;
+    use sea_orm::ActiveValue::Set;
+    use sea_orm::{ActiveModelTrait, Database};
+
+    #[tokio::test]
+    async fn webhook_payload_creates_task() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&db).await.unwrap();
+        workflow::ActiveModel {
+            id: Set("3".to_string()),
+            version: Set(1),
+            deleted: Set(false),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        let engine = TaskEngine::new().with_db(Arc::new(db));
+        engine.register_trigger(
+            Trigger::new("orders", "3", "alice")
+                .input("order {{ payload.order_id }}")
+                .secret("s3cret"),
+        );
+        assert!(!engine.trigger("orders").unwrap().verify(Some("wrong")));
+
+        let task_id = engine
+            .fire_trigger("orders", json!({"order_id": 42, "customer": "acme"}))
+            .await
+            .unwrap();
+        let task = engine.query_task(task_id).await.unwrap().unwrap();
+        assert_eq!(task.input.as_deref(), Some("order 42"));
+        assert_eq!(task.owner_id.as_deref(), Some("alice"));
+        assert_eq!(engine.task_ids_by_workflow("3").await, vec![task_id]);
+
+        let job = crate::entities::job::Model {
+            workflow_id: 3,
+            ..fixtures::job(1, "bill {{ workspace.customer }}")
+        };
+        // 模板引用不存在的变量时报错，执行成功说明 payload 字段已经写入任务变量
+        engine.execute_job(task_id, job).await.unwrap();
+        assert!(matches!(
+            engine.fire_trigger("missing", json!({})).await,
+            Err(TriggerError::NotFound(_))
+        ));
+
+        // 删除工作流后不再触发任务
+        soft_delete::delete_workflow(engine.db().unwrap().as_ref(), "3")
+            .await
+            .unwrap();
+        assert!(matches!(
+            engine.fire_trigger("orders", json!({"order_id": 43})).await,
+            Err(TriggerError::WorkflowNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,342 @@
+//! 工作流版本。
+//!
+//! 任务关联工作流时，工作流的定义和job集合与最新的历史版本比较，有变化时发布一个新版本，
+//! 任务记住开始时的版本号（task.wversion）。之后修改工作流只影响新任务，已经开始的任务、
+//! 包括重启后从数据库恢复的任务，都按照固定的版本执行。
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::entities::{job, workflow, workflow_version};
+use crate::soft_delete;
+
+#[derive(Debug, Error)]
+pub enum VersionError {
+    #[error("DbError: {0}")]
+    Db(#[from] DbErr),
+    #[error("JsonError: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Workflow {0} not found")]
+    WorkflowNotFound(String),
+    #[error("Version {1} of workflow {0} not found")]
+    VersionNotFound(String, i32),
+}
+
+/// 某个版本的工作流定义和job集合
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkflowSnapshot {
+    pub workflow: workflow::Model,
+    pub jobs: Vec<job::Model>,
+}
+
+impl WorkflowSnapshot {
+    pub fn version(&self) -> i32 {
+        self.workflow.version
+    }
+
+    /// 除版本号外内容是否相同
+    fn same_content(&self, other: &WorkflowSnapshot) -> bool {
+        let workflow = workflow::Model {
+            version: other.workflow.version,
+            ..self.workflow.clone()
+        };
+        workflow == other.workflow && self.jobs == other.jobs
+    }
+}
+
+/// 一个历史版本的概要
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "http-api", derive(utoipa::ToSchema))]
+pub struct VersionInfo {
+    pub version: i32,
+    /// 发布时间，unix 毫秒
+    pub created_at: i64,
+    pub jobs: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "http-api", derive(utoipa::ToSchema))]
+pub struct JobRef {
+    pub id: i32,
+    pub workid: String,
+}
+
+/// 两个版本中同一个job的变化
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "http-api", derive(utoipa::ToSchema))]
+pub struct JobChange {
+    pub id: i32,
+    pub workid: String,
+    /// 发生变化的字段
+    pub fields: Vec<String>,
+}
+
+/// 两个版本的差异
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[cfg_attr(feature = "http-api", derive(utoipa::ToSchema))]
+pub struct VersionDiff {
+    pub from: i32,
+    pub to: i32,
+    /// 发生变化的工作流字段
+    pub workflow_fields: Vec<String>,
+    pub added: Vec<JobRef>,
+    pub removed: Vec<JobRef>,
+    pub changed: Vec<JobChange>,
+}
+
+impl VersionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.workflow_fields.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+fn changed_fields<T: PartialEq>(fields: &[(&str, T, T)]) -> Vec<String> {
+    fields
+        .iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|(name, _, _)| name.to_string())
+        .collect()
+}
+
+/// 比较两个版本，job按id对应
+pub fn diff(from: &WorkflowSnapshot, to: &WorkflowSnapshot) -> VersionDiff {
+    let (a, b) = (&from.workflow, &to.workflow);
+    let workflow_fields = changed_fields(&[
+        ("code", &a.code, &b.code),
+        ("name", &a.name, &b.name),
+        ("desc", &a.desc, &b.desc),
+        ("plan", &a.plan, &b.plan),
+        ("input_schema", &a.input_schema, &b.input_schema),
+        ("output_schema", &a.output_schema, &b.output_schema),
+        ("sla", &a.sla, &b.sla),
+    ]);
+
+    let old: BTreeMap<i32, &job::Model> = from.jobs.iter().map(|job| (job.id, job)).collect();
+    let new: BTreeMap<i32, &job::Model> = to.jobs.iter().map(|job| (job.id, job)).collect();
+    let job_ref = |job: &job::Model| JobRef {
+        id: job.id,
+        workid: job.workid.clone(),
+    };
+    let mut diff = VersionDiff {
+        from: from.version(),
+        to: to.version(),
+        workflow_fields,
+        ..Default::default()
+    };
+    for (id, job) in &old {
+        match new.get(id) {
+            None => diff.removed.push(job_ref(job)),
+            Some(other) => {
+                let mut fields = changed_fields(&[
+                    ("workid", Some(&job.workid), Some(&other.workid)),
+                    ("code", job.code.as_ref(), other.code.as_ref()),
+                    ("action", job.action.as_ref(), other.action.as_ref()),
+                    (
+                        "description",
+                        job.description.as_ref(),
+                        other.description.as_ref(),
+                    ),
+                    ("check", job.check.as_ref(), other.check.as_ref()),
+                    ("type", job.r#type.as_ref(), other.r#type.as_ref()),
+                ]);
+                if job.pid != other.pid {
+                    fields.push("pid".to_string());
+                }
+                if !fields.is_empty() {
+                    diff.changed.push(JobChange {
+                        id: *id,
+                        workid: other.workid.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+    diff.added = new
+        .iter()
+        .filter(|(id, _)| !old.contains_key(id))
+        .map(|(_, job)| job_ref(job))
+        .collect();
+    diff
+}
+
+/// 数据库中工作流当前的定义和未删除的job
+pub async fn current(
+    db: &DatabaseConnection,
+    workflow_id: &str,
+) -> Result<WorkflowSnapshot, VersionError> {
+    let workflow = soft_delete::active_workflow(db, workflow_id)
+        .await?
+        .ok_or_else(|| VersionError::WorkflowNotFound(workflow_id.to_string()))?;
+    let jobs = soft_delete::active_jobs(db, &workflow.id).await?;
+    Ok(WorkflowSnapshot { workflow, jobs })
+}
+
+/// 工作流的所有历史版本，按版本号排序
+pub async fn list_versions(
+    db: &DatabaseConnection,
+    workflow_id: &str,
+) -> Result<Vec<VersionInfo>, VersionError> {
+    let rows = workflow_version::Entity::find()
+        .filter(workflow_version::Column::WorkflowId.eq(workflow_id))
+        .order_by_asc(workflow_version::Column::Version)
+        .all(db)
+        .await?;
+    rows.into_iter()
+        .map(|row| {
+            let snapshot: WorkflowSnapshot = serde_json::from_str(&row.snapshot)?;
+            Ok(VersionInfo {
+                version: row.version,
+                created_at: row.created_at,
+                jobs: snapshot.jobs.len(),
+            })
+        })
+        .collect()
+}
+
+/// 读取一个历史版本
+pub async fn load_version(
+    db: &DatabaseConnection,
+    workflow_id: &str,
+    version: i32,
+) -> Result<WorkflowSnapshot, VersionError> {
+    let row = workflow_version::Entity::find()
+        .filter(workflow_version::Column::WorkflowId.eq(workflow_id))
+        .filter(workflow_version::Column::Version.eq(version))
+        .one(db)
+        .await?
+        .ok_or_else(|| VersionError::VersionNotFound(workflow_id.to_string(), version))?;
+    Ok(serde_json::from_str(&row.snapshot)?)
+}
+
+/// 比较两个历史版本
+pub async fn diff_versions(
+    db: &DatabaseConnection,
+    workflow_id: &str,
+    from: i32,
+    to: i32,
+) -> Result<VersionDiff, VersionError> {
+    let from = load_version(db, workflow_id, from).await?;
+    let to = load_version(db, workflow_id, to).await?;
+    Ok(diff(&from, &to))
+}
+
+/// 返回工作流的最新版本，当前定义与最新的历史版本不同时先发布一个新版本
+pub async fn ensure_version(
+    db: &DatabaseConnection,
+    workflow_id: &str,
+) -> Result<WorkflowSnapshot, VersionError> {
+    let mut snapshot = current(db, workflow_id).await?;
+    let latest = workflow_version::Entity::find()
+        .filter(workflow_version::Column::WorkflowId.eq(workflow_id))
+        .order_by_desc(workflow_version::Column::Version)
+        .one(db)
+        .await?;
+    let next = match latest {
+        Some(row) => {
+            let published: WorkflowSnapshot = serde_json::from_str(&row.snapshot)?;
+            if published.same_content(&snapshot) {
+                return Ok(published);
+            }
+            row.version.max(snapshot.version()) + 1
+        }
+        None => snapshot.version().max(1),
+    };
+
+    snapshot.workflow.version = next;
+    if let Some(model) = soft_delete::active_workflow(db, workflow_id).await? {
+        let mut active: workflow::ActiveModel = model.into();
+        active.version = Set(next);
+        active.update(db).await?;
+    }
+    workflow_version::ActiveModel {
+        id: NotSet,
+        workflow_id: Set(workflow_id.to_string()),
+        version: Set(next),
+        snapshot: Set(serde_json::to_string(&snapshot)?),
+        created_at: Set(now_millis()),
+    }
+    .insert(db)
+    .await?;
+    tracing::info!("published version {} of workflow {}", next, workflow_id);
+    Ok(snapshot)
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::TaskEngine;
+    use crate::migrate::create_schema;
+    use sea_orm::{Database, IntoActiveModel};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn running_tasks_keep_their_version() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&db).await.unwrap();
+        let workflow = workflow::ActiveModel {
+            id: Set("1".to_string()),
+            version: Set(1),
+            deleted: Set(false),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        let job = job::ActiveModel {
+            workid: Set("w1".to_string()),
+            workflow_id: Set(1),
+            action: Set(Some("summarize".to_string())),
+            deleted: Set(false),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let db = Arc::new(db);
+        let mut engine = TaskEngine::new().with_db(db.clone());
+        engine.init(1, "orders".to_string()).await.unwrap();
+        engine.attach_workflow(1, workflow.clone()).await.unwrap();
+
+        let mut edited = job.clone().into_active_model();
+        edited.action = Set(Some("translate".to_string()));
+        let edited = edited.update(db.as_ref()).await.unwrap();
+        engine.init(2, "orders".to_string()).await.unwrap();
+        engine.attach_workflow(2, workflow).await.unwrap();
+
+        let old = engine.execute_job(1, edited.clone()).await.unwrap();
+        assert!(old.contains("summarize"));
+        let new = engine.execute_job(2, edited).await.unwrap();
+        assert!(new.contains("translate"));
+
+        let versions = list_versions(&db, "1").await.unwrap();
+        assert_eq!(
+            versions.iter().map(|v| v.version).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        let diff = diff_versions(&db, "1", 1, 2).await.unwrap();
+        assert_eq!(diff.changed[0].fields, vec!["action".to_string()]);
+        assert_eq!(
+            engine.query_task(1).await.unwrap().unwrap().wversion,
+            Some(1)
+        );
+    }
+}
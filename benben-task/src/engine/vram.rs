@@ -0,0 +1,139 @@
+//! 显存感知的job调度。
+//!
+//! 多个 ollama agent 共用一张显卡时，同时运行多个大模型会导致显存反复换入换出。
+//! 在目录中为每个agent声明模型占用的显存，执行job前先申请显存额度，已占用的额度加上
+//! 新job的额度超过预算时排队等待，直到前面的job释放。没有声明的agent不受限制。
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use tokio::sync::Notify;
+
+use crate::agent_support::{AgentConfOwn, DefaultProviders};
+
+pub struct VramScheduler {
+    /// 显存预算，MB
+    budget_mb: u64,
+    /// agent code -> 声明的显存，MB
+    catalogue: RwLock<HashMap<String, u64>>,
+    used_mb: Mutex<u64>,
+    released: Notify,
+}
+
+/// 显存额度，释放时归还
+pub struct VramPermit {
+    scheduler: Arc<VramScheduler>,
+    mb: u64,
+}
+
+impl Drop for VramPermit {
+    fn drop(&mut self) {
+        let mut used = self
+            .scheduler
+            .used_mb
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *used = used.saturating_sub(self.mb);
+        self.scheduler.released.notify_waiters();
+    }
+}
+
+impl VramScheduler {
+    pub fn new(budget_mb: u64) -> Self {
+        Self {
+            budget_mb,
+            catalogue: RwLock::new(HashMap::new()),
+            used_mb: Mutex::new(0),
+            released: Notify::new(),
+        }
+    }
+
+    /// 声明agent所用模型占用的显存
+    pub fn declare(&self, code: &str, vram_mb: u64) {
+        self.catalogue
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(code.to_string(), vram_mb);
+    }
+
+    /// 按照 AgentConfig.vram_mb 声明所有 ollama agent
+    pub fn declare_configs(&self, configs: &[AgentConfOwn]) {
+        for conf in configs {
+            if let (DefaultProviders::Ollama, Some(vram_mb)) = (conf.provider, conf.config.vram_mb)
+            {
+                self.declare(&conf.config.code, vram_mb);
+            }
+        }
+    }
+
+    pub fn declared(&self, code: &str) -> Option<u64> {
+        self.catalogue
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(code)
+            .copied()
+    }
+
+    /// 显存预算，MB
+    pub fn budget_mb(&self) -> u64 {
+        self.budget_mb
+    }
+
+    /// 当前已占用的显存，MB
+    pub fn used_mb(&self) -> u64 {
+        *self.used_mb.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 申请agent的显存额度，额度不足时等待。没有声明的agent返回 None。
+    /// 单个超过预算的模型在显卡空闲时独占运行，避免永远无法执行
+    pub async fn acquire(self: &Arc<Self>, code: &str) -> Option<VramPermit> {
+        let need = self.declared(code)?;
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            {
+                let mut used = self.used_mb.lock().unwrap_or_else(|e| e.into_inner());
+                if *used == 0 || *used + need <= self.budget_mb {
+                    *used += need;
+                    return Some(VramPermit {
+                        scheduler: self.clone(),
+                        mb: need,
+                    });
+                }
+                // 在释放锁之前注册，避免错过释放通知
+                released.as_mut().enable();
+            }
+            tracing::debug!("job of {} waiting for {}MB vram", code, need);
+            released.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn serializes_jobs_over_budget() {
+        let scheduler = Arc::new(VramScheduler::new(8000));
+        scheduler.declare("large", 6000);
+        scheduler.declare("small", 2000);
+
+        let large = scheduler.acquire("large").await.unwrap();
+        let small = scheduler.acquire("small").await.unwrap();
+        assert_eq!(scheduler.used_mb(), 8000);
+
+        let waiting = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move { scheduler.acquire("large").await.map(|p| p.mb) })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        drop(small);
+        drop(large);
+        assert_eq!(waiting.await.unwrap(), Some(6000));
+        assert!(scheduler.acquire("unknown").await.is_none());
+    }
+}
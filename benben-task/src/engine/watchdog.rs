@@ -0,0 +1,380 @@
+//! 卡住任务的看门狗。
+//!
+//! worker崩溃后，存储中的任务仍然是 running，但没有任何节点在推进它；任务也可能卡在某个
+//! job上迟迟没有进展。看门狗定期对账，找出两类卡住的任务：
+//!
+//! - 存储中是 running，内存中却没有上下文的任务
+//! - 内存中是 running，超过 `stale_after` 没有进展的任务。任务的事件和完成的job都算作进展，
+//!   看门狗第一次看到还没有进展记录的任务时从这时开始计时
+//!
+//! 卡住的任务按 [StuckAction] 停止或者重新排队等待调度，并广播 [TaskEvent::Stuck] 告警。
+//! 看门狗不会打断正在执行job或者正被其他操作锁住的任务，只告警。
+//!
+//! 多个 worker 共用数据库时通过 [TaskWorker::reconcile] 对账：其他 worker 租约仍然有效的任务
+//! 不在当前进程中是正常的，不算卡住；重新排队的任务通过 [TaskWorker::claim] 认领，而不是
+//! [TaskEngine::recover_from_db] 加载所有任务。
+//!
+//! [TaskWorker::reconcile]: crate::worker::TaskWorker::reconcile
+//! [TaskWorker::claim]: crate::worker::TaskWorker::claim
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use super::events::TaskEvent;
+use super::transition::{Actor, Transition};
+use super::{TaskEngine, TaskEngineError, TaskState};
+use crate::worker::TaskWorker;
+
+/// 卡住的任务的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StuckAction {
+    /// 停止任务，等待人工恢复
+    Stop,
+    /// 重新排队，由调度器再次启动
+    Retry,
+}
+
+impl StuckAction {
+    fn target(&self) -> TaskState {
+        match self {
+            StuckAction::Stop => TaskState::Stopped,
+            StuckAction::Retry => TaskState::Waiting,
+        }
+    }
+}
+
+/// 任务被判定为卡住的原因
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StuckReason {
+    /// 存储中是 running，但没有节点持有任务的上下文
+    NoContext,
+    /// 超过时限没有进展
+    NoProgress { idle_ms: i64 },
+}
+
+impl StuckReason {
+    fn describe(&self) -> String {
+        match self {
+            StuckReason::NoContext => "running without a live context".to_string(),
+            StuckReason::NoProgress { idle_ms } => {
+                format!("no progress for {}s", idle_ms / 1000)
+            }
+        }
+    }
+}
+
+/// 看门狗配置
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchdogPolicy {
+    /// 执行中的任务超过这个时长没有进展即判定为卡住
+    pub stale_after: Duration,
+    pub action: StuckAction,
+}
+
+impl Default for WatchdogPolicy {
+    fn default() -> Self {
+        Self {
+            stale_after: Duration::from_secs(30 * 60),
+            action: StuckAction::Stop,
+        }
+    }
+}
+
+/// 一个卡住的任务
+#[derive(Debug, Clone, PartialEq)]
+pub struct StuckTask {
+    pub task_id: i32,
+    pub reason: StuckReason,
+    /// 执行的处理，任务正在执行job时为 None
+    pub action: Option<StuckAction>,
+}
+
+impl TaskEngine {
+    /// 对账一次，处理并返回卡住的任务
+    pub async fn reconcile(
+        &self,
+        policy: &WatchdogPolicy,
+    ) -> Result<Vec<StuckTask>, TaskEngineError> {
+        self.reconcile_with(policy, None).await
+    }
+
+    /// 对账一次，worker 模式下跳过租约有效的任务，重新排队的任务由 `worker` 认领
+    pub(crate) async fn reconcile_with(
+        &self,
+        policy: &WatchdogPolicy,
+        worker: Option<&TaskWorker>,
+    ) -> Result<Vec<StuckTask>, TaskEngineError> {
+        self.ensure_writable()?;
+        let now = self.clock.now_millis();
+        let stale_ms = policy.stale_after.as_millis() as i64;
+        let mut stuck = Vec::new();
+        let mut transitions = Vec::new();
+
+        for task_id in self.tasks.ids() {
+            let Some(last) = self.tasks.last_progress(task_id) else {
+                self.tasks.touch(task_id, now);
+                continue;
+            };
+            let idle_ms = now - last;
+            if idle_ms < stale_ms {
+                continue;
+            }
+            let reason = StuckReason::NoProgress { idle_ms };
+            let context = if self.tasks.is_executing(task_id) {
+                None
+            } else {
+                self.tasks.try_lock(task_id)
+            };
+            let Some(mut context) = context else {
+                if self.tasks.contains(task_id) {
+                    stuck.push(StuckTask {
+                        task_id,
+                        reason,
+                        action: None,
+                    });
+                }
+                continue;
+            };
+            if context.state != TaskState::Running {
+                continue;
+            }
+            let transition = Transition::check(
+                task_id,
+                &context.state,
+                policy.action.target(),
+                Actor::Engine,
+                format!("Task stuck: {}", reason.describe()),
+            )?;
+            context.state = transition.to.clone();
+            context.execution_history.push(transition.reason.clone());
+            drop(context);
+            transitions.push(transition);
+            stuck.push(StuckTask {
+                task_id,
+                reason,
+                action: Some(policy.action),
+            });
+        }
+
+        if let Some(store) = self.store() {
+            for row in store.list_tasks().await? {
+                let running = row.state.as_deref() == Some(TaskState::Running.as_str());
+                if !running || self.tasks.contains(row.id) {
+                    continue;
+                }
+                // 其他 worker 正在执行的任务
+                if worker.is_some_and(|w| w.lease_active(&row)) {
+                    continue;
+                }
+                let reason = StuckReason::NoContext;
+                transitions.push(Transition::check(
+                    row.id,
+                    &TaskState::Running,
+                    policy.action.target(),
+                    Actor::Engine,
+                    format!("Task stuck: {}", reason.describe()),
+                )?);
+                stuck.push(StuckTask {
+                    task_id: row.id,
+                    reason,
+                    action: Some(policy.action),
+                });
+            }
+        }
+
+        for transition in transitions {
+            let task_id = transition.task_id;
+            match self.save_transition(transition).await {
+                // 对账期间任务被其他 worker 认领，交给它处理
+                Err(TaskEngineError::Conflict(_)) if !self.tasks.contains(task_id) => {
+                    stuck.retain(|t| t.task_id != task_id);
+                }
+                result => result?,
+            }
+        }
+        for task in &stuck {
+            tracing::warn!("task {} stuck: {}", task.task_id, task.reason.describe());
+            self.publish(
+                task.task_id,
+                TaskEvent::Stuck {
+                    task_id: task.task_id,
+                    reason: task.reason.clone(),
+                    action: task.action,
+                },
+            )
+            .await?;
+        }
+        // 重新排队的任务加载到内存中，等待调度
+        let requeued = stuck
+            .iter()
+            .any(|t| t.reason == StuckReason::NoContext && t.action == Some(StuckAction::Retry));
+        if requeued {
+            match worker {
+                Some(worker) => {
+                    worker.claim(self).await?;
+                }
+                None => {
+                    self.recover_from_db().await?;
+                }
+            }
+        }
+        Ok(stuck)
+    }
+}
+
+/// 在后台定期对账，维护模式和备用节点上跳过
+pub fn run_watchdog(
+    engine: Arc<TaskEngine>,
+    policy: WatchdogPolicy,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let clock = engine.clock();
+        loop {
+            if !engine.is_maintenance() && !engine.is_standby() {
+                if let Err(e) = engine.reconcile(&policy).await {
+                    tracing::warn!("watchdog reconcile failed: {}", e);
+                }
+            }
+            clock.sleep(interval).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::clock::MockClock;
+    use crate::engine::store::{MemoryStore, TaskStore};
+    use crate::entities::task;
+    use crate::migrate::create_schema;
+    use sea_orm::Database;
+
+    #[tokio::test]
+    async fn stuck_tasks_are_stopped_or_requeued() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let store = Arc::new(MemoryStore::new());
+        let mut engine = TaskEngine::new()
+            .with_clock(clock.clone())
+            .with_store(store.clone());
+        engine.init(1, "stalled".to_string()).await.unwrap();
+        engine.start(1).await.unwrap();
+        engine.init(2, "busy".to_string()).await.unwrap();
+        engine.start(2).await.unwrap();
+        // 崩溃的worker留下的任务
+        store
+            .save_task(task::Model {
+                id: 3,
+                input: Some("zombie".into()),
+                output: None,
+                state: Some("running".into()),
+                wid: None,
+                planid: None,
+                wversion: None,
+                priority: 0,
+                owner_id: None,
+                tags: None,
+                created_at: 0,
+                report: None,
+                version: 0,
+                worker_id: None,
+                heartbeat_at: None,
+            })
+            .await
+            .unwrap();
+        let mut events = engine.subscribe();
+
+        let policy = WatchdogPolicy {
+            stale_after: Duration::from_secs(600),
+            action: StuckAction::Stop,
+        };
+        let stuck = engine.reconcile(&policy).await.unwrap();
+        assert_eq!(stuck.len(), 1);
+        assert_eq!(stuck[0].reason, StuckReason::NoContext);
+        assert_eq!(
+            store.load_task(3).await.unwrap().unwrap().state.as_deref(),
+            Some("stopped")
+        );
+
+        clock.advance(Duration::from_secs(700));
+        let held = engine.tasks.lock(2).await.unwrap();
+        let stuck = engine.reconcile(&policy).await.unwrap();
+        drop(held);
+        let handled: Vec<(i32, Option<StuckAction>)> =
+            stuck.iter().map(|t| (t.task_id, t.action)).collect();
+        assert_eq!(handled, vec![(1, Some(StuckAction::Stop)), (2, None)]);
+        assert_eq!(engine.get_state(1).await.unwrap(), TaskState::Stopped);
+        assert_eq!(engine.get_state(2).await.unwrap(), TaskState::Running);
+
+        let alerts = std::iter::from_fn(|| events.try_recv().ok())
+            .filter(|e| matches!(e, TaskEvent::Stuck { .. }))
+            .count();
+        assert_eq!(alerts, 3);
+
+        engine.resume(1).await.unwrap();
+        let retry = WatchdogPolicy {
+            action: StuckAction::Retry,
+            ..policy
+        };
+        clock.advance(Duration::from_secs(700));
+        engine.reconcile(&retry).await.unwrap();
+        assert_eq!(engine.get_state(1).await.unwrap(), TaskState::Waiting);
+    }
+
+    #[tokio::test]
+    async fn workers_only_reconcile_expired_leases() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&db).await.unwrap();
+        let db = Arc::new(db);
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let engine = || {
+            TaskEngine::new()
+                .with_db(db.clone())
+                .with_clock(clock.clone())
+        };
+        let (engine_a, engine_b) = (engine(), engine());
+        let ttl = Duration::from_secs(30);
+        let worker = |id: &str| {
+            TaskWorker::new(db.clone(), id)
+                .ttl(ttl)
+                .clock(clock.clone())
+        };
+        let (worker_a, worker_b) = (worker("a"), worker("b"));
+        let task_id = engine_a.create_task("orders".into()).await.unwrap();
+        engine_a.start(task_id).await.unwrap();
+        worker_a.heartbeat(&engine_a).await.unwrap();
+
+        // a 持有有效的租约，任务不在 b 中不算卡住
+        let policy = WatchdogPolicy {
+            stale_after: Duration::from_secs(600),
+            action: StuckAction::Retry,
+        };
+        assert!(worker_b
+            .reconcile(&engine_b, &policy)
+            .await
+            .unwrap()
+            .is_empty());
+        let store = engine_b.store().unwrap();
+        let row = store.load_task(task_id).await.unwrap().unwrap();
+        assert_eq!(row.state.as_deref(), Some("running"));
+
+        // a 崩溃后租约过期，b 把任务重新排队并认领
+        clock.advance(ttl * 2);
+        let stuck = worker_b.reconcile(&engine_b, &policy).await.unwrap();
+        let handled: Vec<(i32, StuckReason)> =
+            stuck.into_iter().map(|t| (t.task_id, t.reason)).collect();
+        assert_eq!(handled, vec![(task_id, StuckReason::NoContext)]);
+        assert_eq!(
+            engine_b.get_state(task_id).await.unwrap(),
+            TaskState::Waiting
+        );
+        let row = store.load_task(task_id).await.unwrap().unwrap();
+        assert_eq!(row.worker_id.as_deref(), Some("b"));
+        assert_eq!(worker_a.heartbeat(&engine_a).await.unwrap(), vec![task_id]);
+    }
+}
@@ -0,0 +1,26 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// agent的few-shot示例，见 [crate::example_library]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "agent_example")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// agent code
+    pub agent: String,
+    #[sea_orm(column_type = "Text")]
+    pub input: String,
+    #[sea_orm(column_type = "Text")]
+    pub output: String,
+    /// json 编码的输入的向量，换了向量模型后为空，检索时重新计算
+    #[sea_orm(column_type = "Text")]
+    pub embedding: Option<String>,
+    /// 写入时间，unix 毫秒
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,21 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 大模型回答的缓存，按请求的hash查找
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "completion_cache")]
+pub struct Model {
+    /// 请求的hash，见 `rig::client::cache::cache_key`
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub key: String,
+    /// json 编码的 CachedCompletion
+    #[sea_orm(column_type = "Text")]
+    pub response: String,
+    /// 写入时间，unix 毫秒
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,29 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 数据集中的一条样本，来自标记为golden的任务中一个job的 (prompt, 输出)，见 [crate::engine::dataset]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "dataset_item")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// 数据集名称
+    pub dataset: String,
+    pub taskid: i32,
+    pub job_id: i32,
+    /// 执行job的agent
+    pub agent: Option<String>,
+    /// 渲染后的prompt
+    #[sea_orm(column_type = "Text")]
+    pub prompt: String,
+    /// 被认可的输出
+    #[sea_orm(column_type = "Text")]
+    pub output: String,
+    /// 写入时间，unix 毫秒
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -4,6 +4,7 @@
 
 use sea_orm::*;
 use crate::entities::{workflow, task, plan, tool_log};
+use crate::soft_delete::SoftDelete;
 
 
 
@@ -34,9 +35,9 @@ pub async fn create_tool_log(db: &DatabaseConnection) -> Result<tool_log::Model,
     tool_log::Entity::insert(tool_log).exec_with_returning(db).await
 }
 
-/// Get all workflows
+/// Get all workflows that are not deleted
 pub async fn get_all_workflows(db: &DatabaseConnection) -> Result<Vec<workflow::Model>, DbErr> {
-    workflow::Entity::find().all(db).await
+    workflow::Entity::find_active().all(db).await
 }
 
 /// Get all tasks for a specific workflow
@@ -1,6 +1,7 @@
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "job")]
 pub struct Model {
     #[sea_orm(primary_key)]
@@ -10,12 +11,18 @@ pub struct Model {
     pub workflow_id: i32, // Foreign key to workflow
     pub pid: Option<i32>,
     pub code: Option<String>,
+    #[sea_orm(column_type = "Text")]
     pub action: Option<String>,
     #[sea_orm(column_name = "desc")]
+    #[sea_orm(column_type = "Text")]
     pub description: Option<String>,
+    #[sea_orm(column_type = "Text")]
     pub check: Option<String>,
     #[sea_orm(column_name = "type")]
     pub r#type: Option<String>,
+    /// 软删除标记
+    #[sea_orm(default_value = false)]
+    pub deleted: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,61 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// job的一次执行尝试，见 [crate::engine::attempts]。流式执行时生成过程中定期写入已生成的输出
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "job_run")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub taskid: i32,
+    pub job_id: i32,
+    /// 到最近一次检查点为止生成的输出
+    #[sea_orm(column_type = "Text")]
+    pub output: String,
+    /// 到最近一次检查点为止收到的数据块数
+    pub chunks: i64,
+    /// 生成是否已经完成，未完成的记录说明生成被中断
+    pub finished: bool,
+    /// 开始时间，unix 毫秒
+    pub started_at: i64,
+    /// 最近一次检查点的时间，unix 毫秒
+    pub updated_at: i64,
+    /// 同一个任务中这个job的第几次尝试，从1开始
+    #[sea_orm(default_value = 1)]
+    #[serde(default = "first_attempt")]
+    pub attempt: i32,
+    /// 执行job的agent
+    pub agent: Option<String>,
+    /// 渲染后的prompt
+    #[sea_orm(column_type = "Text")]
+    pub prompt: Option<String>,
+    #[sea_orm(default_value = 0)]
+    #[serde(default)]
+    pub input_tokens: i64,
+    #[sea_orm(default_value = 0)]
+    #[serde(default)]
+    pub output_tokens: i64,
+    #[sea_orm(default_value = 0)]
+    #[serde(default)]
+    pub duration_ms: i64,
+    /// running、success、failure 或 rejected，见 [crate::engine::attempts::RunStatus]
+    #[sea_orm(default_value = "running")]
+    #[serde(default = "running")]
+    pub status: String,
+    /// 使用的系统提示版本，见 [crate::prompt_version]，没有启用的版本时为空
+    #[serde(default)]
+    pub prompt_version: Option<i32>,
+}
+
+fn first_attempt() -> i32 {
+    1
+}
+
+fn running() -> String {
+    "running".to_string()
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
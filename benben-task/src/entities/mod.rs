@@ -3,10 +3,37 @@ pub mod task;
 pub mod plan;
 pub mod tool_log;
 pub mod job;
+pub mod agent_config;
+pub mod engine_lease;
 pub mod example;
+pub mod task_event;
+pub mod workflow_version;
+pub mod completion_cache;
+pub mod job_run;
+pub mod task_transition;
+pub mod dataset_item;
+pub mod agent_example;
+pub mod prompt_version;
+pub mod task_command;
+pub mod owner_usage;
+
+/// 表结构版本，实体字段发生变化时递增，备份恢复时用于校验兼容性
+pub const SCHEMA_VERSION: u32 = 18;
 
 pub use workflow::Entity as Workflow;
 pub use task::Entity as Task;
 pub use plan::Entity as Plan;
 pub use tool_log::Entity as ToolLog;
-pub use job::Entity as Job;
\ No newline at end of file
+pub use job::Entity as Job;
+pub use agent_config::Entity as AgentConfig;
+pub use engine_lease::Entity as EngineLease;
+pub use task_event::Entity as TaskEvent;
+pub use workflow_version::Entity as WorkflowVersion;
+pub use completion_cache::Entity as CompletionCache;
+pub use job_run::Entity as JobRun;
+pub use task_transition::Entity as TaskTransition;
+pub use dataset_item::Entity as DatasetItem;
+pub use agent_example::Entity as AgentExample;
+pub use prompt_version::Entity as PromptVersion;
+pub use task_command::Entity as TaskCommand;
+pub use owner_usage::Entity as OwnerUsage;
\ No newline at end of file
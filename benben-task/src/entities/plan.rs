@@ -1,16 +1,31 @@
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+/// 任务的计划步骤，由工作流的 plan 字段装填，见 [crate::engine::plan]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "plan")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
-    pub pid: Option<i32>,
-    pub state: Option<String>, // success or failure
+    pub pid: Option<i32>,   // task id
+    pub state: Option<String>, // pending / running / success / failure / skipped
     pub planid: Option<String>, // current execution task id
+    /// 步骤在计划中的序号，从1开始
+    #[sea_orm(default_value = 0)]
+    #[serde(default)]
+    pub seq: i32,
+    /// 步骤的描述
+    #[sea_orm(column_type = "Text")]
+    pub step: Option<String>,
+    /// 执行该步骤的job，为空时由规划agent维护状态
+    pub job_id: Option<i32>,
+    /// 乐观锁版本，每次覆盖时加1
+    #[sea_orm(default_value = 0)]
+    #[serde(default)]
+    pub version: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {}
 
-impl ActiveModelBehavior for ActiveModel {}
\ No newline at end of file
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,30 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// agent系统提示的一个版本，见 [crate::prompt_version]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "prompt_version")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// agent code
+    pub agent: String,
+    /// 同一个agent内从1开始递增
+    pub version: i32,
+    #[sea_orm(column_type = "Text")]
+    pub preamble: String,
+    /// draft、active 或 retired，每个agent最多一个 active 版本
+    pub state: String,
+    /// 修改说明
+    #[sea_orm(column_type = "Text")]
+    pub note: Option<String>,
+    /// 创建时间，unix 毫秒
+    pub created_at: i64,
+    /// 最近一次启用的时间，unix 毫秒
+    pub activated_at: Option<i64>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
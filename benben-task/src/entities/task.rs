@@ -1,15 +1,44 @@
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "task")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
+    #[sea_orm(column_type = "Text")]
     pub input: Option<String>,
+    #[sea_orm(column_type = "Text")]
     pub output: Option<String>,
     pub state: Option<String>,
     pub wid: Option<i32>,  // workflow node id
     pub planid: Option<String>, // current execution task id
+    /// 任务开始时的工作流版本，执行过程中不随工作流修改而变化
+    pub wversion: Option<i32>,
+    /// 调度优先级，越大越先执行
+    #[sea_orm(default_value = 0)]
+    #[serde(default)]
+    pub priority: i32,
+    /// 任务所属的用户，单用户部署时为空
+    pub owner_id: Option<String>,
+    /// 标签，键值对的json对象，见 [crate::engine::query]
+    #[sea_orm(column_type = "Text")]
+    pub tags: Option<String>,
+    /// 创建时间，毫秒时间戳
+    #[sea_orm(default_value = 0)]
+    #[serde(default)]
+    pub created_at: i64,
+    /// 任务完成时生成的报告，json，见 [crate::engine::report]
+    #[sea_orm(column_type = "Text")]
+    pub report: Option<String>,
+    /// 乐观锁版本，每次覆盖时加1，见 [crate::engine::store::StoreError::Conflict]
+    #[sea_orm(default_value = 0)]
+    #[serde(default)]
+    pub version: i32,
+    /// 持有任务租约的worker，见 [crate::worker]
+    pub worker_id: Option<String>,
+    /// worker最近一次续约的时间，unix 毫秒
+    pub heartbeat_at: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,25 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 发给任务所属worker的控制命令，见 [crate::worker]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "task_command")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub task_id: i32,
+    /// pause、resume、stop 或 cancel
+    pub command: String,
+    /// 写入时间，unix 毫秒
+    pub created_at: i64,
+    /// worker处理的时间，为空表示还没有处理
+    pub handled_at: Option<i64>,
+    /// 执行命令失败的原因
+    #[sea_orm(column_type = "Text")]
+    pub error: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
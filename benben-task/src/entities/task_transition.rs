@@ -0,0 +1,24 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 任务状态变更的审计记录，按写入顺序保存
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "task_transition")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub taskid: i32,
+    pub from_state: String,
+    pub to_state: String,
+    /// 发起变更的一方：user、scheduler 或 engine
+    pub actor: String,
+    #[sea_orm(column_type = "Text")]
+    pub reason: String,
+    /// 变更时间，unix 毫秒
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
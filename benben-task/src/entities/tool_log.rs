@@ -1,14 +1,17 @@
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "tool_log")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub taskid: Option<i32>,
     pub planid: Option<String>,
+    #[sea_orm(column_type = "Text")]
     pub args: Option<String>,
     #[sea_orm(column_name = "ouput")] // Keeping the original typo from documentation
+    #[sea_orm(column_type = "Text")]
     pub output: Option<String>,
 }
 
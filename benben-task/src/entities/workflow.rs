@@ -1,14 +1,34 @@
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "workflow")]
 pub struct Model {
-    #[sea_orm(primary_key)]
+    #[sea_orm(primary_key, auto_increment = false)]
     pub id: String,
     pub code: Option<String>, // New plan field
     pub name: Option<String>, // New plan field
+    #[sea_orm(column_type = "Text")]
     pub desc: Option<String>, // New plan field
+    #[sea_orm(column_type = "Text")]
     pub plan: Option<String>, // New plan field
+    /// 任务输入的 JSON Schema，为空时不校验
+    #[sea_orm(column_type = "Text")]
+    pub input_schema: Option<String>,
+    /// 任务最终输出的 JSON Schema，为空时不校验
+    #[sea_orm(column_type = "Text")]
+    pub output_schema: Option<String>,
+    /// SLA 配置，json格式，见 [crate::engine::sla::SlaPolicy]
+    #[sea_orm(column_type = "Text")]
+    pub sla: Option<String>,
+    /// 当前版本，历史版本保存在 workflow_version 中
+    #[sea_orm(default_value = 1)]
+    pub version: i32,
+    /// 软删除标记，历史任务仍然可以关联到已删除的工作流
+    #[sea_orm(default_value = false)]
+    pub deleted: bool,
+    /// 工作流所属的用户，为空时所有用户共享
+    pub owner_id: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
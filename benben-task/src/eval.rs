@@ -0,0 +1,342 @@
+//! prompt 和 agent 的 A/B 评测。
+//!
+//! 调整工作流中job的prompt或者换模型之前，用一组测试用例对比新旧两种配置，避免改坏已经
+//! 能处理的输入。评测集是json：
+//!
+//! ```json
+//! {"name": "orders", "cases": [
+//!   {"name": "count", "input": "how many orders today?",
+//!    "rules": [{"type": "regex", "pattern": "\\d+"}],
+//!    "grader": "the answer states a number of orders"}
+//! ]}
+//! ```
+//!
+//! 每个用例的输出先经过 `rules`（与job的后处理链相同的规则，见 [Guardrail]），再由评分agent
+//! 按 `grader` 的要求判断，全部通过才算通过。[Variant] 是一种配置：agent以及可选的prompt模板，
+//! 模板中的 `{{ input }}` 替换为用例的输入。[EvalReport] 对比两种配置的通过情况、用量和耗时，
+//! 列出A通过而B未通过的用例（回退）以及相反的用例（改进）。
+
+use std::time::Instant;
+
+use rig::agent::Agent;
+use rig::completion::{AssistantContent, Completion, CompletionModel, Usage};
+use serde::{Deserialize, Serialize};
+
+use crate::engine::checker::{CheckDecision, CheckerSpec};
+use crate::engine::guardrail::Guardrail;
+
+/// 一个测试用例
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub name: String,
+    pub input: String,
+    /// 输出必须满足的规则
+    #[serde(default)]
+    pub rules: Vec<Guardrail>,
+    /// 交给评分agent判断的要求
+    #[serde(default)]
+    pub grader: Option<String>,
+}
+
+/// 评测集
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalSuite {
+    pub name: String,
+    pub cases: Vec<EvalCase>,
+}
+
+impl EvalSuite {
+    pub fn parse(suite: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(suite)
+    }
+}
+
+/// 参与对比的一种配置
+pub struct Variant<'a, M: CompletionModel> {
+    pub name: String,
+    pub agent: &'a Agent<M>,
+    /// prompt模板，`{{ input }}` 替换为用例的输入，为空时直接使用输入
+    pub prompt: Option<String>,
+}
+
+impl<'a, M: CompletionModel> Variant<'a, M> {
+    pub fn new(name: impl Into<String>, agent: &'a Agent<M>) -> Self {
+        Self {
+            name: name.into(),
+            agent,
+            prompt: None,
+        }
+    }
+
+    pub fn with_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
+    fn render(&self, input: &str) -> String {
+        match &self.prompt {
+            Some(prompt) => prompt.replace("{{ input }}", input),
+            None => input.to_string(),
+        }
+    }
+}
+
+/// 一个用例的结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaseResult {
+    pub case: String,
+    pub output: String,
+    pub passed: bool,
+    /// 没有通过的规则和评分意见，调用失败时是错误信息
+    pub failures: Vec<String>,
+}
+
+/// 一种配置在整个评测集上的结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VariantReport {
+    pub name: String,
+    pub results: Vec<CaseResult>,
+    pub usage: Usage,
+    pub duration_ms: u64,
+}
+
+impl VariantReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed).count()
+    }
+
+    fn passed_case(&self, case: &str) -> bool {
+        self.results.iter().any(|r| r.case == case && r.passed)
+    }
+}
+
+/// 两种配置的对比
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EvalReport {
+    pub suite: String,
+    pub a: VariantReport,
+    pub b: VariantReport,
+    /// A通过而B没有通过的用例
+    pub regressions: Vec<String>,
+    /// B通过而A没有通过的用例
+    pub improvements: Vec<String>,
+}
+
+impl EvalReport {
+    fn new(suite: &EvalSuite, a: VariantReport, b: VariantReport) -> Self {
+        let names = suite.cases.iter().map(|case| case.name.clone());
+        let regressions = names
+            .clone()
+            .filter(|case| a.passed_case(case) && !b.passed_case(case))
+            .collect();
+        let improvements = names
+            .filter(|case| b.passed_case(case) && !a.passed_case(case))
+            .collect();
+        Self {
+            suite: suite.name.clone(),
+            a,
+            b,
+            regressions,
+            improvements,
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let total = self.a.results.len();
+        let mut md = format!("# 评测 {} 对比\n\n", self.suite);
+        md +=
+            "| 配置 | 通过 | 输入token | 输出token | 耗时(ms) |\n| --- | --- | --- | --- | --- |\n";
+        for variant in [&self.a, &self.b] {
+            md += &format!(
+                "| {} | {}/{} | {} | {} | {} |\n",
+                variant.name,
+                variant.passed(),
+                total,
+                variant.usage.input_tokens,
+                variant.usage.output_tokens,
+                variant.duration_ms
+            );
+        }
+        if !self.regressions.is_empty() {
+            md += &format!("\n回退：{}\n", self.regressions.join("、"));
+        }
+        if !self.improvements.is_empty() {
+            md += &format!("\n改进：{}\n", self.improvements.join("、"));
+        }
+        md += "\n## 未通过的用例\n\n";
+        for variant in [&self.a, &self.b] {
+            for result in variant.results.iter().filter(|r| !r.passed) {
+                md += &format!(
+                    "- {} / {}：{}\n",
+                    variant.name,
+                    result.case,
+                    result.failures.join("；")
+                );
+            }
+        }
+        md
+    }
+}
+
+/// 按评测集运行并对比两种配置，用例的 `grader` 由评分agent判断
+pub struct Evaluator<'a, G: CompletionModel> {
+    grader: Option<&'a Agent<G>>,
+}
+
+impl<'a, G: CompletionModel> Evaluator<'a, G> {
+    pub fn new() -> Self {
+        Self { grader: None }
+    }
+
+    pub fn with_grader(mut self, grader: &'a Agent<G>) -> Self {
+        self.grader = Some(grader);
+        self
+    }
+
+    /// 在评测集上依次运行两种配置
+    pub async fn compare<A: CompletionModel, B: CompletionModel>(
+        &self,
+        suite: &EvalSuite,
+        a: &Variant<'_, A>,
+        b: &Variant<'_, B>,
+    ) -> EvalReport {
+        let a = self.run(suite, a).await;
+        let b = self.run(suite, b).await;
+        EvalReport::new(suite, a, b)
+    }
+
+    /// 在评测集上运行一种配置
+    pub async fn run<M: CompletionModel>(
+        &self,
+        suite: &EvalSuite,
+        variant: &Variant<'_, M>,
+    ) -> VariantReport {
+        let started = Instant::now();
+        let mut usage = Usage::new();
+        let mut results = Vec::new();
+        for case in &suite.cases {
+            let prompt = variant.render(&case.input);
+            let response = match variant.agent.completion(prompt.as_str(), vec![]).await {
+                Ok(builder) => builder.send().await,
+                Err(e) => Err(e),
+            };
+            let result = match response {
+                Ok(response) => {
+                    usage += response.usage;
+                    let output = text_of(&response.choice);
+                    let failures = self.judge(case, &output).await;
+                    CaseResult {
+                        case: case.name.clone(),
+                        passed: failures.is_empty(),
+                        output,
+                        failures,
+                    }
+                }
+                Err(e) => CaseResult {
+                    case: case.name.clone(),
+                    output: String::new(),
+                    passed: false,
+                    failures: vec![format!("completion failed: {e}")],
+                },
+            };
+            results.push(result);
+        }
+        VariantReport {
+            name: variant.name.clone(),
+            results,
+            usage,
+            duration_ms: started.elapsed().as_millis() as u64,
+        }
+    }
+
+    /// 用例的输出没有满足的规则和要求
+    async fn judge(&self, case: &EvalCase, output: &str) -> Vec<String> {
+        let mut failures: Vec<String> = case
+            .rules
+            .iter()
+            .filter_map(|rule| rule.apply(output.to_string()).err())
+            .collect();
+        let (Some(requirement), Some(grader)) = (&case.grader, self.grader) else {
+            return failures;
+        };
+        let spec = CheckerSpec {
+            agent: String::new(),
+            prompt: Some(requirement.clone()),
+            max_retries: 0,
+        };
+        match spec.review(grader, &case.input, output).await {
+            Ok(CheckDecision::Pass) => {}
+            Ok(CheckDecision::Retry { feedback: reason }) | Ok(CheckDecision::Fail { reason }) => {
+                failures.push(format!("grader: {reason}"));
+            }
+            Err(e) => failures.push(format!("grader failed: {e}")),
+        }
+        failures
+    }
+}
+
+impl<G: CompletionModel> Default for Evaluator<'_, G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn text_of(choice: &rig::OneOrMany<AssistantContent>) -> String {
+    choice
+        .iter()
+        .filter_map(|content| match content {
+            AssistantContent::Text(text) => Some(text.text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::client::mock::{self, MockClient};
+    use rig::client::CompletionClient;
+
+    #[tokio::test]
+    async fn compares_two_prompts() {
+        let suite = EvalSuite::parse(
+            r#"{"name": "orders", "cases": [
+                {"name": "count", "input": "orders today",
+                 "rules": [{"type": "regex", "pattern": "\\d+"}]},
+                {"name": "tone", "input": "orders this week", "grader": "polite"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let old = mock::script("eval-old");
+        old.push_text("12 orders");
+        old.push_text("whatever");
+        let new = mock::script("eval-new");
+        new.push_text("a dozen orders");
+        new.push_text("There were 40 orders this week, thank you!");
+        let grader = mock::script("eval-grader");
+        grader.push_text(r#"{"decision": "fail", "reason": "rude"}"#);
+        grader.push_text("PASS");
+
+        let client = MockClient::new();
+        let old_agent = client.agent("eval-old").build();
+        let new_agent = client.agent("eval-new").build();
+        let grader_agent = client.agent("eval-grader").build();
+        let report = Evaluator::new()
+            .with_grader(&grader_agent)
+            .compare(
+                &suite,
+                &Variant::new("old", &old_agent),
+                &Variant::new("new", &new_agent).with_prompt("Answer politely: {{ input }}"),
+            )
+            .await;
+
+        assert_eq!(report.a.passed(), 1);
+        assert_eq!(report.b.passed(), 1);
+        assert_eq!(report.regressions, vec!["count".to_string()]);
+        assert_eq!(report.improvements, vec!["tone".to_string()]);
+        assert!(format!("{:?}", new.requests()[0].chat_history).contains("Answer politely"));
+        assert!(report.to_markdown().contains("| new | 1/2 |"));
+    }
+}
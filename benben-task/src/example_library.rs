@@ -0,0 +1,259 @@
+//! agent的few-shot示例库。
+//!
+//! 每个agent code可以积累一组 (输入, 输出) 示例，保存在 `agent_example` 表中，写入时计算输入的向量。
+//! AgentConfig 中设置了 `few_shot=3` 的agent在每次请求前按prompt检索最相似的3个示例，附加在系统提示
+//! 之后，见 `rig::agent::examples`。小的本地模型在重复类型的任务上因此更准确，基础的系统提示也不需要变长。
+//!
+//! 示例库需要先设置向量模型，数据库使用全局引擎当前的连接：
+//!
+//! ```rust,ignore
+//! ExampleLibrary::init(ExampleLibrary::new(Arc::new(embedder)));
+//! ExampleLibrary::global().unwrap().add("analyst", "orders of march", "12 orders").await?;
+//! ```
+//!
+//! 标记为golden的任务的数据集样本可以通过 [ExampleLibrary::import_dataset] 导入，见
+//! [crate::engine::dataset]。
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::future::BoxFuture;
+use once_cell::sync::OnceCell;
+use rig::agent::examples::{most_similar, Example, ExampleSource};
+use rig::completion::CompletionError;
+use rig::embeddings::embedding::EmbeddingModelDyn;
+use rig::embeddings::{Embedding, EmbeddingError};
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder,
+};
+use thiserror::Error;
+
+use crate::engine::TaskEngine;
+use crate::entities::{agent_example, dataset_item};
+
+static INST: OnceCell<Arc<ExampleLibrary>> = OnceCell::new();
+
+#[derive(Debug, Error)]
+pub enum ExampleError {
+    #[error("Example library has no database")]
+    NoDatabase,
+    #[error(transparent)]
+    Db(#[from] DbErr),
+    #[error(transparent)]
+    Embedding(#[from] EmbeddingError),
+}
+
+/// few-shot示例库
+pub struct ExampleLibrary {
+    embedder: Arc<dyn EmbeddingModelDyn>,
+    /// 为空时使用全局引擎当前的数据库连接
+    db: Option<Arc<DatabaseConnection>>,
+}
+
+impl ExampleLibrary {
+    pub fn new(embedder: Arc<dyn EmbeddingModelDyn>) -> Self {
+        Self { embedder, db: None }
+    }
+
+    /// 使用指定的数据库连接
+    pub fn with_db(mut self, db: Arc<DatabaseConnection>) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    /// 设置全局示例库，只有第一次设置生效，之后创建的agent生效
+    pub fn init(library: ExampleLibrary) -> Arc<ExampleLibrary> {
+        INST.get_or_init(|| Arc::new(library)).clone()
+    }
+
+    pub fn global() -> Option<Arc<ExampleLibrary>> {
+        INST.get().cloned()
+    }
+
+    fn db(&self) -> Result<Arc<DatabaseConnection>, ExampleError> {
+        self.db
+            .clone()
+            .or_else(|| TaskEngine::global().and_then(|engine| engine.db()))
+            .ok_or(ExampleError::NoDatabase)
+    }
+
+    /// 为agent添加一个示例
+    pub async fn add(
+        &self,
+        agent: &str,
+        input: &str,
+        output: &str,
+    ) -> Result<agent_example::Model, ExampleError> {
+        let db = self.db()?;
+        let embedding = self.embedder.embed_text(input).await?;
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        let active = agent_example::ActiveModel {
+            id: NotSet,
+            agent: Set(agent.to_string()),
+            input: Set(input.to_string()),
+            output: Set(output.to_string()),
+            embedding: Set(serde_json::to_string(&embedding.vec).ok()),
+            created_at: Set(created_at),
+        };
+        Ok(active.insert(db.as_ref()).await?)
+    }
+
+    /// 把数据集样本作为执行它们的agent的示例导入，没有agent的样本跳过，返回导入的数量
+    pub async fn import_dataset(
+        &self,
+        items: &[dataset_item::Model],
+    ) -> Result<usize, ExampleError> {
+        let mut imported = 0;
+        for item in items {
+            if let Some(agent) = &item.agent {
+                self.add(agent, &item.prompt, &item.output).await?;
+                imported += 1;
+            }
+        }
+        Ok(imported)
+    }
+
+    /// agent的所有示例，按写入顺序排列
+    pub async fn list(&self, agent: &str) -> Result<Vec<agent_example::Model>, ExampleError> {
+        Ok(agent_example::Entity::find()
+            .filter(agent_example::Column::Agent.eq(agent))
+            .order_by_asc(agent_example::Column::Id)
+            .all(self.db()?.as_ref())
+            .await?)
+    }
+
+    /// 删除示例，示例不存在时返回 false
+    pub async fn remove(&self, id: i32) -> Result<bool, ExampleError> {
+        let result = agent_example::Entity::delete_by_id(id)
+            .exec(self.db()?.as_ref())
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// 与 `query` 最相似的 `k` 个示例，最相似的在前
+    pub async fn similar(
+        &self,
+        agent: &str,
+        query: &str,
+        k: usize,
+    ) -> Result<Vec<agent_example::Model>, ExampleError> {
+        let examples = self.list(agent).await?;
+        if examples.is_empty() || k == 0 {
+            return Ok(vec![]);
+        }
+        let query = self.embedder.embed_text(query).await?;
+        let mut candidates = Vec::with_capacity(examples.len());
+        for example in examples {
+            let vec = example
+                .embedding
+                .as_deref()
+                .and_then(|e| serde_json::from_str::<Vec<f64>>(e).ok())
+                .filter(|vec| vec.len() == query.vec.len());
+            // 向量缺失或者维度不同（换了向量模型）时重新计算并保存
+            let vec = match vec {
+                Some(vec) => vec,
+                None => self.reembed(&example).await?,
+            };
+            let embedding = Embedding {
+                document: example.input.clone(),
+                vec,
+            };
+            candidates.push((embedding, example));
+        }
+        Ok(most_similar(&query, candidates, k))
+    }
+
+    async fn reembed(&self, example: &agent_example::Model) -> Result<Vec<f64>, ExampleError> {
+        let vec = self.embedder.embed_text(&example.input).await?.vec;
+        let mut active = example.clone().into_active_model();
+        active.embedding = Set(serde_json::to_string(&vec).ok());
+        active.update(self.db()?.as_ref()).await?;
+        Ok(vec)
+    }
+
+    /// agent检索示例使用的来源
+    pub fn for_agent(self: &Arc<Self>, agent: &str) -> AgentExamples {
+        AgentExamples {
+            library: self.clone(),
+            agent: agent.to_string(),
+        }
+    }
+}
+
+/// 一个agent的示例
+pub struct AgentExamples {
+    library: Arc<ExampleLibrary>,
+    agent: String,
+}
+
+impl ExampleSource for AgentExamples {
+    fn examples<'a>(
+        &'a self,
+        query: &'a str,
+        k: usize,
+    ) -> BoxFuture<'a, Result<Vec<Example>, CompletionError>> {
+        Box::pin(async move {
+            let examples = self
+                .library
+                .similar(&self.agent, query, k)
+                .await
+                .map_err(|e| CompletionError::RequestError(e.into()))?;
+            Ok(examples
+                .into_iter()
+                .map(|e| Example {
+                    input: e.input,
+                    output: e.output,
+                })
+                .collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrate::create_schema;
+    use rig::client::mock::MockClient;
+    use rig::client::EmbeddingsClient;
+    use sea_orm::Database;
+
+    #[tokio::test]
+    async fn most_similar_examples_of_the_agent() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&db).await.unwrap();
+        let embedder = MockClient::new().embedding_model("examples-embed");
+        let library = Arc::new(ExampleLibrary::new(Arc::new(embedder)).with_db(Arc::new(db)));
+
+        library
+            .add("analyst", "orders of march", "12")
+            .await
+            .unwrap();
+        library.add("analyst", "refunds of may", "3").await.unwrap();
+        let other = library
+            .add("writer", "orders of march", "a poem")
+            .await
+            .unwrap();
+
+        let similar = library
+            .similar("analyst", "refunds of may", 1)
+            .await
+            .unwrap();
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[0].output, "3");
+        assert_eq!(library.list("analyst").await.unwrap().len(), 2);
+
+        let examples = library
+            .for_agent("writer")
+            .examples("orders of march", 3)
+            .await
+            .unwrap();
+        assert_eq!(examples[0].output, "a poem");
+        assert!(library.remove(other.id).await.unwrap());
+        assert!(library.list("writer").await.unwrap().is_empty());
+    }
+}
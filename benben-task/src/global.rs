@@ -0,0 +1,51 @@
+//! 全局单例的初始化。
+//!
+//! client构建器、agent管理器和任务引擎都是进程内的单例。`init*` 只能成功一次，重复初始化
+//! 或者并发初始化的竞争返回 [InitError::AlreadyInitialized]，不会panic；`try_global`
+//! 在未初始化时返回 [InitError::NotInitialized]，库的使用者可以自己决定如何处理。
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum InitError {
+    #[error("{0} is already initialized")]
+    AlreadyInitialized(&'static str),
+    #[error("{0} is not initialized")]
+    NotInitialized(&'static str),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::agent_builder::DynClientBuilder;
+    use crate::engine::TaskEngine;
+
+    #[test]
+    fn repeated_init_is_an_error() {
+        // 默认构建器在第一次使用时创建，之后不能再替换
+        let builder = DynClientBuilder::global();
+        assert!(Arc::ptr_eq(
+            &builder,
+            &DynClientBuilder::try_global().unwrap()
+        ));
+        assert_eq!(
+            DynClientBuilder::init(DynClientBuilder::new()).err(),
+            Some(InitError::AlreadyInitialized("client builder"))
+        );
+
+        let engine = match TaskEngine::try_global() {
+            Ok(engine) => engine,
+            Err(err) => {
+                assert_eq!(err, InitError::NotInitialized("task engine"));
+                TaskEngine::init_global(TaskEngine::new()).unwrap()
+            }
+        };
+        assert_eq!(
+            TaskEngine::init_global(TaskEngine::new()).err(),
+            Some(InitError::AlreadyInitialized("task engine"))
+        );
+        assert!(Arc::ptr_eq(&engine, &TaskEngine::global().unwrap()));
+    }
+}
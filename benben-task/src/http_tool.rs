@@ -0,0 +1,204 @@
+//! 内置的http请求工具，agent不需要额外的mcp服务就可以调用内部的 REST 接口。
+//!
+//! 只允许访问白名单中的域名（`*.example.com` 匹配所有子域名），重定向到白名单以外的
+//! 地址会被拒绝；响应体超过上限时截断。AgentConfig 的 tools 中包含 `http` 时按照该agent的
+//! `http_allowlist` 单独创建，也可以手动注册一个共享实例到工具注册表。
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rig::client::AgentConfig;
+use rig::tool::Tool;
+use rmcp::model::Tool as ToolDefinition;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use url::Url;
+
+#[derive(Debug, Error)]
+pub enum HttpToolError {
+    #[error("invalid url: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("domain not allowed: {0}")]
+    NotAllowed(String),
+    #[error("unsupported method: {0}")]
+    UnsupportedMethod(String),
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    #[default]
+    Get,
+    Post,
+}
+
+/// 工具参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpArgs {
+    #[serde(default)]
+    pub method: HttpMethod,
+    pub url: String,
+    /// POST 的json请求体
+    #[serde(default)]
+    pub body: Option<Value>,
+}
+
+/// 请求结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpOutput {
+    pub status: u16,
+    pub body: String,
+    /// 响应体是否被截断
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Allowlist(Arc<Vec<String>>);
+
+impl Allowlist {
+    fn allows(&self, url: &Url) -> bool {
+        if !matches!(url.scheme(), "http" | "https") {
+            return false;
+        }
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        self.0.iter().any(|domain| match domain.strip_prefix("*.") {
+            Some(parent) => host.ends_with(&format!(".{parent}")),
+            None => host == domain,
+        })
+    }
+}
+
+pub struct HttpTool {
+    allowlist: Allowlist,
+    max_response_bytes: usize,
+    client: reqwest::Client,
+}
+
+impl HttpTool {
+    pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 256 * 1024;
+
+    /// 只允许访问 `allowlist` 中的域名，重定向到其他域名时停止
+    pub fn new(allowlist: Vec<String>) -> Result<Self, HttpToolError> {
+        let allowlist = Allowlist(Arc::new(allowlist));
+        let redirect_allowlist = allowlist.clone();
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= 5 || !redirect_allowlist.allows(attempt.url()) {
+                    attempt.stop()
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .build()?;
+        Ok(Self {
+            allowlist,
+            max_response_bytes: Self::DEFAULT_MAX_RESPONSE_BYTES,
+            client,
+        })
+    }
+
+    /// 按照agent配置创建
+    pub fn from_config(config: &AgentConfig) -> Result<Self, HttpToolError> {
+        let tool = Self::new(config.http_allowlist.clone())?;
+        Ok(match config.http_max_response_bytes {
+            Some(max) => tool.max_response_bytes(max),
+            None => tool,
+        })
+    }
+
+    pub fn max_response_bytes(mut self, max_response_bytes: usize) -> Self {
+        self.max_response_bytes = max_response_bytes;
+        self
+    }
+}
+
+impl Tool for HttpTool {
+    const NAME: &'static str = "http";
+
+    type Args = HttpArgs;
+    type Output = HttpOutput;
+    type Error = HttpToolError;
+
+    fn definition(&self) -> ToolDefinition {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "method": { "type": "string", "enum": ["GET", "POST"] },
+                "url": { "type": "string" },
+                "body": { "type": "object", "description": "json body of a POST request" }
+            },
+            "required": ["url"]
+        });
+        ToolDefinition::new(
+            Self::NAME,
+            "Send an HTTP request to an allowed internal API",
+            schema.as_object().cloned().unwrap_or_default(),
+        )
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let url = Url::parse(&args.url)?;
+        if !self.allowlist.allows(&url) {
+            return Err(HttpToolError::NotAllowed(
+                url.host_str().unwrap_or_default().to_string(),
+            ));
+        }
+
+        let request = match args.method {
+            HttpMethod::Get => self.client.get(url),
+            HttpMethod::Post => {
+                let request = self.client.post(url);
+                match &args.body {
+                    Some(body) => request.json(body),
+                    None => request,
+                }
+            }
+        };
+        let mut response = request.send().await?;
+        let status = response.status().as_u16();
+
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response.chunk().await? {
+            let remaining = self.max_response_bytes - body.len();
+            if chunk.len() > remaining {
+                body.extend_from_slice(&chunk[..remaining]);
+                truncated = true;
+                break;
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(HttpOutput {
+            status,
+            body: String::from_utf8_lossy(&body).into_owned(),
+            truncated,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowlist_matches_domains() {
+        let allowlist = Allowlist(Arc::new(vec![
+            "api.internal".to_string(),
+            "*.corp.example".to_string(),
+        ]));
+        let allows = |url: &str| allowlist.allows(&Url::parse(url).unwrap());
+
+        assert!(allows("http://api.internal/v1/items"));
+        assert!(allows("https://billing.corp.example/x"));
+        assert!(!allows("https://corp.example/x"));
+        assert!(!allows("https://api.internal.evil.com/"));
+        assert!(!allows("file:///etc/passwd"));
+    }
+}
@@ -0,0 +1,174 @@
+//! 基于数据库租约的主备选举。
+//!
+//! 多个引擎实例共用同一个数据库时，通过 `engine_lease` 表中的一行租约决定主节点：
+//! 主节点定期续约，备用节点在租约过期后抢占。抢占使用带旧值条件的更新，保证同一时间
+//! 只有一个实例能拿到租约。实例成为主节点后会从数据库恢复未结束的任务，失去租约后
+//! 立即降级为备用节点，不再推进任务。
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use tokio::task::JoinHandle;
+
+use crate::engine::clock::{Clock, SystemClock};
+use crate::engine::TaskEngine;
+use crate::entities::engine_lease;
+
+/// 默认租约名
+pub const DEFAULT_LEASE: &str = "task_engine";
+
+pub struct LeaderElector {
+    db: Arc<DatabaseConnection>,
+    lease: String,
+    holder: String,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl LeaderElector {
+    /// `holder` 为当前实例的唯一id，例如 主机名+进程号
+    pub fn new(db: Arc<DatabaseConnection>, holder: impl Into<String>) -> Self {
+        Self {
+            db,
+            lease: DEFAULT_LEASE.to_string(),
+            holder: holder.into(),
+            ttl: Duration::from_secs(15),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// 设置租约名，不同的引擎集群使用不同的租约
+    pub fn lease(mut self, lease: impl Into<String>) -> Self {
+        self.lease = lease.into();
+        self
+    }
+
+    /// 设置租约有效期，心跳间隔应明显小于该值
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// 设置计算租约过期时间的时钟
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn holder(&self) -> &str {
+        &self.holder
+    }
+
+    /// 获取或续约租约，返回当前实例是否为主节点
+    pub async fn try_acquire(&self) -> Result<bool, DbErr> {
+        let db = self.db.as_ref();
+        let now = self.clock.now_millis();
+        let expires_at = now + self.ttl.as_millis() as i64;
+
+        let Some(current) = engine_lease::Entity::find_by_id(self.lease.clone())
+            .one(db)
+            .await?
+        else {
+            let lease = engine_lease::ActiveModel {
+                name: Set(self.lease.clone()),
+                holder: Set(self.holder.clone()),
+                expires_at: Set(expires_at),
+            };
+            // 并发插入时主键冲突的一方落选
+            return Ok(engine_lease::Entity::insert(lease).exec(db).await.is_ok());
+        };
+
+        if current.holder != self.holder && current.expires_at > now {
+            return Ok(false);
+        }
+
+        // 只有租约仍是读取时的值才更新，避免两个备用节点同时抢占
+        let result = engine_lease::Entity::update_many()
+            .col_expr(
+                engine_lease::Column::Holder,
+                Expr::value(self.holder.clone()),
+            )
+            .col_expr(engine_lease::Column::ExpiresAt, Expr::value(expires_at))
+            .filter(engine_lease::Column::Name.eq(self.lease.clone()))
+            .filter(engine_lease::Column::Holder.eq(current.holder))
+            .filter(engine_lease::Column::ExpiresAt.eq(current.expires_at))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected == 1)
+    }
+
+    /// 主动释放租约，备用节点可以立即接管
+    pub async fn release(&self) -> Result<(), DbErr> {
+        engine_lease::Entity::update_many()
+            .col_expr(engine_lease::Column::ExpiresAt, Expr::value(0i64))
+            .filter(engine_lease::Column::Name.eq(self.lease.clone()))
+            .filter(engine_lease::Column::Holder.eq(self.holder.clone()))
+            .exec(self.db.as_ref())
+            .await?;
+        Ok(())
+    }
+
+    /// 按固定间隔心跳，根据选举结果提升或降级引擎
+    pub fn spawn(self, engine: Arc<TaskEngine>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            let mut leader = false;
+            loop {
+                ticker.tick().await;
+                // 无法确认租约时按失去租约处理，宁可暂停也不能重复执行
+                let acquired = self.try_acquire().await.unwrap_or_else(|e| {
+                    tracing::error!("lease heartbeat of {} failed: {}", self.holder, e);
+                    false
+                });
+
+                if acquired && !leader {
+                    match engine.promote().await {
+                        Ok(recovered) => {
+                            tracing::info!(
+                                "{} became leader, recovered {} tasks",
+                                self.holder,
+                                recovered
+                            )
+                        }
+                        Err(e) => tracing::error!("task recovery after promotion failed: {}", e),
+                    }
+                } else if !acquired && leader {
+                    engine.demote();
+                }
+                leader = acquired;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrate::create_schema;
+    use sea_orm::Database;
+
+    #[tokio::test]
+    async fn standby_takes_over_expired_lease() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&db).await.unwrap();
+        let db = Arc::new(db);
+
+        let ttl = Duration::from_millis(50);
+        let leader = LeaderElector::new(db.clone(), "a").ttl(ttl);
+        let standby = LeaderElector::new(db.clone(), "b").ttl(ttl);
+
+        assert!(leader.try_acquire().await.unwrap());
+        assert!(!standby.try_acquire().await.unwrap());
+        assert!(leader.try_acquire().await.unwrap());
+
+        tokio::time::sleep(ttl * 2).await;
+        assert!(standby.try_acquire().await.unwrap());
+        assert!(!leader.try_acquire().await.unwrap());
+
+        standby.release().await.unwrap();
+        assert!(leader.try_acquire().await.unwrap());
+    }
+}
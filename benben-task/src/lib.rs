@@ -1,6 +1,33 @@
 pub mod agent_builder;
 pub mod agent_support;
+pub mod api;
+pub mod backup;
+pub mod bootstrap;
+pub mod call_agent_tool;
+#[cfg(any(test, feature = "chaos"))]
+pub mod chaos;
+pub mod database;
+pub mod http_tool;
+pub mod logging;
 pub mod mananger;
+pub mod mcp_manager;
+#[cfg(feature = "http-api")]
+pub mod rest;
+pub mod migrate;
+pub mod patch_tool;
+pub mod preamble;
+pub mod prompt_version;
+pub mod service;
+#[cfg(feature = "shell-tool")]
+pub mod shell_tool;
+pub mod soft_delete;
+pub mod tool_registry;
 pub mod workflow;
 pub mod entities;
-pub mod engine;
\ No newline at end of file
+pub mod engine;
+pub mod eval;
+pub mod example_library;
+pub mod global;
+pub mod leader;
+pub mod worker;
+pub mod workspace;
\ No newline at end of file
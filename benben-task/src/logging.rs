@@ -0,0 +1,285 @@
+//! 日志文件的滚动与保留。
+//!
+//! 引擎通常作为长期运行的桌面或服务器守护进程，日志写到文件里并按时间或大小滚动，只保留
+//! 最近的若干个文件。引擎日志和 provider 的请求/响应日志（rig、reqwest 等target）分别写到
+//! 两个文件，provider 日志量大，一般只在排查问题时打开 debug 级别。
+//!
+//! ```rust,ignore
+//! let _guard = LogConfig::new("./logs")
+//!     .rotation(LogRotation::Size(10 * 1024 * 1024))
+//!     .max_files(7)
+//!     .provider_level(LevelFilter::DEBUG)
+//!     .init()?;
+//! ```
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_appender::rolling::{self, RollingFileAppender};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// provider 请求/响应日志使用的target
+pub const PROVIDER_TARGETS: [&str; 4] = ["rig", "reqwest", "hyper", "rmcp"];
+
+#[derive(Debug, Error)]
+pub enum LoggingError {
+    #[error("IoError: {0}")]
+    Io(#[from] io::Error),
+    #[error("InitError: {0}")]
+    Appender(#[from] rolling::InitError),
+    #[error("InitError: {0}")]
+    Subscriber(#[from] tracing_subscriber::util::TryInitError),
+}
+
+/// 日志文件的滚动方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    /// 文件超过指定字节数时滚动
+    Size(u64),
+    /// 不滚动
+    Never,
+}
+
+/// 日志配置
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    dir: PathBuf,
+    prefix: String,
+    rotation: LogRotation,
+    max_files: Option<usize>,
+    level: LevelFilter,
+    provider_level: LevelFilter,
+    stdout: bool,
+}
+
+/// 持有后台写日志的线程，drop 时写完剩余的日志
+pub struct LogGuard {
+    _guards: Vec<WorkerGuard>,
+}
+
+impl LogConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            prefix: "benben".to_string(),
+            rotation: LogRotation::Daily,
+            max_files: Some(14),
+            level: LevelFilter::INFO,
+            provider_level: LevelFilter::WARN,
+            stdout: false,
+        }
+    }
+
+    /// 文件名前缀，引擎日志为 `<prefix>.log`，provider 日志为 `<prefix>-provider.log`
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn rotation(mut self, rotation: LogRotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// 每类日志最多保留的文件数，包含正在写的文件。None 表示不删除旧文件
+    pub fn max_files(mut self, max_files: impl Into<Option<usize>>) -> Self {
+        self.max_files = max_files.into();
+        self
+    }
+
+    /// 引擎日志级别
+    pub fn level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// provider 请求/响应日志级别
+    pub fn provider_level(mut self, level: LevelFilter) -> Self {
+        self.provider_level = level;
+        self
+    }
+
+    /// 同时输出到标准输出
+    pub fn stdout(mut self, stdout: bool) -> Self {
+        self.stdout = stdout;
+        self
+    }
+
+    /// 设置全局的日志订阅者
+    pub fn init(self) -> Result<LogGuard, LoggingError> {
+        fs::create_dir_all(&self.dir)?;
+        let (engine, engine_guard) = self.writer(&self.prefix)?;
+        let (provider, provider_guard) = self.writer(&format!("{}-provider", self.prefix))?;
+
+        let engine_filter = PROVIDER_TARGETS.iter().fold(
+            Targets::new().with_default(self.level),
+            |targets, target| targets.with_target(*target, LevelFilter::OFF),
+        );
+        let provider_filter = PROVIDER_TARGETS
+            .iter()
+            .fold(Targets::new(), |targets, target| {
+                targets.with_target(*target, self.provider_level)
+            });
+        let stdout = self
+            .stdout
+            .then(|| tracing_subscriber::fmt::layer().with_filter(engine_filter.clone()));
+
+        tracing_subscriber::registry()
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(engine)
+                    .with_filter(engine_filter),
+            )
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(provider)
+                    .with_filter(provider_filter),
+            )
+            .with(stdout)
+            .try_init()?;
+        Ok(LogGuard {
+            _guards: vec![engine_guard, provider_guard],
+        })
+    }
+
+    fn writer(&self, name: &str) -> Result<(NonBlocking, WorkerGuard), LoggingError> {
+        let rotation = match self.rotation {
+            LogRotation::Hourly => rolling::Rotation::HOURLY,
+            LogRotation::Daily => rolling::Rotation::DAILY,
+            LogRotation::Never => rolling::Rotation::NEVER,
+            LogRotation::Size(max_bytes) => {
+                let writer = SizeRollingWriter::new(
+                    self.dir.join(format!("{name}.log")),
+                    max_bytes,
+                    self.max_files,
+                )?;
+                return Ok(tracing_appender::non_blocking(writer));
+            }
+        };
+        let mut builder = RollingFileAppender::builder()
+            .rotation(rotation)
+            .filename_prefix(name)
+            .filename_suffix("log");
+        if let Some(max_files) = self.max_files {
+            builder = builder.max_log_files(max_files.max(1));
+        }
+        Ok(tracing_appender::non_blocking(builder.build(&self.dir)?))
+    }
+}
+
+/// 按大小滚动的日志文件：`x.log` 写满后依次改名为 `x.log.1`、`x.log.2`……
+pub struct SizeRollingWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: Option<usize>,
+    file: File,
+    written: u64,
+}
+
+impl SizeRollingWriter {
+    pub fn new(path: PathBuf, max_bytes: u64, max_files: Option<usize>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes: max_bytes.max(1),
+            max_files,
+            file,
+            written,
+        })
+    }
+
+    fn backup(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{index}"));
+        PathBuf::from(name)
+    }
+
+    fn roll(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        // 除当前文件外保留的备份数
+        let keep = self.max_files.map(|max| max.saturating_sub(1));
+        if keep == Some(0) {
+            fs::remove_file(&self.path)?;
+        } else {
+            let mut last = 1;
+            while exists(&self.backup(last)) {
+                last += 1;
+            }
+            for index in (1..last).rev() {
+                if keep.is_some_and(|keep| index >= keep) {
+                    fs::remove_file(self.backup(index))?;
+                } else {
+                    fs::rename(self.backup(index), self.backup(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.backup(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+fn exists(path: &Path) -> bool {
+    path.try_exists().unwrap_or(false)
+}
+
+impl Write for SizeRollingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.roll()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolls_by_size_and_keeps_max_files() {
+        let dir = std::env::temp_dir().join(format!("benben-logs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("engine.log");
+        let mut writer = SizeRollingWriter::new(path.clone(), 10, Some(3)).unwrap();
+        for line in ["first....\n", "second...\n", "third....\n", "fourth...\n"] {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        writer.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth...\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("engine.log.1")).unwrap(),
+            "third....\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("engine.log.2")).unwrap(),
+            "second...\n"
+        );
+        assert!(!dir.join("engine.log.3").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,43 +1,151 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
 
 use once_cell::sync::OnceCell;
 use rig::{
     agent::Agent,
-    client::{AgentConfig, completion::CompletionModelHandle},
+    client::{AgentConfig, VerifyError, completion::CompletionModelHandle},
+    completion::{AssistantContent, Message, Prompt, PromptError, Usage, message::ToolCall},
+    tokens::TokenCounter,
 };
 use rig_ollama::completion::OllamaCompletionModel;
 use rmcp::handler::server::prompt;
+use sea_orm::DatabaseConnection;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 use crate::{
-    agent_builder::DynClientBuilder,
-    agent_support::{AgentConfOwn, SupportFindTrait},
+    agent_builder::{ClientBuildError, DynClientBuilder},
+    agent_support::{AgentConfOwn, DefaultProviders, SupportFindTrait},
+    bootstrap::ModelBootstrap,
+    engine::{TaskEngine, template::TemplateError},
+    entities::prompt_version,
+    global::InitError,
+    preamble::{AgentVars, PreambleLayers},
+    prompt_version::{PromptVersionError, active_versions},
 };
 
 #[derive(Clone, Default)]
 pub struct AgentManager {
     pub agent_map: HashMap<String, Arc<Agent<CompletionModelHandle<'static>>>>,
     pub agent_vec: Vec<Arc<AgentConfig>>,
+    /// 按 AgentConfig.max_concurrency 限制的agent，agent code 为键
+    pub limits: HashMap<String, Arc<ConcurrencyLimit>>,
+    /// 启用的系统提示版本，agent code 为键，见 [crate::prompt_version]
+    pub prompts: Arc<RwLock<HashMap<String, prompt_version::Model>>>,
+    /// 组合在agent系统提示之前的全局层和工作流层，见 [crate::preamble]
+    pub layers: Arc<RwLock<PreambleLayers>>,
+}
+
+/// agent的并发上限，超过上限的请求按到达顺序排队
+pub struct ConcurrencyLimit {
+    max: usize,
+    semaphore: Arc<Semaphore>,
+    waiting: AtomicUsize,
+}
+
+/// 一次生成占用的并发额度，释放时下一个排队的请求开始执行
+pub struct AgentPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+/// agent当前的并发情况
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ConcurrencyStats {
+    pub max: usize,
+    pub in_flight: usize,
+    pub waiting: usize,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            max,
+            semaphore: Arc::new(Semaphore::new(max)),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// 申请额度，达到上限时排队等待
+    pub async fn acquire(&self) -> AgentPermit {
+        // 排队中的请求被取消时也要减掉计数
+        struct Waiting<'a>(&'a AtomicUsize);
+        impl Drop for Waiting<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let waiting = Waiting(&self.waiting);
+        let permit = self.semaphore.clone().acquire_owned().await;
+        drop(waiting);
+        AgentPermit {
+            _permit: permit.expect("agent semaphore is never closed"),
+        }
+    }
+
+    pub fn stats(&self) -> ConcurrencyStats {
+        ConcurrencyStats {
+            max: self.max,
+            in_flight: self.max - self.semaphore.available_permits(),
+            waiting: self.waiting.load(Ordering::Relaxed),
+        }
+    }
 }
 
+/// 启动时校验凭证的超时，超时后不阻塞初始化
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
 // Static instance for global access
 static INST: OnceCell<Arc<AgentManager>> = OnceCell::new();
 
 impl AgentManager {
-    /// Get or initialize the static RagApi instance
+    /// 全局实例，未初始化时为空
     pub fn global() -> Option<Arc<AgentManager>> {
-        if INST.get().is_none() {
-            None
-        } else {
-            let x = INST.get().expect("RagApi not initialized");
-            Some(x.clone())
-        }
+        INST.get().cloned()
     }
 
-    /// Initialize the static RagApi instance
-    /// Initialize the static RagApi instance
-    pub async fn init_global(support: impl SupportFindTrait) -> Result<Arc<AgentManager>, String> {
-        let mut api = AgentManager::default();
+    /// 全局实例，未初始化时返回错误
+    pub fn try_global() -> Result<Arc<AgentManager>, InitError> {
+        Self::global().ok_or(InitError::NotInitialized("agent manager"))
+    }
+
+    /// 按配置创建所有agent并设置为全局实例，创建失败的agent记录错误信息，不影响初始化
+    pub async fn init_global(
+        support: impl SupportFindTrait,
+    ) -> Result<Arc<AgentManager>, InitError> {
+        Self::init_with_configs(support.find_config(), &[]).await
+    }
+
+    /// 先下载缺失的本地模型再初始化，超时后仍然继续初始化，下载失败的agent记录错误信息
+    pub async fn init_global_with_bootstrap(
+        support: impl SupportFindTrait,
+        bootstrap: &ModelBootstrap,
+    ) -> Result<Arc<AgentManager>, InitError> {
         let support_config = support.find_config();
+        let failed = match bootstrap.run(&support_config).await {
+            Ok(report) => report.failed,
+            Err(e) => {
+                tracing::warn!("{e}");
+                Vec::new()
+            }
+        };
+        Self::init_with_configs(support_config, &failed).await
+    }
+
+    async fn init_with_configs(
+        support_config: Vec<AgentConfOwn>,
+        failed_models: &[(String, String)],
+    ) -> Result<Arc<AgentManager>, InitError> {
+        let mut api = AgentManager::default();
 
         let build = DynClientBuilder::global();
         // let mut agent_futures = Vec::new();
@@ -47,6 +155,29 @@ impl AgentManager {
         } in support_config
         {
             let config_code = config.code.clone();
+            if let Some(max) = config.max_concurrency {
+                api.set_concurrency(&config_code, max);
+            }
+            if provider == DefaultProviders::Ollama {
+                if let Some((_, error)) = failed_models.iter().find(|(model, _)| *model == config.model) {
+                    config.error = Some(format!("model bootstrap failed: {error}"));
+                    api.agent_vec.push(Arc::new(config));
+                    continue;
+                }
+            }
+            // 凭证错误在启动时发现，不等到第一个任务步骤才失败；网络错误只记录，不影响创建
+            let verify =
+                tokio::time::timeout(VERIFY_TIMEOUT, build.verify(provider, config.clone()));
+            match verify.await.unwrap_or(Ok(())) {
+                Err(ClientBuildError::VerifyFailed(VerifyError::InvalidAuthentication)) => {
+                    tracing::error!("agent {config_code} has invalid credentials");
+                    config.error = Some("invalid authentication".to_string());
+                    api.agent_vec.push(Arc::new(config));
+                    continue;
+                }
+                Err(e) => tracing::warn!("agent {config_code} verify failed: {e}"),
+                Ok(()) => {}
+            }
             let future = build.agent(provider, config.clone()).await;
             match future {
                 Ok(agent) => {
@@ -61,13 +192,38 @@ impl AgentManager {
             api.agent_vec.push(Arc::new(config));
         }
 
+        api.set_layers(PreambleLayers::from_env());
+        // 启用的系统提示版本覆盖配置中的 sys_promte
+        if let Some(db) = TaskEngine::global().and_then(|engine| engine.db()) {
+            if let Err(e) = api.reload_prompts(&db).await {
+                tracing::warn!("load prompt versions failed: {e}");
+            }
+        }
+
         let manager = Arc::new(api);
         if INST.set(manager.clone()).is_err() {
-            return Err("agent manager init failed".to_string());
+            return Err(InitError::AlreadyInitialized("agent manager"));
         }
         Ok(manager)
     }
 
+    /// agent使用的模型
+    pub fn model(&self, code: &str) -> Option<String> {
+        self.agent_vec
+            .iter()
+            .find(|config| config.code == code)
+            .map(|config| config.model.clone())
+    }
+
+    /// agent配置的工具，没有这个agent时为空
+    pub fn tools(&self, code: &str) -> Vec<String> {
+        self.agent_vec
+            .iter()
+            .find(|config| config.code == code)
+            .map(|config| config.tools.clone())
+            .unwrap_or_default()
+    }
+
     pub fn list_agent(&self) -> Vec<AgentVo> {
         let mut agent_info_vec = Vec::new();
         for ele in &self.agent_vec {
@@ -80,10 +236,236 @@ impl AgentManager {
         }
         agent_info_vec
     }
-    /// 最终军事以string 吐出去，最终由task 取处理，前后置信息，无论是json diff。
-    pub fn execute(prompt: String,/*  plan: WorkFlow */) -> String {
-        String::new()
+    /// 重新读取所有agent启用的系统提示版本，之后的请求生效，返回启用版本的agent数
+    pub async fn reload_prompts(&self, db: &DatabaseConnection) -> Result<usize, PromptVersionError> {
+        let active: HashMap<String, prompt_version::Model> = active_versions(db)
+            .await?
+            .into_iter()
+            .map(|version| (version.agent.clone(), version))
+            .collect();
+        let count = active.len();
+        *self.prompts.write().unwrap_or_else(|e| e.into_inner()) = active;
+        tracing::info!("{count} agents use a versioned prompt");
+        Ok(count)
+    }
+
+    /// agent当前使用的系统提示版本，没有启用的版本时为空
+    pub fn prompt_version(&self, code: &str) -> Option<i32> {
+        self.active_prompt(code).map(|version| version.version)
+    }
+
+    /// agent当前使用的系统提示，启用的版本优先于配置中的 sys_promte
+    pub fn preamble(&self, code: &str) -> Option<String> {
+        match self.active_prompt(code) {
+            Some(version) => Some(version.preamble),
+            None => self
+                .agent_vec
+                .iter()
+                .find(|config| config.code == code)
+                .and_then(|config| config.sys_promte.clone()),
+        }
+    }
+
+    /// 替换全局层和工作流层，之后的请求生效
+    pub fn set_layers(&self, layers: PreambleLayers) {
+        *self.layers.write().unwrap_or_else(|e| e.into_inner()) = layers;
+    }
+
+    /// agent在工作流中实际使用的系统提示：全局层、工作流层和agent自己的系统提示组合后渲染
+    pub fn layered_preamble(
+        &self,
+        code: &str,
+        workflow_id: Option<&str>,
+    ) -> Result<Option<String>, TemplateError> {
+        let preamble = self.preamble(code).or_else(|| {
+            self.agent_map
+                .get(code)
+                .and_then(|agent| agent.preamble.clone())
+        });
+        self.compose(code, workflow_id, preamble.as_deref())
+    }
+
+    fn compose(
+        &self,
+        code: &str,
+        workflow_id: Option<&str>,
+        preamble: Option<&str>,
+    ) -> Result<Option<String>, TemplateError> {
+        let layers = self.layers.read().unwrap_or_else(|e| e.into_inner());
+        let agent = match self.agent_vec.iter().find(|config| config.code == code) {
+            Some(config) => AgentVars {
+                code: config.code.clone(),
+                name: config.name.clone(),
+                desc: config.desc.clone(),
+            },
+            None => AgentVars {
+                code: code.to_string(),
+                ..Default::default()
+            },
+        };
+        layers.compose(&agent, workflow_id, preamble)
+    }
+
+    fn active_prompt(&self, code: &str) -> Option<prompt_version::Model> {
+        self.prompts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(code)
+            .cloned()
+    }
+
+    /// 设置agent的并发上限
+    pub fn set_concurrency(&mut self, code: &str, max: usize) {
+        self.limits
+            .insert(code.to_string(), Arc::new(ConcurrencyLimit::new(max)));
+    }
+
+    /// 申请agent的并发额度，并行的工作流分支在这里排队，而不是同时压到本地显卡上。
+    /// agent没有并发上限时返回 None
+    pub async fn acquire(&self, code: &str) -> Option<AgentPermit> {
+        match self.limits.get(code) {
+            Some(limit) => Some(limit.acquire().await),
+            None => None,
+        }
+    }
+
+    /// agent的并发情况，没有并发上限时为空
+    pub fn concurrency(&self, code: &str) -> Option<ConcurrencyStats> {
+        self.limits.get(code).map(|limit| limit.stats())
+    }
+
+    /// 按 agent code 调用agent，引擎调用任何已配置的agent都经过这里。
+    /// 最终以 [AgentOutput] 吐出去，由task 取处理前后置信息。
+    ///
+    /// 在 `context` 的对话之后追加 prompt，agent配置了 max_turns 时会执行工具调用并继续对话，
+    /// 返回最终回答、期间发出的工具调用、推理过程和累计用量。agent有并发上限时先排队。
+    pub async fn execute(
+        &self,
+        code: &str,
+        prompt: impl Into<String>,
+        context: ExecuteContext,
+    ) -> Result<AgentOutput, ExecuteError> {
+        let Some(agent) = self.agent_map.get(code) else {
+            let config = self.agent_vec.iter().find(|config| config.code == code);
+            return Err(match config {
+                Some(config) => ExecuteError::Unavailable {
+                    code: code.to_string(),
+                    reason: config.error.clone().unwrap_or_default(),
+                },
+                None => ExecuteError::NotFound(code.to_string()),
+            });
+        };
+        let preamble = match self.active_prompt(code) {
+            Some(version) => Some(version.preamble),
+            None => agent.preamble.clone(),
+        };
+        let preamble = self.compose(code, context.workflow_id.as_deref(), preamble.as_deref())?;
+        let agent = if preamble == agent.preamble {
+            agent.clone()
+        } else {
+            let mut agent = agent.as_ref().clone();
+            agent.preamble = preamble;
+            Arc::new(agent)
+        };
+        let _permit = self.acquire(code).await;
+        let mut history = context.history;
+        if let Some(max_tokens) = context.max_history_tokens {
+            let counter = rig::tokens::counter_for(&self.model(code).unwrap_or_default());
+            let dropped = truncate_history(&mut history, max_tokens, counter.as_ref());
+            if dropped > 0 {
+                tracing::debug!("agent {code} dropped {dropped} messages over {max_tokens} tokens");
+            }
+        }
+        let start = history.len();
+        let mut request = agent
+            .prompt(prompt.into())
+            .with_history(&mut history)
+            .extended_details();
+        if let Some(max_turns) = context.max_turns {
+            request = request.multi_turn(max_turns);
+        }
+        let response = request.await.map_err(Box::new)?;
+
+        let mut output = AgentOutput {
+            text: response.output,
+            tool_calls: Vec::new(),
+            reasoning: None,
+            usage: response.total_usage,
+        };
+        let mut reasoning = Vec::new();
+        for message in &history[start..] {
+            let Message::Assistant { content, .. } = message else {
+                continue;
+            };
+            for content in content.iter() {
+                match content {
+                    AssistantContent::ToolCall(call) => output.tool_calls.push(call.clone()),
+                    AssistantContent::Reasoning(r) => reasoning.extend(r.reasoning.iter().cloned()),
+                    AssistantContent::Text(_) => {}
+                }
+            }
+        }
+        if !reasoning.is_empty() {
+            output.reasoning = Some(reasoning.join("\n"));
+        }
+        Ok(output)
+    }
+}
+
+/// 调用agent时的上下文
+#[derive(Debug, Clone, Default)]
+pub struct ExecuteContext {
+    /// 之前的对话
+    pub history: Vec<Message>,
+    /// 工具调用的最大轮数，为空时使用agent的配置
+    pub max_turns: Option<usize>,
+    /// 执行的工作流，用于组合工作流层的系统提示
+    pub workflow_id: Option<String>,
+    /// 之前对话的token上限，按agent模型的分词器计算，超出时丢弃最早的消息
+    pub max_history_tokens: Option<u64>,
+}
+
+/// 从最早的消息开始丢弃，直到剩余对话的token数不超过 `max_tokens`，返回丢弃的消息数
+fn truncate_history(
+    history: &mut Vec<Message>,
+    max_tokens: u64,
+    counter: &dyn TokenCounter,
+) -> usize {
+    let tokens: Vec<u64> = history
+        .iter()
+        .map(|message| counter.count(&serde_json::to_string(message).unwrap_or_default()))
+        .collect();
+    let mut total: u64 = tokens.iter().sum();
+    let mut dropped = 0;
+    while total > max_tokens && dropped < tokens.len() {
+        total -= tokens[dropped];
+        dropped += 1;
     }
+    history.drain(..dropped);
+    dropped
+}
+
+/// agent的调用结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AgentOutput {
+    pub text: String,
+    /// agent在回答过程中发出的工具调用
+    pub tool_calls: Vec<ToolCall>,
+    pub reasoning: Option<String>,
+    /// 所有轮次的累计用量
+    pub usage: Usage,
+}
+
+#[derive(Debug, Error)]
+pub enum ExecuteError {
+    #[error("agent not found: {0}")]
+    NotFound(String),
+    #[error("agent {code} is unavailable: {reason}")]
+    Unavailable { code: String, reason: String },
+    #[error("{0}")]
+    Prompt(#[from] Box<PromptError>),
+    #[error(transparent)]
+    Template(#[from] TemplateError),
 }
 
 pub struct AgentVo {
@@ -91,3 +473,86 @@ pub struct AgentVo {
     pub desc: String,
     pub error: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::agent::AgentBuilder;
+    use rig::client::mock::{self, MockClient};
+    use rig::client::CompletionClient;
+
+    #[tokio::test]
+    async fn requests_queue_beyond_max_concurrency() {
+        let mut manager = AgentManager::default();
+        manager.set_concurrency("ollama", 1);
+        let manager = Arc::new(manager);
+        assert!(manager.acquire("deepseek").await.is_none());
+
+        let first = manager.acquire("ollama").await.unwrap();
+        let queued = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.acquire("ollama").await.is_some() }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(
+            manager.concurrency("ollama"),
+            Some(ConcurrencyStats {
+                max: 1,
+                in_flight: 1,
+                waiting: 1
+            })
+        );
+        assert!(!queued.is_finished());
+
+        drop(first);
+        assert!(queued.await.unwrap());
+        assert_eq!(manager.concurrency("ollama").unwrap().in_flight, 0);
+    }
+
+    #[tokio::test]
+    async fn execute_routes_by_agent_code() {
+        let script = mock::script("manager-execute");
+        script.push_text("计划已生成");
+        let handle = CompletionModelHandle {
+            inner: Arc::new(MockClient::new().completion_model("manager-execute")),
+        };
+        let mut manager = AgentManager::default();
+        manager.agent_map.insert(
+            "planner".to_string(),
+            Arc::new(AgentBuilder::new(handle).build()),
+        );
+
+        let context = ExecuteContext {
+            history: vec![Message::user("订单表有哪些字段")],
+            ..Default::default()
+        };
+        let output = manager
+            .execute("planner", "生成计划", context)
+            .await
+            .unwrap();
+        assert_eq!(output.text, "计划已生成");
+        assert!(output.tool_calls.is_empty());
+        assert_eq!(script.requests()[0].chat_history.len(), 2);
+
+        let err = manager
+            .execute("missing", "生成计划", ExecuteContext::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ExecuteError::NotFound(code) if code == "missing"));
+    }
+
+    #[test]
+    fn history_is_truncated_from_the_oldest_message() {
+        let counter = rig::tokens::counter_for("qwen2.5:7b");
+        let mut history = vec![
+            Message::user("订单表有哪些字段"),
+            Message::assistant("id、金额、下单时间"),
+            Message::user("按月统计金额"),
+        ];
+        let last = counter.count(&serde_json::to_string(&history[2]).unwrap());
+        assert_eq!(truncate_history(&mut history, last, counter.as_ref()), 2);
+        assert_eq!(history, vec![Message::user("按月统计金额")]);
+        assert_eq!(truncate_history(&mut history, 0, counter.as_ref()), 1);
+        assert!(history.is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+//! stdio MCP 服务的生命周期管理。
+//!
+//! 子进程异常退出后agent只会一直返回MCPError。McpManager 持有所有的mcp客户端，
+//! 定时通过 list_tools 探活，失败后按指数退避重启子进程，并把新的客户端替换进
+//! 共享的 [McpClientSlot]，使用该slot的agent在下一次调用时自动使用新的客户端。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+use rig::agent::McpClientSlot;
+use rig::client::McpStdio;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::agent_builder::{build_agent, ClientBuildError};
+
+/// 探活以及重启配置
+#[derive(Debug, Clone)]
+pub struct ProbeConfig {
+    /// 单次探活的超时时间
+    pub timeout: Duration,
+    /// 第一次重启前的等待时间
+    pub base_backoff: Duration,
+    /// 最长的重启等待时间
+    pub max_backoff: Duration,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            base_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+/// 单个mcp服务的状态
+#[derive(Debug, Clone, PartialEq)]
+pub struct McpServerStatus {
+    pub key: String,
+    pub healthy: bool,
+    pub restarts: u32,
+    /// 连续失败次数
+    pub failures: u32,
+}
+
+struct ManagedServer {
+    config: McpStdio,
+    slot: McpClientSlot,
+    healthy: bool,
+    restarts: u32,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+static INST: OnceCell<Arc<McpManager>> = OnceCell::new();
+
+#[derive(Default)]
+pub struct McpManager {
+    servers: Mutex<HashMap<String, ManagedServer>>,
+    probe: ProbeConfig,
+}
+
+impl McpManager {
+    pub fn new(probe: ProbeConfig) -> Self {
+        Self {
+            servers: Mutex::new(HashMap::new()),
+            probe,
+        }
+    }
+
+    /// 全局实例
+    pub fn global() -> Arc<McpManager> {
+        INST.get_or_init(|| Arc::new(McpManager::default())).clone()
+    }
+
+    /// 启动并托管一个stdio mcp服务，已经托管的key直接返回已有的slot
+    pub async fn register(
+        &self,
+        key: &str,
+        config: McpStdio,
+    ) -> Result<McpClientSlot, ClientBuildError> {
+        let mut servers = self.servers.lock().await;
+        if let Some(server) = servers.get(key) {
+            return Ok(server.slot.clone());
+        }
+
+        let client = build_agent(config.clone()).await?;
+        let slot = McpClientSlot::new(client);
+        servers.insert(
+            key.to_string(),
+            ManagedServer {
+                config,
+                slot: slot.clone(),
+                healthy: true,
+                restarts: 0,
+                failures: 0,
+                retry_at: None,
+            },
+        );
+        Ok(slot)
+    }
+
+    /// 停止托管一个服务，例如任务结束后释放该任务的mcp服务。
+    /// 子进程在最后一个使用该slot的agent释放后关闭
+    pub async fn unregister(&self, key: &str) -> bool {
+        self.servers.lock().await.remove(key).is_some()
+    }
+
+    /// 对所有服务进行一次探活，不健康的服务到达退避时间后重启
+    pub async fn probe_all(&self) {
+        let mut servers = self.servers.lock().await;
+        for (key, server) in servers.iter_mut() {
+            if server.healthy {
+                let client = server.slot.get();
+                let alive = matches!(
+                    tokio::time::timeout(self.probe.timeout, client.list_all_tools()).await,
+                    Ok(Ok(_))
+                );
+                if alive {
+                    continue;
+                }
+                tracing::warn!("mcp server {} failed health probe", key);
+                server.healthy = false;
+                server.retry_at = None;
+            }
+
+            if server.retry_at.is_some_and(|at| Instant::now() < at) {
+                continue;
+            }
+            self.restart(key, server).await;
+        }
+    }
+
+    async fn restart(&self, key: &str, server: &mut ManagedServer) {
+        match build_agent(server.config.clone()).await {
+            Ok(client) => {
+                // 旧的客户端在最后一个引用释放时关闭
+                server.slot.replace(client);
+                server.healthy = true;
+                server.restarts += 1;
+                server.failures = 0;
+                server.retry_at = None;
+                tracing::info!("mcp server {} restarted", key);
+            }
+            Err(e) => {
+                server.failures += 1;
+                let backoff = self
+                    .probe
+                    .base_backoff
+                    .saturating_mul(2u32.saturating_pow(server.failures - 1))
+                    .min(self.probe.max_backoff);
+                server.retry_at = Some(Instant::now() + backoff);
+                tracing::error!(
+                    "mcp server {} restart failed, retry in {:?}: {}",
+                    key,
+                    backoff,
+                    e
+                );
+            }
+        }
+    }
+
+    /// 按固定间隔在后台探活
+    pub fn spawn_probes(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.probe_all().await;
+            }
+        })
+    }
+
+    /// 所有服务的状态
+    pub async fn status(&self) -> Vec<McpServerStatus> {
+        let servers = self.servers.lock().await;
+        let mut status: Vec<McpServerStatus> = servers
+            .iter()
+            .map(|(key, server)| McpServerStatus {
+                key: key.clone(),
+                healthy: server.healthy,
+                restarts: server.restarts,
+                failures: server.failures,
+            })
+            .collect();
+        status.sort_by(|a, b| a.key.cmp(&b.key));
+        status
+    }
+}
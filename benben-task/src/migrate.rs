@@ -0,0 +1,434 @@
+//! 数据库迁移工具，用于从 SQLite 迁移到 Postgres（或任意 sea-orm 支持的数据库）。
+//!
+//! 迁移流程：
+//! 1、引擎进入维护模式，不再接收和推进任务。
+//! 2、在目标库上创建缺失的表。
+//! 3、按实体分批复制数据，每批完成后回调检查点，中断后可以从检查点继续。
+//! 4、逐表核对源库与目标库的行数。
+//! 5、核对通过后切换引擎的数据库连接并退出维护模式。
+//!
+//! 连接 Postgres 需要开启 `postgres` feature。
+
+use std::sync::Arc;
+
+use sea_orm::sea_query::{Table, TableCreateStatement};
+use sea_orm::{
+    ActiveModelTrait, ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr, EntityName,
+    EntityTrait, IntoActiveModel, Iterable, PaginatorTrait, PrimaryKeyToColumn, QueryOrder,
+    QuerySelect, Schema, Statement,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::engine::TaskEngine;
+use crate::entities::{
+    agent_config, agent_example, completion_cache, dataset_item, engine_lease, job, job_run,
+    owner_usage, plan, prompt_version, task, task_command, task_event, task_transition, tool_log,
+    workflow, workflow_version,
+};
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error("database error: {0}")]
+    Db(#[from] DbErr),
+    #[error("row count mismatch for {table}: source {source_count}, target {target_count}")]
+    CountMismatch {
+        table: String,
+        source_count: u64,
+        target_count: u64,
+    },
+    #[error("task engine error: {0}")]
+    Engine(String),
+}
+
+/// 迁移检查点，记录已经复制完成的表以及当前表已复制的行数
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MigrationCheckpoint {
+    pub table: String,
+    pub copied: u64,
+}
+
+/// 迁移配置
+#[derive(Debug, Clone)]
+pub struct MigrationOptions {
+    /// 每批复制的行数
+    pub batch_size: u64,
+    /// 从检查点继续迁移
+    pub resume_from: Option<MigrationCheckpoint>,
+}
+
+impl Default for MigrationOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 500,
+            resume_from: None,
+        }
+    }
+}
+
+/// 单表迁移结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableReport {
+    pub table: String,
+    pub source_count: u64,
+    pub target_count: u64,
+}
+
+/// 复制所有实体到目标库，并核对行数
+pub async fn copy_all<F>(
+    source: &DatabaseConnection,
+    target: &DatabaseConnection,
+    options: &MigrationOptions,
+    on_checkpoint: F,
+) -> Result<Vec<TableReport>, MigrationError>
+where
+    F: FnMut(&MigrationCheckpoint),
+{
+    let mut copier = Copier {
+        source,
+        target,
+        options,
+        resumed: options.resume_from.is_none(),
+        on_checkpoint,
+        reports: Vec::new(),
+    };
+    // 按依赖顺序复制，job 依赖 workflow
+    copier.run(workflow::Entity).await?;
+    copier.run(job::Entity).await?;
+    copier.run(task::Entity).await?;
+    copier.run(plan::Entity).await?;
+    copier.run(tool_log::Entity).await?;
+    copier.run(agent_config::Entity).await?;
+    copier.run(task_event::Entity).await?;
+    copier.run(workflow_version::Entity).await?;
+    copier.run(job_run::Entity).await?;
+    copier.run(task_transition::Entity).await?;
+    copier.run(dataset_item::Entity).await?;
+    copier.run(agent_example::Entity).await?;
+    copier.run(prompt_version::Entity).await?;
+    copier.run(owner_usage::Entity).await?;
+
+    reset_all_sequences(target).await?;
+    Ok(copier.reports)
+}
+
+struct Copier<'a, F> {
+    source: &'a DatabaseConnection,
+    target: &'a DatabaseConnection,
+    options: &'a MigrationOptions,
+    /// 是否已经到达检查点所在的表，之前的表视为已经复制完成
+    resumed: bool,
+    on_checkpoint: F,
+    reports: Vec<TableReport>,
+}
+
+impl<F> Copier<'_, F>
+where
+    F: FnMut(&MigrationCheckpoint),
+{
+    async fn run<E, A>(&mut self, entity: E) -> Result<(), MigrationError>
+    where
+        E: EntityTrait,
+        E::Model: IntoActiveModel<A> + Sync,
+        A: ActiveModelTrait<Entity = E>,
+    {
+        let table = entity.table_name();
+        let start = match &self.options.resume_from {
+            _ if self.resumed => Some(0),
+            Some(checkpoint) if checkpoint.table == table => {
+                self.resumed = true;
+                Some(checkpoint.copied)
+            }
+            _ => None,
+        };
+
+        match start {
+            Some(start) => self.copy::<E, A>(entity, start).await?,
+            None => tracing::info!("skip {} already migrated", table),
+        }
+        self.verify(entity).await
+    }
+
+    async fn copy<E, A>(&mut self, entity: E, start: u64) -> Result<(), DbErr>
+    where
+        E: EntityTrait,
+        E::Model: IntoActiveModel<A>,
+        A: ActiveModelTrait<Entity = E>,
+    {
+        let batch_size = self.options.batch_size.max(1);
+        let mut copied = start;
+        loop {
+            let mut select = E::find();
+            for key in E::PrimaryKey::iter() {
+                select = select.order_by_asc(key.into_column());
+            }
+            let rows = select
+                .offset(copied)
+                .limit(batch_size)
+                .all(self.source)
+                .await?;
+            if rows.is_empty() {
+                break;
+            }
+
+            let len = rows.len() as u64;
+            E::insert_many(rows.into_iter().map(|row| row.into_active_model()))
+                .exec_without_returning(self.target)
+                .await?;
+            copied += len;
+
+            (self.on_checkpoint)(&MigrationCheckpoint {
+                table: entity.table_name().to_string(),
+                copied,
+            });
+            if len < batch_size {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn verify<E>(&mut self, entity: E) -> Result<(), MigrationError>
+    where
+        E: EntityTrait,
+        E::Model: Sync,
+    {
+        let source_count = E::find().count(self.source).await?;
+        let target_count = E::find().count(self.target).await?;
+        if source_count != target_count {
+            return Err(MigrationError::CountMismatch {
+                table: entity.table_name().to_string(),
+                source_count,
+                target_count,
+            });
+        }
+        self.reports.push(TableReport {
+            table: entity.table_name().to_string(),
+            source_count,
+            target_count,
+        });
+        Ok(())
+    }
+}
+
+/// 在引擎维护模式下完成迁移并切换数据库连接，失败时保持原连接并退出维护模式
+pub async fn migrate_engine<F>(
+    engine: &TaskEngine,
+    target: Arc<DatabaseConnection>,
+    options: &MigrationOptions,
+    on_checkpoint: F,
+) -> Result<Vec<TableReport>, MigrationError>
+where
+    F: FnMut(&MigrationCheckpoint),
+{
+    let source = engine
+        .db()
+        .ok_or_else(|| MigrationError::Engine("task engine has no database".to_string()))?;
+
+    engine.enter_maintenance();
+    let result = async {
+        create_schema(target.as_ref()).await?;
+        let reports = copy_all(source.as_ref(), target.as_ref(), options, on_checkpoint).await?;
+        engine
+            .switch_db(target.clone())
+            .map_err(|e| MigrationError::Engine(e.to_string()))?;
+        Ok(reports)
+    }
+    .await;
+    engine.exit_maintenance();
+
+    if let Ok(reports) = &result {
+        tracing::info!("database migration finished: {:?}", reports);
+    }
+    result
+}
+
+/// 在目标库上创建缺失的表
+pub async fn create_schema(db: &DatabaseConnection) -> Result<(), DbErr> {
+    let backend = db.get_database_backend();
+    for statement in schema_statements(backend) {
+        db.execute(backend.build(&statement)).await?;
+    }
+    Ok(())
+}
+
+/// 所有表的建表语句，表已存在时跳过
+pub fn schema_statements(backend: DatabaseBackend) -> Vec<TableCreateStatement> {
+    let schema = Schema::new(backend);
+    vec![
+        schema
+            .create_table_from_entity(workflow::Entity)
+            .if_not_exists()
+            .to_owned(),
+        without_foreign_keys(&schema, job::Entity),
+        schema
+            .create_table_from_entity(task::Entity)
+            .if_not_exists()
+            .to_owned(),
+        schema
+            .create_table_from_entity(plan::Entity)
+            .if_not_exists()
+            .to_owned(),
+        schema
+            .create_table_from_entity(tool_log::Entity)
+            .if_not_exists()
+            .to_owned(),
+        schema
+            .create_table_from_entity(agent_config::Entity)
+            .if_not_exists()
+            .to_owned(),
+        schema
+            .create_table_from_entity(engine_lease::Entity)
+            .if_not_exists()
+            .to_owned(),
+        schema
+            .create_table_from_entity(task_event::Entity)
+            .if_not_exists()
+            .to_owned(),
+        schema
+            .create_table_from_entity(workflow_version::Entity)
+            .if_not_exists()
+            .to_owned(),
+        schema
+            .create_table_from_entity(job_run::Entity)
+            .if_not_exists()
+            .to_owned(),
+        schema
+            .create_table_from_entity(task_transition::Entity)
+            .if_not_exists()
+            .to_owned(),
+        schema
+            .create_table_from_entity(dataset_item::Entity)
+            .if_not_exists()
+            .to_owned(),
+        schema
+            .create_table_from_entity(agent_example::Entity)
+            .if_not_exists()
+            .to_owned(),
+        schema
+            .create_table_from_entity(prompt_version::Entity)
+            .if_not_exists()
+            .to_owned(),
+        schema
+            .create_table_from_entity(owner_usage::Entity)
+            .if_not_exists()
+            .to_owned(),
+        // 缓存不复制，在新库上重新积累
+        schema
+            .create_table_from_entity(completion_cache::Entity)
+            .if_not_exists()
+            .to_owned(),
+        // 命令发件箱只保存还没送达的控制命令，也不复制
+        schema
+            .create_table_from_entity(task_command::Entity)
+            .if_not_exists()
+            .to_owned(),
+    ]
+}
+
+/// 不带外键的建表语句。job.workflow_id 是整数而 workflow.id 是字符串，Postgres 和 MySQL
+/// 拒绝类型不同的外键，SQLite 默认也不检查外键，因此所有数据库上都不建这个外键
+fn without_foreign_keys<E: EntityTrait>(schema: &Schema, entity: E) -> TableCreateStatement {
+    let mut statement = Table::create();
+    statement.table(entity.table_ref()).if_not_exists();
+    for column in E::Column::iter() {
+        statement.col(&mut schema.get_column_def::<E>(column));
+    }
+    statement
+}
+
+/// 对齐所有自增主键表的序列
+pub(crate) async fn reset_all_sequences(db: &DatabaseConnection) -> Result<(), DbErr> {
+    reset_sequences(
+        db,
+        &[
+            job::Entity.table_name(),
+            task::Entity.table_name(),
+            plan::Entity.table_name(),
+            tool_log::Entity.table_name(),
+            agent_config::Entity.table_name(),
+            task_event::Entity.table_name(),
+            workflow_version::Entity.table_name(),
+            job_run::Entity.table_name(),
+            task_transition::Entity.table_name(),
+            dataset_item::Entity.table_name(),
+            agent_example::Entity.table_name(),
+            prompt_version::Entity.table_name(),
+        ],
+    )
+    .await
+}
+
+/// 显式写入自增主键后，Postgres 的序列不会自动前移，需要手动对齐
+async fn reset_sequences(db: &DatabaseConnection, tables: &[&str]) -> Result<(), DbErr> {
+    if db.get_database_backend() != DatabaseBackend::Postgres {
+        return Ok(());
+    }
+    for table in tables {
+        let sql = format!(
+            "SELECT setval(pg_get_serial_sequence('\"{table}\"', 'id'), COALESCE((SELECT MAX(id) FROM \"{table}\"), 0) + 1, false)"
+        );
+        db.execute(Statement::from_string(DatabaseBackend::Postgres, sql))
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_is_portable_across_backends() {
+        for backend in [
+            DatabaseBackend::Sqlite,
+            DatabaseBackend::Postgres,
+            DatabaseBackend::MySql,
+        ] {
+            for statement in schema_statements(backend) {
+                let sql = backend.build(&statement).sql;
+                assert!(!sql.contains("FOREIGN KEY"), "{sql}");
+            }
+        }
+        // MySQL 的 varchar(255) 放不下prompt和模型输出
+        let sql: Vec<String> = schema_statements(DatabaseBackend::MySql)
+            .iter()
+            .map(|statement| DatabaseBackend::MySql.build(statement).sql)
+            .collect();
+        let job_run = sql.iter().find(|sql| sql.contains("`job_run`")).unwrap();
+        assert!(job_run.contains("`output` text NOT NULL"), "{job_run}");
+        assert!(sql[1].contains("`id` int NOT NULL AUTO_INCREMENT PRIMARY KEY"));
+    }
+    use sea_orm::{ActiveValue::Set, Database};
+
+    #[tokio::test]
+    async fn copy_between_sqlite_databases() {
+        let source = Database::connect("sqlite::memory:").await.unwrap();
+        let target = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&source).await.unwrap();
+        create_schema(&target).await.unwrap();
+
+        for i in 0..5 {
+            task::ActiveModel {
+                input: Set(Some(format!("input {}", i))),
+                ..Default::default()
+            }
+            .insert(&source)
+            .await
+            .unwrap();
+        }
+
+        let options = MigrationOptions {
+            batch_size: 2,
+            resume_from: None,
+        };
+        let mut checkpoints = Vec::new();
+        let reports = copy_all(&source, &target, &options, |c| checkpoints.push(c.clone()))
+            .await
+            .unwrap();
+
+        let task_report = reports.iter().find(|r| r.table == "task").unwrap();
+        assert_eq!(task_report.target_count, 5);
+        assert_eq!(checkpoints.last().unwrap().copied, 5);
+    }
+}
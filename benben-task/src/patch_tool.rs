@@ -0,0 +1,584 @@
+//! 内置的 apply_patch 工具，agent 不需要文件类的 mcp 服务就可以修改任务工作目录中的文件。
+//!
+//! 工具调用只暂存修改并生成 unified diff，diff 同时写到工作目录的 `.patches/<id>.diff`
+//! 供人工审阅。审批策略（[PolicySubject] 的 tools 包含 `apply_patch`，input 为 diff）
+//! 通过时立即应用，需要审批时留在 [PatchStaging] 中，等待人工调用 [PatchStaging::approve]
+//! 或者 [PatchStaging::reject]。没有配置审批策略的工具只暂存，所有修改都需要人工审批。
+//! 应用前会检查文件在暂存之后没有被其他修改改变过。
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::OnceCell;
+use rig::tool::Tool;
+use rmcp::model::Tool as ToolDefinition;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::engine::policy::{PolicyDecision, PolicySubject};
+
+/// 暂存的修改保存diff的目录，相对于工作目录
+pub const PATCH_DIR: &str = ".patches";
+
+/// 审批策略，通常是 [crate::engine::TaskEngine::check_policy]
+pub type PatchPolicyFn = Arc<dyn Fn(&PolicySubject) -> PolicyDecision + Send + Sync>;
+
+static STAGING: OnceCell<Arc<PatchStaging>> = OnceCell::new();
+
+#[derive(Debug, Error)]
+pub enum PatchError {
+    #[error("path outside the workspace: {0}")]
+    OutsideWorkspace(String),
+    #[error("text to replace not found in {0}")]
+    NoMatch(String),
+    #[error("{0} changed since the patch was staged")]
+    Conflict(String),
+    #[error("patch rejected: {0}")]
+    Rejected(String),
+    #[error("patch {0} not found")]
+    NotFound(u64),
+    #[error("{0} is too large to diff")]
+    TooLarge(String),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// 对一个文件的修改
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum FileEdit {
+    /// 创建或覆盖文件
+    Write {
+        path: String,
+        content: String,
+    },
+    /// 替换文件中唯一出现的一段文本
+    Replace {
+        path: String,
+        old: String,
+        new: String,
+    },
+    Delete {
+        path: String,
+    },
+}
+
+impl FileEdit {
+    pub fn path(&self) -> &str {
+        match self {
+            FileEdit::Write { path, .. }
+            | FileEdit::Replace { path, .. }
+            | FileEdit::Delete { path } => path,
+        }
+    }
+}
+
+/// 工具参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatchArgs {
+    pub edits: Vec<FileEdit>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatchStatus {
+    Applied,
+    /// 等待人工审批
+    PendingApproval,
+}
+
+/// 调用结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatchOutput {
+    pub patch_id: u64,
+    pub status: PatchStatus,
+    pub diff: String,
+}
+
+/// 暂存的一个文件修改，None 表示文件不存在
+#[derive(Debug, Clone, PartialEq)]
+struct StagedFile {
+    path: PathBuf,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+/// 暂存的修改
+#[derive(Debug, Clone, PartialEq)]
+pub struct StagedPatch {
+    pub id: u64,
+    pub workspace: PathBuf,
+    pub diff: String,
+    files: Vec<StagedFile>,
+}
+
+impl StagedPatch {
+    /// 写入修改，任一文件在暂存后发生变化时不做任何修改
+    fn apply(&self) -> Result<(), PatchError> {
+        // 暂存之后路径可能被换成指向工作目录之外的符号链接
+        let root = self.workspace.canonicalize()?;
+        for file in &self.files {
+            if !inside_workspace(&root, &file.path) {
+                return Err(PatchError::OutsideWorkspace(
+                    file.path.display().to_string(),
+                ));
+            }
+            if read_optional(&file.path)? != file.before {
+                return Err(PatchError::Conflict(file.path.display().to_string()));
+            }
+        }
+        for file in &self.files {
+            match &file.after {
+                Some(content) => {
+                    if let Some(parent) = file.path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(&file.path, content)?;
+                }
+                None if file.before.is_some() => fs::remove_file(&file.path)?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 等待审批的修改
+#[derive(Default)]
+pub struct PatchStaging {
+    next_id: AtomicU64,
+    pending: Mutex<HashMap<u64, StagedPatch>>,
+}
+
+impl PatchStaging {
+    /// 全局的暂存区，内置工具默认使用
+    pub fn global() -> Arc<PatchStaging> {
+        STAGING.get_or_init(Default::default).clone()
+    }
+
+    /// 等待审批的修改，按id排序
+    pub fn pending(&self) -> Vec<StagedPatch> {
+        let mut pending: Vec<StagedPatch> = self.lock().values().cloned().collect();
+        pending.sort_by_key(|patch| patch.id);
+        pending
+    }
+
+    /// 人工审批通过并应用修改
+    pub fn approve(&self, id: u64) -> Result<(), PatchError> {
+        let patch = self.lock().remove(&id).ok_or(PatchError::NotFound(id))?;
+        patch.apply()
+    }
+
+    /// 拒绝并丢弃修改
+    pub fn reject(&self, id: u64) -> Result<(), PatchError> {
+        self.lock()
+            .remove(&id)
+            .map(|_| ())
+            .ok_or(PatchError::NotFound(id))
+    }
+
+    fn stage(&self, patch: StagedPatch) {
+        self.lock().insert(patch.id, patch);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, StagedPatch>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub struct ApplyPatchTool {
+    workspace: PathBuf,
+    staging: Arc<PatchStaging>,
+    policy: Option<PatchPolicyFn>,
+}
+
+impl ApplyPatchTool {
+    /// 修改 `workspace` 目录中的文件，使用全局暂存区
+    pub fn new(workspace: impl Into<PathBuf>) -> Self {
+        Self {
+            workspace: workspace.into(),
+            staging: PatchStaging::global(),
+            policy: None,
+        }
+    }
+
+    pub fn staging(mut self, staging: Arc<PatchStaging>) -> Self {
+        self.staging = staging;
+        self
+    }
+
+    /// 审批策略，批准的修改立即应用
+    pub fn policy(mut self, policy: PatchPolicyFn) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// 工作目录中的路径，拒绝绝对路径、`..` 和解析后指向工作目录之外的符号链接
+    fn resolve(&self, path: &str) -> Result<PathBuf, PatchError> {
+        let outside = || PatchError::OutsideWorkspace(path.to_string());
+        let mut relative = PathBuf::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(part) => relative.push(part),
+                Component::CurDir => {}
+                _ => return Err(outside()),
+            }
+        }
+        if relative.as_os_str().is_empty() || relative.starts_with(PATCH_DIR) {
+            return Err(outside());
+        }
+        let root = self.workspace.canonicalize()?;
+        let target = root.join(relative);
+        if !inside_workspace(&root, &target) {
+            return Err(outside());
+        }
+        Ok(target)
+    }
+
+    fn stage(&self, args: &PatchArgs) -> Result<StagedPatch, PatchError> {
+        // 同一个文件的多次修改依次叠加
+        let mut files: Vec<StagedFile> = Vec::new();
+        let mut display: Vec<String> = Vec::new();
+        for edit in &args.edits {
+            let path = self.resolve(edit.path())?;
+            let index = match files.iter().position(|file| file.path == path) {
+                Some(index) => index,
+                None => {
+                    let before = read_optional(&path)?;
+                    files.push(StagedFile {
+                        path: path.clone(),
+                        after: before.clone(),
+                        before,
+                    });
+                    display.push(edit.path().to_string());
+                    files.len() - 1
+                }
+            };
+            let file = &mut files[index];
+            file.after = match edit {
+                FileEdit::Write { content, .. } => Some(content.clone()),
+                FileEdit::Delete { .. } => None,
+                FileEdit::Replace { old, new, .. } => {
+                    let current = file.after.as_deref().unwrap_or_default();
+                    if old.is_empty() || current.matches(old.as_str()).count() != 1 {
+                        return Err(PatchError::NoMatch(edit.path().to_string()));
+                    }
+                    Some(current.replacen(old.as_str(), new, 1))
+                }
+            };
+        }
+
+        let diff = files
+            .iter()
+            .zip(&display)
+            .map(|(file, name)| {
+                unified_diff(
+                    name,
+                    file.before.as_deref().unwrap_or_default(),
+                    file.after.as_deref().unwrap_or_default(),
+                )
+            })
+            .collect::<Result<String, _>>()?;
+        Ok(StagedPatch {
+            id: self.staging.next_id.fetch_add(1, Ordering::SeqCst) + 1,
+            workspace: self.workspace.clone(),
+            diff,
+            files,
+        })
+    }
+}
+
+/// `target` 已存在的最深一级路径解析符号链接后仍在 `root` 中，并且不在 [PATCH_DIR] 中
+fn inside_workspace(root: &Path, target: &Path) -> bool {
+    let mut existing = target;
+    while existing.symlink_metadata().is_err() {
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => return false,
+        }
+    }
+    match existing.canonicalize() {
+        Ok(real) => real.starts_with(root) && !real.starts_with(root.join(PATCH_DIR)),
+        // 失效的符号链接
+        Err(_) => false,
+    }
+}
+
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// 逐行比较表的大小上限，约32MB
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// 按行比较的 unified diff，每段保留3行上下文。
+///
+/// 相同的开头和结尾不参与比较，剩余部分的行数乘积超过 [MAX_DIFF_CELLS] 时返回 [PatchError::TooLarge]
+pub fn unified_diff(path: &str, before: &str, after: &str) -> Result<String, PatchError> {
+    const CONTEXT: usize = 3;
+    let old: Vec<&str> = before.lines().collect();
+    let new: Vec<&str> = after.lines().collect();
+    if old == new {
+        return Ok(String::new());
+    }
+
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+    let (rows, cols) = (old_end - prefix, new_end - prefix);
+    if (rows + 1).saturating_mul(cols + 1) > MAX_DIFF_CELLS {
+        return Err(PatchError::TooLarge(path.to_string()));
+    }
+
+    // 中间部分的最长公共子序列
+    let mut lcs = vec![vec![0usize; cols + 1]; rows + 1];
+    for i in (0..rows).rev() {
+        for j in (0..cols).rev() {
+            lcs[i][j] = if old[prefix + i] == new[prefix + j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    // (标记, 旧行号, 新行号, 内容)
+    let mut lines: Vec<(char, usize, usize, &str)> =
+        (0..prefix).map(|k| (' ', k, k, old[k])).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < rows || j < cols {
+        let (a, b) = (prefix + i, prefix + j);
+        if i < rows && j < cols && old[a] == new[b] {
+            lines.push((' ', a, b, old[a]));
+            i += 1;
+            j += 1;
+        } else if i < rows && (j == cols || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', a, b, old[a]));
+            i += 1;
+        } else {
+            lines.push(('+', a, b, new[b]));
+            j += 1;
+        }
+    }
+    lines.extend((0..suffix).map(|k| (' ', old_end + k, new_end + k, old[old_end + k])));
+
+    let mut out = format!("--- a/{path}\n+++ b/{path}\n");
+    let changed: Vec<usize> = (0..lines.len()).filter(|&k| lines[k].0 != ' ').collect();
+    let mut k = 0;
+    while k < changed.len() {
+        let start = changed[k].saturating_sub(CONTEXT);
+        let mut end = changed[k];
+        while k < changed.len() && changed[k] <= end + 2 * CONTEXT {
+            end = changed[k];
+            k += 1;
+        }
+        let end = (end + CONTEXT + 1).min(lines.len());
+        let hunk = &lines[start..end];
+        let old_len = hunk.iter().filter(|line| line.0 != '+').count();
+        let new_len = hunk.iter().filter(|line| line.0 != '-').count();
+        let (_, old_start, new_start, _) = hunk[0];
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + usize::from(old_len > 0),
+            old_len,
+            new_start + usize::from(new_len > 0),
+            new_len
+        ));
+        for (mark, _, _, text) in hunk {
+            out.push(*mark);
+            out.push_str(text);
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+impl Tool for ApplyPatchTool {
+    const NAME: &'static str = "apply_patch";
+
+    type Args = PatchArgs;
+    type Output = PatchOutput;
+    type Error = PatchError;
+
+    fn definition(&self) -> ToolDefinition {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "edits": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "op": { "type": "string", "enum": ["write", "replace", "delete"] },
+                            "path": { "type": "string", "description": "path relative to the workspace" },
+                            "content": { "type": "string", "description": "full file content for write" },
+                            "old": { "type": "string", "description": "unique text to replace" },
+                            "new": { "type": "string", "description": "replacement text" }
+                        },
+                        "required": ["op", "path"]
+                    }
+                }
+            },
+            "required": ["edits"]
+        });
+        ToolDefinition::new(
+            Self::NAME,
+            "Edit files in the task workspace. Edits are applied after review.",
+            schema.as_object().cloned().unwrap_or_default(),
+        )
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let patch_dir = self.workspace.join(PATCH_DIR);
+        fs::create_dir_all(&patch_dir)?;
+        let patch = self.stage(&args)?;
+        fs::write(patch_dir.join(format!("{}.diff", patch.id)), &patch.diff)?;
+
+        let decision = match &self.policy {
+            Some(policy) => policy(&PolicySubject {
+                input: patch.diff.clone(),
+                tools: vec![Self::NAME.to_string()],
+                ..Default::default()
+            }),
+            None => PolicyDecision::RequireApproval("no policy configured".to_string()),
+        };
+        let (patch_id, diff) = (patch.id, patch.diff.clone());
+        let status = match decision {
+            PolicyDecision::Approve => {
+                patch.apply()?;
+                PatchStatus::Applied
+            }
+            PolicyDecision::RequireApproval(reason) => {
+                tracing::info!("patch {} awaiting approval: {}", patch_id, reason);
+                self.staging.stage(patch);
+                PatchStatus::PendingApproval
+            }
+            PolicyDecision::Reject(reason) => return Err(PatchError::Rejected(reason)),
+        };
+        Ok(PatchOutput {
+            patch_id,
+            status,
+            diff,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stages_until_approved() {
+        let dir = std::env::temp_dir().join(format!("benben-patch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.rs"), "fn main() {\n    old();\n}\n").unwrap();
+        let staging = Arc::new(PatchStaging::default());
+        let tool = ApplyPatchTool::new(&dir).staging(staging.clone());
+
+        let output = Tool::call(
+            &tool,
+            PatchArgs {
+                edits: vec![
+                    FileEdit::Replace {
+                        path: "main.rs".to_string(),
+                        old: "old()".to_string(),
+                        new: "new()".to_string(),
+                    },
+                    FileEdit::Write {
+                        path: "src/lib.rs".to_string(),
+                        content: "pub fn new() {}\n".to_string(),
+                    },
+                ],
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.status, PatchStatus::PendingApproval);
+        assert!(output.diff.contains("-    old();\n+    new();\n"));
+        assert!(!dir.join("src/lib.rs").exists());
+
+        staging.approve(output.patch_id).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("main.rs")).unwrap(),
+            "fn main() {\n    new();\n}\n"
+        );
+        assert!(dir.join("src/lib.rs").exists());
+
+        let escape = Tool::call(
+            &tool,
+            PatchArgs {
+                edits: vec![FileEdit::Delete {
+                    path: "../outside".to_string(),
+                }],
+            },
+        )
+        .await;
+        assert!(matches!(escape, Err(PatchError::OutsideWorkspace(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn paths_resolve_inside_the_workspace() {
+        let root = std::env::temp_dir().join(format!("benben-patch-link-{}", std::process::id()));
+        let (dir, outside) = (root.join("workspace"), root.join("outside"));
+        fs::create_dir_all(&dir).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, dir.join("link")).unwrap();
+        let tool = ApplyPatchTool::new(&dir).policy(Arc::new(|_| PolicyDecision::Approve));
+        let edit = |edits| Tool::call(&tool, PatchArgs { edits });
+
+        for path in ["link/escape.txt", "./.patches/1.diff"] {
+            let escape = edit(vec![FileEdit::Write {
+                path: path.to_string(),
+                content: "x".to_string(),
+            }])
+            .await;
+            assert!(matches!(escape, Err(PatchError::OutsideWorkspace(_))));
+        }
+        assert!(!outside.join("escape.txt").exists());
+
+        // 删除不存在的文件不做任何修改
+        let output = edit(vec![
+            FileEdit::Write {
+                path: "./tmp.txt".to_string(),
+                content: "x".to_string(),
+            },
+            FileEdit::Delete {
+                path: "tmp.txt".to_string(),
+            },
+        ])
+        .await
+        .unwrap();
+        assert_eq!(output.status, PatchStatus::Applied);
+        assert!(!dir.join("tmp.txt").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn large_files_diff_only_the_changed_lines() {
+        let before: String = (0..20_000).map(|i| format!("line {i}\n")).collect();
+        let after = before.replacen("line 10000\n", "changed\n", 1);
+        let diff = unified_diff("big.txt", &before, &after).unwrap();
+        assert!(diff.contains("@@ -9998,7 +9998,7 @@\n"));
+        assert!(diff.contains("-line 10000\n+changed\n"));
+
+        let rewritten: String = (0..20_000).map(|i| format!("new {i}\n")).collect();
+        assert!(matches!(
+            unified_diff("big.txt", &before, &rewritten),
+            Err(PatchError::TooLarge(_))
+        ));
+    }
+}
@@ -0,0 +1,199 @@
+//! 分层组合agent的系统提示。
+//!
+//! 每个agent的 `sys_promte` 原本是一整段文字，所有agent共同的要求（组织的规范、输出语言等）只能复制到
+//! 每个agent中，修改时容易遗漏。系统提示因此按以下顺序由三层组合，层之间空一行：
+//!
+//! 1、全局层：所有agent共享的组织规范
+//! 2、工作流层：按工作流id配置，只对执行该工作流的请求生效
+//! 3、agent层：agent自己的系统提示，启用的版本优先于配置，见 [crate::prompt_version]
+//!
+//! 组合后的文字使用 minijinja 语法渲染，可以引用以下变量，引用不存在的变量会报错：
+//!
+//! ```text
+//! 你是 {{ vars.org }} 的 {{ agent.name }}（{{ agent.code }}），当前工作流：{{ workflow.id }}。
+//! ```
+//!
+//! 没有配置全局层、工作流层和变量时agent的系统提示原样使用，不经过渲染。
+//! 从环境变量读取配置：
+//!
+//! ```text
+//! preamble.global=所有回答使用中文。
+//! preamble.vars={"org":"benben"}
+//! preamble.workflow.orders=订单相关的金额保留两位小数。
+//! ```
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::engine::template::{render_template, TemplateError};
+
+const ENV_PREFIX: &str = "preamble.";
+const WORKFLOW_ENV_PREFIX: &str = "preamble.workflow.";
+
+/// 全局层、工作流层以及模板变量
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PreambleLayers {
+    pub global: Option<String>,
+    /// 工作流id为键
+    pub workflows: HashMap<String, String>,
+    /// 模板中通过 `vars.xxx` 引用的变量
+    pub vars: HashMap<String, String>,
+}
+
+/// 渲染时引用的agent信息
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AgentVars {
+    pub code: String,
+    pub name: String,
+    pub desc: String,
+}
+
+#[derive(Serialize)]
+struct WorkflowVars<'a> {
+    id: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct LayerVars<'a> {
+    agent: &'a AgentVars,
+    workflow: WorkflowVars<'a>,
+    vars: &'a HashMap<String, String>,
+}
+
+impl PreambleLayers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 读取 `preamble.global`、`preamble.vars` 和 `preamble.workflow.{id}` 环境变量
+    pub fn from_env() -> Self {
+        let mut layers = Self::new();
+        for (key, value) in std::env::vars() {
+            if let Some(workflow_id) = key.strip_prefix(WORKFLOW_ENV_PREFIX) {
+                layers.workflows.insert(workflow_id.to_string(), value);
+                continue;
+            }
+            match key.strip_prefix(ENV_PREFIX) {
+                Some("global") => layers.global = Some(value),
+                Some("vars") => match serde_json::from_str(&value) {
+                    Ok(vars) => layers.vars = vars,
+                    Err(e) => tracing::warn!("invalid preamble.vars: {e}"),
+                },
+                _ => {}
+            }
+        }
+        layers
+    }
+
+    pub fn with_global(mut self, preamble: impl Into<String>) -> Self {
+        self.global = Some(preamble.into());
+        self
+    }
+
+    pub fn with_workflow(
+        mut self,
+        workflow_id: impl Into<String>,
+        preamble: impl Into<String>,
+    ) -> Self {
+        self.workflows.insert(workflow_id.into(), preamble.into());
+        self
+    }
+
+    pub fn with_var(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.vars.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.global.is_none() && self.workflows.is_empty() && self.vars.is_empty()
+    }
+
+    /// 按 全局、工作流、agent 的顺序组合并渲染系统提示，所有层都为空时返回空
+    pub fn compose(
+        &self,
+        agent: &AgentVars,
+        workflow_id: Option<&str>,
+        agent_preamble: Option<&str>,
+    ) -> Result<Option<String>, TemplateError> {
+        if self.is_empty() {
+            return Ok(agent_preamble.map(str::to_string));
+        }
+        let workflow = workflow_id.and_then(|id| self.workflows.get(id));
+        let layers: Vec<&str> = [
+            self.global.as_deref(),
+            workflow.map(String::as_str),
+            agent_preamble,
+        ]
+        .into_iter()
+        .flatten()
+        .map(str::trim)
+        .filter(|layer| !layer.is_empty())
+        .collect();
+        if layers.is_empty() {
+            return Ok(None);
+        }
+        let vars = LayerVars {
+            agent,
+            workflow: WorkflowVars { id: workflow_id },
+            vars: &self.vars,
+        };
+        Ok(Some(render_template(&layers.join("\n\n"), &vars)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rig::agent::AgentBuilder;
+    use rig::client::completion::CompletionModelHandle;
+    use rig::client::mock::{self, MockClient};
+    use rig::client::CompletionClient;
+
+    use super::*;
+    use crate::mananger::{AgentManager, ExecuteContext};
+
+    #[tokio::test]
+    async fn layers_are_composed_in_order() {
+        let layers = PreambleLayers::new()
+            .with_global("Work for {{ vars.org }}.")
+            .with_workflow("orders", "Round amounts in {{ workflow.id }}.")
+            .with_var("org", "benben");
+        let agent = AgentVars {
+            code: "analyst".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            layers
+                .compose(&agent, None, Some("You are {{ agent.code }}."))
+                .unwrap()
+                .as_deref(),
+            Some("Work for benben.\n\nYou are analyst.")
+        );
+        assert!(layers
+            .compose(&agent, None, Some("{{ agent.missing }}"))
+            .is_err());
+
+        let handle = CompletionModelHandle {
+            inner: Arc::new(MockClient::new().completion_model("preamble-layers")),
+        };
+        let mut manager = AgentManager::default();
+        manager.agent_map.insert(
+            "analyst".to_string(),
+            Arc::new(AgentBuilder::new(handle).preamble("Count orders.").build()),
+        );
+        manager.set_layers(layers);
+        let script = mock::script("preamble-layers");
+        script.push_text("12");
+        let context = ExecuteContext {
+            workflow_id: Some("orders".into()),
+            ..Default::default()
+        };
+        manager.execute("analyst", "march", context).await.unwrap();
+        assert_eq!(
+            script.requests()[0].preamble.as_deref(),
+            Some("Work for benben.\n\nRound amounts in orders.\n\nCount orders.")
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! agent系统提示的版本管理。
+//!
+//! 修改系统提示时先创建草稿版本，确认后再启用，同一个agent最多只有一个启用的版本，之前启用的
+//! 版本变为 retired，随时可以重新启用旧版本回滚。[AgentManager] 通过 [AgentManager::reload_prompts]
+//! 读取启用的版本，之后的请求使用启用的系统提示代替 AgentConfig 中的 `sys_promte`；
+//! 每次job执行都在 job_run 中记录使用的版本号，便于事后按版本比较效果。
+//!
+//! ```rust,ignore
+//! let draft = prompt_version::create_draft(&db, "analyst", "You count orders.", None).await?;
+//! prompt_version::activate(&db, "analyst", draft.version).await?;
+//! AgentManager::try_global()?.reload_prompts(&db).await?;
+//! ```
+//!
+//! [AgentManager]: crate::mananger::AgentManager
+//! [AgentManager::reload_prompts]: crate::mananger::AgentManager::reload_prompts
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::entities::prompt_version;
+
+/// 版本的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptState {
+    Draft,
+    Active,
+    /// 曾经启用过，被新的版本替换
+    Retired,
+}
+
+impl PromptState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PromptState::Draft => "draft",
+            PromptState::Active => "active",
+            PromptState::Retired => "retired",
+        }
+    }
+
+    pub fn parse(state: &str) -> Option<PromptState> {
+        match state {
+            "draft" => Some(PromptState::Draft),
+            "active" => Some(PromptState::Active),
+            "retired" => Some(PromptState::Retired),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PromptVersionError {
+    #[error("Agent {0} has no prompt version {1}")]
+    NotFound(String, i32),
+    #[error(transparent)]
+    Db(#[from] DbErr),
+}
+
+/// 创建一个草稿版本，版本号为这个agent当前最大的版本号加1
+pub async fn create_draft(
+    db: &DatabaseConnection,
+    agent: &str,
+    preamble: &str,
+    note: Option<String>,
+) -> Result<prompt_version::Model, PromptVersionError> {
+    let latest = prompt_version::Entity::find()
+        .filter(prompt_version::Column::Agent.eq(agent))
+        .order_by_desc(prompt_version::Column::Version)
+        .one(db)
+        .await?;
+    let version = latest.map(|v| v.version).unwrap_or_default() + 1;
+    let draft = prompt_version::ActiveModel {
+        id: NotSet,
+        agent: Set(agent.to_string()),
+        version: Set(version),
+        preamble: Set(preamble.to_string()),
+        state: Set(PromptState::Draft.as_str().to_string()),
+        note: Set(note),
+        created_at: Set(now_millis()),
+        activated_at: Set(None),
+    }
+    .insert(db)
+    .await?;
+    Ok(draft)
+}
+
+/// agent的所有版本，按版本号排序
+pub async fn list_versions(
+    db: &DatabaseConnection,
+    agent: &str,
+) -> Result<Vec<prompt_version::Model>, PromptVersionError> {
+    Ok(prompt_version::Entity::find()
+        .filter(prompt_version::Column::Agent.eq(agent))
+        .order_by_asc(prompt_version::Column::Version)
+        .all(db)
+        .await?)
+}
+
+/// 所有agent启用的版本
+pub async fn active_versions(
+    db: &DatabaseConnection,
+) -> Result<Vec<prompt_version::Model>, PromptVersionError> {
+    Ok(prompt_version::Entity::find()
+        .filter(prompt_version::Column::State.eq(PromptState::Active.as_str()))
+        .all(db)
+        .await?)
+}
+
+/// 启用一个版本，agent之前启用的版本变为 retired。[AgentManager] 重新加载后生效
+///
+/// [AgentManager]: crate::mananger::AgentManager
+pub async fn activate(
+    db: &DatabaseConnection,
+    agent: &str,
+    version: i32,
+) -> Result<prompt_version::Model, PromptVersionError> {
+    let txn = db.begin().await?;
+    let target = prompt_version::Entity::find()
+        .filter(prompt_version::Column::Agent.eq(agent))
+        .filter(prompt_version::Column::Version.eq(version))
+        .one(&txn)
+        .await?
+        .ok_or_else(|| PromptVersionError::NotFound(agent.to_string(), version))?;
+    let current = prompt_version::Entity::find()
+        .filter(prompt_version::Column::Agent.eq(agent))
+        .filter(prompt_version::Column::State.eq(PromptState::Active.as_str()))
+        .all(&txn)
+        .await?;
+    for row in current.into_iter().filter(|row| row.id != target.id) {
+        let mut retired: prompt_version::ActiveModel = row.into();
+        retired.state = Set(PromptState::Retired.as_str().to_string());
+        retired.update(&txn).await?;
+    }
+    let mut active: prompt_version::ActiveModel = target.into();
+    active.state = Set(PromptState::Active.as_str().to_string());
+    active.activated_at = Set(Some(now_millis()));
+    let active = active.update(&txn).await?;
+    txn.commit().await?;
+    tracing::info!("activated prompt version {} of agent {}", version, agent);
+    Ok(active)
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rig::agent::AgentBuilder;
+    use rig::client::completion::CompletionModelHandle;
+    use rig::client::mock::{self, MockClient};
+    use rig::client::CompletionClient;
+    use sea_orm::Database;
+
+    use super::*;
+    use crate::mananger::{AgentManager, ExecuteContext};
+    use crate::migrate::create_schema;
+
+    #[tokio::test]
+    async fn manager_uses_the_active_version() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&db).await.unwrap();
+        let handle = CompletionModelHandle {
+            inner: Arc::new(MockClient::new().completion_model("prompt-version")),
+        };
+        let mut manager = AgentManager::default();
+        manager.agent_map.insert(
+            "analyst".to_string(),
+            Arc::new(AgentBuilder::new(handle).preamble("v0").build()),
+        );
+
+        create_draft(&db, "analyst", "v1", None).await.unwrap();
+        let v2 = create_draft(&db, "analyst", "v2", Some("shorter".into()))
+            .await
+            .unwrap();
+        assert_eq!(v2.version, 2);
+        activate(&db, "analyst", 1).await.unwrap();
+        activate(&db, "analyst", 2).await.unwrap();
+        assert!(activate(&db, "analyst", 3).await.is_err());
+        let states: Vec<String> = list_versions(&db, "analyst")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|v| v.state)
+            .collect();
+        assert_eq!(states, vec!["retired", "active"]);
+
+        assert_eq!(manager.prompt_version("analyst"), None);
+        assert_eq!(manager.reload_prompts(&db).await.unwrap(), 1);
+        assert_eq!(manager.prompt_version("analyst"), Some(2));
+        let script = mock::script("prompt-version");
+        script.push_text("12");
+        manager
+            .execute("analyst", "count", ExecuteContext::default())
+            .await
+            .unwrap();
+        assert_eq!(script.requests()[0].preamble.as_deref(), Some("v2"));
+    }
+}
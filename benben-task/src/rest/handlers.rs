@@ -0,0 +1,835 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::extract::{FromRequestParts, Path, Query, State};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::Json;
+use futures::Stream;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+pub use benben_api::models::{
+    AgentView, ApprovalView, ArtifactView, BulkFailureView, BulkReportView, DryRunRequest,
+    ExampleRequest, ExampleView, GoldenRequest, GoldenView, JobRunView, PromptDraftRequest,
+    PromptVersionView, ResumeStoppedRequest, TaskAction, TaskSummary, TaskView, TransitionView,
+    TriggeredTask, WorkflowView,
+};
+pub use benben_api::OWNER_HEADER;
+
+use super::ApiError;
+use crate::api::{ErrorCode, ErrorEnvelope};
+use crate::engine::bulk::BulkReport;
+use crate::engine::plan::PlanStepView;
+use crate::engine::preview::{dry_run, DryRunPlan};
+use crate::engine::queue::BlockReason;
+use crate::engine::replay::ToolLogArgs;
+use crate::engine::trace::TraceFormat;
+use crate::engine::trigger::{TriggerError, SECRET_HEADER};
+use crate::engine::versioning::{self, VersionDiff, VersionError, VersionInfo};
+use crate::engine::TaskEngine;
+use crate::entities::{agent_example, job_run, prompt_version, task_transition, workflow};
+use crate::example_library::{ExampleError, ExampleLibrary};
+use crate::mananger::AgentManager;
+use crate::prompt_version::PromptVersionError;
+use crate::soft_delete;
+use crate::worker::ControlCommand;
+
+type EngineState = State<Arc<TaskEngine>>;
+
+/// 发起请求的用户，来自 [OWNER_HEADER] 请求头。带有用户时任务接口只能看到和操作该用户的任务，
+/// 其他用户的任务返回未找到；没有这个请求头时不过滤，用于单用户部署
+#[derive(Debug, Clone, Default)]
+pub struct Owner(pub Option<String>);
+
+impl<S: Send + Sync> FromRequestParts<S> for Owner {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let owner = parts
+            .headers
+            .get(OWNER_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        Ok(Owner(owner))
+    }
+}
+
+impl Owner {
+    /// 任务对发起请求的用户可见
+    async fn check(&self, engine: &TaskEngine, task_id: i32) -> Result<(), ApiError> {
+        if let Some(owner) = &self.0 {
+            engine
+                .for_owner(owner.as_str())
+                .ensure_owned(task_id)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+impl From<task_transition::Model> for TransitionView {
+    fn from(row: task_transition::Model) -> Self {
+        Self {
+            from: row.from_state,
+            to: row.to_state,
+            actor: row.actor,
+            reason: row.reason,
+            created_at: row.created_at,
+        }
+    }
+}
+
+impl From<job_run::Model> for JobRunView {
+    fn from(row: job_run::Model) -> Self {
+        Self {
+            job_id: row.job_id,
+            attempt: row.attempt,
+            agent: row.agent,
+            prompt: row.prompt,
+            output: row.output,
+            input_tokens: row.input_tokens,
+            output_tokens: row.output_tokens,
+            duration_ms: row.duration_ms,
+            status: row.status,
+            started_at: row.started_at,
+            prompt_version: row.prompt_version,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobRunsQuery {
+    #[serde(default)]
+    pub job_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TraceQuery {
+    #[serde(default)]
+    pub format: TraceFormat,
+}
+
+impl From<workflow::Model> for WorkflowView {
+    fn from(workflow: workflow::Model) -> Self {
+        Self {
+            id: workflow.id,
+            code: workflow.code,
+            name: workflow.name,
+            desc: workflow.desc,
+            plan: workflow.plan,
+        }
+    }
+}
+
+impl From<agent_example::Model> for ExampleView {
+    fn from(example: agent_example::Model) -> Self {
+        Self {
+            id: example.id,
+            input: example.input,
+            output: example.output,
+        }
+    }
+}
+
+impl From<prompt_version::Model> for PromptVersionView {
+    fn from(row: prompt_version::Model) -> Self {
+        Self {
+            version: row.version,
+            preamble: row.preamble,
+            state: row.state,
+            note: row.note,
+            created_at: row.created_at,
+            activated_at: row.activated_at,
+        }
+    }
+}
+
+fn prompt_error(err: PromptVersionError) -> ApiError {
+    match err {
+        PromptVersionError::NotFound(..) => ApiError(
+            StatusCode::NOT_FOUND,
+            ErrorEnvelope::not_found(err.to_string()),
+        ),
+        PromptVersionError::Db(e) => ApiError::from(e),
+    }
+}
+
+fn example_library() -> Result<Arc<ExampleLibrary>, ApiError> {
+    ExampleLibrary::global().ok_or_else(|| {
+        ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorEnvelope::internal("example library is not initialized"),
+        )
+    })
+}
+
+fn example_error(err: ExampleError) -> ApiError {
+    let status = match err {
+        ExampleError::NoDatabase => StatusCode::SERVICE_UNAVAILABLE,
+        ExampleError::Db(_) | ExampleError::Embedding(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    ApiError(status, ErrorEnvelope::internal(err.to_string()))
+}
+
+fn db_of(engine: &TaskEngine) -> Result<Arc<sea_orm::DatabaseConnection>, ApiError> {
+    engine.db().ok_or_else(|| {
+        ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorEnvelope::internal("engine has no database"),
+        )
+    })
+}
+
+/// 所有任务
+#[utoipa::path(get, path = "/tasks", tag = "tasks",
+    params(("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只能访问该用户的任务")),
+    responses((status = 200, body = Vec<TaskSummary>)))]
+pub async fn list_tasks(State(engine): EngineState, owner: Owner) -> Json<Vec<TaskSummary>> {
+    let ids = match &owner.0 {
+        Some(owner) => engine.for_owner(owner.as_str()).list_tasks().await,
+        None => engine.list_tasks().await,
+    };
+    let mut tasks = Vec::new();
+    for id in ids {
+        if let Ok(state) = engine.get_state(id).await {
+            tasks.push(TaskSummary { id, state });
+        }
+    }
+    tasks.sort_by_key(|task| task.id);
+    Json(tasks)
+}
+
+/// 任务详情
+#[utoipa::path(get, path = "/tasks/{id}", tag = "tasks",
+    params(("id" = i32, Path, description = "任务id"), ("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只能访问该用户的任务")),
+    responses(
+        (status = 200, body = TaskView),
+        (status = 404, body = ErrorEnvelope),
+    ))]
+pub async fn get_task(
+    State(engine): EngineState,
+    owner: Owner,
+    Path(id): Path<i32>,
+) -> Result<Json<TaskView>, ApiError> {
+    owner.check(&engine, id).await?;
+    let state = engine.get_state(id).await?;
+    let usage = engine.get_usage(id).await?;
+    let history = engine.get_execution_history(id).await?;
+    let cost = engine.get_cost(id).await?;
+    Ok(Json(TaskView {
+        id,
+        state,
+        history,
+        input_tokens: usage.input_tokens,
+        output_tokens: usage.output_tokens,
+        cost,
+    }))
+}
+
+/// 改变任务状态。暂停、继续、停止和取消的任务不在当前进程中时，由持有任务的 worker 执行
+#[utoipa::path(post, path = "/tasks/{id}/{action}", tag = "tasks",
+    params(
+        ("id" = i32, Path, description = "任务id"),
+        ("action" = TaskAction, Path),
+        ("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只能访问该用户的任务"),
+    ),
+    responses(
+        (status = 204),
+        (status = 404, body = ErrorEnvelope),
+        (status = 409, body = ErrorEnvelope),
+    ))]
+pub async fn task_action(
+    State(engine): EngineState,
+    owner: Owner,
+    Path((id, action)): Path<(i32, TaskAction)>,
+) -> Result<StatusCode, ApiError> {
+    owner.check(&engine, id).await?;
+    match action {
+        TaskAction::Start => engine.start(id).await?,
+        // 控制命令经过 send_command，任务由其他 worker 持有时写入命令发件箱
+        TaskAction::Pause => engine.send_command(id, ControlCommand::Pause).await?,
+        TaskAction::Resume => engine.send_command(id, ControlCommand::Resume).await?,
+        TaskAction::Stop => engine.send_command(id, ControlCommand::Stop).await?,
+        TaskAction::Cancel => engine.send_command(id, ControlCommand::Cancel).await?,
+        TaskAction::Finish => engine.finish(id).await?,
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 等待人工审批的job
+#[utoipa::path(get, path = "/approvals", tag = "tasks",
+    params(("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只能访问该用户的任务")),
+    responses((status = 200, body = Vec<ApprovalView>)))]
+pub async fn list_approvals(State(engine): EngineState, owner: Owner) -> Json<Vec<ApprovalView>> {
+    let mut entries = engine.inspect_queue().await;
+    let mut visible = Vec::with_capacity(entries.len());
+    for entry in entries.drain(..) {
+        if owner.check(&engine, entry.task_id).await.is_ok() {
+            visible.push(entry);
+        }
+    }
+    let approvals = visible
+        .into_iter()
+        .flat_map(|entry| {
+            entry
+                .reasons
+                .into_iter()
+                .filter_map(move |reason| match reason {
+                    BlockReason::AwaitingApproval { job_id, reason } => Some(ApprovalView {
+                        task_id: entry.task_id,
+                        job_id,
+                        reason,
+                    }),
+                    BlockReason::AwaitingBudget { report } => Some(ApprovalView {
+                        task_id: entry.task_id,
+                        job_id: report.job_id,
+                        reason: report.describe(),
+                    }),
+                    _ => None,
+                })
+        })
+        .collect();
+    Json(approvals)
+}
+
+/// 审批通过任务的job
+#[utoipa::path(post, path = "/tasks/{id}/jobs/{job_id}/approve", tag = "tasks",
+    params(
+        ("id" = i32, Path, description = "任务id"),
+        ("job_id" = i32, Path, description = "jobid"),
+        ("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只能访问该用户的任务"),
+    ),
+    responses(
+        (status = 204),
+        (status = 404, body = ErrorEnvelope),
+    ))]
+pub async fn approve_job(
+    State(engine): EngineState,
+    owner: Owner,
+    Path((id, job_id)): Path<(i32, i32)>,
+) -> Result<StatusCode, ApiError> {
+    owner.check(&engine, id).await?;
+    engine.approve_job(id, job_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 任务的产物
+#[utoipa::path(get, path = "/tasks/{id}/artifacts", tag = "tasks",
+    params(("id" = i32, Path, description = "任务id"), ("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只能访问该用户的任务")),
+    responses((status = 200, body = Vec<ArtifactView>)))]
+pub async fn list_artifacts(
+    State(engine): EngineState,
+    owner: Owner,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<ArtifactView>>, ApiError> {
+    owner.check(&engine, id).await?;
+    let store = engine.store().ok_or_else(|| {
+        ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorEnvelope::internal("engine has no task store"),
+        )
+    })?;
+    let logs = store.load_tool_logs(id).await.map_err(|err| {
+        ApiError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorEnvelope::internal(err.to_string()),
+        )
+    })?;
+    Ok(Json(
+        logs.into_iter()
+            .map(|log| {
+                let args = log
+                    .args
+                    .as_deref()
+                    .and_then(|args| serde_json::from_str::<ToolLogArgs>(args).ok());
+                ArtifactView {
+                    id: log.id,
+                    job_id: args.as_ref().map(|args| args.job_id),
+                    rejected: args.as_ref().is_some_and(|args| args.rejected),
+                    reasoning: args.as_ref().is_some_and(|args| args.reasoning),
+                    evidence: args.is_some_and(|args| args.evidence),
+                    output: log.output,
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// 任务的计划步骤及其状态
+#[utoipa::path(get, path = "/tasks/{id}/plan", tag = "tasks",
+    params(("id" = i32, Path, description = "任务id"), ("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只能访问该用户的任务")),
+    responses((status = 200, body = Vec<PlanStepView>)))]
+pub async fn get_plan(
+    State(engine): EngineState,
+    owner: Owner,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<PlanStepView>>, ApiError> {
+    owner.check(&engine, id).await?;
+    Ok(Json(engine.get_plan(id).await?))
+}
+
+/// 任务的状态变更记录，按发生顺序排列
+#[utoipa::path(get, path = "/tasks/{id}/transitions", tag = "tasks",
+    params(("id" = i32, Path, description = "任务id"), ("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只能访问该用户的任务")),
+    responses((status = 200, body = Vec<TransitionView>)))]
+pub async fn list_transitions(
+    State(engine): EngineState,
+    owner: Owner,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<TransitionView>>, ApiError> {
+    owner.check(&engine, id).await?;
+    let transitions = engine.get_transitions(id).await?;
+    Ok(Json(
+        transitions.into_iter().map(TransitionView::from).collect(),
+    ))
+}
+
+/// 任务中job的每一次执行尝试，按开始顺序排列
+#[utoipa::path(get, path = "/tasks/{id}/runs", tag = "tasks",
+    params(
+        ("id" = i32, Path, description = "任务id"),
+        ("job_id" = Option<i32>, Query, description = "只返回这个job的尝试"),
+        ("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只能访问该用户的任务"),
+    ),
+    responses((status = 200, body = Vec<JobRunView>)))]
+pub async fn list_job_runs(
+    State(engine): EngineState,
+    owner: Owner,
+    Path(id): Path<i32>,
+    Query(query): Query<JobRunsQuery>,
+) -> Result<Json<Vec<JobRunView>>, ApiError> {
+    owner.check(&engine, id).await?;
+    let runs = engine.get_job_runs(id, query.job_id).await?;
+    Ok(Json(runs.into_iter().map(JobRunView::from).collect()))
+}
+
+/// 导出任务的执行轨迹，JSONL 格式，每行一个json对象
+#[utoipa::path(get, path = "/tasks/{id}/trace", tag = "tasks",
+    params(
+        ("id" = i32, Path, description = "任务id"),
+        ("format" = Option<String>, Query, description = "openai（默认）或 langsmith"),
+        ("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只能访问该用户的任务"),
+    ),
+    responses((status = 200, content_type = "application/x-ndjson", body = String)))]
+pub async fn export_trace(
+    State(engine): EngineState,
+    owner: Owner,
+    Path(id): Path<i32>,
+    Query(query): Query<TraceQuery>,
+) -> Result<([(header::HeaderName, &'static str); 1], String), ApiError> {
+    owner.check(&engine, id).await?;
+    let trace = engine.export_trace(id, query.format).await?;
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], trace))
+}
+
+fn bulk_view(report: BulkReport) -> BulkReportView {
+    BulkReportView {
+        total: report.succeeded.len() + report.failed.len(),
+        succeeded: report.succeeded,
+        failed: report
+            .failed
+            .into_iter()
+            .map(|(task_id, reason)| BulkFailureView { task_id, reason })
+            .collect(),
+    }
+}
+
+/// 暂停发起请求的用户的所有未结束任务
+#[utoipa::path(post, path = "/bulk/pause", tag = "tasks",
+    params(("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只作用于该用户的任务")),
+    responses((status = 200, body = BulkReportView)))]
+pub async fn bulk_pause(State(engine): EngineState, owner: Owner) -> Json<BulkReportView> {
+    let report = engine.pause_owner_tasks(owner.0.as_deref(), |_| {}).await;
+    Json(bulk_view(report))
+}
+
+/// 恢复发起请求的用户已停止的任务，可以只恢复某个时间之后停止的任务
+#[utoipa::path(post, path = "/bulk/resume", tag = "tasks",
+    params(("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只作用于该用户的任务")),
+    request_body = ResumeStoppedRequest,
+    responses((status = 200, body = BulkReportView)))]
+pub async fn bulk_resume(
+    State(engine): EngineState,
+    owner: Owner,
+    Json(request): Json<ResumeStoppedRequest>,
+) -> Json<BulkReportView> {
+    let report = engine
+        .resume_stopped_tasks(owner.0.as_deref(), request.stopped_since, |_| {})
+        .await;
+    Json(bulk_view(report))
+}
+
+/// 把已完成的任务标记为golden，每个job的 (prompt, 输出) 写入数据集
+#[utoipa::path(post, path = "/tasks/{id}/golden", tag = "tasks",
+    params(("id" = i32, Path, description = "任务id"), ("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只能访问该用户的任务")),
+    request_body = GoldenRequest,
+    responses(
+        (status = 200, body = GoldenView),
+        (status = 403, body = ErrorEnvelope),
+    ))]
+pub async fn mark_golden(
+    State(engine): EngineState,
+    owner: Owner,
+    Path(id): Path<i32>,
+    Json(request): Json<GoldenRequest>,
+) -> Result<Json<GoldenView>, ApiError> {
+    owner.check(&engine, id).await?;
+    let items = engine.mark_golden(id, &request.dataset).await?;
+    Ok(Json(GoldenView {
+        dataset: request.dataset,
+        samples: items.len(),
+    }))
+}
+
+/// 把数据集导出为 OpenAI chat 微调格式的JSONL
+#[utoipa::path(get, path = "/datasets/{name}/export", tag = "datasets",
+    params(("name" = String, Path, description = "数据集名称")),
+    responses(
+        (status = 200, content_type = "application/x-ndjson", body = String),
+        (status = 404, body = ErrorEnvelope),
+    ))]
+pub async fn export_dataset(
+    State(engine): EngineState,
+    Path(name): Path<String>,
+) -> Result<([(header::HeaderName, &'static str); 1], String), ApiError> {
+    let dataset = engine.export_dataset(&name).await?;
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], dataset))
+}
+
+/// 所有未删除的工作流
+#[utoipa::path(get, path = "/workflows", tag = "workflows",
+    responses((status = 200, body = Vec<WorkflowView>)))]
+pub async fn list_workflows(
+    State(engine): EngineState,
+) -> Result<Json<Vec<WorkflowView>>, ApiError> {
+    let db = db_of(&engine)?;
+    let workflows = soft_delete::active_workflows(db.as_ref()).await?;
+    Ok(Json(workflows.into_iter().map(Into::into).collect()))
+}
+
+/// 工作流详情
+#[utoipa::path(get, path = "/workflows/{id}", tag = "workflows",
+    params(("id" = String, Path, description = "工作流id")),
+    responses(
+        (status = 200, body = WorkflowView),
+        (status = 404, body = ErrorEnvelope),
+    ))]
+pub async fn get_workflow(
+    State(engine): EngineState,
+    Path(id): Path<String>,
+) -> Result<Json<WorkflowView>, ApiError> {
+    let db = db_of(&engine)?;
+    let workflow = soft_delete::active_workflow(db.as_ref(), &id)
+        .await?
+        .ok_or_else(|| {
+            ApiError(
+                StatusCode::NOT_FOUND,
+                ErrorEnvelope::not_found(format!("Workflow {id} not found")),
+            )
+        })?;
+    Ok(Json(workflow.into()))
+}
+
+impl From<VersionError> for ApiError {
+    fn from(err: VersionError) -> Self {
+        let status = match err {
+            VersionError::WorkflowNotFound(_) | VersionError::VersionNotFound(..) => {
+                StatusCode::NOT_FOUND
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let envelope = if status == StatusCode::NOT_FOUND {
+            ErrorEnvelope::not_found(err.to_string())
+        } else {
+            ErrorEnvelope::internal(err.to_string())
+        };
+        ApiError(status, envelope)
+    }
+}
+
+impl From<TriggerError> for ApiError {
+    fn from(err: TriggerError) -> Self {
+        let (status, code) = match err {
+            TriggerError::Engine(err) => return err.into(),
+            TriggerError::NotFound(_) | TriggerError::WorkflowNotFound(_) => {
+                (StatusCode::NOT_FOUND, ErrorCode::NotFound)
+            }
+            TriggerError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, ErrorCode::InvalidArgument),
+            TriggerError::Template(_) => (StatusCode::BAD_REQUEST, ErrorCode::InvalidArgument),
+        };
+        ApiError(status, ErrorEnvelope::new(code, err.to_string()))
+    }
+}
+
+/// webhook：使用请求体作为 payload 触发任务，触发器配置了密钥时通过 `x-trigger-secret` 请求头携带
+#[utoipa::path(post, path = "/hooks/{name}", tag = "tasks",
+    params(("name" = String, Path, description = "触发器名称")),
+    request_body = Object,
+    responses(
+        (status = 202, body = TriggeredTask),
+        (status = 401, body = ErrorEnvelope),
+        (status = 404, body = ErrorEnvelope),
+    ))]
+pub async fn fire_webhook(
+    State(engine): EngineState,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(payload): Json<serde_json::Value>,
+) -> Result<(StatusCode, Json<TriggeredTask>), ApiError> {
+    let trigger = engine
+        .trigger(&name)
+        .ok_or_else(|| TriggerError::NotFound(name.clone()))?;
+    let secret = headers.get(SECRET_HEADER).and_then(|v| v.to_str().ok());
+    if !trigger.verify(secret) {
+        return Err(TriggerError::Unauthorized(name).into());
+    }
+    let task_id = engine.fire_trigger(&name, payload).await?;
+    Ok((StatusCode::ACCEPTED, Json(TriggeredTask { task_id })))
+}
+
+/// 工作流的历史版本
+#[utoipa::path(get, path = "/workflows/{id}/versions", tag = "workflows",
+    params(("id" = String, Path, description = "工作流id")),
+    responses((status = 200, body = Vec<VersionInfo>)))]
+pub async fn list_workflow_versions(
+    State(engine): EngineState,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<VersionInfo>>, ApiError> {
+    let db = db_of(&engine)?;
+    Ok(Json(versioning::list_versions(&db, &id).await?))
+}
+
+/// 比较工作流的两个版本
+#[utoipa::path(get, path = "/workflows/{id}/versions/{from}/diff/{to}", tag = "workflows",
+    params(
+        ("id" = String, Path, description = "工作流id"),
+        ("from" = i32, Path, description = "旧版本"),
+        ("to" = i32, Path, description = "新版本"),
+    ),
+    responses(
+        (status = 200, body = VersionDiff),
+        (status = 404, body = ErrorEnvelope),
+    ))]
+pub async fn diff_workflow_versions(
+    State(engine): EngineState,
+    Path((id, from, to)): Path<(String, i32, i32)>,
+) -> Result<Json<VersionDiff>, ApiError> {
+    let db = db_of(&engine)?;
+    Ok(Json(versioning::diff_versions(&db, &id, from, to).await?))
+}
+
+/// 试运行工作流，返回执行计划，不调用模型
+#[utoipa::path(post, path = "/workflows/{id}/dry-run", tag = "workflows",
+    params(("id" = String, Path, description = "工作流id")),
+    request_body = DryRunRequest,
+    responses(
+        (status = 200, body = DryRunPlan),
+        (status = 404, body = ErrorEnvelope),
+    ))]
+pub async fn dry_run_workflow(
+    State(engine): EngineState,
+    Path(id): Path<String>,
+    Json(request): Json<DryRunRequest>,
+) -> Result<Json<DryRunPlan>, ApiError> {
+    let db = db_of(&engine)?;
+    let workflow = soft_delete::active_workflow(db.as_ref(), &id)
+        .await?
+        .ok_or_else(|| {
+            ApiError(
+                StatusCode::NOT_FOUND,
+                ErrorEnvelope::not_found(format!("Workflow {id} not found")),
+            )
+        })?;
+    let jobs = soft_delete::active_jobs(db.as_ref(), &workflow.id).await?;
+    let agents = AgentManager::global()
+        .map(|manager| manager.agent_vec.clone())
+        .unwrap_or_default();
+    Ok(Json(dry_run(&workflow, &jobs, &request.input, &agents)))
+}
+
+/// 取消工作流下发起请求的用户的所有未结束任务
+#[utoipa::path(post, path = "/workflows/{id}/cancel-tasks", tag = "workflows",
+    params(("id" = String, Path, description = "工作流id"), ("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只作用于该用户的任务")),
+    responses((status = 200, body = BulkReportView)))]
+pub async fn cancel_workflow_tasks(
+    State(engine): EngineState,
+    owner: Owner,
+    Path(id): Path<String>,
+) -> Json<BulkReportView> {
+    let report = engine
+        .cancel_workflow_tasks(&id, owner.0.as_deref(), |_| {})
+        .await;
+    Json(bulk_view(report))
+}
+
+/// 暂停工作流下发起请求的用户的所有未结束任务
+#[utoipa::path(post, path = "/workflows/{id}/pause-tasks", tag = "workflows",
+    params(("id" = String, Path, description = "工作流id"), ("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只作用于该用户的任务")),
+    responses((status = 200, body = BulkReportView)))]
+pub async fn pause_workflow_tasks(
+    State(engine): EngineState,
+    owner: Owner,
+    Path(id): Path<String>,
+) -> Json<BulkReportView> {
+    let report = engine
+        .pause_workflow_tasks(&id, owner.0.as_deref(), |_| {})
+        .await;
+    Json(bulk_view(report))
+}
+
+/// 所有agent
+#[utoipa::path(get, path = "/agents", tag = "agents",
+    responses((status = 200, body = Vec<AgentView>)))]
+pub async fn list_agents() -> Json<Vec<AgentView>> {
+    let agents = AgentManager::global()
+        .map(|manager| manager.list_agent())
+        .unwrap_or_default();
+    Json(
+        agents
+            .into_iter()
+            .map(|agent| AgentView {
+                name: agent.name,
+                desc: agent.desc,
+                error: agent.error,
+            })
+            .collect(),
+    )
+}
+
+/// agent的few-shot示例
+#[utoipa::path(get, path = "/agents/{code}/examples", tag = "agents",
+    params(("code" = String, Path, description = "agent code")),
+    responses(
+        (status = 200, body = Vec<ExampleView>),
+        (status = 503, body = ErrorEnvelope),
+    ))]
+pub async fn list_examples(Path(code): Path<String>) -> Result<Json<Vec<ExampleView>>, ApiError> {
+    let examples = example_library()?
+        .list(&code)
+        .await
+        .map_err(example_error)?;
+    Ok(Json(examples.into_iter().map(ExampleView::from).collect()))
+}
+
+/// 为agent添加一个few-shot示例
+#[utoipa::path(post, path = "/agents/{code}/examples", tag = "agents",
+    params(("code" = String, Path, description = "agent code")),
+    request_body = ExampleRequest,
+    responses(
+        (status = 201, body = ExampleView),
+        (status = 503, body = ErrorEnvelope),
+    ))]
+pub async fn add_example(
+    Path(code): Path<String>,
+    Json(request): Json<ExampleRequest>,
+) -> Result<(StatusCode, Json<ExampleView>), ApiError> {
+    let example = example_library()?
+        .add(&code, &request.input, &request.output)
+        .await
+        .map_err(example_error)?;
+    Ok((StatusCode::CREATED, Json(ExampleView::from(example))))
+}
+
+/// agent系统提示的所有版本
+#[utoipa::path(get, path = "/agents/{code}/prompts", tag = "agents",
+    params(("code" = String, Path, description = "agent code")),
+    responses((status = 200, body = Vec<PromptVersionView>)))]
+pub async fn list_prompt_versions(
+    State(engine): EngineState,
+    Path(code): Path<String>,
+) -> Result<Json<Vec<PromptVersionView>>, ApiError> {
+    let db = db_of(&engine)?;
+    let versions = crate::prompt_version::list_versions(&db, &code)
+        .await
+        .map_err(prompt_error)?;
+    Ok(Json(
+        versions.into_iter().map(PromptVersionView::from).collect(),
+    ))
+}
+
+/// 创建系统提示的草稿版本
+#[utoipa::path(post, path = "/agents/{code}/prompts", tag = "agents",
+    params(("code" = String, Path, description = "agent code")),
+    request_body = PromptDraftRequest,
+    responses((status = 201, body = PromptVersionView)))]
+pub async fn create_prompt_draft(
+    State(engine): EngineState,
+    Path(code): Path<String>,
+    Json(request): Json<PromptDraftRequest>,
+) -> Result<(StatusCode, Json<PromptVersionView>), ApiError> {
+    let db = db_of(&engine)?;
+    let draft = crate::prompt_version::create_draft(&db, &code, &request.preamble, request.note)
+        .await
+        .map_err(prompt_error)?;
+    Ok((StatusCode::CREATED, Json(PromptVersionView::from(draft))))
+}
+
+/// 启用系统提示的一个版本，全局的 AgentManager 随即重新加载
+#[utoipa::path(post, path = "/agents/{code}/prompts/{version}/activate", tag = "agents",
+    params(
+        ("code" = String, Path, description = "agent code"),
+        ("version" = i32, Path, description = "版本号"),
+    ),
+    responses(
+        (status = 200, body = PromptVersionView),
+        (status = 404, body = ErrorEnvelope),
+    ))]
+pub async fn activate_prompt_version(
+    State(engine): EngineState,
+    Path((code, version)): Path<(String, i32)>,
+) -> Result<Json<PromptVersionView>, ApiError> {
+    let db = db_of(&engine)?;
+    let active = crate::prompt_version::activate(&db, &code, version)
+        .await
+        .map_err(prompt_error)?;
+    if let Some(manager) = AgentManager::global() {
+        manager.reload_prompts(&db).await.map_err(prompt_error)?;
+    }
+    Ok(Json(PromptVersionView::from(active)))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventsQuery {
+    /// 是否包含模型的推理过程
+    #[serde(default = "default_true")]
+    pub reasoning: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// 任务事件流，每条事件是一个json编码的 TaskEvent
+#[utoipa::path(get, path = "/events", tag = "events",
+    params(
+        ("reasoning" = Option<bool>, Query, description = "是否包含推理过程事件，默认包含"),
+        ("x-owner-id" = Option<String>, Header, description = "请求所属的用户，只能访问该用户的任务"),
+    ),
+    responses((status = 200, content_type = "text/event-stream", body = String)))]
+pub async fn events(
+    State(engine): EngineState,
+    owner: Owner,
+    Query(query): Query<EventsQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let mut events = engine.subscribe();
+    let stream = async_stream::stream! {
+        loop {
+            match events.recv().await {
+                Ok(event) if !query.reasoning && event.is_reasoning() => {}
+                Ok(event) if owner.check(&engine, event.task_id()).await.is_err() => {}
+                Ok(event) => {
+                    if let Ok(event) = Event::default().json_data(&event) {
+                        yield Ok(event);
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("event stream lagged, skipped {skipped} events");
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    };
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
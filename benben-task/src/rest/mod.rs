@@ -0,0 +1,284 @@
+//! HTTP 接口。
+//!
+//! 基于 axum 提供任务、工作流、agent、事件和产物的 REST 接口。OpenAPI 文档在编译时由
+//! utoipa 根据处理函数上的注解生成，通过 `/openapi.json` 提供，前端和第三方集成可以直接
+//! 生成带类型的客户端。需要开启 `http-api` feature。
+//!
+//! 开启 `web-ui` feature 后在 `/ui` 提供一个内置的管理页面。
+//!
+//! 任务相关的接口按 [OWNER_HEADER] 请求头中的用户过滤，见 [TaskEngine::for_owner]。
+//!
+//! 其他 Rust 服务可以使用 `benben-client` crate 调用这些接口，请求、响应和错误都是带类型的。
+
+mod handlers;
+#[cfg(feature = "web-ui")]
+mod ui;
+
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use utoipa::OpenApi;
+
+use crate::api::{ErrorCode, ErrorEnvelope};
+use crate::engine::{TaskEngine, TaskEngineError};
+
+pub use handlers::{
+    AgentView, ApprovalView, ArtifactView, DryRunRequest, Owner, TaskAction, TaskSummary,
+    TaskView, TriggeredTask, WorkflowView, OWNER_HEADER,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "benben-task", description = "任务引擎的 HTTP 接口"),
+    paths(
+        handlers::list_tasks,
+        handlers::get_task,
+        handlers::task_action,
+        handlers::list_artifacts,
+        handlers::get_plan,
+        handlers::list_transitions,
+        handlers::list_job_runs,
+        handlers::export_trace,
+        handlers::mark_golden,
+        handlers::export_dataset,
+        handlers::list_approvals,
+        handlers::approve_job,
+        handlers::bulk_pause,
+        handlers::bulk_resume,
+        handlers::fire_webhook,
+        handlers::list_workflows,
+        handlers::get_workflow,
+        handlers::list_workflow_versions,
+        handlers::diff_workflow_versions,
+        handlers::dry_run_workflow,
+        handlers::cancel_workflow_tasks,
+        handlers::pause_workflow_tasks,
+        handlers::list_agents,
+        handlers::list_examples,
+        handlers::add_example,
+        handlers::list_prompt_versions,
+        handlers::create_prompt_draft,
+        handlers::activate_prompt_version,
+        handlers::events,
+    ),
+    components(schemas(ErrorEnvelope, ErrorCode)),
+    tags(
+        (name = "tasks"),
+        (name = "workflows"),
+        (name = "datasets"),
+        (name = "agents"),
+        (name = "events"),
+    )
+)]
+pub struct ApiDoc;
+
+/// OpenAPI 文档
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+}
+
+/// 所有接口的路由，包括 `/openapi.json`
+pub fn router(engine: Arc<TaskEngine>) -> Router {
+    let router = Router::new()
+        .route("/tasks", get(handlers::list_tasks))
+        .route("/tasks/{id}", get(handlers::get_task))
+        .route("/tasks/{id}/artifacts", get(handlers::list_artifacts))
+        .route("/tasks/{id}/plan", get(handlers::get_plan))
+        .route("/tasks/{id}/transitions", get(handlers::list_transitions))
+        .route("/tasks/{id}/runs", get(handlers::list_job_runs))
+        .route("/tasks/{id}/trace", get(handlers::export_trace))
+        .route("/tasks/{id}/golden", post(handlers::mark_golden))
+        .route("/tasks/{id}/{action}", post(handlers::task_action))
+        .route(
+            "/tasks/{id}/jobs/{job_id}/approve",
+            post(handlers::approve_job),
+        )
+        .route("/approvals", get(handlers::list_approvals))
+        .route("/bulk/pause", post(handlers::bulk_pause))
+        .route("/bulk/resume", post(handlers::bulk_resume))
+        .route("/hooks/{name}", post(handlers::fire_webhook))
+        .route("/workflows", get(handlers::list_workflows))
+        .route("/workflows/{id}", get(handlers::get_workflow))
+        .route(
+            "/workflows/{id}/versions",
+            get(handlers::list_workflow_versions),
+        )
+        .route(
+            "/workflows/{id}/versions/{from}/diff/{to}",
+            get(handlers::diff_workflow_versions),
+        )
+        .route("/workflows/{id}/dry-run", post(handlers::dry_run_workflow))
+        .route(
+            "/workflows/{id}/cancel-tasks",
+            post(handlers::cancel_workflow_tasks),
+        )
+        .route(
+            "/workflows/{id}/pause-tasks",
+            post(handlers::pause_workflow_tasks),
+        )
+        .route("/datasets/{name}/export", get(handlers::export_dataset))
+        .route("/agents", get(handlers::list_agents))
+        .route(
+            "/agents/{code}/examples",
+            get(handlers::list_examples).post(handlers::add_example),
+        )
+        .route(
+            "/agents/{code}/prompts",
+            get(handlers::list_prompt_versions).post(handlers::create_prompt_draft),
+        )
+        .route(
+            "/agents/{code}/prompts/{version}/activate",
+            post(handlers::activate_prompt_version),
+        )
+        .route("/events", get(handlers::events))
+        .route("/openapi.json", get(|| async { Json(openapi()) }));
+    #[cfg(feature = "web-ui")]
+    let router = router.merge(ui::routes());
+    router.with_state(engine)
+}
+
+/// 接口返回的错误
+#[derive(Debug)]
+pub struct ApiError(pub StatusCode, pub ErrorEnvelope);
+
+impl From<TaskEngineError> for ApiError {
+    fn from(err: TaskEngineError) -> Self {
+        let status = match &err {
+            TaskEngineError::NotFound(_) => StatusCode::NOT_FOUND,
+            TaskEngineError::InvalidTransition { .. }
+            | TaskEngineError::Cancelled(_)
+            | TaskEngineError::Conflict(_) => StatusCode::CONFLICT,
+            TaskEngineError::Rejected(_) => StatusCode::FORBIDDEN,
+            TaskEngineError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            TaskEngineError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            TaskEngineError::Provider(_) => StatusCode::BAD_GATEWAY,
+            TaskEngineError::Db(_) | TaskEngineError::Other(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        ApiError(status, ErrorEnvelope::from(&err))
+    }
+}
+
+impl From<sea_orm::DbErr> for ApiError {
+    fn from(err: sea_orm::DbErr) -> Self {
+        ApiError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorEnvelope::internal(err.to_string()),
+        )
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(self.1)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openapi_covers_all_resources() {
+        let doc = serde_json::to_value(openapi()).unwrap();
+        let paths = doc["paths"].as_object().unwrap();
+        for path in [
+            "/tasks",
+            "/tasks/{id}",
+            "/tasks/{id}/artifacts",
+            "/tasks/{id}/plan",
+            "/tasks/{id}/transitions",
+            "/tasks/{id}/runs",
+            "/tasks/{id}/trace",
+            "/tasks/{id}/golden",
+            "/datasets/{name}/export",
+            "/tasks/{id}/jobs/{job_id}/approve",
+            "/approvals",
+            "/hooks/{name}",
+            "/workflows/{id}/dry-run",
+            "/workflows/{id}/cancel-tasks",
+            "/workflows/{id}/pause-tasks",
+            "/bulk/pause",
+            "/bulk/resume",
+            "/agents",
+            "/agents/{code}/examples",
+            "/agents/{code}/prompts",
+            "/agents/{code}/prompts/{version}/activate",
+            "/events",
+        ] {
+            assert!(paths.contains_key(path), "missing {path}");
+        }
+        assert!(doc["components"]["schemas"]["TaskView"].is_object());
+    }
+
+    #[tokio::test]
+    async fn requests_are_scoped_to_the_owner() {
+        use axum::extract::{Path, State};
+
+        let engine = Arc::new(TaskEngine::new());
+        let first = engine.create_task_as("alice", "a".into()).await.unwrap();
+        let second = engine.create_task_as("bob", "b".into()).await.unwrap();
+        let alice = || Owner(Some("alice".to_string()));
+
+        let Json(tasks) = handlers::list_tasks(State(engine.clone()), alice()).await;
+        assert_eq!(tasks.iter().map(|t| t.id).collect::<Vec<_>>(), vec![first]);
+        let Json(tasks) = handlers::list_tasks(State(engine.clone()), Owner(None)).await;
+        assert_eq!(tasks.len(), 2);
+
+        let err = handlers::get_task(State(engine.clone()), alice(), Path(second))
+            .await
+            .unwrap_err();
+        assert_eq!(err.0, StatusCode::NOT_FOUND);
+        let err = handlers::task_action(
+            State(engine.clone()),
+            alice(),
+            Path((second, TaskAction::Cancel)),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.0, StatusCode::NOT_FOUND);
+        assert!(handlers::get_task(State(engine), alice(), Path(first))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn actions_reach_tasks_held_by_other_workers() {
+        use axum::extract::{Path, State};
+        use sea_orm::Database;
+
+        use crate::engine::TaskState;
+        use crate::worker::TaskWorker;
+
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        crate::migrate::create_schema(&db).await.unwrap();
+        let db = Arc::new(db);
+        let engine_a = TaskEngine::new().with_db(db.clone());
+        let engine_b = Arc::new(TaskEngine::new().with_db(db.clone()));
+        let worker_a = TaskWorker::new(db, "a");
+        let task_id = engine_a.create_task_as("alice", "a".into()).await.unwrap();
+        engine_a.start(task_id).await.unwrap();
+        worker_a.heartbeat(&engine_a).await.unwrap();
+
+        // 任务不在 b 中，暂停命令写入发件箱，由持有任务的 a 执行
+        let status = handlers::task_action(State(engine_b.clone()), Owner(Some("alice".to_string())), Path((task_id, TaskAction::Pause)))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let err = handlers::task_action(State(engine_b.clone()), Owner(Some("bob".to_string())), Path((task_id, TaskAction::Pause)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.0, StatusCode::NOT_FOUND);
+        let err = handlers::task_action(State(engine_b), Owner(None), Path((task_id + 1, TaskAction::Stop)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.0, StatusCode::NOT_FOUND);
+
+        assert_eq!(worker_a.poll_commands(&engine_a).await.unwrap(), 1);
+        assert_eq!(engine_a.get_state(task_id).await.unwrap(), TaskState::Pending);
+    }
+}
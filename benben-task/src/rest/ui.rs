@@ -0,0 +1,40 @@
+//! 内置的管理页面。
+//!
+//! 单个 html 文件编译进二进制，只使用 HTTP 接口和 `/events` 事件流，不依赖前端构建工具。
+//! 页面展示 agent、工作流、任务及其实时输出、待审批的job，以及每个任务的用量和费用。
+
+use std::sync::Arc;
+
+use axum::response::{Html, Redirect};
+use axum::routing::get;
+use axum::Router;
+
+use crate::engine::TaskEngine;
+
+/// 管理页面
+pub const INDEX_HTML: &str = include_str!("ui/index.html");
+
+/// 页面的路由，`/` 跳转到 `/ui`
+pub fn routes() -> Router<Arc<TaskEngine>> {
+    Router::new()
+        .route("/", get(|| async { Redirect::temporary("/ui") }))
+        .route("/ui", get(|| async { Html(INDEX_HTML) }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_uses_documented_paths() {
+        let doc = serde_json::to_value(super::super::openapi()).unwrap();
+        let paths = doc["paths"].as_object().unwrap();
+        for path in ["/agents", "/workflows", "/tasks", "/approvals", "/events"] {
+            assert!(
+                INDEX_HTML.contains(&format!("'{path}")),
+                "page misses {path}"
+            );
+            assert!(paths.contains_key(path));
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! 信号处理与系统服务集成。
+//!
+//! 收到 SIGTERM/SIGINT（Windows 上为 Ctrl-C 和关机事件）时引擎进入维护模式，不再接受
+//! 新任务，在宽限期内等待运行中的任务结束，超时仍在运行的任务被暂停，下次启动时从数据库
+//! 恢复。作为 systemd 服务运行时通过 `NOTIFY_SOCKET` 上报就绪、状态和看门狗心跳，
+//! 崩溃后由 systemd 或 Windows 服务管理器重启，单元文件和 `sc.exe` 命令可以由这里生成。
+//!
+//! ```rust,ignore
+//! let engine = TaskEngine::init_global(TaskEngine::new().with_db(db))?;
+//! let report = service::run_until_signal(engine, Duration::from_secs(30)).await?;
+//! ```
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+use crate::engine::{TaskEngine, TaskState};
+
+/// 触发关闭的信号
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownSignal {
+    /// SIGINT 或 Ctrl-C
+    Interrupt,
+    /// SIGTERM 或系统关机
+    Terminate,
+}
+
+/// 关闭的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// 宽限期内结束的任务数
+    pub drained: usize,
+    /// 超过宽限期被暂停的任务
+    pub paused: Vec<i32>,
+}
+
+/// 等待关闭信号
+#[cfg(unix)]
+pub async fn wait_for_signal() -> io::Result<ShutdownSignal> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    Ok(tokio::select! {
+        _ = interrupt.recv() => ShutdownSignal::Interrupt,
+        _ = terminate.recv() => ShutdownSignal::Terminate,
+    })
+}
+
+/// 等待关闭信号
+#[cfg(windows)]
+pub async fn wait_for_signal() -> io::Result<ShutdownSignal> {
+    use tokio::signal::windows::{ctrl_break, ctrl_c, ctrl_close, ctrl_shutdown};
+
+    let mut interrupt = ctrl_c()?;
+    let mut brk = ctrl_break()?;
+    let mut close = ctrl_close()?;
+    let mut shutdown = ctrl_shutdown()?;
+    Ok(tokio::select! {
+        _ = interrupt.recv() => ShutdownSignal::Interrupt,
+        _ = brk.recv() => ShutdownSignal::Interrupt,
+        _ = close.recv() => ShutdownSignal::Terminate,
+        _ = shutdown.recv() => ShutdownSignal::Terminate,
+    })
+}
+
+impl TaskEngine {
+    /// 优雅关闭：进入维护模式，等待运行中的任务结束，超过宽限期后暂停剩余的任务
+    pub async fn shutdown(&self, grace: Duration) -> ShutdownReport {
+        self.enter_maintenance();
+        let clock = self.clock();
+        let running = self.running_tasks().await;
+        let deadline = clock.now() + grace;
+        let mut remaining = running.clone();
+        while !remaining.is_empty() && clock.now() < deadline {
+            clock.sleep(Duration::from_millis(100)).await;
+            remaining = self.running_tasks().await;
+        }
+
+        let mut paused = Vec::new();
+        for task_id in remaining {
+            match self.pause(task_id).await {
+                Ok(()) => paused.push(task_id),
+                Err(e) => tracing::error!("failed to pause task {} on shutdown: {}", task_id, e),
+            }
+        }
+        let report = ShutdownReport {
+            drained: running.len() - paused.len(),
+            paused,
+        };
+        tracing::info!(
+            "task engine shut down, {} tasks drained, {} paused",
+            report.drained,
+            report.paused.len()
+        );
+        report
+    }
+
+    async fn running_tasks(&self) -> Vec<i32> {
+        let mut running = Vec::new();
+        for task_id in self.list_tasks().await {
+            if matches!(self.get_state(task_id).await, Ok(TaskState::Running)) {
+                running.push(task_id);
+            }
+        }
+        running
+    }
+}
+
+/// 上报就绪，等待关闭信号后优雅关闭引擎
+pub async fn run_until_signal(
+    engine: Arc<TaskEngine>,
+    grace: Duration,
+) -> io::Result<ShutdownReport> {
+    let watchdog = spawn_watchdog();
+    notify(&[SdNotify::Ready, SdNotify::Status("running".to_string())])?;
+
+    let signal = wait_for_signal().await?;
+    tracing::info!("received {:?}, shutting down", signal);
+    notify(&[
+        SdNotify::Stopping,
+        SdNotify::Status("draining tasks".to_string()),
+    ])?;
+    let report = engine.shutdown(grace).await;
+    if let Some(watchdog) = watchdog {
+        watchdog.abort();
+    }
+    Ok(report)
+}
+
+/// 发给 systemd 的通知
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdNotify {
+    Ready,
+    Stopping,
+    /// 在 `systemctl status` 中显示的状态
+    Status(String),
+    /// 看门狗心跳
+    Watchdog,
+}
+
+impl SdNotify {
+    fn as_line(&self) -> String {
+        match self {
+            SdNotify::Ready => "READY=1".to_string(),
+            SdNotify::Stopping => "STOPPING=1".to_string(),
+            SdNotify::Status(status) => format!("STATUS={}", status.replace('\n', " ")),
+            SdNotify::Watchdog => "WATCHDOG=1".to_string(),
+        }
+    }
+}
+
+/// 通知 systemd，不是由 systemd 以 `Type=notify` 启动时什么也不做，返回 false
+#[cfg(target_os = "linux")]
+pub fn notify(messages: &[SdNotify]) -> io::Result<bool> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    let path = path.to_string_lossy().into_owned();
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes())?,
+        None => SocketAddr::from_pathname(&path)?,
+    };
+    let state: Vec<String> = messages.iter().map(SdNotify::as_line).collect();
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.join("\n").as_bytes(), &addr)?;
+    Ok(true)
+}
+
+/// 通知 systemd，非 Linux 平台上什么也不做
+#[cfg(not(target_os = "linux"))]
+pub fn notify(_messages: &[SdNotify]) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// systemd 配置了 `WatchdogSec` 时要求的心跳间隔，取超时时间的一半
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    Some(Duration::from_micros(usec / 2))
+}
+
+/// 配置了看门狗时在后台定期发送心跳
+pub fn spawn_watchdog() -> Option<JoinHandle<()>> {
+    let interval = watchdog_interval()?;
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = notify(&[SdNotify::Watchdog]) {
+                tracing::warn!("watchdog notification failed: {}", e);
+            }
+        }
+    }))
+}
+
+/// systemd 单元文件，进程崩溃时自动重启
+#[derive(Debug, Clone)]
+pub struct SystemdUnit {
+    description: String,
+    exec: String,
+    user: Option<String>,
+    working_dir: Option<String>,
+    restart_sec: u64,
+    watchdog_sec: Option<u64>,
+    stop_timeout_sec: u64,
+}
+
+impl SystemdUnit {
+    /// `exec` 为启动命令，使用绝对路径
+    pub fn new(description: impl Into<String>, exec: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            exec: exec.into(),
+            user: None,
+            working_dir: None,
+            restart_sec: 5,
+            watchdog_sec: None,
+            stop_timeout_sec: 60,
+        }
+    }
+
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    pub fn working_dir(mut self, dir: impl Into<String>) -> Self {
+        self.working_dir = Some(dir.into());
+        self
+    }
+
+    /// 崩溃后等待多少秒重启
+    pub fn restart_sec(mut self, secs: u64) -> Self {
+        self.restart_sec = secs;
+        self
+    }
+
+    /// 超过指定秒数没有心跳时 systemd 认为进程卡死并重启
+    pub fn watchdog_sec(mut self, secs: u64) -> Self {
+        self.watchdog_sec = Some(secs);
+        self
+    }
+
+    /// 发送 SIGTERM 后等待退出的秒数，应当大于关闭的宽限期
+    pub fn stop_timeout_sec(mut self, secs: u64) -> Self {
+        self.stop_timeout_sec = secs;
+        self
+    }
+
+    pub fn render(&self) -> String {
+        let mut service = vec![
+            "Type=notify".to_string(),
+            format!("ExecStart={}", self.exec),
+            "Restart=on-failure".to_string(),
+            format!("RestartSec={}", self.restart_sec),
+            "KillSignal=SIGTERM".to_string(),
+            format!("TimeoutStopSec={}", self.stop_timeout_sec),
+        ];
+        if let Some(secs) = self.watchdog_sec {
+            service.push(format!("WatchdogSec={secs}"));
+        }
+        if let Some(user) = &self.user {
+            service.push(format!("User={user}"));
+        }
+        if let Some(dir) = &self.working_dir {
+            service.push(format!("WorkingDirectory={dir}"));
+        }
+        format!(
+            "[Unit]\nDescription={}\nAfter=network-online.target\nWants=network-online.target\n\n\
+             [Service]\n{}\n\n[Install]\nWantedBy=multi-user.target\n",
+            self.description,
+            service.join("\n")
+        )
+    }
+}
+
+/// 注册 Windows 服务并在崩溃后自动重启的 `sc.exe` 命令，需要以管理员身份执行。
+/// 程序需要自己实现 Windows 服务控制协议，或者通过 WinSW、NSSM 等包装后注册
+pub fn windows_service_commands(name: &str, exe: &str, restart_ms: u64) -> Vec<String> {
+    vec![
+        format!("sc.exe create {name} binPath= \"{exe}\" start= auto"),
+        format!(
+            "sc.exe failure {name} reset= 86400 actions= restart/{restart_ms}/restart/{restart_ms}/restart/{restart_ms}"
+        ),
+        format!("sc.exe failureflag {name} 1"),
+        format!("sc.exe start {name}"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn shutdown_pauses_tasks_still_running_after_grace() {
+        let mut engine = TaskEngine::new();
+        engine.init(1, "a".to_string()).await.unwrap();
+        engine.init(2, "b".to_string()).await.unwrap();
+        engine.start(1).await.unwrap();
+
+        let report = engine.shutdown(Duration::from_millis(200)).await;
+        assert_eq!(
+            report,
+            ShutdownReport {
+                drained: 0,
+                paused: vec![1]
+            }
+        );
+        assert!(engine.is_maintenance());
+        assert_eq!(engine.get_state(1).await.unwrap(), TaskState::Pending);
+
+        let unit = SystemdUnit::new("benben", "/usr/bin/benben")
+            .watchdog_sec(30)
+            .render();
+        assert!(unit.contains("Type=notify\n") && unit.contains("Restart=on-failure\n"));
+        assert!(unit.contains("WatchdogSec=30\n"));
+    }
+}
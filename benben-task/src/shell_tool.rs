@@ -0,0 +1,243 @@
+//! 受控的shell命令工具，需要开启 `shell-tool` feature。
+//!
+//! 只在任务工作目录中执行命令，命令直接启动而不经过shell解释，避免拼接注入。
+//! 白名单中的命令直接执行，其他命令交给审批回调决定，没有回调时拒绝。
+//! 执行有超时限制，超时后子进程被杀掉；输出超过上限时截断，超出的部分读出后直接丢弃。
+
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use rig::tool::Tool;
+use rmcp::model::Tool as ToolDefinition;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+
+/// 审批回调，返回 true 表示允许执行
+pub type ApprovalFn = Arc<dyn Fn(ShellArgs) -> BoxFuture<'static, bool> + Send + Sync>;
+
+#[derive(Debug, Error)]
+pub enum ShellError {
+    #[error("command not allowed: {0}")]
+    NotAllowed(String),
+    #[error("command timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// 工具参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShellArgs {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// 执行结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShellOutput {
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// 输出是否被截断
+    pub truncated: bool,
+}
+
+pub struct ShellTool {
+    workspace: PathBuf,
+    allowed: Vec<String>,
+    timeout: Duration,
+    max_output_bytes: usize,
+    approval: Option<ApprovalFn>,
+}
+
+impl ShellTool {
+    /// 在 `workspace` 目录中执行命令，默认不允许任何命令
+    pub fn new(workspace: impl Into<PathBuf>) -> Self {
+        Self {
+            workspace: workspace.into(),
+            allowed: Vec::new(),
+            timeout: Duration::from_secs(30),
+            max_output_bytes: 64 * 1024,
+            approval: None,
+        }
+    }
+
+    /// 白名单命令，例如 ["ls", "cargo"]
+    pub fn allow(mut self, commands: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.allowed.extend(commands.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// stdout 与 stderr 各自保留的最大字节数
+    pub fn max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    /// 非白名单命令的审批回调
+    pub fn approval(mut self, approval: ApprovalFn) -> Self {
+        self.approval = Some(approval);
+        self
+    }
+
+    async fn check(&self, args: &ShellArgs) -> Result<(), ShellError> {
+        if self.allowed.contains(&args.command) {
+            return Ok(());
+        }
+        match &self.approval {
+            Some(approval) if approval(args.clone()).await => Ok(()),
+            _ => Err(ShellError::NotAllowed(args.command.clone())),
+        }
+    }
+}
+
+/// 最多保留 `limit` 字节，其余的输出继续读出并丢弃，子进程不会因为管道写满而阻塞
+async fn read_bounded(
+    mut reader: impl AsyncRead + Unpin,
+    limit: usize,
+) -> std::io::Result<(String, bool)> {
+    let mut bytes = Vec::new();
+    (&mut reader)
+        .take(limit as u64)
+        .read_to_end(&mut bytes)
+        .await?;
+    let rest = tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+    Ok((String::from_utf8_lossy(&bytes).into_owned(), rest > 0))
+}
+
+impl Tool for ShellTool {
+    const NAME: &'static str = "shell";
+
+    type Args = ShellArgs;
+    type Output = ShellOutput;
+    type Error = ShellError;
+
+    fn definition(&self) -> ToolDefinition {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "command": { "type": "string", "description": "program to run" },
+                "args": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["command"]
+        });
+        ToolDefinition::new(
+            Self::NAME,
+            "Run a command in the task workspace",
+            schema.as_object().cloned().unwrap_or_default(),
+        )
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        self.check(&args).await?;
+
+        let mut child = Command::new(&args.command)
+            .args(&args.args)
+            .current_dir(&self.workspace)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let (Some(stdout), Some(stderr)) = (child.stdout.take(), child.stderr.take()) else {
+            return Err(std::io::Error::other("child output is not piped").into());
+        };
+        let run = async {
+            tokio::try_join!(
+                read_bounded(stdout, self.max_output_bytes),
+                read_bounded(stderr, self.max_output_bytes),
+                child.wait(),
+            )
+        };
+        // 超时后 child 被丢弃，子进程随之被杀掉
+        let ((stdout, stdout_truncated), (stderr, stderr_truncated), status) =
+            tokio::time::timeout(self.timeout, run)
+                .await
+                .map_err(|_| ShellError::Timeout(self.timeout))??;
+
+        Ok(ShellOutput {
+            exit_code: status.code(),
+            stdout,
+            stderr,
+            truncated: stdout_truncated || stderr_truncated,
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn runs_allowed_and_rejects_others() {
+        let dir = std::env::temp_dir();
+        let tool = ShellTool::new(&dir).allow(["echo"]).max_output_bytes(4);
+
+        let output = Tool::call(
+            &tool,
+            ShellArgs {
+                command: "echo".to_string(),
+                args: vec!["hello".to_string()],
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.stdout, "hell");
+        assert!(output.truncated);
+
+        // 超过管道缓冲区的输出也能读完，不会让子进程阻塞到超时
+        let large = ShellTool::new(&dir)
+            .allow(["head"])
+            .max_output_bytes(16)
+            .timeout(Duration::from_secs(5));
+        let output = Tool::call(
+            &large,
+            ShellArgs {
+                command: "head".to_string(),
+                args: vec![
+                    "-c".to_string(),
+                    "1048576".to_string(),
+                    "/dev/zero".to_string(),
+                ],
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.exit_code, Some(0));
+        assert_eq!(output.stdout.len(), 16);
+        assert!(output.truncated);
+
+        let denied = Tool::call(
+            &tool,
+            ShellArgs {
+                command: "rm".to_string(),
+                args: vec![],
+            },
+        )
+        .await;
+        assert!(matches!(denied, Err(ShellError::NotAllowed(_))));
+
+        let approved = ShellTool::new(&dir).approval(Arc::new(|_| Box::pin(async { true })));
+        let output = Tool::call(
+            &approved,
+            ShellArgs {
+                command: "pwd".to_string(),
+                args: vec![],
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.exit_code, Some(0));
+    }
+}
@@ -0,0 +1,192 @@
+//! 工作流、job和agent配置的软删除。
+//!
+//! 删除只设置 `deleted` 标记，不删除记录：历史任务仍然可以通过 `wid` 关联到已删除的工作流，
+//! 误删后也可以恢复。查询这些实体时默认使用 [SoftDelete::find_active] 或者这里的 `active_*`
+//! 函数排除已删除的记录；需要解析历史任务的工作流时使用 [workflow_with_deleted]。
+
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    Select,
+};
+
+use crate::entities::{agent_config, job, workflow};
+
+/// 带有 `deleted` 标记的实体
+pub trait SoftDelete: EntityTrait {
+    fn deleted_column() -> Self::Column;
+
+    /// 排除已删除记录的查询，代替 `find()` 使用
+    fn find_active() -> Select<Self> {
+        Self::find().filter(Self::deleted_column().eq(false))
+    }
+}
+
+impl SoftDelete for workflow::Entity {
+    fn deleted_column() -> Self::Column {
+        workflow::Column::Deleted
+    }
+}
+
+impl SoftDelete for job::Entity {
+    fn deleted_column() -> Self::Column {
+        job::Column::Deleted
+    }
+}
+
+impl SoftDelete for agent_config::Entity {
+    fn deleted_column() -> Self::Column {
+        agent_config::Column::Deleted
+    }
+}
+
+/// 没有删除的工作流
+pub async fn active_workflows(db: &DatabaseConnection) -> Result<Vec<workflow::Model>, DbErr> {
+    workflow::Entity::find_active().all(db).await
+}
+
+/// 按id查询没有删除的工作流
+pub async fn active_workflow(
+    db: &DatabaseConnection,
+    id: &str,
+) -> Result<Option<workflow::Model>, DbErr> {
+    workflow::Entity::find_active()
+        .filter(workflow::Column::Id.eq(id))
+        .one(db)
+        .await
+}
+
+/// 按id查询工作流，包括已删除的，用于解析历史任务
+pub async fn workflow_with_deleted(
+    db: &DatabaseConnection,
+    id: &str,
+) -> Result<Option<workflow::Model>, DbErr> {
+    workflow::Entity::find_by_id(id.to_string()).one(db).await
+}
+
+pub async fn delete_workflow(db: &DatabaseConnection, id: &str) -> Result<(), DbErr> {
+    set_workflow_deleted(db, id, true).await
+}
+
+pub async fn restore_workflow(db: &DatabaseConnection, id: &str) -> Result<(), DbErr> {
+    set_workflow_deleted(db, id, false).await
+}
+
+async fn set_workflow_deleted(
+    db: &DatabaseConnection,
+    id: &str,
+    deleted: bool,
+) -> Result<(), DbErr> {
+    let model = workflow_with_deleted(db, id)
+        .await?
+        .ok_or(DbErr::RecordNotFound(format!("workflow {}", id)))?;
+    let mut active: workflow::ActiveModel = model.into();
+    active.deleted = Set(deleted);
+    active.update(db).await?;
+    Ok(())
+}
+
+/// 工作流中没有删除的job，按id排序。job 的 `workflow_id` 是整数，id不是整数的工作流没有job
+pub async fn active_jobs(
+    db: &DatabaseConnection,
+    workflow_id: &str,
+) -> Result<Vec<job::Model>, DbErr> {
+    let Ok(workflow_id) = workflow_id.parse::<i32>() else {
+        return Ok(vec![]);
+    };
+    job::Entity::find_active()
+        .filter(job::Column::WorkflowId.eq(workflow_id))
+        .order_by_asc(job::Column::Id)
+        .all(db)
+        .await
+}
+
+pub async fn delete_job(db: &DatabaseConnection, id: i32) -> Result<(), DbErr> {
+    set_job_deleted(db, id, true).await
+}
+
+pub async fn restore_job(db: &DatabaseConnection, id: i32) -> Result<(), DbErr> {
+    set_job_deleted(db, id, false).await
+}
+
+async fn set_job_deleted(db: &DatabaseConnection, id: i32, deleted: bool) -> Result<(), DbErr> {
+    let model = job::Entity::find_by_id(id)
+        .one(db)
+        .await?
+        .ok_or(DbErr::RecordNotFound(format!("job {}", id)))?;
+    let mut active: job::ActiveModel = model.into();
+    active.deleted = Set(deleted);
+    active.update(db).await?;
+    Ok(())
+}
+
+/// 没有删除的agent配置
+pub async fn active_agent_configs(
+    db: &DatabaseConnection,
+) -> Result<Vec<agent_config::Model>, DbErr> {
+    agent_config::Entity::find_active().all(db).await
+}
+
+pub async fn delete_agent_config(db: &DatabaseConnection, code: &str) -> Result<(), DbErr> {
+    set_agent_config_deleted(db, code, true).await
+}
+
+pub async fn restore_agent_config(db: &DatabaseConnection, code: &str) -> Result<(), DbErr> {
+    set_agent_config_deleted(db, code, false).await
+}
+
+async fn set_agent_config_deleted(
+    db: &DatabaseConnection,
+    code: &str,
+    deleted: bool,
+) -> Result<(), DbErr> {
+    let model = agent_config::Entity::find()
+        .filter(agent_config::Column::Code.eq(code))
+        .one(db)
+        .await?
+        .ok_or(DbErr::RecordNotFound(format!("agent config {}", code)))?;
+    let mut active: agent_config::ActiveModel = model.into();
+    active.deleted = Set(deleted);
+    active.update(db).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrate::create_schema;
+    use sea_orm::Database;
+
+    #[tokio::test]
+    async fn deleted_workflows_are_hidden_until_restored() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&db).await.unwrap();
+        workflow::ActiveModel {
+            id: Set("7".into()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        job::ActiveModel {
+            workid: Set("w1".into()),
+            workflow_id: Set(7),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        delete_workflow(&db, "7").await.unwrap();
+        assert!(active_workflow(&db, "7").await.unwrap().is_none());
+        assert!(workflow_with_deleted(&db, "7").await.unwrap().is_some());
+        assert!(active_workflows(&db).await.unwrap().is_empty());
+
+        restore_workflow(&db, "7").await.unwrap();
+        assert!(active_workflow(&db, "7").await.unwrap().is_some());
+        let job_id = active_jobs(&db, "7").await.unwrap()[0].id;
+        delete_job(&db, job_id).await.unwrap();
+        assert!(active_jobs(&db, "7").await.unwrap().is_empty());
+        assert!(delete_agent_config(&db, "missing").await.is_err());
+    }
+}
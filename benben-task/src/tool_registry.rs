@@ -0,0 +1,57 @@
+//! Rust 原生工具注册表。
+//!
+//! 实现了 rig `Tool` 的工具按名称注册到这里，AgentConfig 中通过 `tools=["add"]` 引用，
+//! 创建agent时与mcp工具合并，请求时一起提供给模型。
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use once_cell::sync::OnceCell;
+use rig::tool::{Tool, ToolDyn};
+
+static INST: OnceCell<ToolRegistry> = OnceCell::new();
+
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: RwLock<HashMap<String, Arc<dyn ToolDyn>>>,
+}
+
+impl ToolRegistry {
+    /// 全局注册表
+    pub fn global() -> &'static ToolRegistry {
+        INST.get_or_init(ToolRegistry::default)
+    }
+
+    /// 注册工具，同名的工具会被替换
+    pub fn register(&self, tool: impl Tool + 'static) {
+        self.register_dyn(Arc::new(tool));
+    }
+
+    pub fn register_dyn(&self, tool: Arc<dyn ToolDyn>) {
+        self.tools
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(tool.name(), tool);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn ToolDyn>> {
+        self.tools
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned()
+    }
+
+    /// 已注册的工具名称
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .tools
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys()
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+}
@@ -0,0 +1,444 @@
+//! 多个引擎进程共用同一个数据库时按租约分配任务。
+//!
+//! [LeaderElector] 只让一个主节点执行所有任务，任务多到一台机器执行不过来时改用 worker 模式：
+//! 每个进程是一个 worker，任务的 `worker_id` 和 `heartbeat_at` 两列是任务的租约，worker 定期
+//! 对自己持有的任务续约，并认领租约过期的任务。认领使用带旧值条件的更新，同一个任务同一时间只有一个
+//! worker 能拿到租约；认领同时把任务的版本加1。引擎写入任务时使用自己上一次写入得到的版本，
+//! 原来的 worker 在发现租约丢失之前的写入因此返回 [TaskEngineError::Conflict]，不会覆盖新 worker 的修改。
+//!
+//! - 新创建的任务还没有 worker，由创建它的进程在下一次续约时认领；创建超过租约有效期仍然没有 worker
+//!   的任务可以被任何 worker 认领
+//! - worker 续约时发现任务已经被其他 worker 认领，立即丢弃内存中的任务，不再推进
+//! - 暂停、继续、取消等控制命令通过 [TaskEngine::send_command] 发送：任务不在当前进程中时写入
+//!   `task_command` 表，由持有任务的 worker 轮询执行
+//! - 看门狗通过 [TaskWorker::reconcile] 对账，只处理租约已经过期的任务
+//!
+//! worker 模式下每个进程只执行自己认领的任务，不要再使用 [LeaderElector] 或者
+//! [TaskEngine::recover_from_db] 加载所有任务。
+//!
+//! ```rust,ignore
+//! let engine = Arc::new(TaskEngine::connect(&config).await?);
+//! let worker = TaskWorker::new(engine.db().unwrap(), format!("{}-{}", host, std::process::id()));
+//! worker.spawn(engine.clone(), Duration::from_secs(5));
+//! ```
+//!
+//! [LeaderElector]: crate::leader::LeaderElector
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::engine::clock::{Clock, SystemClock};
+use crate::engine::watchdog::{StuckTask, WatchdogPolicy};
+use crate::engine::{TaskEngine, TaskEngineError, TaskState};
+use crate::entities::{task, task_command};
+
+/// 发给任务的控制命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlCommand {
+    Pause,
+    Resume,
+    Stop,
+    Cancel,
+}
+
+impl ControlCommand {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ControlCommand::Pause => "pause",
+            ControlCommand::Resume => "resume",
+            ControlCommand::Stop => "stop",
+            ControlCommand::Cancel => "cancel",
+        }
+    }
+
+    pub fn parse(command: &str) -> Option<ControlCommand> {
+        match command {
+            "pause" => Some(ControlCommand::Pause),
+            "resume" => Some(ControlCommand::Resume),
+            "stop" => Some(ControlCommand::Stop),
+            "cancel" => Some(ControlCommand::Cancel),
+            _ => None,
+        }
+    }
+
+    async fn apply(&self, engine: &TaskEngine, task_id: i32) -> Result<(), TaskEngineError> {
+        match self {
+            ControlCommand::Pause => engine.pause(task_id).await,
+            ControlCommand::Resume => engine.resume(task_id).await,
+            ControlCommand::Stop => engine.stop(task_id).await,
+            ControlCommand::Cancel => engine.cancel(task_id).await,
+        }
+    }
+}
+
+/// 把命令写入发件箱，由持有任务的 worker 执行
+pub async fn enqueue_command(
+    db: &DatabaseConnection,
+    task_id: i32,
+    command: ControlCommand,
+    created_at: i64,
+) -> Result<task_command::Model, DbErr> {
+    task_command::ActiveModel {
+        id: NotSet,
+        task_id: Set(task_id),
+        command: Set(command.as_str().to_string()),
+        created_at: Set(created_at),
+        handled_at: Set(None),
+        error: Set(None),
+    }
+    .insert(db)
+    .await
+}
+
+pub struct TaskWorker {
+    db: Arc<DatabaseConnection>,
+    worker_id: String,
+    ttl: Duration,
+    batch: u64,
+    clock: Arc<dyn Clock>,
+}
+
+impl TaskWorker {
+    /// `worker_id` 为当前进程的唯一id，例如 主机名+进程号
+    pub fn new(db: Arc<DatabaseConnection>, worker_id: impl Into<String>) -> Self {
+        Self {
+            db,
+            worker_id: worker_id.into(),
+            ttl: Duration::from_secs(30),
+            batch: 16,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// 设置租约有效期，心跳间隔应明显小于该值
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// 每次最多认领的任务数量
+    pub fn batch(mut self, batch: u64) -> Self {
+        self.batch = batch.max(1);
+        self
+    }
+
+    /// 设置计算租约过期时间的时钟
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn worker_id(&self) -> &str {
+        &self.worker_id
+    }
+
+    /// 对内存中的任务续约，丢弃并返回已经被其他 worker 认领的任务
+    pub async fn heartbeat(&self, engine: &TaskEngine) -> Result<Vec<i32>, TaskEngineError> {
+        let held = engine.list_tasks().await;
+        if held.is_empty() {
+            return Ok(vec![]);
+        }
+        let db = self.db.as_ref();
+        task::Entity::update_many()
+            .col_expr(task::Column::WorkerId, Expr::value(self.worker_id.clone()))
+            .col_expr(
+                task::Column::HeartbeatAt,
+                Expr::value(self.clock.now_millis()),
+            )
+            .filter(task::Column::Id.is_in(held.clone()))
+            .filter(
+                Condition::any()
+                    .add(task::Column::WorkerId.eq(self.worker_id.clone()))
+                    .add(task::Column::WorkerId.is_null()),
+            )
+            .exec(db)
+            .await?;
+        let lost: Vec<i32> = task::Entity::find()
+            .select_only()
+            .column(task::Column::Id)
+            .filter(task::Column::Id.is_in(held))
+            .filter(task::Column::WorkerId.ne(self.worker_id.clone()))
+            .into_tuple()
+            .all(db)
+            .await?;
+        for task_id in &lost {
+            tracing::warn!(
+                "worker {} lost the lease of task {}",
+                self.worker_id,
+                task_id
+            );
+            engine.remove_task(*task_id).await?;
+        }
+        Ok(lost)
+    }
+
+    /// 认领租约过期以及创建后一直没有 worker 的任务，加载到引擎中，返回认领的任务
+    pub async fn claim(&self, engine: &TaskEngine) -> Result<Vec<i32>, TaskEngineError> {
+        let db = self.db.as_ref();
+        let now = self.clock.now_millis();
+        let cutoff = now - self.ttl.as_millis() as i64;
+        let candidates = task::Entity::find()
+            .filter(
+                task::Column::State
+                    .is_not_in([TaskState::Finished.as_str(), TaskState::Cancelled.as_str()]),
+            )
+            .filter(task::Column::Id.is_not_in(engine.list_tasks().await))
+            .filter(
+                Condition::any()
+                    .add(task::Column::HeartbeatAt.lt(cutoff))
+                    .add(
+                        Condition::all()
+                            .add(task::Column::HeartbeatAt.is_null())
+                            .add(task::Column::CreatedAt.lt(cutoff)),
+                    ),
+            )
+            .order_by_desc(task::Column::Priority)
+            .order_by_asc(task::Column::Id)
+            .limit(self.batch)
+            .all(db)
+            .await?;
+
+        let mut claimed = Vec::new();
+        for row in candidates {
+            // 只有租约和版本仍是读取时的值才更新，避免两个 worker 同时认领，或者抢走刚续约的任务
+            let heartbeat = match row.heartbeat_at {
+                Some(at) => task::Column::HeartbeatAt.eq(at),
+                None => task::Column::HeartbeatAt.is_null(),
+            };
+            let result = task::Entity::update_many()
+                .col_expr(task::Column::WorkerId, Expr::value(self.worker_id.clone()))
+                .col_expr(task::Column::HeartbeatAt, Expr::value(now))
+                .col_expr(
+                    task::Column::Version,
+                    Expr::col(task::Column::Version).add(1),
+                )
+                .filter(task::Column::Id.eq(row.id))
+                .filter(task::Column::Version.eq(row.version))
+                .filter(heartbeat)
+                .exec(db)
+                .await?;
+            if result.rows_affected != 1 {
+                continue;
+            }
+            if let Some(previous) = &row.worker_id {
+                tracing::info!(
+                    "worker {} took over task {} from {}",
+                    self.worker_id,
+                    row.id,
+                    previous
+                );
+            }
+            claimed.push(task::Model {
+                version: row.version + 1,
+                worker_id: Some(self.worker_id.clone()),
+                heartbeat_at: Some(now),
+                ..row
+            });
+        }
+        let ids = claimed.iter().map(|task| task.id).collect();
+        engine.recover_tasks(claimed).await?;
+        Ok(ids)
+    }
+
+    /// 执行发给当前 worker 持有的任务的命令，返回处理的命令数量
+    pub async fn poll_commands(&self, engine: &TaskEngine) -> Result<usize, TaskEngineError> {
+        let held = engine.list_tasks().await;
+        if held.is_empty() {
+            return Ok(0);
+        }
+        let db = self.db.as_ref();
+        let owned: Vec<i32> = task::Entity::find()
+            .select_only()
+            .column(task::Column::Id)
+            .filter(task::Column::Id.is_in(held))
+            .filter(task::Column::WorkerId.eq(self.worker_id.clone()))
+            .into_tuple()
+            .all(db)
+            .await?;
+        let commands = task_command::Entity::find()
+            .filter(task_command::Column::HandledAt.is_null())
+            .filter(task_command::Column::TaskId.is_in(owned))
+            .order_by_asc(task_command::Column::Id)
+            .all(db)
+            .await?;
+        let handled = commands.len();
+        for command in commands {
+            let error = match ControlCommand::parse(&command.command) {
+                Some(c) => c.apply(engine, command.task_id).await.err(),
+                None => Some(TaskEngineError::Other(format!(
+                    "Unknown command {}",
+                    command.command
+                ))),
+            };
+            if let Some(e) = &error {
+                tracing::warn!(
+                    "command {} of task {} failed: {}",
+                    command.command,
+                    command.task_id,
+                    e
+                );
+            }
+            let mut active: task_command::ActiveModel = command.into();
+            active.handled_at = Set(Some(self.clock.now_millis()));
+            active.error = Set(error.map(|e| e.to_string()));
+            active.update(db).await?;
+        }
+        Ok(handled)
+    }
+
+    /// 续约、认领任务并执行命令
+    pub async fn tick(&self, engine: &TaskEngine) -> Result<(), TaskEngineError> {
+        self.heartbeat(engine).await?;
+        let claimed = self.claim(engine).await?;
+        if !claimed.is_empty() {
+            tracing::info!("worker {} claimed tasks {:?}", self.worker_id, claimed);
+        }
+        self.poll_commands(engine).await?;
+        Ok(())
+    }
+
+    /// 任务的租约是否仍然有效。还没有 worker 的新任务在创建后的租约有效期内同样算作有效，
+    /// 与 [TaskWorker::claim] 的条件相反
+    pub(crate) fn lease_active(&self, row: &task::Model) -> bool {
+        let cutoff = self.clock.now_millis() - self.ttl.as_millis() as i64;
+        row.heartbeat_at.unwrap_or(row.created_at) >= cutoff
+    }
+
+    /// worker 模式下对账一次，见 [crate::engine::watchdog]。租约有效的任务由持有它的 worker 负责，
+    /// 重新排队的任务由当前 worker 认领
+    pub async fn reconcile(
+        &self,
+        engine: &TaskEngine,
+        policy: &WatchdogPolicy,
+    ) -> Result<Vec<StuckTask>, TaskEngineError> {
+        engine.reconcile_with(policy, Some(self)).await
+    }
+
+    /// 主动释放所有租约，其他 worker 可以立即认领，用于进程退出前
+    pub async fn release(&self) -> Result<(), DbErr> {
+        task::Entity::update_many()
+            .col_expr(task::Column::HeartbeatAt, Expr::value(0i64))
+            .filter(task::Column::WorkerId.eq(self.worker_id.clone()))
+            .exec(self.db.as_ref())
+            .await?;
+        Ok(())
+    }
+
+    /// 按固定间隔续约、认领任务并执行命令
+    pub fn spawn(self, engine: Arc<TaskEngine>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.tick(&engine).await {
+                    tracing::error!("worker {} heartbeat failed: {}", self.worker_id, e);
+                }
+            }
+        })
+    }
+}
+
+impl TaskEngine {
+    /// 执行控制命令，任务不在当前进程中时写入命令发件箱，由持有任务的 worker 执行
+    pub async fn send_command(
+        &self,
+        task_id: i32,
+        command: ControlCommand,
+    ) -> Result<(), TaskEngineError> {
+        if self.list_tasks().await.contains(&task_id) {
+            return command.apply(self, task_id).await;
+        }
+        let stored = match self.store() {
+            Some(store) => store.load_task(task_id).await?.is_some(),
+            None => false,
+        };
+        if !stored {
+            return Err(TaskEngineError::task_not_found(task_id));
+        }
+        let db = self.db().ok_or_else(|| {
+            TaskEngineError::Unavailable("Sending commands requires a database".to_string())
+        })?;
+        enqueue_command(&db, task_id, command, self.clock().now_millis()).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::clock::MockClock;
+    use crate::migrate::create_schema;
+    use sea_orm::Database;
+
+    #[tokio::test]
+    async fn workers_take_over_expired_leases() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        create_schema(&db).await.unwrap();
+        let db = Arc::new(db);
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let engine =
+            |clock: Arc<MockClock>| TaskEngine::new().with_db(db.clone()).with_clock(clock);
+        let (engine_a, engine_b) = (engine(clock.clone()), engine(clock.clone()));
+        let ttl = Duration::from_secs(30);
+        let worker_a = TaskWorker::new(db.clone(), "a")
+            .ttl(ttl)
+            .clock(clock.clone());
+        let worker_b = TaskWorker::new(db.clone(), "b")
+            .ttl(ttl)
+            .clock(clock.clone());
+
+        // 刚创建的任务留给创建它的进程认领
+        let task_id = engine_a.create_task("orders".into()).await.unwrap();
+        engine_a.start(task_id).await.unwrap();
+        assert!(worker_b.claim(&engine_b).await.unwrap().is_empty());
+        assert!(worker_a.heartbeat(&engine_a).await.unwrap().is_empty());
+        clock.advance(ttl);
+        assert!(worker_a.heartbeat(&engine_a).await.unwrap().is_empty());
+        assert!(worker_b.claim(&engine_b).await.unwrap().is_empty());
+
+        // 任务不在 b 中，命令经过发件箱由 a 执行
+        engine_b
+            .send_command(task_id, ControlCommand::Pause)
+            .await
+            .unwrap();
+        assert_eq!(worker_b.poll_commands(&engine_b).await.unwrap(), 0);
+        assert_eq!(worker_a.poll_commands(&engine_a).await.unwrap(), 1);
+        assert_eq!(
+            engine_a.get_state(task_id).await.unwrap(),
+            TaskState::Pending
+        );
+
+        // a 停止续约，租约过期后由 b 接管，a 续约时发现并丢弃任务
+        clock.advance(ttl * 2);
+        assert_eq!(worker_b.claim(&engine_b).await.unwrap(), vec![task_id]);
+        assert_eq!(
+            engine_b.get_state(task_id).await.unwrap(),
+            TaskState::Pending
+        );
+        // a 还没有发现租约丢失，基于认领前版本的写入被拒绝
+        assert!(matches!(
+            engine_a.set_priority(task_id, 5).await.unwrap_err(),
+            TaskEngineError::Conflict(_)
+        ));
+        assert!(matches!(
+            engine_a.resume(task_id).await.unwrap_err(),
+            TaskEngineError::Conflict(_)
+        ));
+        engine_b.set_priority(task_id, 2).await.unwrap();
+        assert_eq!(worker_a.heartbeat(&engine_a).await.unwrap(), vec![task_id]);
+        assert!(engine_a.get_state(task_id).await.is_err());
+
+        worker_b.release().await.unwrap();
+        assert_eq!(worker_a.claim(&engine_a).await.unwrap(), vec![task_id]);
+    }
+}
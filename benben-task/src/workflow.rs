@@ -18,6 +18,10 @@
 //! step5 ---完成工作。
 //!           
 
+use crate::engine::preview::{dry_run, DryRunPlan};
+use crate::mananger::AgentManager;
+use crate::soft_delete;
+
 pub struct TaskVo {
     // 调用这个任务的时候work flow的定义
     pub input: String,
@@ -25,26 +29,54 @@ pub struct TaskVo {
     // 存在一个智能体触发机制，其应当是一个智能体，能够实现给出结果之后，可进行
     pub workflowid: String,
     // 其设定了人工参与的空间，即在整个执行空间之重需要部分区域由人参与。
+    // 试运行：只渲染执行计划，不创建任务也不调用模型。
+    pub dry_run: bool,
+    // 发起任务的用户，只能使用自己的或者共享的工作流，任务计入该用户的配额。
+    pub owner_id: String,
 }
 
 /// [start task]  开始任务。
 /// step 1 通过 workflowId 查询 工作流程plan字段。
 /// step 2 创建任务 得到任务id
-/// step 3 plan  | 分割符号  完成对计划表的装填。
+/// step 3 plan  | 分割符号或者json数组  完成对计划表的装填，见 [crate::engine::plan]。
 /// step 4 通过workflowId 查询workflowId所装填的job 智能体全貌。
 /// 其中work 是一个智能体，他是个单独的智能体通过所有job只能体的描述选择智能体执行，
 /// 其决策依据就是plan计划执行对智能体的调度，并完成对计划表的维护。
 /// 
 /// 完成入库操作之后，待着workflowId  taskId 以及 input 丢入任务执行引擎。
-pub async fn start_task(_task: TaskVo) {
-    // In a real implementation, this would:
-    // 1. Query the workflow by workflowid
-    // 2. Create a new task in the database
-    // 3. Initialize the task with the engine
-    // 4. Start the task execution
-    
-    // For now, we're just providing the function structure
+///
+/// `dry_run` 为 true 时返回执行计划，见 [crate::engine::preview]。
+/// `owner_id` 不能为空，其他用户的工作流视为不存在，见 [crate::engine::tenant]。
+pub async fn start_task(task: TaskVo) -> Result<Option<DryRunPlan>, Box<dyn std::error::Error>> {
+    if task.owner_id.is_empty() {
+        return Err("owner_id is required".into());
+    }
+    // 1. Query the workflow by workflowid and validate the input against its input schema
+    let engine = crate::engine::TaskEngine::global();
+    if let Some(db) = engine.as_ref().and_then(|engine| engine.db()) {
+        let workflow = soft_delete::active_workflow(db.as_ref(), &task.workflowid)
+            .await?
+            .filter(|w| w.owner_id.as_ref().is_none_or(|owner| *owner == task.owner_id))
+            .ok_or_else(|| format!("Workflow {} not found", task.workflowid))?;
+        crate::engine::schema::TaskSchemas::from_workflow(&workflow)?.validate_input(&task.input)?;
+
+        if task.dry_run {
+            let jobs = soft_delete::active_jobs(db.as_ref(), &workflow.id).await?;
+            let agents = AgentManager::global().map(|m| m.agent_vec.clone()).unwrap_or_default();
+            return Ok(Some(dry_run(&workflow, &jobs, &task.input, &agents)));
+        }
+
+        // 2. Create the task for its owner and attach the workflow, which fills the plan rows (step 3),
+        // the scheduler starts it within the owner's quota
+        if let Some(engine) = &engine {
+            let task_id = engine.create_task_as(&task.owner_id, task.input).await?;
+            engine.attach_workflow(task_id, workflow).await?;
+            return Ok(None);
+        }
+    }
+
     println!("Task start functionality would be implemented here");
+    Ok(None)
 }
 
 ///[stop_task] 根据任务Id进行任务暂停任务执行，
@@ -161,4 +193,67 @@ pub async fn finish_task(task_id: &str) {
             eprintln!("Invalid task ID: {}", task_id);
         }
     }
-}
\ No newline at end of file
+}
+
+/// [cancel_workflow_tasks] 批量取消某个工作流下的所有任务
+/// 返回批量操作结果，引擎未初始化时返回None
+pub async fn cancel_workflow_tasks(workflow_id: &str) -> Option<crate::engine::bulk::BulkReport> {
+    let engine = bulk_engine()?;
+    let report = engine
+        .cancel_workflow_tasks(workflow_id, None, |p| {
+            tracing::debug!("cancelling workflow {} tasks: {}/{}", workflow_id, p.done, p.total);
+        })
+        .await;
+    log_failures("cancel", &report);
+    Some(report)
+}
+
+/// [pause_workflow_tasks] 批量暂停某个工作流下的所有任务
+pub async fn pause_workflow_tasks(workflow_id: &str) -> Option<crate::engine::bulk::BulkReport> {
+    let engine = bulk_engine()?;
+    let report = engine
+        .pause_workflow_tasks(workflow_id, None, |p| {
+            tracing::debug!("pausing workflow {} tasks: {}/{}", workflow_id, p.done, p.total);
+        })
+        .await;
+    log_failures("pause", &report);
+    Some(report)
+}
+
+/// [pause_owner_tasks] 批量暂停某个用户的所有任务
+pub async fn pause_owner_tasks(owner: &str) -> Option<crate::engine::bulk::BulkReport> {
+    let engine = bulk_engine()?;
+    let report = engine
+        .pause_owner_tasks(Some(owner), |p| {
+            tracing::debug!("pausing tasks of {}: {}/{}", owner, p.done, p.total);
+        })
+        .await;
+    log_failures("pause", &report);
+    Some(report)
+}
+
+/// [resume_stopped_tasks] 批量恢复已停止的任务，可以只恢复某个用户的、`since`（unix 毫秒）之后停止的任务
+pub async fn resume_stopped_tasks(owner: Option<&str>, since: Option<i64>) -> Option<crate::engine::bulk::BulkReport> {
+    let engine = bulk_engine()?;
+    let report = engine
+        .resume_stopped_tasks(owner, since, |p| {
+            tracing::debug!("resuming stopped tasks: {}/{}", p.done, p.total);
+        })
+        .await;
+    log_failures("resume", &report);
+    Some(report)
+}
+
+fn bulk_engine() -> Option<std::sync::Arc<crate::engine::TaskEngine>> {
+    let engine = crate::engine::TaskEngine::global();
+    if engine.is_none() {
+        tracing::warn!("task engine not initialized");
+    }
+    engine
+}
+
+fn log_failures(action: &str, report: &crate::engine::bulk::BulkReport) {
+    for (id, e) in &report.failed {
+        tracing::warn!("failed to {} task {}: {}", action, id, e);
+    }
+}
@@ -0,0 +1,30 @@
+//! 任务工作目录。
+//!
+//! 每个任务使用一个独立的目录，并作为 mcp roots 传给该任务的stdio mcp服务，
+//! 文件类的mcp服务只能访问自己任务的目录，而不是整个仓库。
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 默认的工作目录根路径，相对于进程的当前目录
+pub const DEFAULT_WORKSPACE_DIR: &str = "./workspace";
+
+/// 任务工作目录的路径
+pub fn task_workspace_path(base: &Path, task_id: i32) -> PathBuf {
+    base.join(format!("task-{}", task_id))
+}
+
+/// 创建任务工作目录，返回绝对路径
+pub fn create_task_workspace(base: &Path, task_id: i32) -> io::Result<PathBuf> {
+    let path = task_workspace_path(base, task_id);
+    std::fs::create_dir_all(&path)?;
+    std::fs::canonicalize(path)
+}
+
+/// 删除任务工作目录，目录不存在时直接返回
+pub fn remove_task_workspace(base: &Path, task_id: i32) -> io::Result<()> {
+    match std::fs::remove_dir_all(task_workspace_path(base, task_id)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
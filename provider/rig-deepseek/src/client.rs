@@ -10,10 +10,14 @@
 //! ```
 
 use reqwest::Client as HttpClient;
+use reqwest::header::HeaderMap;
+use rig::client::timeout::Timeouts;
+use rig::client::transport::Transport;
 use rig::client::{
     ClientBuilderError, CompletionClient, ProviderClient, VerifyClient, VerifyError,
 };
 use rig::impl_conversion_traits;
+use std::time::Duration;
 
 use crate::completion::DsCompletionModel;
 
@@ -25,7 +29,10 @@ const DEEPSEEK_API_BASE_URL: &str = "https://api.deepseek.com";
 pub struct ClientBuilder<'a> {
     api_key: &'a str,
     base_url: &'a str,
+    default_headers: HeaderMap,
     http_client: Option<reqwest::Client>,
+    timeouts: Timeouts,
+    transport: Transport,
 }
 
 impl<'a> ClientBuilder<'a> {
@@ -33,7 +40,10 @@ impl<'a> ClientBuilder<'a> {
         Self {
             api_key,
             base_url: DEEPSEEK_API_BASE_URL,
+            default_headers: HeaderMap::new(),
             http_client: None,
+            timeouts: Timeouts::default(),
+            transport: Transport::default(),
         }
     }
 
@@ -42,22 +52,57 @@ impl<'a> ClientBuilder<'a> {
         self
     }
 
+    /// Headers added to every request, e.g. the organization header of a gateway.
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.default_headers.extend(headers);
+        self
+    }
+
     pub fn custom_client(mut self, client: reqwest::Client) -> Self {
         self.http_client = Some(client);
         self
     }
 
+    /// Timeout of a whole request. Streaming requests time out when the response or the next
+    /// chunk takes longer than this.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.request = Some(timeout);
+        self
+    }
+
+    /// Send all requests through a proxy, ignored with a [Self::custom_client].
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.transport.proxy = Some(proxy);
+        self
+    }
+
+    /// Trust an additional root certificate, ignored with a [Self::custom_client].
+    pub fn root_certificate(mut self, certificate: reqwest::Certificate) -> Self {
+        self.transport.root_certificates.push(certificate);
+        self
+    }
+
+    /// Timeout of establishing a connection, ignored with a [Self::custom_client].
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.connect = Some(timeout);
+        self
+    }
+
     pub fn build(self) -> Result<Client, ClientBuilderError> {
         let http_client = if let Some(http_client) = self.http_client {
             http_client
         } else {
-            reqwest::Client::builder().build()?
+            self.transport
+                .apply(self.timeouts.client_builder())
+                .build()?
         };
 
         Ok(Client {
             base_url: self.base_url.to_string(),
             api_key: self.api_key.to_string(),
+            default_headers: self.default_headers,
             http_client,
+            timeouts: self.timeouts,
         })
     }
 }
@@ -66,7 +111,9 @@ impl<'a> ClientBuilder<'a> {
 pub struct Client {
     pub base_url: String,
     api_key: String,
+    default_headers: HeaderMap,
     http_client: HttpClient,
+    pub(crate) timeouts: Timeouts,
 }
 
 impl std::fmt::Debug for Client {
@@ -105,23 +152,57 @@ impl Client {
     }
 
     pub(crate) fn post(&self, path: &str) -> reqwest::RequestBuilder {
-        let url = format!("{}/{}", self.base_url, path).replace("//", "/");
-        self.http_client.post(url).bearer_auth(&self.api_key)
+        self.http_client
+            .post(self.url(path))
+            .headers(self.default_headers.clone())
+            .bearer_auth(&self.api_key)
     }
 
     pub(crate) fn get(&self, path: &str) -> reqwest::RequestBuilder {
-        let url = format!("{}/{}", self.base_url, path).replace("//", "/");
-        self.http_client.get(url).bearer_auth(&self.api_key)
+        self.http_client
+            .get(self.url(path))
+            .headers(self.default_headers.clone())
+            .bearer_auth(&self.api_key)
+    }
+
+    /// Gateways may serve the API below a path, e.g. `https://gateway.corp/deepseek/`.
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.base_url.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
     }
 }
 
+/// Send a request, through [rig::http_record] when the `http-record` feature is enabled so
+/// tests can run against recorded responses.
+pub(crate) async fn send(request: reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+    #[cfg(feature = "http-record")]
+    return rig::http_record::send(request).await;
+    #[cfg(not(feature = "http-record"))]
+    request.send().await
+}
+
 impl ProviderClient for Client {
-    fn from_config(config: rig::client::AgentConfig) -> Box<dyn ProviderClient>
+    fn from_config(
+        config: rig::client::AgentConfig,
+    ) -> Result<Box<dyn ProviderClient>, ClientBuilderError>
     where
         Self: Sized,
     {
-        let api_key = config.api_key.as_ref().expect("DEEPSEEK_API_KEY not set");
-        Box::new(Self::new(api_key))
+        let api_key = config
+            .api_key
+            .as_deref()
+            .filter(|key| !key.is_empty())
+            .ok_or(ClientBuilderError::InvalidProperty("api_key"))?;
+        let mut builder = Self::builder(api_key).default_headers(config.header_map()?);
+        if !config.base_url.is_empty() {
+            builder = builder.base_url(&config.base_url);
+        }
+        builder.timeouts = config.timeouts();
+        builder.transport = config.transport()?;
+        Ok(Box::new(builder.build()?))
     }
 }
 
@@ -140,10 +221,16 @@ impl CompletionClient for Client {
 impl VerifyClient for Client {
     #[cfg_attr(feature = "worker", worker::send)]
     async fn verify(&self) -> Result<(), VerifyError> {
-        let response = self.get("/user/balance").send().await?;
+        // `/models` is also served by DeepSeek compatible gateways, unlike `/user/balance`
+        let response = send(self.timeouts.apply(self.get("/models"))).await?;
         match response.status() {
             reqwest::StatusCode::OK => Ok(()),
-            reqwest::StatusCode::UNAUTHORIZED => Err(VerifyError::InvalidAuthentication),
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                Err(VerifyError::InvalidAuthentication)
+            }
+            reqwest::StatusCode::PAYMENT_REQUIRED => {
+                Err(VerifyError::ProviderError("insufficient balance".to_string()))
+            }
             reqwest::StatusCode::INTERNAL_SERVER_ERROR
             | reqwest::StatusCode::SERVICE_UNAVAILABLE => {
                 Err(VerifyError::ProviderError(response.text().await?))
@@ -159,3 +246,30 @@ impl VerifyClient for Client {
 impl_conversion_traits!(
     AsEmbeddings for Client
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn gateway_base_url_and_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "openai-organization",
+            HeaderValue::from_static("org-benben"),
+        );
+        let client = Client::builder("key")
+            .base_url("https://gateway.corp/deepseek/")
+            .default_headers(headers)
+            .build()
+            .unwrap();
+
+        let request = client.post("/chat/completions").build().unwrap();
+        assert_eq!(
+            request.url().as_str(),
+            "https://gateway.corp/deepseek/chat/completions"
+        );
+        assert_eq!(request.headers()["openai-organization"], "org-benben");
+    }
+}
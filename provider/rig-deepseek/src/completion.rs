@@ -2,12 +2,13 @@ use rig::json_utils::merge;
 use rig::streaming::StreamingCompletionResponse;
 
 use rig::completion::{self, CompletionError, CompletionRequest};
+use rig::telemetry::redact::redact;
 use serde_json::json;
 use tracing::{Instrument, info_span};
 
 use crate::streaming::send_compatible_streaming_request;
 use crate::{
-    client::Client,
+    client::{Client, send},
     convert::{
         ApiResponse,
         rsp_req::{DsCompletionResponse, create_completion_request},
@@ -58,45 +59,60 @@ impl completion::CompletionModel for DsCompletionModel {
                 gen_ai.response.model = tracing::field::Empty,
                 gen_ai.usage.output_tokens = tracing::field::Empty,
                 gen_ai.usage.input_tokens = tracing::field::Empty,
-                gen_ai.input.messages = serde_json::to_string(&request.get("messages").unwrap()).unwrap(),
+                gen_ai.usage.cache_read.input_tokens = tracing::field::Empty,
+                gen_ai.usage.cache_miss.input_tokens = tracing::field::Empty,
+                gen_ai.input.messages = redact(&serde_json::to_string(&request.get("messages").unwrap()).unwrap()),
                 gen_ai.output.messages = tracing::field::Empty,
             )
         } else {
             tracing::Span::current()
         };
 
-        tracing::debug!("DeepSeek completion request: {request:?}");
+        tracing::debug!("DeepSeek completion request: {}", redact(&request.to_string()));
 
         async move {
-            let response = self
+            let request = self
                 .client
-                .post("/chat/completions")
-                .json(&request)
-                .send()
-                .await?;
+                .timeouts
+                .apply(self.client.post("/chat/completions").json(&request));
+            let response = send(request).await?;
 
             if response.status().is_success() {
                 let t = response.text().await?;
-                tracing::debug!(target: "rig", "DeepSeek completion: {t}");
+                tracing::debug!(target: "rig", "DeepSeek completion: {}", redact(&t));
 
                 match serde_json::from_str::<ApiResponse<DsCompletionResponse>>(&t)? {
                     ApiResponse::Ok(response) => {
                         let span = tracing::Span::current();
                         span.record(
                             "gen_ai.output.messages",
-                            serde_json::to_string(&response.choices).unwrap(),
+                            redact(&serde_json::to_string(&response.choices).unwrap()),
                         );
                         span.record("gen_ai.usage.input_tokens", response.usage.prompt_tokens);
                         span.record(
                             "gen_ai.usage.output_tokens",
                             response.usage.completion_tokens,
                         );
+                        span.record(
+                            "gen_ai.usage.cache_read.input_tokens",
+                            response.usage.prompt_cache_hit_tokens,
+                        );
+                        span.record(
+                            "gen_ai.usage.cache_miss.input_tokens",
+                            response.usage.prompt_cache_miss_tokens,
+                        );
+                        tracing::debug!(
+                            target: "rig",
+                            "DeepSeek prompt cache hit {} / miss {}",
+                            response.usage.prompt_cache_hit_tokens,
+                            response.usage.prompt_cache_miss_tokens
+                        );
                         response.try_into()
                     }
-                    ApiResponse::Err(err) => Err(CompletionError::ProviderError(err.message)),
+                    ApiResponse::Err(err) => Err(CompletionError::provider(err.message)),
                 }
             } else {
-                Err(CompletionError::ProviderError(response.text().await?))
+                Err(CompletionError::from_response(response).await)
             }
         }
         .instrument(span)
@@ -130,13 +146,16 @@ impl completion::CompletionModel for DsCompletionModel {
                 gen_ai.response.model = tracing::field::Empty,
                 gen_ai.usage.output_tokens = tracing::field::Empty,
                 gen_ai.usage.input_tokens = tracing::field::Empty,
-                gen_ai.input.messages = serde_json::to_string(&request.get("messages").unwrap()).unwrap(),
+                gen_ai.usage.cache_read.input_tokens = tracing::field::Empty,
+                gen_ai.usage.cache_miss.input_tokens = tracing::field::Empty,
+                gen_ai.input.messages = redact(&serde_json::to_string(&request.get("messages").unwrap()).unwrap()),
                 gen_ai.output.messages = tracing::field::Empty,
             )
         } else {
             tracing::Span::current()
         };
 
-        tracing::Instrument::instrument(send_compatible_streaming_request(builder), span).await
+        let response = send_compatible_streaming_request(builder, self.client.timeouts);
+        tracing::Instrument::instrument(response, span).await
     }
 }
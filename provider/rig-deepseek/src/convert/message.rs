@@ -32,6 +32,9 @@ pub enum DsMessage {
             skip_serializing_if = "Vec::is_empty"
         )]
         tool_calls: Vec<DsToolCall>,
+        /// Reasoning of `deepseek-reasoner`. Only in responses, the API rejects it in requests.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reasoning_content: Option<String>,
     },
     #[serde(rename = "tool")]
     ToolResult {
@@ -109,6 +112,7 @@ impl TryFrom<RigMessage> for Vec<DsMessage> {
                             content: text.text,
                             name: None,
                             tool_calls: vec![],
+                            reasoning_content: None,
                         }),
                         _ => None,
                     })
@@ -134,6 +138,7 @@ impl TryFrom<RigMessage> for Vec<DsMessage> {
                         content: "".to_string(),
                         name: None,
                         tool_calls,
+                        reasoning_content: None,
                     });
                 }
 
@@ -21,6 +21,6 @@ pub(crate) struct ApiErrorResponse {
 // 消息转化成统一错误信息
 impl From<ApiErrorResponse> for CompletionError {
     fn from(err: ApiErrorResponse) -> Self {
-        CompletionError::ProviderError(err.message)
+        CompletionError::provider(err.message)
     }
 }
@@ -3,11 +3,14 @@ use serde_json::json;
 
 use rig::{
     OneOrMany,
-    completion::{CompletionError, CompletionRequest, CompletionResponse, Usage},
+    completion::{
+        CompletionError, CompletionRequest, CompletionResponse, TokenLogprob, TopLogprob, Usage,
+    },
     json_utils,
-    message::AssistantContent,
+    message::{AssistantContent, Reasoning},
 };
 
+use crate::completion::{DEEPSEEK_CHAT, DEEPSEEK_REASONER};
 use crate::convert::{
         message::{DsMessage, RigMessage},
         tool::{DsToolChoice, DsToolDefinition},
@@ -26,7 +29,11 @@ pub struct DsCompletionResponse {
 pub struct DsUsage {
     pub completion_tokens: u32,
     pub prompt_tokens: u32,
+    /// 命中 DeepSeek 上下文硬盘缓存的输入 token 数。
+    #[serde(default)]
     pub prompt_cache_hit_tokens: u32,
+    /// 未命中缓存的输入 token 数。
+    #[serde(default)]
     pub prompt_cache_miss_tokens: u32,
     pub total_tokens: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -47,6 +54,27 @@ impl DsUsage {
             prompt_tokens_details: None,
         }
     }
+
+    /// 缓存命中率，preamble 越稳定命中率越高；没有输入 token 时返回 `None`。
+    pub fn cache_hit_ratio(&self) -> Option<f64> {
+        let total = self.prompt_cache_hit_tokens + self.prompt_cache_miss_tokens;
+        if total == 0 {
+            None
+        } else {
+            Some(self.prompt_cache_hit_tokens as f64 / total as f64)
+        }
+    }
+}
+
+impl From<&DsUsage> for Usage {
+    fn from(usage: &DsUsage) -> Self {
+        Usage {
+            input_tokens: usage.prompt_tokens as u64,
+            output_tokens: usage.completion_tokens as u64,
+            total_tokens: usage.total_tokens as u64,
+            cached_input_tokens: usage.prompt_cache_hit_tokens as u64,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
@@ -65,10 +93,60 @@ pub struct PromptTokensDetails {
 pub struct Choice {
     pub index: usize,
     pub message: DsMessage,
-    pub logprobs: Option<serde_json::Value>,
+    /// 请求中设置 `"logprobs": true` 时返回，`top_logprobs` 控制每个位置返回的候选数。
+    #[serde(default)]
+    pub logprobs: Option<DsLogprobs>,
     pub finish_reason: String,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct DsLogprobs {
+    #[serde(default)]
+    pub content: Option<Vec<DsTokenLogprob>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DsTokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<Vec<u8>>,
+    #[serde(default)]
+    pub top_logprobs: Vec<DsTopLogprob>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DsTopLogprob {
+    pub token: String,
+    pub logprob: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<Vec<u8>>,
+}
+
+impl From<&DsTokenLogprob> for TokenLogprob {
+    fn from(value: &DsTokenLogprob) -> Self {
+        TokenLogprob {
+            token: value.token.clone(),
+            logprob: value.logprob,
+            top_logprobs: value
+                .top_logprobs
+                .iter()
+                .map(|top| TopLogprob {
+                    token: top.token.clone(),
+                    logprob: top.logprob,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl DsCompletionResponse {
+    /// 第一个choice中每个生成token的对数概率，请求中没有开启 logprobs 时为空。
+    pub fn logprobs(&self) -> Option<&[DsTokenLogprob]> {
+        self.choices.first()?.logprobs.as_ref()?.content.as_deref()
+    }
+}
+
 impl TryFrom<DsCompletionResponse> for CompletionResponse<DsCompletionResponse> {
     type Error = CompletionError;
 
@@ -80,6 +158,7 @@ impl TryFrom<DsCompletionResponse> for CompletionResponse<DsCompletionResponse>
             DsMessage::Assistant {
                 content,
                 tool_calls,
+                reasoning_content,
                 ..
             } => {
                 let mut content = if content.trim().is_empty() {
@@ -87,6 +166,10 @@ impl TryFrom<DsCompletionResponse> for CompletionResponse<DsCompletionResponse>
                 } else {
                     vec![AssistantContent::text(content)]
                 };
+                // Reasoning is kept apart from the answer
+                if let Some(reasoning) = reasoning_content.as_deref().filter(|r| !r.is_empty()) {
+                    content.insert(0, AssistantContent::Reasoning(Reasoning::new(reasoning)));
+                }
 
                 content.extend(
                     tool_calls
@@ -113,24 +196,37 @@ impl TryFrom<DsCompletionResponse> for CompletionResponse<DsCompletionResponse>
             )
         })?;
 
-        let usage = Usage {
-            input_tokens: response.usage.prompt_tokens as u64,
-            output_tokens: response.usage.completion_tokens as u64,
-            total_tokens: response.usage.total_tokens as u64,
-        };
+        let usage = Usage::from(&response.usage);
+        let logprobs = response
+            .logprobs()
+            .map(|logprobs| logprobs.iter().map(TokenLogprob::from).collect());
 
         Ok(CompletionResponse {
             choice,
             usage,
+            logprobs,
+            timings: None,
+            served_by: None,
             raw_response: response,
         })
     }
 }
 
+/// DeepSeek has no `think` parameter, thinking is a separate model: `think` switches between
+/// `deepseek-chat` and `deepseek-reasoner`. Other models are kept.
+fn reasoning_model(model: String, think: Option<bool>) -> String {
+    match (model.as_str(), think) {
+        (DEEPSEEK_CHAT, Some(true)) => DEEPSEEK_REASONER.to_string(),
+        (DEEPSEEK_REASONER, Some(false)) => DEEPSEEK_CHAT.to_string(),
+        _ => model,
+    }
+}
+
 pub fn create_completion_request(
     model: String,
     completion_request: CompletionRequest,
 ) -> Result<serde_json::Value, CompletionError> {
+    let model = reasoning_model(model, completion_request.think);
     // Build up the order of messages (context, chat_history, prompt)
     let mut partial_history = vec![];
 
@@ -176,6 +272,13 @@ pub fn create_completion_request(
             "tool_choice": tool_choice,
         })
     };
+    // top_p, penalties and seed are top level fields of the request body
+    let request = json_utils::merge(request, json!(completion_request.sampling));
+    let request = if completion_request.stop.is_empty() {
+        request
+    } else {
+        json_utils::merge(request, json!({ "stop": completion_request.stop }))
+    };
 
     let request = if let Some(params) = completion_request.additional_params {
         json_utils::merge(request, params)
@@ -185,3 +288,86 @@ pub fn create_completion_request(
 
     Ok(request)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn think_switches_reasoner_model() {
+        assert_eq!(
+            reasoning_model(DEEPSEEK_CHAT.into(), Some(true)),
+            DEEPSEEK_REASONER
+        );
+        assert_eq!(
+            reasoning_model(DEEPSEEK_REASONER.into(), Some(false)),
+            DEEPSEEK_CHAT
+        );
+        assert_eq!(reasoning_model(DEEPSEEK_CHAT.into(), None), DEEPSEEK_CHAT);
+        assert_eq!(reasoning_model("custom".into(), Some(true)), "custom");
+    }
+
+    #[test]
+    fn reasoning_is_kept_apart_from_answer() {
+        let response: DsCompletionResponse = serde_json::from_value(json!({
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "42",
+                    "reasoning_content": "6 times 7"
+                },
+                "finish_reason": "stop"
+            }],
+            "usage": { "completion_tokens": 4, "prompt_tokens": 5, "total_tokens": 9 }
+        }))
+        .unwrap();
+
+        let response = CompletionResponse::try_from(response).unwrap();
+        assert_eq!(response.reasoning().as_deref(), Some("6 times 7"));
+        assert_eq!(response.choice.len(), 2);
+        assert_eq!(
+            response.choice.iter().last(),
+            Some(&AssistantContent::text("42"))
+        );
+
+        // reasoning is never sent back to the API
+        let message = RigMessage(rig::message::Message::Assistant {
+            id: None,
+            content: response.choice,
+        });
+        let messages: Vec<DsMessage> = message.try_into().unwrap();
+        let body = serde_json::to_string(&messages).unwrap();
+        assert!(!body.contains("reasoning_content"));
+    }
+
+    #[test]
+    fn logprobs_are_parsed() {
+        let response: DsCompletionResponse = serde_json::from_value(json!({
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "yes" },
+                "logprobs": {
+                    "content": [{
+                        "token": "yes",
+                        "logprob": -0.1,
+                        "bytes": [121, 101, 115],
+                        "top_logprobs": [
+                            { "token": "yes", "logprob": -0.1 },
+                            { "token": "no", "logprob": -2.4 }
+                        ]
+                    }]
+                },
+                "finish_reason": "stop"
+            }],
+            "usage": { "completion_tokens": 1, "prompt_tokens": 5, "total_tokens": 6 }
+        }))
+        .unwrap();
+
+        let response = CompletionResponse::try_from(response).unwrap();
+        let logprobs = response.logprobs.as_ref().unwrap();
+        assert_eq!(logprobs[0].token, "yes");
+        assert_eq!(logprobs[0].top_logprobs[1].token, "no");
+        assert!((response.confidence().unwrap() - (-0.1f64).exp()).abs() < 1e-9);
+    }
+}
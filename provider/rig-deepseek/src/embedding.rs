@@ -4,7 +4,7 @@ use serde_json::json;
 
 use crate::convert::{ApiErrorResponse, ApiResponse};
 
-use super::client::Client;
+use super::client::{Client, send};
 
 #[derive(Clone)]
 pub struct OlEmbeddingModel {
@@ -38,7 +38,7 @@ impl EmbeddingModel for OlEmbeddingModel {
             "model": self.model,
             "input": docs,
         });
-        let response = self.client.post("api/embed")?.json(&payload).send().await?;
+        let response = send(self.client.post("api/embed")?.json(&payload)).await?;
 
         if !response.status().is_success() {
             return Err(EmbeddingError::ProviderError(response.text().await?));
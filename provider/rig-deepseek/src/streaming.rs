@@ -1,12 +1,13 @@
 use std::collections::HashMap;
 
 use async_stream::stream;
-use futures::StreamExt as _;
 use reqwest_eventsource::{Event, RequestBuilderExt as _};
 use serde::{Deserialize, Serialize};
+use rig::client::timeout::Timeouts;
+use rig::telemetry::redact::redact;
 
 use rig::{
-    completion::{CompletionError, GetTokenUsage, Usage},
+    completion::{CompletionError, GetTokenUsage, ProviderErrorKind, Usage},
     json_utils,
     streaming::{RawStreamingChoice, StreamingCompletionResponse},
 };
@@ -58,16 +59,36 @@ pub struct DsStreamingCompletionResponse {
 
 impl GetTokenUsage for DsStreamingCompletionResponse {
     fn token_usage(&self) -> Option<Usage> {
-        let mut usage = Usage::new();
-        usage.input_tokens = self.usage.prompt_tokens as u64;
-        usage.output_tokens = self.usage.completion_tokens as u64;
-        usage.total_tokens = self.usage.total_tokens as u64;
-        Some(usage)
+        Some(Usage::from(&self.usage))
+    }
+}
+
+/// Classify an SSE error like the error of a non-streaming request, so a failed stream can be
+/// retried or fall back to another model. Malformed events stay response errors.
+async fn sse_error(err: reqwest_eventsource::Error) -> CompletionError {
+    match err {
+        reqwest_eventsource::Error::InvalidStatusCode(_, response)
+        | reqwest_eventsource::Error::InvalidContentType(_, response) => {
+            CompletionError::from_response(response).await
+        }
+        reqwest_eventsource::Error::Transport(err) => {
+            let message = err.to_string();
+            let kind = if err.is_timeout() {
+                ProviderErrorKind::Timeout
+            } else if err.is_connect() {
+                ProviderErrorKind::Unavailable
+            } else {
+                ProviderErrorKind::classify(err.status().map(|s| s.as_u16()), &message)
+            };
+            CompletionError::Provider { kind, message }
+        }
+        err => CompletionError::ResponseError(err.to_string()),
     }
 }
 
 pub(crate) async fn send_compatible_streaming_request(
     request_builder: reqwest::RequestBuilder,
+    timeouts: Timeouts,
 ) -> Result<
     crate::streaming::StreamingCompletionResponse<DsStreamingCompletionResponse>,
     CompletionError,
@@ -80,9 +101,19 @@ pub(crate) async fn send_compatible_streaming_request(
     let stream = Box::pin(stream! {
         let mut final_usage = DsUsage::new();
         let mut text_response = String::new();
+        let mut reasoning_response = String::new();
         let mut calls: HashMap<usize, (String, String, String)> = HashMap::new();
 
-        while let Some(event_result) = event_source.next().await {
+        loop {
+            // the first event waits for the response, later ones for the next chunk
+            let event_result = match timeouts.next_chunk(&mut event_source).await {
+                Ok(Some(event_result)) => event_result,
+                Ok(None) => break,
+                Err(err) => {
+                    yield Err(err);
+                    break;
+                }
+            };
             match event_result {
                 Ok(Event::Open) => {
                     tracing::trace!("SSE connection opened");
@@ -149,6 +180,7 @@ pub(crate) async fn send_compatible_streaming_request(
 
                         // DeepSeek-specific reasoning stream
                         if let Some(content) = &delta.reasoning_content {
+                            reasoning_response += content;
                             yield Ok(crate::streaming::RawStreamingChoice::Reasoning {
                                 reasoning: content.to_string(),
                                 id: None,
@@ -170,7 +202,7 @@ pub(crate) async fn send_compatible_streaming_request(
                 }
                 Err(err) => {
                     tracing::error!(?err, "SSE error");
-                    yield Err(CompletionError::ResponseError(err.to_string()));
+                    yield Err(sse_error(err).await);
                     break;
                 }
             }
@@ -203,10 +235,15 @@ pub(crate) async fn send_compatible_streaming_request(
         let message = DsMessage::Assistant {
             content: text_response,
             name: None,
-            tool_calls
+            tool_calls,
+            reasoning_content: (!reasoning_response.is_empty()).then_some(reasoning_response),
         };
 
-        span.record("gen_ai.output.messages", serde_json::to_string(&message).unwrap());
+        span.record("gen_ai.output.messages", redact(&serde_json::to_string(&message).unwrap()));
+        span.record("gen_ai.usage.input_tokens", final_usage.prompt_tokens);
+        span.record("gen_ai.usage.output_tokens", final_usage.completion_tokens);
+        span.record("gen_ai.usage.cache_read.input_tokens", final_usage.prompt_cache_hit_tokens);
+        span.record("gen_ai.usage.cache_miss.input_tokens", final_usage.prompt_cache_miss_tokens);
 
         yield Ok(crate::streaming::RawStreamingChoice::FinalResponse(
             DsStreamingCompletionResponse { usage: final_usage.clone() }
@@ -217,3 +254,39 @@ pub(crate) async fn send_compatible_streaming_request(
         stream,
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status_error(status: u16, body: &'static str) -> reqwest_eventsource::Error {
+        let response = http::Response::builder()
+            .status(status)
+            .header("retry-after", "2")
+            .body(body)
+            .unwrap();
+        reqwest_eventsource::Error::InvalidStatusCode(
+            response.status(),
+            reqwest::Response::from(response),
+        )
+    }
+
+    #[tokio::test]
+    async fn sse_errors_are_classified() {
+        let err = sse_error(status_error(429, "slow down")).await;
+        assert_eq!(
+            err.provider_kind(),
+            Some(&ProviderErrorKind::RateLimited {
+                retry_after: Some(std::time::Duration::from_secs(2))
+            })
+        );
+        assert!(err.is_retryable());
+
+        let err = sse_error(status_error(401, "invalid api key")).await;
+        assert_eq!(err.provider_kind(), Some(&ProviderErrorKind::AuthFailed));
+        assert!(!err.is_retryable());
+
+        let err = sse_error(reqwest_eventsource::Error::StreamEnded).await;
+        assert!(matches!(err, CompletionError::ResponseError(_)));
+    }
+}
@@ -38,13 +38,17 @@
 //! let agent = client.agent("llama3.2");
 //! let extractor = client.extractor::<serde_json::Value>("llama3.2");
 //! ```
+use rig::client::timeout::Timeouts;
+use rig::client::transport::Transport;
 use rig::client::{
     ClientBuilderError, CompletionClient, EmbeddingsClient, ProviderClient, VerifyClient,
     VerifyError,
 };
 use rig::embeddings::EmbeddingsBuilder;
+use std::time::Duration;
 
 use reqwest;
+use reqwest::header::HeaderMap;
 use rig::Embed;
 // use reqwest_eventsource::{Event, RequestBuilderExt}; // (Not used currently as Ollama does not support SSE)
 use url::Url;
@@ -57,7 +61,11 @@ const OLLAMA_API_BASE_URL: &str = "http://localhost:11434";
 
 pub struct ClientBuilder<'a> {
     base_url: &'a str,
+    api_key: Option<&'a str>,
+    default_headers: HeaderMap,
     http_client: Option<reqwest::Client>,
+    timeouts: Timeouts,
+    transport: Transport,
 }
 
 impl<'a> ClientBuilder<'a> {
@@ -65,7 +73,11 @@ impl<'a> ClientBuilder<'a> {
     pub fn new() -> Self {
         Self {
             base_url: OLLAMA_API_BASE_URL,
+            api_key: None,
+            default_headers: HeaderMap::new(),
             http_client: None,
+            timeouts: Timeouts::default(),
+            transport: Transport::default(),
         }
     }
 
@@ -74,30 +86,86 @@ impl<'a> ClientBuilder<'a> {
         self
     }
 
+    /// Sent as a bearer token, for Ollama behind an authenticating reverse proxy.
+    pub fn api_key(mut self, api_key: &'a str) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+
+    /// Headers added to every request, e.g. the auth header of a gateway.
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.default_headers.extend(headers);
+        self
+    }
+
     pub fn custom_client(mut self, client: reqwest::Client) -> Self {
         self.http_client = Some(client);
         self
     }
 
+    /// Timeout of a whole request. Streaming requests time out when the response or the next
+    /// chunk takes longer than this.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.request = Some(timeout);
+        self
+    }
+
+    /// Send all requests through a proxy, ignored with a [Self::custom_client].
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.transport.proxy = Some(proxy);
+        self
+    }
+
+    /// Trust an additional root certificate, ignored with a [Self::custom_client].
+    pub fn root_certificate(mut self, certificate: reqwest::Certificate) -> Self {
+        self.transport.root_certificates.push(certificate);
+        self
+    }
+
+    /// Timeout of establishing a connection, ignored with a [Self::custom_client].
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.connect = Some(timeout);
+        self
+    }
+
     pub fn build(self) -> Result<Client, ClientBuilderError> {
         let http_client = if let Some(http_client) = self.http_client {
             http_client
         } else {
-            reqwest::Client::builder().build()?
+            self.transport
+                .apply(self.timeouts.client_builder())
+                .build()?
         };
 
         Ok(Client {
             base_url: Url::parse(self.base_url)
                 .map_err(|_| ClientBuilderError::InvalidProperty("base_url"))?,
+            api_key: self.api_key.map(str::to_string),
+            default_headers: self.default_headers,
             http_client,
+            timeouts: self.timeouts,
         })
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Client {
     base_url: Url,
+    api_key: Option<String>,
+    default_headers: HeaderMap,
     http_client: reqwest::Client,
+    pub(crate) timeouts: Timeouts,
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("base_url", &self.base_url)
+            .field("http_client", &self.http_client)
+            .field("api_key", &self.api_key.as_ref().map(|_| "<REDACTED>"))
+            .field("timeouts", &self.timeouts)
+            .finish()
+    }
 }
 
 impl Default for Client {
@@ -131,21 +199,48 @@ impl Client {
 
     pub(crate) fn post(&self, path: &str) -> Result<reqwest::RequestBuilder, url::ParseError> {
         let url = self.base_url.join(path)?;
-        Ok(self.http_client.post(url))
+        Ok(self.authorize(self.http_client.post(url)))
     }
 
     pub(crate) fn get(&self, path: &str) -> Result<reqwest::RequestBuilder, url::ParseError> {
         let url = self.base_url.join(path)?;
-        Ok(self.http_client.get(url))
+        Ok(self.authorize(self.http_client.get(url)))
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let request = request.headers(self.default_headers.clone());
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
     }
 }
 
+/// Send a request, through [rig::http_record] when the `http-record` feature is enabled so
+/// tests can run against recorded responses.
+pub(crate) async fn send(request: reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+    #[cfg(feature = "http-record")]
+    return rig::http_record::send(request).await;
+    #[cfg(not(feature = "http-record"))]
+    request.send().await
+}
+
 impl ProviderClient for Client {
-    fn from_config(config: rig::client::AgentConfig) -> Box<dyn ProviderClient>
+    fn from_config(
+        config: rig::client::AgentConfig,
+    ) -> Result<Box<dyn ProviderClient>, ClientBuilderError>
     where
         Self: Sized,
     {
-        Box::new(Self::builder().base_url(&config.base_url).build().unwrap())
+        let mut builder = Self::builder()
+            .base_url(&config.base_url)
+            .default_headers(config.header_map()?);
+        if let Some(api_key) = config.api_key.as_deref().filter(|key| !key.is_empty()) {
+            builder = builder.api_key(api_key);
+        }
+        builder.timeouts = config.timeouts();
+        builder.transport = config.transport()?;
+        Ok(Box::new(builder.build()?))
     }
 }
 
@@ -173,11 +268,8 @@ impl EmbeddingsClient for Client {
 impl VerifyClient for Client {
     #[cfg_attr(feature = "worker", worker::send)]
     async fn verify(&self) -> Result<(), VerifyError> {
-        let response = self
-            .get("api/tags")
-            .expect("Failed to build request")
-            .send()
-            .await?;
+        let request = self.get("api/tags").expect("Failed to build request");
+        let response = send(self.timeouts.apply(request)).await?;
         match response.status() {
             reqwest::StatusCode::OK => Ok(()),
             _ => {
@@ -187,3 +279,25 @@ impl VerifyClient for Client {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{AUTHORIZATION, HeaderValue};
+
+    #[test]
+    fn requests_carry_auth_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-gateway", HeaderValue::from_static("benben"));
+        let client = Client::builder()
+            .api_key("secret")
+            .default_headers(headers)
+            .build()
+            .unwrap();
+
+        let request = client.post("api/chat").unwrap().build().unwrap();
+        assert_eq!(request.headers()[AUTHORIZATION], "Bearer secret");
+        assert_eq!(request.headers()["x-gateway"], "benben");
+        assert!(!format!("{client:?}").contains("secret"));
+    }
+}
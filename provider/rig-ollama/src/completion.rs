@@ -3,11 +3,12 @@
 use futures::StreamExt as _;
 use serde_json::{Value, json};
 use tracing::info_span;
+use rig::telemetry::redact::redact;
 
 use rig::{completion::{self, CompletionError, CompletionRequest}, json_utils, streaming::StreamingCompletionResponse};
 
 use crate::{
-    client::Client,
+    client::{Client, send},
     convert::{
         message::{OlMessage, RigMessage},
         rsp_req::{OllamaCompletionResponse, create_completion_request},
@@ -64,13 +65,19 @@ impl OllamaCompletionModel {
         );
 
         // Convert internal prompt into a provider Message
+        let options = json_utils::merge(
+            json!({ "temperature": completion_request.temperature }),
+            json!(completion_request.sampling),
+        );
+        let options = if completion_request.stop.is_empty() {
+            options
+        } else {
+            json_utils::merge(options, json!({ "stop": completion_request.stop }))
+        };
         let options = if let Some(extra) = completion_request.additional_params {
-            json_utils::merge(
-                json!({ "temperature": completion_request.temperature }),
-                extra,
-            )
+            json_utils::merge(options, extra)
         } else {
-            json!({ "temperature": completion_request.temperature })
+            options
         };
 
         let mut request_payload = json!({
@@ -89,7 +96,7 @@ impl OllamaCompletionModel {
             );
         }
 
-        tracing::debug!(target: "rig", "Chat mode payload: {}", request_payload);
+        tracing::debug!(target: "rig", "Chat mode payload: {}", redact(&request_payload.to_string()));
 
         Ok(request_payload)
     }
@@ -121,7 +128,7 @@ impl completion::CompletionModel for OllamaCompletionModel {
                 gen_ai.response.model = tracing::field::Empty,
                 gen_ai.usage.output_tokens = tracing::field::Empty,
                 gen_ai.usage.input_tokens = tracing::field::Empty,
-                gen_ai.input.messages = serde_json::to_string(&request.get("messages").unwrap()).unwrap(),
+                gen_ai.input.messages = redact(&serde_json::to_string(&request.get("messages").unwrap()).unwrap()),
                 gen_ai.output.messages = tracing::field::Empty,
             )
         } else {
@@ -129,22 +136,26 @@ impl completion::CompletionModel for OllamaCompletionModel {
         };
 
         let async_block = async move {
-            let response = self.client.post("api/chat")?.json(&request).send().await?;
+            let request = self
+                .client
+                .timeouts
+                .apply(self.client.post("api/chat")?.json(&request));
+            let response = send(request).await?;
 
             if !response.status().is_success() {
-                return Err(CompletionError::ProviderError(response.text().await?));
+                return Err(CompletionError::from_response(response).await);
             }
 
             let bytes = response.bytes().await?;
 
-            tracing::debug!(target: "rig", "Received response from Ollama: {}", String::from_utf8_lossy(&bytes));
+            tracing::debug!(target: "rig", "Received response from Ollama: {}", redact(&String::from_utf8_lossy(&bytes)));
 
             let response: OllamaCompletionResponse = serde_json::from_slice(&bytes)?;
             let span = tracing::Span::current();
             span.record("gen_ai.response.model_name", &response.model);
             span.record(
                 "gen_ai.output.messages",
-                serde_json::to_string(&vec![&response.message]).unwrap(),
+                redact(&serde_json::to_string(&vec![&response.message]).unwrap()),
             );
             span.record(
                 "gen_ai.usage.input_tokens",
@@ -157,6 +168,14 @@ impl completion::CompletionModel for OllamaCompletionModel {
 
             let response: completion::CompletionResponse<OllamaCompletionResponse> =
                 response.try_into()?;
+            if let Some(timings) = &response.timings {
+                tracing::debug!(
+                    target: "rig",
+                    "Ollama timings: {timings:?}, prompt {:?} tokens/s, output {:?} tokens/s",
+                    timings.prompt_tokens_per_second(&response.usage),
+                    timings.output_tokens_per_second(&response.usage),
+                );
+            }
 
             Ok(response)
         };
@@ -6,6 +6,7 @@ use rig::message::{
 };
 use rig::{OneOrMany, json_utils, message};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(tag = "role", rename_all = "lowercase")]
 pub enum OlMessage {
@@ -41,6 +42,9 @@ pub enum OlMessage {
         #[serde(rename = "tool_name")]
         name: String,
         content: String,
+        /// Id of the call this is the result of.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tool_call_id: Option<String>,
     },
 }
 pub struct RigMessage(pub Message);
@@ -75,9 +79,12 @@ impl TryFrom<RigMessage> for Vec<OlMessage> {
                                     .collect::<Vec<_>>()
                                     .join("\n");
 
+                                // the tool name is filled in from the matching call when the
+                                // whole history is converted, see `resolve_tool_names`
                                 Ok::<_, MessageError>(OlMessage::ToolResult {
-                                    name: id,
+                                    name: id.clone(),
                                     content: content_string,
+                                    tool_call_id: Some(id),
                                 })
                             }
                             _ => unreachable!(),
@@ -158,9 +165,9 @@ impl From<OlMessage> for Message {
             } => {
                 let mut assistant_contents =
                     vec![message::AssistantContent::Text(Text { text: content })];
-                for tc in tool_calls {
+                for (index, tc) in tool_calls.into_iter().enumerate() {
                     assistant_contents.push(message::AssistantContent::tool_call(
-                        tc.function.name.clone(),
+                        tc.call_id(index),
                         tc.function.name,
                         tc.function.arguments,
                     ));
@@ -174,9 +181,13 @@ impl From<OlMessage> for Message {
             OlMessage::System { content, .. } => Message::User {
                 content: OneOrMany::one(message::UserContent::Text(Text { text: content })),
             },
-            OlMessage::ToolResult { name, content } => Message::User {
+            OlMessage::ToolResult {
+                name,
+                content,
+                tool_call_id,
+            } => Message::User {
                 content: OneOrMany::one(message::UserContent::tool_result(
-                    name,
+                    tool_call_id.unwrap_or(name),
                     OneOrMany::one(message::ToolResultContent::text(content)),
                 )),
             },
@@ -184,6 +195,31 @@ impl From<OlMessage> for Message {
     }
 }
 
+/// Replace the call ids in the `tool_name` of tool results by the names of the tools called,
+/// looked up in the tool calls of the preceding assistant messages.
+pub(crate) fn resolve_tool_names(messages: &mut [OlMessage]) {
+    let mut names: HashMap<String, String> = HashMap::new();
+    for message in messages.iter_mut() {
+        match message {
+            OlMessage::Assistant { tool_calls, .. } => {
+                for (index, tool_call) in tool_calls.iter().enumerate() {
+                    names.insert(tool_call.call_id(index), tool_call.function.name.clone());
+                }
+            }
+            OlMessage::ToolResult {
+                name,
+                tool_call_id: Some(id),
+                ..
+            } => {
+                if let Some(tool_name) = names.get(id) {
+                    *name = tool_name.clone();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 impl OlMessage {
     /// Constructs a system message.
     pub fn system(content: &str) -> Self {
@@ -1,16 +1,19 @@
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
+use std::time::Duration;
 
 use rig::{
     OneOrMany,
-    completion::{self, CompletionError, CompletionRequest, Usage},
+    completion::{self, CompletionError, CompletionRequest, Timings, Usage},
     json_utils,
+    message::Reasoning,
+    telemetry::redact::redact,
 };
 
 use crate::convert::{
-        message::{OlMessage, RigMessage},
-        tool::OlToolDefinition,
-    };
+    message::{OlMessage, RigMessage, resolve_tool_names},
+    tool::OlToolDefinition,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OllamaCompletionResponse {
@@ -34,6 +37,32 @@ pub struct OllamaCompletionResponse {
     pub eval_duration: Option<u64>,
 }
 
+impl OllamaCompletionResponse {
+    pub fn timings(&self) -> Timings {
+        timings(
+            self.total_duration,
+            self.load_duration,
+            self.prompt_eval_duration,
+            self.eval_duration,
+        )
+    }
+}
+
+/// Timings from the durations Ollama reports in nanoseconds.
+pub(crate) fn timings(
+    total: Option<u64>,
+    load: Option<u64>,
+    prompt_eval: Option<u64>,
+    eval: Option<u64>,
+) -> Timings {
+    Timings {
+        load: load.map(Duration::from_nanos),
+        prompt_eval: prompt_eval.map(Duration::from_nanos),
+        eval: eval.map(Duration::from_nanos),
+        total: total.map(Duration::from_nanos),
+    }
+}
+
 impl TryFrom<OllamaCompletionResponse>
     for completion::CompletionResponse<OllamaCompletionResponse>
 {
@@ -48,15 +77,24 @@ impl TryFrom<OllamaCompletionResponse>
                 ..
             } => {
                 let mut assistant_contents = Vec::new();
+                // Thinking of reasoning models is kept apart from the answer.
+                if let Some(thinking) = thinking.as_deref().filter(|t| !t.is_empty()) {
+                    assistant_contents.push(completion::AssistantContent::Reasoning(
+                        Reasoning::new(thinking),
+                    ));
+                }
                 // Add the assistant's text content if any.
                 if !content.is_empty() {
                     assistant_contents.push(completion::AssistantContent::text(&content));
                 }
                 // Process tool_calls following Ollama's chat response definition.
-                // Each ToolCall has an id, a type, and a function field.
-                for tc in tool_calls.iter() {
+                // Calls without an id from Ollama get one, kept in the raw response as well.
+                let mut tool_calls = tool_calls;
+                for (index, tc) in tool_calls.iter_mut().enumerate() {
+                    let id = tc.call_id(index);
+                    tc.id = Some(id.clone());
                     assistant_contents.push(completion::AssistantContent::tool_call(
-                        tc.function.name.clone(),
+                        id,
                         tc.function.name.clone(),
                         tc.function.arguments.clone(),
                     ));
@@ -93,7 +131,11 @@ impl TryFrom<OllamaCompletionResponse>
                         input_tokens: prompt_tokens,
                         output_tokens: completion_tokens,
                         total_tokens: prompt_tokens + completion_tokens,
+                        cached_input_tokens: 0,
                     },
+                    logprobs: None,
+                    timings: Some(raw_response.timings()),
+                    served_by: None,
                     raw_response,
                 })
             }
@@ -134,15 +176,22 @@ pub(crate) fn create_completion_request(
             .flatten()
             .collect::<Vec<OlMessage>>(),
     );
+    resolve_tool_names(&mut full_history);
 
     // Convert internal prompt into a provider Message
+    let options = json_utils::merge(
+        json!({ "temperature": completion_request.temperature }),
+        json!(completion_request.sampling),
+    );
+    let options = if completion_request.stop.is_empty() {
+        options
+    } else {
+        json_utils::merge(options, json!({ "stop": completion_request.stop }))
+    };
     let options = if let Some(extra) = completion_request.additional_params {
-        json_utils::merge(
-            json!({ "temperature": completion_request.temperature }),
-            extra,
-        )
+        json_utils::merge(options, extra)
     } else {
-        json!({ "temperature": completion_request.temperature })
+        options
     };
 
     let mut request_payload = json!({
@@ -160,8 +209,154 @@ pub(crate) fn create_completion_request(
                 .collect::<Vec<OlToolDefinition>>()
         );
     }
+    // `think` is a top level field, not a model option
+    if let Some(think) = completion_request.think {
+        request_payload["think"] = json!(think);
+    }
 
-    tracing::debug!(target: "rig", "Chat mode payload: {}", request_payload);
+    tracing::debug!(target: "rig", "Chat mode payload: {}", redact(&request_payload.to_string()));
 
     Ok(request_payload)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::completion::{AssistantContent, Message, SamplingParams};
+    use rig::message::{ToolResultContent, UserContent};
+
+    #[test]
+    fn same_tool_called_twice_keeps_distinct_ids() {
+        let response: OllamaCompletionResponse = serde_json::from_value(json!({
+            "model": "qwen3",
+            "created_at": "2025-01-01T00:00:00Z",
+            "done": true,
+            "message": {
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [
+                    { "function": { "name": "add", "arguments": { "x": 1 } } },
+                    { "function": { "name": "add", "arguments": { "x": 2 } } },
+                    { "id": "call_9", "function": { "name": "sub", "arguments": {} } }
+                ]
+            }
+        }))
+        .unwrap();
+        let response = completion::CompletionResponse::try_from(response).unwrap();
+        let ids: Vec<String> = response
+            .choice
+            .iter()
+            .filter_map(|content| match content {
+                AssistantContent::ToolCall(call) => Some(call.id.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ids, vec!["add_0", "add_1", "call_9"]);
+
+        let results = ids.iter().map(|id| {
+            UserContent::tool_result(id, OneOrMany::one(ToolResultContent::text(id.clone())))
+        });
+        let request = CompletionRequest {
+            preamble: None,
+            chat_history: OneOrMany::many(vec![
+                Message::user("1 + 2"),
+                Message::Assistant {
+                    id: None,
+                    content: response.choice,
+                },
+                Message::User {
+                    content: OneOrMany::many(results).unwrap(),
+                },
+            ])
+            .unwrap(),
+            documents: vec![],
+            tools: vec![],
+            temperature: None,
+            max_tokens: None,
+            sampling: Default::default(),
+            think: None,
+            stop: vec![],
+            tool_choice: None,
+            additional_params: None,
+        };
+        let payload = create_completion_request("qwen3".to_string(), request).unwrap();
+        let tools: Vec<(&str, &str)> = payload["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|m| m["role"] == "tool")
+            .map(|m| {
+                (
+                    m["tool_name"].as_str().unwrap(),
+                    m["tool_call_id"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            tools,
+            vec![("add", "add_0"), ("add", "add_1"), ("sub", "call_9")]
+        );
+    }
+
+    #[test]
+    fn server_timings_are_converted() {
+        let response: OllamaCompletionResponse = serde_json::from_value(json!({
+            "model": "qwen3",
+            "created_at": "2025-01-01T00:00:00Z",
+            "done": true,
+            "message": { "role": "assistant", "content": "hi" },
+            "total_duration": 2_500_000_000u64,
+            "load_duration": 1_000_000_000u64,
+            "prompt_eval_count": 20,
+            "prompt_eval_duration": 500_000_000u64,
+            "eval_count": 10,
+            "eval_duration": 1_000_000_000u64
+        }))
+        .unwrap();
+        let response = completion::CompletionResponse::try_from(response).unwrap();
+        let timings = response.timings.unwrap();
+        assert_eq!(timings.load, Some(Duration::from_secs(1)));
+        assert_eq!(timings.total, Some(Duration::from_millis(2500)));
+        assert_eq!(
+            timings.prompt_tokens_per_second(&response.usage),
+            Some(40.0)
+        );
+        assert_eq!(
+            timings.output_tokens_per_second(&response.usage),
+            Some(10.0)
+        );
+    }
+
+    #[test]
+    fn sampling_params_and_stop_go_to_options() {
+        let request = CompletionRequest {
+            preamble: None,
+            chat_history: OneOrMany::one(Message::user("hi")),
+            documents: vec![],
+            tools: vec![],
+            temperature: Some(0.3),
+            max_tokens: None,
+            sampling: SamplingParams {
+                top_p: Some(0.9),
+                seed: Some(7),
+                ..Default::default()
+            },
+            think: Some(false),
+            stop: vec!["</answer>".to_string()],
+            tool_choice: None,
+            additional_params: Some(json!({ "num_ctx": 4096 })),
+        };
+        let payload = create_completion_request("qwen3".to_string(), request).unwrap();
+        assert_eq!(
+            payload["options"],
+            json!({
+                "temperature": 0.3,
+                "top_p": 0.9,
+                "seed": 7,
+                "stop": ["</answer>"],
+                "num_ctx": 4096
+            })
+        );
+        assert_eq!(payload["think"], json!(false));
+    }
+}
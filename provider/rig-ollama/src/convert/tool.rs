@@ -27,6 +27,9 @@ impl From<Tool> for OlToolDefinition {
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct OlToolCall {
+    /// Id of the call, sent by newer Ollama versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     #[serde(default, rename = "type")]
     pub r#type: OlToolType,
     pub function: Function,
@@ -43,11 +46,23 @@ pub struct Function {
     pub arguments: Value,
 }
 
+impl OlToolCall {
+    /// Id of the `index`th call of a message: the id sent by Ollama, or one generated from the
+    /// function name and the index, so that calls of the same tool in one turn stay distinct.
+    /// Generated ids are deterministic, so recorded requests stay the same between runs.
+    pub fn call_id(&self, index: usize) -> String {
+        self.id
+            .clone()
+            .unwrap_or_else(|| format!("{}_{}", self.function.name, index))
+    }
+}
+
 // ---------- Additional Message Types ----------
 
 impl From<ToolCall> for OlToolCall {
     fn from(tool_call: ToolCall) -> Self {
         Self {
+            id: Some(tool_call.id),
             r#type: OlToolType::Function,
             function: Function {
                 name: tool_call.function.name,
@@ -1,10 +1,11 @@
+use rig::embeddings::batch::{DEFAULT_MAX_BATCH_BYTES, split_batches};
 use rig::embeddings::{Embedding, EmbeddingError, EmbeddingModel};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::convert::{ApiErrorResponse, ApiResponse};
 
-use super::client::Client;
+use super::client::{Client, send};
 
 #[derive(Clone)]
 pub struct OlEmbeddingModel {
@@ -28,17 +29,35 @@ impl EmbeddingModel for OlEmbeddingModel {
     fn ndims(&self) -> usize {
         self.ndims
     }
+    /// Texts over [Self::MAX_DOCUMENTS] or [DEFAULT_MAX_BATCH_BYTES] are sent in several
+    /// requests, one after the other. Use [rig::embeddings::BatchEmbedder] to send them
+    /// concurrently.
     #[cfg_attr(feature = "worker", worker::send)]
     async fn embed_texts(
         &self,
         documents: impl IntoIterator<Item = String>,
     ) -> Result<Vec<Embedding>, EmbeddingError> {
         let docs: Vec<String> = documents.into_iter().collect();
+        let mut embeddings = Vec::with_capacity(docs.len());
+        for range in split_batches(&docs, Self::MAX_DOCUMENTS, DEFAULT_MAX_BATCH_BYTES) {
+            embeddings.extend(self.embed_batch(docs[range].to_vec()).await?);
+        }
+        Ok(embeddings)
+    }
+}
+
+impl OlEmbeddingModel {
+    /// Embed texts in a single request
+    async fn embed_batch(&self, docs: Vec<String>) -> Result<Vec<Embedding>, EmbeddingError> {
         let payload = json!({
             "model": self.model,
             "input": docs,
         });
-        let response = self.client.post("api/embed")?.json(&payload).send().await?;
+        let request = self
+            .client
+            .timeouts
+            .apply(self.client.post("api/embed")?.json(&payload));
+        let response = send(request).await?;
 
         if !response.status().is_success() {
             return Err(EmbeddingError::ProviderError(response.text().await?));
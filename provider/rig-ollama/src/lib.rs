@@ -3,6 +3,7 @@ pub mod client;
 pub mod completion;
 pub mod embedding;
 pub mod model;
+pub mod pull;
 pub mod streaming;
 
 
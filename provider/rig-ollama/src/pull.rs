@@ -0,0 +1,124 @@
+//! Model management: list the models installed on an Ollama server and pull missing ones.
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::client::{Client, send};
+
+#[derive(Debug, Error)]
+pub enum PullError {
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("url error: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("ollama error: {0}")]
+    Ollama(String),
+}
+
+/// A progress line reported by `/api/pull`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullProgress {
+    pub status: String,
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub completed: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    models: Vec<TagModel>,
+}
+
+#[derive(Deserialize)]
+struct TagModel {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PullLine {
+    Error { error: String },
+    Progress(PullProgress),
+}
+
+/// Model names without a tag refer to the `latest` tag.
+pub fn normalize_model_name(model: &str) -> String {
+    if model.contains(':') {
+        model.to_string()
+    } else {
+        format!("{model}:latest")
+    }
+}
+
+impl Client {
+    /// Names of the models installed on the server, as reported by `/api/tags`.
+    pub async fn list_models(&self) -> Result<Vec<String>, PullError> {
+        let response = send(self.get("api/tags")?).await?.error_for_status()?;
+        let tags: TagsResponse = response.json().await?;
+        Ok(tags.models.into_iter().map(|model| model.name).collect())
+    }
+
+    /// The models of `models` that are not installed on the server.
+    pub async fn missing_models(&self, models: &[String]) -> Result<Vec<String>, PullError> {
+        let installed: Vec<String> = self
+            .list_models()
+            .await?
+            .iter()
+            .map(|name| normalize_model_name(name))
+            .collect();
+        let mut missing: Vec<String> = Vec::new();
+        for model in models {
+            if !installed.contains(&normalize_model_name(model)) && !missing.contains(model) {
+                missing.push(model.clone());
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Pull a model, calling `on_progress` for every progress line reported by the server.
+    pub async fn pull_model<F>(&self, model: &str, mut on_progress: F) -> Result<(), PullError>
+    where
+        F: FnMut(&PullProgress),
+    {
+        let response = self
+            .post("api/pull")?
+            .json(&serde_json::json!({ "model": model, "stream": true }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // progress is streamed as NDJSON, a line may be split across chunks
+        let mut stream = response.bytes_stream();
+        let mut buffer = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            buffer.extend_from_slice(&chunk?);
+            while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                handle_pull_line(&line, &mut on_progress)?;
+            }
+        }
+        handle_pull_line(&buffer, &mut on_progress)
+    }
+}
+
+fn handle_pull_line<F>(line: &[u8], on_progress: &mut F) -> Result<(), PullError>
+where
+    F: FnMut(&PullProgress),
+{
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(());
+    }
+    match serde_json::from_slice(line)? {
+        PullLine::Error { error } => Err(PullError::Ollama(error)),
+        PullLine::Progress(progress) => {
+            on_progress(&progress);
+            Ok(())
+        }
+    }
+}
@@ -1,9 +1,9 @@
 use async_stream::try_stream;
-use futures::StreamExt as _;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tracing::info_span;
 use tracing_futures::Instrument;
+use rig::telemetry::redact::redact;
 
 use rig::{
     completion::{CompletionError, CompletionRequest, GetTokenUsage},
@@ -12,8 +12,12 @@ use rig::{
 };
 
 use crate::{
+    client::send,
     completion::OllamaCompletionModel,
-    convert::{message::OlMessage, rsp_req::OllamaCompletionResponse},
+    convert::{
+        message::OlMessage,
+        rsp_req::{OllamaCompletionResponse, timings},
+    },
 };
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -38,6 +42,15 @@ impl GetTokenUsage for OllamaStreamingCompletionResponse {
 
         Some(usage)
     }
+
+    fn timings(&self) -> Option<rig::completion::Timings> {
+        Some(timings(
+            self.total_duration,
+            self.load_duration,
+            self.prompt_eval_duration,
+            self.eval_duration,
+        ))
+    }
 }
 
 impl OllamaCompletionModel {
@@ -62,17 +75,20 @@ impl OllamaCompletionModel {
                 gen_ai.response.model = self.model,
                 gen_ai.usage.output_tokens = tracing::field::Empty,
                 gen_ai.usage.input_tokens = tracing::field::Empty,
-                gen_ai.input.messages = serde_json::to_string(&request.get("messages").unwrap()).unwrap(),
+                gen_ai.input.messages = redact(&serde_json::to_string(&request.get("messages").unwrap()).unwrap()),
                 gen_ai.output.messages = tracing::field::Empty,
             )
         } else {
             tracing::Span::current()
         };
 
-        let response = self.client.post("api/chat")?.json(&request).send().await?;
+        let timeouts = self.client.timeouts;
+        let response = timeouts
+            .first_byte(send(self.client.post("api/chat")?.json(&request)))
+            .await?;
 
         if !response.status().is_success() {
-            return Err(CompletionError::ProviderError(response.text().await?));
+            return Err(CompletionError::from_response(response).await);
         }
 
         let stream = Box::pin(try_stream! {
@@ -80,8 +96,9 @@ impl OllamaCompletionModel {
             let mut byte_stream = response.bytes_stream();
             let mut tool_calls_final = Vec::new();
             let mut text_response = String::new();
+            let mut thinking_response = String::new();
 
-            while let Some(chunk) = byte_stream.next().await {
+            while let Some(chunk) = timeouts.next_chunk(&mut byte_stream).await? {
                 let bytes = chunk?;
 
                 for line in bytes.split(|&b| b == b'\n') {
@@ -89,7 +106,7 @@ impl OllamaCompletionModel {
                         continue;
                     }
 
-                    tracing::debug!(target: "rig", "Received NDJSON line from Ollama: {}", String::from_utf8_lossy(line));
+                    tracing::debug!(target: "rig", "Received NDJSON line from Ollama: {}", redact(&String::from_utf8_lossy(line)));
 
                     let response: OllamaCompletionResponse = serde_json::from_slice(line)?;
 
@@ -98,12 +115,12 @@ impl OllamaCompletionModel {
                         span.record("gen_ai.usage.output_tokens", response.eval_count);
                         let message = OlMessage::Assistant {
                             content: text_response.clone(),
-                            thinking: None,
+                            thinking: (!thinking_response.is_empty()).then(|| thinking_response.clone()),
                             images: None,
                             name: None,
                             tool_calls: tool_calls_final.clone()
                         };
-                        span.record("gen_ai.output.messages", serde_json::to_string(&vec![message]).unwrap());
+                        span.record("gen_ai.output.messages", redact(&serde_json::to_string(&vec![message]).unwrap()));
                         yield RawStreamingChoice::FinalResponse(
                             OllamaStreamingCompletionResponse {
                                 total_duration: response.total_duration,
@@ -118,15 +135,22 @@ impl OllamaCompletionModel {
                         break;
                     }
 
-                    if let OlMessage::Assistant { content, tool_calls, .. } = response.message {
+                    if let OlMessage::Assistant { content, thinking, tool_calls, .. } = response.message {
+                        if let Some(thinking) = thinking.filter(|t| !t.is_empty()) {
+                            thinking_response += &thinking;
+                            yield RawStreamingChoice::Reasoning { id: None, reasoning: thinking };
+                        }
                         if !content.is_empty() {
                             text_response += &content;
                             yield RawStreamingChoice::Message(content);
                         }
-                        for tool_call in tool_calls {
+                        for mut tool_call in tool_calls {
+                            // index over the whole stream, calls may arrive in separate chunks
+                            let id = tool_call.call_id(tool_calls_final.len());
+                            tool_call.id = Some(id.clone());
                             tool_calls_final.push(tool_call.clone());
                             yield RawStreamingChoice::ToolCall {
-                                id: String::new(),
+                                id,
                                 name: tool_call.function.name,
                                 arguments: tool_call.function.arguments,
                                 call_id: None,
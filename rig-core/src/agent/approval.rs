@@ -0,0 +1,93 @@
+//! Confirming tool calls before they run.
+//!
+//! An agent or a single prompt request can carry a [ToolApprovalFn]. It is awaited before every
+//! native or MCP tool call with the tool name and arguments, so an embedder can show a
+//! confirmation dialog for file writes or shell commands. A denied call is not executed, the
+//! model gets the reason as the tool result instead.
+
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use serde_json::Value;
+
+/// The decision on a tool call.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolApproval {
+    /// Run the tool with the arguments given by the model.
+    Approve,
+    /// Do not run the tool, the reason is returned to the model.
+    Deny(String),
+    /// Run the tool with these arguments instead.
+    Edit(Value),
+}
+
+/// Callback deciding on a tool call, given the tool name and arguments.
+pub type ToolApprovalFn =
+    Arc<dyn Fn(String, Value) -> BoxFuture<'static, ToolApproval> + Send + Sync>;
+
+/// Ask the callback about a tool call. Returns the arguments to call the tool with, or the
+/// message returned to the model when the call is denied.
+pub(crate) async fn approve(
+    approval: Option<&ToolApprovalFn>,
+    tool_name: &str,
+    args: &Value,
+) -> Result<Value, String> {
+    let Some(approval) = approval else {
+        return Ok(args.clone());
+    };
+    match approval(tool_name.to_string(), args.clone()).await {
+        ToolApproval::Approve => Ok(args.clone()),
+        ToolApproval::Edit(args) => Ok(args),
+        ToolApproval::Deny(reason) => {
+            tracing::info!(target: "rig", "tool call {tool_name} denied: {reason}");
+            Err(format!("Tool call denied: {reason}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::CompletionClient;
+    use crate::client::mock::{self, MockClient};
+    use crate::completion::{Message, Prompt};
+    use crate::message::{ToolResultContent, UserContent};
+    use futures::FutureExt;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn denied_tool_call_is_not_executed() {
+        let script = mock::script("approval-test");
+        script.push_tool_call("write_file", json!({"path": "/etc/passwd"}));
+        script.push_text("ok");
+
+        let asked = Arc::new(Mutex::new(vec![]));
+        let seen = asked.clone();
+        let approval: ToolApprovalFn = Arc::new(move |name, args| {
+            seen.lock().unwrap().push((name, args));
+            async { ToolApproval::Deny("not allowed".into()) }.boxed()
+        });
+        let agent = MockClient::new()
+            .agent("approval-test")
+            .tool_approval(approval)
+            .build();
+
+        assert_eq!(agent.prompt("write it").multi_turn(1).await.unwrap(), "ok");
+        assert_eq!(
+            *asked.lock().unwrap(),
+            vec![("write_file".to_string(), json!({"path": "/etc/passwd"}))]
+        );
+        let requests = script.requests();
+        let Some(Message::User { content }) = requests[1].chat_history.iter().last() else {
+            panic!("expected the tool result");
+        };
+        let UserContent::ToolResult(result) = content.first() else {
+            panic!("expected the tool result");
+        };
+        assert_eq!(
+            result.content.first(),
+            ToolResultContent::text("Tool call denied: not allowed")
+        );
+    }
+}
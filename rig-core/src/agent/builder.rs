@@ -1,14 +1,17 @@
 use std::{collections::HashMap, sync::Arc};
 
-use rmcp::{RoleClient, model::InitializeRequestParam, service::RunningService};
 use tokio::time::error::Elapsed;
 
 use crate::{
-    completion::{CompletionModel, Document},
+    completion::{CompletionModel, Document, SamplingParams},
     message::ToolChoice,
+    telemetry::redact::Redactor,
+    tool::{Tool, ToolDyn, ToolSet},
 };
 
-use super::Agent;
+use super::examples::{ExampleSource, FewShot};
+use super::rerank::{ContextRerank, Reranker};
+use super::{Agent, McpClient, McpClientSlot, ToolApprovalFn};
 
 /// A builder for creating an agent
 ///
@@ -55,7 +58,40 @@ where
     /// Temperature of the model
     temperature: Option<f64>,
 
-    mcp_client: Option<RunningService<RoleClient, InitializeRequestParam>>,
+    /// Sampling parameters besides the temperature
+    sampling: SamplingParams,
+
+    /// Thinking of reasoning models
+    think: Option<bool>,
+
+    /// Stop sequences
+    stop: Vec<String>,
+
+    mcp_client: Option<McpClientSlot>,
+
+    /// MCP resources added as context documents
+    mcp_resources: Vec<String>,
+
+    /// Native tools
+    tools: ToolSet,
+
+    /// Redactor of recorded span fields
+    redactor: Option<Arc<Redactor>>,
+
+    /// Confirmation of tool calls
+    tool_approval: Option<ToolApprovalFn>,
+
+    /// Default depth of multi-turn prompts
+    max_turns: Option<usize>,
+
+    /// Identical tool calls in a row aborting a prompt
+    tool_loop_limit: Option<usize>,
+
+    /// Reranking of the context documents
+    rerank: Option<ContextRerank>,
+
+    /// Few-shot examples appended to the preamble
+    few_shot: Option<FewShot>,
 }
 
 impl<M> AgentBuilder<M>
@@ -71,9 +107,20 @@ where
             static_context: vec![],
             static_tools: vec![],
             temperature: None,
+            sampling: SamplingParams::default(),
+            think: None,
+            stop: vec![],
             max_tokens: None,
             additional_params: None,
             mcp_client: None,
+            mcp_resources: vec![],
+            tools: ToolSet::default(),
+            redactor: None,
+            tool_approval: None,
+            max_turns: None,
+            tool_loop_limit: None,
+            rerank: None,
+            few_shot: None,
         }
     }
 
@@ -122,8 +169,50 @@ where
     }
 
     /// Set the temperature of the model
-    pub fn temperature(mut self, _temperature: f64) -> Self {
-        self.temperature = Some(0 as f64);
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Set the sampling parameters besides the temperature, replacing the ones set before
+    pub fn sampling(mut self, sampling: SamplingParams) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Set the nucleus sampling probability mass of the model
+    pub fn top_p(mut self, top_p: f64) -> Self {
+        self.sampling.top_p = Some(top_p);
+        self
+    }
+
+    /// Set the frequency penalty of the model
+    pub fn frequency_penalty(mut self, penalty: f64) -> Self {
+        self.sampling.frequency_penalty = Some(penalty);
+        self
+    }
+
+    /// Set the presence penalty of the model
+    pub fn presence_penalty(mut self, penalty: f64) -> Self {
+        self.sampling.presence_penalty = Some(penalty);
+        self
+    }
+
+    /// Set the sampling seed of the model
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.sampling.seed = Some(seed);
+        self
+    }
+
+    /// Enable or disable thinking of reasoning models, e.g. to skip it for simple steps
+    pub fn think(mut self, think: bool) -> Self {
+        self.think = Some(think);
+        self
+    }
+
+    /// Add a sequence where the model stops generating
+    pub fn stop(mut self, stop: impl Into<String>) -> Self {
+        self.stop.push(stop.into());
         self
     }
 
@@ -140,22 +229,85 @@ where
     }
 
     /// Set Mcp Client
-    pub fn mcp_client(
-        mut self,
-        client: RunningService<RoleClient, InitializeRequestParam>,
-    ) -> Self {
-        self.mcp_client = Some(client);
+    pub fn mcp_client(mut self, client: McpClient) -> Self {
+        self.mcp_client = Some(McpClientSlot::new(client));
+        self
+    }
+
+    /// Set a shared Mcp Client slot, so the client can be replaced after the agent is built
+    pub fn mcp_slot(mut self, slot: McpClientSlot) -> Self {
+        self.mcp_client = Some(slot);
+        self
+    }
+
+    /// Add a native tool to the agent
+    pub fn tool(self, tool: impl Tool + 'static) -> Self {
+        self.tool_dyn(Arc::new(tool))
+    }
+
+    /// Add a shared native tool to the agent
+    pub fn tool_dyn(mut self, tool: Arc<dyn ToolDyn>) -> Self {
+        self.static_tools.push(tool.name());
+        self.tools.add_dyn(tool);
+        self
+    }
+
+    /// Read an MCP resource before every completion and add its text contents as context
+    /// documents
+    pub fn mcp_resource(mut self, uri: impl Into<String>) -> Self {
+        self.mcp_resources.push(uri.into());
+        self
+    }
+
+    /// Score the context documents against the prompt with the reranker and only send the
+    /// `top_n` most relevant ones
+    pub fn rerank(mut self, reranker: impl Reranker + 'static, top_n: usize) -> Self {
+        self.rerank = Some(ContextRerank {
+            reranker: Arc::new(reranker),
+            top_n,
+        });
+        self
+    }
+
+    /// Look up the `k` examples most relevant to the prompt before every completion and
+    /// append them to the preamble
+    pub fn few_shot(mut self, source: impl ExampleSource + 'static, k: usize) -> Self {
+        self.few_shot = Some(FewShot {
+            source: Arc::new(source),
+            k,
+        });
+        self
+    }
+
+    /// Redact the span fields recorded for this agent with its own redactor instead of the
+    /// global one
+    pub fn redactor(mut self, redactor: Arc<Redactor>) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Ask the callback before running any tool, it can approve, deny or edit the call.
+    /// A prompt request can override it with its own callback.
+    pub fn tool_approval(mut self, approval: ToolApprovalFn) -> Self {
+        self.tool_approval = Some(approval);
+        self
+    }
+
+    /// Default maximum depth of multi-turn prompts, `.multi_turn()` on a request overrides it
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = Some(max_turns);
+        self
+    }
+
+    /// Abort a prompt with [`crate::completion::PromptError::ToolLoopError`] when the model
+    /// calls the same tool with identical arguments `limit` times in a row
+    pub fn tool_loop_limit(mut self, limit: usize) -> Self {
+        self.tool_loop_limit = Some(limit);
         self
     }
 
     /// Build the agent
     pub fn build(self) -> Agent<M> {
-        let mcp = if let Some(mcp_rc) = self.mcp_client {
-            Some(Arc::new(mcp_rc))
-        } else {
-            None
-        };
-
         Agent {
             name: self.name,
             description: self.description,
@@ -164,9 +316,43 @@ where
             static_context: self.static_context,
             static_tools: self.static_tools,
             temperature: self.temperature,
+            sampling: self.sampling,
+            think: self.think,
+            stop: self.stop,
             max_tokens: self.max_tokens,
             additional_params: self.additional_params,
-            mcp_client: mcp,
+            mcp_client: self.mcp_client,
+            mcp_resources: self.mcp_resources,
+            tools: self.tools,
+            redactor: self.redactor,
+            tool_approval: self.tool_approval,
+            max_turns: self.max_turns,
+            tool_loop_limit: self.tool_loop_limit,
+            rerank: self.rerank,
+            few_shot: self.few_shot,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::CompletionClient;
+    use crate::client::mock::{self, MockClient};
+    use crate::completion::Prompt;
+
+    #[tokio::test]
+    async fn sampling_parameters_reach_the_request() {
+        let script = mock::script("builder-temperature-test");
+        let agent = MockClient::new()
+            .agent("builder-temperature-test")
+            .temperature(0.7)
+            .top_p(0.9)
+            .build();
+
+        agent.prompt("hello").await.unwrap();
+        let requests = script.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].temperature, Some(0.7));
+        assert_eq!(requests[0].sampling.top_p, Some(0.9));
+    }
+}
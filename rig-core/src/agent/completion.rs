@@ -1,17 +1,24 @@
+use super::examples::FewShot;
+use super::mcp::{prompt_message, resource_documents, tool_result_content, tool_result_to_string};
 use super::prompt_request::{self, PromptRequest};
+use super::rerank::ContextRerank;
+use super::{McpClient, McpClientSlot, ToolApprovalFn};
 use crate::{
+    OneOrMany,
     agent::prompt_request::streaming::StreamingPromptRequest,
     completion::{
         Chat, Completion, CompletionError, CompletionModel, CompletionRequestBuilder, Document,
-        GetTokenUsage, Message, Prompt, PromptError,
+        GetTokenUsage, Message, Prompt, PromptError, SamplingParams,
     },
+    message::ToolResultContent,
     streaming::{StreamingChat, StreamingCompletion, StreamingPrompt},
+    telemetry::redact::{self, Redactor},
+    tool::ToolSet,
 };
 use futures::{StreamExt, TryStreamExt, stream};
-use rmcp::{
-    RoleClient,
-    model::{CallToolRequestParam, InitializeRequestParam},
-    service::RunningService,
+use rmcp::model::{
+    CallToolRequestParam, GetPromptRequestParam, Prompt as McpPrompt, ReadResourceRequestParam,
+    ResourceContents,
 };
 use serde_json::Value;
 use std::{borrow::Cow, sync::Arc};
@@ -58,12 +65,34 @@ where
     pub static_tools: Vec<String>,
     /// Temperature of the model
     pub temperature: Option<f64>,
+    /// Sampling parameters besides the temperature
+    pub sampling: SamplingParams,
+    /// Whether reasoning models think before answering, `None` keeps the model default
+    pub think: Option<bool>,
+    /// Sequences where the model stops generating
+    pub stop: Vec<String>,
     /// Maximum number of tokens for the completion
     pub max_tokens: Option<u64>,
     /// Additional parameters to be passed to the model
     pub additional_params: Option<serde_json::Value>,
     /// agent mcp server
-    pub mcp_client: Option<Arc<RunningService<RoleClient, InitializeRequestParam>>>,
+    pub mcp_client: Option<McpClientSlot>,
+    /// MCP resources read before every completion and added as context documents
+    pub mcp_resources: Vec<String>,
+    /// Native tools, offered to the model together with the MCP tools
+    pub tools: ToolSet,
+    /// Redactor applied to recorded span fields instead of the global one
+    pub redactor: Option<Arc<Redactor>>,
+    /// Callback confirming tool calls before they run
+    pub tool_approval: Option<ToolApprovalFn>,
+    /// Default maximum depth of multi-turn prompts, used when a request doesn't set one
+    pub max_turns: Option<usize>,
+    /// Number of identical tool calls in a row that aborts a prompt
+    pub tool_loop_limit: Option<usize>,
+    /// Reranking of the context documents against the prompt
+    pub rerank: Option<ContextRerank>,
+    /// Few-shot examples appended to the preamble
+    pub few_shot: Option<FewShot>,
 }
 
 impl<M> Agent<M>
@@ -75,45 +104,119 @@ where
         self.name.as_deref().unwrap_or(UNKNOWN_AGENT_NAME)
     }
 
+    /// Redact a text with the agent's redactor, or the one in effect.
+    pub(crate) fn redact(&self, text: &str) -> String {
+        match self.redactor.clone().or_else(redact::current) {
+            Some(redactor) => redactor.redact(text),
+            None => text.to_string(),
+        }
+    }
+
     pub async fn call(&self, func_name: &str, args: &Value) -> Result<String, CompletionError> {
-        if let Some(mcp_client) = self.mcp_client.clone() {
+        self.call_tool(func_name, args)
+            .await
+            .map(|content| tool_result_to_string(&content))
+    }
+
+    /// Call a tool on the agent's MCP server and return its result as rig content, keeping
+    /// images and resources returned by the server.
+    pub async fn call_tool(
+        &self,
+        func_name: &str,
+        args: &Value,
+    ) -> Result<OneOrMany<ToolResultContent>, CompletionError> {
+        // native tools take precedence over mcp tools with the same name
+        if let Some(result) = self.tools.call(func_name, args.clone()).await {
+            return result
+                .map(|output| OneOrMany::one(ToolResultContent::text(output)))
+                .map_err(|e| CompletionError::RequestError(Box::new(e)));
+        }
+
+        if let Some(mcp_client) = self.mcp_client.as_ref().map(McpClientSlot::get) {
+            #[cfg(any(test, feature = "chaos"))]
+            crate::chaos::delay_mcp_call().await;
+
             let obj = args.as_object();
             let req = CallToolRequestParam {
                 name: Cow::Owned(func_name.to_string()),
                 arguments: obj.cloned(),
             };
-            let result = mcp_client
-                .call_tool(req)
-                .await
-                .map_err(|e| CompletionError::MCPError(e.to_string()))?;
+            let result = mcp_client.call_tool(req).await.map_err(|e| {
+                #[cfg(feature = "otel-metrics")]
+                crate::telemetry::metrics::RigMetrics::global().record_mcp_failure(func_name);
+                CompletionError::MCPError(e.to_string())
+            })?;
 
-            // Extract the result content as a string
-            let result_str = result
+            let content = result
                 .content
                 .iter()
-                .map(|c| match &c.raw {
-                    rmcp::model::RawContent::Text(text) => text.text.clone(),
-                    rmcp::model::RawContent::Image(image) => {
-                        format!("[Image: {}]", image.mime_type)
-                    }
-                    rmcp::model::RawContent::Resource(resource) => match &resource.resource {
-                        rmcp::model::ResourceContents::TextResourceContents { text, .. } => {
-                            text.clone()
-                        }
-                        rmcp::model::ResourceContents::BlobResourceContents { .. } => {
-                            "[Binary Resource]".to_string()
-                        }
-                    },
-                    rmcp::model::RawContent::Audio(_) => "[Audio]".to_string(),
-                    rmcp::model::RawContent::ResourceLink(_) => "[Resource Link]".to_string(),
-                })
-                .collect::<Vec<_>>()
-                .join("\n");
-
-            return Ok(result_str);
+                .map(|c| tool_result_content(&c.raw))
+                .collect::<Vec<_>>();
+            if let Ok(content) = OneOrMany::many(content) {
+                return Ok(content);
+            }
         }
 
-        Ok("".to_string())
+        Ok(OneOrMany::one(ToolResultContent::text("")))
+    }
+
+    fn require_mcp_client(&self) -> Result<Arc<McpClient>, CompletionError> {
+        self.mcp_client
+            .as_ref()
+            .map(McpClientSlot::get)
+            .ok_or_else(|| CompletionError::MCPError("agent has no mcp client".to_string()))
+    }
+
+    /// List the prompts offered by the agent's MCP server. Agents without an MCP server have
+    /// no prompts.
+    pub async fn list_mcp_prompts(&self) -> Result<Vec<McpPrompt>, CompletionError> {
+        let Some(client) = self.mcp_client.as_ref().map(McpClientSlot::get) else {
+            return Ok(Vec::new());
+        };
+        client
+            .list_all_prompts()
+            .await
+            .map_err(|e| CompletionError::MCPError(e.to_string()))
+    }
+
+    /// Render a prompt of the agent's MCP server into messages, e.g. to use as chat history.
+    pub async fn render_mcp_prompt(
+        &self,
+        name: &str,
+        args: Option<serde_json::Map<String, Value>>,
+    ) -> Result<Vec<Message>, CompletionError> {
+        let result = self
+            .require_mcp_client()?
+            .get_prompt(GetPromptRequestParam {
+                name: name.to_string(),
+                arguments: args,
+            })
+            .await
+            .map_err(|e| CompletionError::MCPError(e.to_string()))?;
+        Ok(result.messages.iter().map(prompt_message).collect())
+    }
+
+    /// Read a resource of the agent's MCP server.
+    pub async fn read_mcp_resource(
+        &self,
+        uri: &str,
+    ) -> Result<Vec<ResourceContents>, CompletionError> {
+        let result = self
+            .require_mcp_client()?
+            .read_resource(ReadResourceRequestParam {
+                uri: uri.to_string(),
+            })
+            .await
+            .map_err(|e| CompletionError::MCPError(e.to_string()))?;
+        Ok(result.contents)
+    }
+
+    async fn mcp_resource_documents(&self) -> Result<Vec<Document>, CompletionError> {
+        let mut documents = Vec::new();
+        for uri in &self.mcp_resources {
+            documents.extend(resource_documents(self.read_mcp_resource(uri).await?));
+        }
+        Ok(documents)
     }
 }
 
@@ -127,6 +230,17 @@ where
         chat_history: Vec<Message>,
     ) -> Result<CompletionRequestBuilder<M>, CompletionError> {
         let prompt = prompt.into();
+        let rerank_query = self
+            .rerank
+            .as_ref()
+            .map(|_| prompt.rag_text().unwrap_or_default());
+        let preamble = match &self.few_shot {
+            Some(few_shot) => {
+                let query = prompt.rag_text().unwrap_or_default();
+                few_shot.preamble(self.preamble.as_deref(), &query).await?
+            }
+            None => self.preamble.clone(),
+        };
 
         // Find the latest message in the chat history that contains RAG text
         // let rag_text = prompt.rag_text();
@@ -142,19 +256,33 @@ where
             .completion_request(prompt)
             .messages(chat_history)
             .temperature_opt(self.temperature)
+            .sampling(self.sampling.clone())
+            .think_opt(self.think)
+            .stops(self.stop.clone())
             .max_tokens_opt(self.max_tokens)
-            .additional_params_opt(self.additional_params.clone())
-            .documents(self.static_context.clone());
-        let completion_request = if let Some(preamble) = &self.preamble {
-            completion_request.preamble(preamble.to_owned())
+            .additional_params_opt(self.additional_params.clone());
+        let mut documents = self.static_context.clone();
+        if !self.mcp_resources.is_empty() {
+            documents.extend(self.mcp_resource_documents().await?);
+        }
+        if let (Some(rerank), Some(query)) = (&self.rerank, rerank_query) {
+            documents = rerank.apply(&query, documents).await?;
+        }
+        let completion_request = completion_request.documents(documents);
+        let completion_request = if let Some(preamble) = preamble {
+            completion_request.preamble(preamble)
         } else {
             completion_request
         };
-        if let Some(client) = self.mcp_client.clone() {
+        let completion_request = completion_request.tools(self.tools.definitions());
+        if let Some(client) = self.mcp_client.as_ref().map(McpClientSlot::get) {
             let tools = client
                 .list_all_tools()
                 .await
-                .map_err(|_| CompletionError::MCPError("".to_string()))?;
+                .map_err(|_| CompletionError::MCPError("".to_string()))?
+                .into_iter()
+                .filter(|tool| !self.tools.contains(&tool.name))
+                .collect();
             return Ok(completion_request.tools(tools));
         }
         Ok(completion_request)
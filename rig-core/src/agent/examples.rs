@@ -0,0 +1,174 @@
+//! Few-shot examples appended to the preamble of an agent.
+//!
+//! With an [ExampleSource] set on the agent (see [crate::agent::AgentBuilder::few_shot]) the
+//! `k` examples most similar to the prompt are looked up before every completion and appended
+//! to the preamble as input/output pairs. The base preamble stays short while repeated kinds of
+//! tasks still get worked examples, which helps small local models the most.
+//!
+//! [EmbeddedExamples] keeps the examples in memory and ranks them by the cosine similarity of
+//! their embedded inputs. Applications keeping their examples in a database implement
+//! [ExampleSource] directly and can rank with [most_similar].
+
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::completion::CompletionError;
+use crate::embeddings::distance::VectorDistance;
+use crate::embeddings::{Embedding, EmbeddingModel};
+
+/// An input and the output expected for it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Example {
+    pub input: String,
+    pub output: String,
+}
+
+/// Looks up the examples relevant to a prompt.
+pub trait ExampleSource: Send + Sync {
+    /// Up to `k` examples for the query, most relevant first.
+    fn examples<'a>(
+        &'a self,
+        query: &'a str,
+        k: usize,
+    ) -> BoxFuture<'a, Result<Vec<Example>, CompletionError>>;
+}
+
+/// The example source of an agent and the number of examples it injects.
+#[derive(Clone)]
+pub struct FewShot {
+    pub source: Arc<dyn ExampleSource>,
+    pub k: usize,
+}
+
+impl FewShot {
+    /// The preamble with the examples relevant to the query appended.
+    pub async fn preamble(
+        &self,
+        preamble: Option<&str>,
+        query: &str,
+    ) -> Result<Option<String>, CompletionError> {
+        let examples = self.source.examples(query, self.k).await?;
+        Ok(with_examples(preamble, &examples))
+    }
+}
+
+/// Append the examples to the preamble, the preamble is unchanged when there are none.
+pub fn with_examples(preamble: Option<&str>, examples: &[Example]) -> Option<String> {
+    if examples.is_empty() {
+        return preamble.map(str::to_string);
+    }
+    let mut composed = preamble
+        .map(|p| format!("{}\n\n", p.trim_end()))
+        .unwrap_or_default();
+    composed.push_str("# Examples");
+    for (i, example) in examples.iter().enumerate() {
+        composed.push_str(&format!(
+            "\n\n## Example {}\n\nInput:\n{}\n\nOutput:\n{}",
+            i + 1,
+            example.input.trim(),
+            example.output.trim()
+        ));
+    }
+    Some(composed)
+}
+
+/// The `k` candidates whose embedding is most similar to the query, most similar first.
+pub fn most_similar<T>(
+    query: &Embedding,
+    candidates: impl IntoIterator<Item = (Embedding, T)>,
+    k: usize,
+) -> Vec<T> {
+    let mut scored: Vec<(f64, T)> = candidates
+        .into_iter()
+        .map(|(embedding, item)| (query.cosine_similarity(&embedding, false), item))
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().take(k).map(|(_, item)| item).collect()
+}
+
+/// Examples kept in memory, ranked by the embeddings of their inputs.
+pub struct EmbeddedExamples<E: EmbeddingModel> {
+    model: E,
+    examples: Vec<(Embedding, Example)>,
+}
+
+impl<E: EmbeddingModel> EmbeddedExamples<E> {
+    /// Embed the inputs of the examples with the model.
+    pub async fn new(model: E, examples: Vec<Example>) -> Result<Self, CompletionError> {
+        let embeddings = model
+            .embed_texts(examples.iter().map(|e| e.input.clone()))
+            .await
+            .map_err(|e| CompletionError::RequestError(e.into()))?;
+        Ok(Self {
+            model,
+            examples: embeddings.into_iter().zip(examples).collect(),
+        })
+    }
+}
+
+impl<E: EmbeddingModel> ExampleSource for EmbeddedExamples<E> {
+    fn examples<'a>(
+        &'a self,
+        query: &'a str,
+        k: usize,
+    ) -> BoxFuture<'a, Result<Vec<Example>, CompletionError>> {
+        Box::pin(async move {
+            if self.examples.is_empty() || k == 0 {
+                return Ok(vec![]);
+            }
+            let query = self
+                .model
+                .embed_text(query)
+                .await
+                .map_err(|e| CompletionError::RequestError(e.into()))?;
+            Ok(most_similar(&query, self.examples.iter().cloned(), k))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock::{self, MockClient};
+    use crate::client::{CompletionClient, EmbeddingsClient};
+    use crate::completion::Prompt;
+
+    fn example(input: &str, output: &str) -> Example {
+        Example {
+            input: input.to_string(),
+            output: output.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn similar_examples_are_appended_to_the_preamble() {
+        let examples = EmbeddedExamples::new(
+            MockClient::new().embedding_model("few-shot-embed"),
+            vec![
+                example("2 + 2", "4"),
+                example("capital of France", "Paris"),
+                example("3 * 3", "9"),
+            ],
+        )
+        .await
+        .unwrap();
+        let agent = MockClient::new()
+            .agent("few-shot-test")
+            .preamble("Answer briefly.")
+            .few_shot(examples, 1)
+            .build();
+        mock::script("few-shot-test").push_text("Rome");
+
+        agent.prompt("capital of France").await.unwrap();
+        let request = mock::script("few-shot-test").requests().remove(0);
+        assert_eq!(
+            request.preamble.as_deref(),
+            Some(
+                "Answer briefly.\n\n# Examples\n\n## Example 1\n\nInput:\ncapital of France\n\nOutput:\nParis"
+            )
+        );
+        assert_eq!(with_examples(None, &[]), None);
+    }
+}
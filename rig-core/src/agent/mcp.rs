@@ -0,0 +1,230 @@
+//! Swappable MCP client handle shared between agents and whoever supervises the MCP server, and
+//! conversion of MCP tool results, prompts and resources into rig types.
+
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use rmcp::{
+    ClientHandler, ErrorData, RoleClient,
+    model::{
+        ClientInfo, ListRootsResult, PromptMessage, PromptMessageContent, PromptMessageRole,
+        RawContent, ResourceContents, Root, RootsCapabilities,
+    },
+    service::{RequestContext, RunningService},
+};
+
+use crate::OneOrMany;
+use crate::completion::{Document, Message};
+use crate::message::{ImageMediaType, MimeType, ToolResultContent, UserContent};
+
+/// The MCP client type used by agents.
+pub type McpClient = RunningService<RoleClient, McpClientHandler>;
+
+/// Client side handler of an MCP connection.
+///
+/// Besides the client info sent on initialization it answers the server's `roots/list` request,
+/// telling filesystem servers which directories they may access.
+#[derive(Debug, Clone, Default)]
+pub struct McpClientHandler {
+    info: ClientInfo,
+    roots: Vec<Root>,
+}
+
+impl McpClientHandler {
+    pub fn new(info: ClientInfo) -> Self {
+        Self {
+            info,
+            roots: Vec::new(),
+        }
+    }
+
+    /// Set the roots exposed to the server. The roots capability is announced when any root is
+    /// set.
+    pub fn roots(mut self, roots: impl IntoIterator<Item = Root>) -> Self {
+        self.roots = roots.into_iter().collect();
+        if !self.roots.is_empty() && self.info.capabilities.roots.is_none() {
+            self.info.capabilities.roots = Some(RootsCapabilities::default());
+        }
+        self
+    }
+}
+
+impl ClientHandler for McpClientHandler {
+    async fn list_roots(
+        &self,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<ListRootsResult, ErrorData> {
+        Ok(ListRootsResult {
+            roots: self.roots.clone(),
+        })
+    }
+
+    fn get_info(&self) -> ClientInfo {
+        self.info.clone()
+    }
+}
+
+/// Build an MCP root for a local directory. The directory must exist.
+pub fn root_from_path(path: impl AsRef<Path>) -> std::io::Result<Root> {
+    let path = std::fs::canonicalize(path)?;
+    Ok(Root {
+        uri: format!("file://{}", path.display()),
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned()),
+    })
+}
+
+/// A shared slot holding the current MCP client of an agent.
+///
+/// Cloning the slot shares it: when a supervisor restarts a crashed MCP server it can
+/// [replace](McpClientSlot::replace) the client and every agent holding the slot picks up the new
+/// client on its next call.
+#[derive(Clone)]
+pub struct McpClientSlot(Arc<RwLock<Arc<McpClient>>>);
+
+impl McpClientSlot {
+    pub fn new(client: McpClient) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(client))))
+    }
+
+    /// The current client.
+    pub fn get(&self) -> Arc<McpClient> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replace the client, returning the previous one.
+    pub fn replace(&self, client: McpClient) -> Arc<McpClient> {
+        let mut current = self.0.write().unwrap_or_else(|e| e.into_inner());
+        std::mem::replace(&mut *current, Arc::new(client))
+    }
+}
+
+/// Convert one block of MCP tool call content into rig tool result content.
+///
+/// Images (and embedded blob resources with an image mime type) become
+/// [ToolResultContent::Image] with the base64 data, text and text resources become
+/// [ToolResultContent::Text]. Content rig has no variant for (audio, non-image blobs, resource
+/// links) is kept as its MCP json representation so no data is lost.
+pub fn tool_result_content(content: &RawContent) -> ToolResultContent {
+    match content {
+        RawContent::Text(text) => ToolResultContent::text(text.text.clone()),
+        RawContent::Image(image) => image_content(&image.data, &image.mime_type),
+        RawContent::Resource(resource) => match &resource.resource {
+            ResourceContents::TextResourceContents { text, .. } => {
+                ToolResultContent::text(text.clone())
+            }
+            ResourceContents::BlobResourceContents {
+                blob,
+                mime_type: Some(mime_type),
+                ..
+            } if mime_type.starts_with("image/") => image_content(blob, mime_type),
+            ResourceContents::BlobResourceContents { .. } => json_content(content),
+        },
+        RawContent::Audio(_) | RawContent::ResourceLink(_) => json_content(content),
+    }
+}
+
+/// Render tool result content as a single string, e.g. for hooks and logs. Text is kept as is,
+/// other content is rendered as json.
+pub fn tool_result_to_string(content: &OneOrMany<ToolResultContent>) -> String {
+    content
+        .iter()
+        .map(|content| match content {
+            ToolResultContent::Text(text) => text.text.clone(),
+            other => serde_json::to_string(other).unwrap_or_default(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Convert a message of a rendered MCP prompt into a rig message.
+///
+/// Images in user messages are kept as image content, other non-text content is rendered as
+/// text (resource text as is, everything else as json).
+pub fn prompt_message(message: &PromptMessage) -> Message {
+    let text = match (&message.role, &message.content) {
+        (PromptMessageRole::User, PromptMessageContent::Image { image }) => {
+            return Message::User {
+                content: OneOrMany::one(UserContent::image_base64(
+                    image.data.clone(),
+                    ImageMediaType::from_mime_type(&image.mime_type),
+                    None,
+                )),
+            };
+        }
+        (_, PromptMessageContent::Text { text }) => text.clone(),
+        (_, PromptMessageContent::Resource { resource }) => match &resource.resource {
+            ResourceContents::TextResourceContents { text, .. } => text.clone(),
+            ResourceContents::BlobResourceContents { .. } => {
+                serde_json::to_string(&message.content).unwrap_or_default()
+            }
+        },
+        (_, content) => serde_json::to_string(content).unwrap_or_default(),
+    };
+    match message.role {
+        PromptMessageRole::User => Message::user(text),
+        PromptMessageRole::Assistant => Message::assistant(text),
+    }
+}
+
+/// Convert the contents of an MCP resource into context documents. Binary contents are skipped.
+pub fn resource_documents(contents: Vec<ResourceContents>) -> Vec<Document> {
+    contents
+        .into_iter()
+        .filter_map(|content| match content {
+            ResourceContents::TextResourceContents {
+                uri,
+                mime_type,
+                text,
+                ..
+            } => Some(Document {
+                id: uri,
+                text,
+                additional_props: mime_type
+                    .map(|mime_type| [("mime_type".to_string(), mime_type)].into())
+                    .unwrap_or_default(),
+            }),
+            ResourceContents::BlobResourceContents { uri, .. } => {
+                tracing::warn!(target: "rig", "skipping binary mcp resource {uri} as context");
+                None
+            }
+        })
+        .collect()
+}
+
+fn image_content(data: &str, mime_type: &str) -> ToolResultContent {
+    ToolResultContent::image_base64(data, ImageMediaType::from_mime_type(mime_type), None)
+}
+
+fn json_content(content: &RawContent) -> ToolResultContent {
+    ToolResultContent::text(serde_json::to_string(content).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::DocumentSourceKind;
+
+    #[test]
+    fn image_content_keeps_data() {
+        let content = tool_result_content(&RawContent::image("aGVsbG8=", "image/png"));
+        let ToolResultContent::Image(image) = content else {
+            panic!("expected image content");
+        };
+        assert_eq!(image.media_type, Some(ImageMediaType::PNG));
+        assert!(matches!(image.data, DocumentSourceKind::Base64(ref data) if data == "aGVsbG8="));
+    }
+
+    #[test]
+    fn prompt_messages_keep_roles() {
+        let user = prompt_message(&PromptMessage::new_text(PromptMessageRole::User, "hi"));
+        assert_eq!(user, Message::user("hi"));
+
+        let assistant = prompt_message(&PromptMessage::new_text(
+            PromptMessageRole::Assistant,
+            "hello",
+        ));
+        assert_eq!(assistant, Message::assistant("hello"));
+    }
+}
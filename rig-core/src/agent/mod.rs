@@ -106,14 +106,23 @@
 //! let response = agent.prompt("What does \"glarb-glarb\" mean?").await
 //!     .expect("Failed to prompt the agent");
 //! ```
+mod approval;
 mod builder;
 mod completion;
+mod mcp;
+pub mod examples;
+pub mod rerank;
 pub(crate) mod prompt_request;
 // mod tool;
 
 pub use crate::message::Text;
+pub use approval::{ToolApproval, ToolApprovalFn};
 pub use builder::AgentBuilder;
 pub use completion::Agent;
+pub use mcp::{
+    McpClient, McpClientHandler, McpClientSlot, prompt_message, resource_documents, root_from_path,
+    tool_result_content, tool_result_to_string,
+};
 pub use prompt_request::PromptHook;
 pub use prompt_request::streaming::{
     FinalResponse, MultiTurnStreamItem, StreamingPromptRequest, stream_to_stdout,
@@ -14,10 +14,11 @@ use tracing::info_span;
 use crate::{
     OneOrMany,
     completion::{Completion, CompletionError, CompletionModel, Message, PromptError, Usage},
-    message::{AssistantContent, UserContent},
+    message::{AssistantContent, ToolResultContent, UserContent},
+    telemetry::redact,
 };
 
-use super::Agent;
+use super::{Agent, ToolApprovalFn, approval, tool_result_to_string};
 
 pub trait PromptType {}
 pub struct Standard;
@@ -53,6 +54,10 @@ where
     state: PhantomData<S>,
     /// Optional per-request hook for events
     hook: Option<P>,
+    /// Confirmation of tool calls, overrides the agent's
+    tool_approval: Option<ToolApprovalFn>,
+    /// Identical tool calls in a row aborting the request
+    tool_loop_limit: Option<usize>,
 }
 
 impl<'a, M> PromptRequest<'a, Standard, M, ()>
@@ -64,10 +69,12 @@ where
         Self {
             prompt: prompt.into(),
             chat_history: None,
-            max_depth: 0,
+            max_depth: agent.max_turns.unwrap_or(0),
             agent,
             state: PhantomData,
             hook: None,
+            tool_approval: None,
+            tool_loop_limit: agent.tool_loop_limit,
         }
     }
 }
@@ -91,6 +98,8 @@ where
             agent: self.agent,
             state: PhantomData,
             hook: self.hook,
+            tool_approval: self.tool_approval,
+            tool_loop_limit: self.tool_loop_limit,
         }
     }
     /// Set the maximum depth for multi-turn conversations (ie, the maximum number of turns an LLM can have calling tools before writing a text response).
@@ -103,6 +112,8 @@ where
             agent: self.agent,
             state: PhantomData,
             hook: self.hook,
+            tool_approval: self.tool_approval,
+            tool_loop_limit: self.tool_loop_limit,
         }
     }
 
@@ -115,6 +126,8 @@ where
             agent: self.agent,
             state: PhantomData,
             hook: self.hook,
+            tool_approval: self.tool_approval,
+            tool_loop_limit: self.tool_loop_limit,
         }
     }
 
@@ -130,8 +143,55 @@ where
             agent: self.agent,
             state: PhantomData,
             hook: Some(hook),
+            tool_approval: self.tool_approval,
+            tool_loop_limit: self.tool_loop_limit,
         }
     }
+
+    /// Ask the callback before running any tool of this request instead of the agent's callback
+    pub fn with_tool_approval(mut self, approval: ToolApprovalFn) -> Self {
+        self.tool_approval = Some(approval);
+        self
+    }
+
+    /// Abort with [`PromptError::ToolLoopError`] when the model calls the same tool with
+    /// identical arguments `limit` times in a row
+    pub fn tool_loop_limit(mut self, limit: usize) -> Self {
+        self.tool_loop_limit = Some(limit);
+        self
+    }
+}
+
+/// Detects the model calling the same tool with identical arguments over and over.
+#[derive(Debug, Default)]
+pub(crate) struct ToolLoopGuard {
+    limit: Option<usize>,
+    last: Option<(String, Value)>,
+    repeats: usize,
+}
+
+impl ToolLoopGuard {
+    pub(crate) fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    /// Record a tool call, returns the number of identical calls in a row once it reaches the
+    /// limit.
+    pub(crate) fn record(&mut self, tool_name: &str, args: &Value) -> Option<usize> {
+        match &self.last {
+            Some((name, last_args)) if name == tool_name && last_args == args => self.repeats += 1,
+            _ => {
+                self.last = Some((tool_name.to_string(), args.clone()));
+                self.repeats = 1;
+            }
+        }
+        self.limit
+            .filter(|limit| self.repeats >= *limit)
+            .map(|_| self.repeats)
+    }
 }
 
 // dead code allowed because of functions being left empty to allow for users to not have to implement every single function
@@ -240,6 +300,12 @@ where
     P: PromptHook<M>,
 {
     async fn send(self) -> Result<PromptResponse, PromptError> {
+        // the provider records the messages inside this future, with the agent's redactor
+        let redactor = self.agent.redactor.clone();
+        redact::scope(redactor, self.send_inner()).await
+    }
+
+    async fn send_inner(self) -> Result<PromptResponse, PromptError> {
         let agent_span = if tracing::Span::current().is_disabled() {
             info_span!(
                 "invoke_agent",
@@ -264,11 +330,12 @@ where
         };
 
         if let Some(text) = self.prompt.rag_text() {
-            agent_span.record("gen_ai.prompt", text);
+            agent_span.record("gen_ai.prompt", agent.redact(&text));
         }
 
         let mut current_max_depth = 0;
         let mut usage = Usage::new();
+        let mut loop_guard = ToolLoopGuard::new(self.tool_loop_limit);
         let current_span_id: AtomicU64 = AtomicU64::new(0);
 
         // We need to do at least 2 loops for 1 roundtrip (user expects normal message)
@@ -367,7 +434,7 @@ where
                     tracing::info!("Depth reached: {}/{}", current_max_depth, self.max_depth);
                 }
 
-                agent_span.record("gen_ai.completion", &merged_texts);
+                agent_span.record("gen_ai.completion", agent.redact(&merged_texts));
                 agent_span.record("gen_ai.usage.input_tokens", usage.input_tokens);
                 agent_span.record("gen_ai.usage.output_tokens", usage.output_tokens);
 
@@ -375,11 +442,33 @@ where
                 return Ok(PromptResponse::new(merged_texts, usage));
             }
 
+            for choice in &tool_calls {
+                if let AssistantContent::ToolCall(tool_call) = choice
+                    && let Some(repeats) =
+                        loop_guard.record(&tool_call.function.name, &tool_call.function.arguments)
+                {
+                    tracing::warn!(
+                        "tool {} called {repeats} times in a row with the same arguments",
+                        tool_call.function.name
+                    );
+                    return Err(PromptError::ToolLoopError {
+                        tool_name: tool_call.function.name.clone(),
+                        repeats,
+                        chat_history: Box::new(chat_history.clone()),
+                    });
+                }
+            }
+
             let hook = self.hook.clone();
+            let tool_approval = self
+                .tool_approval
+                .clone()
+                .or_else(|| agent.tool_approval.clone());
             let tool_content = stream::iter(tool_calls)
                 .then(|choice| {
                     let hook1 = hook.clone();
                     let hook2 = hook.clone();
+                    let tool_approval = tool_approval.clone();
 
                     let tool_span = info_span!(
                         "execute_tool",
@@ -405,40 +494,50 @@ where
                     async move {
                         if let AssistantContent::ToolCall(tool_call) = choice {
                             let tool_name = &tool_call.function.name;
-                            
+
                             let tool_span = tracing::Span::current();
                             tool_span.record("gen_ai.tool.name", tool_name);
                             tool_span.record("gen_ai.tool.call.id", &tool_call.id);
-                            tool_span.record("gen_ai.tool.call.arguments", & tool_call.function.arguments.to_string());
+                            tool_span.record(
+                                "gen_ai.tool.call.arguments",
+                                agent.redact(&tool_call.function.arguments.to_string()),
+                            );
+                            let approved = approval::approve(
+                                tool_approval.as_ref(),
+                                tool_name,
+                                &tool_call.function.arguments,
+                            )
+                            .await;
+                            let args = approved.as_ref().unwrap_or(&tool_call.function.arguments);
                             if let Some(hook) = hook1 {
-                                hook.on_tool_call(tool_name, & tool_call.function.arguments).await;
+                                hook.on_tool_call(tool_name, args).await;
                             }
-                            let output = match agent.call(tool_name, & tool_call.function.arguments).await {
-                                Ok(output) => output,
-                                Err(e) => {
-                                    let error_msg = format!("CompletionError: {:?}", e);
-                                    error_msg
-                                }
+                            let content = match &approved {
+                                Ok(args) => match agent.call_tool(tool_name, args).await {
+                                    Ok(content) => content,
+                                    Err(e) => {
+                                        let error_msg = format!("CompletionError: {:?}", e);
+                                        OneOrMany::one(ToolResultContent::text(error_msg))
+                                    }
+                                },
+                                Err(denied) => OneOrMany::one(ToolResultContent::text(denied)),
                             };
+                            let output = tool_result_to_string(&content);
                             if let Some(hook) = hook2 {
-                                hook.on_tool_result(tool_name, & tool_call.function.arguments, &output.to_string())
+                                hook.on_tool_result(tool_name, args, &output.to_string())
                                     .await;
                             }
-                            tool_span.record("gen_ai.tool.call.result", &output);
-                            tracing::info!(
-                                "executed tool {tool_name} result: {output}"
-                            );
+                            let redacted = agent.redact(&output);
+                            tool_span.record("gen_ai.tool.call.result", &redacted);
+                            tracing::info!("executed tool {tool_name} result: {redacted}");
                             if let Some(call_id) = tool_call.call_id.clone() {
                                 Ok(UserContent::tool_result_with_call_id(
                                     tool_call.id.clone(),
                                     call_id,
-                                    OneOrMany::one(output.into()),
+                                    content,
                                 ))
                             } else {
-                                Ok(UserContent::tool_result(
-                                    tool_call.id.clone(),
-                                    OneOrMany::one(output.into()),
-                                ))
+                                Ok(UserContent::tool_result(tool_call.id.clone(), content))
                             }
                         } else {
                             unreachable!(
@@ -467,3 +566,32 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::CompletionClient;
+    use crate::client::mock::{self, MockClient};
+    use crate::completion::Prompt;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn repeated_tool_call_aborts() {
+        let script = mock::script("loop-guard-test");
+        for _ in 0..3 {
+            script.push_tool_call("list_files", json!({"path": "."}));
+        }
+        let agent = MockClient::new()
+            .agent("loop-guard-test")
+            .max_turns(10)
+            .tool_loop_limit(3)
+            .build();
+
+        let err = agent.prompt("what is here").await.unwrap_err();
+        assert!(matches!(
+            err,
+            PromptError::ToolLoopError { ref tool_name, repeats: 3, .. } if tool_name == "list_files"
+        ));
+        assert_eq!(script.requests().len(), 3);
+    }
+}
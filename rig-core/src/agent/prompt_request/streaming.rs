@@ -13,11 +13,13 @@ use tracing::info_span;
 use tracing_futures::Instrument;
 
 use crate::{
-    agent::Agent,
+    agent::{Agent, ToolApprovalFn, approval, tool_result_to_string},
     completion::{CompletionError, CompletionModel, PromptError},
     message::{Message, Text},
 };
 
+use super::ToolLoopGuard;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub type StreamingResult<R> =
     Pin<Box<dyn Stream<Item = Result<MultiTurnStreamItem<R>, StreamingError>> + Send>>;
@@ -105,6 +107,10 @@ where
     agent: Arc<Agent<M>>,
     /// Optional per-request hook for events
     hook: Option<P>,
+    /// Confirmation of tool calls, overrides the agent's
+    tool_approval: Option<ToolApprovalFn>,
+    /// Identical tool calls in a row aborting the request
+    tool_loop_limit: Option<usize>,
 }
 
 impl<M, P> StreamingPromptRequest<M, P>
@@ -118,9 +124,11 @@ where
         Self {
             prompt: prompt.into(),
             chat_history: None,
-            max_depth: 0,
+            max_depth: agent.max_turns.unwrap_or(0),
+            tool_loop_limit: agent.tool_loop_limit,
             agent,
             hook: None,
+            tool_approval: None,
         }
     }
 
@@ -148,9 +156,24 @@ where
             max_depth: self.max_depth,
             agent: self.agent,
             hook: Some(hook),
+            tool_approval: self.tool_approval,
+            tool_loop_limit: self.tool_loop_limit,
         }
     }
 
+    /// Ask the callback before running any tool of this request instead of the agent's callback
+    pub fn with_tool_approval(mut self, approval: ToolApprovalFn) -> Self {
+        self.tool_approval = Some(approval);
+        self
+    }
+
+    /// Abort with [`PromptError::ToolLoopError`] when the model calls the same tool with
+    /// identical arguments `limit` times in a row
+    pub fn tool_loop_limit(mut self, limit: usize) -> Self {
+        self.tool_loop_limit = Some(limit);
+        self
+    }
+
     #[cfg_attr(feature = "worker", worker::send)]
     async fn send(self) -> StreamingResult<M::StreamingResponse> {
         let agent_span = if tracing::Span::current().is_disabled() {
@@ -169,12 +192,15 @@ where
         };
 
         let prompt = self.prompt;
+        let agent = self.agent;
+        let tool_approval = self
+            .tool_approval
+            .clone()
+            .or_else(|| agent.tool_approval.clone());
         if let Some(text) = prompt.rag_text() {
-            agent_span.record("gen_ai.prompt", text);
+            agent_span.record("gen_ai.prompt", agent.redact(&text));
         }
 
-        let agent = self.agent;
-
         let chat_history = if let Some(history) = self.chat_history {
             Arc::new(RwLock::new(history))
         } else {
@@ -189,6 +215,7 @@ where
         let mut max_depth_reached = false;
 
         let mut aggregated_usage = crate::completion::Usage::new();
+        let mut loop_guard = ToolLoopGuard::new(self.tool_loop_limit);
 
         Box::pin(async_stream::stream! {
             let _guard = agent_span.enter();
@@ -266,6 +293,15 @@ where
                             did_call_tool = false;
                         },
                         Ok(StreamedAssistantContent::ToolCall(tool_call)) => {
+                            if let Some(repeats) = loop_guard.record(&tool_call.function.name, &tool_call.function.arguments) {
+                                tracing::warn!("tool {} called {repeats} times in a row with the same arguments", tool_call.function.name);
+                                yield Err(Box::new(PromptError::ToolLoopError {
+                                    tool_name: tool_call.function.name.clone(),
+                                    repeats,
+                                    chat_history: Box::new((*chat_history.read().await).clone()),
+                                }).into());
+                                break 'outer;
+                            }
                             let tool_span = info_span!(
                                 parent: tracing::Span::current(),
                                 "execute_tool",
@@ -279,30 +315,35 @@ where
 
                             async {
                                 let tool_span = tracing::Span::current();
+                                let approved = approval::approve(tool_approval.as_ref(), &tool_call.function.name, &tool_call.function.arguments).await;
+                                let args = approved.as_ref().unwrap_or(&tool_call.function.arguments);
                                 if let Some(ref hook) = self.hook {
-                                    hook.on_tool_call(&tool_call.function.name, &tool_call.function.arguments.to_string()).await;
+                                    hook.on_tool_call(&tool_call.function.name, &args.to_string()).await;
                                 }
 
                                 tool_span.record("gen_ai.tool.name", &tool_call.function.name);
-                                tool_span.record("gen_ai.tool.call.arguments", &tool_call.function.arguments.to_string());
-
-                                let tool_result = match
-                                agent.call(&tool_call.function.name, &tool_call.function.arguments).await {
-                                    Ok(thing) => thing,
-                                    Err(e) => e.to_string()
+                                tool_span.record("gen_ai.tool.call.arguments", agent.redact(&args.to_string()));
+
+                                let tool_content = match &approved {
+                                    Ok(args) => match agent.call_tool(&tool_call.function.name, args).await {
+                                        Ok(thing) => thing,
+                                        Err(e) => OneOrMany::one(ToolResultContent::text(e.to_string()))
+                                    },
+                                    Err(denied) => OneOrMany::one(ToolResultContent::text(denied)),
                                 };
+                                let tool_result = tool_result_to_string(&tool_content);
 
-                                tool_span.record("gen_ai.tool.call.result", &tool_result);
+                                tool_span.record("gen_ai.tool.call.result", agent.redact(&tool_result));
 
                                 if let Some(ref hook) = self.hook {
-                                    hook.on_tool_result(&tool_call.function.name, &tool_call.function.arguments, &tool_result.to_string())
+                                    hook.on_tool_result(&tool_call.function.name, args, &tool_result.to_string())
                                     .await;
                                 }
 
                                 let tool_call_msg = AssistantContent::ToolCall(tool_call.clone());
 
                                 tool_calls.push(tool_call_msg);
-                                tool_results.push((tool_call.id, tool_call.call_id, tool_result));
+                                tool_results.push((tool_call.id, tool_call.call_id, tool_content));
 
                                 did_call_tool = true;
                                 // break;
@@ -351,14 +392,14 @@ where
                             content: OneOrMany::one(UserContent::tool_result_with_call_id(
                                 &id,
                                 call_id.clone(),
-                                OneOrMany::one(ToolResultContent::text(&tool_result)),
+                                tool_result,
                             )),
                         });
                     } else {
                         chat_history.write().await.push(Message::User {
                             content: OneOrMany::one(UserContent::tool_result(
                                 &id,
-                                OneOrMany::one(ToolResultContent::text(&tool_result)),
+                                tool_result,
                             )),
                         });
                     }
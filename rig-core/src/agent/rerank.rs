@@ -0,0 +1,159 @@
+//! Reranking the context documents of an agent.
+//!
+//! Static context and MCP resources are all added to every completion request. With a
+//! [Reranker] set on the agent (see [crate::agent::AgentBuilder::rerank]) the documents are
+//! scored against the prompt first and only the `top_n` most relevant ones are sent.
+//!
+//! [CompletionReranker] scores with a completion model, usually a small and cheap one. Providers
+//! with a dedicated rerank endpoint can implement [Reranker] directly.
+
+use std::sync::Arc;
+
+use futures::{StreamExt, TryStreamExt, future::BoxFuture, stream};
+
+use crate::completion::{CompletionError, CompletionModel, Document};
+use crate::message::AssistantContent;
+
+const RERANK_PREAMBLE: &str = "Rate how relevant the document is to the query on a scale \
+from 0 (unrelated) to 10 (answers the query). Reply with the number only.";
+
+/// Scores documents by relevance to a query.
+pub trait Reranker: Send + Sync {
+    /// One score per document, in the order of the documents. Higher is more relevant.
+    fn score<'a>(
+        &'a self,
+        query: &'a str,
+        documents: &'a [Document],
+    ) -> BoxFuture<'a, Result<Vec<f64>, CompletionError>>;
+}
+
+/// The reranker of an agent and the number of documents it keeps.
+#[derive(Clone)]
+pub struct ContextRerank {
+    pub reranker: Arc<dyn Reranker>,
+    pub top_n: usize,
+}
+
+impl ContextRerank {
+    /// Keep the `top_n` documents most relevant to the query, most relevant first.
+    pub async fn apply(
+        &self,
+        query: &str,
+        documents: Vec<Document>,
+    ) -> Result<Vec<Document>, CompletionError> {
+        if documents.len() <= self.top_n {
+            return Ok(documents);
+        }
+        let scores = self.reranker.score(query, &documents).await?;
+        let mut scored: Vec<(f64, Document)> = scores.into_iter().zip(documents).collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(scored
+            .into_iter()
+            .take(self.top_n)
+            .map(|(_, document)| document)
+            .collect())
+    }
+}
+
+/// Asks a completion model to rate each document from 0 to 10.
+#[derive(Clone)]
+pub struct CompletionReranker<M: CompletionModel> {
+    model: M,
+    concurrency: usize,
+}
+
+impl<M: CompletionModel> CompletionReranker<M> {
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            concurrency: 4,
+        }
+    }
+
+    /// Number of documents scored at the same time
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    async fn score_one(&self, query: &str, document: &Document) -> Result<f64, CompletionError> {
+        let prompt = format!("Query: {query}\n\nDocument:\n{}", document.text);
+        let response = self
+            .model
+            .completion_request(prompt)
+            .preamble(RERANK_PREAMBLE.to_string())
+            .temperature(0.0)
+            .max_tokens(8)
+            .send()
+            .await?;
+        let score = response.choice.iter().find_map(|content| match content {
+            AssistantContent::Text(text) => parse_score(&text.text),
+            _ => None,
+        });
+        if score.is_none() {
+            tracing::warn!(target: "rig", "unreadable relevance score for document {}", document.id);
+        }
+        Ok(score.unwrap_or(0.0))
+    }
+}
+
+impl<M: CompletionModel> Reranker for CompletionReranker<M> {
+    fn score<'a>(
+        &'a self,
+        query: &'a str,
+        documents: &'a [Document],
+    ) -> BoxFuture<'a, Result<Vec<f64>, CompletionError>> {
+        Box::pin(
+            stream::iter(documents)
+                .map(|document| self.score_one(query, document))
+                .buffered(self.concurrency)
+                .try_collect(),
+        )
+    }
+}
+
+/// The first number in the text, clamped to 0..=10.
+fn parse_score(text: &str) -> Option<f64> {
+    text.split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .find_map(|part| part.parse::<f64>().ok())
+        .map(|score| score.clamp(0.0, 10.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::CompletionClient;
+    use crate::client::mock::{self, MockClient};
+    use std::collections::HashMap;
+
+    fn document(id: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            text: format!("text of {id}"),
+            additional_props: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_most_relevant_documents() {
+        let script = mock::script("rerank-test");
+        for reply in ["2", "Score: 9", "n/a"] {
+            script.push_text(reply);
+        }
+        let rerank = ContextRerank {
+            reranker: Arc::new(
+                CompletionReranker::new(MockClient::new().completion_model("rerank-test"))
+                    .concurrency(1),
+            ),
+            top_n: 2,
+        };
+
+        let kept = rerank
+            .apply("query", vec![document("a"), document("b"), document("c")])
+            .await
+            .unwrap();
+        let ids: Vec<&str> = kept.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "a"]);
+        assert_eq!(parse_score("7.5/10"), Some(7.5));
+    }
+}
@@ -0,0 +1,95 @@
+//! Fault injection for resilience testing.
+//!
+//! Only compiled with the `chaos` feature. Tests [install] a set of [Faults] to make provider
+//! responses get lost or MCP calls slow, so retry, fallback and recovery code can be exercised
+//! deterministically. Nothing is injected until faults are installed.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::completion::{CompletionError, ProviderErrorKind};
+
+static FAULTS: RwLock<Option<Arc<Faults>>> = RwLock::new(None);
+
+/// The faults to inject.
+#[derive(Debug, Default)]
+pub struct Faults {
+    drop_every_nth_response: Option<u64>,
+    mcp_delay: Option<Duration>,
+    responses: AtomicU64,
+}
+
+impl Faults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop every `n`th completion response, the call then fails with a retryable
+    /// [ProviderErrorKind::Unavailable] error as if the connection was lost.
+    pub fn drop_every_nth_response(mut self, n: u64) -> Self {
+        self.drop_every_nth_response = Some(n.max(1));
+        self
+    }
+
+    /// Delay every MCP tool call.
+    pub fn mcp_delay(mut self, delay: Duration) -> Self {
+        self.mcp_delay = Some(delay);
+        self
+    }
+
+    fn drop_response(&self) -> bool {
+        let Some(n) = self.drop_every_nth_response else {
+            return false;
+        };
+        (self.responses.fetch_add(1, Ordering::SeqCst) + 1).is_multiple_of(n)
+    }
+}
+
+/// Install faults, replacing the previously installed ones.
+pub fn install(faults: Faults) {
+    *FAULTS.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(faults));
+}
+
+/// Remove all installed faults.
+pub fn clear() {
+    *FAULTS.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+fn current() -> Option<Arc<Faults>> {
+    FAULTS.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Turn a completion result into an injected error when the response should be dropped.
+pub(crate) fn intercept_response<T>(
+    result: Result<T, CompletionError>,
+) -> Result<T, CompletionError> {
+    match current() {
+        Some(faults) if result.is_ok() && faults.drop_response() => {
+            Err(CompletionError::Provider {
+                kind: ProviderErrorKind::Unavailable,
+                message: "response dropped by fault injection".to_string(),
+            })
+        }
+        _ => result,
+    }
+}
+
+/// Wait for the injected MCP delay, if any.
+pub(crate) async fn delay_mcp_call() {
+    if let Some(delay) = current().and_then(|faults| faults.mcp_delay) {
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_every_nth_response() {
+        let faults = Faults::new().drop_every_nth_response(3);
+        let dropped: Vec<bool> = (0..6).map(|_| faults.drop_response()).collect();
+        assert_eq!(dropped, vec![false, false, true, false, false, true]);
+    }
+}
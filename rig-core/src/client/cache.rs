@@ -0,0 +1,329 @@
+//! Caching completion responses.
+//!
+//! [CachedCompletionModel] wraps a model and looks every request up in a [CompletionCache] before
+//! sending it. The key is a hash of the model name and the whole normalized request (preamble,
+//! messages, documents, tools and parameters), see [cache_key]. With the default
+//! [CachePolicy::Deterministic] only requests with temperature 0 are cached, other requests are
+//! expected to give different answers.
+//!
+//! A cache hit reports zero token usage, so replaying a step does not bill its tokens again.
+//! Streaming requests are never cached. [MemoryCache] is an in-memory LRU cache, other stores
+//! (e.g. a database table) implement [CompletionCache].
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::OneOrMany;
+use crate::client::completion::CompletionModelHandle;
+use crate::completion::{
+    AssistantContent, CompletionError, CompletionModel, CompletionRequest, CompletionResponse,
+    Usage,
+};
+use crate::streaming::{StreamUsage, StreamingCompletionResponse};
+
+/// A cached completion response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedCompletion {
+    pub choice: OneOrMany<AssistantContent>,
+    /// Usage of the original request
+    pub usage: Usage,
+}
+
+/// A store of completion responses.
+pub trait CompletionCache: Send + Sync {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<CachedCompletion>>;
+
+    fn put<'a>(&'a self, key: &'a str, value: CachedCompletion) -> BoxFuture<'a, ()>;
+}
+
+/// Which requests are cached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CachePolicy {
+    /// Only requests with temperature 0.
+    #[default]
+    Deterministic,
+    /// Every request.
+    Always,
+}
+
+impl CachePolicy {
+    pub fn applies(&self, request: &CompletionRequest) -> bool {
+        match self {
+            CachePolicy::Deterministic => request.temperature == Some(0.0),
+            CachePolicy::Always => true,
+        }
+    }
+}
+
+/// Cache key of a request to a model: a 128 bit FNV-1a hash, stable across builds so that keys
+/// can be persisted.
+pub fn cache_key(model: &str, request: &CompletionRequest) -> String {
+    let normalized = json!({
+        "model": model,
+        "preamble": request.preamble,
+        "chat_history": request.chat_history,
+        "documents": request.documents,
+        "tools": request.tools,
+        "temperature": request.temperature,
+        "max_tokens": request.max_tokens,
+        "sampling": request.sampling,
+        "think": request.think,
+        "stop": request.stop,
+        "tool_choice": request.tool_choice,
+        "additional_params": request.additional_params,
+    });
+    let mut hash: u128 = 0x6c62272e07bb014262b821756295c58d;
+    for byte in normalized.to_string().bytes() {
+        hash ^= byte as u128;
+        hash = hash.wrapping_mul(0x0000000001000000000000000000013b);
+    }
+    format!("{hash:032x}")
+}
+
+/// In-memory cache evicting the least recently used entry once full.
+#[derive(Debug)]
+pub struct MemoryCache {
+    capacity: usize,
+    state: Mutex<LruState>,
+}
+
+#[derive(Debug, Default)]
+struct LruState {
+    entries: HashMap<String, (CachedCompletion, u64)>,
+    tick: u64,
+}
+
+impl MemoryCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entries
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl CompletionCache for MemoryCache {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Option<CachedCompletion>> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.tick += 1;
+        let tick = state.tick;
+        let value = state.entries.get_mut(key).map(|(value, used)| {
+            *used = tick;
+            value.clone()
+        });
+        Box::pin(async move { value })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, value: CachedCompletion) -> BoxFuture<'a, ()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.tick += 1;
+        let tick = state.tick;
+        if !state.entries.contains_key(key) && state.entries.len() >= self.capacity {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        state.entries.insert(key.to_string(), (value, tick));
+        Box::pin(async {})
+    }
+}
+
+/// Hits and misses of cached models.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Tokens of the original requests answered from the cache
+    pub tokens_saved: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    tokens_saved: AtomicU64,
+}
+
+/// Counters shared by every cached model.
+fn counters() -> &'static Counters {
+    static COUNTERS: OnceLock<Counters> = OnceLock::new();
+    COUNTERS.get_or_init(Counters::default)
+}
+
+/// Cache statistics of all cached models since startup.
+pub fn stats() -> CacheStats {
+    let counters = counters();
+    CacheStats {
+        hits: counters.hits.load(Ordering::Relaxed),
+        misses: counters.misses.load(Ordering::Relaxed),
+        tokens_saved: counters.tokens_saved.load(Ordering::Relaxed),
+    }
+}
+
+/// A model answering repeated requests from a cache.
+#[derive(Clone)]
+pub struct CachedCompletionModel<'a> {
+    inner: CompletionModelHandle<'a>,
+    cache: Arc<dyn CompletionCache>,
+    /// Model identity in the cache keys, e.g. `provider/model`
+    model: String,
+    policy: CachePolicy,
+}
+
+impl<'a> CachedCompletionModel<'a> {
+    pub fn new(
+        inner: CompletionModelHandle<'a>,
+        cache: Arc<dyn CompletionCache>,
+        model: impl Into<String>,
+    ) -> Self {
+        Self {
+            inner,
+            cache,
+            model: model.into(),
+            policy: CachePolicy::default(),
+        }
+    }
+
+    pub fn policy(mut self, policy: CachePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl CompletionModel for CachedCompletionModel<'_> {
+    type Response = ();
+    type StreamingResponse = StreamUsage;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        if !self.policy.applies(&request) {
+            return self.inner.completion(request).await;
+        }
+        let key = cache_key(&self.model, &request);
+        if let Some(cached) = self.cache.get(&key).await {
+            let counters = counters();
+            counters.hits.fetch_add(1, Ordering::Relaxed);
+            counters
+                .tokens_saved
+                .fetch_add(cached.usage.total_tokens, Ordering::Relaxed);
+            tracing::debug!(target: "rig", "completion cache hit {key} for {}", self.model);
+            return Ok(CompletionResponse {
+                choice: cached.choice,
+                usage: Usage::new(),
+                logprobs: None,
+                timings: None,
+                served_by: None,
+                raw_response: (),
+            });
+        }
+        counters().misses.fetch_add(1, Ordering::Relaxed);
+        let response = self.inner.completion(request).await?;
+        self.cache
+            .put(
+                &key,
+                CachedCompletion {
+                    choice: response.choice.clone(),
+                    usage: response.usage,
+                },
+            )
+            .await;
+        Ok(response)
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        self.inner.stream(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::CompletionClient;
+    use crate::client::mock::{self, MockClient};
+    use crate::completion::Message;
+
+    fn request(temperature: f64) -> CompletionRequest {
+        CompletionRequest {
+            preamble: None,
+            chat_history: OneOrMany::one(Message::user("2 + 2")),
+            documents: vec![],
+            tools: vec![],
+            temperature: Some(temperature),
+            max_tokens: None,
+            sampling: Default::default(),
+            think: None,
+            stop: vec![],
+            tool_choice: None,
+            additional_params: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn deterministic_requests_are_served_from_cache() {
+        let script = mock::script("cache-test");
+        script.push_text("4");
+        script.push_text("four");
+        let model = CachedCompletionModel::new(
+            CompletionModelHandle {
+                inner: Arc::new(MockClient::new().completion_model("cache-test")),
+            },
+            Arc::new(MemoryCache::new(8)),
+            "mock/cache-test",
+        );
+
+        let first = model.completion(request(0.0)).await.unwrap();
+        let second = model.completion(request(0.0)).await.unwrap();
+        assert_eq!(first.choice, second.choice);
+        assert_eq!(script.requests().len(), 1);
+
+        let warm = model.completion(request(0.7)).await.unwrap();
+        assert_eq!(warm.choice, OneOrMany::one(AssistantContent::text("four")));
+        assert_eq!(script.requests().len(), 2);
+        assert_ne!(cache_key("a", &request(0.0)), cache_key("b", &request(0.0)));
+    }
+
+    #[tokio::test]
+    async fn memory_cache_evicts_least_recently_used() {
+        let cache = MemoryCache::new(2);
+        let value = CachedCompletion {
+            choice: OneOrMany::one(AssistantContent::text("x")),
+            usage: Usage::new(),
+        };
+        cache.put("a", value.clone()).await;
+        cache.put("b", value.clone()).await;
+        assert!(cache.get("a").await.is_some());
+        cache.put("c", value).await;
+        assert!(cache.get("b").await.is_none());
+        assert!(cache.get("a").await.is_some());
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -4,7 +4,7 @@ use crate::completion::{
     CompletionError, CompletionModel, CompletionModelDyn, CompletionRequest, CompletionResponse,
     GetTokenUsage,
 };
-use crate::streaming::StreamingCompletionResponse;
+use crate::streaming::{StreamUsage, StreamingCompletionResponse};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::future::Future;
@@ -58,14 +58,16 @@ pub struct CompletionModelHandle<'a> {
 
 impl CompletionModel for CompletionModelHandle<'_> {
     type Response = ();
-    type StreamingResponse = ();
+    type StreamingResponse = StreamUsage;
 
-    fn completion(
+    async fn completion(
         &self,
         request: CompletionRequest,
-    ) -> impl Future<Output = Result<CompletionResponse<Self::Response>, CompletionError>> + Send
-    {
-        self.inner.completion(request)
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let result = self.inner.completion(request).await;
+        #[cfg(any(test, feature = "chaos"))]
+        let result = crate::chaos::intercept_response(result);
+        result
     }
 
     fn stream(
@@ -0,0 +1,180 @@
+//! A completion model that transparently falls back to other models.
+//!
+//! [FallbackCompletionModel] wraps an ordered chain of models. Requests are sent to the first
+//! model in the chain; when it fails with a retryable error (see [CompletionError::is_retryable])
+//! the same request is sent to the next model, and so on. Non-retryable errors are returned
+//! immediately since another provider would most likely fail the same way.
+//!
+//! Each response records the label of the model that served it in `served_by`, so concurrent
+//! requests through the same chain can tell which provider answered them.
+
+use crate::client::completion::CompletionModelHandle;
+use crate::completion::{CompletionError, CompletionModel, CompletionRequest, CompletionResponse};
+use crate::streaming::{StreamUsage, StreamingCompletionResponse};
+
+/// A completion model backed by an ordered chain of labelled models.
+#[derive(Clone)]
+pub struct FallbackCompletionModel<'a> {
+    chain: Vec<(String, CompletionModelHandle<'a>)>,
+}
+
+impl<'a> FallbackCompletionModel<'a> {
+    /// Create a fallback chain with the primary model. `label` identifies the model in logs and
+    /// in the `served_by` of responses, e.g. `deepseek/deepseek-chat`.
+    pub fn new(label: impl Into<String>, primary: CompletionModelHandle<'a>) -> Self {
+        Self {
+            chain: vec![(label.into(), primary)],
+        }
+    }
+
+    /// Append a model to the end of the chain.
+    pub fn fallback(mut self, label: impl Into<String>, model: CompletionModelHandle<'a>) -> Self {
+        self.chain.push((label.into(), model));
+        self
+    }
+
+    /// The label of the model at `index`, logging when it is not the primary model.
+    fn served_by(&self, index: usize) -> Option<String> {
+        let label = &self.chain[index].0;
+        if index > 0 {
+            tracing::info!(target: "rig", "completion served by fallback model {label}");
+        }
+        Some(label.clone())
+    }
+}
+
+impl CompletionModel for FallbackCompletionModel<'_> {
+    type Response = ();
+    type StreamingResponse = StreamUsage;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let mut last_err = None;
+        for (index, (label, model)) in self.chain.iter().enumerate() {
+            match model.completion(request.clone()).await {
+                Ok(mut response) => {
+                    response.served_by = self.served_by(index);
+                    return Ok(response);
+                }
+                Err(err) if err.is_retryable() => {
+                    tracing::warn!(target: "rig", "model {label} failed, trying next in chain: {err}");
+                    last_err = Some(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(last_err.expect("fallback chain always contains the primary model"))
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        // Only fall back while opening the stream, never after output has started.
+        let mut last_err = None;
+        for (index, (label, model)) in self.chain.iter().enumerate() {
+            match model.stream(request.clone()).await {
+                Ok(response) => return Ok(response.with_served_by(self.served_by(index))),
+                Err(err) if err.is_retryable() => {
+                    tracing::warn!(target: "rig", "model {label} failed, trying next in chain: {err}");
+                    last_err = Some(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(last_err.expect("fallback chain always contains the primary model"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::OneOrMany;
+    use crate::completion::{AssistantContent, Usage};
+
+    #[derive(Clone)]
+    struct MockModel(Option<&'static str>);
+
+    impl CompletionModel for MockModel {
+        type Response = ();
+        type StreamingResponse = ();
+
+        async fn completion(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<CompletionResponse<()>, CompletionError> {
+            match self.0 {
+                Some(err) => Err(CompletionError::provider(err)),
+                None => Ok(CompletionResponse {
+                    choice: OneOrMany::one(AssistantContent::text("ok")),
+                    usage: Usage::new(),
+                    logprobs: None,
+                    timings: None,
+                    served_by: None,
+                    raw_response: (),
+                }),
+            }
+        }
+
+        async fn stream(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<StreamingCompletionResponse<()>, CompletionError> {
+            Err(CompletionError::provider("unsupported"))
+        }
+    }
+
+    fn handle(model: MockModel) -> CompletionModelHandle<'static> {
+        CompletionModelHandle {
+            inner: Arc::new(model),
+        }
+    }
+
+    fn request() -> CompletionRequest {
+        CompletionRequest {
+            preamble: None,
+            chat_history: OneOrMany::one("hello".into()),
+            documents: Vec::new(),
+            tools: Vec::new(),
+            temperature: None,
+            max_tokens: None,
+            sampling: Default::default(),
+            think: None,
+            stop: vec![],
+            tool_choice: None,
+            additional_params: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn falls_back_on_retryable_error() {
+        let model = FallbackCompletionModel::new("primary", handle(MockModel(Some("rate limit"))))
+            .fallback("backup", handle(MockModel(None)));
+
+        let response = model.completion(request()).await.unwrap();
+        assert_eq!(response.served_by.as_deref(), Some("backup"));
+
+        // The label survives erasing the chain behind a handle, as agents do.
+        let chain = CompletionModelHandle {
+            inner: Arc::new(FallbackCompletionModel::new(
+                "primary",
+                handle(MockModel(None)),
+            )),
+        };
+        let response = chain.completion(request()).await.unwrap();
+        assert_eq!(response.served_by.as_deref(), Some("primary"));
+    }
+
+    #[tokio::test]
+    async fn stops_on_non_retryable_error() {
+        let model =
+            FallbackCompletionModel::new("primary", handle(MockModel(Some("invalid api key"))))
+                .fallback("backup", handle(MockModel(None)));
+
+        assert!(model.completion(request()).await.is_err());
+    }
+}
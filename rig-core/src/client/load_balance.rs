@@ -0,0 +1,228 @@
+//! A completion model that spreads requests across several instances of the same model.
+//!
+//! [LoadBalancedCompletionModel] wraps multiple models (typically the same model served by
+//! different hosts) behind a single handle and picks one for each request according to a
+//! [BalanceStrategy].
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::StreamExt;
+use serde::Deserialize;
+
+use crate::client::completion::CompletionModelHandle;
+use crate::completion::{CompletionError, CompletionModel, CompletionRequest, CompletionResponse};
+use crate::streaming::{StreamUsage, StreamingCompletionResponse};
+
+/// How [LoadBalancedCompletionModel] picks the model serving the next request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceStrategy {
+    /// Cycle through the models in order.
+    #[default]
+    RoundRobin,
+    /// Pick the model with the fewest requests currently in flight.
+    LeastInFlight,
+}
+
+#[derive(Clone)]
+struct Member<'a> {
+    label: String,
+    model: CompletionModelHandle<'a>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+/// Decrements the in-flight counter of a member when the request completes. For streams the
+/// guard lives in the returned stream, so the request counts as in flight until it is dropped.
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A completion model backed by a pool of interchangeable models.
+#[derive(Clone)]
+pub struct LoadBalancedCompletionModel<'a> {
+    members: Vec<Member<'a>>,
+    strategy: BalanceStrategy,
+    next: Arc<AtomicUsize>,
+}
+
+impl<'a> LoadBalancedCompletionModel<'a> {
+    /// Create an empty pool using the given strategy.
+    pub fn new(strategy: BalanceStrategy) -> Self {
+        Self {
+            members: Vec::new(),
+            strategy,
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Add a model to the pool. `label` identifies the member in logs, e.g. the host url.
+    pub fn member(mut self, label: impl Into<String>, model: CompletionModelHandle<'a>) -> Self {
+        self.members.push(Member {
+            label: label.into(),
+            model,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        });
+        self
+    }
+
+    /// Number of requests currently in flight for each member, in insertion order.
+    pub fn in_flight(&self) -> Vec<(String, usize)> {
+        self.members
+            .iter()
+            .map(|m| (m.label.clone(), m.in_flight.load(Ordering::SeqCst)))
+            .collect()
+    }
+
+    fn pick(&self) -> Result<(&Member<'a>, InFlightGuard), CompletionError> {
+        if self.members.is_empty() {
+            return Err(CompletionError::RequestError(
+                "load balanced model has no members".into(),
+            ));
+        }
+
+        let index = match self.strategy {
+            BalanceStrategy::RoundRobin => {
+                self.next.fetch_add(1, Ordering::Relaxed) % self.members.len()
+            }
+            BalanceStrategy::LeastInFlight => {
+                // Start scanning from a rotating offset so ties are spread evenly.
+                let offset = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..self.members.len())
+                    .map(|i| (offset + i) % self.members.len())
+                    .min_by_key(|i| self.members[*i].in_flight.load(Ordering::SeqCst))
+                    .unwrap_or(0)
+            }
+        };
+
+        let member = &self.members[index];
+        member.in_flight.fetch_add(1, Ordering::SeqCst);
+        tracing::debug!(target: "rig", "load balancer routed request to {}", member.label);
+        Ok((member, InFlightGuard(member.in_flight.clone())))
+    }
+}
+
+impl CompletionModel for LoadBalancedCompletionModel<'_> {
+    type Response = ();
+    type StreamingResponse = StreamUsage;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let (member, _guard) = self.pick()?;
+        member.model.completion(request).await
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        let (member, guard) = self.pick()?;
+        let response = member.model.stream(request).await?;
+        let served_by = response.served_by;
+        let inner = response.inner.map(move |chunk| {
+            let _guard = &guard;
+            chunk
+        });
+        Ok(StreamingCompletionResponse::stream(Box::pin(inner)).with_served_by(served_by))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OneOrMany;
+    use crate::streaming::RawStreamingChoice;
+
+    #[derive(Clone)]
+    struct MockModel;
+
+    impl CompletionModel for MockModel {
+        type Response = ();
+        type StreamingResponse = ();
+
+        async fn completion(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<CompletionResponse<()>, CompletionError> {
+            Err(CompletionError::provider("unsupported"))
+        }
+
+        async fn stream(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<StreamingCompletionResponse<()>, CompletionError> {
+            let chunks = vec![
+                Ok(RawStreamingChoice::Message("ok".into())),
+                Ok(RawStreamingChoice::FinalResponse(())),
+            ];
+            Ok(StreamingCompletionResponse::stream(Box::pin(
+                futures::stream::iter(chunks),
+            )))
+        }
+    }
+
+    fn pool(strategy: BalanceStrategy) -> LoadBalancedCompletionModel<'static> {
+        ["a", "b", "c"].into_iter().fold(
+            LoadBalancedCompletionModel::new(strategy),
+            |pool, label| {
+                pool.member(
+                    label,
+                    CompletionModelHandle {
+                        inner: Arc::new(MockModel),
+                    },
+                )
+            },
+        )
+    }
+
+    #[test]
+    fn round_robin_cycles_members() {
+        let pool = pool(BalanceStrategy::RoundRobin);
+        let labels: Vec<String> = (0..4)
+            .map(|_| pool.pick().unwrap().0.label.clone())
+            .collect();
+        assert_eq!(labels, vec!["a", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn least_in_flight_avoids_busy_members() {
+        let pool = pool(BalanceStrategy::LeastInFlight);
+        let (_, first) = pool.pick().unwrap();
+        let (_, second) = pool.pick().unwrap();
+        let (third, _guard) = pool.pick().unwrap();
+        assert_eq!(third.label, "c");
+        drop(first);
+        drop(second);
+        assert_eq!(pool.in_flight().iter().filter(|(_, n)| *n == 0).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn streams_stay_in_flight_until_dropped() {
+        let pool = pool(BalanceStrategy::RoundRobin);
+        let request = CompletionRequest {
+            preamble: None,
+            chat_history: OneOrMany::one("hello".into()),
+            documents: Vec::new(),
+            tools: Vec::new(),
+            temperature: None,
+            max_tokens: None,
+            sampling: Default::default(),
+            think: None,
+            stop: vec![],
+            tool_choice: None,
+            additional_params: None,
+        };
+
+        let mut stream = pool.stream(request).await.unwrap();
+        assert_eq!(pool.in_flight()[0].1, 1);
+        while stream.next().await.is_some() {}
+        drop(stream);
+        assert_eq!(pool.in_flight()[0].1, 0);
+    }
+}
@@ -0,0 +1,341 @@
+//! A scripted provider for tests.
+//!
+//! Only compiled with the `mock` feature. [MockClient] implements [ProviderClient], so it can be
+//! registered in a client builder like a real provider and needs no server or API key. Each
+//! model name has its own [MockScript] of queued responses: a completion pops the next one, and
+//! answers with the text of the last user message once the queue is empty. Embeddings are
+//! deterministic vectors derived from a hash of the text.
+//!
+//! ```rust,ignore
+//! let script = rig::client::mock::script("planner");
+//! script.push_tool_call("add", json!({"x": 1, "y": 2}));
+//! script.push_text("3");
+//! let agent = MockClient::new().agent("planner").build();
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use futures::stream;
+use serde_json::Value;
+
+use crate::OneOrMany;
+use crate::client::{
+    AgentConfig, ClientBuilderError, CompletionClient, EmbeddingsClient, ProviderClient,
+    VerifyClient, VerifyError,
+};
+use crate::completion::message::UserContent;
+use crate::completion::{
+    AssistantContent, CompletionError, CompletionModel, CompletionRequest, CompletionResponse,
+    Message, Usage,
+};
+use crate::embeddings::{Embedding, EmbeddingError, EmbeddingModel};
+use crate::streaming::{RawStreamingChoice, StreamingCompletionResponse};
+
+/// Number of dimensions of mock embeddings when not given.
+pub const DEFAULT_NDIMS: usize = 8;
+
+/// A scripted response.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockResponse {
+    Text(String),
+    ToolCall {
+        name: String,
+        arguments: Value,
+    },
+    /// Fail the request with a provider error.
+    Error(String),
+}
+
+/// The queued responses of a model and the requests it received.
+#[derive(Debug, Default)]
+pub struct MockScript {
+    responses: Mutex<VecDeque<MockResponse>>,
+    requests: Mutex<Vec<CompletionRequest>>,
+}
+
+impl MockScript {
+    pub fn push(&self, response: MockResponse) {
+        self.responses
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(response);
+    }
+
+    pub fn push_text(&self, text: impl Into<String>) {
+        self.push(MockResponse::Text(text.into()));
+    }
+
+    pub fn push_tool_call(&self, name: impl Into<String>, arguments: Value) {
+        self.push(MockResponse::ToolCall {
+            name: name.into(),
+            arguments,
+        });
+    }
+
+    pub fn push_error(&self, message: impl Into<String>) {
+        self.push(MockResponse::Error(message.into()));
+    }
+
+    /// Requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<CompletionRequest> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Drop queued responses and received requests.
+    pub fn reset(&self) {
+        self.responses
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    fn next(&self, request: &CompletionRequest) -> MockResponse {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(request.clone());
+        self.responses
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front()
+            .unwrap_or_else(|| MockResponse::Text(last_user_text(request)))
+    }
+}
+
+/// The script of a model, shared by every mock client.
+pub fn script(model: &str) -> Arc<MockScript> {
+    static SCRIPTS: OnceLock<Mutex<HashMap<String, Arc<MockScript>>>> = OnceLock::new();
+    SCRIPTS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(model.to_string())
+        .or_default()
+        .clone()
+}
+
+fn last_user_text(request: &CompletionRequest) -> String {
+    request
+        .chat_history
+        .iter()
+        .filter_map(|message| match message {
+            Message::User { content } => content.iter().find_map(|content| match content {
+                UserContent::Text(text) => Some(text.text.clone()),
+                _ => None,
+            }),
+            _ => None,
+        })
+        .last()
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MockClient;
+
+impl MockClient {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl ProviderClient for MockClient {
+    fn from_config(_config: AgentConfig) -> Result<Box<dyn ProviderClient>, ClientBuilderError> {
+        Ok(Box::new(Self::new()))
+    }
+}
+
+impl VerifyClient for MockClient {
+    async fn verify(&self) -> Result<(), VerifyError> {
+        Ok(())
+    }
+}
+
+impl CompletionClient for MockClient {
+    type CompletionModel = MockCompletionModel;
+
+    fn completion_model(&self, model: &str) -> MockCompletionModel {
+        MockCompletionModel {
+            script: script(model),
+        }
+    }
+}
+
+impl EmbeddingsClient for MockClient {
+    type EmbeddingModel = MockEmbeddingModel;
+
+    fn embedding_model(&self, _model: &str) -> MockEmbeddingModel {
+        MockEmbeddingModel {
+            ndims: DEFAULT_NDIMS,
+        }
+    }
+
+    fn embedding_model_with_ndims(&self, _model: &str, ndims: usize) -> MockEmbeddingModel {
+        MockEmbeddingModel { ndims }
+    }
+}
+
+/// A completion model answering from its [MockScript].
+#[derive(Debug, Clone)]
+pub struct MockCompletionModel {
+    script: Arc<MockScript>,
+}
+
+impl MockCompletionModel {
+    pub fn script(&self) -> &Arc<MockScript> {
+        &self.script
+    }
+}
+
+impl CompletionModel for MockCompletionModel {
+    type Response = ();
+    type StreamingResponse = ();
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<()>, CompletionError> {
+        let choice = match self.script.next(&request) {
+            MockResponse::Text(text) => AssistantContent::text(text),
+            MockResponse::ToolCall { name, arguments } => {
+                let id = format!("call_{}", self.script.requests().len());
+                AssistantContent::tool_call(id, name, arguments)
+            }
+            MockResponse::Error(message) => return Err(CompletionError::provider(message)),
+        };
+        Ok(CompletionResponse {
+            choice: OneOrMany::one(choice),
+            usage: Usage::new(),
+            logprobs: None,
+            timings: None,
+            served_by: None,
+            raw_response: (),
+        })
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<()>, CompletionError> {
+        let chunk = match self.completion(request).await?.choice.first() {
+            AssistantContent::ToolCall(call) => RawStreamingChoice::ToolCall {
+                id: call.id,
+                call_id: call.call_id,
+                name: call.function.name,
+                arguments: call.function.arguments,
+            },
+            AssistantContent::Text(text) => RawStreamingChoice::Message(text.text),
+            _ => RawStreamingChoice::Message(String::new()),
+        };
+        let chunks = vec![Ok(chunk), Ok(RawStreamingChoice::FinalResponse(()))];
+        Ok(StreamingCompletionResponse::stream(Box::pin(stream::iter(
+            chunks,
+        ))))
+    }
+}
+
+/// An embedding model returning the same unit vector for the same text.
+#[derive(Debug, Clone)]
+pub struct MockEmbeddingModel {
+    ndims: usize,
+}
+
+impl MockEmbeddingModel {
+    fn embed(&self, text: &str) -> Vec<f64> {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in text.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        let vec: Vec<f64> = (0..self.ndims)
+            .map(|_| {
+                // xorshift, seeded by the hash of the text
+                hash ^= hash << 13;
+                hash ^= hash >> 7;
+                hash ^= hash << 17;
+                (hash % 2000) as f64 / 1000.0 - 1.0
+            })
+            .collect();
+        let norm = vec.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            return vec;
+        }
+        vec.into_iter().map(|x| x / norm).collect()
+    }
+}
+
+impl EmbeddingModel for MockEmbeddingModel {
+    const MAX_DOCUMENTS: usize = 1024;
+
+    fn ndims(&self) -> usize {
+        self.ndims
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        Ok(texts
+            .into_iter()
+            .map(|document| Embedding {
+                vec: self.embed(&document),
+                document,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::completion::Prompt;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn scripted_responses_and_embeddings() {
+        let client = MockClient::new();
+        let script = script("mock-test");
+        script.push_tool_call("add", json!({"x": 1, "y": 2}));
+        script.push_error("overloaded");
+        let model = client.completion_model("mock-test");
+
+        let request = CompletionRequest {
+            preamble: None,
+            chat_history: OneOrMany::one(Message::user("1 + 2")),
+            documents: vec![],
+            tools: vec![],
+            temperature: None,
+            max_tokens: None,
+            sampling: Default::default(),
+            think: None,
+            stop: vec![],
+            tool_choice: None,
+            additional_params: None,
+        };
+        let response = model.completion(request.clone()).await.unwrap();
+        assert!(matches!(
+            response.choice.first(),
+            AssistantContent::ToolCall(call) if call.function.name == "add"
+        ));
+        assert!(model.completion(request).await.is_err());
+
+        let agent = client.agent("mock-test").build();
+        assert_eq!(agent.prompt("echo me").await.unwrap(), "echo me");
+        assert_eq!(script.requests().len(), 3);
+
+        let embeddings = client.embedding_model("any");
+        let a = embeddings.embed_text("hello").await.unwrap();
+        let b = embeddings.embed_text("hello").await.unwrap();
+        let c = embeddings.embed_text("world").await.unwrap();
+        assert_eq!(a.vec, b.vec);
+        assert_ne!(a.vec, c.vec);
+        assert_eq!(a.vec.len(), DEFAULT_NDIMS);
+    }
+}
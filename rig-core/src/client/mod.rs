@@ -2,14 +2,32 @@
 //! Clients are used to create models for completion, embeddings, etc.
 //! Dyn-compatible traits have been provided to allow for more provider-agnostic code.
 
+pub mod cache;
 pub mod completion;
 pub mod embeddings;
+pub mod fallback;
+pub mod load_balance;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod rate_limit;
+pub mod secret_scan;
+pub mod size_limit;
+pub mod timeout;
+pub mod transport;
 pub mod verify;
 
 #[cfg(feature = "derive")]
 pub use rig_derive::ProviderClient;
+use load_balance::BalanceStrategy;
+use rate_limit::RateLimit;
+use size_limit::SizeLimits;
+use timeout::Timeouts;
+use transport::{ProxyConfig, Transport};
+use crate::telemetry::redact::RedactionConfig;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -23,6 +41,8 @@ pub enum ClientBuilderError {
     ),
     #[error("invalid property: {0}")]
     InvalidProperty(&'static str),
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
 }
 
 #[derive(Clone, Deserialize)]
@@ -33,6 +53,9 @@ pub struct McpStdio {
     pub args: Vec<String>,
     // 必须是相对路径，绝对路径不能超过 cargo manifest  rutime currentdir。
     pub path: Option<String>,
+    /// 暴露给mcp服务的 roots 目录，例如 ["./workspace/task-42"]，文件类的mcp服务只能访问这些目录。
+    #[serde(default)]
+    pub roots: Vec<String>,
 }
 /// McpType : 理论是上resource 应当是配置类型，当是stdio 形态的时候应当由args统一进行设定。
 /// roots: 再这个client中应当是默认的 特定workspace中，应当再切换版本时进行指定。
@@ -61,6 +84,110 @@ pub struct AgentConfig {
     // todo 认证系统。主要针对可能得大模型
     // pub auth_map: Option<HashMap<String, Option<String>>>,
     pub mcp: McpType,
+    /// 主模型出现可重试错误时，按顺序尝试的备用模型。
+    #[serde(default)]
+    pub fallback: Vec<FallbackTarget>,
+    /// 同一个provider的多个实例地址，不为空时请求会在这些地址之间负载均衡。
+    #[serde(default)]
+    pub pool: Vec<String>,
+    #[serde(default)]
+    pub pool_strategy: BalanceStrategy,
+    /// 限流配置，为空时使用 provider 的默认配置。
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    /// 原生工具名称，需要先在工具注册表中注册。
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// 本地模型占用的显存，MB，用于显存感知调度。
+    #[serde(default)]
+    pub vram_mb: Option<u64>,
+    /// 内置http工具允许访问的域名，`*.example.com` 匹配所有子域名。
+    #[serde(default)]
+    pub http_allowlist: Vec<String>,
+    /// 内置http工具的最大响应大小，字节。
+    #[serde(default)]
+    pub http_max_response_bytes: Option<usize>,
+    /// 请求大小限制，为空时使用 provider 的默认配置。
+    #[serde(default)]
+    pub size_limit: Option<SizeLimits>,
+    /// span 和日志的脱敏配置，为空时使用全局配置。
+    #[serde(default)]
+    pub redaction: Option<RedactionConfig>,
+    /// 一次 prompt 中工具调用的最大轮数，为空时不允许多轮。
+    #[serde(default)]
+    pub max_turns: Option<usize>,
+    /// 同一个工具以相同参数连续调用达到该次数时中止 prompt，为空时不检测。
+    #[serde(default)]
+    pub tool_loop_limit: Option<usize>,
+    /// 请求超时，秒。流式请求按等待响应和每个数据块的间隔计算，为空时不限制。
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// 建立连接的超时，秒。
+    #[serde(default)]
+    pub connect_timeout: Option<u64>,
+    /// 出站代理，支持 http / https / socks5。
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+    /// 额外信任的根证书，PEM 文件路径，用于自签名证书的内部网关。
+    #[serde(default)]
+    pub root_certificates: Vec<String>,
+    /// 每个请求额外附带的请求头，例如网关的认证或组织信息。
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// 推理模型是否先思考再回答，为空时使用模型的默认行为。
+    /// ollama 对应请求的 `think` 参数，deepseek 在 chat 和 reasoner 模型之间切换。
+    #[serde(default)]
+    pub think: Option<bool>,    /// 同时进行的生成数上限，超过上限的请求排队等待，为空时不限制。
+    /// 本地 ollama 通常只能同时服务 1 到 2 个生成。
+    #[serde(default)]
+    pub max_concurrency: Option<usize>,
+    /// 每次请求前按prompt检索的few-shot示例数，附加在系统提示之后，为空时不检索。
+    #[serde(default)]
+    pub few_shot: Option<usize>,
+}
+
+impl AgentConfig {
+    /// provider client 的超时配置
+    pub fn timeouts(&self) -> Timeouts {
+        Timeouts {
+            request: self.timeout.map(Duration::from_secs),
+            connect: self.connect_timeout.map(Duration::from_secs),
+        }
+    }
+
+    /// 额外请求头，名称或值不合法时返回错误
+    pub fn header_map(&self) -> Result<reqwest::header::HeaderMap, ClientBuilderError> {
+        self.headers
+            .iter()
+            .map(|(name, value)| {
+                Ok((
+                    name.parse().map_err(|_| ClientBuilderError::InvalidProperty("headers"))?,
+                    value.parse().map_err(|_| ClientBuilderError::InvalidProperty("headers"))?,
+                ))
+            })
+            .collect()
+    }
+
+    /// provider client 的代理和根证书，证书文件在这里读取
+    pub fn transport(&self) -> Result<Transport, ClientBuilderError> {
+        Ok(Transport {
+            proxy: self.proxy.as_ref().map(ProxyConfig::to_proxy).transpose()?,
+            root_certificates: self
+                .root_certificates
+                .iter()
+                .map(transport::load_certificate)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// 备用模型配置，base_url / api_key 为空时沿用主配置。
+#[derive(Clone, Debug, Deserialize)]
+pub struct FallbackTarget {
+    pub provider: String,
+    pub model: String,
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
 }
 
 /// The base ProviderClient trait, facilitates conversion between client types
@@ -68,10 +195,10 @@ pub struct AgentConfig {
 ///
 /// All conversion traits must be implemented, they are automatically
 /// implemented if the respective client trait is implemented.
-pub trait ProviderClient: AsCompletion + AsEmbeddings + Debug {
-    /// Create a client from the process's environment.
-    /// Panics if an environment is improperly configured.
-    fn from_config(config: AgentConfig) -> Box<dyn ProviderClient>
+pub trait ProviderClient: AsCompletion + AsEmbeddings + AsVerify + Debug {
+    /// Create a client from an agent's configuration.
+    /// Returns an error if the configuration is invalid, e.g. a malformed header or proxy.
+    fn from_config(config: AgentConfig) -> Result<Box<dyn ProviderClient>, ClientBuilderError>
     where
         Self: Sized;
 }
@@ -0,0 +1,253 @@
+//! Token-bucket rate limiting for provider clients.
+//!
+//! A [RateLimiter] enforces a [RateLimit] (requests and/or tokens per minute). Completion and
+//! embedding models can be wrapped with [RateLimitedCompletionModel] and
+//! [RateLimitedEmbeddingModel] so every call waits for capacity instead of failing with a
+//! provider rate-limit error. Cloned limiters share the same buckets, so one limiter can be
+//! shared by every model talking to the same provider account.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::client::completion::CompletionModelHandle;
+use crate::completion::{CompletionError, CompletionModel, CompletionRequest, CompletionResponse};
+use crate::embeddings::{Embedding, EmbeddingError, EmbeddingModel};
+use crate::streaming::{StreamUsage, StreamingCompletionResponse};
+use crate::tokens::{Approximate, TokenCounter};
+
+/// Rate limit configuration. Unset fields are not limited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub struct RateLimit {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn per_minute(limit: u32) -> Self {
+        let capacity = limit.max(1) as f64;
+        Self {
+            capacity,
+            available: capacity,
+            refill_per_sec: capacity / 60.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.available = (self.available + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Time to wait until `amount` is available. Requests larger than the bucket only wait for a
+    /// full bucket, otherwise they could never be served.
+    fn wait_for(&self, amount: f64) -> Duration {
+        let amount = amount.min(self.capacity);
+        if self.available >= amount {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((amount - self.available) / self.refill_per_sec)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+}
+
+/// A shared token-bucket rate limiter.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Arc::new(Mutex::new(Buckets {
+                requests: limit.requests_per_minute.map(Bucket::per_minute),
+                tokens: limit.tokens_per_minute.map(Bucket::per_minute),
+            })),
+        }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Wait until one request using an estimated `tokens` tokens may be sent, then take the
+    /// capacity from the buckets.
+    pub async fn acquire(&self, tokens: u64) {
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().await;
+                let now = Instant::now();
+                let mut wait = Duration::ZERO;
+                if let Some(bucket) = buckets.requests.as_mut() {
+                    bucket.refill(now);
+                    wait = wait.max(bucket.wait_for(1.0));
+                }
+                if let Some(bucket) = buckets.tokens.as_mut() {
+                    bucket.refill(now);
+                    wait = wait.max(bucket.wait_for(tokens as f64));
+                }
+
+                if wait.is_zero() {
+                    if let Some(bucket) = buckets.requests.as_mut() {
+                        bucket.available -= 1.0;
+                    }
+                    if let Some(bucket) = buckets.tokens.as_mut() {
+                        bucket.available -= (tokens as f64).min(bucket.capacity);
+                    }
+                    return;
+                }
+                wait
+            };
+
+            tracing::debug!(target: "rig", "rate limited, waiting {}ms", wait.as_millis());
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Correct the token bucket once the real usage of a request is known. The bucket may go
+    /// negative when a request used more tokens than estimated, delaying the following requests.
+    pub async fn settle(&self, estimated: u64, actual: u64) {
+        let mut buckets = self.buckets.lock().await;
+        if let Some(bucket) = buckets.tokens.as_mut() {
+            bucket.available =
+                (bucket.available + estimated as f64 - actual as f64).min(bucket.capacity);
+        }
+    }
+}
+
+/// A completion model whose calls pass through a [RateLimiter]. Requests reserve their
+/// estimated tokens up front, settled with the reported usage when the response arrives.
+#[derive(Clone)]
+pub struct RateLimitedCompletionModel<'a> {
+    inner: CompletionModelHandle<'a>,
+    limiter: RateLimiter,
+    counter: Arc<dyn TokenCounter>,
+}
+
+impl<'a> RateLimitedCompletionModel<'a> {
+    pub fn new(inner: CompletionModelHandle<'a>, limiter: RateLimiter) -> Self {
+        Self {
+            inner,
+            limiter,
+            counter: Arc::new(Approximate),
+        }
+    }
+
+    /// Estimate tokens with the tokenizer of the model, see [crate::tokens::counter_for].
+    pub fn with_counter(mut self, counter: Arc<dyn TokenCounter>) -> Self {
+        self.counter = counter;
+        self
+    }
+}
+
+impl CompletionModel for RateLimitedCompletionModel<'_> {
+    type Response = ();
+    type StreamingResponse = StreamUsage;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let estimated = self.counter.count_request(&request);
+        self.limiter.acquire(estimated).await;
+        let response = self.inner.completion(request).await?;
+        self.limiter
+            .settle(estimated, response.usage.total_tokens)
+            .await;
+        Ok(response)
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        self.limiter
+            .acquire(self.counter.count_request(&request))
+            .await;
+        self.inner.stream(request).await
+    }
+}
+
+/// An embedding model whose calls pass through a [RateLimiter].
+#[derive(Clone)]
+pub struct RateLimitedEmbeddingModel<M> {
+    inner: M,
+    limiter: RateLimiter,
+    counter: Arc<dyn TokenCounter>,
+}
+
+impl<M: EmbeddingModel> RateLimitedEmbeddingModel<M> {
+    pub fn new(inner: M, limiter: RateLimiter) -> Self {
+        Self {
+            inner,
+            limiter,
+            counter: Arc::new(Approximate),
+        }
+    }
+
+    /// Estimate tokens with the tokenizer of the model, see [crate::tokens::counter_for].
+    pub fn with_counter(mut self, counter: Arc<dyn TokenCounter>) -> Self {
+        self.counter = counter;
+        self
+    }
+}
+
+impl<M: EmbeddingModel> EmbeddingModel for RateLimitedEmbeddingModel<M> {
+    const MAX_DOCUMENTS: usize = M::MAX_DOCUMENTS;
+
+    fn ndims(&self) -> usize {
+        self.inner.ndims()
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<Embedding>, EmbeddingError> {
+        let texts: Vec<String> = texts.into_iter().collect();
+        let estimated = texts.iter().map(|t| self.counter.count(t)).sum();
+        self.limiter.acquire(estimated).await;
+        self.inner.embed_texts(texts).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_when_requests_exhausted() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_minute: Some(2),
+            tokens_per_minute: None,
+        });
+
+        let start = tokio::time::Instant::now();
+        limiter.acquire(0).await;
+        limiter.acquire(0).await;
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        limiter.acquire(0).await;
+        assert!(start.elapsed() >= Duration::from_secs(29));
+    }
+}
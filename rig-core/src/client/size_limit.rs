@@ -0,0 +1,394 @@
+//! Pre-flight request size checks.
+//!
+//! Providers reject oversized requests with errors that are hard to act on (a bare 400, a
+//! truncated prompt on Ollama). [SizeLimits] estimates the request body size and prompt tokens
+//! before sending and fails fast with a [ProviderErrorKind::ContextLengthExceeded] error. With
+//! `truncate_history` the oldest chat history is dropped until the request fits instead.
+//!
+//! Models of one provider often have different context windows, so `context_windows` caps
+//! `max_prompt_tokens` per model name (see [SizeLimits::for_model]). With `summarize_documents`,
+//! [SizeLimitedCompletionModel] first asks the model itself to summarize the largest static
+//! context documents, largest first, before any history is dropped. A summary that fails or is
+//! still too long is truncated to the size the request needs to lose.
+//!
+//! Tokens are counted with a [TokenCounter] from [crate::tokens], [Approximate] unless
+//! [SizeLimitedCompletionModel::with_counter] sets the counter of the model.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::OneOrMany;
+use crate::client::completion::CompletionModelHandle;
+use crate::completion::{
+    AssistantContent, CompletionError, CompletionModel, CompletionRequest, CompletionResponse,
+    Message, ProviderErrorKind,
+};
+use crate::streaming::{StreamUsage, StreamingCompletionResponse};
+use crate::tokens::{Approximate, TokenCounter};
+
+pub use crate::tokens::estimate_tokens;
+
+/// Request size limits of a provider. Unset fields are not checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeLimits {
+    /// Maximum size of the serialized request, in bytes.
+    #[serde(default)]
+    pub max_request_bytes: Option<usize>,
+    /// Maximum estimated prompt tokens, including the requested `max_tokens` of output.
+    #[serde(default)]
+    pub max_prompt_tokens: Option<u64>,
+    /// Drop the oldest chat history messages until the request fits instead of failing.
+    #[serde(default)]
+    pub truncate_history: bool,
+    /// Summarize the largest static context documents with the model before dropping history.
+    #[serde(default)]
+    pub summarize_documents: bool,
+    /// Context windows in tokens by model name, capping `max_prompt_tokens` for that model.
+    #[serde(default)]
+    pub context_windows: BTreeMap<String, u64>,
+}
+
+/// Estimated size of a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RequestSize {
+    pub bytes: usize,
+    pub tokens: u64,
+}
+
+/// Estimate the size of the parts of a request that are sent to the provider.
+pub fn estimate_request(request: &CompletionRequest) -> RequestSize {
+    estimate_request_with(request, &Approximate)
+}
+
+/// Estimate the size of a request, counting tokens with `counter`.
+pub fn estimate_request_with(
+    request: &CompletionRequest,
+    counter: &dyn TokenCounter,
+) -> RequestSize {
+    let body = serde_json::json!({
+        "preamble": request.preamble,
+        "messages": request.chat_history,
+        "documents": request.documents,
+        "tools": request.tools,
+    })
+    .to_string();
+    RequestSize {
+        bytes: body.len(),
+        tokens: counter.count(&body) + request.max_tokens.unwrap_or(0),
+    }
+}
+
+/// The longest prefix of a text with at most `tokens` tokens.
+fn truncate_to_tokens(text: &str, tokens: u64, counter: &dyn TokenCounter) -> String {
+    let ends: Vec<usize> = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([text.len()])
+        .collect();
+    let (mut fits, mut too_long) = (0, ends.len());
+    while too_long - fits > 1 {
+        let mid = (fits + too_long) / 2;
+        if counter.count(&text[..ends[mid]]) <= tokens {
+            fits = mid;
+        } else {
+            too_long = mid;
+        }
+    }
+    text[..ends[fits]].to_string()
+}
+
+impl SizeLimits {
+    pub fn is_empty(&self) -> bool {
+        self.max_request_bytes.is_none() && self.max_prompt_tokens.is_none()
+    }
+
+    /// The limits for one model: its context window, if configured, caps `max_prompt_tokens`.
+    pub fn for_model(&self, model: &str) -> SizeLimits {
+        let window = self.context_windows.get(model).copied();
+        SizeLimits {
+            max_prompt_tokens: match (self.max_prompt_tokens, window) {
+                (Some(max), Some(window)) => Some(max.min(window)),
+                (max, window) => max.or(window),
+            },
+            context_windows: BTreeMap::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Tokens the request has to lose to fit `max_prompt_tokens`.
+    fn excess_tokens(&self, request: &CompletionRequest, counter: &dyn TokenCounter) -> u64 {
+        self.max_prompt_tokens.map_or(0, |max| {
+            estimate_request_with(request, counter)
+                .tokens
+                .saturating_sub(max)
+        })
+    }
+
+    /// Check the request against the limits.
+    pub fn check(&self, request: &CompletionRequest) -> Result<RequestSize, CompletionError> {
+        self.check_with(request, &Approximate)
+    }
+
+    /// Check the request against the limits, counting tokens with `counter`.
+    pub fn check_with(
+        &self,
+        request: &CompletionRequest,
+        counter: &dyn TokenCounter,
+    ) -> Result<RequestSize, CompletionError> {
+        let size = estimate_request_with(request, counter);
+        if let Some(max) = self.max_request_bytes.filter(|max| size.bytes > *max) {
+            return Err(context_length_exceeded(format!(
+                "request body of {} bytes exceeds the limit of {max} bytes",
+                size.bytes
+            )));
+        }
+        if let Some(max) = self.max_prompt_tokens.filter(|max| size.tokens > *max) {
+            return Err(context_length_exceeded(format!(
+                "request of about {} tokens exceeds the limit of {max} tokens",
+                size.tokens
+            )));
+        }
+        Ok(size)
+    }
+
+    /// Check the request, dropping the oldest chat history when `truncate_history` is set.
+    /// The prompt (the last message) is never dropped.
+    pub fn fit(&self, request: CompletionRequest) -> Result<CompletionRequest, CompletionError> {
+        self.fit_with(request, &Approximate)
+    }
+
+    /// [SizeLimits::fit], counting tokens with `counter`.
+    pub fn fit_with(
+        &self,
+        mut request: CompletionRequest,
+        counter: &dyn TokenCounter,
+    ) -> Result<CompletionRequest, CompletionError> {
+        let mut dropped = 0;
+        loop {
+            match self.check_with(&request, counter) {
+                Ok(_) => {
+                    if dropped > 0 {
+                        tracing::warn!("dropped {dropped} history messages to fit the size limits");
+                    }
+                    return Ok(request);
+                }
+                Err(e) if !self.truncate_history || request.chat_history.len() <= 1 => {
+                    return Err(e);
+                }
+                Err(_) => {
+                    let messages = request.chat_history.rest();
+                    request.chat_history =
+                        OneOrMany::many(messages).expect("history has more than one message");
+                    dropped += 1;
+                }
+            }
+        }
+    }
+}
+
+fn context_length_exceeded(message: String) -> CompletionError {
+    CompletionError::Provider {
+        kind: ProviderErrorKind::ContextLengthExceeded,
+        message,
+    }
+}
+
+/// A completion model that checks every request against [SizeLimits] before sending it.
+#[derive(Clone)]
+pub struct SizeLimitedCompletionModel<'a> {
+    inner: CompletionModelHandle<'a>,
+    limits: SizeLimits,
+    counter: Arc<dyn TokenCounter>,
+}
+
+impl<'a> SizeLimitedCompletionModel<'a> {
+    pub fn new(inner: CompletionModelHandle<'a>, limits: SizeLimits) -> Self {
+        Self {
+            inner,
+            limits,
+            counter: Arc::new(Approximate),
+        }
+    }
+
+    /// Count tokens with the tokenizer of the model, see [crate::tokens::counter_for].
+    pub fn with_counter(mut self, counter: Arc<dyn TokenCounter>) -> Self {
+        self.counter = counter;
+        self
+    }
+
+    /// Summarize documents when enabled, then check the request with [SizeLimits::fit].
+    async fn fit(
+        &self,
+        mut request: CompletionRequest,
+    ) -> Result<CompletionRequest, CompletionError> {
+        if self.limits.summarize_documents {
+            let mut order: Vec<usize> = (0..request.documents.len()).collect();
+            order.sort_by_key(|i| std::cmp::Reverse(request.documents[*i].text.len()));
+            for i in order {
+                if self.limits.excess_tokens(&request, self.counter.as_ref()) == 0 {
+                    break;
+                }
+                request.documents[i]
+                    .additional_props
+                    .insert("summarized".to_string(), "true".to_string());
+                let excess = self.limits.excess_tokens(&request, self.counter.as_ref());
+                let doc = &mut request.documents[i];
+                let tokens = self.counter.count(&doc.text);
+                let target = tokens.saturating_sub(excess);
+                // Truncate when the summary fails or is still too long
+                let summary = match self.summarize(&doc.text, target).await {
+                    Ok(summary) => summary,
+                    Err(e) => {
+                        tracing::warn!("failed to summarize document {}: {e}", doc.id);
+                        doc.text.clone()
+                    }
+                };
+                doc.text = truncate_to_tokens(&summary, target, self.counter.as_ref());
+                tracing::warn!("shrank document {} from about {tokens} tokens", doc.id);
+            }
+        }
+        self.limits.fit_with(request, self.counter.as_ref())
+    }
+
+    /// Ask the model for a summary of at most about `tokens` tokens.
+    async fn summarize(&self, text: &str, tokens: u64) -> Result<String, CompletionError> {
+        let request = CompletionRequest {
+            preamble: Some(format!(
+                "Summarize the document in at most {tokens} tokens. Keep the names, numbers and \
+                 facts needed to answer questions about it. Reply with the summary only."
+            )),
+            chat_history: OneOrMany::one(Message::user(text)),
+            documents: vec![],
+            tools: vec![],
+            temperature: None,
+            max_tokens: Some(tokens.max(1)),
+            sampling: Default::default(),
+            think: None,
+            stop: vec![],
+            tool_choice: None,
+            additional_params: None,
+        };
+        let response = self.inner.completion(request).await?;
+        Ok(response
+            .choice
+            .iter()
+            .filter_map(|content| match content {
+                AssistantContent::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+impl CompletionModel for SizeLimitedCompletionModel<'_> {
+    type Response = ();
+    type StreamingResponse = StreamUsage;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let request = self.fit(request).await?;
+        self.inner.completion(request).await
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        let request = self.fit(request).await?;
+        self.inner.stream(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::client::CompletionClient;
+    use crate::client::mock::{self, MockClient};
+    use crate::completion::Document;
+
+    fn request(messages: Vec<Message>) -> CompletionRequest {
+        CompletionRequest {
+            preamble: None,
+            chat_history: OneOrMany::many(messages).unwrap(),
+            documents: vec![],
+            tools: vec![],
+            temperature: None,
+            max_tokens: None,
+            sampling: Default::default(),
+            think: None,
+            stop: vec![],
+            tool_choice: None,
+            additional_params: None,
+        }
+    }
+
+    #[test]
+    fn fails_fast_or_truncates_history() {
+        let history = vec![
+            Message::user("a".repeat(400)),
+            Message::assistant("b".repeat(400)),
+            Message::user("summarize"),
+        ];
+        let limits = SizeLimits {
+            max_prompt_tokens: Some(150),
+            ..Default::default()
+        };
+        let err = limits.fit(request(history.clone())).unwrap_err();
+        assert_eq!(
+            err.provider_kind(),
+            Some(&ProviderErrorKind::ContextLengthExceeded)
+        );
+
+        let limits = SizeLimits {
+            truncate_history: true,
+            ..limits
+        };
+        let fitted = limits.fit(request(history)).unwrap();
+        assert_eq!(fitted.chat_history.len(), 2);
+        assert_eq!(estimate_tokens("订单abcd"), 3);
+    }
+
+    #[tokio::test]
+    async fn oversized_documents_are_summarized_per_model_window() {
+        let script = mock::script("size-summary");
+        script.push_text("q3 revenue was 42");
+        script.push_text("42");
+        script.push_error("summary failed");
+        script.push_text("42");
+        let limits = SizeLimits {
+            summarize_documents: true,
+            context_windows: BTreeMap::from([("size-summary".to_string(), 300)]),
+            ..Default::default()
+        };
+        let model = SizeLimitedCompletionModel::new(
+            CompletionModelHandle {
+                inner: Arc::new(MockClient::new().completion_model("size-summary")),
+            },
+            limits.for_model("size-summary"),
+        );
+        assert_eq!(limits.for_model("other").max_prompt_tokens, None);
+
+        let mut with_document = request(vec![Message::user("what was q3 revenue?")]);
+        with_document.documents = vec![Document {
+            id: "report".to_string(),
+            text: "x".repeat(4000),
+            additional_props: Default::default(),
+        }];
+        model.completion(with_document.clone()).await.unwrap();
+        let sent = &script.requests()[1];
+        assert_eq!(sent.documents[0].text, "q3 revenue was 42");
+        assert_eq!(sent.documents[0].additional_props["summarized"], "true");
+
+        model.completion(with_document).await.unwrap();
+        let sent = &script.requests()[3];
+        assert!(estimate_request(sent).tokens <= 300);
+        assert!(!sent.documents[0].text.is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+//! Request timeouts of provider clients.
+//!
+//! `connect` limits establishing the connection and is set on the reqwest client. `request`
+//! limits a whole completion or embedding call. Streams may run for minutes, so for them
+//! `request` limits the wait for the response and for every following chunk instead: a stream
+//! only times out once the provider goes quiet.
+
+use std::future::Future;
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+
+use crate::completion::{CompletionError, ProviderErrorKind};
+
+/// Timeouts of a provider client. Unset timeouts do not limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    pub request: Option<Duration>,
+    pub connect: Option<Duration>,
+}
+
+impl Timeouts {
+    /// A reqwest client builder with the connect timeout set.
+    pub fn client_builder(&self) -> reqwest::ClientBuilder {
+        let builder = reqwest::Client::builder();
+        match self.connect {
+            Some(connect) => builder.connect_timeout(connect),
+            None => builder,
+        }
+    }
+
+    /// Set the request timeout on a non-streaming request.
+    pub fn apply(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self.request {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        }
+    }
+
+    /// Wait for the response of a streaming request.
+    pub async fn first_byte<T, E>(
+        &self,
+        response: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, CompletionError>
+    where
+        CompletionError: From<E>,
+    {
+        match self.request {
+            Some(timeout) => match tokio::time::timeout(timeout, response).await {
+                Ok(response) => Ok(response?),
+                Err(_) => Err(timed_out("no response", timeout)),
+            },
+            None => Ok(response.await?),
+        }
+    }
+
+    /// Wait for the next chunk of a stream.
+    pub async fn next_chunk<S: Stream + Unpin>(
+        &self,
+        stream: &mut S,
+    ) -> Result<Option<S::Item>, CompletionError> {
+        match self.request {
+            Some(timeout) => tokio::time::timeout(timeout, stream.next())
+                .await
+                .map_err(|_| timed_out("stream idle", timeout)),
+            None => Ok(stream.next().await),
+        }
+    }
+}
+
+fn timed_out(what: &str, timeout: Duration) -> CompletionError {
+    CompletionError::Provider {
+        kind: ProviderErrorKind::Timeout,
+        message: format!("{what} for {}ms", timeout.as_millis()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    #[tokio::test]
+    async fn idle_stream_times_out() {
+        let timeouts = Timeouts {
+            request: Some(Duration::from_millis(20)),
+            connect: None,
+        };
+        let mut ready = stream::iter([1]);
+        assert_eq!(timeouts.next_chunk(&mut ready).await.unwrap(), Some(1));
+        assert_eq!(timeouts.next_chunk(&mut ready).await.unwrap(), None);
+
+        let mut idle = stream::pending::<u8>();
+        let err = timeouts.next_chunk(&mut idle).await.unwrap_err();
+        assert!(err.is_retryable());
+        assert_eq!(err.provider_kind(), Some(&ProviderErrorKind::Timeout));
+        let never = std::future::pending::<Result<u8, CompletionError>>();
+        assert!(timeouts.first_byte(never).await.is_err());
+    }
+}
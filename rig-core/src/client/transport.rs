@@ -0,0 +1,89 @@
+//! Proxy and TLS settings of provider clients.
+//!
+//! Provider client builders take a [reqwest::Proxy] and extra root certificates, e.g. the
+//! outbound proxy of a corporate network or the self-signed certificate of an internal gateway.
+//! [ProxyConfig] is the serializable form used in [super::AgentConfig]. `socks5://` proxies need
+//! the `socks` feature.
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::ClientBuilderError;
+
+/// An outbound proxy for all requests of a client.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ProxyConfig {
+    /// `http://`, `https://` or `socks5://` url
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl ProxyConfig {
+    pub fn to_proxy(&self) -> Result<reqwest::Proxy, ClientBuilderError> {
+        let proxy = reqwest::Proxy::all(&self.url)?;
+        Ok(match &self.username {
+            Some(username) => {
+                proxy.basic_auth(username, self.password.as_deref().unwrap_or_default())
+            }
+            None => proxy,
+        })
+    }
+}
+
+/// Load a PEM encoded root certificate.
+pub fn load_certificate(
+    path: impl AsRef<Path>,
+) -> Result<reqwest::Certificate, ClientBuilderError> {
+    let pem = std::fs::read(path.as_ref())?;
+    Ok(reqwest::Certificate::from_pem(&pem)?)
+}
+
+/// Proxy and root certificates applied to a reqwest client.
+#[derive(Debug, Clone, Default)]
+pub struct Transport {
+    pub proxy: Option<reqwest::Proxy>,
+    pub root_certificates: Vec<reqwest::Certificate>,
+}
+
+impl Transport {
+    pub fn apply(self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        if let Some(proxy) = self.proxy {
+            builder = builder.proxy(proxy);
+        }
+        for certificate in self.root_certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let proxy = ProxyConfig {
+            url: "http://proxy.corp:3128".into(),
+            username: Some("user".into()),
+            password: None,
+        };
+        let transport = Transport {
+            proxy: Some(proxy.to_proxy().unwrap()),
+            root_certificates: vec![],
+        };
+        assert!(transport.apply(reqwest::Client::builder()).build().is_ok());
+
+        let invalid = ProxyConfig {
+            url: "not a url".into(),
+            ..proxy
+        };
+        assert!(invalid.to_proxy().is_err());
+        assert!(matches!(
+            load_certificate("/nonexistent/ca.pem"),
+            Err(ClientBuilderError::IoError(_))
+        ));
+    }
+}
@@ -1,5 +1,7 @@
 pub mod message;
+pub mod provider_error;
 pub mod request;
 
 pub use message::{AssistantContent, Message, MessageError};
+pub use provider_error::ProviderErrorKind;
 pub use request::*;
@@ -0,0 +1,215 @@
+//! Structured classification of errors returned by completion model providers.
+//!
+//! Providers report failures in very different shapes (HTTP status codes, JSON error bodies,
+//! plain text). [ProviderErrorKind] normalizes them into a small set of kinds so that callers
+//! can decide whether to retry, back off or fall back to another model without matching on
+//! error strings.
+
+use std::{fmt, time::Duration};
+
+use super::CompletionError;
+
+/// The kind of failure reported by a completion model provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderErrorKind {
+    /// The provider throttled the request. `retry_after` is set when the provider told us how
+    /// long to wait.
+    RateLimited { retry_after: Option<Duration> },
+    /// The prompt (plus requested output) does not fit in the model's context window.
+    ContextLengthExceeded,
+    /// The API key is missing, invalid or lacks permissions.
+    AuthFailed,
+    /// The requested model does not exist on the provider.
+    ModelNotFound,
+    /// The provider timed out while handling the request.
+    Timeout,
+    /// The provider is temporarily unavailable or overloaded.
+    Unavailable,
+    /// Any other provider error.
+    Other,
+}
+
+impl ProviderErrorKind {
+    /// Whether a request failing with this kind may succeed if sent again unchanged.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ProviderErrorKind::RateLimited { .. }
+                | ProviderErrorKind::Timeout
+                | ProviderErrorKind::Unavailable
+        )
+    }
+
+    /// Classify a provider error from an optional HTTP status code and the error message.
+    pub fn classify(status: Option<u16>, message: &str) -> Self {
+        let lower = message.to_lowercase();
+
+        if lower.contains("context length")
+            || lower.contains("context_length")
+            || lower.contains("maximum context")
+            || lower.contains("context window")
+            || lower.contains("too many tokens")
+        {
+            return ProviderErrorKind::ContextLengthExceeded;
+        }
+
+        match status {
+            Some(429) => return ProviderErrorKind::RateLimited { retry_after: None },
+            Some(401) | Some(403) => return ProviderErrorKind::AuthFailed,
+            Some(404) if lower.contains("model") => return ProviderErrorKind::ModelNotFound,
+            Some(408) | Some(504) => return ProviderErrorKind::Timeout,
+            Some(413) => return ProviderErrorKind::ContextLengthExceeded,
+            Some(500) | Some(502) | Some(503) => return ProviderErrorKind::Unavailable,
+            _ => {}
+        }
+
+        if lower.contains("rate limit") || lower.contains("too many requests") {
+            ProviderErrorKind::RateLimited { retry_after: None }
+        } else if lower.contains("api key")
+            || lower.contains("unauthorized")
+            || lower.contains("authentication")
+        {
+            ProviderErrorKind::AuthFailed
+        } else if lower.contains("model")
+            && (lower.contains("not found") || lower.contains("does not exist"))
+        {
+            ProviderErrorKind::ModelNotFound
+        } else if lower.contains("timeout") || lower.contains("timed out") {
+            ProviderErrorKind::Timeout
+        } else if lower.contains("overloaded") || lower.contains("unavailable") {
+            ProviderErrorKind::Unavailable
+        } else {
+            ProviderErrorKind::Other
+        }
+    }
+
+    /// Parse the value of a `Retry-After` header given in seconds.
+    pub fn parse_retry_after(value: &str) -> Option<Duration> {
+        value
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|secs| secs.is_finite() && *secs >= 0.0)
+            .map(Duration::from_secs_f64)
+    }
+}
+
+impl fmt::Display for ProviderErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderErrorKind::RateLimited {
+                retry_after: Some(after),
+            } => write!(f, "RateLimited(retry after {}s)", after.as_secs_f64()),
+            ProviderErrorKind::RateLimited { retry_after: None } => write!(f, "RateLimited"),
+            ProviderErrorKind::ContextLengthExceeded => write!(f, "ContextLengthExceeded"),
+            ProviderErrorKind::AuthFailed => write!(f, "AuthFailed"),
+            ProviderErrorKind::ModelNotFound => write!(f, "ModelNotFound"),
+            ProviderErrorKind::Timeout => write!(f, "Timeout"),
+            ProviderErrorKind::Unavailable => write!(f, "Unavailable"),
+            ProviderErrorKind::Other => write!(f, "Other"),
+        }
+    }
+}
+
+impl CompletionError {
+    /// Build a classified provider error from an error message without HTTP context.
+    pub fn provider(message: impl Into<String>) -> Self {
+        let message = message.into();
+        CompletionError::Provider {
+            kind: ProviderErrorKind::classify(None, &message),
+            message,
+        }
+    }
+
+    /// Build a classified provider error from a non-successful HTTP response.
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(ProviderErrorKind::parse_retry_after);
+        let message = match response.text().await {
+            Ok(text) => text,
+            Err(err) => return CompletionError::HttpError(err),
+        };
+
+        let kind = match ProviderErrorKind::classify(Some(status), &message) {
+            ProviderErrorKind::RateLimited { .. } => ProviderErrorKind::RateLimited { retry_after },
+            kind => kind,
+        };
+        CompletionError::Provider { kind, message }
+    }
+
+    /// The classified provider error kind, if this is a provider error.
+    pub fn provider_kind(&self) -> Option<&ProviderErrorKind> {
+        match self {
+            CompletionError::Provider { kind, .. } => Some(kind),
+            _ => None,
+        }
+    }
+
+    /// Whether retrying the same request may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            CompletionError::Provider { kind, .. } => kind.is_retryable(),
+            CompletionError::HttpError(err) => err.is_timeout() || err.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// How long the provider asked us to wait before retrying, if it told us.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self.provider_kind() {
+            Some(ProviderErrorKind::RateLimited { retry_after }) => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_by_status_and_message() {
+        assert_eq!(
+            ProviderErrorKind::classify(Some(429), "slow down"),
+            ProviderErrorKind::RateLimited { retry_after: None }
+        );
+        assert_eq!(
+            ProviderErrorKind::classify(Some(401), "Authentication Fails"),
+            ProviderErrorKind::AuthFailed
+        );
+        assert_eq!(
+            ProviderErrorKind::classify(Some(404), r#"{"error":"model 'llama9' not found"}"#),
+            ProviderErrorKind::ModelNotFound
+        );
+        assert_eq!(
+            ProviderErrorKind::classify(
+                Some(400),
+                "This model's maximum context length is 65536 tokens"
+            ),
+            ProviderErrorKind::ContextLengthExceeded
+        );
+        assert_eq!(
+            ProviderErrorKind::classify(None, "Server overloaded"),
+            ProviderErrorKind::Unavailable
+        );
+        assert_eq!(
+            ProviderErrorKind::classify(Some(400), "bad request"),
+            ProviderErrorKind::Other
+        );
+    }
+
+    #[test]
+    fn retryability() {
+        assert!(CompletionError::provider("rate limit reached").is_retryable());
+        assert!(!CompletionError::provider("invalid api key").is_retryable());
+        assert_eq!(
+            ProviderErrorKind::parse_retry_after("2"),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(ProviderErrorKind::parse_retry_after("soon"), None);
+    }
+}
@@ -64,9 +64,10 @@
 //! the individual traits, structs, and enums defined in this module.
 
 use super::message::{AssistantContent, DocumentMediaType};
+use super::provider_error::ProviderErrorKind;
 use crate::client::completion::CompletionModelHandle;
 use crate::message::ToolChoice;
-use crate::streaming::StreamingCompletionResponse;
+use crate::streaming::{StreamUsage, StreamingCompletionResponse};
 use crate::{OneOrMany, streaming};
 use crate::{
     json_utils,
@@ -79,6 +80,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::ops::{Add, AddAssign};
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 
 // Errors
@@ -110,6 +112,13 @@ pub enum CompletionError {
     /// Error returned by the completion model provider
     #[error("ProviderError: {0}")]
     ProviderError(String),
+
+    /// Error returned by the completion model provider, classified by kind
+    #[error("ProviderError({kind}): {message}")]
+    Provider {
+        kind: ProviderErrorKind,
+        message: String,
+    },
 }
 
 /// Prompt errors
@@ -132,6 +141,15 @@ pub enum PromptError {
         chat_history: Box<Vec<Message>>,
         prompt: Message,
     },
+
+    /// The LLM called the same tool with identical arguments too many times in a row, it is most
+    /// likely stuck. The limit is set with `.tool_loop_limit()` on the agent or the request.
+    #[error("ToolLoopError: {tool_name} called {repeats} times in a row with the same arguments")]
+    ToolLoopError {
+        tool_name: String,
+        repeats: usize,
+        chat_history: Box<Vec<Message>>,
+    },
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -229,15 +247,72 @@ pub struct CompletionResponse<T> {
     pub choice: OneOrMany<AssistantContent>,
     /// Tokens used during prompting and responding
     pub usage: Usage,
+    /// Log probabilities of the generated tokens, when requested and returned by the provider
+    pub logprobs: Option<Vec<TokenLogprob>>,
+    /// Server side timings, reported by local model servers like Ollama
+    pub timings: Option<Timings>,
+    /// Label of the model that served the request, set by
+    /// [crate::client::fallback::FallbackCompletionModel]
+    pub served_by: Option<String>,
     /// The raw response returned by the completion model provider
     pub raw_response: T,
 }
 
+impl<T> CompletionResponse<T> {
+    /// Reasoning the model emitted before its answer, if any. Not part of the answer itself.
+    pub fn reasoning(&self) -> Option<String> {
+        let reasoning: Vec<&str> = self
+            .choice
+            .iter()
+            .filter_map(|content| match content {
+                AssistantContent::Reasoning(reasoning) => Some(reasoning.reasoning.iter()),
+                _ => None,
+            })
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        (!reasoning.is_empty()).then(|| reasoning.join("\n"))
+    }
+
+    /// Probability of the generated text, the exponential of the mean token log probability.
+    /// Close to 1 when the model was confident about every token.
+    pub fn confidence(&self) -> Option<f64> {
+        let logprobs = self
+            .logprobs
+            .as_ref()
+            .filter(|logprobs| !logprobs.is_empty())?;
+        let mean = logprobs.iter().map(|token| token.logprob).sum::<f64>() / logprobs.len() as f64;
+        Some(mean.exp())
+    }
+}
+
+/// Log probability of a generated token.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    /// The most likely tokens at this position, when requested
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+/// An alternative token at a position.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+}
+
 /// A trait for grabbing the token usage of a completion response.
 ///
 /// Primarily designed for streamed completion responses in streamed multi-turn, as otherwise it would be impossible to do.
 pub trait GetTokenUsage {
     fn token_usage(&self) -> Option<crate::completion::Usage>;
+
+    /// Server side timings of the response, if the provider reports them.
+    fn timings(&self) -> Option<Timings> {
+        None
+    }
 }
 
 impl GetTokenUsage for () {
@@ -257,6 +332,9 @@ where
             None
         }
     }
+    fn timings(&self) -> Option<Timings> {
+        self.as_ref().and_then(T::timings)
+    }
 }
 
 /// Struct representing the token usage for a completion request.
@@ -269,6 +347,10 @@ pub struct Usage {
     pub output_tokens: u64,
     /// We store this separately as some providers may only report one number
     pub total_tokens: u64,
+    /// The number of input tokens served from the provider's prompt cache (if reported).
+    /// These are already included in `input_tokens`.
+    #[serde(default)]
+    pub cached_input_tokens: u64,
 }
 
 impl Usage {
@@ -278,8 +360,14 @@ impl Usage {
             input_tokens: 0,
             output_tokens: 0,
             total_tokens: 0,
+            cached_input_tokens: 0,
         }
     }
+
+    /// Returns the number of input tokens that missed the prompt cache.
+    pub fn uncached_input_tokens(&self) -> u64 {
+        self.input_tokens.saturating_sub(self.cached_input_tokens)
+    }
 }
 
 impl Default for Usage {
@@ -296,6 +384,7 @@ impl Add for Usage {
             input_tokens: self.input_tokens + other.input_tokens,
             output_tokens: self.output_tokens + other.output_tokens,
             total_tokens: self.total_tokens + other.total_tokens,
+            cached_input_tokens: self.cached_input_tokens + other.cached_input_tokens,
         }
     }
 }
@@ -305,9 +394,38 @@ impl AddAssign for Usage {
         self.input_tokens += other.input_tokens;
         self.output_tokens += other.output_tokens;
         self.total_tokens += other.total_tokens;
+        self.cached_input_tokens += other.cached_input_tokens;
     }
 }
 
+/// Where a local model server spent the time of a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timings {
+    /// Loading the model into memory
+    pub load: Option<Duration>,
+    /// Evaluating the prompt
+    pub prompt_eval: Option<Duration>,
+    /// Generating the output
+    pub eval: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+impl Timings {
+    /// Prompt tokens evaluated per second.
+    pub fn prompt_tokens_per_second(&self, usage: &Usage) -> Option<f64> {
+        per_second(usage.input_tokens, self.prompt_eval?)
+    }
+
+    /// Output tokens generated per second.
+    pub fn output_tokens_per_second(&self, usage: &Usage) -> Option<f64> {
+        per_second(usage.output_tokens, self.eval?)
+    }
+}
+
+fn per_second(tokens: u64, duration: Duration) -> Option<f64> {
+    (!duration.is_zero()).then(|| tokens as f64 / duration.as_secs_f64())
+}
+
 /// Trait defining a completion model that can be used to generate completion responses.
 /// This trait is meant to be implemented by the user to define a custom completion model,
 /// either from a third party provider (e.g.: OpenAI) or a local model.
@@ -352,7 +470,7 @@ pub trait CompletionModelDyn: Send + Sync {
     fn stream(
         &self,
         request: CompletionRequest,
-    ) -> BoxFuture<'_, Result<StreamingCompletionResponse<()>, CompletionError>>;
+    ) -> BoxFuture<'_, Result<StreamingCompletionResponse<StreamUsage>, CompletionError>>;
 
     fn completion_request(
         &self,
@@ -375,6 +493,9 @@ where
                 .map(|resp| CompletionResponse {
                     choice: resp.choice,
                     usage: resp.usage,
+                    logprobs: resp.logprobs,
+                    timings: resp.timings,
+                    served_by: resp.served_by,
                     raw_response: (),
                 })
         })
@@ -383,16 +504,17 @@ where
     fn stream(
         &self,
         request: CompletionRequest,
-    ) -> BoxFuture<'_, Result<StreamingCompletionResponse<()>, CompletionError>> {
+    ) -> BoxFuture<'_, Result<StreamingCompletionResponse<StreamUsage>, CompletionError>> {
         Box::pin(async move {
             let resp = self.stream(request).await?;
+            let served_by = resp.served_by;
             let inner = resp.inner;
 
             let stream = Box::pin(streaming::StreamingResultDyn {
                 inner: Box::pin(inner),
             });
 
-            Ok(StreamingCompletionResponse::stream(stream))
+            Ok(StreamingCompletionResponse::stream(stream).with_served_by(served_by))
         })
     }
 
@@ -410,6 +532,31 @@ where
     }
 }
 
+/// Sampling parameters besides the temperature. Parameters left to `None` use the provider's
+/// default. The field names match the Ollama options and the OpenAI style request body, so
+/// providers can merge the serialized struct directly.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplingParams {
+    /// Nucleus sampling, only tokens within the top `top_p` probability mass are considered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    /// Penalizes tokens by how often they already appeared
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    /// Penalizes tokens that already appeared at all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    /// Seed for reproducible sampling, where the provider supports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl SamplingParams {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
 /// Struct representing a general completion request that can be sent to a completion model provider.
 #[derive(Debug, Clone)]
 pub struct CompletionRequest {
@@ -426,6 +573,13 @@ pub struct CompletionRequest {
     pub temperature: Option<f64>,
     /// The max tokens to be sent to the completion model provider
     pub max_tokens: Option<u64>,
+    /// Sampling parameters besides the temperature
+    pub sampling: SamplingParams,
+    /// Whether a reasoning model thinks before answering. `None` keeps the default of the model.
+    /// Thinking improves hard answers at the cost of latency and output tokens.
+    pub think: Option<bool>,
+    /// Sequences where the model stops generating, not included in the response
+    pub stop: Vec<String>,
     /// Whether tools are required to be used by the model provider or not before providing a response.
     pub tool_choice: Option<ToolChoice>,
     /// Additional provider-specific parameters to be sent to the completion model provider
@@ -515,6 +669,9 @@ pub struct CompletionRequestBuilder<M: CompletionModel> {
     tools: Vec<Tool>,
     temperature: Option<f64>,
     max_tokens: Option<u64>,
+    sampling: SamplingParams,
+    think: Option<bool>,
+    stop: Vec<String>,
     tool_choice: Option<ToolChoice>,
     additional_params: Option<serde_json::Value>,
 }
@@ -530,6 +687,9 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
             tools: Vec::new(),
             temperature: None,
             max_tokens: None,
+            sampling: SamplingParams::default(),
+            think: None,
+            stop: Vec::new(),
             tool_choice: None,
             additional_params: None,
         }
@@ -638,6 +798,61 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
         self
     }
 
+    /// Sets all the sampling parameters, replacing the ones set before.
+    pub fn sampling(mut self, sampling: SamplingParams) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Sets the nucleus sampling probability mass for the completion request.
+    pub fn top_p(mut self, top_p: f64) -> Self {
+        self.sampling.top_p = Some(top_p);
+        self
+    }
+
+    /// Sets the frequency penalty for the completion request.
+    pub fn frequency_penalty(mut self, penalty: f64) -> Self {
+        self.sampling.frequency_penalty = Some(penalty);
+        self
+    }
+
+    /// Sets the presence penalty for the completion request.
+    pub fn presence_penalty(mut self, penalty: f64) -> Self {
+        self.sampling.presence_penalty = Some(penalty);
+        self
+    }
+
+    /// Sets the sampling seed for the completion request.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.sampling.seed = Some(seed);
+        self
+    }
+
+    /// Enables or disables thinking of reasoning models for the completion request.
+    pub fn think(mut self, think: bool) -> Self {
+        self.think = Some(think);
+        self
+    }
+
+    /// Sets whether reasoning models think, `None` keeps the default of the model.
+    pub fn think_opt(mut self, think: Option<bool>) -> Self {
+        self.think = think;
+        self
+    }
+
+    /// Adds a stop sequence to the completion request.
+    pub fn stop(mut self, stop: impl Into<String>) -> Self {
+        self.stop.push(stop.into());
+        self
+    }
+
+    /// Adds a list of stop sequences to the completion request.
+    pub fn stops(self, stops: Vec<String>) -> Self {
+        stops
+            .into_iter()
+            .fold(self, |builder, stop| builder.stop(stop))
+    }
+
     /// Sets the thing.
     pub fn tool_choice(mut self, tool_choice: ToolChoice) -> Self {
         self.tool_choice = Some(tool_choice);
@@ -656,6 +871,9 @@ impl<M: CompletionModel> CompletionRequestBuilder<M> {
             tools: self.tools,
             temperature: self.temperature,
             max_tokens: self.max_tokens,
+            sampling: self.sampling,
+            think: self.think,
+            stop: self.stop,
             tool_choice: self.tool_choice,
             additional_params: self.additional_params,
         }
@@ -739,6 +957,9 @@ mod tests {
             tools: Vec::new(),
             temperature: None,
             max_tokens: None,
+            sampling: Default::default(),
+            think: None,
+            stop: vec![],
             tool_choice: None,
             additional_params: None,
         };
@@ -769,6 +990,9 @@ mod tests {
             tools: Vec::new(),
             temperature: None,
             max_tokens: None,
+            sampling: Default::default(),
+            think: None,
+            stop: vec![],
             tool_choice: None,
             additional_params: None,
         };
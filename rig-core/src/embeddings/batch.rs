@@ -0,0 +1,230 @@
+//! Embedding large numbers of texts in batches.
+//!
+//! [BatchEmbedder] splits the texts with [split_batches] so that no request holds more than
+//! [EmbeddingModel::MAX_DOCUMENTS] texts or more than `max_batch_bytes` of text, sends up to
+//! `concurrency` batches at the same time and retries batches failing with a retryable error.
+//! The embeddings are returned in the order of the texts.
+//!
+//! ```rust,ignore
+//! let embeddings = BatchEmbedder::new(model)
+//!     .concurrency(4)
+//!     .on_progress(|progress| println!("{}/{}", progress.embedded, progress.total))
+//!     .embed(chunks)
+//!     .await?;
+//! ```
+
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{StreamExt, TryStreamExt, stream};
+
+use super::{Embedding, EmbeddingError, EmbeddingModel};
+
+/// Default text size limit of a batch, in bytes.
+pub const DEFAULT_MAX_BATCH_BYTES: usize = 1024 * 1024;
+
+/// Progress of a [BatchEmbedder::embed] call, reported after each finished batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbedProgress {
+    /// Texts embedded so far
+    pub embedded: usize,
+    pub total: usize,
+    pub batches_done: usize,
+    pub batches_total: usize,
+}
+
+pub type ProgressFn = Arc<dyn Fn(EmbedProgress) + Send + Sync>;
+
+/// Split texts into consecutive batches of at most `max_documents` texts and `max_bytes` bytes.
+/// A text larger than `max_bytes` gets a batch of its own.
+pub fn split_batches(
+    texts: &[String],
+    max_documents: usize,
+    max_bytes: usize,
+) -> Vec<Range<usize>> {
+    let max_documents = max_documents.max(1);
+    let mut batches = vec![];
+    let mut start = 0;
+    let mut bytes = 0;
+    for (i, text) in texts.iter().enumerate() {
+        let full = i - start >= max_documents || (i > start && bytes + text.len() > max_bytes);
+        if full {
+            batches.push(start..i);
+            start = i;
+            bytes = 0;
+        }
+        bytes += text.len();
+    }
+    if start < texts.len() {
+        batches.push(start..texts.len());
+    }
+    batches
+}
+
+/// Embeds texts in concurrent, size limited and retried batches.
+#[derive(Clone)]
+pub struct BatchEmbedder<M: EmbeddingModel> {
+    model: M,
+    max_batch_size: usize,
+    max_batch_bytes: usize,
+    concurrency: usize,
+    max_retries: u32,
+    retry_delay: Duration,
+    on_progress: Option<ProgressFn>,
+}
+
+impl<M: EmbeddingModel> BatchEmbedder<M> {
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            max_batch_size: M::MAX_DOCUMENTS,
+            max_batch_bytes: DEFAULT_MAX_BATCH_BYTES,
+            concurrency: 4,
+            max_retries: 2,
+            retry_delay: Duration::from_millis(500),
+            on_progress: None,
+        }
+    }
+
+    /// Number of texts per request, capped at [EmbeddingModel::MAX_DOCUMENTS].
+    pub fn max_batch_size(mut self, size: usize) -> Self {
+        self.max_batch_size = size.clamp(1, M::MAX_DOCUMENTS.max(1));
+        self
+    }
+
+    /// Text size limit of a request, in bytes.
+    pub fn max_batch_bytes(mut self, bytes: usize) -> Self {
+        self.max_batch_bytes = bytes;
+        self
+    }
+
+    /// Number of batches sent at the same time.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Retries of a failed batch. The delay doubles after every attempt.
+    pub fn retries(mut self, max_retries: u32, delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    pub fn on_progress(
+        mut self,
+        on_progress: impl Fn(EmbedProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_progress = Some(Arc::new(on_progress));
+        self
+    }
+
+    /// Embed the texts, the embeddings are in the same order as the texts.
+    pub async fn embed(&self, texts: Vec<String>) -> Result<Vec<Embedding>, EmbeddingError> {
+        let batches = split_batches(&texts, self.max_batch_size, self.max_batch_bytes);
+        let mut progress = EmbedProgress {
+            embedded: 0,
+            total: texts.len(),
+            batches_done: 0,
+            batches_total: batches.len(),
+        };
+
+        let mut results = stream::iter(batches)
+            .map(|range| {
+                let batch = texts[range.clone()].to_vec();
+                async move {
+                    let embeddings = self.embed_batch(batch).await?;
+                    Ok::<_, EmbeddingError>((range.start, embeddings))
+                }
+            })
+            .buffer_unordered(self.concurrency)
+            .map_ok(|(start, embeddings)| {
+                progress.embedded += embeddings.len();
+                progress.batches_done += 1;
+                if let Some(on_progress) = &self.on_progress {
+                    on_progress(progress);
+                }
+                (start, embeddings)
+            })
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        results.sort_by_key(|(start, _)| *start);
+        Ok(results
+            .into_iter()
+            .flat_map(|(_, embeddings)| embeddings)
+            .collect())
+    }
+
+    async fn embed_batch(&self, batch: Vec<String>) -> Result<Vec<Embedding>, EmbeddingError> {
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+        loop {
+            match self.model.embed_texts(batch.clone()).await {
+                Ok(embeddings) if embeddings.len() == batch.len() => return Ok(embeddings),
+                Ok(_) => {
+                    return Err(EmbeddingError::ResponseError(
+                        "Number of returned embeddings does not match input".into(),
+                    ));
+                }
+                Err(e) if attempt < self.max_retries && e.is_retryable() => {
+                    attempt += 1;
+                    tracing::warn!(
+                        target: "rig",
+                        "embedding batch failed, retry {attempt}/{}: {e}",
+                        self.max_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::EmbeddingsClient;
+    use crate::client::mock::MockClient;
+    use std::sync::Mutex;
+
+    #[test]
+    fn batches_respect_count_and_size() {
+        let texts: Vec<String> = ["aaaa", "bb", "cccccccc", "d", "e"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(split_batches(&texts, 2, 100), vec![0..2, 2..4, 4..5]);
+        assert_eq!(split_batches(&texts, 10, 6), vec![0..2, 2..3, 3..5]);
+        assert!(split_batches(&[], 10, 6).is_empty());
+    }
+
+    #[tokio::test]
+    async fn embeds_in_order_with_progress() {
+        let model = MockClient::new().embedding_model("batch-test");
+        let texts: Vec<String> = (0..25).map(|i| format!("chunk {i}")).collect();
+        let reports = Arc::new(Mutex::new(vec![]));
+        let seen = reports.clone();
+
+        let embeddings = BatchEmbedder::new(model.clone())
+            .max_batch_size(10)
+            .concurrency(3)
+            .on_progress(move |progress| seen.lock().unwrap().push(progress))
+            .embed(texts.clone())
+            .await
+            .unwrap();
+
+        let documents: Vec<&String> = embeddings.iter().map(|e| &e.document).collect();
+        assert_eq!(documents, texts.iter().collect::<Vec<_>>());
+        assert_eq!(
+            embeddings[3].vec,
+            model.embed_text("chunk 3").await.unwrap().vec
+        );
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports.last().unwrap().embedded, 25);
+    }
+}
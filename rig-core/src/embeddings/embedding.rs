@@ -6,6 +6,7 @@
 //! Finally, the module defines the [EmbeddingError] enum, which represents various errors that
 //! can occur during embedding generation or processing.
 
+use crate::completion::ProviderErrorKind;
 use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
 
@@ -35,6 +36,19 @@ pub enum EmbeddingError {
     ProviderError(String),
 }
 
+impl EmbeddingError {
+    /// Whether the request may succeed if sent again unchanged.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            EmbeddingError::HttpError(e) => e.is_timeout() || e.is_connect(),
+            EmbeddingError::ProviderError(message) => {
+                ProviderErrorKind::classify(None, message).is_retryable()
+            }
+            _ => false,
+        }
+    }
+}
+
 /// Trait for embedding models that can generate embeddings for documents.
 pub trait EmbeddingModel: Clone + Sync + Send {
     /// The maximum number of documents that can be embedded in a single request.
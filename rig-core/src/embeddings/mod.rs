@@ -3,11 +3,13 @@
 //! natural language processing (NLP) tasks such as text classification, information retrieval,
 //! and document similarity.
 
+pub mod batch;
 pub mod builder;
 pub mod embed;
 pub mod embedding;
 
 pub mod distance;
+pub use batch::BatchEmbedder;
 pub use builder::EmbeddingsBuilder;
 pub use embed::{Embed, EmbedError, TextEmbedder, to_texts};
 pub use embedding::{Embedding, EmbeddingError, EmbeddingModel};
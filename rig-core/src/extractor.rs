@@ -0,0 +1,156 @@
+//! Structured data extraction.
+//!
+//! An extractor asks the model to call a single `submit` tool whose parameters are the target
+//! json schema, and returns the arguments of that call. Models that answer with plain text are
+//! accepted as long as the text is valid json.
+//!
+//! [DynExtractor] works with a schema known only at runtime (e.g. read from a workflow), while
+//! [Extractor] derives the schema from a Rust type and deserializes the result.
+//!
+//! # Example
+//! ```rust,ignore
+//! #[derive(serde::Deserialize, schemars::JsonSchema)]
+//! struct Invoice {
+//!     number: String,
+//!     total: f64,
+//! }
+//!
+//! let extractor = Extractor::<_, Invoice>::new(agent);
+//! let invoice = extractor.extract("Invoice #42, total 13.5 EUR").await?;
+//! ```
+
+use std::marker::PhantomData;
+
+use rmcp::model::Tool as ToolDefinition;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::agent::Agent;
+use crate::completion::{AssistantContent, Completion, CompletionError, CompletionModel};
+use crate::message::ToolChoice;
+
+/// Name of the tool the model is asked to call with the extracted data.
+pub const SUBMIT_TOOL: &str = "submit";
+
+const INSTRUCTIONS: &str = "Extract the data from the text and submit it by calling the `submit` tool. \
+Use null for values that are not present in the text, do not make them up.";
+
+#[derive(Debug, Error)]
+pub enum ExtractionError {
+    #[error("CompletionError: {0}")]
+    CompletionError(#[from] CompletionError),
+    /// The model neither called the submit tool nor answered with json
+    #[error("NoData: the model did not submit any data")]
+    NoData,
+    /// The submitted data does not deserialize into the target type
+    #[error("DeserializationError: {0}")]
+    DeserializationError(#[from] serde_json::Error),
+}
+
+/// Extracts json matching a schema provided at runtime.
+pub struct DynExtractor<M: CompletionModel> {
+    agent: Agent<M>,
+    schema: Value,
+    instructions: Option<String>,
+}
+
+impl<M: CompletionModel> DynExtractor<M> {
+    pub fn new(agent: Agent<M>, schema: Value) -> Self {
+        Self {
+            agent,
+            schema,
+            instructions: None,
+        }
+    }
+
+    /// Extra instructions sent along with every text, e.g. what the extracted fields mean.
+    pub fn instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.instructions = Some(instructions.into());
+        self
+    }
+
+    pub fn schema(&self) -> &Value {
+        &self.schema
+    }
+
+    /// Definition of the submit tool, its parameters are the extraction schema.
+    pub fn definition(&self) -> ToolDefinition {
+        ToolDefinition::new(
+            SUBMIT_TOOL,
+            "Submit the extracted data",
+            self.schema.as_object().cloned().unwrap_or_default(),
+        )
+    }
+
+    /// Extract json from `text`.
+    pub async fn extract_value(&self, text: &str) -> Result<Value, ExtractionError> {
+        let prompt = match &self.instructions {
+            Some(instructions) => format!("{INSTRUCTIONS}\n{instructions}\n\n{text}"),
+            None => format!("{INSTRUCTIONS}\n\n{text}"),
+        };
+        let response = self
+            .agent
+            .completion(prompt, vec![])
+            .await?
+            .tool(self.definition())
+            .tool_choice(ToolChoice::Specific {
+                function_names: vec![SUBMIT_TOOL.to_string()],
+            })
+            .send()
+            .await?;
+
+        let mut text = String::new();
+        for content in response.choice.iter() {
+            match content {
+                AssistantContent::ToolCall(call) if call.function.name == SUBMIT_TOOL => {
+                    return Ok(match &call.function.arguments {
+                        // some providers pass the arguments as a json string
+                        Value::String(arguments) => serde_json::from_str(arguments)?,
+                        arguments => arguments.clone(),
+                    });
+                }
+                AssistantContent::Text(t) => text.push_str(&t.text),
+                _ => {}
+            }
+        }
+        serde_json::from_str(strip_code_fence(&text)).map_err(|_| ExtractionError::NoData)
+    }
+}
+
+/// Extracts a `T`, the schema is derived from the type.
+pub struct Extractor<M: CompletionModel, T> {
+    inner: DynExtractor<M>,
+    _data: PhantomData<fn() -> T>,
+}
+
+impl<M: CompletionModel, T: JsonSchema + DeserializeOwned> Extractor<M, T> {
+    pub fn new(agent: Agent<M>) -> Self {
+        let schema = serde_json::to_value(schemars::schema_for!(T)).unwrap_or_default();
+        Self {
+            inner: DynExtractor::new(agent, schema),
+            _data: PhantomData,
+        }
+    }
+
+    pub fn instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.inner = self.inner.instructions(instructions);
+        self
+    }
+
+    pub async fn extract(&self, text: &str) -> Result<T, ExtractionError> {
+        Ok(serde_json::from_value(
+            self.inner.extract_value(text).await?,
+        )?)
+    }
+}
+
+fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+    text.strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|t| t.strip_suffix("```"))
+        .map(str::trim)
+        .unwrap_or(text)
+}
@@ -0,0 +1,227 @@
+//! Recording and replaying provider HTTP traffic for offline tests.
+//!
+//! Only compiled with the `http-record` feature. Providers send their requests through [send].
+//! With a [Recorder] in [RecordMode::Record] the request is sent and the raw response is saved
+//! to `<dir>/<key>.json`, where the key is a hash of the method, url and body. In
+//! [RecordMode::Replay] nothing is sent: the saved response is served back, and a request without
+//! a fixture gets a `404` response naming the missing key. Headers are not part of the key, so
+//! API keys never end up in fixture names, and they are not saved either.
+//!
+//! Responses are read completely before being returned, streaming responses are replayed as a
+//! single body. DeepSeek streaming goes through an event source and is not recorded.
+//!
+//! ```rust,ignore
+//! // RIG_HTTP_RECORD=record RIG_HTTP_FIXTURES=tests/fixtures/http cargo test
+//! rig::http_record::install_from_env();
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+static RECORDER: RwLock<Option<Arc<Recorder>>> = RwLock::new(None);
+
+/// Directory used by [install_from_env] when `RIG_HTTP_FIXTURES` is not set.
+pub const DEFAULT_FIXTURE_DIR: &str = "tests/fixtures/http";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordMode {
+    /// Send requests and save their responses.
+    Record,
+    /// Serve saved responses without sending anything.
+    Replay,
+}
+
+/// Where fixtures are kept and whether they are written or read.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    dir: PathBuf,
+    mode: RecordMode,
+}
+
+/// A recorded request/response pair.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    pub method: String,
+    pub url: String,
+    pub request_body: Option<String>,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: String,
+}
+
+impl Recorder {
+    pub fn new(dir: impl Into<PathBuf>, mode: RecordMode) -> Self {
+        Self {
+            dir: dir.into(),
+            mode,
+        }
+    }
+
+    pub fn mode(&self) -> RecordMode {
+        self.mode
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+
+    /// The saved fixture of a key, if any.
+    pub fn load(&self, key: &str) -> Option<Fixture> {
+        let text = std::fs::read_to_string(self.path(key)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn save(&self, key: &str, fixture: &Fixture) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let text = serde_json::to_string_pretty(fixture).map_err(std::io::Error::other)?;
+        std::fs::write(self.path(key), text)
+    }
+}
+
+/// Install a recorder, replacing the previously installed one.
+pub fn install(recorder: Recorder) {
+    *RECORDER.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(recorder));
+}
+
+/// Install a recorder from `RIG_HTTP_RECORD` (`record` or `replay`) and `RIG_HTTP_FIXTURES`.
+/// Does nothing when `RIG_HTTP_RECORD` is not set to one of the modes.
+pub fn install_from_env() {
+    let mode = match std::env::var("RIG_HTTP_RECORD").as_deref() {
+        Ok("record") => RecordMode::Record,
+        Ok("replay") => RecordMode::Replay,
+        _ => return,
+    };
+    let dir = std::env::var("RIG_HTTP_FIXTURES").unwrap_or_else(|_| DEFAULT_FIXTURE_DIR.into());
+    install(Recorder::new(dir, mode));
+}
+
+/// Remove the installed recorder, requests are sent normally again.
+pub fn clear() {
+    *RECORDER.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+pub fn current() -> Option<Arc<Recorder>> {
+    RECORDER.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Fixture key of a request: an FNV-1a hash of the method, url and body, stable across builds.
+pub fn request_key(method: &str, url: &str, body: Option<&[u8]>) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let parts: [&[u8]; 4] = [
+        method.as_bytes(),
+        url.as_bytes(),
+        b"\n",
+        body.unwrap_or_default(),
+    ];
+    for byte in parts.into_iter().flatten() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{hash:016x}")
+}
+
+/// Send a request through the installed recorder, or normally when there is none.
+pub async fn send(request: reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+    let Some(recorder) = current() else {
+        return request.send().await;
+    };
+    let (client, request) = request.build_split();
+    let request = request?;
+    let method = request.method().to_string();
+    let url = request.url().to_string();
+    let request_body = request.body().and_then(|body| body.as_bytes());
+    let key = request_key(&method, &url, request_body);
+
+    match recorder.mode {
+        RecordMode::Replay => Ok(match recorder.load(&key) {
+            Some(fixture) => into_response(&fixture),
+            None => missing_fixture(&key, &method, &url),
+        }),
+        RecordMode::Record => {
+            let request_body = request_body.map(|body| String::from_utf8_lossy(body).into_owned());
+            let response = client.execute(request).await?;
+            let fixture = Fixture {
+                method,
+                url,
+                request_body,
+                status: response.status().as_u16(),
+                content_type: response
+                    .headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string),
+                body: response.text().await?,
+            };
+            if let Err(e) = recorder.save(&key, &fixture) {
+                tracing::warn!(target: "rig", "Failed to save http fixture {key}: {e}");
+            }
+            Ok(into_response(&fixture))
+        }
+    }
+}
+
+fn into_response(fixture: &Fixture) -> reqwest::Response {
+    let mut builder = http::Response::builder().status(fixture.status);
+    if let Some(content_type) = &fixture.content_type {
+        builder = builder.header(http::header::CONTENT_TYPE, content_type);
+    }
+    builder
+        .body(fixture.body.clone())
+        .unwrap_or_else(|_| http::Response::new(fixture.body.clone()))
+        .into()
+}
+
+fn missing_fixture(key: &str, method: &str, url: &str) -> reqwest::Response {
+    let mut response =
+        http::Response::new(format!("no recorded response {key} for {method} {url}"));
+    *response.status_mut() = http::StatusCode::NOT_FOUND;
+    response.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn replays_saved_fixture() {
+        let dir = std::env::temp_dir().join(format!("rig-http-record-{}", std::process::id()));
+        let recorder = Recorder::new(&dir, RecordMode::Replay);
+        let client = reqwest::Client::new();
+        let body = r#"{"model":"m1"}"#;
+        let key = request_key("POST", "http://localhost:1/api/chat", Some(body.as_bytes()));
+        recorder
+            .save(
+                &key,
+                &Fixture {
+                    method: "POST".into(),
+                    url: "http://localhost:1/api/chat".into(),
+                    request_body: Some(body.into()),
+                    status: 200,
+                    content_type: Some("application/json".into()),
+                    body: r#"{"done":true}"#.into(),
+                },
+            )
+            .unwrap();
+        install(recorder);
+
+        let response = send(client.post("http://localhost:1/api/chat").body(body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), r#"{"done":true}"#);
+
+        let missing = send(client.post("http://localhost:1/api/chat").body("{}"))
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), 404);
+
+        clear();
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -107,15 +107,22 @@
 extern crate self as rig;
 
 pub mod agent;
+#[cfg(any(test, feature = "chaos"))]
+pub mod chaos;
 
 pub mod cli_chatbot;
 pub mod client;
 pub mod completion;
 pub mod embeddings;
+pub mod extractor;
+#[cfg(feature = "http-record")]
+pub mod http_record;
 pub mod json_utils;
 pub mod one_or_many;
 pub mod prelude;
 pub mod streaming;
+pub mod tokens;
+pub mod tool;
 
 // Re-export commonly used types and traits
 pub use completion::message;
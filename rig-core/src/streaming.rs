@@ -13,7 +13,7 @@ use crate::agent::Agent;
 use crate::agent::prompt_request::streaming::StreamingPromptRequest;
 use crate::completion::{
     CompletionError, CompletionModel, CompletionRequestBuilder, CompletionResponse, GetTokenUsage,
-    Message, Usage,
+    Message, Timings, Usage,
 };
 use crate::message::{AssistantContent, Reasoning, Text, ToolCall, ToolFunction};
 use futures::stream::{AbortHandle, Abortable};
@@ -115,6 +115,11 @@ where
     /// if the provider didn't yield it during the stream
     pub response: Option<R>,
     pub final_response_yielded: AtomicBool,
+    /// Text and reasoning chunks received so far
+    chunks: u64,
+    /// Label of the model that serves the stream, set by
+    /// [crate::client::fallback::FallbackCompletionModel]
+    pub served_by: Option<String>,
 }
 
 impl<R> StreamingCompletionResponse<R>
@@ -135,9 +140,39 @@ where
             choice: OneOrMany::one(AssistantContent::text("")),
             response: None,
             final_response_yielded: AtomicBool::new(false),
+            chunks: 0,
+            served_by: None,
         }
     }
 
+    /// Set the label of the model serving the stream.
+    pub fn with_served_by(mut self, served_by: Option<String>) -> Self {
+        self.served_by = served_by;
+        self
+    }
+
+    /// Token usage of the stream. Without a final response, e.g. after [Self::cancel], the
+    /// output tokens are estimated from the number of received chunks (providers like Ollama
+    /// send one token per chunk) and the input tokens are unknown.
+    pub fn usage(&self) -> Usage {
+        match self.response.as_ref().and_then(GetTokenUsage::token_usage) {
+            Some(usage) => usage,
+            None => Usage {
+                output_tokens: self.chunks,
+                total_tokens: self.chunks,
+                ..Usage::new()
+            },
+        }
+    }
+
+    /// Whether [Self::usage] is reported by the provider rather than estimated.
+    pub fn has_final_usage(&self) -> bool {
+        self.response
+            .as_ref()
+            .and_then(GetTokenUsage::token_usage)
+            .is_some()
+    }
+
     pub fn cancel(&self) {
         self.abort_handle.abort();
     }
@@ -160,9 +195,14 @@ where
     R: Clone + Unpin + GetTokenUsage,
 {
     fn from(value: StreamingCompletionResponse<R>) -> CompletionResponse<Option<R>> {
+        let usage = value.usage();
+        let timings = value.response.as_ref().and_then(GetTokenUsage::timings);
         CompletionResponse {
             choice: value.choice,
-            usage: Usage::new(), // Usage is not tracked in streaming responses
+            usage,
+            logprobs: None,
+            timings,
+            served_by: value.served_by,
             raw_response: value.response,
         }
     }
@@ -197,6 +237,13 @@ where
                 if choice.is_empty() || !stream.text.is_empty() {
                     choice.insert(0, AssistantContent::text(stream.text.clone()));
                 }
+                // Reasoning is kept as its own content, ahead of the answer it led to
+                if !stream.reasoning.is_empty() {
+                    choice.insert(
+                        0,
+                        AssistantContent::Reasoning(Reasoning::new(&stream.reasoning)),
+                    );
+                }
 
                 stream.choice = OneOrMany::many(choice)
                     .expect("There should be at least one assistant message");
@@ -215,12 +262,14 @@ where
                     // Forward the streaming tokens to the outer stream
                     // and concat the text together
                     stream.text = format!("{}{}", stream.text, text.clone());
+                    stream.chunks += 1;
                     Poll::Ready(Some(Ok(StreamedAssistantContent::text(&text))))
                 }
                 RawStreamingChoice::Reasoning { id, reasoning } => {
                     // Forward the streaming tokens to the outer stream
                     // and concat the text together
                     stream.reasoning = format!("{}{}", stream.reasoning, reasoning.clone());
+                    stream.chunks += 1;
                     Poll::Ready(Some(Ok(StreamedAssistantContent::Reasoning(Reasoning {
                         id,
                         reasoning: vec![stream.reasoning.clone()],
@@ -309,12 +358,31 @@ pub trait StreamingCompletion<M: CompletionModel> {
     ) -> impl Future<Output = Result<CompletionRequestBuilder<M>, CompletionError>>;
 }
 
+/// Final response of a type-erased stream, see
+/// [crate::client::completion::CompletionModelHandle]. Only the token usage of the provider
+/// response is kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamUsage {
+    pub usage: Option<Usage>,
+    pub timings: Option<Timings>,
+}
+
+impl GetTokenUsage for StreamUsage {
+    fn token_usage(&self) -> Option<Usage> {
+        self.usage
+    }
+
+    fn timings(&self) -> Option<Timings> {
+        self.timings
+    }
+}
+
 pub(crate) struct StreamingResultDyn<R: Clone + Unpin> {
     pub(crate) inner: StreamingResult<R>,
 }
 
-impl<R: Clone + Unpin> Stream for StreamingResultDyn<R> {
-    type Item = Result<RawStreamingChoice<()>, CompletionError>;
+impl<R: Clone + Unpin + GetTokenUsage> Stream for StreamingResultDyn<R> {
+    type Item = Result<RawStreamingChoice<StreamUsage>, CompletionError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let stream = self.get_mut();
@@ -324,8 +392,11 @@ impl<R: Clone + Unpin> Stream for StreamingResultDyn<R> {
             Poll::Ready(None) => Poll::Ready(None),
             Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err))),
             Poll::Ready(Some(Ok(chunk))) => match chunk {
-                RawStreamingChoice::FinalResponse(_) => {
-                    Poll::Ready(Some(Ok(RawStreamingChoice::FinalResponse(()))))
+                RawStreamingChoice::FinalResponse(response) => {
+                    Poll::Ready(Some(Ok(RawStreamingChoice::FinalResponse(StreamUsage {
+                        usage: response.token_usage(),
+                        timings: response.timings(),
+                    }))))
                 }
                 RawStreamingChoice::Message(m) => {
                     Poll::Ready(Some(Ok(RawStreamingChoice::Message(m))))
@@ -496,6 +567,25 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn usage_of_erased_and_cancelled_streams() {
+        let inner = create_mock_stream().inner;
+        let mut erased = StreamingCompletionResponse::stream(Box::pin(StreamingResultDyn {
+            inner: Box::pin(inner),
+        }));
+        while erased.next().await.is_some() {}
+        assert!(erased.has_final_usage());
+        assert_eq!(erased.usage().total_tokens, 15);
+
+        let mut cancelled = create_mock_stream();
+        cancelled.next().await.unwrap().unwrap();
+        cancelled.next().await.unwrap().unwrap();
+        cancelled.cancel();
+        assert!(cancelled.next().await.is_none());
+        assert!(!cancelled.has_final_usage());
+        assert_eq!(cancelled.usage().output_tokens, 2);
+    }
+
     #[tokio::test]
     async fn test_stream_pause_resume() {
         let stream = create_mock_stream();
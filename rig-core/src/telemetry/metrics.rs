@@ -0,0 +1,342 @@
+//! OpenTelemetry metrics for completion providers and MCP servers.
+//!
+//! Available with the `otel-metrics` feature. Instruments are created from the global meter
+//! provider, so install an SDK meter provider (OTLP, Prometheus, ...) before building agents.
+//! Names follow the OpenTelemetry GenAI semantic conventions where one exists:
+//!
+//! - `gen_ai.client.operation.duration`: request latency in seconds, per provider and model.
+//! - `gen_ai.client.token.usage`: input and output tokens per request.
+//! - `gen_ai.server.request.duration`: time the provider spent on a request, per phase
+//!   (`load`, `prompt_eval`, `eval`), for providers reporting [Timings] such as Ollama. Shows
+//!   whether a slow request waited for the model to load, read a long prompt or generated a long
+//!   answer.
+//! - `rig.mcp.call.failures`: failed MCP tool calls, per tool.
+//!
+//! Wrap a completion model in [MeteredCompletionModel] to record its requests.
+
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Histogram, Meter};
+
+use crate::client::completion::CompletionModelHandle;
+use crate::completion::{
+    CompletionError, CompletionModel, CompletionRequest, CompletionResponse, ProviderErrorKind,
+    Timings, Usage,
+};
+use crate::streaming::{StreamUsage, StreamingCompletionResponse};
+
+/// Instruments recorded by rig.
+pub struct RigMetrics {
+    operation_duration: Histogram<f64>,
+    token_usage: Histogram<u64>,
+    server_duration: Histogram<f64>,
+    mcp_failures: Counter<u64>,
+}
+
+impl RigMetrics {
+    pub fn new(meter: &Meter) -> Self {
+        Self {
+            operation_duration: meter
+                .f64_histogram("gen_ai.client.operation.duration")
+                .with_unit("s")
+                .with_description("Duration of completion requests")
+                .build(),
+            token_usage: meter
+                .u64_histogram("gen_ai.client.token.usage")
+                .with_unit("{token}")
+                .with_description("Tokens used by completion requests")
+                .build(),
+            server_duration: meter
+                .f64_histogram("gen_ai.server.request.duration")
+                .with_unit("s")
+                .with_description("Time the provider spent on completion requests, per phase")
+                .build(),
+            mcp_failures: meter
+                .u64_counter("rig.mcp.call.failures")
+                .with_description("Failed MCP tool calls")
+                .build(),
+        }
+    }
+
+    /// Instruments of the global meter provider, created on first use.
+    pub fn global() -> Arc<Self> {
+        static GLOBAL: OnceLock<Arc<RigMetrics>> = OnceLock::new();
+        GLOBAL
+            .get_or_init(|| Arc::new(Self::new(&opentelemetry::global::meter("rig"))))
+            .clone()
+    }
+
+    pub fn record_request(
+        &self,
+        provider: &str,
+        model: &str,
+        duration: Duration,
+        error: Option<&CompletionError>,
+    ) {
+        let mut attributes = vec![
+            KeyValue::new("gen_ai.system", provider.to_string()),
+            KeyValue::new("gen_ai.request.model", model.to_string()),
+        ];
+        if let Some(error) = error {
+            attributes.push(KeyValue::new("error.type", error_type(error)));
+        }
+        self.operation_duration
+            .record(duration.as_secs_f64(), &attributes);
+    }
+
+    pub fn record_usage(&self, provider: &str, model: &str, usage: &Usage) {
+        for (token_type, tokens) in [
+            ("input", usage.input_tokens),
+            ("output", usage.output_tokens),
+        ] {
+            self.token_usage.record(
+                tokens,
+                &[
+                    KeyValue::new("gen_ai.system", provider.to_string()),
+                    KeyValue::new("gen_ai.request.model", model.to_string()),
+                    KeyValue::new("gen_ai.token.type", token_type),
+                ],
+            );
+        }
+    }
+
+    pub fn record_timings(&self, provider: &str, model: &str, timings: &Timings) {
+        for (phase, duration) in [
+            ("load", timings.load),
+            ("prompt_eval", timings.prompt_eval),
+            ("eval", timings.eval),
+        ] {
+            let Some(duration) = duration else {
+                continue;
+            };
+            self.server_duration.record(
+                duration.as_secs_f64(),
+                &[
+                    KeyValue::new("gen_ai.system", provider.to_string()),
+                    KeyValue::new("gen_ai.request.model", model.to_string()),
+                    KeyValue::new("rig.server.phase", phase),
+                ],
+            );
+        }
+    }
+
+    pub fn record_mcp_failure(&self, tool: &str) {
+        self.mcp_failures
+            .add(1, &[KeyValue::new("gen_ai.tool.name", tool.to_string())]);
+    }
+}
+
+/// Low cardinality name of an error for the `error.type` attribute.
+fn error_type(error: &CompletionError) -> &'static str {
+    match error.provider_kind() {
+        Some(ProviderErrorKind::RateLimited { .. }) => "rate_limited",
+        Some(ProviderErrorKind::ContextLengthExceeded) => "context_length_exceeded",
+        Some(ProviderErrorKind::AuthFailed) => "auth_failed",
+        Some(ProviderErrorKind::ModelNotFound) => "model_not_found",
+        Some(ProviderErrorKind::Timeout) => "timeout",
+        Some(ProviderErrorKind::Unavailable) => "unavailable",
+        Some(ProviderErrorKind::Other) => "provider",
+        None => match error {
+            CompletionError::HttpError(_) => "http",
+            CompletionError::MCPError(_) => "mcp",
+            _ => "other",
+        },
+    }
+}
+
+/// A completion model that records the latency and token usage of every request.
+/// Streaming requests record the time until the stream is established.
+#[derive(Clone)]
+pub struct MeteredCompletionModel<'a> {
+    inner: CompletionModelHandle<'a>,
+    provider: String,
+    model: String,
+    metrics: Arc<RigMetrics>,
+}
+
+impl<'a> MeteredCompletionModel<'a> {
+    pub fn new(
+        inner: CompletionModelHandle<'a>,
+        provider: impl Into<String>,
+        model: impl Into<String>,
+    ) -> Self {
+        Self {
+            inner,
+            provider: provider.into(),
+            model: model.into(),
+            metrics: RigMetrics::global(),
+        }
+    }
+
+    /// Record into the given instruments instead of the global ones.
+    pub fn with_metrics(mut self, metrics: Arc<RigMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+}
+
+impl CompletionModel for MeteredCompletionModel<'_> {
+    type Response = ();
+    type StreamingResponse = StreamUsage;
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<Self::Response>, CompletionError> {
+        let start = Instant::now();
+        let result = self.inner.completion(request).await;
+        self.metrics.record_request(
+            &self.provider,
+            &self.model,
+            start.elapsed(),
+            result.as_ref().err(),
+        );
+        if let Ok(response) = &result {
+            self.metrics
+                .record_usage(&self.provider, &self.model, &response.usage);
+            if let Some(timings) = &response.timings {
+                self.metrics
+                    .record_timings(&self.provider, &self.model, timings);
+            }
+        }
+        result
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<StreamingCompletionResponse<Self::StreamingResponse>, CompletionError> {
+        let start = Instant::now();
+        let result = self.inner.stream(request).await;
+        self.metrics.record_request(
+            &self.provider,
+            &self.model,
+            start.elapsed(),
+            result.as_ref().err(),
+        );
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OneOrMany;
+    use crate::completion::{AssistantContent, Message};
+    use opentelemetry::metrics::MeterProvider as _;
+    use opentelemetry_sdk::error::OTelSdkResult;
+    use opentelemetry_sdk::metrics::data::ResourceMetrics;
+    use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
+    use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider, Temporality};
+    use std::sync::Mutex;
+
+    #[derive(Clone)]
+    struct MockModel;
+
+    impl CompletionModel for MockModel {
+        type Response = ();
+        type StreamingResponse = ();
+
+        async fn completion(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<CompletionResponse<()>, CompletionError> {
+            Ok(CompletionResponse {
+                choice: OneOrMany::one(AssistantContent::text("ok")),
+                usage: Usage {
+                    input_tokens: 7,
+                    output_tokens: 3,
+                    ..Usage::new()
+                },
+                logprobs: None,
+                timings: Some(Timings {
+                    prompt_eval: Some(Duration::from_millis(40)),
+                    eval: Some(Duration::from_millis(300)),
+                    ..Timings::default()
+                }),
+                served_by: None,
+                raw_response: (),
+            })
+        }
+
+        async fn stream(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<StreamingCompletionResponse<()>, CompletionError> {
+            Err(CompletionError::provider("unsupported"))
+        }
+    }
+
+    /// Keeps the names of exported metrics.
+    #[derive(Clone, Default)]
+    struct NameExporter(Arc<Mutex<Vec<String>>>);
+
+    impl PushMetricExporter for NameExporter {
+        async fn export(&self, metrics: &ResourceMetrics) -> OTelSdkResult {
+            let mut names = self.0.lock().unwrap();
+            for scope in metrics.scope_metrics() {
+                names.extend(scope.metrics().map(|m| m.name().to_string()));
+            }
+            Ok(())
+        }
+
+        fn force_flush(&self) -> OTelSdkResult {
+            Ok(())
+        }
+
+        fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+            Ok(())
+        }
+
+        fn temporality(&self) -> Temporality {
+            Temporality::Cumulative
+        }
+    }
+
+    #[tokio::test]
+    async fn records_latency_and_tokens() {
+        let exporter = NameExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        let metrics = Arc::new(RigMetrics::new(&provider.meter("test")));
+        let model = MeteredCompletionModel::new(
+            CompletionModelHandle {
+                inner: Arc::new(MockModel),
+            },
+            "mock",
+            "m1",
+        )
+        .with_metrics(metrics.clone());
+
+        let request = CompletionRequest {
+            preamble: None,
+            chat_history: OneOrMany::one(Message::user("hi")),
+            documents: vec![],
+            tools: vec![],
+            temperature: None,
+            max_tokens: None,
+            sampling: Default::default(),
+            think: None,
+            stop: vec![],
+            tool_choice: None,
+            additional_params: None,
+        };
+        model.completion(request.clone()).await.unwrap();
+        assert!(model.stream(request).await.is_err());
+        metrics.record_mcp_failure("search");
+        provider.force_flush().unwrap();
+
+        let names = exporter.0.lock().unwrap();
+        for name in [
+            "gen_ai.client.operation.duration",
+            "gen_ai.client.token.usage",
+            "gen_ai.server.request.duration",
+            "rig.mcp.call.failures",
+        ] {
+            assert!(names.iter().any(|n| n == name), "{name} not exported");
+        }
+    }
+}
@@ -6,6 +6,10 @@
 use crate::completion::GetTokenUsage;
 use serde::Serialize;
 
+#[cfg(feature = "otel-metrics")]
+pub mod metrics;
+pub mod redact;
+
 pub trait ProviderRequestExt {
     type InputMessage: Serialize;
 
@@ -58,6 +62,10 @@ impl SpanCombinator for tracing::Span {
         if let Some(usage) = usage.token_usage() {
             self.record("gen_ai.usage.input_tokens", usage.input_tokens);
             self.record("gen_ai.usage.output_tokens", usage.output_tokens);
+            self.record(
+                "gen_ai.usage.cache_read.input_tokens",
+                usage.cached_input_tokens,
+            );
         }
     }
 
@@ -81,7 +89,7 @@ impl SpanCombinator for tracing::Span {
         let input_as_json_string =
             serde_json::to_string(input).expect("Serializing a Rust type to JSON should not break");
 
-        self.record("gen_ai.input.messages", input_as_json_string);
+        self.record("gen_ai.input.messages", redact::redact(&input_as_json_string));
     }
 
     fn record_model_output<T>(&self, input: &T)
@@ -91,6 +99,6 @@ impl SpanCombinator for tracing::Span {
         let input_as_json_string =
             serde_json::to_string(input).expect("Serializing a Rust type to JSON should not break");
 
-        self.record("gen_ai.input.messages", input_as_json_string);
+        self.record("gen_ai.output.messages", redact::redact(&input_as_json_string));
     }
 }
@@ -0,0 +1,212 @@
+//! Redaction of sensitive data before it is recorded in spans and logs.
+//!
+//! Span fields such as `gen_ai.input.messages` and tool call results carry whole messages, which
+//! may include API keys from tool results or personal data. A [Redactor] replaces regex matches
+//! (the builtin patterns of [SecretScanner] plus custom ones) with `[REDACTED:<pattern>]`, and
+//! the values of deny-listed json keys with `[REDACTED:<key>]`.
+//!
+//! A redactor installed with [set_global] applies everywhere. An agent built with
+//! [AgentBuilder::redactor](crate::agent::AgentBuilder::redactor) uses its own redactor instead
+//! while its prompt requests run, including the fields the provider records, through [scope].
+//! Streaming requests apply the agent's redactor to the agent spans only.
+
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::client::secret_scan::{SecretAction, SecretScanner};
+
+/// Replaces sensitive data in text and json.
+#[derive(Debug)]
+pub struct Redactor {
+    scanner: SecretScanner,
+    /// Lowercase json keys whose values are always redacted.
+    deny_keys: Vec<String>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Redactor {
+    /// A redactor with the builtin secret patterns.
+    pub fn new() -> Self {
+        Self {
+            scanner: SecretScanner::new(SecretAction::Redact),
+            deny_keys: Vec::new(),
+        }
+    }
+
+    /// A redactor without any pattern.
+    pub fn empty() -> Self {
+        Self {
+            scanner: SecretScanner::without_builtin(SecretAction::Redact),
+            deny_keys: Vec::new(),
+        }
+    }
+
+    /// Add a custom pattern.
+    pub fn pattern(mut self, name: &str, regex: &str) -> Result<Self, regex::Error> {
+        self.scanner = self.scanner.pattern(name, regex)?;
+        Ok(self)
+    }
+
+    /// Always redact the values of a json key, compared case-insensitively.
+    pub fn deny_key(mut self, key: &str) -> Self {
+        self.deny_keys.push(key.to_lowercase());
+        self
+    }
+
+    /// Redact a text. Text that is a json object or array also has its deny-listed keys
+    /// redacted.
+    pub fn redact(&self, text: &str) -> String {
+        let trimmed = text.trim_start();
+        if !self.deny_keys.is_empty()
+            && (trimmed.starts_with('{') || trimmed.starts_with('['))
+            && let Ok(mut value) = serde_json::from_str::<Value>(text)
+        {
+            self.redact_json(&mut value);
+            return value.to_string();
+        }
+        let mut text = text.to_string();
+        self.scanner.redact(&mut text);
+        text
+    }
+
+    /// Redact every string and deny-listed key of a json value in place.
+    pub fn redact_json(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => {
+                self.scanner.redact(text);
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.redact_json(item)),
+            Value::Object(fields) => {
+                for (key, value) in fields.iter_mut() {
+                    if self.deny_keys.contains(&key.to_lowercase()) {
+                        *value = Value::String(format!("[REDACTED:{key}]"));
+                    } else {
+                        self.redact_json(value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Serializable redaction settings, e.g. for an agent config.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactionConfig {
+    /// Use the builtin secret patterns.
+    #[serde(default = "default_builtin")]
+    pub builtin: bool,
+    #[serde(default)]
+    pub patterns: Vec<RedactionPattern>,
+    #[serde(default)]
+    pub deny_keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactionPattern {
+    pub name: String,
+    pub regex: String,
+}
+
+fn default_builtin() -> bool {
+    true
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            builtin: true,
+            patterns: Vec::new(),
+            deny_keys: Vec::new(),
+        }
+    }
+}
+
+impl RedactionConfig {
+    pub fn build(&self) -> Result<Redactor, regex::Error> {
+        let mut redactor = if self.builtin {
+            Redactor::new()
+        } else {
+            Redactor::empty()
+        };
+        for pattern in &self.patterns {
+            redactor = redactor.pattern(&pattern.name, &pattern.regex)?;
+        }
+        for key in &self.deny_keys {
+            redactor = redactor.deny_key(key);
+        }
+        Ok(redactor)
+    }
+}
+
+static GLOBAL: RwLock<Option<Arc<Redactor>>> = RwLock::new(None);
+
+tokio::task_local! {
+    static SCOPED: Arc<Redactor>;
+}
+
+/// Install or remove the redactor used outside of agents with their own redactor.
+pub fn set_global(redactor: Option<Arc<Redactor>>) {
+    *GLOBAL.write().unwrap_or_else(|e| e.into_inner()) = redactor;
+}
+
+pub fn global() -> Option<Arc<Redactor>> {
+    GLOBAL.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Run a future with a redactor in effect, falling back to the global one when `None`.
+pub async fn scope<F: Future>(redactor: Option<Arc<Redactor>>, future: F) -> F::Output {
+    match redactor {
+        Some(redactor) => SCOPED.scope(redactor, future).await,
+        None => future.await,
+    }
+}
+
+/// The redactor in effect: the one of the running [scope], or the global one.
+pub fn current() -> Option<Arc<Redactor>> {
+    SCOPED.try_with(Arc::clone).ok().or_else(global)
+}
+
+/// Redact a text with the redactor in effect, unchanged when there is none.
+pub fn redact(text: &str) -> String {
+    match current() {
+        Some(redactor) => redactor.redact(text),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn redacts_patterns_keys_and_scopes() {
+        let redactor = RedactionConfig {
+            patterns: vec![RedactionPattern {
+                name: "phone".to_string(),
+                regex: r"\b1\d{10}\b".to_string(),
+            }],
+            deny_keys: vec!["Password".to_string()],
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let messages = r#"[{"content":"call 13800138000, key sk-abcdefghijklmnopqrstuvwx","password":"hunter2"}]"#;
+        assert_eq!(
+            redactor.redact(messages),
+            r#"[{"content":"call [REDACTED:phone], key [REDACTED:api_key]","password":"[REDACTED:password]"}]"#
+        );
+
+        assert_eq!(redact("13800138000"), "13800138000");
+        let scoped = scope(Some(Arc::new(redactor)), async { redact("13800138000") }).await;
+        assert_eq!(scoped, "[REDACTED:phone]");
+    }
+}
@@ -0,0 +1,199 @@
+//! Token counting.
+//!
+//! Request size limits, history truncation and token rate limits all need the number of tokens
+//! a request will use before it is sent. Providers count with their own tokenizers, so this
+//! module offers one [TokenCounter] per tokenizer family and picks one by model name with
+//! [counter_for]:
+//!
+//! - [Tiktoken] counts exactly with the `cl100k_base` or `o200k_base` encodings (feature
+//!   `tiktoken`). DeepSeek models use a different BPE vocabulary of similar size, so
+//!   `cl100k_base` is a close estimate for them.
+//! - [LlamaHeuristic] estimates SentencePiece tokenizers of local models (llama, qwen, mistral,
+//!   gemma...): word pieces of about four letters, one token per digit, punctuation mark and
+//!   non-ascii character.
+//! - [Approximate] is the fallback: four ascii characters or one other character per token.
+//!
+//! The estimates over-count rather than under-count for most tokenizers.
+
+use std::sync::Arc;
+
+use crate::completion::CompletionRequest;
+
+/// Counts the tokens of a text for one tokenizer.
+pub trait TokenCounter: Send + Sync {
+    fn count(&self, text: &str) -> u64;
+
+    /// Tokens of the parts of a request that are sent to the provider, plus the requested
+    /// `max_tokens` of output.
+    fn count_request(&self, request: &CompletionRequest) -> u64 {
+        let body = serde_json::json!({
+            "preamble": request.preamble,
+            "messages": request.chat_history,
+            "documents": request.documents,
+            "tools": request.tools,
+        })
+        .to_string();
+        self.count(&body) + request.max_tokens.unwrap_or(0)
+    }
+}
+
+/// Four ascii characters or one other character per token.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Approximate;
+
+impl TokenCounter for Approximate {
+    fn count(&self, text: &str) -> u64 {
+        estimate_tokens(text)
+    }
+}
+
+/// Estimate the number of tokens of a text with [Approximate].
+pub fn estimate_tokens(text: &str) -> u64 {
+    let (ascii, other) = text.chars().fold((0u64, 0u64), |(ascii, other), c| {
+        if c.is_ascii() {
+            (ascii + 1, other)
+        } else {
+            (ascii, other + 1)
+        }
+    });
+    ascii.div_ceil(4) + other
+}
+
+/// Estimates SentencePiece tokenizers of llama-family models.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LlamaHeuristic;
+
+impl TokenCounter for LlamaHeuristic {
+    fn count(&self, text: &str) -> u64 {
+        let mut tokens = 0;
+        let mut letters = 0u64;
+        for c in text.chars() {
+            if c.is_ascii_alphabetic() {
+                letters += 1;
+                continue;
+            }
+            tokens += letters.div_ceil(4);
+            letters = 0;
+            // Spaces are merged into the following word piece
+            if !c.is_whitespace() {
+                tokens += 1;
+            }
+        }
+        tokens + letters.div_ceil(4)
+    }
+}
+
+/// Exact counts with a tiktoken encoding.
+#[cfg(feature = "tiktoken")]
+#[derive(Clone, Copy)]
+pub struct Tiktoken {
+    bpe: &'static tiktoken_rs::CoreBPE,
+}
+
+#[cfg(feature = "tiktoken")]
+impl Tiktoken {
+    /// The encoding of gpt-4 and gpt-3.5 models.
+    pub fn cl100k_base() -> Self {
+        Self {
+            bpe: tiktoken_rs::cl100k_base_singleton(),
+        }
+    }
+
+    /// The encoding of gpt-4o and newer models.
+    pub fn o200k_base() -> Self {
+        Self {
+            bpe: tiktoken_rs::o200k_base_singleton(),
+        }
+    }
+}
+
+#[cfg(feature = "tiktoken")]
+impl TokenCounter for Tiktoken {
+    fn count(&self, text: &str) -> u64 {
+        self.bpe.encode_ordinary(text).len() as u64
+    }
+}
+
+/// Tokenizer families known by model name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenizerFamily {
+    /// OpenAI `o200k_base` models
+    O200k,
+    /// OpenAI `cl100k_base` models and DeepSeek
+    Cl100k,
+    /// SentencePiece models usually served by Ollama
+    Llama,
+    Unknown,
+}
+
+impl TokenizerFamily {
+    /// Guess the tokenizer family from a model name such as `deepseek-chat` or `qwen2.5:7b`.
+    pub fn of(model: &str) -> Self {
+        let model = model.to_lowercase();
+        let model = model.rsplit('/').next().unwrap_or_default();
+        if ["gpt-4o", "gpt-4.1", "o1", "o3", "o4"]
+            .iter()
+            .any(|prefix| model.starts_with(prefix))
+        {
+            TokenizerFamily::O200k
+        } else if ["gpt-", "deepseek", "text-embedding"]
+            .iter()
+            .any(|prefix| model.starts_with(prefix))
+        {
+            TokenizerFamily::Cl100k
+        } else if [
+            "llama",
+            "codellama",
+            "qwen",
+            "mistral",
+            "mixtral",
+            "gemma",
+            "phi",
+            "yi",
+            "vicuna",
+        ]
+        .iter()
+        .any(|prefix| model.starts_with(prefix))
+        {
+            TokenizerFamily::Llama
+        } else {
+            TokenizerFamily::Unknown
+        }
+    }
+}
+
+/// The most precise counter available for a model. Without the `tiktoken` feature OpenAI and
+/// DeepSeek models fall back to [Approximate].
+pub fn counter_for(model: &str) -> Arc<dyn TokenCounter> {
+    match TokenizerFamily::of(model) {
+        #[cfg(feature = "tiktoken")]
+        TokenizerFamily::O200k => Arc::new(Tiktoken::o200k_base()),
+        #[cfg(feature = "tiktoken")]
+        TokenizerFamily::Cl100k => Arc::new(Tiktoken::cl100k_base()),
+        TokenizerFamily::Llama => Arc::new(LlamaHeuristic),
+        _ => Arc::new(Approximate),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_are_picked_by_model() {
+        assert_eq!(
+            TokenizerFamily::of("deepseek-chat"),
+            TokenizerFamily::Cl100k
+        );
+        assert_eq!(TokenizerFamily::of("qwen2.5:7b"), TokenizerFamily::Llama);
+        assert_eq!(
+            TokenizerFamily::of("openai/gpt-4o-mini"),
+            TokenizerFamily::O200k
+        );
+        assert_eq!(estimate_tokens("订单abcd"), 3);
+        assert_eq!(LlamaHeuristic.count("total: 42 orders"), 7);
+        assert_eq!(counter_for("llama3").count("hello world"), 4);
+        #[cfg(feature = "tiktoken")]
+        assert_eq!(counter_for("deepseek-chat").count("hello world"), 2);
+    }
+}
@@ -0,0 +1,172 @@
+//! Native in-process tools.
+//!
+//! MCP tools live in another process. A [Tool] is plain Rust code called in-process: it
+//! describes itself with an MCP tool definition so both kinds of tools can be offered to the
+//! model side by side, and agents route tool calls to the native tool when one is registered
+//! under the called name.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use rmcp::model::Tool as ToolDefinition;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ToolError {
+    /// The arguments or the output could not be (de)serialized
+    #[error("JsonError: {0}")]
+    JsonError(#[from] serde_json::Error),
+    /// The tool returned an error
+    #[error("ToolCallError: {0}")]
+    ToolCallError(#[from] Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// A tool implemented in Rust.
+pub trait Tool: Send + Sync {
+    /// The name of the tool, must be unique among the tools of an agent.
+    const NAME: &'static str;
+
+    type Args: DeserializeOwned + Send;
+    type Output: Serialize;
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// The definition sent to the model. Its name should be [Tool::NAME].
+    fn definition(&self) -> ToolDefinition;
+
+    fn call(
+        &self,
+        args: Self::Args,
+    ) -> impl Future<Output = Result<Self::Output, Self::Error>> + Send;
+}
+
+/// Object safe version of [Tool], taking and returning json.
+pub trait ToolDyn: Send + Sync {
+    fn name(&self) -> String;
+
+    fn definition(&self) -> ToolDefinition;
+
+    /// Call the tool, string outputs are returned as is, other outputs as json.
+    fn call(&self, args: Value) -> BoxFuture<'_, Result<String, ToolError>>;
+}
+
+impl<T: Tool> ToolDyn for T {
+    fn name(&self) -> String {
+        T::NAME.to_string()
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        Tool::definition(self)
+    }
+
+    fn call(&self, args: Value) -> BoxFuture<'_, Result<String, ToolError>> {
+        Box::pin(async move {
+            let args: T::Args = serde_json::from_value(args)?;
+            let output = Tool::call(self, args)
+                .await
+                .map_err(|e| ToolError::ToolCallError(Box::new(e)))?;
+            Ok(match serde_json::to_value(output)? {
+                Value::String(output) => output,
+                output => output.to_string(),
+            })
+        })
+    }
+}
+
+/// A set of native tools, keyed by name.
+#[derive(Clone, Default)]
+pub struct ToolSet {
+    tools: HashMap<String, Arc<dyn ToolDyn>>,
+}
+
+impl ToolSet {
+    pub fn add(&mut self, tool: impl ToolDyn + 'static) {
+        self.add_dyn(Arc::new(tool));
+    }
+
+    pub fn add_dyn(&mut self, tool: Arc<dyn ToolDyn>) {
+        self.tools.insert(tool.name(), tool);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.tools.contains_key(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Definitions of all tools, sorted by name.
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        let mut definitions: Vec<ToolDefinition> =
+            self.tools.values().map(|tool| tool.definition()).collect();
+        definitions.sort_by(|a, b| a.name.cmp(&b.name));
+        definitions
+    }
+
+    /// Call a tool by name, `None` when no tool has this name.
+    pub async fn call(&self, name: &str, args: Value) -> Option<Result<String, ToolError>> {
+        let tool = self.tools.get(name)?;
+        Some(tool.call(args).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct OperationArgs {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Debug, Error)]
+    #[error("math error")]
+    struct MathError;
+
+    struct Adder;
+
+    impl Tool for Adder {
+        const NAME: &'static str = "add";
+
+        type Args = OperationArgs;
+        type Output = i32;
+        type Error = MathError;
+
+        fn definition(&self) -> ToolDefinition {
+            let schema = json!({
+                "type": "object",
+                "properties": {
+                    "x": { "type": "number" },
+                    "y": { "type": "number" }
+                }
+            });
+            ToolDefinition::new(
+                Self::NAME,
+                "Add x and y together",
+                schema.as_object().cloned().unwrap_or_default(),
+            )
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok(args.x + args.y)
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_tool_by_name() {
+        let mut tools = ToolSet::default();
+        tools.add(Adder);
+
+        assert_eq!(tools.definitions()[0].name, "add");
+        let output = tools.call("add", json!({ "x": 1, "y": 2 })).await;
+        assert_eq!(output.unwrap().unwrap(), "3");
+        assert!(tools.call("subtract", json!({})).await.is_none());
+    }
+}